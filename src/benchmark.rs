@@ -0,0 +1,165 @@
+use crate::bsdiff::BsDiff;
+use crate::cli::Preset;
+use crate::patch::preset_level;
+use crate::utils::{create_tmp_dir, format_bytes};
+use crate::zstdiff::ZstdDiff;
+use crate::{get_temp_path, is_tty};
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use console::style;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// 单个存储方式的测试结果
+#[derive(Debug, Clone)]
+pub struct BenchmarkResult {
+    /// 存储方式名称
+    pub method: String,
+    /// 补丁数据大小（字节）
+    pub size: u64,
+    /// 生成补丁数据的耗时
+    pub elapsed: Duration,
+}
+
+/// 对两个文件分别使用各存储方式生成补丁数据，统计补丁数据大小与耗时
+///
+/// 依次测试完整存储（补丁数据即更新文件）、每个压缩预设对应级别的 zstd 差异和 bsdiff 差异，
+/// 补丁数据写入临时目录，测试结束后删除
+///
+/// # 参数
+///
+/// - `old` - 旧文件路径
+/// - `new` - 新文件路径
+///
+/// # 返回值
+///
+/// - `Ok(Vec<BenchmarkResult>)` - 各存储方式的测试结果，第一项为完整存储
+/// - `Err(anyhow::Error)` - 读取文件或生成补丁数据失败
+pub fn run_benchmark(old: &Path, new: &Path) -> Result<Vec<BenchmarkResult>> {
+    let work_dir = create_tmp_dir(&get_temp_path(), "benchmark-", "")
+        .with_context(|| "Create benchmark dir failed".to_string())?;
+    let result = run_methods(old, new, &work_dir);
+    fs::remove_dir_all(&work_dir).ok();
+    result
+}
+
+/// 在工作目录中依次执行各存储方式
+fn run_methods(old: &Path, new: &Path, work_dir: &Path) -> Result<Vec<BenchmarkResult>> {
+    let full_size = fs::metadata(new)
+        .with_context(|| format!("Read new file failed: {}", new.display()))?
+        .len();
+    let mut results = vec![BenchmarkResult {
+        method: "full".to_string(),
+        size: full_size,
+        elapsed: Duration::ZERO,
+    }];
+
+    // zstd 差异（以旧文件为字典），每个压缩预设测试一次
+    for preset in Preset::value_variants() {
+        let name = preset
+            .to_possible_value()
+            .map(|value| value.get_name().to_string())
+            .unwrap_or_default();
+        let level = preset_level(preset);
+        let patch = work_dir.join(format!("zstd-{}.diff", name));
+        results.push(measure(format!("zstd {} ({})", name, level), &patch, || {
+            ZstdDiff::file_diff(old, new, &patch, level, None)
+        })?);
+    }
+
+    // bsdiff 差异
+    let patch = work_dir.join("bsdiff.diff");
+    results.push(measure("bsdiff".to_string(), &patch, || {
+        BsDiff::file_diff(old, new, &patch)
+    })?);
+    Ok(results)
+}
+
+/// 执行一次差异生成并统计耗时与补丁数据大小
+fn measure(method: String, patch: &Path, diff: impl FnOnce() -> Result<()>) -> Result<BenchmarkResult> {
+    let start = Instant::now();
+    diff().with_context(|| format!("Benchmark {} failed", method))?;
+    let elapsed = start.elapsed();
+    let size = fs::metadata(patch)
+        .with_context(|| format!("Read benchmark output failed: {}", patch.display()))?
+        .len();
+    Ok(BenchmarkResult { method, size, elapsed })
+}
+
+/// 生成测试结果表格
+///
+/// 终端中输出对齐的表格并高亮最小的补丁数据，否则输出以制表符分隔的原始数值（字节与毫秒），便于脚本处理
+///
+/// # 参数
+///
+/// - `results` - 测试结果，第一项为完整存储，作为比例的基准
+/// - `tty` - 是否按终端格式输出
+pub fn format_benchmark(results: &[BenchmarkResult], tty: bool) -> String {
+    let full_size = results.first().map_or(0, |result| result.size);
+    let ratio = |size: u64| {
+        if full_size == 0 {
+            0.0
+        } else {
+            size as f64 * 100.0 / full_size as f64
+        }
+    };
+    let mut output = String::new();
+
+    if !tty {
+        output.push_str("method\tsize\tratio\ttime_ms\n");
+        for result in results {
+            output.push_str(&format!(
+                "{}\t{}\t{:.2}\t{}\n",
+                result.method,
+                result.size,
+                ratio(result.size),
+                result.elapsed.as_millis()
+            ));
+        }
+        return output;
+    }
+
+    let smallest = results.iter().map(|result| result.size).min().unwrap_or(0);
+    let method_w = results
+        .iter()
+        .map(|result| result.method.len())
+        .max()
+        .unwrap_or(0)
+        .max("Method".len());
+    output.push_str(&format!(
+        "{}\n",
+        style(format!(
+            "{:<method_w$}  {:>12}  {:>8}  {:>9}",
+            "Method", "Size", "Ratio", "Time"
+        ))
+        .bold()
+    ));
+    for result in results {
+        let line = format!(
+            "{:<method_w$}  {:>12}  {:>7.2}%  {:>8.2}s",
+            result.method,
+            format_bytes(result.size),
+            ratio(result.size),
+            result.elapsed.as_secs_f64()
+        );
+        if result.size == smallest {
+            output.push_str(&format!("{}\n", style(line).green()));
+        } else {
+            output.push_str(&format!("{}\n", line));
+        }
+    }
+    output
+}
+
+/// 运行存储方式测试并输出结果表格
+///
+/// # 参数
+///
+/// - `old` - 旧文件路径
+/// - `new` - 新文件路径
+pub fn print_benchmark(old: &Path, new: &Path) -> Result<()> {
+    let results = run_benchmark(old, new)?;
+    print!("{}", format_benchmark(&results, is_tty()));
+    Ok(())
+}
@@ -1,3 +1,4 @@
+use crate::utils::{CountWriter, FileHashes, get_sha256};
 use anyhow::{Context, Result};
 use std::fs;
 use std::fs::File;
@@ -21,6 +22,23 @@ impl BsDiff {
         new_file_path: impl AsRef<Path>,
         patch_file_path: impl AsRef<Path>,
     ) -> Result<()> {
+        Self::file_diff_hashed(old_file_path, new_file_path, patch_file_path).map(|_| ())
+    }
+
+    /// 创建差异文件，并计算已读入内存的旧文件与新文件的 SHA-256（无需再次读取文件）
+    ///
+    /// # 参数
+    /// - `old_file_path`: 旧文件路径
+    /// - `update_file_path`: 更新后的文件路径
+    /// - `patch_file_path`: 输出的bsdiff文件路径
+    ///
+    /// # 返回值
+    /// - `Result<FileHashes>`: 操作结果，成功返回Ok(旧文件与新文件的哈希值)，失败返回对应的错误信息
+    pub fn file_diff_hashed(
+        old_file_path: impl AsRef<Path>,
+        new_file_path: impl AsRef<Path>,
+        patch_file_path: impl AsRef<Path>,
+    ) -> Result<FileHashes> {
         let old = fs::read(old_file_path).with_context(|| "Read old file error")?;
         let update = fs::read(new_file_path).with_context(|| "Read new file error")?;
 
@@ -29,7 +47,27 @@ impl BsDiff {
 
         bsdiff::diff(&old, &update, &mut writer)?;
         writer.flush().with_context(|| "Flush patch writer failed")?;
-        Ok(())
+        Ok(FileHashes {
+            old: get_sha256(&old),
+            new: get_sha256(&update),
+        })
+    }
+
+    /// 计算差异文件大小（不写入文件）
+    ///
+    /// # 参数
+    /// - `old_file_path`: 旧文件路径
+    /// - `update_file_path`: 更新后的文件路径
+    ///
+    /// # 返回值
+    /// - `Result<u64>`: 操作结果，成功返回Ok(差异文件大小)，失败返回对应的错误信息
+    pub fn file_diff_size(old_file_path: impl AsRef<Path>, new_file_path: impl AsRef<Path>) -> Result<u64> {
+        let old = fs::read(old_file_path).with_context(|| "Read old file error")?;
+        let update = fs::read(new_file_path).with_context(|| "Read new file error")?;
+
+        let mut writer = CountWriter::default();
+        bsdiff::diff(&old, &update, &mut writer)?;
+        Ok(writer.count)
     }
 
     /// 修补文件
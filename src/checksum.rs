@@ -0,0 +1,150 @@
+use crate::manifest::PatchManifest;
+use anyhow::{Context, Result, anyhow};
+use std::fs;
+use std::path::Path;
+
+/// 校验文件中记录的补丁镜像
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChecksumImage {
+    /// 补丁镜像索引
+    pub index: u32,
+    /// 补丁清单 ID
+    pub id: String,
+    /// 补丁版本
+    pub version: String,
+}
+
+/// 补丁文件校验文件
+///
+/// 文本格式，每行一个 `键: 值`，空行与 `#` 开头的行被忽略：
+///
+/// ```text
+/// sha256: <补丁文件的 SHA-256>
+/// file: <补丁文件名>
+/// image: <补丁镜像索引> <补丁清单 ID> <补丁版本>
+/// ```
+///
+/// 每个补丁镜像一行 `image`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Checksum {
+    /// 补丁文件的 SHA-256（小写十六进制）
+    pub sha256: String,
+    /// 补丁文件名
+    pub file_name: String,
+    /// 补丁镜像列表
+    pub images: Vec<ChecksumImage>,
+}
+
+impl Checksum {
+    /// 根据补丁文件哈希与补丁清单创建校验信息
+    ///
+    /// # 参数
+    ///
+    /// - `sha256` - 补丁文件的 SHA-256
+    /// - `file_name` - 补丁文件名
+    /// - `manifests` - 补丁镜像索引与补丁清单列表
+    pub fn new(sha256: &str, file_name: &str, manifests: &[(u32, PatchManifest)]) -> Self {
+        Self {
+            sha256: sha256.to_ascii_lowercase(),
+            file_name: file_name.to_string(),
+            images: manifests
+                .iter()
+                .map(|(index, manifest)| ChecksumImage {
+                    index: *index,
+                    id: manifest.id.clone(),
+                    version: manifest.patch_version.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    /// 生成校验文件内容
+    pub fn to_text(&self) -> String {
+        let mut result = String::from("# WimPatch checksum\n");
+        result.push_str(&format!("sha256: {}\n", self.sha256));
+        result.push_str(&format!("file: {}\n", self.file_name));
+        for image in &self.images {
+            result.push_str(&format!("image: {} {} {}\n", image.index, image.id, image.version));
+        }
+        result
+    }
+
+    /// 解析校验文件内容
+    ///
+    /// # 参数
+    ///
+    /// - `text` - 校验文件内容
+    ///
+    /// # 返回值
+    ///
+    /// - `Ok(Checksum)` - 解析成功
+    /// - `Err(anyhow::Error)` - 格式错误或缺少 `sha256`
+    pub fn from_text(text: &str) -> Result<Self> {
+        let mut sha256 = None;
+        let mut file_name = String::new();
+        let mut images = Vec::new();
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line
+                .split_once(':')
+                .ok_or_else(|| anyhow!("Invalid checksum line: {}", line))?;
+            let value = value.trim();
+            match key.trim() {
+                "sha256" => {
+                    if value.len() != 64 || !value.chars().all(|c| c.is_ascii_hexdigit()) {
+                        return Err(anyhow!("Invalid SHA-256: {}", value));
+                    }
+                    sha256 = Some(value.to_ascii_lowercase());
+                }
+                "file" => file_name = value.to_string(),
+                "image" => {
+                    let mut fields = value.split_whitespace();
+                    let (Some(index), Some(id), Some(version)) = (fields.next(), fields.next(), fields.next()) else {
+                        return Err(anyhow!("Invalid checksum image line: {}", line));
+                    };
+                    images.push(ChecksumImage {
+                        index: index
+                            .parse()
+                            .with_context(|| format!("Invalid image index: {}", index))?,
+                        id: id.to_string(),
+                        version: version.to_string(),
+                    });
+                }
+                // 忽略未知的键，便于以后扩展
+                _ => {}
+            }
+        }
+        Ok(Self {
+            sha256: sha256.ok_or_else(|| anyhow!("Checksum file has no sha256 line"))?,
+            file_name,
+            images,
+        })
+    }
+
+    /// 写入校验文件
+    ///
+    /// # 参数
+    ///
+    /// - `path` - 校验文件路径
+    pub fn write(&self, path: &Path) -> Result<()> {
+        fs::write(path, self.to_text()).with_context(|| format!("Write checksum file failed: {}", path.display()))
+    }
+
+    /// 读取校验文件
+    ///
+    /// # 参数
+    ///
+    /// - `path` - 校验文件路径
+    pub fn read(path: &Path) -> Result<Self> {
+        let text =
+            fs::read_to_string(path).with_context(|| format!("Read checksum file failed: {}", path.display()))?;
+        Self::from_text(&text).with_context(|| format!("Parse checksum file failed: {}", path.display()))
+    }
+
+    /// 判断补丁文件哈希是否与记录的哈希一致（不区分大小写）
+    pub fn matches(&self, sha256: &str) -> bool {
+        self.sha256.eq_ignore_ascii_case(sha256)
+    }
+}
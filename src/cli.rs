@@ -1,3 +1,5 @@
+use crate::patch::{DEFAULT_IO_RETRIES, DEFAULT_MAX_CHAIN_LENGTH};
+use crate::utils::normalize_guid;
 use clap::{Parser, Subcommand, ValueEnum};
 use semver::Version;
 use std::path::PathBuf;
@@ -33,15 +35,42 @@ pub struct App {
     #[clap(long)]
     pub(crate) debug: bool,
 
+    /// 静默模式
+    #[clap(help = "Suppress all output except errors and requested reports, and disable progress bars")]
+    #[clap(short, long)]
+    pub(crate) quiet: bool,
+
     /// 临时目录路径
     #[clap(help = "Scratch directory")]
     #[clap(long)]
     pub(crate) scratchdir: Option<PathBuf>,
 
+    /// 非终端环境下输出进度汇总的间隔（单位：秒）
+    #[clap(
+        help = "When output is not a terminal, print a progress summary every N seconds instead of one line per file"
+    )]
+    #[clap(long, value_parser = clap::value_parser!(u64).range(1..))]
+    pub(crate) progress_interval: Option<u64>,
+
     /// 设置程序语言
-    #[clap(help = "Set program language")]
-    #[clap(long, value_enum)]
-    pub(crate) language: Option<Language>,
+    #[clap(help = "Set program language [possible values: en, zh-cn, zh-tw, ja-jp]")]
+    #[clap(long)]
+    pub(crate) language: Option<String>,
+
+    /// 自定义翻译文件路径
+    #[clap(help = "Load translations from an external locale file (same structure as the built-in locales)")]
+    #[clap(long, value_parser = exist_file_parser)]
+    pub(crate) locale_file: Option<PathBuf>,
+
+    /// wimgapi.dll 路径
+    #[clap(help = "Load wimgapi.dll from this path instead of searching the default locations")]
+    #[clap(long, value_parser = exist_file_parser)]
+    pub(crate) wimgapi_path: Option<PathBuf>,
+
+    /// 临时文件名随机部分的长度
+    #[clap(help = "Length of the random part of temporary file and mount directory names [default: 10]")]
+    #[clap(long, value_parser = clap::value_parser!(u64).range(4..=32))]
+    pub(crate) temp_name_length: Option<u64>,
 }
 
 #[derive(Parser, Debug)]
@@ -68,15 +97,32 @@ pub struct Intrinsic {
     pub(crate) scratchdir: Option<PathBuf>,
 
     /// 设置程序语言
-    #[clap(help = "Set program language")]
-    #[clap(long, value_enum)]
-    pub(crate) language: Option<Language>,
+    #[clap(help = "Set program language [possible values: en, zh-cn, zh-tw, ja-jp]")]
+    #[clap(long)]
+    pub(crate) language: Option<String>,
+
+    /// 自定义翻译文件路径
+    #[clap(help = "Load translations from an external locale file (same structure as the built-in locales)")]
+    #[clap(long, value_parser = exist_file_parser)]
+    pub(crate) locale_file: Option<PathBuf>,
+
+    /// wimgapi.dll 路径
+    #[clap(help = "Load wimgapi.dll from this path instead of searching the default locations")]
+    #[clap(long, value_parser = exist_file_parser)]
+    pub(crate) wimgapi_path: Option<PathBuf>,
+
+    /// 临时文件名随机部分的长度
+    #[clap(help = "Length of the random part of temporary file and mount directory names [default: 10]")]
+    #[clap(long, value_parser = clap::value_parser!(u64).range(4..=32))]
+    pub(crate) temp_name_length: Option<u64>,
 }
 
 #[derive(Subcommand, Debug)]
 pub enum IntrinsicCommands {
     Create,
     Apply,
+    Merge,
+    Info,
 }
 
 #[derive(Subcommand, Debug)]
@@ -95,7 +141,12 @@ pub enum Commands {
 
         /// 源镜像索引
         #[clap(help = "Index of the image in the base wim file")]
-        #[arg(long = "base-index", requires = "target_index", conflicts_with = "index")]
+        #[arg(
+            long = "base-index",
+            group = "base_select",
+            requires = "target_select",
+            conflicts_with = "index"
+        )]
         base_index: Option<u32>,
 
         /// 更新镜像文件路径
@@ -105,17 +156,32 @@ pub enum Commands {
 
         /// 更新镜像索引
         #[clap(help = "Index of the image in the target wim file")]
-        #[arg(long = "target-index", requires = "base_index", conflicts_with = "index")]
+        #[arg(
+            long = "target-index",
+            group = "target_select",
+            requires = "base_select",
+            conflicts_with = "index"
+        )]
         target_index: Option<u32>,
 
+        /// 源镜像名称
+        #[clap(help = "Select the base image by name or display name instead of index (e.g. \"Windows 11 Pro\")")]
+        #[arg(long, group = "base_select", requires = "target_select", conflicts_with_all = ["index", "base_index"])]
+        base_name: Option<String>,
+
+        /// 更新镜像名称
+        #[clap(help = "Select the target image by name or display name instead of index")]
+        #[arg(long, group = "target_select", requires = "base_select", conflicts_with_all = ["index", "target_index"])]
+        target_name: Option<String>,
+
         /// 输出补丁文件路径
         #[clap(help = "Out patch file path")]
         #[clap(short, long)]
         out: PathBuf,
 
-        /// 压缩算法
-        #[clap(help = "Compression algorithm")]
-        #[clap(short, long, value_enum, default_value_t = Compress::Lzx)]
+        /// 压缩算法（补丁包需要挂载，不支持 LZMS）
+        #[clap(help = "Compression algorithm: none, xpress or lzx")]
+        #[clap(short, long, default_value = "lzx", value_parser = parse_patch_compress)]
         compress: Compress,
 
         /// 存储类型
@@ -152,14 +218,214 @@ pub enum Commands {
         #[clap(help = "Exclude files from the patch file")]
         #[clap(short, long)]
         exclude: Option<Vec<String>>,
+
+        /// 仅包含路径中含有该项的文件
+        #[clap(
+            help = "Only keep files whose path contains this item (case-insensitive, repeatable); --exclude takes precedence"
+        )]
+        #[clap(long)]
+        include: Option<Vec<String>>,
+
+        /// 从文件读取包含路径列表
+        #[clap(
+            help = "Read additional --include items from a file (one per line, blank lines and # comments are ignored)"
+        )]
+        #[clap(long, value_parser = exist_file_parser)]
+        include_from: Option<PathBuf>,
+
+        /// 仅评估补丁内容
+        #[clap(help = "Only report the operations and estimated size without creating the patch file")]
+        #[clap(long)]
+        dry_run: bool,
+
+        /// 补丁数据大小上限（单位：字节）
+        #[clap(help = "Abort if the staged patch payload exceeds this size in bytes")]
+        #[clap(long)]
+        max_patch_size: Option<u64>,
+
+        /// 忽略空目录
+        #[clap(help = "Skip added directories that contain no changed files")]
+        #[clap(long)]
+        skip_empty_dirs: bool,
+
+        /// 可启动镜像索引
+        #[clap(
+            help = "Set the bootable image index of the patch file (0 clears the bootable flag, defaults to preserving the bootable image of the base image)"
+        )]
+        #[clap(long)]
+        boot_index: Option<u32>,
+
+        /// 捕获补丁镜像时额外排除的路径
+        #[clap(help = "Additional paths to exclude when capturing the patch image (case-insensitive substring match)")]
+        #[clap(long)]
+        capture_exclude: Option<Vec<String>>,
+
+        /// 不使用默认的捕获排除列表
+        #[clap(help = "Do not exclude the default system paths ($ntfs.log, pagefile.sys, etc.) when capturing")]
+        #[clap(long)]
+        no_default_exclude: bool,
+
+        /// 补丁清单格式
+        #[clap(help = "Patch manifest format (binary also embeds a compact manifest for huge patches)")]
+        #[clap(long, value_enum, default_value_t = ManifestFormat::Xml)]
+        manifest_format: ManifestFormat,
+
+        /// 差异存储时同时嵌入完整文件
+        #[clap(
+            help = "Also embed the full file for every Zstd/Bsdiff modified file, used when the diff fails to apply (larger patch)"
+        )]
+        #[clap(long)]
+        embed_full_fallback: bool,
+
+        /// 跳过临时目录剩余空间检查
+        #[clap(help = "Skip the free space check of the scratch directory")]
+        #[clap(long)]
+        skip_space_check: bool,
+
+        /// 覆盖补丁清单时间戳，用于可复现构建
+        #[clap(help = "Override the manifest timestamp (RFC3339 or unix epoch, defaults to SOURCE_DATE_EPOCH if set)")]
+        #[clap(long, value_parser = parse_source_date)]
+        source_date: Option<i64>,
+
+        /// 修改文件的比较方式
+        #[clap(help = "How to detect modified files: meta (size+mtime), bytes (byte compare) or hash (SHA-256)")]
+        #[clap(long, value_enum, default_value_t = CompareMode::Bytes)]
+        compare_mode: CompareMode,
+
+        /// 以压缩形式暂存完整文件
+        #[clap(help = "Stage full-file payloads zstd-compressed until capture to save scratch space")]
+        #[clap(long)]
+        stage_compressed: bool,
+
+        /// 自动匹配多个镜像索引时同时构建的最大镜像数量
+        #[clap(help = "Maximum number of image indices built at once when indices are auto-matched")]
+        #[clap(long, default_value_t = 1)]
+        concurrency: usize,
+
+        /// 补丁文件已存在时的处理方式
+        #[clap(help = "What to do when the patch file already exists")]
+        #[clap(long, value_enum, default_value_t = OnExisting::Error)]
+        on_existing: OnExisting,
+
+        /// 追加到已存在的补丁文件
+        #[clap(help = "Same as --on-existing append")]
+        #[clap(long, conflicts_with_all = ["overwrite", "on_existing"])]
+        append: bool,
+
+        /// 覆盖已存在的补丁文件
+        #[clap(help = "Same as --on-existing overwrite")]
+        #[clap(long, conflicts_with = "on_existing")]
+        overwrite: bool,
+
+        /// 自动匹配索引时镜像数量不同则报错
+        #[clap(help = "Fail instead of skipping unmatched indices when auto-matching images with different counts")]
+        #[clap(long)]
+        strict_index: bool,
+
+        /// 补丁文件校验文件输出路径
+        #[clap(
+            help = "Write a checksum file with the SHA-256 of the patch file and each image's manifest id and version"
+        )]
+        #[clap(long)]
+        emit_checksum: Option<PathBuf>,
+
+        /// 记录并保留文件的 NTFS 备用数据流
+        #[clap(help = "Record NTFS alternate data streams of added and modified files and re-create them on apply")]
+        #[clap(long)]
+        preserve_streams: bool,
+
+        /// 排除每次启动都会变化的易变文件
+        #[clap(
+            help = "Exclude volatile files that change on every boot (*.evtx, *.etl, Prefetch\\*, *.LOG1, *.LOG2, *.regtrans-ms, *.blf)"
+        )]
+        #[clap(long)]
+        exclude_volatile: bool,
+
+        /// zstd 差异的字典窗口大小（单位：字节）
+        #[clap(
+            help = "Use only the last N bytes of each old file as the zstd dictionary (faster on large files, larger diffs)"
+        )]
+        #[clap(long)]
+        dict_window: Option<u64>,
+
+        /// zstd 压缩级别（代替压缩预设对应的级别）
+        #[clap(help = "Use this zstd level (0-22) for diffs instead of the level derived from --preset")]
+        #[clap(long, value_parser = parse_zstd_level)]
+        zstd_level: Option<i32>,
+
+        /// 仅比较镜像中的该子目录（相对于镜像根目录）
+        #[clap(
+            help = "Only compare this subdirectory of both images (relative to the image root, e.g. Windows\\System32)"
+        )]
+        #[clap(long, value_parser = parse_subtree)]
+        subtree: Option<String>,
+
+        /// 创建结果摘要（JSON）文件路径
+        #[clap(
+            help = "Write per-index operation counts, payload bytes, storage and manifest id/version to this JSON file (also on failure)"
+        )]
+        #[clap(long)]
+        summary_json: Option<PathBuf>,
+
+        /// 捕获补丁镜像时不处理安全描述符
+        #[clap(help = "Skip file and directory security descriptors (ACLs) when capturing the patch image")]
+        #[clap(long)]
+        no_acl: bool,
+
+        /// 注册表配置单元按键和值生成结构化差异
+        #[clap(
+            help = "Store recognized registry hives as key/value-level diffs (storage \"hive\"), falling back to --storage if parsing fails"
+        )]
+        #[clap(long)]
+        hive_diff: bool,
+
+        /// 比较目录时不区分路径大小写
+        #[clap(
+            help = "Match paths case-insensitively when comparing, so case-only renames become modifies instead of delete+add"
+        )]
+        #[clap(long)]
+        compare_ignore_case: bool,
+
+        /// 比较目录时跟随目录重解析点的最大嵌套层数
+        #[clap(
+            help = "Follow directory junctions and symlinks that point inside the image when comparing, up to this many nested levels (0 = record them as entries); loops are skipped with a warning"
+        )]
+        #[clap(long, default_value_t = 0)]
+        compare_follow_reparse_depth: u32,
+
+        /// 比较目录时输出被判定为相同的文件
+        #[clap(
+            help = "Also log files present in both trees that were deemed identical, to diagnose comparison results"
+        )]
+        #[clap(long)]
+        report_unchanged: bool,
+
+        /// 签名私钥文件路径
+        #[clap(
+            help = "Sign each patch image's manifest and payload hashes with this Ed25519 private key (Base64 32-byte seed)"
+        )]
+        #[clap(long, value_parser = exist_file_parser)]
+        sign_key: Option<PathBuf>,
     },
 
     /// Apply image patch file
     Apply {
-        /// 源镜像文件路径
-        #[clap(help = "Original wim image file path")]
-        #[clap(short, long, value_parser = exist_file_parser)]
-        base: PathBuf,
+        /// 源镜像文件路径（可多次指定，依次应用同一补丁）
+        #[clap(
+            help = "Original wim image file path (repeat to apply the same patch to several base images; use {stem} in --target)"
+        )]
+        #[clap(short, long, value_parser = exist_file_parser, required_unless_present = "base_list")]
+        base: Vec<PathBuf>,
+
+        /// 源镜像列表文件
+        #[clap(help = "Read additional base image paths from a file, one per line (# starts a comment)")]
+        #[clap(long, value_parser = exist_file_parser)]
+        base_list: Option<PathBuf>,
+
+        /// 某个源镜像应用失败后继续应用其余源镜像
+        #[clap(help = "With several base images, continue with the remaining bases after one fails")]
+        #[clap(long)]
+        keep_going: bool,
 
         /// 补丁文件路径
         #[clap(help = "Patch file path")]
@@ -167,24 +433,198 @@ pub enum Commands {
         patch: PathBuf,
 
         /// 目标镜像文件路径
-        #[clap(help = "Output image path after applying patch (target image)")]
-        #[clap(short, long)]
-        target: PathBuf,
+        #[clap(
+            help = "Output image path after applying patch (target image), {stem} is replaced by the base image file name"
+        )]
+        #[clap(short, long, required_unless_present = "no_export")]
+        target: Option<PathBuf>,
 
         /// 源镜像索引
         #[clap(help = "Index of the image in the base wim file")]
         #[clap(short, long)]
         index: Option<u32>,
 
+        /// 源镜像名称
+        #[clap(help = "Select the base image by name or display name instead of index")]
+        #[clap(long, conflicts_with = "index")]
+        base_name: Option<String>,
+
         /// 排除文件
         #[clap(help = "Exclude files from the patch file")]
         #[clap(short, long)]
         exclude: Option<Vec<String>>,
 
+        /// 仅包含路径中含有该项的文件
+        #[clap(
+            help = "Only keep files whose path contains this item (case-insensitive, repeatable); --exclude takes precedence"
+        )]
+        #[clap(long)]
+        include: Option<Vec<String>>,
+
+        /// 从文件读取包含路径列表
+        #[clap(
+            help = "Read additional --include items from a file (one per line, blank lines and # comments are ignored)"
+        )]
+        #[clap(long, value_parser = exist_file_parser)]
+        include_from: Option<PathBuf>,
+
         /// 强制应用补丁
         #[clap(help = "Force apply patch")]
         #[clap(short, long)]
         force: bool,
+
+        /// 仅应用到指定版本
+        #[clap(help = "Only apply patches up to (and including) the specified version")]
+        #[clap(long, value_parser = parse_version)]
+        up_to_version: Option<Version>,
+
+        /// 跳过不高于指定版本的补丁
+        #[clap(help = "Skip patches up to (and including) this version; the base is already updated to it")]
+        #[clap(long, value_parser = parse_version)]
+        from_version: Option<Version>,
+
+        /// 导出前暂停以检查应用结果
+        #[clap(help = "Mount the applied image read-only and wait for Enter before exporting")]
+        #[clap(long)]
+        pause_before_export: bool,
+
+        /// 不检查补丁的工具版本
+        #[clap(
+            help = "Do not check whether the patches were created by a newer WimPatch (a newer major version otherwise requires --force)"
+        )]
+        #[clap(long)]
+        no_tool_version_check: bool,
+
+        /// 跳过临时目录剩余空间检查
+        #[clap(help = "Skip the free space check of the scratch directory")]
+        #[clap(long)]
+        skip_space_check: bool,
+
+        /// 文件被占用时的最大重试次数
+        #[clap(help = "Retry count when a file is temporarily locked (sharing violation)")]
+        #[clap(long, default_value_t = DEFAULT_IO_RETRIES)]
+        io_retries: u32,
+
+        /// 代替基础镜像实际 GUID 匹配补丁
+        #[clap(help = "Match patches against this GUID instead of the actual base GUID (for re-captured images)")]
+        #[clap(long, value_parser = parse_guid)]
+        base_guid: Option<String>,
+
+        /// 为目标镜像写入完整性信息
+        #[clap(help = "Write integrity information to the target image (WIM_FLAG_VERIFY, slower export)")]
+        #[clap(long)]
+        verify: bool,
+
+        /// 应用前从操作路径开头去除的路径层数
+        #[clap(help = "Strip the given number of leading path components from each patched path (like tar)")]
+        #[clap(long, default_value_t = 0)]
+        strip_components: usize,
+
+        /// 应用前添加到操作路径开头的路径前缀
+        #[clap(help = "Prepend this relative path to each patched path (applied after --strip-components)")]
+        #[clap(long)]
+        path_prefix: Option<String>,
+
+        /// 应用结果报告文件路径
+        #[clap(
+            help = "Write an apply summary to this file, also on failure (JSON if the extension is .json, otherwise text)"
+        )]
+        #[clap(long)]
+        report: Option<PathBuf>,
+
+        /// 目标镜像保留基础镜像的创建时间
+        #[clap(
+            help = "Keep the base image's creation time in the target image (the last modification time is still updated)"
+        )]
+        #[clap(long)]
+        preserve_creation_time: bool,
+
+        /// 跳过所有删除操作，仅应用新增和修改操作
+        #[clap(help = "Skip all delete operations and only apply adds and modifies")]
+        #[clap(long)]
+        no_delete: bool,
+
+        /// 应用前校验补丁文件哈希的校验文件路径
+        #[clap(help = "Verify the patch file's SHA-256 against a checksum file written by create --emit-checksum")]
+        #[clap(long, value_parser = exist_file_parser)]
+        check_checksum: Option<PathBuf>,
+
+        /// 补丁链的最大长度
+        #[clap(help = "Maximum number of patches in a chain, longer chains are rejected (0 disables the limit)")]
+        #[clap(long, default_value_t = DEFAULT_MAX_CHAIN_LENGTH)]
+        max_chain_length: usize,
+
+        /// 基础镜像匹配模式
+        #[clap(help = "How permissive base matching is when only the GUID or only the image statistics match")]
+        #[clap(long, value_enum, default_value_t = MatchMode::Exact)]
+        match_mode: MatchMode,
+
+        /// 目标镜像已存在时的处理方式
+        #[clap(help = "What to do when the target image already exists: error or overwrite")]
+        #[clap(long, default_value = "error", value_parser = parse_target_on_existing)]
+        on_existing: OnExisting,
+
+        /// 卸载前将应用结果复制到的目录
+        #[clap(
+            help = "Also copy the applied image contents to this directory before unmounting (one subdirectory per index if several images are applied; ACLs are not preserved)"
+        )]
+        #[clap(long)]
+        extract_to: Option<PathBuf>,
+
+        /// 不导出目标镜像
+        #[clap(help = "Do not export a target image, only write the applied contents to --extract-to")]
+        #[clap(long, requires = "extract_to", conflicts_with_all = ["target", "verify", "preserve_creation_time"])]
+        no_export: bool,
+
+        /// 成功导出目标镜像后执行的命令
+        #[clap(
+            help = "Command run through cmd /C after the target image is exported successfully; the target path is passed in %WIMPATCH_TARGET%, and a non-zero exit code becomes WimPatch's exit code"
+        )]
+        #[clap(long, conflicts_with = "no_export")]
+        post_apply: Option<String>,
+
+        /// 不复制基础镜像，直接修改基础镜像文件
+        #[clap(
+            help = "Apply directly to the base image file instead of a scratch copy; the patched images are committed into the base WIM, so only use it on a disposable copy"
+        )]
+        #[clap(long)]
+        no_base_copy: bool,
+
+        /// 释放与重新捕获整镜像补丁时不处理安全描述符
+        #[clap(
+            help = "Skip file and directory security descriptors (ACLs) when applying and re-capturing whole-image patches"
+        )]
+        #[clap(long)]
+        no_acl: bool,
+
+        /// 目标镜像的压缩算法
+        #[clap(help = "Compression algorithm of the exported target image")]
+        #[clap(long, value_enum, default_value_t = Compress::Lzx)]
+        target_compress: Compress,
+
+        /// 允许应用低于镜像已记录版本的补丁
+        #[clap(help = "Allow applying a patch older than the version already recorded in the base image (rollback)")]
+        #[clap(long)]
+        allow_downgrade: bool,
+
+        /// 文件操作的执行顺序
+        #[clap(
+            help = "Order of file operations: phased runs all deletes, then adds, then modifies (robust to type changes and renames); manifest keeps the recorded order"
+        )]
+        #[clap(long, value_enum, default_value_t = ApplyOrder::Phased)]
+        apply_order: ApplyOrder,
+
+        /// 受信任的签名者公钥文件路径
+        #[clap(
+            help = "Verify signed patch images against this Ed25519 public key (Base64) before applying; unsigned images only warn"
+        )]
+        #[clap(long, value_parser = exist_file_parser)]
+        verify_signature: Option<PathBuf>,
+
+        /// 拒绝未签名的补丁
+        #[clap(help = "Reject patch images that are not signed (requires --verify-signature)")]
+        #[clap(long, requires = "verify_signature")]
+        require_signature: bool,
     },
 
     /// Merge multiple incremental patches into one merge patch
@@ -198,9 +638,101 @@ pub enum Commands {
         #[clap(short, long)]
         out: PathBuf,
 
-        /// 压缩算法
-        #[clap(help = "Compression algorithm")]
-        #[clap(short, long, value_enum, default_value_t = Compress::Lzx)]
+        /// 压缩算法（补丁包需要挂载，不支持 LZMS）
+        #[clap(help = "Compression algorithm: none, xpress or lzx")]
+        #[clap(short, long, default_value = "lzx", value_parser = parse_patch_compress)]
+        compress: Compress,
+
+        /// 可启动镜像索引
+        #[clap(
+            help = "Set the bootable image index of the merged patch (0 clears the bootable flag, defaults to preserving the first bootable input)"
+        )]
+        #[clap(long)]
+        boot_index: Option<u32>,
+
+        /// 校验输入补丁包并为输出补丁包写入完整性信息
+        #[clap(help = "Verify inputs and write integrity information to the merged patch (WIM_FLAG_VERIFY, slower)")]
+        #[clap(long)]
+        verify: bool,
+
+        /// 读取合并后的补丁数据，报告跨补丁内容相同的数据（仅统计，不改写补丁）
+        #[clap(
+            help = "Read the merged payloads back and report identical payloads shared across patches (report only)"
+        )]
+        #[clap(long)]
+        report_shared_payloads: bool,
+
+        /// 保留输入补丁包的压缩类型
+        #[clap(
+            help = "Keep the compression type of the input patches instead of --compress to avoid recompressing images (inputs with a different type are still recompressed)"
+        )]
+        #[clap(long)]
+        keep_compression: bool,
+
+        /// 检测到冲突补丁时中止合并
+        #[clap(
+            help = "Abort instead of warning when two patches share the base GUID, index and version but contain different operations"
+        )]
+        #[clap(long)]
+        fail_on_conflict: bool,
+
+        /// 导出每个输入补丁包后重新解析合并后补丁包中对应镜像的补丁清单
+        #[clap(
+            help = "Re-read and parse the manifests of each input's images in the merged patch right after exporting them, stopping at the first invalid output"
+        )]
+        #[clap(long)]
+        verify_after_each: bool,
+
+        /// 将补丁链展平为单个补丁
+        #[clap(
+            help = "Apply the whole chain to --base and emit one patch from the base to the final image instead of stacking the patches"
+        )]
+        #[clap(long, requires = "base")]
+        flatten: bool,
+
+        /// 展平时补丁链起点的基础镜像
+        #[clap(help = "Base image the chain starts from, used with --flatten")]
+        #[clap(long, value_parser = exist_file_parser, requires = "flatten")]
+        base: Option<PathBuf>,
+
+        /// 展平时的基础镜像索引
+        #[clap(help = "Base image index to flatten, defaults to all matched images")]
+        #[clap(long, requires = "flatten")]
+        base_index: Option<u32>,
+
+        /// 展平时的存储类型
+        #[clap(
+            help = "Storage type of the flattened patch, defaults to the storage most used by the inputs' modify operations"
+        )]
+        #[clap(long, value_enum, requires = "flatten")]
+        storage: Option<Storage>,
+
+        /// 展平时的压缩级别
+        #[clap(help = "Compression level of the flattened patch, defaults to medium as for create")]
+        #[clap(long, value_enum, requires = "flatten")]
+        preset: Option<Preset>,
+
+        /// 展平时的补丁清单格式
+        #[clap(help = "Manifest format of the flattened patch, defaults to xml as for create")]
+        #[clap(long, value_enum, requires = "flatten")]
+        manifest_format: Option<ManifestFormat>,
+    },
+
+    /// Split a merged patch into one patch file per version
+    Split {
+        /// 补丁文件路径
+        #[clap(help = "Patch file path")]
+        #[clap(value_parser = exist_file_parser)]
+        patch: PathBuf,
+
+        /// 输出目录
+        #[clap(help = "Output directory, each patch is written as <version>_<base guid>.wim")]
+        #[clap(short, long)]
+        out_dir: PathBuf,
+
+        /// 压缩算法（补丁包需要挂载，不支持 LZMS）
+        #[clap(help = "Compression algorithm: none, xpress or lzx")]
+        #[clap(short, long, default_value = "lzx", value_parser = parse_patch_compress)]
         compress: Compress,
     },
 
@@ -214,10 +746,332 @@ pub enum Commands {
         #[clap(help = "Out print patch info as xml")]
         #[clap(short, long)]
         xml: bool,
+
+        /// 按基础镜像GUID过滤
+        #[clap(help = "Only show patches whose base image GUID matches")]
+        #[clap(short, long, value_parser = parse_guid)]
+        guid: Option<String>,
+
+        /// 显示操作的大小统计
+        #[clap(help = "Show size statistics of the operations (added/modified bytes, storage types, largest files)")]
+        #[clap(long)]
+        detailed: bool,
+
+        /// 列出的最大操作数量
+        #[clap(help = "Number of largest operations listed with --detailed")]
+        #[clap(long, default_value_t = 10, requires = "detailed")]
+        top: usize,
+
+        /// 显示信息前校验补丁文件哈希的校验文件路径
+        #[clap(help = "Verify the patch file's SHA-256 against a checksum file written by create --emit-checksum")]
+        #[clap(long, value_parser = exist_file_parser)]
+        check_checksum: Option<PathBuf>,
+
+        /// 逐行列出每个操作
+        #[clap(help = "List every operation (action, storage type, size and path), one per line")]
+        #[clap(long, conflicts_with_all = ["xml", "detailed"])]
+        list: bool,
+
+        /// 列出操作的排序方式
+        #[clap(help = "Sort order of the operations listed with --list")]
+        #[clap(long, value_enum, default_value_t = ListSort::Path, requires = "list")]
+        sort: ListSort,
+
+        /// 每个镜像列出的最大操作数量
+        #[clap(help = "Maximum number of operations listed per image with --list")]
+        #[clap(long, requires = "list")]
+        limit: Option<usize>,
+    },
+
+    /// Extract the embedded patch manifest to a file
+    ExtractManifest {
+        /// 补丁文件路径
+        #[clap(help = "Patch file path")]
+        #[clap(value_parser = exist_file_parser)]
+        patch: PathBuf,
+
+        /// 补丁镜像索引
+        #[clap(help = "Only extract the manifest of this image index (defaults to all, one file per index)")]
+        #[clap(short, long)]
+        index: Option<u32>,
+
+        /// 输出文件路径
+        #[clap(help = "Output file path (without --index, the image index is appended to the file name)")]
+        #[clap(short, long)]
+        out: PathBuf,
+
+        /// 输出JSON
+        #[clap(help = "Write the manifest as JSON instead of XML")]
+        #[clap(long)]
+        json: bool,
+    },
+
+    /// List the files of a wim image without mounting it
+    Tree {
+        /// 镜像文件路径
+        #[clap(help = "Wim image file path")]
+        #[clap(value_parser = exist_file_parser)]
+        image: PathBuf,
+
+        /// 镜像索引
+        #[clap(help = "Index of the image to list")]
+        #[clap(short, long, default_value_t = 1)]
+        index: u32,
+
+        /// 显示文件大小
+        #[clap(help = "Also show file sizes and mark directories (slower)")]
+        #[clap(long)]
+        sizes: bool,
+    },
+
+    /// Create a patch between two directories without any wim image
+    CreateDir {
+        /// 基础目录路径
+        #[clap(help = "Base directory path")]
+        #[clap(short, long, value_parser = exist_dir_parser)]
+        base_dir: PathBuf,
+
+        /// 更新目录路径
+        #[clap(help = "Target directory path")]
+        #[clap(short, long, value_parser = exist_dir_parser)]
+        target_dir: PathBuf,
+
+        /// 输出目录补丁路径
+        #[clap(help = "Out patch directory path (must not exist or be empty)")]
+        #[clap(short, long)]
+        out: PathBuf,
+
+        /// 存储类型
+        #[clap(help = "Storage type of the patch (whole-image is not supported)")]
+        #[clap(short = 's', long, value_enum, default_value_t = Storage::Zstd)]
+        storage: Storage,
+
+        /// 压缩级别
+        #[clap(help = "Compression level")]
+        #[clap(short = 'p', long, value_enum, default_value_t = Preset::Medium)]
+        preset: Preset,
+
+        /// 补丁版本
+        #[clap(help = "Version of the patch")]
+        #[clap(short, long, value_parser = parse_version)]
+        version: Version,
+
+        /// 补丁作者
+        #[clap(help = "Author of the patch")]
+        #[clap(short, long, default_value = "unknown")]
+        author: String,
+
+        /// 补丁名称
+        #[clap(help = "Name of the patch")]
+        #[clap(short, long)]
+        name: Option<String>,
+
+        /// 补丁描述
+        #[clap(help = "Description of the patch")]
+        #[clap(short, long)]
+        description: Option<String>,
+
+        /// 排除文件
+        #[clap(help = "Exclude files from the patch")]
+        #[clap(short, long)]
+        exclude: Option<Vec<String>>,
+
+        /// 仅包含路径中含有该项的文件
+        #[clap(
+            help = "Only keep files whose path contains this item (case-insensitive, repeatable); --exclude takes precedence"
+        )]
+        #[clap(long)]
+        include: Option<Vec<String>>,
+
+        /// 从文件读取包含路径列表
+        #[clap(
+            help = "Read additional --include items from a file (one per line, blank lines and # comments are ignored)"
+        )]
+        #[clap(long, value_parser = exist_file_parser)]
+        include_from: Option<PathBuf>,
+
+        /// 仅评估补丁内容
+        #[clap(help = "Only report the operations and estimated size without creating the patch")]
+        #[clap(long)]
+        dry_run: bool,
+
+        /// 忽略空目录
+        #[clap(help = "Skip added directories that contain no changed files")]
+        #[clap(long)]
+        skip_empty_dirs: bool,
+
+        /// 差异存储时同时嵌入完整文件
+        #[clap(
+            help = "Also embed the full file for every Zstd/Bsdiff modified file, used when the diff fails to apply"
+        )]
+        #[clap(long)]
+        embed_full_fallback: bool,
+
+        /// 覆盖补丁清单时间戳，用于可复现构建
+        #[clap(help = "Override the manifest timestamp (RFC3339 or unix epoch)")]
+        #[clap(long, value_parser = parse_source_date)]
+        source_date: Option<i64>,
+
+        /// 修改文件的比较方式
+        #[clap(help = "How to detect modified files: meta (size+mtime), bytes (byte compare) or hash (SHA-256)")]
+        #[clap(long, value_enum, default_value_t = CompareMode::Bytes)]
+        compare_mode: CompareMode,
+
+        /// 清空已存在的输出目录
+        #[clap(help = "Remove the contents of a non-empty output directory instead of failing")]
+        #[clap(long)]
+        overwrite: bool,
+
+        /// 记录并保留文件的 NTFS 备用数据流
+        #[clap(help = "Record NTFS alternate data streams of added and modified files and re-create them on apply")]
+        #[clap(long)]
+        preserve_streams: bool,
+
+        /// 排除每次启动都会变化的易变文件
+        #[clap(
+            help = "Exclude volatile files that change on every boot (*.evtx, *.etl, Prefetch\\*, *.LOG1, *.LOG2, *.regtrans-ms, *.blf)"
+        )]
+        #[clap(long)]
+        exclude_volatile: bool,
+
+        /// zstd 差异的字典窗口大小（单位：字节）
+        #[clap(
+            help = "Use only the last N bytes of each old file as the zstd dictionary (faster on large files, larger diffs)"
+        )]
+        #[clap(long)]
+        dict_window: Option<u64>,
+
+        /// zstd 压缩级别（代替压缩预设对应的级别）
+        #[clap(help = "Use this zstd level (0-22) for diffs instead of the level derived from --preset")]
+        #[clap(long, value_parser = parse_zstd_level)]
+        zstd_level: Option<i32>,
+
+        /// 注册表配置单元按键和值生成结构化差异
+        #[clap(
+            help = "Store recognized registry hives as key/value-level diffs (storage \"hive\"), falling back to --storage if parsing fails"
+        )]
+        #[clap(long)]
+        hive_diff: bool,
+
+        /// 比较目录时不区分路径大小写
+        #[clap(
+            help = "Match paths case-insensitively when comparing, so case-only renames become modifies instead of delete+add"
+        )]
+        #[clap(long)]
+        compare_ignore_case: bool,
+
+        /// 比较目录时跟随目录重解析点的最大嵌套层数
+        #[clap(
+            help = "Follow directory junctions and symlinks that point inside the image when comparing, up to this many nested levels (0 = record them as entries); loops are skipped with a warning"
+        )]
+        #[clap(long, default_value_t = 0)]
+        compare_follow_reparse_depth: u32,
+
+        /// 比较目录时输出被判定为相同的文件
+        #[clap(
+            help = "Also log files present in both trees that were deemed identical, to diagnose comparison results"
+        )]
+        #[clap(long)]
+        report_unchanged: bool,
+
+        /// 签名私钥文件路径
+        #[clap(
+            help = "Sign the directory patch's manifest and payload hashes with this Ed25519 private key (Base64 32-byte seed), written to signature.xml"
+        )]
+        #[clap(long, value_parser = exist_file_parser)]
+        sign_key: Option<PathBuf>,
+    },
+
+    /// Apply a directory patch created by create-dir to a directory
+    ApplyDir {
+        /// 基础目录路径
+        #[clap(help = "Base directory path")]
+        #[clap(short, long, value_parser = exist_dir_parser)]
+        base_dir: PathBuf,
+
+        /// 目录补丁路径
+        #[clap(help = "Patch directory path created by create-dir")]
+        #[clap(short, long, value_parser = exist_dir_parser)]
+        patch: PathBuf,
+
+        /// 输出目录路径
+        #[clap(help = "Output directory (must not exist or be empty; the same path as --base-dir patches in place)")]
+        #[clap(short, long)]
+        out_dir: PathBuf,
+
+        /// 排除文件
+        #[clap(help = "Exclude files from the patch")]
+        #[clap(short, long)]
+        exclude: Option<Vec<String>>,
+
+        /// 仅包含路径中含有该项的文件
+        #[clap(
+            help = "Only keep files whose path contains this item (case-insensitive, repeatable); --exclude takes precedence"
+        )]
+        #[clap(long)]
+        include: Option<Vec<String>>,
+
+        /// 从文件读取包含路径列表
+        #[clap(
+            help = "Read additional --include items from a file (one per line, blank lines and # comments are ignored)"
+        )]
+        #[clap(long, value_parser = exist_file_parser)]
+        include_from: Option<PathBuf>,
+
+        /// 强制应用补丁
+        #[clap(help = "Force apply patch")]
+        #[clap(short, long)]
+        force: bool,
+
+        /// 文件被占用时的最大重试次数
+        #[clap(help = "Retry count when a file is temporarily locked (sharing violation)")]
+        #[clap(long, default_value_t = DEFAULT_IO_RETRIES)]
+        io_retries: u32,
+
+        /// 跳过所有删除操作，仅应用新增和修改操作
+        #[clap(help = "Skip all delete operations and only apply adds and modifies")]
+        #[clap(long)]
+        no_delete: bool,
+
+        /// 文件操作的执行顺序
+        #[clap(
+            help = "Order of file operations: phased runs all deletes, then adds, then modifies (robust to type changes and renames); manifest keeps the recorded order"
+        )]
+        #[clap(long, value_enum, default_value_t = ApplyOrder::Phased)]
+        apply_order: ApplyOrder,
+
+        /// 受信任的签名者公钥文件路径
+        #[clap(
+            help = "Verify a signed directory patch against this Ed25519 public key (Base64) before applying; unsigned patches only warn"
+        )]
+        #[clap(long, value_parser = exist_file_parser)]
+        verify_signature: Option<PathBuf>,
+
+        /// 拒绝未签名的补丁
+        #[clap(help = "Reject directory patches that are not signed (requires --verify-signature)")]
+        #[clap(long, requires = "verify_signature")]
+        require_signature: bool,
     },
 
     /// Cleanup invalid mount
     Clean {},
+
+    /// Run a self-test that creates and applies a synthetic patch
+    SelfTest {},
+
+    /// Compare patch size and time of each storage method on a pair of sample files
+    #[command(hide = true)]
+    Benchmark {
+        /// 旧文件路径
+        #[clap(help = "Old (base) sample file path")]
+        #[clap(value_parser = exist_file_parser)]
+        old: PathBuf,
+
+        /// 新文件路径
+        #[clap(help = "New (target) sample file path")]
+        #[clap(value_parser = exist_file_parser)]
+        new: PathBuf,
+    },
 }
 
 /// Compression preset
@@ -242,6 +1096,76 @@ pub enum Storage {
     Zstd,
     /// BSDiff differential storage
     Bsdiff,
+    /// Whole target image, applied with WIMApplyImage instead of per-file copying
+    WholeImage,
+}
+
+/// Patch manifest format
+#[derive(Debug, Clone, ValueEnum, PartialEq, Copy)]
+pub enum ManifestFormat {
+    /// XML manifest only
+    Xml,
+    /// XML manifest plus a compact binary manifest for faster parsing
+    Binary,
+}
+
+/// File comparison mode used to detect modified files
+#[derive(Debug, Clone, ValueEnum, PartialEq, Copy)]
+pub enum CompareMode {
+    /// Trust size and modification time only (fastest, misses changes that keep both)
+    Meta,
+    /// Compare contents byte by byte when size and modification time match (reads both files)
+    Bytes,
+    /// Compare SHA-256 hashes when sizes match (ignores modification time, one pass per file)
+    Hash,
+}
+
+/// How permissive base image matching is when the GUID or the image statistics differ
+#[derive(Debug, Clone, ValueEnum, PartialEq, Copy, Default)]
+pub enum MatchMode {
+    /// Require both the base image GUID and the image statistics to match
+    #[default]
+    Exact,
+    /// Also accept a matching GUID whose image statistics differ
+    Guid,
+    /// Also accept matching image statistics with a different GUID (e.g. a re-captured image)
+    Stats,
+    /// Accept either a matching GUID or matching image statistics
+    Any,
+}
+
+/// Order in which file operations are applied
+#[derive(Debug, Clone, Copy, ValueEnum, PartialEq, Default)]
+pub enum ApplyOrder {
+    /// Apply all deletes first, then adds, then modifies, keeping the recorded order within each phase
+    #[default]
+    Phased,
+    /// Apply operations in the order recorded in the patch manifest
+    Manifest,
+}
+
+/// Sort order of the operations listed by info --list
+#[derive(Debug, Clone, ValueEnum, PartialEq, Copy, Default)]
+pub enum ListSort {
+    /// Sort by path
+    #[default]
+    Path,
+    /// Sort by size, largest first
+    Size,
+    /// Group by action (add, modify, delete), then by path
+    Action,
+}
+
+/// What to do when the output file already exists
+#[derive(Debug, Clone, ValueEnum, PartialEq, Copy, Default)]
+pub enum OnExisting {
+    /// Refuse to touch the existing file
+    #[default]
+    Error,
+    /// Replace the existing file
+    Overwrite,
+    /// Append to the existing file (create only)
+    Append,
 }
 
 /// Compression algorithm
@@ -253,6 +1177,8 @@ pub enum Compress {
     Xpress,
     /// Lzx compression
     Lzx,
+    /// Lzms compression (smallest and slowest)
+    Lzms,
 }
 
 /// 用于 clap 参数解析：验证路径必须为已存在文件。
@@ -311,3 +1237,97 @@ fn parse_version(s: &str) -> Result<Version, semver::Error> {
     let v = Version::parse(s)?;
     Ok(v)
 }
+
+/// 用于 clap 参数解析：验证字符串是否为有效的 GUID，并规范化为标准 UUID 格式。
+///
+/// # 参数:
+/// - `s`: 命令行中传入的 GUID 字符串。
+///
+/// # 返回值:
+/// - `Ok(String)`: 规范化后的 GUID 字符串。
+/// - `Err(String)`: 如果解析失败，返回错误信息。
+fn parse_guid(s: &str) -> Result<String, String> {
+    normalize_guid(s).ok_or_else(|| format!("Invalid GUID: {}", s))
+}
+
+/// 用于 clap 参数解析：目标镜像已存在时的处理方式，应用补丁不支持追加。
+///
+/// # 参数:
+/// - `s`: 命令行中传入的处理方式。
+///
+/// # 返回值:
+/// - `Ok(OnExisting)`: `error` 或 `overwrite`。
+/// - `Err(String)`: 如果不是 `error` 或 `overwrite`，返回错误信息。
+fn parse_target_on_existing(s: &str) -> Result<OnExisting, String> {
+    match OnExisting::from_str(s, true) {
+        Ok(OnExisting::Append) | Err(_) => Err(format!("Invalid value (expected error or overwrite): {}", s)),
+        Ok(on_existing) => Ok(on_existing),
+    }
+}
+
+/// 用于 clap 参数解析：补丁包的压缩算法，补丁包在应用时需要挂载，不支持 LZMS。
+///
+/// # 参数:
+/// - `s`: 命令行中传入的压缩算法。
+///
+/// # 返回值:
+/// - `Ok(Compress)`: `none`、`xpress` 或 `lzx`。
+/// - `Err(String)`: 如果不是 `none`、`xpress` 或 `lzx`，返回错误信息。
+pub fn parse_patch_compress(s: &str) -> Result<Compress, String> {
+    match Compress::from_str(s, true) {
+        Ok(Compress::Lzms) | Err(_) => Err(format!("Invalid value (expected none, xpress or lzx): {}", s)),
+        Ok(compress) => Ok(compress),
+    }
+}
+
+/// 用于 clap 参数解析：验证 zstd 压缩级别是否在 0 到 22 之间。
+///
+/// # 参数:
+/// - `s`: 命令行中传入的压缩级别。
+///
+/// # 返回值:
+/// - `Ok(i32)`: 压缩级别。
+/// - `Err(String)`: 如果不是整数或超出范围，返回错误信息。
+pub fn parse_zstd_level(s: &str) -> Result<i32, String> {
+    match s.trim().parse::<i32>() {
+        Ok(level) if (0..=22).contains(&level) => Ok(level),
+        _ => Err(format!("Invalid zstd level (expected 0-22): {}", s)),
+    }
+}
+
+/// 用于 clap 参数解析：将子目录规范化为镜像内的相对路径。
+///
+/// # 参数:
+/// - `s`: 命令行中传入的子目录，可使用 `/` 或 `\` 分隔。
+///
+/// # 返回值:
+/// - `Ok(String)`: 以 `\` 分隔、不含首尾分隔符的相对路径。
+/// - `Err(String)`: 如果为空、为绝对路径或包含 `.` / `..`，返回错误信息。
+pub fn parse_subtree(s: &str) -> Result<String, String> {
+    let trimmed = s.trim();
+    if trimmed.contains(':') || trimmed.starts_with(['\\', '/']) {
+        return Err(format!("Subtree must be relative to the image root: {}", s));
+    }
+    let parts: Vec<&str> = trimmed.split(['\\', '/']).filter(|part| !part.is_empty()).collect();
+    if parts.is_empty() || parts.iter().any(|part| *part == "." || *part == "..") {
+        return Err(format!("Invalid subtree: {}", s));
+    }
+    Ok(parts.join("\\"))
+}
+
+/// 用于 clap 参数解析：将 RFC3339 时间或 Unix 时间戳解析为 Unix 时间（秒）。
+///
+/// # 参数:
+/// - `s`: 命令行或 `SOURCE_DATE_EPOCH` 环境变量中的时间字符串。
+///
+/// # 返回值:
+/// - `Ok(i64)`: Unix 时间（秒）。
+/// - `Err(String)`: 如果解析失败，返回错误信息。
+pub fn parse_source_date(s: &str) -> Result<i64, String> {
+    if let Ok(secs) = s.trim().parse::<i64>() {
+        return Ok(secs);
+    }
+    chrono::DateTime::parse_from_rfc3339(s.trim())
+        .map(|dt| dt.timestamp())
+        .map_err(|_| format!("Invalid source date (expected RFC3339 or unix epoch): {}", s))
+}
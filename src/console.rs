@@ -1,3 +1,4 @@
+use crate::{is_debug, is_quiet};
 use console::style;
 use rust_i18n::t;
 
@@ -15,6 +16,21 @@ pub enum ConsoleType {
 }
 
 pub fn write_console(console_type: ConsoleType, message: &str) {
+    // 静默模式下仅向标准错误输出错误信息
+    if is_quiet() {
+        if let ConsoleType::Error = console_type {
+            eprintln!(" {}      {}", style(t!("console.error")).red(), message);
+        }
+        return;
+    }
+
+    // 调试信息仅在调试模式下输出
+    if let ConsoleType::Debug = console_type
+        && !is_debug()
+    {
+        return;
+    }
+
     let title = match &console_type {
         ConsoleType::Info => style(t!("console.info")).cyan(),
         ConsoleType::Success => style(t!("console.success")).green(),
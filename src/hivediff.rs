@@ -0,0 +1,825 @@
+use crate::utils::get_tmp_path;
+use crate::wimgapi::win32_error_message;
+use anyhow::{Context, Result, anyhow};
+use libloading::Library;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::ffi::{OsStr, c_void};
+use std::fs;
+use std::fs::File;
+use std::io::Read;
+use std::os::windows::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::ptr::{null, null_mut};
+
+/// 注册表配置单元事务日志的扩展名（不区分大小写）
+const HIVE_LOG_EXTENSIONS: [&str; 3] = ["LOG", "LOG1", "LOG2"];
+
+/// 按键和值生成结构化差异的注册表配置单元文件名（不区分大小写）
+pub const HIVE_FILE_NAMES: [&str; 11] = [
+    "SOFTWARE",
+    "SYSTEM",
+    "SAM",
+    "SECURITY",
+    "DEFAULT",
+    "COMPONENTS",
+    "DRIVERS",
+    "ELAM",
+    "BCD-Template",
+    "NTUSER.DAT",
+    "UsrClass.dat",
+];
+
+/// 结构化差异文件格式版本
+const HIVE_PATCH_VERSION: u32 = 1;
+
+/// 保存重建的配置单元时使用的 Windows 版本（所有版本的 offreg.dll 均支持，Windows 7 及以后的系统均可加载）
+const HIVE_SAVE_VERSION: (u32, u32) = (6, 1);
+
+/// 读取与设置键的安全描述符时包含的部分：所有者、组、DACL 与 SACL
+const KEY_SECURITY_INFORMATION: u32 = 0x0000_000F;
+
+/// ERROR_SUCCESS
+const ERROR_SUCCESS: u32 = 0;
+
+type OrHkey = *mut c_void;
+
+type DosfOrOpenHive = unsafe extern "system" fn(lpHivePath: *const u16, phkResult: *mut OrHkey) -> u32;
+type DosfOrCloseHive = unsafe extern "system" fn(Handle: OrHkey) -> u32;
+type DosfOrSaveHive = unsafe extern "system" fn(
+    AuthorHandle: OrHkey,
+    lpHivePath: *const u16,
+    dwOsMajorVersion: u32,
+    dwOsMinorVersion: u32,
+) -> u32;
+type DosfOrOpenKey = unsafe extern "system" fn(Handle: OrHkey, lpSubKey: *const u16, phkResult: *mut OrHkey) -> u32;
+type DosfOrCloseKey = unsafe extern "system" fn(Handle: OrHkey) -> u32;
+type DosfOrCreateKey = unsafe extern "system" fn(
+    Handle: OrHkey,
+    lpSubKey: *const u16,
+    lpClass: *const u16,
+    dwOptions: u32,
+    pSecurityDescriptor: *const c_void,
+    phkResult: *mut OrHkey,
+    pdwDisposition: *mut u32,
+) -> u32;
+type DosfOrDeleteKey = unsafe extern "system" fn(Handle: OrHkey, lpSubKey: *const u16) -> u32;
+type DosfOrQueryInfoKey = unsafe extern "system" fn(
+    Handle: OrHkey,
+    lpClass: *mut u16,
+    lpcClass: *mut u32,
+    lpcSubKeys: *mut u32,
+    lpcMaxSubKeyLen: *mut u32,
+    lpcMaxClassLen: *mut u32,
+    lpcValues: *mut u32,
+    lpcMaxValueNameLen: *mut u32,
+    lpcMaxValueLen: *mut u32,
+    lpcbSecurityDescriptor: *mut u32,
+    lpftLastWriteTime: *mut c_void,
+) -> u32;
+type DosfOrEnumKey = unsafe extern "system" fn(
+    Handle: OrHkey,
+    dwIndex: u32,
+    lpName: *mut u16,
+    lpcName: *mut u32,
+    lpClass: *mut u16,
+    lpcClass: *mut u32,
+    lpftLastWriteTime: *mut c_void,
+) -> u32;
+type DosfOrEnumValue = unsafe extern "system" fn(
+    Handle: OrHkey,
+    dwIndex: u32,
+    lpValueName: *mut u16,
+    lpcValueName: *mut u32,
+    lpType: *mut u32,
+    lpData: *mut u8,
+    lpcbData: *mut u32,
+) -> u32;
+type DosfOrSetValue = unsafe extern "system" fn(
+    Handle: OrHkey,
+    lpValueName: *const u16,
+    dwType: u32,
+    lpData: *const u8,
+    cbData: u32,
+) -> u32;
+type DosfOrDeleteValue = unsafe extern "system" fn(Handle: OrHkey, lpValueName: *const u16) -> u32;
+type DosfOrGetKeySecurity = unsafe extern "system" fn(
+    Handle: OrHkey,
+    SecurityInformation: u32,
+    pSecurityDescriptor: *mut c_void,
+    lpcbSecurityDescriptor: *mut u32,
+) -> u32;
+type DosfOrSetKeySecurity =
+    unsafe extern "system" fn(Handle: OrHkey, SecurityInformation: u32, pSecurityDescriptor: *const c_void) -> u32;
+
+/// 脱机注册表库（offreg.dll），读写未加载到系统注册表中的配置单元文件
+#[allow(non_snake_case)]
+struct OffReg {
+    _lib: Library,
+    OROpenHive: DosfOrOpenHive,
+    ORCloseHive: DosfOrCloseHive,
+    ORSaveHive: DosfOrSaveHive,
+    OROpenKey: DosfOrOpenKey,
+    ORCloseKey: DosfOrCloseKey,
+    ORCreateKey: DosfOrCreateKey,
+    ORDeleteKey: DosfOrDeleteKey,
+    ORQueryInfoKey: DosfOrQueryInfoKey,
+    OREnumKey: DosfOrEnumKey,
+    OREnumValue: DosfOrEnumValue,
+    ORSetValue: DosfOrSetValue,
+    ORDeleteValue: DosfOrDeleteValue,
+    ORGetKeySecurity: DosfOrGetKeySecurity,
+    ORSetKeySecurity: DosfOrSetKeySecurity,
+}
+
+/// 注册表值
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HiveValue {
+    /// 值名称（默认值为空字符串）
+    pub name: String,
+    /// 值类型（REG_SZ、REG_DWORD 等）
+    pub kind: u32,
+    /// 值数据
+    pub data: Vec<u8>,
+}
+
+/// 注册表键，子键与值按小写名称索引（注册表名称不区分大小写）
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HiveKey {
+    /// 键名称
+    pub name: String,
+    /// 键的类名（如 SYSTEM 配置单元中 Lsa 下保存启动密钥的键）
+    pub class: String,
+    /// 自相对格式的安全描述符
+    pub security: Vec<u8>,
+    /// 值
+    pub values: BTreeMap<String, HiveValue>,
+    /// 子键
+    pub subkeys: BTreeMap<String, HiveKey>,
+}
+
+/// 结构化差异中的单个操作，路径为相对配置单元根键、以 `\` 分隔的键路径（根键为空字符串）
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum HiveOp {
+    /// 创建键（父键已存在）
+    CreateKey {
+        path: String,
+        class: String,
+        security: Vec<u8>,
+    },
+    /// 删除键（子键已先行删除）
+    DeleteKey { path: String },
+    /// 设置键的安全描述符
+    SetSecurity { path: String, security: Vec<u8> },
+    /// 设置值
+    SetValue { path: String, value: HiveValue },
+    /// 删除值
+    DeleteValue { path: String, name: String },
+}
+
+/// 结构化差异文件内容（MessagePack 序列化后以 zstd 压缩）
+#[derive(Debug, Serialize, Deserialize)]
+struct HivePatch {
+    version: u32,
+    ops: Vec<HiveOp>,
+}
+
+pub struct HiveDiff {}
+
+impl HiveDiff {
+    /// 判断文件是否为可生成结构化差异的注册表配置单元
+    ///
+    /// # 参数
+    /// - `path`: 文件路径
+    ///
+    /// # 返回值
+    /// - `true`: 文件名为已知的配置单元名称，且文件以 `regf` 签名开头
+    pub fn is_hive(path: &Path) -> bool {
+        let known = path.file_name().is_some_and(|name| {
+            HIVE_FILE_NAMES
+                .iter()
+                .any(|hive| name.to_string_lossy().eq_ignore_ascii_case(hive))
+        });
+        let mut signature = [0u8; 4];
+        known
+            && File::open(path)
+                .and_then(|mut file| file.read_exact(&mut signature))
+                .is_ok()
+            && &signature == b"regf"
+    }
+
+    /// 获取事务日志所属的配置单元路径
+    ///
+    /// # 参数
+    /// - `path`: 文件路径
+    ///
+    /// # 返回值
+    /// - 路径为配置单元的事务日志（如 `SOFTWARE.LOG1`）时返回配置单元路径，否则返回 `None`
+    pub fn log_owner(path: &str) -> Option<&str> {
+        let (hive, extension) = path.rsplit_once('.')?;
+        HIVE_LOG_EXTENSIONS
+            .iter()
+            .any(|log| log.eq_ignore_ascii_case(extension))
+            .then_some(hive)
+    }
+
+    /// 清空配置单元的事务日志
+    ///
+    /// 重建的配置单元由 offreg 完整保存，原有事务日志中的记录与其不对应，加载配置单元时重放会将其损坏
+    ///
+    /// # 参数
+    /// - `hive_path`: 配置单元路径
+    pub fn reset_logs(hive_path: &Path) -> Result<()> {
+        for extension in HIVE_LOG_EXTENSIONS {
+            let mut log = hive_path.as_os_str().to_owned();
+            log.push(".");
+            log.push(extension);
+            let log = PathBuf::from(log);
+            if log.is_file() {
+                // 截断现有文件以保留隐藏与系统属性
+                File::options()
+                    .write(true)
+                    .truncate(true)
+                    .open(&log)
+                    .with_context(|| format!("Reset hive log {} failed", log.display()))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// 比较两个键树，生成将 `old` 转换为 `new` 的操作
+    ///
+    /// 生成后在内存中对 `old` 重放一次，结果与 `new` 不一致时返回错误（调用方回退为普通差异）
+    ///
+    /// # 参数
+    /// - `old`: 旧配置单元的根键
+    /// - `new`: 新配置单元的根键
+    ///
+    /// # 返回值
+    /// - `Ok(Vec<HiveOp>)`: 差异操作，按顺序应用
+    /// - `Err`: 存在无法表示的差异（如已有键的类名改变）
+    pub fn diff(old: &HiveKey, new: &HiveKey) -> Result<Vec<HiveOp>> {
+        let mut ops = Vec::new();
+        diff_key("", old, new, &mut ops)?;
+
+        let mut replayed = old.clone();
+        Self::apply(&mut replayed, &ops)?;
+        if !same_key(&replayed, new) {
+            return Err(anyhow!("Replaying the hive diff does not reproduce the new hive"));
+        }
+        Ok(ops)
+    }
+
+    /// 在内存中的键树上应用差异操作
+    ///
+    /// # 参数
+    /// - `root`: 配置单元的根键
+    /// - `ops`: 差异操作
+    ///
+    /// # 返回值
+    /// - `Ok(())`: 应用成功
+    /// - `Err`: 操作引用的键不存在
+    pub fn apply(root: &mut HiveKey, ops: &[HiveOp]) -> Result<()> {
+        for op in ops {
+            match op {
+                HiveOp::CreateKey { path, class, security } => {
+                    let (parent, name) = split_key_path(path);
+                    find_key_mut(root, parent)?.subkeys.insert(
+                        name.to_lowercase(),
+                        HiveKey {
+                            name: name.to_string(),
+                            class: class.clone(),
+                            security: security.clone(),
+                            ..Default::default()
+                        },
+                    );
+                }
+                HiveOp::DeleteKey { path } => {
+                    let (parent, name) = split_key_path(path);
+                    find_key_mut(root, parent)?
+                        .subkeys
+                        .remove(&name.to_lowercase())
+                        .ok_or_else(|| anyhow!("Hive key not found: {}", path))?;
+                }
+                HiveOp::SetSecurity { path, security } => {
+                    find_key_mut(root, path)?.security = security.clone();
+                }
+                HiveOp::SetValue { path, value } => {
+                    find_key_mut(root, path)?
+                        .values
+                        .insert(value.name.to_lowercase(), value.clone());
+                }
+                HiveOp::DeleteValue { path, name } => {
+                    find_key_mut(root, path)?.values.remove(&name.to_lowercase());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 序列化差异操作
+    ///
+    /// # 参数
+    /// - `ops`: 差异操作
+    /// - `level`: zstd 压缩级别
+    pub fn encode(ops: &[HiveOp], level: i32) -> Result<Vec<u8>> {
+        let patch = HivePatch {
+            version: HIVE_PATCH_VERSION,
+            ops: ops.to_vec(),
+        };
+        let bytes = rmp_serde::to_vec(&patch).with_context(|| "Serialize hive diff failed")?;
+        zstd::encode_all(bytes.as_slice(), level).with_context(|| "Compress hive diff failed")
+    }
+
+    /// 反序列化差异操作
+    ///
+    /// # 参数
+    /// - `content`: [`HiveDiff::encode`] 生成的内容
+    pub fn decode(content: &[u8]) -> Result<Vec<HiveOp>> {
+        let bytes = zstd::decode_all(content).with_context(|| "Decompress hive diff failed")?;
+        let patch: HivePatch = rmp_serde::from_slice(&bytes).with_context(|| "Parse hive diff failed")?;
+        if patch.version != HIVE_PATCH_VERSION {
+            return Err(anyhow!("Unsupported hive diff version {}", patch.version));
+        }
+        Ok(patch.ops)
+    }
+
+    /// 读取配置单元文件的键树
+    ///
+    /// # 参数
+    /// - `path`: 配置单元文件路径
+    pub fn read_hive(path: &Path) -> Result<HiveKey> {
+        let offreg = OffReg::new()?;
+        let hive = offreg.open_hive(path)?;
+        let result = offreg.read_key(hive, "");
+        offreg.close_hive(hive);
+        result.with_context(|| format!("Read hive {} failed", path.display()))
+    }
+
+    /// 生成结构化差异文件
+    ///
+    /// 生成后在临时文件上试应用一次并重新读取，与新配置单元不一致时返回错误，调用方应回退为普通差异
+    ///
+    /// # 参数
+    /// - `old_file_path`: 旧配置单元路径
+    /// - `new_file_path`: 新配置单元路径
+    /// - `patch_file_path`: 输出的差异文件路径
+    /// - `level`: zstd 压缩级别
+    ///
+    /// # 返回值
+    /// - `Ok(())`: 生成成功
+    /// - `Err`: 无法解析配置单元、差异无法表示或试应用结果不一致
+    pub fn file_diff(
+        old_file_path: impl AsRef<Path>,
+        new_file_path: impl AsRef<Path>,
+        patch_file_path: impl AsRef<Path>,
+        level: i32,
+    ) -> Result<()> {
+        let old_file_path = old_file_path.as_ref();
+        let patch_file_path = patch_file_path.as_ref();
+        let new = Self::read_hive(new_file_path.as_ref())?;
+        let ops = Self::diff(&Self::read_hive(old_file_path)?, &new)?;
+        fs::write(patch_file_path, Self::encode(&ops, level)?).with_context(|| "Write hive diff failed")?;
+
+        // 试应用，确认 offreg.dll 能够按差异重建出相同的键树
+        let trial = get_tmp_path(patch_file_path.parent().unwrap_or(Path::new("")), ".hive-", ".tmp");
+        let rebuilt = Self::file_patch(old_file_path, patch_file_path, &trial).and_then(|()| Self::read_hive(&trial));
+        fs::remove_file(&trial).ok();
+        if !same_key(&rebuilt?, &new) {
+            fs::remove_file(patch_file_path).ok();
+            return Err(anyhow!("Rebuilt hive does not match the new hive"));
+        }
+        Ok(())
+    }
+
+    /// 计算结构化差异的大小（仅评估模式，不写入文件也不试应用）
+    ///
+    /// # 参数
+    /// - `old_file_path`: 旧配置单元路径
+    /// - `new_file_path`: 新配置单元路径
+    /// - `level`: zstd 压缩级别
+    pub fn file_diff_size(old_file_path: impl AsRef<Path>, new_file_path: impl AsRef<Path>, level: i32) -> Result<u64> {
+        let ops = Self::diff(
+            &Self::read_hive(old_file_path.as_ref())?,
+            &Self::read_hive(new_file_path.as_ref())?,
+        )?;
+        Ok(Self::encode(&ops, level)?.len() as u64)
+    }
+
+    /// 应用结构化差异文件，重建配置单元
+    ///
+    /// 重建的配置单元与更新后的配置单元键和值相同，但文件内容与大小通常不同
+    ///
+    /// # 参数
+    /// - `old_file_path`: 旧配置单元路径
+    /// - `patch_file_path`: 差异文件路径
+    /// - `new_file_path`: 输出的配置单元路径（可与旧配置单元相同）
+    pub fn file_patch(
+        old_file_path: impl AsRef<Path>,
+        patch_file_path: impl AsRef<Path>,
+        new_file_path: impl AsRef<Path>,
+    ) -> Result<()> {
+        let new_file_path = new_file_path.as_ref();
+        let ops = Self::decode(&fs::read(patch_file_path).with_context(|| "Read hive diff failed")?)?;
+
+        let offreg = OffReg::new()?;
+        let hive = offreg.open_hive(old_file_path.as_ref())?;
+        // ORSaveHive 不能覆盖已存在的文件，先保存到同一目录下的临时文件再替换
+        let saved = get_tmp_path(new_file_path.parent().unwrap_or(Path::new("")), ".hive-", ".tmp");
+        let result = ops
+            .iter()
+            .try_for_each(|op| offreg.apply_op(hive, op))
+            .and_then(|()| offreg.save_hive(hive, &saved));
+        offreg.close_hive(hive);
+        if let Err(e) = result {
+            fs::remove_file(&saved).ok();
+            return Err(e);
+        }
+        fs::rename(&saved, new_file_path)
+            .or_else(|_| fs::copy(&saved, new_file_path).map(|_| ()))
+            .inspect_err(|_| {
+                fs::remove_file(&saved).ok();
+            })
+            .with_context(|| format!("Replace {} failed", new_file_path.display()))?;
+        fs::remove_file(&saved).ok();
+        Ok(())
+    }
+}
+
+/// 递归比较两个键，生成差异操作
+fn diff_key(path: &str, old: &HiveKey, new: &HiveKey, ops: &mut Vec<HiveOp>) -> Result<()> {
+    if old.class != new.class {
+        return Err(anyhow!("Class of hive key '{}' changed", path));
+    }
+    if old.security != new.security {
+        ops.push(HiveOp::SetSecurity {
+            path: path.to_string(),
+            security: new.security.clone(),
+        });
+    }
+
+    // 值
+    for (name, old_value) in &old.values {
+        if !new.values.contains_key(name) {
+            ops.push(HiveOp::DeleteValue {
+                path: path.to_string(),
+                name: old_value.name.clone(),
+            });
+        }
+    }
+    for (name, value) in &new.values {
+        if old.values.get(name) != Some(value) {
+            ops.push(HiveOp::SetValue {
+                path: path.to_string(),
+                value: value.clone(),
+            });
+        }
+    }
+
+    // 子键
+    for (name, old_subkey) in &old.subkeys {
+        if !new.subkeys.contains_key(name) {
+            delete_tree(&join_key_path(path, &old_subkey.name), old_subkey, ops);
+        }
+    }
+    for (name, subkey) in &new.subkeys {
+        match old.subkeys.get(name) {
+            // 操作按不区分大小写的路径定位，无法表示仅大小写不同的改名
+            Some(old_subkey) if old_subkey.name != subkey.name => {
+                return Err(anyhow!("Hive key '{}' renamed to '{}'", old_subkey.name, subkey.name));
+            }
+            Some(old_subkey) => diff_key(&join_key_path(path, &old_subkey.name), old_subkey, subkey, ops)?,
+            None => create_tree(&join_key_path(path, &subkey.name), subkey, ops),
+        }
+    }
+    Ok(())
+}
+
+/// 生成创建整个键树的操作
+fn create_tree(path: &str, key: &HiveKey, ops: &mut Vec<HiveOp>) {
+    ops.push(HiveOp::CreateKey {
+        path: path.to_string(),
+        class: key.class.clone(),
+        security: key.security.clone(),
+    });
+    for value in key.values.values() {
+        ops.push(HiveOp::SetValue {
+            path: path.to_string(),
+            value: value.clone(),
+        });
+    }
+    for subkey in key.subkeys.values() {
+        create_tree(&join_key_path(path, &subkey.name), subkey, ops);
+    }
+}
+
+/// 生成删除整个键树的操作（先删除子键）
+fn delete_tree(path: &str, key: &HiveKey, ops: &mut Vec<HiveOp>) {
+    for subkey in key.subkeys.values() {
+        delete_tree(&join_key_path(path, &subkey.name), subkey, ops);
+    }
+    ops.push(HiveOp::DeleteKey { path: path.to_string() });
+}
+
+/// 比较两个键树（按不区分大小写的名称对应子键与值，但名称的大小写也必须一致，仅大小写不同的改名视为不一致）
+fn same_key(left: &HiveKey, right: &HiveKey) -> bool {
+    left.class == right.class
+        && left.security == right.security
+        && left.values.len() == right.values.len()
+        && left
+            .values
+            .iter()
+            .all(|(name, value)| right.values.get(name) == Some(value))
+        && left.subkeys.len() == right.subkeys.len()
+        && left.subkeys.iter().all(|(name, subkey)| {
+            right
+                .subkeys
+                .get(name)
+                .is_some_and(|other| other.name == subkey.name && same_key(subkey, other))
+        })
+}
+
+/// 拼接键路径
+fn join_key_path(parent: &str, name: &str) -> String {
+    if parent.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}\\{}", parent, name)
+    }
+}
+
+/// 拆分键路径为父键路径与键名称
+fn split_key_path(path: &str) -> (&str, &str) {
+    path.rsplit_once('\\').unwrap_or(("", path))
+}
+
+/// 按路径查找键（不区分大小写）
+fn find_key_mut<'a>(root: &'a mut HiveKey, path: &str) -> Result<&'a mut HiveKey> {
+    let mut key = root;
+    for name in path.split('\\').filter(|name| !name.is_empty()) {
+        key = key
+            .subkeys
+            .get_mut(&name.to_lowercase())
+            .ok_or_else(|| anyhow!("Hive key not found: {}", path))?;
+    }
+    Ok(key)
+}
+
+/// 将字符串转成以 NUL 结尾的 UTF-16
+fn to_wide(s: impl AsRef<OsStr>) -> Vec<u16> {
+    s.as_ref().encode_wide().chain(Some(0)).collect()
+}
+
+/// 将 offreg.dll 返回的 Win32 错误码转换为错误
+fn check(code: u32, operation: &str) -> Result<()> {
+    match code {
+        ERROR_SUCCESS => Ok(()),
+        _ => Err(anyhow!(
+            "{} failed: {} (0x{:08X})",
+            operation,
+            win32_error_message(code).unwrap_or_default(),
+            code
+        )),
+    }
+}
+
+impl OffReg {
+    /// 加载 offreg.dll
+    fn new() -> Result<Self> {
+        let lib = unsafe { Library::new("offreg.dll") }.with_context(|| "Load offreg.dll failed")?;
+        unsafe {
+            Ok(Self {
+                OROpenHive: *lib.get(b"OROpenHive")?,
+                ORCloseHive: *lib.get(b"ORCloseHive")?,
+                ORSaveHive: *lib.get(b"ORSaveHive")?,
+                OROpenKey: *lib.get(b"OROpenKey")?,
+                ORCloseKey: *lib.get(b"ORCloseKey")?,
+                ORCreateKey: *lib.get(b"ORCreateKey")?,
+                ORDeleteKey: *lib.get(b"ORDeleteKey")?,
+                ORQueryInfoKey: *lib.get(b"ORQueryInfoKey")?,
+                OREnumKey: *lib.get(b"OREnumKey")?,
+                OREnumValue: *lib.get(b"OREnumValue")?,
+                ORSetValue: *lib.get(b"ORSetValue")?,
+                ORDeleteValue: *lib.get(b"ORDeleteValue")?,
+                ORGetKeySecurity: *lib.get(b"ORGetKeySecurity")?,
+                ORSetKeySecurity: *lib.get(b"ORSetKeySecurity")?,
+                _lib: lib,
+            })
+        }
+    }
+
+    /// 打开配置单元文件
+    fn open_hive(&self, path: &Path) -> Result<OrHkey> {
+        let mut hive = null_mut();
+        check(
+            unsafe { (self.OROpenHive)(to_wide(path).as_ptr(), &mut hive) },
+            &format!("Open hive {}", path.display()),
+        )?;
+        Ok(hive)
+    }
+
+    /// 关闭配置单元
+    fn close_hive(&self, hive: OrHkey) {
+        unsafe { (self.ORCloseHive)(hive) };
+    }
+
+    /// 保存配置单元到新文件
+    fn save_hive(&self, hive: OrHkey, path: &Path) -> Result<()> {
+        let (major, minor) = HIVE_SAVE_VERSION;
+        check(
+            unsafe { (self.ORSaveHive)(hive, to_wide(path).as_ptr(), major, minor) },
+            &format!("Save hive {}", path.display()),
+        )
+    }
+
+    /// 打开子键后执行操作（路径为空时直接使用根键）
+    fn with_key<T>(&self, hive: OrHkey, path: &str, operation: impl FnOnce(OrHkey) -> Result<T>) -> Result<T> {
+        if path.is_empty() {
+            return operation(hive);
+        }
+        let mut key = null_mut();
+        check(
+            unsafe { (self.OROpenKey)(hive, to_wide(path).as_ptr(), &mut key) },
+            &format!("Open hive key {}", path),
+        )?;
+        let result = operation(key);
+        unsafe { (self.ORCloseKey)(key) };
+        result
+    }
+
+    /// 递归读取键树
+    fn read_key(&self, key: OrHkey, name: &str) -> Result<HiveKey> {
+        let mut class = vec![0u16; 1024];
+        let mut class_len = class.len() as u32;
+        let (mut subkey_count, mut max_subkey_len) = (0u32, 0u32);
+        let (mut value_count, mut max_value_name_len, mut max_value_len) = (0u32, 0u32, 0u32);
+        let mut security_len = 0u32;
+        check(
+            unsafe {
+                (self.ORQueryInfoKey)(
+                    key,
+                    class.as_mut_ptr(),
+                    &mut class_len,
+                    &mut subkey_count,
+                    &mut max_subkey_len,
+                    null_mut(),
+                    &mut value_count,
+                    &mut max_value_name_len,
+                    &mut max_value_len,
+                    &mut security_len,
+                    null_mut(),
+                )
+            },
+            &format!("Query hive key {}", name),
+        )?;
+        let mut result = HiveKey {
+            name: name.to_string(),
+            class: String::from_utf16_lossy(&class[..class_len as usize]),
+            ..Default::default()
+        };
+
+        // 安全描述符
+        let mut security = vec![0u8; security_len as usize];
+        check(
+            unsafe {
+                (self.ORGetKeySecurity)(
+                    key,
+                    KEY_SECURITY_INFORMATION,
+                    security.as_mut_ptr() as *mut c_void,
+                    &mut security_len,
+                )
+            },
+            &format!("Get security of hive key {}", name),
+        )?;
+        security.truncate(security_len as usize);
+        result.security = security;
+
+        // 值
+        let mut name_buffer = vec![0u16; max_value_name_len as usize + 1];
+        let mut data = vec![0u8; max_value_len as usize];
+        for index in 0..value_count {
+            let mut name_len = name_buffer.len() as u32;
+            let mut data_len = data.len() as u32;
+            let mut kind = 0u32;
+            check(
+                unsafe {
+                    (self.OREnumValue)(
+                        key,
+                        index,
+                        name_buffer.as_mut_ptr(),
+                        &mut name_len,
+                        &mut kind,
+                        data.as_mut_ptr(),
+                        &mut data_len,
+                    )
+                },
+                &format!("Enumerate values of hive key {}", name),
+            )?;
+            let value_name = String::from_utf16_lossy(&name_buffer[..name_len as usize]);
+            result.values.insert(
+                value_name.to_lowercase(),
+                HiveValue {
+                    name: value_name,
+                    kind,
+                    data: data[..data_len as usize].to_vec(),
+                },
+            );
+        }
+
+        // 子键
+        let mut name_buffer = vec![0u16; max_subkey_len as usize + 1];
+        for index in 0..subkey_count {
+            let mut name_len = name_buffer.len() as u32;
+            check(
+                unsafe {
+                    (self.OREnumKey)(
+                        key,
+                        index,
+                        name_buffer.as_mut_ptr(),
+                        &mut name_len,
+                        null_mut(),
+                        null_mut(),
+                        null_mut(),
+                    )
+                },
+                &format!("Enumerate subkeys of hive key {}", name),
+            )?;
+            let subkey_name = String::from_utf16_lossy(&name_buffer[..name_len as usize]);
+            let subkey = self.with_key(key, &subkey_name, |subkey| self.read_key(subkey, &subkey_name))?;
+            result.subkeys.insert(subkey_name.to_lowercase(), subkey);
+        }
+        Ok(result)
+    }
+
+    /// 在配置单元上执行一个差异操作
+    fn apply_op(&self, hive: OrHkey, op: &HiveOp) -> Result<()> {
+        match op {
+            HiveOp::CreateKey { path, class, security } => {
+                let (parent, name) = split_key_path(path);
+                // 类名的 UTF-16 缓冲区需在调用期间保持有效
+                let class = (!class.is_empty()).then(|| to_wide(class));
+                self.with_key(hive, parent, |parent| {
+                    let mut key = null_mut();
+                    let mut disposition = 0u32;
+                    check(
+                        unsafe {
+                            (self.ORCreateKey)(
+                                parent,
+                                to_wide(name).as_ptr(),
+                                class.as_ref().map_or(null(), |class| class.as_ptr()),
+                                0,
+                                if security.is_empty() {
+                                    null()
+                                } else {
+                                    security.as_ptr() as *const c_void
+                                },
+                                &mut key,
+                                &mut disposition,
+                            )
+                        },
+                        &format!("Create hive key {}", path),
+                    )?;
+                    unsafe { (self.ORCloseKey)(key) };
+                    Ok(())
+                })
+            }
+            HiveOp::DeleteKey { path } => {
+                let (parent, name) = split_key_path(path);
+                self.with_key(hive, parent, |parent| {
+                    check(
+                        unsafe { (self.ORDeleteKey)(parent, to_wide(name).as_ptr()) },
+                        &format!("Delete hive key {}", path),
+                    )
+                })
+            }
+            HiveOp::SetSecurity { path, security } => self.with_key(hive, path, |key| {
+                check(
+                    unsafe {
+                        (self.ORSetKeySecurity)(key, KEY_SECURITY_INFORMATION, security.as_ptr() as *const c_void)
+                    },
+                    &format!("Set security of hive key {}", path),
+                )
+            }),
+            HiveOp::SetValue { path, value } => self.with_key(hive, path, |key| {
+                check(
+                    unsafe {
+                        (self.ORSetValue)(
+                            key,
+                            to_wide(&value.name).as_ptr(),
+                            value.kind,
+                            value.data.as_ptr(),
+                            value.data.len() as u32,
+                        )
+                    },
+                    &format!("Set hive value {}\\{}", path, value.name),
+                )
+            }),
+            HiveOp::DeleteValue { path, name } => self.with_key(hive, path, |key| {
+                check(
+                    unsafe { (self.ORDeleteValue)(key, to_wide(name).as_ptr()) },
+                    &format!("Delete hive value {}\\{}", path, name),
+                )
+            }),
+        }
+    }
+}
@@ -1,10 +1,10 @@
-use crate::cli::{Compress, Preset, Storage};
-use crate::patch::WimPatch;
+use crate::cli::{Compress, OnExisting, Preset, Storage};
+use crate::patch::{ApplyOptions, CreateOptions, DEFAULT_IO_RETRIES, DEFAULT_MAX_CHAIN_LENGTH, MergeOptions, WimPatch};
 use anyhow::{Context, Result};
 use dialoguer::{Confirm, Input, Select};
 use rust_i18n::t;
 use semver::Version;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 /// 交互模式创建补丁
 ///
@@ -120,6 +120,7 @@ pub fn create_interactive_patch(wim_patch: &WimPatch) -> Result<()> {
             t!("interactive.storage_zstd"),
             t!("interactive.storage_bsdiff"),
             t!("interactive.storage_full"),
+            t!("interactive.storage_whole_image"),
         ])
         .interact()?;
 
@@ -127,6 +128,7 @@ pub fn create_interactive_patch(wim_patch: &WimPatch) -> Result<()> {
         0 => Storage::Zstd,
         1 => Storage::Bsdiff,
         2 => Storage::Full,
+        3 => Storage::WholeImage,
         _ => Storage::Zstd,
     };
 
@@ -220,6 +222,37 @@ pub fn create_interactive_patch(wim_patch: &WimPatch) -> Result<()> {
     println!("{}: {}", t!("interactive.description"), description);
     println!();
 
+    // 创建补丁选项（补丁文件已存在时的处理方式在确认后设置）
+    let mut options = CreateOptions {
+        storage,
+        preset,
+        version,
+        author,
+        name,
+        description,
+        ..Default::default()
+    };
+
+    // 可选：创建前仅评估补丁内容，显示变更文件数量与预估补丁大小
+    if Confirm::new()
+        .with_prompt(t!("interactive.estimate_prompt"))
+        .default(false)
+        .interact()?
+    {
+        let estimate_options = CreateOptions {
+            dry_run: true,
+            ..options.clone()
+        };
+        wim_patch.create_patch(
+            &base_image,
+            base_index,
+            &target_image,
+            target_index,
+            &patch_image,
+            &estimate_options,
+        )?;
+    }
+
     // 确认创建补丁
     if !Confirm::new()
         .with_prompt(t!("interactive.confirm_create"))
@@ -230,6 +263,13 @@ pub fn create_interactive_patch(wim_patch: &WimPatch) -> Result<()> {
         return Ok(());
     }
 
+    // 补丁文件已存在时确认是否覆盖
+    let Some(on_existing) = confirm_overwrite(&patch_image)? else {
+        println!("{}", t!("interactive.cancelled"));
+        return Ok(());
+    };
+    options.on_existing = on_existing;
+
     // 调用创建补丁的方法
     wim_patch.create_patch(
         &base_image,
@@ -237,14 +277,7 @@ pub fn create_interactive_patch(wim_patch: &WimPatch) -> Result<()> {
         &target_image,
         target_index,
         &patch_image,
-        &storage,
-        &preset,
-        &version,
-        &author,
-        &name,
-        &description,
-        None,
-        &Compress::Lzx,
+        &options,
     )
 }
 
@@ -361,6 +394,192 @@ pub fn apply_interactive_patch(wim_patch: &WimPatch) -> Result<()> {
         return Ok(());
     }
 
+    // 目标镜像已存在时确认是否覆盖
+    let Some(on_existing) = confirm_overwrite(&target_image)? else {
+        println!("{}", t!("interactive.cancelled"));
+        return Ok(());
+    };
+
     // 调用应用补丁的方法
-    wim_patch.apply_patch(&base_image, base_index, &patch_image, &target_image, None, force)
+    let options = ApplyOptions {
+        force,
+        on_existing,
+        io_retries: DEFAULT_IO_RETRIES,
+        max_chain_length: DEFAULT_MAX_CHAIN_LENGTH,
+        ..Default::default()
+    };
+    wim_patch.apply_patch(&base_image, base_index, &patch_image, &target_image, &options)
+}
+
+/// 输出文件已存在时确认是否覆盖
+///
+/// # 参数
+///
+/// - `output` - 输出文件路径
+///
+/// # 返回值
+///
+/// - `Ok(Some(OnExisting))` - 输出文件不存在，或用户确认覆盖
+/// - `Ok(None)` - 用户取消覆盖
+/// - `Err(anyhow::Error)` - 读取用户输入失败
+fn confirm_overwrite(output: &Path) -> Result<Option<OnExisting>> {
+    if !output.exists() {
+        return Ok(Some(OnExisting::Error));
+    }
+    let overwrite = Confirm::new()
+        .with_prompt(t!("interactive.overwrite_existing", path = output.display()))
+        .default(false)
+        .interact()?;
+    Ok(overwrite.then_some(OnExisting::Overwrite))
+}
+
+/// 交互式合并补丁
+///
+/// # 参数
+///
+/// - `wim_patch` - 用于合并补丁的 WimPatch 实例
+///
+/// # 返回值
+///
+/// - `Result<()>` - 如果合并补丁成功，则返回 Ok(())，否则返回错误信息
+pub fn merge_interactive_patch(wim_patch: &WimPatch) -> Result<()> {
+    // 显示欢迎信息
+    println!("{}", t!("interactive.welcome"));
+    println!();
+
+    // 逐个获取补丁文件路径，留空或输入 done 结束
+    let mut patches: Vec<PathBuf> = Vec::new();
+    loop {
+        let path_input: String = Input::new()
+            .with_prompt(t!("interactive.merge_patch_prompt"))
+            .allow_empty(true)
+            .interact_text()?;
+        let path_input = path_input.trim();
+        if path_input.is_empty() || path_input.eq_ignore_ascii_case("done") {
+            if patches.is_empty() {
+                println!("{}", t!("interactive.merge_patch_required"));
+                continue;
+            }
+            break;
+        }
+        let path = PathBuf::from(path_input.trim_start_matches("\"").trim_end_matches("\""));
+        // 检查文件是否存在
+        if path.exists() && path.is_file() {
+            patches.push(path);
+        } else {
+            println!("{}: {}", t!("interactive.file_not_exist"), path.display());
+        }
+    }
+
+    // 获取合并后的补丁文件输出路径
+    let out = loop {
+        let path_input: String = Input::new()
+            .with_prompt(t!("interactive.patch_image_prompt"))
+            .allow_empty(false)
+            .interact_text()?;
+        let path = PathBuf::from(path_input.trim_start_matches("\"").trim_end_matches("\""));
+        // 只检查目录是否存在，文件可以不存在
+        if let Some(parent) = path.parent() {
+            if parent.exists() || parent == PathBuf::from(".") {
+                break path;
+            } else {
+                println!("{}: {}", t!("interactive.file_not_exist"), parent.display());
+            }
+        } else {
+            break path;
+        }
+    };
+
+    // 获取压缩算法
+    let compress_selection = Select::new()
+        .with_prompt(t!("interactive.compress_options"))
+        .default(0)
+        .items(&[
+            t!("interactive.compress_lzx"),
+            t!("interactive.compress_xpress"),
+            t!("interactive.compress_none"),
+        ])
+        .interact()?;
+
+    let compress = match compress_selection {
+        0 => Compress::Lzx,
+        1 => Compress::Xpress,
+        2 => Compress::None,
+        _ => Compress::Lzx,
+    };
+
+    // 显示配置摘要
+    println!("\n--- {} ---", t!("interactive.config_summary"));
+    for patch in &patches {
+        println!("{}: {}", t!("interactive.patch_image"), patch.display());
+    }
+    println!("{}: {}", t!("interactive.target_image"), out.display());
+    println!("{}: {:?}", t!("interactive.compress"), compress);
+    println!();
+
+    // 确认合并补丁
+    if !Confirm::new()
+        .with_prompt(t!("interactive.confirm_merge"))
+        .default(true)
+        .interact()?
+    {
+        println!("{}", t!("interactive.cancelled"));
+        return Ok(());
+    }
+
+    // 调用合并补丁的方法
+    let options = MergeOptions {
+        compress,
+        boot_index: None,
+        verify: false,
+        report_shared: false,
+        keep_compression: false,
+        fail_on_conflict: false,
+        verify_after_each: false,
+        flatten_base: None,
+        flatten_base_index: None,
+        flatten_storage: None,
+        flatten_preset: None,
+        flatten_manifest_format: None,
+    };
+    wim_patch.merge_patches(&patches, &out, &options)
+}
+
+/// 交互式查看补丁信息
+///
+/// # 参数
+///
+/// - `wim_patch` - 用于读取补丁信息的 WimPatch 实例
+///
+/// # 返回值
+///
+/// - `Result<()>` - 如果读取补丁信息成功，则返回 Ok(())，否则返回错误信息
+pub fn info_interactive_patch(wim_patch: &WimPatch) -> Result<()> {
+    // 显示欢迎信息
+    println!("{}", t!("interactive.welcome"));
+    println!();
+
+    // 获取补丁文件路径
+    let patch_image = loop {
+        let path_input: String = Input::new()
+            .with_prompt(t!("interactive.patch_image_path"))
+            .allow_empty(false)
+            .interact_text()?;
+        let path = PathBuf::from(path_input.trim_start_matches("\"").trim_end_matches("\""));
+        // 检查文件是否存在
+        if path.exists() && path.is_file() {
+            break path;
+        } else {
+            println!("{}: {}", t!("interactive.file_not_exist"), path.display());
+        }
+    };
+
+    // 获取是否输出 XML
+    let xml = Confirm::new()
+        .with_prompt(t!("interactive.xml_prompt"))
+        .default(false)
+        .interact()?;
+
+    println!("{}", wim_patch.get_patch_info(&patch_image, xml, None, None)?);
+    Ok(())
 }
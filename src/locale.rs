@@ -0,0 +1,77 @@
+use anyhow::{Context, Result};
+use rust_i18n::Backend;
+use serde_yaml::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// 内置英文翻译文件，用于校验外部翻译文件的键是否完整
+const BUILTIN_LOCALE: &str = include_str!("../locales/en.yml");
+
+/// 外部翻译文件中的翻译（键 -> 文本）
+static LOCALE_OVERRIDES: OnceLock<HashMap<String, String>> = OnceLock::new();
+
+/// 运行时翻译后端，优先使用 `--locale-file` 加载的翻译
+pub struct RuntimeBackend;
+
+impl Backend for RuntimeBackend {
+    fn available_locales(&self) -> Vec<&str> {
+        Vec::new()
+    }
+
+    fn translate(&self, _locale: &str, key: &str) -> Option<&str> {
+        LOCALE_OVERRIDES.get()?.get(key).map(|value| value.as_str())
+    }
+}
+
+/// 加载外部翻译文件
+///
+/// # 参数
+/// - `path`: 翻译文件路径（与 `locales/*.yml` 结构相同）
+///
+/// # 返回值
+/// - `Ok(Vec<String>)`: 加载成功，返回翻译文件中缺失的键
+/// - `Err(anyhow::Error)`: 读取或解析失败
+pub fn load_locale_file(path: &Path) -> Result<Vec<String>> {
+    let content = fs::read_to_string(path).with_context(|| format!("Read locale file failed: {}", path.display()))?;
+    let value: Value = serde_yaml::from_str(&content).with_context(|| "Parse locale file failed")?;
+    let mut translations = HashMap::new();
+    flatten_translations("", &value, &mut translations);
+
+    // 检查缺失的键
+    let builtin: Value = serde_yaml::from_str(BUILTIN_LOCALE).with_context(|| "Parse builtin locale failed")?;
+    let mut required = HashMap::new();
+    flatten_translations("", &builtin, &mut required);
+    let mut missing: Vec<String> = required
+        .into_keys()
+        .filter(|key| !translations.contains_key(key))
+        .collect();
+    missing.sort();
+
+    LOCALE_OVERRIDES.set(translations).ok();
+    Ok(missing)
+}
+
+/// 将嵌套的翻译结构展开为以 `.` 分隔的键
+fn flatten_translations(prefix: &str, value: &Value, out: &mut HashMap<String, String>) {
+    match value {
+        Value::Mapping(mapping) => {
+            for (key, value) in mapping {
+                let Some(key) = key.as_str() else {
+                    continue;
+                };
+                let key = if prefix.is_empty() {
+                    key.to_string()
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                flatten_translations(&key, value, out);
+            }
+        }
+        Value::String(text) => {
+            out.insert(prefix.to_string(), text.clone());
+        }
+        _ => {}
+    }
+}
@@ -3,46 +3,93 @@
 // 禁用未使用代码警告
 #![allow(dead_code)]
 
+use crate::benchmark::print_benchmark;
 use crate::cli::{App, Commands, Intrinsic, IntrinsicCommands, Language};
 use crate::console::{write_console, ConsoleType};
-use crate::interactive::{apply_interactive_patch, create_interactive_patch};
-use crate::patch::WimPatch;
-use crate::utils::{get_tmp_name, launched_from_explorer};
-use anyhow::Result;
-use clap::Parser;
+use crate::interactive::{
+    apply_interactive_patch, create_interactive_patch, info_interactive_patch, merge_interactive_patch,
+};
+use crate::locale::{load_locale_file, RuntimeBackend};
+use crate::patch::{
+    expand_base_options, expand_base_stem, ApplyOptions, BatchApplyResult, CreateOptions, MergeOptions, WimPatch,
+    DEFAULT_CAPTURE_EXCLUDE,
+};
+use crate::progress::ConsoleProgress;
+use crate::selftest::run_self_test;
+use crate::utils::{get_tmp_path, launched_from_explorer, merge_pattern_file, run_post_apply};
+use crate::wimgapi::Wimgapi;
+use anyhow::{anyhow, Result};
+use clap::{Parser, ValueEnum};
 use ::console::Term;
 use rust_i18n::{set_locale, t};
 use std::env::temp_dir;
 use std::option::Option;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
-use std::sync::OnceLock;
-use std::thread::sleep;
+use std::sync::{Mutex, OnceLock};
+use std::thread::{self, sleep};
 use std::time::Duration;
 use std::{fs, process};
 use sys_locale::get_locale;
 
+mod benchmark;
 mod bsdiff;
+mod checksum;
 mod cli;
 mod console;
+mod hivediff;
 mod interactive;
+mod locale;
 mod manifest;
 mod patch;
+mod progress;
+mod report;
+mod selftest;
+mod signature;
 mod test;
 mod utils;
 mod wimgapi;
 mod zstdiff;
 
-rust_i18n::i18n!("locales");
+rust_i18n::i18n!("locales", backend = RuntimeBackend);
 
 static DEBUG: AtomicBool = AtomicBool::new(false);
+static QUIET: AtomicBool = AtomicBool::new(false);
 static BUFFER_SIZE: AtomicUsize = AtomicUsize::new(65536);
+static TEMP_NAME_LENGTH: AtomicUsize = AtomicUsize::new(10);
 static IS_TTY: OnceLock<bool> = OnceLock::new();
 static TEMP_PATH: OnceLock<PathBuf> = OnceLock::new();
+static WIMGAPI_PATH: OnceLock<PathBuf> = OnceLock::new();
+static ACTIVE_MOUNTS: Mutex<Vec<(PathBuf, PathBuf, u32)>> = Mutex::new(Vec::new());
+static UNMOUNT_WIMGAPI: OnceLock<Option<Wimgapi>> = OnceLock::new();
+static CANCELLED: AtomicBool = AtomicBool::new(false);
 
-/// 获取临时目录路径
-pub fn get_temp_path() -> &'static PathBuf {
-    TEMP_PATH.get_or_init(|| temp_dir().join(get_tmp_name(".tmp", "", 6)))
+/// 按下 Ctrl-C 后等待正在执行的操作卸载镜像并清理的最长时间，超时后强制退出
+const CANCEL_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// 获取临时目录根路径（程序退出时整体删除）
+pub fn get_temp_root() -> &'static PathBuf {
+    TEMP_PATH.get_or_init(|| get_tmp_path(&temp_dir(), ".tmp", ""))
+}
+
+/// 获取临时目录路径（批量应用时各基础镜像通过 `ApplyOptions::scratch_dir` 使用其中独立的子目录）
+pub fn get_temp_path() -> PathBuf {
+    get_temp_root().clone()
+}
+
+/// 获取通过 `--wimgapi-path` 指定的 wimgapi.dll 路径，未指定时返回 `None`（自动搜索）
+pub fn get_wimgapi_path() -> Option<PathBuf> {
+    WIMGAPI_PATH.get().cloned()
+}
+
+/// 判断是否为调试模式
+pub fn is_debug() -> bool {
+    DEBUG.load(Ordering::Relaxed)
+}
+
+/// 判断是否为静默模式（仅输出错误信息）
+pub fn is_quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
 }
 
 /// 判断是否为终端
@@ -50,6 +97,58 @@ pub fn is_tty() -> bool {
     *IS_TTY.get_or_init(|| Term::stdout().features().is_attended())
 }
 
+/// 登记挂载点，以便在 Ctrl-C 时卸载
+///
+/// 首次登记时预先加载用于卸载的 wimgapi，强制退出时无需在 Ctrl-C 处理中加载
+pub fn register_mount(mount_path: &Path, image_path: &Path, index: u32) {
+    UNMOUNT_WIMGAPI.get_or_init(|| Wimgapi::new(get_wimgapi_path()).ok());
+    if let Ok(mut mounts) = ACTIVE_MOUNTS.lock() {
+        mounts.push((mount_path.to_path_buf(), image_path.to_path_buf(), index));
+    }
+}
+
+/// 取消登记挂载点
+pub fn unregister_mount(mount_path: &Path) {
+    if let Ok(mut mounts) = ACTIVE_MOUNTS.lock() {
+        mounts.retain(|(path, _, _)| path != mount_path);
+    }
+}
+
+/// 判断是否仍有已登记的挂载点（卸载失败时挂载点会保留登记）
+pub fn has_active_mounts() -> bool {
+    ACTIVE_MOUNTS.lock().is_ok_and(|mounts| !mounts.is_empty())
+}
+
+/// 判断用户是否已按下 Ctrl-C 请求取消
+pub fn is_cancelled() -> bool {
+    CANCELLED.load(Ordering::Relaxed)
+}
+
+/// 检查是否已请求取消，已取消时返回错误，以便调用方沿正常的错误路径卸载镜像并清理
+pub fn check_cancelled() -> Result<()> {
+    if is_cancelled() {
+        return Err(anyhow!(t!("cancel.cancelled")));
+    }
+    Ok(())
+}
+
+/// 卸载已登记的挂载点（放弃更改），删除临时目录后强制退出程序
+fn force_exit() -> ! {
+    if let Ok(mounts) = ACTIVE_MOUNTS.lock()
+        && !mounts.is_empty()
+        && let Some(Some(wimgapi)) = UNMOUNT_WIMGAPI.get()
+    {
+        for (mount_path, image_path, index) in mounts.iter() {
+            wimgapi.unmount_image(mount_path, image_path, *index, false).ok();
+        }
+    }
+
+    // 删除临时目录
+    fs::remove_dir_all(get_temp_root()).ok();
+
+    process::exit(1);
+}
+
 fn main() -> Result<()> {
     // 判断是否从资源管理器启动
     if launched_from_explorer() {
@@ -66,20 +165,37 @@ fn main() -> Result<()> {
 
     // 设置 Ctrl-C 信号处理
     ctrlc::set_handler(move || {
-        // 删除临时目录
-        fs::remove_dir_all(get_temp_path()).ok();
+        // 再次按下时强制退出
+        if CANCELLED.swap(true, Ordering::SeqCst) {
+            force_exit();
+        }
 
-        // 强制退出程序
-        process::exit(1);
+        // 第一次按下时请求取消，由正在执行的操作在检查点返回错误，沿正常路径卸载镜像并清理
+        write_console(ConsoleType::Warning, &t!("cancel.requested"));
+        thread::spawn(|| {
+            sleep(CANCEL_TIMEOUT);
+            write_console(ConsoleType::Error, &t!("cancel.timeout"));
+            force_exit();
+        });
     })
     .expect("Error setting Ctrl-C handler");
 
     // 处理交互模式命令行
     if let Ok(cli) = Intrinsic::try_parse() {
-        set_globals(cli.debug, cli.language, cli.scratchdir, cli.buffer_size);
+        set_globals(
+            cli.debug,
+            false,
+            cli.language,
+            cli.locale_file,
+            cli.scratchdir,
+            cli.buffer_size,
+            cli.wimgapi_path,
+            cli.temp_name_length,
+        );
 
         // 初始化 WimPatch 实例
-        let wim_patch = WimPatch::new().expect(&t!("wim_patch.new.failed"));
+        let mut wim_patch = WimPatch::new().expect(&t!("wim_patch.new.failed"));
+        wim_patch.set_progress_observer(ConsoleProgress::default());
 
         let result = match cli.command {
             IntrinsicCommands::Create => match create_interactive_patch(&wim_patch) {
@@ -102,14 +218,31 @@ fn main() -> Result<()> {
                     Err(e)
                 }
             },
+            IntrinsicCommands::Merge => match merge_interactive_patch(&wim_patch) {
+                Ok(()) => {
+                    write_console(ConsoleType::Success, &format!("{}", t!("merge_patch.success")));
+                    Ok(())
+                }
+                Err(e) => {
+                    write_console(ConsoleType::Error, &format!("{}: {:?}", t!("merge_patch.failed"), e));
+                    Err(e)
+                }
+            },
+            IntrinsicCommands::Info => match info_interactive_patch(&wim_patch) {
+                Ok(()) => Ok(()),
+                Err(e) => {
+                    write_console(ConsoleType::Error, &format!("{}: {:?}", t!("get_patch_info.failed"), e));
+                    Err(e)
+                }
+            },
         };
 
         // 释放WimPatch实例
         drop(wim_patch);
 
         // 删除临时目录
-        if get_temp_path().exists()
-            && let Err(e) = fs::remove_dir_all(get_temp_path())
+        if get_temp_root().exists()
+            && let Err(e) = fs::remove_dir_all(get_temp_root())
         {
             write_console(
                 ConsoleType::Warning,
@@ -122,11 +255,63 @@ fn main() -> Result<()> {
 
     // 处理命令行
     let cli = App::parse();
-    set_globals(cli.debug, cli.language, cli.scratchdir, cli.buffer_size);
+    set_globals(
+        cli.debug,
+        cli.quiet,
+        cli.language,
+        cli.locale_file,
+        cli.scratchdir,
+        cli.buffer_size,
+        cli.wimgapi_path,
+        cli.temp_name_length,
+    );
+
+    // 自检（在初始化 WimPatch 实例之前执行，以便报告 wimgapi.dll 加载失败）
+    if let Commands::SelfTest {} = cli.command {
+        let result = match run_self_test() {
+            Ok(()) => {
+                write_console(ConsoleType::Success, &format!("{}", t!("self_test.success")));
+                Ok(())
+            }
+            Err(e) => {
+                write_console(ConsoleType::Error, &format!("{}: {:?}", t!("self_test.failed"), e));
+                Err(e)
+            }
+        };
+
+        // 删除临时目录
+        if get_temp_root().exists()
+            && let Err(e) = fs::remove_dir_all(get_temp_root())
+        {
+            write_console(
+                ConsoleType::Warning,
+                &format!("{}: {}", t!("remove_temp_dir_failed"), e),
+            );
+        }
 
-    // 初始化 WimPatch 实例
-    let wim_patch = WimPatch::new().expect(&t!("wim_patch.new.failed"));
+        return result;
+    }
 
+    // 初始化 WimPatch 实例（目录补丁与存储方式测试不需要加载 wimgapi.dll，静默模式下不显示进度）
+    let mut wim_patch = match cli.command {
+        Commands::CreateDir { .. } | Commands::ApplyDir { .. } | Commands::Benchmark { .. } => {
+            WimPatch::without_wimgapi()
+        }
+        _ => WimPatch::new(),
+    }
+    .expect(&t!("wim_patch.new.failed"));
+    if !cli.quiet {
+        // 指定 --progress-interval 时非终端环境下仅按间隔输出进度汇总
+        match cli.progress_interval {
+            Some(seconds) => {
+                wim_patch.set_progress_observer(ConsoleProgress::with_interval(Duration::from_secs(seconds)))
+            }
+            None => wim_patch.set_progress_observer(ConsoleProgress::default()),
+        }
+    }
+
+    // 应用后命令的非零退出码，作为程序的退出码
+    let mut exit_code = None;
     let result = match cli.command {
         // 创建补丁文件
         Commands::Create {
@@ -135,6 +320,8 @@ fn main() -> Result<()> {
             mut base_index,
             target: update,
             mut target_index,
+            base_name,
+            target_name,
             out: patch,
             preset,
             version,
@@ -143,7 +330,39 @@ fn main() -> Result<()> {
             description,
             storage,
             exclude,
+            include,
+            include_from,
             compress,
+            dry_run,
+            max_patch_size,
+            skip_empty_dirs,
+            boot_index,
+            capture_exclude,
+            no_default_exclude,
+            manifest_format,
+            embed_full_fallback,
+            skip_space_check,
+            source_date,
+            compare_mode,
+            stage_compressed,
+            concurrency,
+            on_existing,
+            append,
+            overwrite,
+            strict_index,
+            emit_checksum,
+            preserve_streams,
+            exclude_volatile,
+            dict_window,
+            zstd_level,
+            subtree,
+            summary_json,
+            no_acl,
+            hive_diff,
+            compare_ignore_case,
+            sign_key,
+            compare_follow_reparse_depth,
+            report_unchanged,
         } => {
             // 当用户指定--storage bsdiff并且还指定了--preset参数时，发出警告
             let args: Vec<String> = std::env::args().collect();
@@ -152,33 +371,96 @@ fn main() -> Result<()> {
                 write_console(ConsoleType::Warning, &format!("{}", t!("create_patch.bsdiff_preset")));
             }
 
+            // --append 与 --overwrite 为 --on-existing 的简写
+            let on_existing = if append {
+                cli::OnExisting::Append
+            } else if overwrite {
+                cli::OnExisting::Overwrite
+            } else {
+                on_existing
+            };
+
             // 当用户指定--index参数时，index_base和index_updated参数等于index
             if let Some(index) = index {
                 base_index = Some(index);
                 target_index = Some(index);
             }
 
-            match wim_patch.create_patch(
-                &base,
-                base_index,
-                &update,
-                target_index,
-                &patch,
-                &storage,
-                &preset,
-                &version.to_string(),
-                &author,
-                &name.unwrap_or(format!(
+            // 设置捕获补丁镜像时排除的路径
+            if no_default_exclude || capture_exclude.is_some() {
+                let mut exclude: Vec<String> = if no_default_exclude {
+                    Vec::new()
+                } else {
+                    DEFAULT_CAPTURE_EXCLUDE.iter().map(|path| path.to_string()).collect()
+                };
+                exclude.extend(capture_exclude.unwrap_or_default());
+                wim_patch.set_capture_exclude(exclude);
+            }
+
+            // 未指定 --source-date 时使用 SOURCE_DATE_EPOCH 环境变量
+            let source_date = source_date.or_else(|| {
+                let value = std::env::var("SOURCE_DATE_EPOCH").ok()?;
+                cli::parse_source_date(&value)
+                    .inspect_err(|e| {
+                        write_console(
+                            ConsoleType::Warning,
+                            &format!("{}: {}", t!("create_patch.invalid_source_date"), e),
+                        )
+                    })
+                    .ok()
+            });
+
+            let options = CreateOptions {
+                storage,
+                preset,
+                version: version.to_string(),
+                author,
+                name: name.unwrap_or(format!(
                     "{}-patch-v{}",
                     base.file_stem().unwrap().to_string_lossy(),
                     version
                 )),
-                &description.unwrap_or_default(),
-                exclude.as_deref(),
-                &compress,
-            ) {
+                description: description.unwrap_or_default(),
+                exclude,
+                include: merge_pattern_file(include, include_from.as_deref())?,
+                compress,
+                dry_run,
+                max_patch_size,
+                skip_empty_dirs,
+                boot_index,
+                manifest_format,
+                embed_full_fallback,
+                skip_space_check,
+                source_date,
+                compare_mode,
+                stage_compressed,
+                concurrency,
+                on_existing,
+                strict_index,
+                emit_checksum,
+                preserve_streams,
+                exclude_volatile,
+                dict_window,
+                base_name,
+                target_name,
+                zstd_level,
+                subtree,
+                summary_json,
+                no_acl,
+                hive_diff,
+                compare_ignore_case,
+                sign_key,
+                compare_follow_reparse_depth,
+                report_unchanged,
+            };
+
+            match wim_patch.create_patch(&base, base_index, &update, target_index, &patch, &options) {
                 Ok(()) => {
-                    write_console(ConsoleType::Success, &format!("{}", t!("create_patch.success")));
+                    if dry_run {
+                        write_console(ConsoleType::Success, &format!("{}", t!("create_patch.dry_run_done")));
+                    } else {
+                        write_console(ConsoleType::Success, &format!("{}", t!("create_patch.success")));
+                    }
                     Ok(())
                 }
                 Err(e) => {
@@ -190,30 +472,164 @@ fn main() -> Result<()> {
 
         // 应用补丁文件
         Commands::Apply {
-            base: src,
+            base,
+            base_list,
+            keep_going,
             patch,
             target,
             index,
+            base_name,
             exclude,
+            include,
+            include_from,
             force,
+            up_to_version,
+            from_version,
+            pause_before_export,
+            no_tool_version_check,
+            skip_space_check,
+            io_retries,
+            base_guid,
+            verify,
+            strip_components,
+            path_prefix,
+            report,
+            preserve_creation_time,
+            no_delete,
+            check_checksum,
+            max_chain_length,
+            match_mode,
+            on_existing,
+            extract_to,
+            no_export,
+            post_apply,
+            no_base_copy,
+            no_acl,
+            target_compress,
+            allow_downgrade,
+            apply_order,
+            verify_signature,
+            require_signature,
         } => {
             if force {
                 write_console(ConsoleType::Warning, &format!("{}", t!("apply_patch.force_warning")));
             }
-            match wim_patch.apply_patch(&src, index, &patch, &target, exclude.as_deref(), force) {
-                Ok(()) => {
-                    write_console(ConsoleType::Success, &format!("{}", t!("apply_patch.success")));
-                    Ok(())
+            if no_delete {
+                write_console(
+                    ConsoleType::Warning,
+                    &format!("{}", t!("apply_patch.no_delete_warning")),
+                );
+            }
+            let options = ApplyOptions {
+                exclude,
+                include: merge_pattern_file(include, include_from.as_deref())?,
+                force,
+                up_to_version,
+                from_version,
+                pause_before_export,
+                skip_tool_version_check: no_tool_version_check,
+                skip_space_check,
+                io_retries,
+                base_guid,
+                verify,
+                strip_components,
+                path_prefix,
+                report,
+                preserve_creation_time,
+                no_delete,
+                check_checksum,
+                max_chain_length,
+                match_mode,
+                base_name,
+                extract_to,
+                no_export,
+                on_existing,
+                no_base_copy,
+                scratch_dir: None,
+                no_acl,
+                target_compress,
+                allow_downgrade,
+                apply_order,
+                verify_signature,
+                require_signature,
+            };
+            // 指定 --no-export 时不导出目标镜像
+            let target = target.unwrap_or_default();
+            let bases: Vec<PathBuf> = merge_pattern_file(
+                Some(base.iter().map(|path| path.to_string_lossy().into_owned()).collect()),
+                base_list.as_deref(),
+            )?
+            .unwrap_or_default()
+            .into_iter()
+            .map(PathBuf::from)
+            .collect();
+            // 仅在应用完全成功后执行应用后命令
+            let mut after_apply = |target: &Path| -> Result<()> {
+                match post_apply.as_ref().map(|command| {
+                    write_console(
+                        ConsoleType::Info,
+                        &format!("{}: {}", t!("apply_patch.post_apply"), command),
+                    );
+                    run_post_apply(command, target)
+                }) {
+                    None | Some(Ok(0)) => Ok(()),
+                    Some(Ok(code)) => {
+                        exit_code = Some(code);
+                        let e = anyhow!(t!("apply_patch.post_apply_failed", code = code));
+                        write_console(ConsoleType::Error, &format!("{}", e));
+                        Err(e)
+                    }
+                    Some(Err(e)) => {
+                        write_console(ConsoleType::Error, &format!("{}: {:?}", t!("apply_patch.failed"), e));
+                        Err(e)
+                    }
                 }
-                Err(e) => {
-                    write_console(ConsoleType::Error, &format!("{}: {:?}", t!("apply_patch.failed"), e));
-                    Err(e)
+            };
+            match bases.as_slice() {
+                [src] => {
+                    // 单个基础镜像时路径中的 `{stem}` 同样替换为基础镜像的文件名
+                    let target = expand_base_stem(&target, src);
+                    match wim_patch.apply_patch(src, index, &patch, &target, &expand_base_options(&options, src)) {
+                        Ok(()) => {
+                            write_console(ConsoleType::Success, &format!("{}", t!("apply_patch.success")));
+                            after_apply(&target)
+                        }
+                        Err(e) => {
+                            write_console(ConsoleType::Error, &format!("{}: {:?}", t!("apply_patch.failed"), e));
+                            Err(e)
+                        }
+                    }
+                }
+                _ => {
+                    match wim_patch.apply_patch_batch(&bases, index, &patch, &target, &options, keep_going, after_apply)
+                    {
+                        Ok(results) => print_batch_summary(&results),
+                        Err(e) => {
+                            write_console(ConsoleType::Error, &format!("{}: {:?}", t!("apply_patch.failed"), e));
+                            Err(e)
+                        }
+                    }
                 }
             }
         }
 
         // 获取补丁文件信息
-        Commands::Info { patch, xml } => match wim_patch.get_patch_info(&patch, xml) {
+        Commands::Info {
+            patch,
+            xml,
+            guid,
+            detailed,
+            top,
+            check_checksum,
+            list,
+            sort,
+            limit,
+        } => match check_checksum
+            .map_or(Ok(()), |checksum| wim_patch.check_checksum(&patch, &checksum))
+            .and_then(|()| match list {
+                true => wim_patch.list_operations(&patch, guid.as_deref(), sort, limit),
+                false => wim_patch.get_patch_info(&patch, xml, guid.as_deref(), detailed.then_some(top)),
+            }) {
             Ok(info) => {
                 println!("{}", info);
                 Ok(())
@@ -224,18 +640,252 @@ fn main() -> Result<()> {
             }
         },
 
+        // 提取补丁清单
+        Commands::ExtractManifest {
+            patch,
+            index,
+            out,
+            json,
+        } => match wim_patch.extract_manifest(&patch, index, &out, json) {
+            Ok(written) => {
+                for path in written {
+                    write_console(
+                        ConsoleType::Success,
+                        &format!("{}: {}", t!("extract_manifest.written"), path.display()),
+                    );
+                }
+                Ok(())
+            }
+            Err(e) => {
+                write_console(
+                    ConsoleType::Error,
+                    &format!("{}: {:?}", t!("extract_manifest.failed"), e),
+                );
+                Err(e)
+            }
+        },
+
+        // 列出镜像中的文件
+        Commands::Tree { image, index, sizes } => match wim_patch.list_tree(&image, index, sizes) {
+            Ok(entries) => {
+                for entry in &entries {
+                    match (sizes, entry.size) {
+                        (false, _) => println!("{}", entry.path),
+                        (true, Some(size)) => println!("{:>12}  {}", size, entry.path),
+                        (true, None) => println!("{:>12}  {}\\", "<DIR>", entry.path),
+                    }
+                }
+                Ok(())
+            }
+            Err(e) => {
+                write_console(ConsoleType::Error, &format!("{}: {:?}", t!("tree.failed"), e));
+                Err(e)
+            }
+        },
+
         // 合并补丁文件
-        Commands::Merge { patch, out, compress } => match wim_patch.merge_patches(&patch, &out, compress) {
-            Ok(()) => {
-                write_console(ConsoleType::Success, &format!("{}", t!("merge_patch.success")));
+        Commands::Merge {
+            patch,
+            out,
+            compress,
+            boot_index,
+            verify,
+            report_shared_payloads,
+            keep_compression,
+            fail_on_conflict,
+            verify_after_each,
+            flatten,
+            base,
+            base_index,
+            storage,
+            preset,
+            manifest_format,
+        } => {
+            let options = MergeOptions {
+                compress,
+                boot_index,
+                verify,
+                report_shared: report_shared_payloads,
+                keep_compression,
+                fail_on_conflict,
+                verify_after_each,
+                flatten_base: base.filter(|_| flatten),
+                flatten_base_index: base_index,
+                flatten_storage: storage,
+                flatten_preset: preset,
+                flatten_manifest_format: manifest_format,
+            };
+            match wim_patch.merge_patches(&patch, &out, &options) {
+                Ok(()) => {
+                    write_console(ConsoleType::Success, &format!("{}", t!("merge_patch.success")));
+                    Ok(())
+                }
+                Err(e) => {
+                    write_console(ConsoleType::Error, &format!("{}: {:?}", t!("merge_patch.failed"), e));
+                    Err(e)
+                }
+            }
+        }
+
+        // 拆分补丁文件
+        Commands::Split {
+            patch,
+            out_dir,
+            compress,
+        } => match wim_patch.split_patch(&patch, &out_dir, compress) {
+            Ok(written) => {
+                for path in written {
+                    write_console(
+                        ConsoleType::Info,
+                        &format!("{}: {}", t!("split_patch.written"), path.display()),
+                    );
+                }
+                write_console(ConsoleType::Success, &format!("{}", t!("split_patch.success")));
                 Ok(())
             }
             Err(e) => {
-                write_console(ConsoleType::Error, &format!("{}: {:?}", t!("merge_patch.failed"), e));
+                write_console(ConsoleType::Error, &format!("{}: {:?}", t!("split_patch.failed"), e));
                 Err(e)
             }
         },
 
+        // 在两个目录之间创建目录补丁
+        Commands::CreateDir {
+            base_dir,
+            target_dir,
+            out,
+            storage,
+            preset,
+            version,
+            author,
+            name,
+            description,
+            exclude,
+            include,
+            include_from,
+            dry_run,
+            skip_empty_dirs,
+            embed_full_fallback,
+            source_date,
+            compare_mode,
+            overwrite,
+            preserve_streams,
+            exclude_volatile,
+            dict_window,
+            zstd_level,
+            hive_diff,
+            compare_ignore_case,
+            compare_follow_reparse_depth,
+            report_unchanged,
+            sign_key,
+        } => {
+            let options = CreateOptions {
+                storage,
+                preset,
+                version: version.to_string(),
+                author,
+                name: name.unwrap_or(format!(
+                    "{}-patch-v{}",
+                    base_dir.file_name().unwrap_or_default().to_string_lossy(),
+                    version
+                )),
+                description: description.unwrap_or_default(),
+                exclude,
+                include: merge_pattern_file(include, include_from.as_deref())?,
+                compress: cli::Compress::None,
+                dry_run,
+                max_patch_size: None,
+                skip_empty_dirs,
+                boot_index: None,
+                manifest_format: cli::ManifestFormat::Xml,
+                embed_full_fallback,
+                skip_space_check: true,
+                source_date,
+                compare_mode,
+                stage_compressed: false,
+                concurrency: 1,
+                on_existing: if overwrite {
+                    cli::OnExisting::Overwrite
+                } else {
+                    cli::OnExisting::Error
+                },
+                strict_index: false,
+                emit_checksum: None,
+                preserve_streams,
+                exclude_volatile,
+                dict_window,
+                base_name: None,
+                target_name: None,
+                zstd_level,
+                subtree: None,
+                summary_json: None,
+                no_acl: false,
+                hive_diff,
+                compare_ignore_case,
+                sign_key,
+                compare_follow_reparse_depth,
+                report_unchanged,
+            };
+            match wim_patch.create_dir_patch(&base_dir, &target_dir, &out, &options) {
+                Ok(created) => {
+                    if dry_run {
+                        write_console(ConsoleType::Success, &format!("{}", t!("create_patch.dry_run_done")));
+                    } else if created {
+                        write_console(ConsoleType::Success, &format!("{}", t!("dir_patch.create_success")));
+                    }
+                    Ok(())
+                }
+                Err(e) => {
+                    write_console(
+                        ConsoleType::Error,
+                        &format!("{}: {:?}", t!("dir_patch.create_failed"), e),
+                    );
+                    Err(e)
+                }
+            }
+        }
+
+        // 将目录补丁应用到目录
+        Commands::ApplyDir {
+            base_dir,
+            patch,
+            out_dir,
+            exclude,
+            include,
+            include_from,
+            force,
+            io_retries,
+            no_delete,
+            apply_order,
+            verify_signature,
+            require_signature,
+        } => {
+            let options = ApplyOptions {
+                exclude,
+                include: merge_pattern_file(include, include_from.as_deref())?,
+                force,
+                io_retries,
+                no_delete,
+                apply_order,
+                verify_signature,
+                require_signature,
+                ..Default::default()
+            };
+            match wim_patch.apply_dir_patch(&base_dir, &patch, &out_dir, &options) {
+                Ok(()) => {
+                    write_console(ConsoleType::Success, &format!("{}", t!("dir_patch.apply_success")));
+                    Ok(())
+                }
+                Err(e) => {
+                    write_console(
+                        ConsoleType::Error,
+                        &format!("{}: {:?}", t!("dir_patch.apply_failed"), e),
+                    );
+                    Err(e)
+                }
+            }
+        }
+
         // 清理无效的挂载点
         Commands::Clean {} => match wim_patch.clean() {
             Ok(()) => {
@@ -247,14 +897,22 @@ fn main() -> Result<()> {
                 Err(e)
             }
         },
+
+        // 自检已在前面处理
+        Commands::SelfTest {} => unreachable!(),
+
+        // 比较各存储方式的补丁大小与耗时
+        Commands::Benchmark { old, new } => print_benchmark(&old, &new).inspect_err(|e| {
+            write_console(ConsoleType::Error, &format!("{}: {:?}", t!("benchmark.failed"), e));
+        }),
     };
 
     // 释放WimPatch实例
     drop(wim_patch);
 
     // 删除临时目录
-    if get_temp_path().exists()
-        && let Err(e) = fs::remove_dir_all(get_temp_path())
+    if get_temp_root().exists()
+        && let Err(e) = fs::remove_dir_all(get_temp_root())
     {
         write_console(
             ConsoleType::Warning,
@@ -262,14 +920,72 @@ fn main() -> Result<()> {
         );
     }
 
+    if let Some(code) = exit_code {
+        process::exit(code);
+    }
     result
 }
 
+/// 输出批量应用补丁的结果汇总
+///
+/// # 返回值
+///
+/// - `Ok(())` - 全部源镜像应用成功
+/// - `Err(anyhow::Error)` - 存在应用失败或未应用的源镜像
+fn print_batch_summary(results: &[BatchApplyResult]) -> Result<()> {
+    let succeeded = results
+        .iter()
+        .filter(|result| matches!(result.result, Some(Ok(()))))
+        .count();
+    println!();
+    println!(
+        "{}",
+        t!(
+            "apply_patch.batch_summary",
+            succeeded = succeeded,
+            total = results.len()
+        )
+    );
+    for result in results {
+        let status = match &result.result {
+            Some(Ok(())) => t!("apply_patch.batch_ok"),
+            Some(Err(_)) => t!("apply_patch.batch_failed"),
+            None => t!("apply_patch.batch_skipped"),
+        };
+        println!(
+            "  [{}] {} -> {}",
+            status,
+            result.base.display(),
+            result.target.display()
+        );
+    }
+    match succeeded == results.len() {
+        true => Ok(()),
+        false => Err(anyhow!(t!(
+            "apply_patch.batch_incomplete",
+            failed = results.len() - succeeded
+        ))),
+    }
+}
+
 /// 设置全局选项
-fn set_globals(debug: bool, language: Option<Language>, scratchdir: Option<PathBuf>, buffer_size: Option<usize>) {
+#[allow(clippy::too_many_arguments)]
+fn set_globals(
+    debug: bool,
+    quiet: bool,
+    language: Option<String>,
+    locale_file: Option<PathBuf>,
+    scratchdir: Option<PathBuf>,
+    buffer_size: Option<usize>,
+    wimgapi_path: Option<PathBuf>,
+    temp_name_length: Option<u64>,
+) {
     // 设置调试模式
     DEBUG.store(debug, Ordering::Relaxed);
 
+    // 设置静默模式
+    QUIET.store(quiet, Ordering::Relaxed);
+
     // 设置临时目录
     if let Some(path) = scratchdir {
         fs::create_dir_all(&path).unwrap();
@@ -281,13 +997,28 @@ fn set_globals(debug: bool, language: Option<Language>, scratchdir: Option<PathB
         BUFFER_SIZE.store(buffer_size, Ordering::Relaxed);
     }
 
+    // 设置临时文件名随机部分的长度
+    if let Some(length) = temp_name_length {
+        TEMP_NAME_LENGTH.store(length as usize, Ordering::Relaxed);
+    }
+
+    // 设置 wimgapi.dll 路径
+    if let Some(path) = wimgapi_path {
+        WIMGAPI_PATH.get_or_init(|| path);
+    }
+
     // 设置国际化
-    if let Some(lang) = language {
-        match lang {
-            Language::En => set_locale("en"),
-            Language::ZhCn => set_locale("zh-CN"),
-            Language::ZhTw => set_locale("zh-TW"),
-            Language::JaJp => set_locale("ja-JP"),
+    if let Some(name) = language {
+        match Language::from_str(&name, true) {
+            Ok(Language::En) => set_locale("en"),
+            Ok(Language::ZhCn) => set_locale("zh-CN"),
+            Ok(Language::ZhTw) => set_locale("zh-TW"),
+            Ok(Language::JaJp) => set_locale("ja-JP"),
+            Err(_) => {
+                // 未知语言回退为英文
+                set_locale("en");
+                write_console(ConsoleType::Warning, &t!("locale.unknown_language", language = name));
+            }
         };
     } else {
         // 获取系统语言
@@ -299,4 +1030,20 @@ fn set_globals(debug: bool, language: Option<Language>, scratchdir: Option<PathB
             _ => set_locale("en"),
         };
     }
+
+    // 加载自定义翻译文件
+    if let Some(path) = locale_file {
+        match load_locale_file(&path) {
+            Ok(missing) if !missing.is_empty() => write_console(
+                ConsoleType::Warning,
+                &format!(
+                    "{}: {}",
+                    t!("locale.missing_keys", count = missing.len()),
+                    missing.join(", ")
+                ),
+            ),
+            Ok(_) => {}
+            Err(e) => write_console(ConsoleType::Warning, &format!("{}: {:?}", t!("locale.load_failed"), e)),
+        }
+    }
 }
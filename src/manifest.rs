@@ -1,10 +1,12 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
 use quick_xml::SeError;
 use serde::{Deserialize, Serialize};
 use std::time::{SystemTime, UNIX_EPOCH};
 use uuid::Uuid;
 
 /// 补丁清单结构体
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 #[serde(rename = "PatchManifest")]
 pub struct PatchManifest {
     /// 补丁清单唯一标识符
@@ -51,6 +53,14 @@ pub struct PatchManifest {
     #[serde(rename = "TargetImageInfo")]
     pub target_image_info: ImageInfo,
 
+    /// 是否为整镜像补丁（补丁镜像即完整的更新镜像，应用时直接释放并重新捕获）
+    #[serde(rename = "WholeImage", default, skip_serializing_if = "std::ops::Not::not")]
+    pub whole_image: bool,
+
+    /// 是否为部分补丁（仅比较了子目录或创建时排除了部分差异），目标镜像信息中的统计信息为应用补丁后的估算值
+    #[serde(rename = "Partial", default, skip_serializing_if = "std::ops::Not::not")]
+    pub partial: bool,
+
     /// 操作集合
     pub operations: Vec<Operation>,
 }
@@ -105,7 +115,7 @@ pub struct ImageInfo {
 }
 
 /// 操作集合结构体
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 #[serde(rename = "Operation")]
 pub struct Operation {
     /// 操作类型
@@ -123,6 +133,60 @@ pub struct Operation {
     /// 存储类型（full/bsdiff/zstdiff）
     #[serde(rename = "Storage", skip_serializing_if = "Option::is_none")]
     pub storage: Option<String>,
+
+    /// 引用的补丁数据路径（内容相同的文件只存储一份）
+    #[serde(rename = "PayloadRef", skip_serializing_if = "Option::is_none")]
+    pub payload_ref: Option<String>,
+
+    /// 补丁中是否同时存储了完整文件，差异补丁应用失败时用于回退
+    #[serde(rename = "FullFallback", default, skip_serializing_if = "std::ops::Not::not")]
+    pub full_fallback: bool,
+
+    /// 目标文件是否为 NTFS 稀疏文件，应用时保留稀疏性
+    #[serde(rename = "Sparse", default, skip_serializing_if = "std::ops::Not::not")]
+    pub sparse: bool,
+
+    /// 名称与基础镜像仅大小写不同（忽略大小写比较时），应用后按操作路径的大小写重命名
+    #[serde(rename = "CaseRename", default, skip_serializing_if = "std::ops::Not::not")]
+    pub case_rename: bool,
+
+    /// 文件的 NTFS 备用数据流（创建补丁时指定 --preserve-streams 才会记录）
+    #[serde(rename = "Stream", default, skip_serializing_if = "Vec::is_empty")]
+    pub streams: Vec<StreamInfo>,
+
+    /// zstd 差异使用的字典窗口大小（仅使用旧文件末尾的这些字节作为字典，未指定时使用整个旧文件）
+    ///
+    /// XML 与二进制清单均记录该字段；不识别该字段的旧版本会使用整个旧文件作为字典，无法应用此类差异
+    #[serde(rename = "DictWindow", default, skip_serializing_if = "Option::is_none")]
+    pub dict_window: Option<u64>,
+
+    /// 更新后文件的 SHA-256（创建补丁时在暂存或生成差异的同一遍读取中计算，仅评估模式下不记录差异文件的哈希）
+    #[serde(rename = "Sha256", default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+
+    /// 基础文件的 SHA-256（仅差异存储的修改操作记录），应用前校验目标文件，不一致时不在其上应用字节差异
+    #[serde(rename = "BaseSha256", default, skip_serializing_if = "Option::is_none")]
+    pub base_sha256: Option<String>,
+}
+
+/// NTFS 备用数据流信息
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename = "Stream")]
+pub struct StreamInfo {
+    /// 数据流名称（不含前导冒号与 `:$DATA` 类型后缀）
+    #[serde(rename = "@name")]
+    pub name: String,
+
+    /// 数据流大小
+    #[serde(rename = "@size")]
+    pub size: u64,
+}
+
+impl Operation {
+    /// 获取补丁数据在补丁镜像中的路径
+    pub fn payload_path(&self) -> &str {
+        self.payload_ref.as_deref().unwrap_or(&self.path)
+    }
 }
 
 /// 目录修改类型枚举
@@ -150,6 +214,9 @@ impl PatchManifest {
     /// * `target_image_guid` - 目标镜像唯一标识符
     /// * `target_image_info` - 目标镜像信息
     /// * `operations` - 操作集合
+    /// * `source_date` - 覆盖时间戳的 Unix 时间（秒），为 `None` 时使用当前时间
+    ///
+    /// 指定 `source_date` 时补丁清单 ID 由镜像 GUID、版本与时间戳生成，相同输入可得到相同的清单
     ///
     /// # 返回值
     ///
@@ -164,21 +231,31 @@ impl PatchManifest {
         target_image_guid: &str,
         target_image_info: &ImageInfo,
         operations: &[Operation],
+        source_date: Option<i64>,
     ) -> Self {
-        // 生成当前时间的ISO 8601格式时间戳
-        let now = SystemTime::now();
-        let timestamp = now
-            .duration_since(UNIX_EPOCH)
-            .map(|dur| dur.as_secs())
-            .map(|secs| {
-                chrono::DateTime::from_timestamp(secs as i64, 0)
-                    .map(|dt| dt.to_rfc3339())
-                    .unwrap_or_default()
-            })
-            .unwrap_or_else(|_| "".to_string());
+        // 生成ISO 8601格式时间戳（未指定时使用当前时间）
+        let secs = source_date.or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .ok()
+                .map(|dur| dur.as_secs() as i64)
+        });
+        let timestamp = secs
+            .and_then(|secs| chrono::DateTime::from_timestamp(secs, 0))
+            .map(|dt| dt.to_rfc3339())
+            .unwrap_or_default();
+
+        // 可复现构建时根据输入生成确定的 ID
+        let id = match source_date {
+            Some(_) => Uuid::new_v5(
+                &Uuid::NAMESPACE_OID,
+                format!("{}|{}|{}|{}", base_image_guid, target_image_guid, version, timestamp).as_bytes(),
+            ),
+            None => Uuid::new_v4(),
+        };
 
         PatchManifest {
-            id: Uuid::new_v4().to_string(),
+            id: id.to_string(),
             name: name.to_string(),
             patch_version: version.to_string(),
             timestamp,
@@ -189,13 +266,34 @@ impl PatchManifest {
             base_image_info: base_image_info.clone(),
             target_image_guid: target_image_guid.to_string(),
             target_image_info: target_image_info.clone(),
+            whole_image: false,
+            partial: false,
             operations: operations.to_vec(),
         }
     }
 
     /// 生成XML字符串
+    ///
+    /// 操作路径中 XML 无法表示或解析时会丢失的字符按 [`encode_xml_path`] 转义
     pub fn to_xml(&self) -> Result<String, SeError> {
-        quick_xml::se::to_string(self)
+        if !self.operations.iter().any(|operation| {
+            needs_xml_path_escape(&operation.path)
+                || operation.payload_ref.as_deref().is_some_and(needs_xml_path_escape)
+        }) {
+            return quick_xml::se::to_string(self);
+        }
+
+        let mut manifest = self.clone();
+        for operation in &mut manifest.operations {
+            operation.path = encode_xml_path(&operation.path);
+            operation.payload_ref = operation.payload_ref.as_deref().map(encode_xml_path);
+        }
+        quick_xml::se::to_string(&manifest)
+    }
+
+    /// 生成JSON字符串
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
     }
 
     /// 从XML字符串解析
@@ -209,8 +307,109 @@ impl PatchManifest {
     /// * `Ok(PatchManifest)` - 如果解析成功
     /// * `Err` - 如果发生错误
     pub fn from_xml(xml_str: &str) -> Result<Self, quick_xml::DeError> {
-        quick_xml::de::from_str(xml_str)
+        let mut manifest: Self = quick_xml::de::from_str(xml_str)?;
+        for operation in &mut manifest.operations {
+            operation.path = decode_xml_path(&operation.path);
+            operation.payload_ref = operation.payload_ref.as_deref().map(decode_xml_path);
+        }
+        Ok(manifest)
+    }
+
+    /// 生成二进制清单（MessagePack 编码后再进行 Base64 编码，以便嵌入镜像信息 XML）
+    pub fn to_binary(&self) -> anyhow::Result<String> {
+        let data = rmp_serde::to_vec_named(self)?;
+        Ok(STANDARD.encode(data))
+    }
+
+    /// 从二进制清单解析
+    ///
+    /// # 参数
+    ///
+    /// * `binary_str` - Base64 编码的 MessagePack 清单
+    ///
+    /// # 返回值
+    ///
+    /// * `Ok(PatchManifest)` - 如果解析成功
+    /// * `Err` - 如果发生错误
+    pub fn from_binary(binary_str: &str) -> anyhow::Result<Self> {
+        let data = STANDARD.decode(binary_str.trim())?;
+        Ok(rmp_serde::from_slice(&data)?)
+    }
+}
+
+/// 判断字符在操作路径中是否需要转义
+///
+/// XML 1.0 不允许控制字符（制表符、换行与回车在解析时会被规范化或去除）与 U+FFFE/U+FFFF，
+/// 路径首尾的空格在解析时会被去除；`?` 为转义前缀，同样需要转义
+fn is_xml_path_escaped(c: char, edge: bool) -> bool {
+    c < ' ' || c == '?' || c == '\u{FFFE}' || c == '\u{FFFF}' || (edge && c == ' ')
+}
+
+/// 判断操作路径是否包含需要转义的字符
+fn needs_xml_path_escape(path: &str) -> bool {
+    let last = path.chars().count().saturating_sub(1);
+    path.chars()
+        .enumerate()
+        .any(|(i, c)| is_xml_path_escaped(c, i == 0 || i == last))
+}
+
+/// 转义操作路径中 XML 无法无损表示的字符
+///
+/// 需要转义的字符写为 `?` 加 4 位十六进制码点（如 `?000A`）。Windows 文件名不允许包含 `?`，
+/// 普通路径保持不变，与旧版本生成的清单兼容；增补平面字符（如 emoji）由 XML 直接保存，无需转义
+///
+/// # 参数
+///
+/// * `path` - 操作路径
+///
+/// # 返回值
+///
+/// * `String` - 转义后的路径
+pub fn encode_xml_path(path: &str) -> String {
+    let last = path.chars().count().saturating_sub(1);
+    let mut encoded = String::with_capacity(path.len());
+    for (i, c) in path.chars().enumerate() {
+        if is_xml_path_escaped(c, i == 0 || i == last) {
+            encoded.push_str(&format!("?{:04X}", c as u32));
+        } else {
+            encoded.push(c);
+        }
+    }
+    encoded
+}
+
+/// 还原 [`encode_xml_path`] 转义的操作路径
+///
+/// # 参数
+///
+/// * `path` - 清单中的操作路径
+///
+/// # 返回值
+///
+/// * `String` - 原始路径，无效的转义序列保持不变
+pub fn decode_xml_path(path: &str) -> String {
+    let mut decoded = String::with_capacity(path.len());
+    let mut rest = path;
+    while let Some(pos) = rest.find('?') {
+        decoded.push_str(&rest[..pos]);
+        let escaped = rest[pos + 1..]
+            .get(..4)
+            .filter(|hex| hex.chars().all(|c| c.is_ascii_hexdigit()))
+            .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+            .and_then(char::from_u32);
+        match escaped {
+            Some(c) => {
+                decoded.push(c);
+                rest = &rest[pos + 5..];
+            }
+            None => {
+                decoded.push('?');
+                rest = &rest[pos + 1..];
+            }
+        }
     }
+    decoded.push_str(rest);
+    decoded
 }
 
 impl ImageInfo {
@@ -1,49 +1,690 @@
 use crate::bsdiff::BsDiff;
-use crate::cli::{Compress, Preset, Storage};
+use crate::checksum::Checksum;
+use crate::cli::{ApplyOrder, CompareMode, Compress, ListSort, ManifestFormat, MatchMode, OnExisting, Preset, Storage};
 use crate::console::{ConsoleType, write_console};
-use crate::manifest::{Action, ImageInfo, Operation, PatchManifest};
-use crate::utils::{DiffType, compare_directories, format_bytes, get_tmp_name, replace_xml_field};
+use crate::hivediff::HiveDiff;
+use crate::manifest::{Action, ImageInfo, Operation, PatchManifest, StreamInfo};
+use crate::progress::{NoProgress, OperationProgress, OverallProgress, ProgressObserver};
+use crate::report::{ApplyReport, CreateSummary, OperationCounts, OperationOutcome, PatchAttributes, TargetStats};
+use crate::signature::{PatchSignature, payload_hashes, read_signing_key, read_verifying_key};
+use crate::utils::{
+    CompareOptions, DiffType, compare_subtree_with_total, copy_dir, copy_hashed, copy_sparse, create_tmp_dir,
+    dir_stats, for_each_bounded, format_bytes, format_guid, get_file_sha256, get_free_space, get_tmp_path,
+    get_xml_field, is_same_guid, is_sparse_file, link_or_copy, list_streams, long_path, make_sparse, normalize_guid,
+    replace_xml_field, restore_metadata, restore_name_case, retry_io, stream_path, wildcard_match,
+};
 use crate::wimgapi::{
-    WIM_COMPRESS_LZX, WIM_COMPRESS_NONE, WIM_COMPRESS_XPRESS, WIM_CREATE_ALWAYS, WIM_FLAG_MOUNT_READONLY,
-    WIM_GENERIC_MOUNT, WIM_GENERIC_READ, WIM_GENERIC_WRITE, WIM_MOUNT_FLAG_INVALID, WIM_MOUNT_FLAG_NO_MOUNTDIR,
-    WIM_MOUNT_FLAG_NO_WIM, WIM_MSG_PROCESS, WIM_MSG_PROGRESS, WIM_OPEN_ALWAYS, WIM_OPEN_EXISTING, WimMountInfoLevel1,
-    Wimgapi,
+    Handle, HandleGuard, MountGuard, WIM_COMPRESS_LZMS, WIM_COMPRESS_LZX, WIM_COMPRESS_NONE, WIM_COMPRESS_XPRESS,
+    WIM_CREATE_ALWAYS, WIM_CREATE_NEW, WIM_FLAG_FILEINFO, WIM_FLAG_MOUNT_READONLY, WIM_FLAG_NO_APPLY,
+    WIM_FLAG_NO_DIRACL, WIM_FLAG_NO_FILEACL, WIM_FLAG_VERIFY, WIM_GENERIC_MOUNT, WIM_GENERIC_READ, WIM_GENERIC_WRITE,
+    WIM_MOUNT_FLAG_INVALID, WIM_MOUNT_FLAG_NO_MOUNTDIR, WIM_MOUNT_FLAG_NO_WIM, WIM_MSG_ABORT_IMAGE, WIM_MSG_FILEINFO,
+    WIM_MSG_PROCESS, WIM_MSG_PROGRESS, WIM_OPEN_ALWAYS, WIM_OPEN_EXISTING, WimInfo, WimMountInfoLevel1, Wimgapi,
 };
 use crate::zstdiff::ZstdDiff;
-use crate::{get_temp_path, is_tty};
+use crate::{
+    check_cancelled, get_temp_path, get_wimgapi_path, has_active_mounts, is_cancelled, is_debug, is_tty,
+    register_mount, unregister_mount,
+};
 use anyhow::{Context, Result, anyhow};
 use chrono::{DateTime, Local};
+use clap::ValueEnum;
 use console::style;
-use indicatif::MultiProgress;
-use indicatif::{ProgressBar, ProgressStyle};
+use quick_xml::escape::escape;
 use rust_i18n::t;
 use semver::Version;
-use std::collections::HashSet;
+use serde::Serialize;
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::Read;
 use std::path::{Path, PathBuf};
 use std::string::String;
-use std::time::Duration;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Condvar, Mutex};
 use std::{fs, ptr};
+use windows::Win32::Storage::FileSystem::{FILE_ATTRIBUTE_DIRECTORY, WIN32_FIND_DATAW};
+
+/// 捕获补丁镜像时默认排除的系统路径
+pub const DEFAULT_CAPTURE_EXCLUDE: [&str; 7] = [
+    "$ntfs.log",
+    "hiberfil.sys",
+    "pagefile.sys",
+    "swapfile.sys",
+    "System Volume Information",
+    "RECYCLER",
+    "Windows\\CSC",
+];
+
+/// 指定 `--exclude-volatile` 时创建补丁排除的易变文件（每次启动都会变化、与补丁内容无关）
+///
+/// 模式不区分大小写，`*` 匹配任意字符（包括路径分隔符），模式可匹配完整路径或从任一路径分隔符之后开始的部分路径
+pub const VOLATILE_EXCLUDE: [&str; 7] = [
+    // 事件日志（Windows\System32\winevt\Logs）
+    "*.evtx",
+    // ETW 事件跟踪日志
+    "*.etl",
+    // 预读取数据（Windows\Prefetch）
+    "Prefetch\\*",
+    // 注册表配置单元事务日志
+    "*.LOG1",
+    "*.LOG2",
+    // 注册表事务（TxR）日志与 CLFS 基本日志文件
+    "*.regtrans-ms",
+    "*.blf",
+];
+
+/// 文件被占用时默认的最大重试次数
+pub const DEFAULT_IO_RETRIES: u32 = 2;
+
+/// 默认的补丁链最大长度
+pub const DEFAULT_MAX_CHAIN_LENGTH: usize = 64;
+
+/// 压缩暂存的补丁数据文件后缀，捕获前解压并还原为原文件名
+pub(crate) const STAGED_PAYLOAD_SUFFIX: &str = ".wimpatch-stage.zst";
+
+/// 压缩暂存补丁数据时使用的 zstd 压缩级别（暂存数据在捕获前即被解压，优先速度）
+const STAGED_PAYLOAD_LEVEL: i32 = 3;
+
+/// 备用数据流在补丁中的存储文件后缀，后接数据流序号
+pub(crate) const STREAM_PAYLOAD_SUFFIX: &str = ".wimpatch-stream";
+
+/// 目录补丁中补丁清单的文件名
+pub(crate) const DIR_PATCH_MANIFEST: &str = "manifest.xml";
+
+/// 目录补丁中补丁数据所在的子目录
+pub(crate) const DIR_PATCH_PAYLOAD: &str = "payload";
+
+/// 目录补丁中签名的文件名（指定签名私钥时写入）
+pub(crate) const DIR_PATCH_SIGNATURE: &str = "signature.xml";
+
+/// 批量应用时路径模板中代表基础镜像文件名（不含扩展名）的占位符
+pub(crate) const BASE_STEM_PLACEHOLDER: &str = "{stem}";
+
+/// 应用补丁后记录在目标镜像信息中的补丁版本字段，用于拒绝意外降级
+pub(crate) const APPLIED_VERSION_FIELD: &str = "WIMPATCHVERSION";
 
 pub struct WimPatch {
-    multi_pb: MultiProgress,
-    wimgapi: Wimgapi,
+    /// 已加载的 wimgapi（目录补丁不需要加载 wimgapi.dll，此时为 `None`）
+    wimgapi: Option<Wimgapi>,
+    /// 进度观察者
+    progress: Box<dyn ProgressObserver>,
+    /// 捕获补丁镜像时排除的路径（小写，按包含关系匹配）
+    capture_exclude: Vec<String>,
+    /// 并行构建多个镜像时，同一时间只允许一个线程向补丁文件追加镜像
+    capture_lock: Mutex<()>,
+    /// 应用补丁时各基础镜像索引被 `--exclude` 排除、未命中 `--include` 或被 `--no-delete` 跳过的文件操作数量
+    excluded: Mutex<HashMap<u32, u64>>,
+    /// 应用补丁时各基础镜像索引按结构化差异重建的注册表配置单元（小写路径 -> 重建后文件大小与更新镜像中文件大小之差）
+    rebuilt_hives: Mutex<HashMap<u32, HashMap<String, i64>>>,
+}
+
+/// 创建补丁选项
+#[derive(Debug, Clone)]
+pub struct CreateOptions {
+    /// 存储类型
+    pub storage: Storage,
+    /// 压缩预设
+    pub preset: Preset,
+    /// 补丁版本
+    pub version: String,
+    /// 作者
+    pub author: String,
+    /// 名称
+    pub name: String,
+    /// 描述
+    pub description: String,
+    /// 排除路径列表
+    pub exclude: Option<Vec<String>>,
+    /// 包含路径列表（指定时仅保留路径包含任一项的差异，排除列表优先）
+    pub include: Option<Vec<String>>,
+    /// 补丁文件压缩算法
+    pub compress: Compress,
+    /// 仅评估补丁内容，不生成补丁文件
+    pub dry_run: bool,
+    /// 补丁数据大小上限（字节）
+    pub max_patch_size: Option<u64>,
+    /// 忽略不包含任何文件变更的新增目录
+    pub skip_empty_dirs: bool,
+    /// 补丁文件的可启动镜像索引（0 表示清除可启动标记）
+    pub boot_index: Option<u32>,
+    /// 补丁清单格式
+    pub manifest_format: ManifestFormat,
+    /// 差异存储时同时嵌入完整文件，应用差异失败时回退
+    pub embed_full_fallback: bool,
+    /// 跳过临时目录剩余空间检查
+    pub skip_space_check: bool,
+    /// 覆盖补丁清单时间戳的 Unix 时间（秒），用于可复现构建
+    pub source_date: Option<i64>,
+    /// 修改文件的比较方式
+    pub compare_mode: CompareMode,
+    /// 以 zstd 压缩形式暂存完整文件，捕获前再解压
+    pub stage_compressed: bool,
+    /// 自动匹配多个镜像索引时同时构建的最大镜像数量
+    pub concurrency: usize,
+    /// 补丁文件已存在时的处理方式（追加时检查其为可与新补丁衔接的补丁文件，目录补丁仅支持覆盖）
+    pub on_existing: OnExisting,
+    /// 自动匹配索引时，基础镜像与更新镜像的镜像数量不同则报错
+    pub strict_index: bool,
+    /// 补丁文件校验文件输出路径
+    pub emit_checksum: Option<PathBuf>,
+    /// 记录新增与修改文件的 NTFS 备用数据流，应用时重新创建
+    pub preserve_streams: bool,
+    /// 排除 [`VOLATILE_EXCLUDE`] 中每次启动都会变化的易变文件
+    pub exclude_volatile: bool,
+    /// zstd 差异的字典窗口大小（字节），仅使用旧文件末尾的这些字节作为字典，为 `None` 时使用整个旧文件
+    pub dict_window: Option<u64>,
+    /// 按镜像名称选择基础镜像（代替基础镜像索引）
+    pub base_name: Option<String>,
+    /// 按镜像名称选择更新镜像（代替更新镜像索引）
+    pub target_name: Option<String>,
+    /// zstd 压缩级别，指定时代替压缩预设对应的级别
+    pub zstd_level: Option<i32>,
+    /// 仅比较两个镜像中的该子目录（相对于镜像根目录，以 `\` 分隔），补丁中的路径仍相对于镜像根目录
+    pub subtree: Option<String>,
+    /// 创建结果摘要（JSON）文件路径（捕获失败时同样写入）
+    pub summary_json: Option<PathBuf>,
+    /// 捕获补丁镜像（及整镜像模式下释放更新镜像）时不处理文件与目录的安全描述符
+    pub no_acl: bool,
+    /// 差异存储时注册表配置单元按键和值生成结构化差异（`storage: "hive"`），应用时重建配置单元
+    pub hive_diff: bool,
+    /// 比较目录时不区分路径大小写，仅大小写不同的文件记录为修改（路径使用目标镜像中的大小写）
+    pub compare_ignore_case: bool,
+    /// 签名私钥文件路径，指定时对每个补丁镜像的补丁清单与补丁数据签名（不支持整镜像补丁）
+    pub sign_key: Option<PathBuf>,
+    /// 比较目录时跟随目录重解析点（联接、符号链接）的最大嵌套层数，0 表示不跟随；形成循环或指向镜像之外的不跟随
+    pub compare_follow_reparse_depth: u32,
+    /// 比较目录时输出两个镜像中都存在且被判定为相同的文件，用于诊断比较结果（不影响补丁内容）
+    pub report_unchanged: bool,
+}
+
+impl Default for CreateOptions {
+    /// 与命令行参数默认值一致的创建选项（补丁名称与描述为空）
+    fn default() -> Self {
+        Self {
+            storage: Storage::Zstd,
+            preset: Preset::Medium,
+            version: "1.0.0".to_string(),
+            author: "unknown".to_string(),
+            name: String::new(),
+            description: String::new(),
+            exclude: None,
+            include: None,
+            compress: Compress::Lzx,
+            dry_run: false,
+            max_patch_size: None,
+            skip_empty_dirs: false,
+            boot_index: None,
+            manifest_format: ManifestFormat::Xml,
+            embed_full_fallback: false,
+            skip_space_check: false,
+            source_date: None,
+            compare_mode: CompareMode::Bytes,
+            stage_compressed: false,
+            concurrency: 1,
+            on_existing: OnExisting::Error,
+            strict_index: false,
+            emit_checksum: None,
+            preserve_streams: false,
+            exclude_volatile: false,
+            dict_window: None,
+            base_name: None,
+            target_name: None,
+            zstd_level: None,
+            subtree: None,
+            summary_json: None,
+            no_acl: false,
+            hive_diff: false,
+            compare_ignore_case: false,
+            sign_key: None,
+            compare_follow_reparse_depth: 0,
+            report_unchanged: false,
+        }
+    }
+}
+
+/// 创建补丁时未写入补丁的差异（被排除、未命中包含列表或被忽略的空目录），用于估算应用补丁后的目标镜像统计信息
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub(crate) struct DroppedDiffs {
+    /// 未写入补丁的差异数量
+    pub count: u64,
+    /// 应用补丁后的目标镜像相对更新镜像的目录数差异
+    pub dirs: i64,
+    /// 应用补丁后的目标镜像相对更新镜像的文件数差异
+    pub files: i64,
+    /// 应用补丁后的目标镜像相对更新镜像的总字节数差异
+    pub bytes: i64,
+}
+
+impl DroppedDiffs {
+    /// 记录一个未写入补丁的差异：新增的文件仍不存在，删除的文件仍然存在，修改的文件保持原大小
+    ///
+    /// # 参数
+    ///
+    /// - `diff_type` - 差异类型
+    /// - `old` - 基础目录中的路径（删除和修改时有效）
+    /// - `new` - 更新目录中的路径（新增和修改时有效）
+    pub(crate) fn record(&mut self, diff_type: &DiffType, old: Option<&Path>, new: Option<&Path>) {
+        let stat = |path: Option<&Path>| {
+            path.and_then(|path| path.metadata().ok())
+                .map(|metadata| (metadata.is_dir(), metadata.len() as i64))
+        };
+        self.count += 1;
+        match diff_type {
+            DiffType::Add => match stat(new) {
+                Some((true, _)) => self.dirs -= 1,
+                Some((false, len)) => {
+                    self.files -= 1;
+                    self.bytes -= len;
+                }
+                None => {}
+            },
+            DiffType::Delete => match stat(old) {
+                Some((true, _)) => self.dirs += 1,
+                Some((false, len)) => {
+                    self.files += 1;
+                    self.bytes += len;
+                }
+                None => {}
+            },
+            DiffType::Modify => {
+                self.bytes += stat(old).map_or(0, |(_, len)| len) - stat(new).map_or(0, |(_, len)| len);
+            }
+        }
+    }
+}
+
+/// 应用补丁选项
+#[derive(Debug, Clone)]
+pub struct ApplyOptions {
+    /// 排除路径列表
+    pub exclude: Option<Vec<String>>,
+    /// 包含路径列表（指定时仅应用路径包含任一项的操作，排除列表优先）
+    pub include: Option<Vec<String>>,
+    /// 是否强制应用
+    pub force: bool,
+    /// 仅应用到指定版本（包含该版本）
+    pub up_to_version: Option<Version>,
+    /// 跳过版本不高于指定版本的补丁（基础镜像已更新到该版本）
+    pub from_version: Option<Version>,
+    /// 导出前暂停，挂载应用后的镜像以供检查
+    pub pause_before_export: bool,
+    /// 跳过补丁工具版本检查
+    pub skip_tool_version_check: bool,
+    /// 跳过临时目录剩余空间检查
+    pub skip_space_check: bool,
+    /// 文件被占用时的最大重试次数
+    pub io_retries: u32,
+    /// 匹配补丁时代替实际基础镜像 GUID 的 GUID（用于重新捕获后 GUID 改变的镜像）
+    pub base_guid: Option<String>,
+    /// 为目标镜像写入完整性信息
+    pub verify: bool,
+    /// 应用前从操作路径开头去除的路径层数
+    pub strip_components: usize,
+    /// 应用前添加到操作路径开头的路径前缀
+    pub path_prefix: Option<String>,
+    /// 应用结果报告文件路径（应用失败时同样写入）
+    pub report: Option<PathBuf>,
+    /// 目标镜像保留基础镜像的创建时间
+    pub preserve_creation_time: bool,
+    /// 跳过所有删除操作
+    pub no_delete: bool,
+    /// 应用前用于校验补丁文件哈希的校验文件路径
+    pub check_checksum: Option<PathBuf>,
+    /// 补丁链的最大长度（0 表示不限制）
+    pub max_chain_length: usize,
+    /// 基础镜像 GUID 或统计信息不一致时的匹配模式
+    pub match_mode: MatchMode,
+    /// 按镜像名称选择基础镜像（代替基础镜像索引）
+    pub base_name: Option<String>,
+    /// 卸载前将应用补丁后的镜像内容复制到该目录
+    pub extract_to: Option<PathBuf>,
+    /// 不导出目标镜像（仅与 `extract_to` 一起使用）
+    pub no_export: bool,
+    /// 目标镜像已存在时的处理方式（不支持追加）
+    pub on_existing: OnExisting,
+    /// 不复制基础镜像，直接将补丁提交到提供的基础镜像文件
+    pub no_base_copy: bool,
+    /// 临时文件与挂载目录所在的目录，未指定时使用临时目录（批量应用时为各基础镜像独立的子目录）
+    pub scratch_dir: Option<PathBuf>,
+    /// 释放与重新捕获整镜像补丁时不处理文件与目录的安全描述符
+    pub no_acl: bool,
+    /// 目标镜像的压缩算法
+    pub target_compress: Compress,
+    /// 允许应用低于镜像已记录版本的补丁
+    pub allow_downgrade: bool,
+    /// 文件操作的执行顺序
+    pub apply_order: ApplyOrder,
+    /// 受信任的签名者公钥文件路径，指定时应用前校验已签名补丁镜像的签名
+    pub verify_signature: Option<PathBuf>,
+    /// 拒绝未签名的补丁镜像（需同时指定 `verify_signature`）
+    pub require_signature: bool,
+}
+impl Default for ApplyOptions {
+    /// 与命令行参数默认值一致的应用选项
+    fn default() -> Self {
+        Self {
+            exclude: None,
+            include: None,
+            force: false,
+            up_to_version: None,
+            from_version: None,
+            pause_before_export: false,
+            skip_tool_version_check: false,
+            skip_space_check: false,
+            io_retries: DEFAULT_IO_RETRIES,
+            base_guid: None,
+            verify: false,
+            strip_components: 0,
+            path_prefix: None,
+            report: None,
+            preserve_creation_time: false,
+            no_delete: false,
+            check_checksum: None,
+            max_chain_length: DEFAULT_MAX_CHAIN_LENGTH,
+            match_mode: MatchMode::Exact,
+            base_name: None,
+            extract_to: None,
+            no_export: false,
+            on_existing: OnExisting::Error,
+            no_base_copy: false,
+            scratch_dir: None,
+            no_acl: false,
+            target_compress: Compress::Lzx,
+            allow_downgrade: false,
+            apply_order: ApplyOrder::Phased,
+            verify_signature: None,
+            require_signature: false,
+        }
+    }
+}
+
+impl ApplyOptions {
+    /// 获取临时文件与挂载目录所在的目录
+    pub(crate) fn scratch_path(&self) -> PathBuf {
+        self.scratch_dir.clone().unwrap_or_else(get_temp_path)
+    }
+}
+
+/// 应用补丁镜像时在各步骤间传递的选项与应用结果报告
+pub(crate) struct ApplyContext<'a> {
+    /// 应用补丁选项
+    options: &'a ApplyOptions,
+    /// 应用结果报告，记录文件操作结果（失败时保留失败前的进度）
+    report: &'a mut ApplyReport,
+}
+
+/// 对基础镜像执行的一组文件操作
+pub(crate) struct OperationRun<'a> {
+    /// 基础镜像挂载路径
+    base_mount: &'a Path,
+    /// 补丁镜像挂载路径
+    patch_mount: &'a Path,
+    /// 操作列表
+    operations: &'a [Operation],
+    /// 进度任务编号（镜像索引）
+    task: u32,
+    /// 应用选项（排除列表、强制模式、重试次数与路径重映射）
+    options: &'a ApplyOptions,
+}
+
+/// 批量应用补丁时单个基础镜像的结果
+#[derive(Debug)]
+pub struct BatchApplyResult {
+    /// 基础镜像路径
+    pub base: PathBuf,
+    /// 目标镜像路径
+    pub target: PathBuf,
+    /// 应用结果（`None` 表示之前的基础镜像失败且未指定 `--keep-going`，未应用）
+    pub result: Option<Result<()>>,
+}
+
+/// 镜像中的文件或目录（`tree` 命令）
+#[derive(Debug, Clone, PartialEq)]
+pub struct TreeEntry {
+    /// 相对镜像根目录的路径
+    pub path: String,
+    /// 是否为目录（仅指定 `--sizes` 时可知，否则为 `false`）
+    pub is_dir: bool,
+    /// 文件大小（仅指定 `--sizes` 时记录，目录为 `None`）
+    pub size: Option<u64>,
+}
+
+/// 枚举镜像文件时通过 `pvUserData` 传给 [`TreeCallback`] 的状态
+struct TreeCollector<'a> {
+    /// 应用镜像时传入的根目录（各消息中的路径以它开头）
+    root: String,
+    /// 小写的排除路径列表
+    exclude: &'a [String],
+    /// 已枚举的文件和目录
+    entries: Vec<TreeEntry>,
+}
+
+/// 捕获补丁镜像时通过 `pvUserData` 传给 [`CaptureCallback`] 的状态
+pub(crate) struct CaptureState<'a> {
+    /// 小写的排除路径列表
+    exclude: &'a [String],
+    /// 已捕获（未被排除）的文件和目录数量
+    processed: AtomicU64,
+    /// 判断是否应中止捕获（默认为用户按下 Ctrl-C）
+    should_abort: fn() -> bool,
+}
+
+impl<'a> CaptureState<'a> {
+    /// 创建捕获状态，按下 Ctrl-C 时中止捕获
+    ///
+    /// # 参数
+    ///
+    /// - `exclude` - 小写的排除路径列表
+    pub(crate) fn new(exclude: &'a [String]) -> Self {
+        Self::with_abort(exclude, is_cancelled)
+    }
+
+    /// 创建捕获状态，并指定判断是否中止捕获的函数
+    ///
+    /// # 参数
+    ///
+    /// - `exclude` - 小写的排除路径列表
+    /// - `should_abort` - 每个文件捕获前调用，返回 `true` 时中止捕获
+    pub(crate) fn with_abort(exclude: &'a [String], should_abort: fn() -> bool) -> Self {
+        Self {
+            exclude,
+            processed: AtomicU64::new(0),
+            should_abort,
+        }
+    }
+
+    /// 已捕获（未被排除）的文件和目录数量
+    pub(crate) fn processed(&self) -> u64 {
+        self.processed.load(Ordering::Relaxed)
+    }
+}
+
+/// 基础镜像与补丁基线的匹配程度，按从强到弱排列
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MatchConfidence {
+    /// GUID 与镜像统计信息均一致
+    Exact,
+    /// GUID 一致，镜像统计信息不一致
+    GuidOnly,
+    /// 镜像统计信息一致，GUID 不一致
+    StatsOnly,
+    /// GUID 与镜像统计信息均不满足匹配模式，指定 `--force` 强制应用或指定起始版本放宽校验
+    Forced,
+}
+
+impl MatchConfidence {
+    /// 判断匹配模式是否接受该匹配程度（[`MatchConfidence::Forced`] 只能由 `--force` 接受）
+    pub fn allowed_by(self, mode: MatchMode) -> bool {
+        match self {
+            MatchConfidence::Exact => true,
+            MatchConfidence::GuidOnly => matches!(mode, MatchMode::Guid | MatchMode::Any),
+            MatchConfidence::StatsOnly => matches!(mode, MatchMode::Stats | MatchMode::Any),
+            MatchConfidence::Forced => false,
+        }
+    }
+
+    /// 匹配程度名称，用于输出与报告
+    pub fn as_str(self) -> &'static str {
+        match self {
+            MatchConfidence::Exact => "exact",
+            MatchConfidence::GuidOnly => "guid-only",
+            MatchConfidence::StatsOnly => "stats-only",
+            MatchConfidence::Forced => "forced",
+        }
+    }
+}
+
+/// 合并补丁选项
+#[derive(Debug, Clone)]
+pub struct MergeOptions {
+    /// 压缩算法
+    pub compress: Compress,
+    /// 可启动镜像索引，未指定时保留第一个带可启动标记的补丁包的可启动镜像
+    pub boot_index: Option<u32>,
+    /// 校验输入补丁包的完整性信息并为输出补丁包写入完整性信息
+    pub verify: bool,
+    /// 合并后读取补丁数据，报告跨补丁内容相同的数据（仅统计，不改写补丁）
+    pub report_shared: bool,
+    /// 尽量保留输入补丁包的压缩类型，避免重新压缩
+    pub keep_compression: bool,
+    /// 检测到冲突补丁时中止合并（默认仅输出警告）
+    pub fail_on_conflict: bool,
+    /// 导出每个输入补丁包后解析合并后补丁包中对应镜像的补丁清单，输出无效时立即中止
+    pub verify_after_each: bool,
+    /// 指定时将补丁链展平为单个补丁：先将所有补丁应用到该基础镜像，再比较基础镜像与最终镜像
+    pub flatten_base: Option<PathBuf>,
+    /// 展平时的基础镜像索引，未指定时处理所有匹配的镜像
+    pub flatten_base_index: Option<u32>,
+    /// 展平时的存储类型，未指定时使用输入补丁中修改操作最常用的存储类型
+    pub flatten_storage: Option<Storage>,
+    /// 展平时的压缩级别，未指定时与创建补丁的默认值相同
+    pub flatten_preset: Option<Preset>,
+    /// 展平时的补丁清单格式，未指定时与创建补丁的默认值相同
+    pub flatten_manifest_format: Option<ManifestFormat>,
+}
+
+/// 合并时检测到的冲突：多个补丁的基础镜像 GUID、索引与版本相同，但操作不同
+#[derive(Debug, Clone, PartialEq)]
+pub struct MergeConflict {
+    /// 基础镜像 GUID
+    pub base_guid: String,
+    /// 基础镜像索引
+    pub base_index: u32,
+    /// 补丁版本
+    pub version: String,
+    /// 冲突补丁的 ID
+    pub ids: Vec<String>,
+}
+
+/// 并行构建多个镜像时的捕获队列：比较与差异阶段并行执行，捕获按镜像顺序依次进行，
+/// 使补丁文件中的镜像顺序与串行构建时一致
+#[derive(Default)]
+pub(crate) struct CaptureQueue {
+    /// 下一个允许捕获的位置，以及已结束但尚未轮到的位置
+    state: Mutex<(usize, HashSet<usize>)>,
+    ready: Condvar,
+}
+
+impl CaptureQueue {
+    /// 领取指定位置的捕获凭据，凭据释放时该位置视为结束（已捕获、无需捕获或失败）
+    ///
+    /// # 参数
+    ///
+    /// - `position` - 镜像在构建顺序中的位置（从 0 开始）
+    pub(crate) fn ticket(&self, position: usize) -> CaptureTicket<'_> {
+        CaptureTicket { queue: self, position }
+    }
+}
+
+/// 捕获队列中某个位置的凭据
+pub(crate) struct CaptureTicket<'a> {
+    queue: &'a CaptureQueue,
+    position: usize,
+}
+
+impl CaptureTicket<'_> {
+    /// 等待轮到该位置（之前的位置均已结束）
+    pub(crate) fn wait(&self) {
+        let mut state = self.queue.state.lock().unwrap_or_else(|e| e.into_inner());
+        while state.0 < self.position {
+            state = self.queue.ready.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+}
+
+impl Drop for CaptureTicket<'_> {
+    fn drop(&mut self) {
+        // 标记该位置已结束，并越过所有已结束的连续位置，唤醒等待后续位置的线程
+        let mut state = self.queue.state.lock().unwrap_or_else(|e| e.into_inner());
+        let (next, finished) = &mut *state;
+        finished.insert(self.position);
+        while finished.remove(next) {
+            *next += 1;
+        }
+        self.queue.ready.notify_all();
+    }
 }
 
 impl WimPatch {
     /// 初始化 WimPatch 实例
     pub fn new() -> Result<Self> {
-        // 进度条管理器
-        let multi_pb = MultiProgress::new();
-
         // 加载 wimgapi
-        let wimgapi = Wimgapi::new(None).with_context(|| "Failed to load wimgapi.dll".to_string())?;
+        let wimgapi = Wimgapi::new(get_wimgapi_path()).with_context(|| "Failed to load wimgapi.dll".to_string())?;
+        Self::with_wimgapi(Some(wimgapi))
+    }
+
+    /// 初始化不加载 wimgapi.dll 的 WimPatch 实例，仅可用于目录补丁（`create_dir_patch`/`apply_dir_patch`）
+    pub fn without_wimgapi() -> Result<Self> {
+        Self::with_wimgapi(None)
+    }
 
+    fn with_wimgapi(wimgapi: Option<Wimgapi>) -> Result<Self> {
         // 创建临时目录
         if !get_temp_path().exists() {
             fs::create_dir_all(get_temp_path()).with_context(|| t!("create_temp_dir.failed"))?;
         }
 
-        Ok(Self { wimgapi, multi_pb })
+        Ok(Self {
+            wimgapi,
+            progress: Box::new(NoProgress),
+            capture_exclude: DEFAULT_CAPTURE_EXCLUDE
+                .iter()
+                .map(|path| path.to_ascii_lowercase())
+                .collect(),
+            capture_lock: Mutex::new(()),
+            excluded: Mutex::new(HashMap::new()),
+            rebuilt_hives: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// 获取已加载的 wimgapi
+    ///
+    /// # 返回值
+    ///
+    /// - `Ok(&Wimgapi)` - 已加载的 wimgapi
+    /// - `Err(anyhow::Error)` - 实例由 [`WimPatch::without_wimgapi`] 创建，未加载 wimgapi.dll
+    fn wimgapi(&self) -> Result<&Wimgapi> {
+        self.wimgapi
+            .as_ref()
+            .ok_or_else(|| anyhow!("wimgapi.dll is not loaded (WimPatch was created by WimPatch::without_wimgapi)"))
+    }
+
+    /// 设置进度观察者，默认不输出任何进度
+    ///
+    /// # 参数
+    ///
+    /// * `observer` - 进度观察者，如命令行使用的 `ConsoleProgress`
+    pub fn set_progress_observer(&mut self, observer: impl ProgressObserver + 'static) {
+        self.progress = Box::new(observer);
+    }
+
+    /// 设置捕获补丁镜像时排除的路径
+    ///
+    /// # 参数
+    ///
+    /// * `exclude` - 排除路径列表，路径中包含任一项（不区分大小写）即被排除
+    pub fn set_capture_exclude(&mut self, exclude: Vec<String>) {
+        self.capture_exclude = exclude.iter().map(|path| path.to_ascii_lowercase()).collect();
     }
 
     /// 解析补丁包的清单信息
@@ -57,6 +698,15 @@ impl WimPatch {
     /// * `Ok(PatchManifest)` - 解析成功，返回补丁清单
     /// * `Err(anyhow::Error)` - 解析失败，返回错误信息
     fn parse_patch_info(&self, image_info: &str) -> Result<PatchManifest> {
+        // 优先解析二进制清单，失败时回退到 XML 清单
+        if let (Some(start), Some(end)) = (
+            image_info.find("<PatchManifestBinary>"),
+            image_info.find("</PatchManifestBinary>"),
+        ) && let Ok(manifest) = PatchManifest::from_binary(&image_info[start + "<PatchManifestBinary>".len()..end])
+        {
+            return Ok(manifest);
+        }
+
         // 解析PatchManifest
         if let (Some(start), Some(end)) = (image_info.find("<PatchManifest>"), image_info.find("</PatchManifest>")) {
             let manifest_xml = &image_info[start..end + "</PatchManifest>".len()];
@@ -75,41 +725,33 @@ impl WimPatch {
     ///
     /// * `patch` - 补丁包文件路径
     /// * `out_xml` - 是否输出 XML 格式的清单信息
+    /// * `guid` - 仅输出基础镜像 GUID 与之匹配的补丁（已规范化）
+    /// * `detailed` - 显示操作的大小统计时，列出的最大操作数量（`None` 表示不显示）
     ///
     /// # 返回值
     ///
     /// * `Ok(String)` - 成功，返回清单信息字符串
     /// * `Err(anyhow::Error)` - 失败，返回错误信息
-    pub fn get_patch_info(&self, patch: &Path, out_xml: bool) -> Result<String> {
-        // 打开补丁包
-        let patch_handle = self
-            .wimgapi
-            .open(patch, WIM_GENERIC_READ, WIM_OPEN_EXISTING, WIM_COMPRESS_NONE)
-            .with_context(|| format!("Open patch image {} failed", patch.display()))?;
-
-        self.wimgapi
-            .set_temp_path(patch_handle, get_temp_path())
-            .with_context(|| "Set temp path failed")?;
-
+    pub fn get_patch_info(
+        &self,
+        patch: &Path,
+        out_xml: bool,
+        guid: Option<&str>,
+        detailed: Option<usize>,
+    ) -> Result<String> {
         let mut result = String::new();
-        for index in 1..=self.wimgapi.get_image_count(patch_handle) {
-            let image_handle = self
-                .wimgapi
-                .load_image(patch_handle, index)
-                .with_context(|| format!("Load image from patch image failed, index: {}", index))?;
-
-            // 获取补丁包的镜像信息
-            let image_info = self
-                .wimgapi
-                .get_image_info(image_handle)
-                .with_context(|| "Get image info from patch image failed".to_string())?;
-
-            self.wimgapi
-                .close(image_handle)
-                .with_context(|| "Close patch image failed".to_string())?;
-
-            // 解析PatchManifest
-            let manifest = self.parse_patch_info(&image_info)?;
+        for (index, manifest) in self.get_manifests(patch)? {
+            let base_guid =
+                normalize_guid(&manifest.base_image_guid).unwrap_or_else(|| manifest.base_image_guid.clone());
+            let target_guid =
+                normalize_guid(&manifest.target_image_guid).unwrap_or_else(|| manifest.target_image_guid.clone());
+
+            // 按基础镜像 GUID 过滤
+            if let Some(guid) = guid
+                && !is_same_guid(guid, &base_guid)
+            {
+                continue;
+            }
 
             if out_xml {
                 result.push_str(&manifest.to_xml().unwrap());
@@ -122,7 +764,9 @@ impl WimPatch {
             result.push_str(&format!("{:-^total_w$}\n", "-"));
             result.push_str(&format!("{:<label_w$} {}\n", "File:", patch.display()));
             result.push_str(&format!("{:<label_w$} {}\n", "Index:", index));
-            result.push_str(&format!("{:<label_w$} {{{}}}\n", "Id:", manifest.id));
+            result.push_str(&format!("{:<label_w$} {}\n", "Id:", manifest.id));
+            result.push_str(&format!("{:<label_w$} {}\n", "Base Guid:", base_guid));
+            result.push_str(&format!("{:<label_w$} {}\n", "Target Guid:", target_guid));
             result.push_str(&format!(
                 "{:<label_w$} {}\n",
                 "Size:",
@@ -165,10 +809,17 @@ impl WimPatch {
                 "Operations:", add_count, modify_count, delete_count, total
             ));
 
+            // 显示操作的大小统计
+            if let Some(top) = detailed {
+                result.push_str("\nOperation Details:\n");
+                result.push_str(&format!("{:-^total_w$}\n", "-"));
+                result.push_str(&Self::format_operation_details(&manifest.operations, top, label_w));
+            }
+
             // 显示基础镜像信息
             result.push_str("\nBase Image Information:\n");
             result.push_str(&format!("{:-^total_w$}\n", "-"));
-            result.push_str(&format!("{:<label_w$} {{{}}}\n", "Guid:", manifest.base_image_guid));
+            result.push_str(&format!("{:<label_w$} {}\n", "Guid:", base_guid));
             result.push_str(&format!("{:<label_w$} {}\n", "Index:", manifest.base_image_info.index));
 
             if let Some(name) = &manifest.base_image_info.name {
@@ -201,7 +852,7 @@ impl WimPatch {
             // 显示更新镜像信息
             result.push_str("\nTarget Image Information:\n");
             result.push_str(&format!("{:-^total_w$}\n", "-"));
-            result.push_str(&format!("{:<label_w$} {{{}}}\n", "Guid:", manifest.target_image_guid));
+            result.push_str(&format!("{:<label_w$} {}\n", "Guid:", target_guid));
             result.push_str(&format!(
                 "{:<label_w$} {}\n",
                 "Index:", manifest.target_image_info.index
@@ -231,543 +882,1519 @@ impl WimPatch {
 
             result.push('\n');
         }
-        self.wimgapi
-            .close(patch_handle)
-            .with_context(|| "Close patch failed".to_string())?;
+
+        // 显示补丁文件的 WIM 属性
+        if !out_xml && !result.is_empty() {
+            let attributes = PatchAttributes::from(&self.get_wim_info(patch)?);
+            let label_w = 18;
+            let total_w = label_w + patch.display().to_string().len() + 1;
+            result.push_str("Patch File Information:\n");
+            result.push_str(&format!("{:-^total_w$}\n", "-"));
+            result.push_str(&attributes.to_text(label_w));
+            result.push('\n');
+        }
         Ok(result)
     }
 
-    /// 创建补丁
+    /// 逐行列出补丁包中每个镜像的操作，多镜像补丁包按索引分组输出
     ///
     /// # 参数
     ///
-    /// - `base_image` - 基础镜像路径
-    /// - `index_base` - 基础镜像索引
-    /// - `updated_image` - 更新镜像路径
-    /// - `index_updated` - 更新镜像索引
-    /// - `patch_image` - 补丁镜像路径
-    /// - `storage` - 存储配置
-    /// - `preset` - 预设配置
-    /// - `version` - 补丁版本
-    /// - `author` - 作者
-    /// - `name` - 名称
-    /// - `description` - 描述
-    /// - `exclude` - 排除路径列表
-    /// - `compress` - 压缩算法
+    /// * `patch` - 补丁包文件路径
+    /// * `guid` - 仅列出基础镜像 GUID 与之匹配的补丁（已规范化）
+    /// * `sort` - 操作的排序方式
+    /// * `limit` - 每个镜像列出的最大操作数量（`None` 表示不限制）
     ///
     /// # 返回值
     ///
-    /// - `Ok(())` - 成功
-    /// - `Err(anyhow::Error)` - 失败
-    pub fn create_patch(
+    /// * `Ok(String)` - 成功，返回操作列表字符串
+    /// * `Err(anyhow::Error)` - 读取补丁清单失败
+    pub fn list_operations(
         &self,
-        base_image: &Path,
-        base_index: Option<u32>,
-        target_image: &Path,
-        target_index: Option<u32>,
-        patch_image: &Path,
-        storage: &Storage,
-        preset: &Preset,
-        version: &str,
-        author: &str,
-        name: &str,
-        description: &str,
-        exclude: Option<&[String]>,
-        compress: &Compress,
-    ) -> Result<()> {
-        // 获取基础镜像文件卷数
-        let base_handle = self
-            .wimgapi
-            .open(
-                base_image,
-                WIM_GENERIC_READ | WIM_GENERIC_MOUNT,
-                WIM_OPEN_EXISTING,
-                WIM_COMPRESS_NONE,
-            )
-            .with_context(|| "Open base image failed".to_string())?;
-        let base_image_count = self.wimgapi.get_image_count(base_handle);
-        self.wimgapi
-            .close(base_handle)
-            .with_context(|| "Close base handle error")?;
-
-        // 获取更新镜像文件卷数
-        let target_handle = self
-            .wimgapi
-            .open(
-                target_image,
-                WIM_GENERIC_READ | WIM_GENERIC_MOUNT,
-                WIM_OPEN_EXISTING,
-                WIM_COMPRESS_NONE,
-            )
-            .with_context(|| "Open update image failed".to_string())?;
-        let target_image_count = self.wimgapi.get_image_count(target_handle);
-        self.wimgapi
-            .close(target_handle)
-            .with_context(|| "Close update handle error")?;
-
-        // 选择要处理的镜像索引
-        if let Some(base_index) = base_index
-            && let Some(target_index) = target_index
-        {
-            if base_index > base_image_count || target_index > target_image_count {
-                return Err(anyhow!("Index {} is out of range", base_index));
-            }
-            write_console(
-                ConsoleType::Info,
-                &format!(
-                    "{}: {}({}{}) -> {}({}{})",
-                    t!("create_patch.create_patch"),
-                    t!("create_patch.base"),
-                    t!("create_patch.index"),
-                    base_index,
-                    t!("create_patch.target"),
-                    t!("create_patch.index"),
-                    target_index
-                ),
-            );
-
-            self.build_patch_image(
-                base_image,
-                base_index,
-                target_image,
-                target_index,
-                patch_image,
-                storage,
-                preset,
-                version,
-                author,
-                name,
-                description,
-                exclude,
-                *compress,
-            )?;
-        } else {
-            // 用户未指定索引，遍历所有基础镜像和更新镜像的组合(1-1、2-2、3-3等)
-            for index in 1..=base_image_count.min(target_image_count) {
-                write_console(
-                    ConsoleType::Info,
-                    &format!(
-                        "{}: {}({}{}) -> {}({}{})",
-                        t!("create_patch.create_patch"),
-                        t!("create_patch.base"),
-                        t!("create_patch.index"),
-                        index,
-                        t!("create_patch.target"),
-                        t!("create_patch.index"),
-                        index
-                    ),
-                );
-                self.build_patch_image(
-                    base_image,
-                    index,
-                    target_image,
-                    index,
-                    patch_image,
-                    storage,
-                    preset,
-                    version,
-                    author,
-                    name,
-                    description,
-                    exclude,
-                    *compress,
-                )?;
+        patch: &Path,
+        guid: Option<&str>,
+        sort: ListSort,
+        limit: Option<usize>,
+    ) -> Result<String> {
+        let mut result = String::new();
+        for (index, manifest) in self.get_manifests(patch)? {
+            // 按基础镜像 GUID 过滤
+            if let Some(guid) = guid
+                && !is_same_guid(&manifest.base_image_guid, guid)
+            {
+                continue;
             }
+            result.push_str(&format!(
+                "Index {} ({} operations):\n",
+                index,
+                manifest.operations.len()
+            ));
+            result.push_str(&Self::format_operation_list(&manifest.operations, sort, limit));
+            result.push('\n');
         }
-
-        self.multi_pb
-            .clear()
-            .with_context(|| "Clear multi pb failed".to_string())?;
-        Ok(())
+        Ok(result)
     }
 
-    /// 构建补丁镜像
+    /// 从数据流读取补丁包并获取清单信息，参数与 `get_patch_info` 相同
     ///
     /// # 参数
     ///
-    /// - `base_image` - 基础镜像路径
-    /// - `base_index` - 基础镜像索引
-    /// - `updated_image` - 更新镜像路径
-    /// - `updated_index` - 更新镜像索引
-    /// - `patch_image` - 输出补丁镜像路径
-    /// - `storage` - 存储配置
-    /// - `preset` - 预设配置
-    /// - `version` - 补丁版本
-    /// - `author` - 作者
-    /// - `name` - 名称
-    /// - `description` - 描述
-    /// - `exclude` - 排除路径列表
-    /// - `compress` - 压缩算法
+    /// * `patch` - 补丁包数据流
+    /// * `out_xml` - 是否输出 XML 格式的清单信息
+    /// * `guid` - 仅输出基础镜像 GUID 与之匹配的补丁（已规范化）
+    /// * `detailed` - 显示操作的大小统计时，列出的最大操作数量（`None` 表示不显示）
     ///
     /// # 返回值
     ///
-    /// - `Ok(())` - 成功
-    /// - `Err(anyhow::Error)` - 失败
-    fn build_patch_image(
+    /// * `Ok(String)` - 成功，返回清单信息字符串
+    /// * `Err(anyhow::Error)` - 写入临时文件或读取清单失败
+    pub fn get_patch_info_from_reader(
         &self,
-        base_image: &Path,
-        base_index: u32,
-        target_image: &Path,
-        target_index: u32,
-        patch_image: &Path,
-        storage: &Storage,
-        preset: &Preset,
-        version: &str,
-        author: &str,
-        name: &str,
-        description: &str,
-        exclude: Option<&[String]>,
-        compress: Compress,
-    ) -> Result<()> {
-        // 创建主进度条
-        let main_pb = self.multi_pb.add(ProgressBar::new(6));
-        main_pb.set_style(
-            ProgressStyle::with_template("{prefix:.bold.dim} [{elapsed_precise}] [{bar}] {pos}/{len}: {msg}")
-                .unwrap()
-                .progress_chars("=> "),
-        );
-        main_pb.enable_steady_tick(Duration::from_millis(80));
+        patch: impl Read,
+        out_xml: bool,
+        guid: Option<&str>,
+        detailed: Option<usize>,
+    ) -> Result<String> {
+        Self::with_spooled_patch(patch, |patch_image| {
+            self.get_patch_info(patch_image, out_xml, guid, detailed)
+        })
+    }
 
-        main_pb.set_message(t!("create_patch.read_image_info"));
-        if !is_tty() {
-            println!("{}", t!("create_patch.read_image_info"));
-        }
+    /// 将补丁包数据流写入临时目录中的临时文件并执行操作，返回前删除临时文件（包括出错时）
+    ///
+    /// # 参数
+    ///
+    /// * `patch` - 补丁包数据流
+    /// * `operation` - 使用临时文件路径执行的操作
+    ///
+    /// # 返回值
+    ///
+    /// * `Ok(T)` - 操作结果
+    /// * `Err(anyhow::Error)` - 写入临时文件失败或操作失败
+    pub(crate) fn with_spooled_patch<T>(mut patch: impl Read, operation: impl FnOnce(&Path) -> Result<T>) -> Result<T> {
+        let patch_image = get_tmp_path(&get_temp_path(), "stream-", ".wim");
+        let result = fs::File::create(&patch_image)
+            .and_then(|mut file| std::io::copy(&mut patch, &mut file))
+            .with_context(|| format!("Write patch stream to {} failed", patch_image.display()))
+            .and_then(|_| operation(&patch_image));
+        fs::remove_file(&patch_image).ok();
+        result
+    }
 
-        // 打开基础镜像文件
-        let base_handle = self.wimgapi.open(
-            base_image,
-            WIM_GENERIC_READ | WIM_GENERIC_MOUNT,
-            WIM_OPEN_EXISTING,
-            WIM_COMPRESS_NONE,
-        )?;
-        self.wimgapi
-            .set_temp_path(base_handle, get_temp_path())
-            .with_context(|| "Set temp path failed".to_string())?;
-        let base_image_handle = self
-            .wimgapi
-            .load_image(base_handle, base_index)
-            .with_context(|| "Load base image failed".to_string())?;
+    /// 先写入临时目录中的临时文件，成功后再替换输出文件，失败时删除临时文件且不修改已存在的输出文件
+    ///
+    /// # 参数
+    ///
+    /// * `output` - 最终输出文件路径
+    /// * `operation` - 写入临时文件的操作，参数为临时文件路径
+    ///
+    /// # 返回值
+    ///
+    /// * `Ok(T)` - 操作结果
+    /// * `Err(anyhow::Error)` - 操作失败或替换输出文件失败
+    pub(crate) fn with_atomic_output<T>(output: &Path, operation: impl FnOnce(&Path) -> Result<T>) -> Result<T> {
+        let temp = get_tmp_path(&get_temp_path(), "output-", ".wim");
+        let result = operation(&temp).and_then(|value| {
+            // 临时目录与输出文件不在同一个卷时无法直接重命名，先复制到输出文件所在目录再重命名
+            if fs::rename(&temp, output).is_err() {
+                let staged = get_tmp_path(output.parent().unwrap_or(Path::new("")), ".wimpatch-", ".tmp");
+                let replaced = fs::copy(&temp, &staged).and_then(|_| fs::rename(&staged, output));
+                if replaced.is_err() {
+                    fs::remove_file(&staged).ok();
+                }
+                replaced.with_context(|| format!("Replace {} failed", output.display()))?;
+            }
+            Ok(value)
+        });
+        fs::remove_file(&temp).ok();
+        result
+    }
 
-        // 读取基础镜像卷信息
-        let base_image_manifest = self
-            .wimgapi
-            .get_image_info(base_image_handle)
-            .with_context(|| "Get base image info failed".to_string())?;
-        let base_image_attributes = self
-            .wimgapi
-            .get_attributes(base_handle)
+    /// 读取补丁包中每个镜像的补丁清单
+    ///
+    /// # 参数
+    ///
+    /// * `patch` - 补丁包文件路径
+    ///
+    /// # 返回值
+    ///
+    /// * `Ok(Vec<(u32, PatchManifest)>)` - 镜像索引与解析后的补丁清单，按索引升序排列
+    /// * `Err(anyhow::Error)` - 打开补丁包、读取镜像信息或解析补丁清单失败
+    pub fn get_manifests(&self, patch: &Path) -> Result<Vec<(u32, PatchManifest)>> {
+        self.get_manifests_with_compression(patch)
+            .map(|(manifests, _)| manifests)
+    }
+
+    /// 读取补丁包中每个镜像的补丁清单以及补丁包的压缩类型
+    ///
+    /// # 参数
+    ///
+    /// * `patch` - 补丁包文件路径
+    ///
+    /// # 返回值
+    ///
+    /// * `Ok((Vec<(u32, PatchManifest)>, u32))` - 镜像索引与解析后的补丁清单（按索引升序排列）以及 WIM 压缩类型
+    /// * `Err(anyhow::Error)` - 打开补丁包、读取属性、读取镜像信息或解析补丁清单失败
+    fn get_manifests_with_compression(&self, patch: &Path) -> Result<(Vec<(u32, PatchManifest)>, u32)> {
+        let wimgapi = self.wimgapi()?;
+        // 打开补丁包
+        let patch_handle = HandleGuard::new(
+            wimgapi,
+            wimgapi
+                .open(patch, WIM_GENERIC_READ, WIM_OPEN_EXISTING, WIM_COMPRESS_NONE)
+                .with_context(|| format!("Open patch image {} failed", patch.display()))?,
+        );
+        wimgapi
+            .set_temp_path(patch_handle.handle(), &get_temp_path())
+            .with_context(|| "Set temp path failed".to_string())?;
+        let compression_type = wimgapi
+            .get_attributes(patch_handle.handle())
+            .with_context(|| "Get patch attributes failed".to_string())?
+            .compression_type;
+
+        let mut manifests = Vec::new();
+        for index in 1..=wimgapi.get_image_count(patch_handle.handle()) {
+            let manifest = self
+                .read_patch_manifest(patch_handle.handle(), index)
+                .with_context(|| format!("Read patch manifest failed, index: {}", index))?;
+            manifests.push((index, manifest));
+        }
+
+        patch_handle.close().with_context(|| "Close patch failed".to_string())?;
+        Ok((manifests, compression_type))
+    }
+
+    /// 读取已打开补丁包中指定镜像的镜像信息并解析补丁清单
+    ///
+    /// # 参数
+    ///
+    /// * `patch_handle` - 补丁包文件句柄
+    /// * `index` - 镜像索引
+    ///
+    /// # 返回值
+    ///
+    /// * `Ok(PatchManifest)` - 解析后的补丁清单
+    /// * `Err(anyhow::Error)` - 加载镜像、读取镜像信息或解析补丁清单失败
+    fn read_patch_manifest(&self, patch_handle: Handle, index: u32) -> Result<PatchManifest> {
+        let wimgapi = self.wimgapi()?;
+        wimgapi
+            .load_image(patch_handle, index)
+            .map_err(anyhow::Error::from)
+            .and_then(|image_handle| {
+                let image_info = wimgapi.get_image_info(image_handle);
+                wimgapi.close(image_handle).ok();
+                image_info.map_err(anyhow::Error::from)
+            })
+            .and_then(|image_info| self.parse_patch_info(&image_info))
+    }
+
+    /// 计算补丁文件的 SHA-256，并与补丁清单 ID 和版本一同写入校验文件
+    ///
+    /// # 参数
+    ///
+    /// * `patch` - 补丁文件路径
+    /// * `out` - 校验文件路径
+    ///
+    /// # 返回值
+    ///
+    /// * `Ok(())` - 成功
+    /// * `Err(anyhow::Error)` - 读取补丁文件或写入校验文件失败
+    pub fn write_checksum(&self, patch: &Path, out: &Path) -> Result<()> {
+        let manifests = self.get_manifests(patch)?;
+        let sha256 =
+            get_file_sha256(patch, None).with_context(|| format!("Hash patch file {} failed", patch.display()))?;
+        let file_name = patch.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+        Checksum::new(&sha256, &file_name, &manifests).write(out)?;
+        write_console(
+            ConsoleType::Info,
+            &format!("{}: {}", t!("checksum.written"), out.display()),
+        );
+        Ok(())
+    }
+
+    /// 使用校验文件校验补丁文件的 SHA-256
+    ///
+    /// # 参数
+    ///
+    /// * `patch` - 补丁文件路径
+    /// * `checksum` - 校验文件路径
+    ///
+    /// # 返回值
+    ///
+    /// * `Ok(())` - 哈希一致
+    /// * `Err(anyhow::Error)` - 读取失败或哈希不一致
+    pub fn check_checksum(&self, patch: &Path, checksum: &Path) -> Result<()> {
+        let checksum = Checksum::read(checksum)?;
+        let sha256 =
+            get_file_sha256(patch, None).with_context(|| format!("Hash patch file {} failed", patch.display()))?;
+        if !checksum.matches(&sha256) {
+            return Err(anyhow!(
+                "{}",
+                t!(
+                    "checksum.mismatch",
+                    path = patch.display(),
+                    expected = checksum.sha256,
+                    actual = sha256
+                )
+            ));
+        }
+        write_console(ConsoleType::Success, &t!("checksum.verified", sha256 = sha256));
+        Ok(())
+    }
+
+    /// 对即将捕获的补丁镜像签名
+    ///
+    /// # 参数
+    ///
+    /// * `sign_key` - 签名私钥文件路径
+    /// * `manifest_xml` - 将嵌入镜像信息的补丁清单（按应用时的方式解析，保证签名的清单与应用时一致）
+    /// * `patch_dir` - 补丁数据目录（捕获时排除的路径不计入）
+    ///
+    /// # 返回值
+    ///
+    /// * `Ok(PatchSignature)` - 补丁签名
+    /// * `Err(anyhow::Error)` - 读取私钥、解析补丁清单或计算补丁数据哈希失败
+    fn sign_patch_image(&self, sign_key: &Path, manifest_xml: &str, patch_dir: &Path) -> Result<PatchSignature> {
+        let key = read_signing_key(sign_key)?;
+        let manifest = self.parse_patch_info(manifest_xml)?;
+        let payloads = payload_hashes(patch_dir, |path| {
+            is_capture_excluded(&path.to_string_lossy(), &self.capture_exclude)
+        })?;
+        PatchSignature::sign(&key, &manifest, &payloads)
+    }
+
+    /// 应用补丁镜像前校验其签名，未指定 `verify_signature` 时不做任何检查
+    ///
+    /// # 参数
+    ///
+    /// * `index` - 补丁镜像索引
+    /// * `image_info` - 补丁镜像的镜像信息 XML
+    /// * `manifest` - 补丁镜像的补丁清单
+    /// * `patch_mount` - 补丁镜像挂载路径
+    /// * `options` - 应用选项
+    ///
+    /// # 返回值
+    ///
+    /// * `Ok(())` - 签名有效，或补丁未签名且未指定 `require_signature`（输出警告）
+    /// * `Err(anyhow::Error)` - 签名无效，或补丁未签名且指定了 `require_signature`
+    fn verify_patch_signature(
+        &self,
+        index: u32,
+        image_info: &str,
+        manifest: &PatchManifest,
+        patch_mount: &Path,
+        options: &ApplyOptions,
+    ) -> Result<()> {
+        let Some(key_path) = &options.verify_signature else {
+            return Ok(());
+        };
+        let Some(signature) = PatchSignature::from_image_info(image_info) else {
+            if options.require_signature {
+                return Err(anyhow!("{}", t!("apply_patch.signature_missing", index = index)));
+            }
+            write_console(
+                ConsoleType::Warning,
+                &t!("apply_patch.signature_unsigned", index = index),
+            );
+            return Ok(());
+        };
+        let signature = signature?;
+        let key = read_verifying_key(key_path)?;
+        let payloads = payload_hashes(patch_mount, |_| false)?;
+        signature
+            .verify(&key, manifest, &payloads)
+            .with_context(|| t!("apply_patch.signature_invalid", index = index).to_string())?;
+        write_console(
+            ConsoleType::Success,
+            &t!(
+                "apply_patch.signature_verified",
+                index = index,
+                signer = signature.signer
+            ),
+        );
+        Ok(())
+    }
+
+    /// 提取补丁文件中嵌入的补丁清单并写入文件
+    ///
+    /// # 参数
+    ///
+    /// * `patch` - 补丁文件路径
+    /// * `index` - 补丁镜像索引，为 `None` 时提取所有镜像（每个索引一个文件）
+    /// * `out` - 输出文件路径
+    /// * `json` - 是否输出为JSON格式
+    ///
+    /// # 返回值
+    ///
+    /// * `Ok(Vec<PathBuf>)` - 写入的文件列表
+    /// * `Err(anyhow::Error)` - 失败
+    pub fn extract_manifest(&self, patch: &Path, index: Option<u32>, out: &Path, json: bool) -> Result<Vec<PathBuf>> {
+        let manifests = self.get_manifests(patch)?;
+        if let Some(index) = index
+            && !manifests.iter().any(|(image_index, _)| *image_index == index)
+        {
+            return Err(anyhow!("{}", t!("extract_manifest.index_not_found", index = index)));
+        }
+
+        // 任一清单写入失败时删除已写入的文件，不留下不完整的输出
+        let mut written = Vec::new();
+        let result = (|| -> Result<()> {
+            for (image_index, manifest) in manifests {
+                if index.is_some_and(|index| index != image_index) {
+                    continue;
+                }
+                let content = if json {
+                    manifest
+                        .to_json()
+                        .with_context(|| "Serialize patch manifest to json failed")?
+                } else {
+                    manifest
+                        .to_xml()
+                        .with_context(|| "Serialize patch manifest to xml failed")?
+                };
+                let path = match index {
+                    Some(_) => out.to_path_buf(),
+                    None => manifest_output_path(out, image_index),
+                };
+                fs::write(&path, content).with_context(|| format!("Write manifest {} failed", path.display()))?;
+                written.push(path);
+            }
+            Ok(())
+        })();
+        if let Err(e) = result {
+            for path in &written {
+                fs::remove_file(path).ok();
+            }
+            return Err(e);
+        }
+        Ok(written)
+    }
+
+    /// 不挂载镜像，列出镜像中的文件和目录
+    ///
+    /// 以 `WIM_FLAG_NO_APPLY` 应用镜像，仅通过 `WIM_MSG_PROCESS` 消息（指定 `sizes` 时还有 `WIM_MSG_FILEINFO`）
+    /// 获取文件列表，不写入任何文件，也不需要挂载镜像。与捕获补丁镜像相同，跳过系统路径（`$ntfs.log`、`pagefile.sys` 等）
+    ///
+    /// # 参数
+    ///
+    /// * `image` - 镜像文件路径
+    /// * `index` - 镜像索引
+    /// * `sizes` - 是否获取文件大小与目录标识
+    ///
+    /// # 返回值
+    ///
+    /// * `Ok(Vec<TreeEntry>)` - 镜像中的文件和目录（按枚举顺序）
+    /// * `Err(anyhow::Error)` - 打开、加载或枚举镜像失败
+    pub fn list_tree(&self, image: &Path, index: u32, sizes: bool) -> Result<Vec<TreeEntry>> {
+        let wimgapi = self.wimgapi()?;
+        let handle = HandleGuard::new(
+            wimgapi,
+            wimgapi
+                .open(image, WIM_GENERIC_READ, WIM_OPEN_EXISTING, WIM_COMPRESS_NONE)
+                .map_err(|e| anyhow!("Open image {} error ({})", image.display(), e))?,
+        );
+        wimgapi
+            .set_temp_path(handle.handle(), &get_temp_path())
+            .map_err(|e| anyhow!("Set temp path error ({})", e))?;
+        let image_handle = HandleGuard::new(
+            wimgapi,
+            wimgapi
+                .load_image(handle.handle(), index)
+                .map_err(|e| anyhow!("Load image {} error ({})", index, e))?,
+        );
+
+        // 不写入任何文件，但应用目标目录仍需存在
+        let root = create_tmp_dir(&get_temp_path(), "tree-", "")
+            .with_context(|| format!("Create directory in {} failed", get_temp_path().display()))?;
+        let mut collector = TreeCollector {
+            root: root.to_string_lossy().into_owned(),
+            exclude: &self.capture_exclude,
+            entries: Vec::new(),
+        };
+
+        // 注册消息回调函数（收集状态通过 pvUserData 传入）
+        wimgapi.register_message_callback(
+            handle.handle(),
+            TreeCallback,
+            &mut collector as *mut TreeCollector as *mut std::ffi::c_void,
+        );
+        let flags = if sizes {
+            WIM_FLAG_NO_APPLY | WIM_FLAG_FILEINFO
+        } else {
+            WIM_FLAG_NO_APPLY
+        };
+        let result = wimgapi.apply_image(image_handle.handle(), &root, flags);
+        wimgapi.unregister_message_callback(handle.handle(), TreeCallback);
+        fs::remove_dir_all(&root).ok();
+        result.map_err(|e| anyhow!("Enumerate image {} error ({})", index, e))?;
+
+        Ok(collector.entries)
+    }
+
+    /// 生成操作的大小统计信息
+    ///
+    /// # 参数
+    ///
+    /// * `operations` - 操作集合
+    /// * `top` - 列出的最大操作数量
+    /// * `label_w` - 标签列宽度
+    ///
+    /// # 返回值
+    ///
+    /// * `String` - 新增字节数、修改目标字节数、修改操作的存储类型分布以及最大的操作
+    pub(crate) fn format_operation_details(operations: &[Operation], top: usize, label_w: usize) -> String {
+        let mut result = String::new();
+
+        let added_bytes: u64 = operations
+            .iter()
+            .filter(|op| op.action == Action::Add)
+            .filter_map(|op| op.size)
+            .sum();
+        let modified_bytes: u64 = operations
+            .iter()
+            .filter(|op| op.action == Action::Modify)
+            .filter_map(|op| op.size)
+            .sum();
+        result.push_str(&format!("{:<label_w$} {}\n", "Added Bytes:", format_bytes(added_bytes)));
+        result.push_str(&format!(
+            "{:<label_w$} {}\n",
+            "Modified Bytes:",
+            format_bytes(modified_bytes)
+        ));
+
+        // 修改操作的存储类型分布
+        let mut storages: BTreeMap<&str, (usize, u64)> = BTreeMap::new();
+        for op in operations.iter().filter(|op| op.action == Action::Modify) {
+            let entry = storages.entry(op.storage.as_deref().unwrap_or("unknown")).or_default();
+            entry.0 += 1;
+            entry.1 += op.size.unwrap_or(0);
+        }
+        if !storages.is_empty() {
+            result.push_str("Modify Storage:\n");
+            for (storage, (count, bytes)) in &storages {
+                result.push_str(&format!("  {:<16} {} ({})\n", storage, count, format_bytes(*bytes)));
+            }
+        }
+
+        // 空文件（修改为空的文件按完整文件存储）与稀疏文件
+        let empty_count = operations
+            .iter()
+            .filter(|op| op.action == Action::Modify && op.size == Some(0))
+            .count();
+        if empty_count > 0 {
+            result.push_str(&format!("{:<label_w$} {}\n", "Empty Files:", empty_count));
+        }
+        let sparse_count = operations.iter().filter(|op| op.sparse).count();
+        if sparse_count > 0 {
+            result.push_str(&format!("{:<label_w$} {}\n", "Sparse Files:", sparse_count));
+        }
+        let stream_count: usize = operations.iter().map(|op| op.streams.len()).sum();
+        if stream_count > 0 {
+            result.push_str(&format!("{:<label_w$} {}\n", "Data Streams:", stream_count));
+        }
+
+        // 最大的操作
+        let mut largest: Vec<&Operation> = operations
+            .iter()
+            .filter(|op| op.action != Action::Delete && op.size.is_some_and(|size| size > 0))
+            .collect();
+        largest.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+        if top > 0 && !largest.is_empty() {
+            result.push_str(&format!("Largest Operations (top {}):\n", top.min(largest.len())));
+            for op in largest.iter().take(top) {
+                result.push_str(&format!(
+                    "  {:<6} {:>10}  {}\n",
+                    format!("{:?}", op.action),
+                    format_bytes(op.size.unwrap_or(0)),
+                    op.path
+                ));
+            }
+        }
+        result
+    }
+
+    /// 生成操作列表，每行包含操作符号（+/~/-）、存储类型、大小与路径
+    ///
+    /// # 参数
+    ///
+    /// * `operations` - 操作集合
+    /// * `sort` - 排序方式
+    /// * `limit` - 列出的最大操作数量（`None` 表示不限制）
+    ///
+    /// # 返回值
+    ///
+    /// * `String` - 操作列表，超出数量限制时以省略的操作数量结尾
+    pub(crate) fn format_operation_list(operations: &[Operation], sort: ListSort, limit: Option<usize>) -> String {
+        let mut sorted: Vec<&Operation> = operations.iter().collect();
+        match sort {
+            ListSort::Path => sorted.sort_by(|a, b| a.path.cmp(&b.path)),
+            ListSort::Size => sorted.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path))),
+            ListSort::Action => sorted.sort_by_key(|op| {
+                let order = match op.action {
+                    Action::Add => 0,
+                    Action::Modify => 1,
+                    Action::Delete => 2,
+                };
+                (order, op.path.as_str())
+            }),
+        }
+
+        let mut result = String::new();
+        let shown = limit.unwrap_or(sorted.len()).min(sorted.len());
+        for op in sorted.iter().take(shown) {
+            let symbol = match op.action {
+                Action::Add => '+',
+                Action::Modify => '~',
+                Action::Delete => '-',
+            };
+            let size = match op.action {
+                Action::Delete => "-".to_string(),
+                _ => op.size.map(format_bytes).unwrap_or_else(|| "-".to_string()),
+            };
+            result.push_str(&format!(
+                "{} {:<8} {:>10}  {}\n",
+                symbol,
+                op.storage.as_deref().unwrap_or("-"),
+                size,
+                op.path
+            ));
+        }
+        if shown < sorted.len() {
+            result.push_str(&format!("... {} more\n", sorted.len() - shown));
+        }
+        result
+    }
+
+    /// 创建补丁
+    ///
+    /// 指定 `options.summary_json` 时，无论创建成功与否都写入创建结果摘要
+    ///
+    /// # 参数
+    ///
+    /// - `base_image` - 基础镜像路径
+    /// - `index_base` - 基础镜像索引
+    /// - `updated_image` - 更新镜像路径
+    /// - `index_updated` - 更新镜像索引
+    /// - `patch_image` - 补丁镜像路径
+    /// - `options` - 创建补丁选项
+    ///
+    /// # 返回值
+    ///
+    /// - `Ok(())` - 成功
+    /// - `Err(anyhow::Error)` - 失败
+    pub fn create_patch(
+        &self,
+        base_image: &Path,
+        base_index: Option<u32>,
+        target_image: &Path,
+        target_index: Option<u32>,
+        patch_image: &Path,
+        options: &CreateOptions,
+    ) -> Result<()> {
+        // 记录创建过程（并行构建的镜像共用同一摘要）
+        let summary = Mutex::new(CreateSummary::new(base_image, target_image, patch_image));
+        let result = self.create_patch_images(
+            base_image,
+            base_index,
+            target_image,
+            target_index,
+            patch_image,
+            options,
+            &summary,
+        );
+        let Some(summary_path) = &options.summary_json else {
+            return result;
+        };
+        let mut summary = summary.into_inner().unwrap_or_else(|e| e.into_inner());
+        match &result {
+            Ok(()) => summary.success = true,
+            Err(e) => summary.error = Some(format!("{:#}", e)),
+        }
+
+        // 摘要写入失败不影响创建结果
+        match summary.write(summary_path) {
+            Ok(()) => write_console(
+                ConsoleType::Info,
+                &format!("{}: {}", t!("create_patch.summary_written"), summary_path.display()),
+            ),
+            Err(e) => write_console(
+                ConsoleType::Warning,
+                &format!("{}: {:?}", t!("create_patch.summary_write_failed"), e),
+            ),
+        }
+        result
+    }
+
+    /// 选择镜像索引并构建补丁镜像，比较与捕获结果记录到 `summary`
+    #[allow(clippy::too_many_arguments)]
+    fn create_patch_images(
+        &self,
+        base_image: &Path,
+        base_index: Option<u32>,
+        target_image: &Path,
+        target_index: Option<u32>,
+        patch_image: &Path,
+        options: &CreateOptions,
+        summary: &Mutex<CreateSummary>,
+    ) -> Result<()> {
+        let wimgapi = self.wimgapi()?;
+        // 指定镜像名称时按名称选择镜像索引
+        let base_index = match &options.base_name {
+            Some(name) => Some(self.resolve_image_name(base_image, name)?),
+            None => base_index,
+        };
+        let target_index = match &options.target_name {
+            Some(name) => Some(self.resolve_image_name(target_image, name)?),
+            None => target_index,
+        };
+
+        check_zstd_level(options)?;
+        check_include(options.include.as_deref())?;
+
+        // 整镜像补丁包含完整镜像，无法只比较子目录
+        if options.subtree.is_some() && options.storage == Storage::WholeImage {
+            return Err(anyhow!("{}", t!("create_patch.subtree_whole_image")));
+        }
+
+        // 签名覆盖补丁数据文件，整镜像补丁不支持签名；开始比较前先确认私钥可用
+        if let Some(sign_key) = &options.sign_key {
+            if options.storage == Storage::WholeImage {
+                return Err(anyhow!("{}", t!("create_patch.sign_whole_image")));
+            }
+            read_signing_key(sign_key)?;
+        }
+
+        // 挂载镜像需要管理员权限
+        Self::check_elevated()?;
+
+        // 基础镜像与更新镜像为同一文件的同一索引时，补丁必然为空
+        if is_same_source(base_image, base_index, target_image, target_index) {
+            return Err(anyhow!("{}", t!("create_patch.same_source")));
+        }
+
+        // 检查临时目录剩余空间（补丁数据最多与更新镜像大小相当）
+        if !options.dry_run && !options.skip_space_check {
+            let target_size = fs::metadata(target_image)
+                .with_context(|| "Get target image size failed".to_string())?
+                .len();
+            Self::check_scratch_space(&get_temp_path(), target_size)?;
+        }
+
+        // 获取基础镜像文件卷数
+        let base_handle = wimgapi
+            .open(
+                base_image,
+                WIM_GENERIC_READ | WIM_GENERIC_MOUNT,
+                WIM_OPEN_EXISTING,
+                WIM_COMPRESS_NONE,
+            )
+            .with_context(|| "Open base image failed".to_string())?;
+        let base_image_count = wimgapi.get_image_count(base_handle);
+        let base_attributes = wimgapi.get_attributes(base_handle);
+        wimgapi.close(base_handle).with_context(|| "Close base handle error")?;
+        let base_attributes = base_attributes.with_context(|| "Get base image attributes failed".to_string())?;
+        let base_guid = format_guid(&base_attributes.guid);
+
+        // 获取更新镜像文件卷数
+        let target_handle = wimgapi
+            .open(
+                target_image,
+                WIM_GENERIC_READ | WIM_GENERIC_MOUNT,
+                WIM_OPEN_EXISTING,
+                WIM_COMPRESS_NONE,
+            )
+            .with_context(|| "Open update image failed".to_string())?;
+        let target_image_count = wimgapi.get_image_count(target_handle);
+        wimgapi
+            .close(target_handle)
+            .with_context(|| "Close update handle error")?;
+
+        // 处理已存在的补丁文件（默认拒绝修改，指定 --on-existing 后覆盖或追加新镜像）
+        let mut overwrite = false;
+        if !options.dry_run && patch_image.exists() {
+            match options.on_existing {
+                OnExisting::Error => {
+                    return Err(anyhow!(
+                        "{}",
+                        t!("create_patch.output_exists", path = patch_image.display())
+                    ));
+                }
+                OnExisting::Overwrite => {
+                    // 先写入临时文件，全部镜像构建成功后再替换，失败时保留原补丁文件
+                    overwrite = true;
+                    write_console(ConsoleType::Info, &t!("create_patch.overwrite_existing"));
+                }
+                OnExisting::Append => self.check_append_target(patch_image, &base_guid, options)?,
+            }
+        }
+
+        // 构建补丁镜像并设置可启动镜像
+        let build_images = |patch_image: &Path| -> Result<()> {
+            // 已生成补丁镜像的基础镜像索引（按索引顺序）
+            let mut captured_indices = Vec::new();
+            // 选择要处理的镜像索引
+            if let Some(base_index) = base_index
+                && let Some(target_index) = target_index
+            {
+                if base_index > base_image_count || target_index > target_image_count {
+                    return Err(anyhow!("Index {} is out of range", base_index));
+                }
+                write_console(
+                    ConsoleType::Info,
+                    &format!(
+                        "{}: {}({}{}) -> {}({}{})",
+                        t!("create_patch.create_patch"),
+                        t!("create_patch.base"),
+                        t!("create_patch.index"),
+                        base_index,
+                        t!("create_patch.target"),
+                        t!("create_patch.index"),
+                        target_index
+                    ),
+                );
+
+                let queue = CaptureQueue::default();
+                let ticket = queue.ticket(0);
+                if self.build_patch_image(
+                    base_image,
+                    base_index,
+                    target_image,
+                    target_index,
+                    patch_image,
+                    options,
+                    &ticket,
+                    summary,
+                )? {
+                    captured_indices.push(base_index);
+                } else if !options.dry_run {
+                    return Err(anyhow!("{}", t!("create_patch.no_changes_all")));
+                }
+            } else {
+                // 镜像数量不同时，多出的镜像无法匹配（指定 --strict-index 时报错，否则警告）
+                let (skipped_base, skipped_target) = unmatched_indices(base_image_count, target_image_count);
+                if options.strict_index && (!skipped_base.is_empty() || !skipped_target.is_empty()) {
+                    return Err(anyhow!(
+                        "{}",
+                        t!(
+                            "create_patch.index_count_mismatch",
+                            base = base_image_count,
+                            target = target_image_count
+                        )
+                    ));
+                }
+                let join = |indices: &[u32]| indices.iter().map(u32::to_string).collect::<Vec<_>>().join(", ");
+                if !skipped_base.is_empty() {
+                    write_console(
+                        ConsoleType::Warning,
+                        &t!("create_patch.skipped_base_indices", indices = join(&skipped_base)),
+                    );
+                }
+                if !skipped_target.is_empty() {
+                    write_console(
+                        ConsoleType::Warning,
+                        &t!("create_patch.skipped_target_indices", indices = join(&skipped_target)),
+                    );
+                }
+
+                // 用户未指定索引，遍历所有基础镜像和更新镜像的组合(1-1、2-2、3-3等)，最多同时构建 concurrency 个
+                // 比较与差异阶段并行执行，捕获按索引顺序依次追加到补丁文件
+                let indices: Vec<u32> = (1..=base_image_count.min(target_image_count)).collect();
+                let captured = Mutex::new(Vec::new());
+                let queue = CaptureQueue::default();
+                for_each_bounded(&indices, options.concurrency, |&index| {
+                    let ticket = queue.ticket(index as usize - 1);
+                    write_console(
+                        ConsoleType::Info,
+                        &format!(
+                            "{}: {}({}{}) -> {}({}{})",
+                            t!("create_patch.create_patch"),
+                            t!("create_patch.base"),
+                            t!("create_patch.index"),
+                            index,
+                            t!("create_patch.target"),
+                            t!("create_patch.index"),
+                            index
+                        ),
+                    );
+                    if self.build_patch_image(
+                        base_image,
+                        index,
+                        target_image,
+                        index,
+                        patch_image,
+                        options,
+                        &ticket,
+                        summary,
+                    )? {
+                        captured.lock().unwrap_or_else(|e| e.into_inner()).push(index);
+                    }
+                    Ok(())
+                })?;
+                captured_indices = captured.into_inner().unwrap_or_else(|e| e.into_inner());
+                captured_indices.sort_unstable();
+                if captured_indices.is_empty() && !options.dry_run {
+                    return Err(anyhow!("{}", t!("create_patch.no_changes_all")));
+                }
+            }
+            if options.dry_run {
+                return Ok(());
+            }
+
+            // 设置可启动镜像（未指定 --boot-index 时，基础镜像的可启动镜像已生成补丁则保留其可启动标记）
+            let patch_handle = wimgapi
+                .open(patch_image, WIM_GENERIC_WRITE, WIM_OPEN_EXISTING, WIM_COMPRESS_NONE)
+                .with_context(|| "Open patch image failed".to_string())?;
+            let result = (|| -> Result<()> {
+                let boot_index = match options.boot_index {
+                    Some(boot_index) => Some(boot_index),
+                    None => preserved_boot_index(
+                        base_attributes.boot_index,
+                        &captured_indices,
+                        wimgapi.get_image_count(patch_handle),
+                    )
+                    // 追加到已设置可启动镜像的补丁文件时不覆盖原有标记
+                    .filter(|_| {
+                        wimgapi
+                            .get_attributes(patch_handle)
+                            .is_ok_and(|attributes| attributes.boot_index == 0)
+                    }),
+                };
+                match boot_index {
+                    Some(boot_index) => self.set_boot_index(patch_handle, boot_index),
+                    None => Ok(()),
+                }
+            })();
+            wimgapi
+                .close(patch_handle)
+                .with_context(|| "Close patch handle error")?;
+            result
+        };
+        if overwrite {
+            Self::with_atomic_output(patch_image, build_images)?;
+        } else {
+            build_images(patch_image)?;
+        }
+
+        self.progress.clear();
+
+        // 写入补丁文件校验文件
+        if !options.dry_run
+            && let Some(checksum) = &options.emit_checksum
+        {
+            self.write_checksum(patch_image, checksum)?;
+        }
+        Ok(())
+    }
+
+    /// 检查追加的目标补丁文件：必须为补丁文件，补丁名称与新补丁不同或新补丁无法与已有补丁衔接时发出警告
+    ///
+    /// # 参数
+    ///
+    /// - `patch_image` - 已存在的补丁文件路径
+    /// - `base_guid` - 新补丁的基础镜像 GUID
+    /// - `options` - 创建补丁选项
+    ///
+    /// # 返回值
+    ///
+    /// - `Ok(())` - 可以追加
+    /// - `Err(anyhow::Error)` - 已存在的文件不是补丁文件
+    fn check_append_target(&self, patch_image: &Path, base_guid: &str, options: &CreateOptions) -> Result<()> {
+        let existing = self
+            .get_manifests(patch_image)
+            .with_context(|| t!("create_patch.append_not_patch", path = patch_image.display()))?;
+        if existing.is_empty() {
+            return Err(anyhow!(
+                "{}",
+                t!("create_patch.append_not_patch", path = patch_image.display())
+            ));
+        }
+
+        // 补丁名称不一致时，合并后的补丁包难以辨认
+        if let Some((index, manifest)) = existing.iter().find(|(_, manifest)| manifest.name != options.name) {
+            write_console(
+                ConsoleType::Warning,
+                &t!(
+                    "create_patch.append_name_mismatch",
+                    index = index,
+                    existing = manifest.name,
+                    name = options.name
+                ),
+            );
+        }
+
+        // 新补丁的基础镜像应为已有补丁的基础镜像或更新镜像，否则应用时无法形成补丁链
+        if !is_appendable_to(&existing, base_guid) {
+            write_console(
+                ConsoleType::Warning,
+                &t!("create_patch.append_unchained", guid = base_guid),
+            );
+        }
+        Ok(())
+    }
+
+    /// 设置 WIM 文件的可启动镜像索引
+    ///
+    /// # 参数
+    ///
+    /// - `handle` - 以写入方式打开的 WIM 文件句柄
+    /// - `boot_index` - 可启动镜像索引，0 表示清除可启动标记
+    ///
+    /// # 返回值
+    ///
+    /// - `Ok(())` - 成功
+    /// - `Err(anyhow::Error)` - 索引超出范围或设置失败
+    fn set_boot_index(&self, handle: Handle, boot_index: u32) -> Result<()> {
+        let wimgapi = self.wimgapi()?;
+        let image_count = wimgapi.get_image_count(handle);
+        if boot_index > image_count {
+            return Err(anyhow!(
+                "{}",
+                t!("boot_index.out_of_range", index = boot_index, count = image_count)
+            ));
+        }
+        if !wimgapi.set_boot_image(handle, boot_index) {
+            return Err(anyhow!("Set boot image failed"));
+        }
+        Ok(())
+    }
+
+    /// 构建补丁镜像
+    ///
+    /// # 参数
+    ///
+    /// - `base_image` - 基础镜像路径
+    /// - `base_index` - 基础镜像索引
+    /// - `updated_image` - 更新镜像路径
+    /// - `updated_index` - 更新镜像索引
+    /// - `patch_image` - 输出补丁镜像路径
+    /// - `options` - 创建补丁选项
+    /// - `ticket` - 捕获队列凭据，轮到该镜像后才输出评估结果或写入补丁文件
+    /// - `summary` - 创建结果摘要，记录比较得到的文件操作与捕获结果
+    ///
+    /// # 返回值
+    ///
+    /// - `Ok(true)` - 成功，补丁镜像已写入补丁文件
+    /// - `Ok(false)` - 基础镜像与更新镜像没有差异（或仅评估），未写入补丁镜像
+    /// - `Err(anyhow::Error)` - 失败
+    #[allow(clippy::too_many_arguments)]
+    fn build_patch_image(
+        &self,
+        base_image: &Path,
+        base_index: u32,
+        target_image: &Path,
+        target_index: u32,
+        patch_image: &Path,
+        options: &CreateOptions,
+        ticket: &CaptureTicket,
+        summary: &Mutex<CreateSummary>,
+    ) -> Result<bool> {
+        let wimgapi = self.wimgapi()?;
+        // 开始处理镜像（共 6 个阶段）
+        self.progress.task_started(base_index, 6);
+
+        self.progress.stage(base_index, &t!("create_patch.read_image_info"));
+
+        // 打开基础镜像文件（句柄在离开作用域时自动关闭）
+        let base_handle = HandleGuard::new(
+            wimgapi,
+            wimgapi.open(
+                base_image,
+                WIM_GENERIC_READ | WIM_GENERIC_MOUNT,
+                WIM_OPEN_EXISTING,
+                WIM_COMPRESS_NONE,
+            )?,
+        );
+        wimgapi
+            .set_temp_path(base_handle.handle(), &get_temp_path())
+            .with_context(|| "Set temp path failed".to_string())?;
+        let base_image_handle = HandleGuard::new(
+            wimgapi,
+            wimgapi
+                .load_image(base_handle.handle(), base_index)
+                .with_context(|| "Load base image failed".to_string())?,
+        );
+
+        // 读取基础镜像卷信息
+        let base_image_manifest = wimgapi
+            .get_image_info(base_image_handle.handle())
+            .with_context(|| "Get base image info failed".to_string())?;
+        let base_image_attributes = wimgapi
+            .get_attributes(base_handle.handle())
             .with_context(|| "Get base image attributes failed".to_string())?;
         let base_image_info =
             ImageInfo::from_xml(&base_image_manifest).with_context(|| "Parse base image info failed".to_string())?;
 
         // 打开更新镜像文件
-        let target_handle = self.wimgapi.open(
-            target_image,
-            WIM_GENERIC_READ | WIM_GENERIC_MOUNT,
-            WIM_OPEN_EXISTING,
-            WIM_COMPRESS_NONE,
-        )?;
-        self.wimgapi
-            .set_temp_path(target_handle, get_temp_path())
+        let target_handle = HandleGuard::new(
+            wimgapi,
+            wimgapi.open(
+                target_image,
+                WIM_GENERIC_READ | WIM_GENERIC_MOUNT,
+                WIM_OPEN_EXISTING,
+                WIM_COMPRESS_NONE,
+            )?,
+        );
+        wimgapi
+            .set_temp_path(target_handle.handle(), &get_temp_path())
             .with_context(|| "Set temp path failed".to_string())?;
-        let target_image_handle = self
-            .wimgapi
-            .load_image(target_handle, target_index)
-            .with_context(|| "Load target image failed".to_string())?;
+        let target_image_handle = HandleGuard::new(
+            wimgapi,
+            wimgapi
+                .load_image(target_handle.handle(), target_index)
+                .with_context(|| "Load target image failed".to_string())?,
+        );
 
         // 读取更新镜像卷信息
-        let target_image_manifest = self
-            .wimgapi
-            .get_image_info(target_image_handle)
+        let target_image_manifest = wimgapi
+            .get_image_info(target_image_handle.handle())
             .with_context(|| "Get target image info failed".to_string())?;
-        let target_image_attributes = self
-            .wimgapi
-            .get_attributes(target_handle)
+        let target_image_attributes = wimgapi
+            .get_attributes(target_handle.handle())
             .with_context(|| "Get target image attributes failed".to_string())?;
         let target_image_info = ImageInfo::from_xml(&target_image_manifest)
             .with_context(|| "Parse target image info failed".to_string())?;
-        main_pb.inc(1);
+        self.progress.step(base_index);
 
         // 挂载基础镜像文件
-        main_pb.set_message(t!("create_patch.mount_base"));
-        if !is_tty() {
-            println!("{}", t!("create_patch.mount_base"));
-        }
+        check_cancelled()?;
+        self.progress.stage(base_index, &t!("create_patch.mount_base"));
 
-        let base_mount = get_temp_path().join(get_tmp_name("base-", "", 6));
-        if base_mount.exists() {
-            fs::remove_dir_all(&base_mount).with_context(|| "Remove base mount dir failed".to_string())?;
-        }
-        fs::create_dir_all(&base_mount).with_context(|| "Create base mount dir failed".to_string())?;
-        if let Err(e) = self
-            .wimgapi
-            .mount_image_handle(base_image_handle, &base_mount, WIM_FLAG_MOUNT_READONLY)
-        {
-            self.wimgapi.close(base_image_handle).ok();
-            self.wimgapi.close(base_handle).ok();
-            return Err(anyhow!("{}: {}", t!("create_patch.mount_base_failed"), e));
-        }
-        main_pb.inc(1);
+        let base_mount = create_tmp_dir(&get_temp_path(), "base-", "")
+            .with_context(|| "Create base mount dir failed".to_string())?;
+        let base_mounted = MountGuard::mount(base_image_handle, &base_mount, WIM_FLAG_MOUNT_READONLY)
+            .map_err(|e| anyhow!("{}: {}", t!("create_patch.mount_base_failed"), e))?;
+        self.progress.step(base_index);
 
         // 挂载更新镜像文件
-        main_pb.set_message(t!("create_patch.mount_target"));
-        if !is_tty() {
-            println!("{}", t!("create_patch.mount_target"));
-        }
-        let target_mount = get_temp_path().join(get_tmp_name("target-", "", 6));
-        if target_mount.exists() {
-            fs::remove_dir_all(&target_mount).with_context(|| "Remove target mount dir failed".to_string())?;
-        }
-        fs::create_dir_all(&target_mount).with_context(|| "Create target mount dir failed".to_string())?;
-        if let Err(e) = self
-            .wimgapi
-            .mount_image_handle(target_image_handle, &target_mount, WIM_FLAG_MOUNT_READONLY)
-        {
-            self.wimgapi.unmount_image_handle(base_image_handle).ok();
-            self.wimgapi.close(base_image_handle).ok();
-            self.wimgapi.close(base_handle).ok();
-            self.wimgapi.close(target_image_handle).ok();
-            self.wimgapi.close(target_handle).ok();
-            return Err(anyhow!("{}: {}", t!("create_patch.mount_target_failed"), e));
-        }
-        main_pb.inc(1);
+        check_cancelled()?;
+        self.progress.stage(base_index, &t!("create_patch.mount_target"));
+        let target_mount = create_tmp_dir(&get_temp_path(), "target-", "")
+            .with_context(|| "Create target mount dir failed".to_string())?;
+        let target_mounted = MountGuard::mount(target_image_handle, &target_mount, WIM_FLAG_MOUNT_READONLY)
+            .map_err(|e| anyhow!("{}: {}", t!("create_patch.mount_target_failed"), e))?;
+        self.progress.step(base_index);
+
+        // 比较文件差异（整镜像模式下直接释放更新镜像）
+        check_cancelled()?;
+        let whole_image = options.storage == Storage::WholeImage;
+        let message = if whole_image {
+            t!("create_patch.extract_target")
+        } else {
+            t!("create_patch.compare_diff")
+        };
+        self.progress.stage(base_index, &message);
 
-        // 比较文件差异
-        main_pb.set_message(t!("create_patch.compare_diff"));
-        if !is_tty() {
-            println!("{}", t!("create_patch.compare_diff"));
-        }
+        let patch_dir = if options.dry_run {
+            get_tmp_path(&get_temp_path(), "patch-", "")
+        } else {
+            create_tmp_dir(&get_temp_path(), "patch-", "").with_context(|| "Create patch dir failed".to_string())?
+        };
+        let (operations, estimate, dropped) = if whole_image {
+            let (operations, estimate) = self.extract_whole_image(
+                target_handle.handle(),
+                target_index,
+                &patch_dir,
+                &target_image_info,
+                options,
+            )?;
+            (operations, estimate, DroppedDiffs::default())
+        } else {
+            self.create_operations(&base_mount, &target_mount, &patch_dir, base_index, options)?
+        };
 
-        let patch_dir = get_temp_path().join(get_tmp_name("patch-", "", 6));
-        if patch_dir.exists() {
-            fs::remove_dir_all(&patch_dir).with_context(|| "Remove patch dir failed".to_string())?;
-        }
-        fs::create_dir_all(&patch_dir).with_context(|| "Create patch dir failed".to_string())?;
-        let operations = match self.create_operations(&base_mount, &target_mount, &patch_dir, storage, preset, exclude)
-        {
-            Ok(operations) => operations,
-            Err(e) => {
-                self.wimgapi.unmount_image_handle(base_image_handle).ok();
-                self.wimgapi.close(base_image_handle).ok();
-                self.wimgapi.close(base_handle).ok();
-                self.wimgapi.unmount_image_handle(target_image_handle).ok();
-                self.wimgapi.close(target_image_handle).ok();
-                self.wimgapi.close(target_handle).ok();
-                return Err(e);
-            }
+        // 仅比较子目录时统计两个镜像中该子目录的内容，子目录之外在应用补丁后保持基础镜像的内容
+        let subtree_stats = match &options.subtree {
+            Some(subtree) if !whole_image => Some((
+                dir_stats(base_mount.join(subtree)).with_context(|| "Read base subtree failed".to_string())?,
+                dir_stats(target_mount.join(subtree)).with_context(|| "Read target subtree failed".to_string())?,
+            )),
+            _ => None,
         };
-        main_pb.inc(1);
+        self.progress.step(base_index);
 
         // 卸载基础镜像
-        main_pb.set_message(t!("create_patch.unmount_base"));
-        if !is_tty() {
-            println!("{}", t!("create_patch.unmount_base"));
-        }
-        if let Err(e) = self.wimgapi.unmount_image_handle(base_image_handle) {
-            self.wimgapi.close(base_image_handle).ok();
-            self.wimgapi.close(base_handle).ok();
-            self.wimgapi.unmount_image_handle(target_image_handle).ok();
-            self.wimgapi.close(target_image_handle).ok();
-            self.wimgapi.close(target_handle).ok();
-            return Err(anyhow!("{}: {}", t!("create_patch.unmount_base_failed"), e));
-        }
-        self.wimgapi
-            .close(base_image_handle)
-            .with_context(|| "Close base image handle error")?;
-        self.wimgapi
-            .close(base_handle)
-            .with_context(|| "Close base handle error")?;
+        self.progress.stage(base_index, &t!("create_patch.unmount_base"));
+        base_mounted
+            .unmount()
+            .map_err(|e| anyhow!("{}: {}", t!("create_patch.unmount_base_failed"), e))?;
+        base_handle.close().with_context(|| "Close base handle error")?;
 
         // 卸载更新镜像
-        main_pb.set_message(t!("create_patch.unmount_target"));
-        if !is_tty() {
-            println!("{}", t!("create_patch.unmount_target"));
+        self.progress.stage(base_index, &t!("create_patch.unmount_target"));
+        target_mounted
+            .unmount()
+            .map_err(|e| anyhow!("{}: {}", t!("create_patch.unmount_target_failed"), e))?;
+        target_handle.close().with_context(|| "Close target handle error")?;
+        self.progress.step(base_index);
+
+        // 记录比较得到的文件操作（指定 --summary-json 时写入创建结果摘要文件）
+        let staged_size: u64 = estimate.values().map(|(_, bytes)| bytes).sum();
+        let storage = options
+            .storage
+            .to_possible_value()
+            .map(|value| value.get_name().to_string())
+            .unwrap_or_default();
+        summary.lock().unwrap_or_else(|e| e.into_inner()).record_image(
+            base_index,
+            target_index,
+            &options.version,
+            &storage,
+            &operations,
+            staged_size,
+        );
+
+        // 没有任何差异时不写入空的补丁镜像
+        if operations.is_empty() && !whole_image {
+            self.progress.task_finished(base_index);
+            write_console(
+                ConsoleType::Warning,
+                &t!("create_patch.no_changes", base = base_index, target = target_index),
+            );
+            return Ok(false);
+        }
+
+        // 仅评估模式：按镜像顺序输出操作列表与预估大小
+        if options.dry_run {
+            self.progress.task_finished(base_index);
+            ticket.wait();
+            println!(
+                "{}",
+                Self::format_dry_run_report(base_index, target_index, &operations, &estimate)
+            );
         }
-        if let Err(e) = self.wimgapi.unmount_image_handle(target_image_handle) {
-            self.wimgapi.close(target_image_handle).ok();
-            self.wimgapi.close(target_handle).ok();
-            return Err(anyhow!("{}: {}", t!("create_patch.unmount_target_failed"), e));
+
+        // 检查补丁数据大小是否超出上限（未压缩大小，结果偏保守）
+        if let Some(max_patch_size) = options.max_patch_size
+            && staged_size > max_patch_size
+        {
+            return Err(anyhow!(
+                "{}",
+                t!(
+                    "create_patch.max_patch_size_exceeded",
+                    size = format_bytes(staged_size),
+                    limit = format_bytes(max_patch_size)
+                )
+            ));
+        }
+
+        // 仅评估模式不创建补丁文件
+        if options.dry_run {
+            return Ok(false);
         }
-        self.wimgapi
-            .close(target_image_handle)
-            .with_context(|| "Close target image handle error")?;
-        self.wimgapi
-            .close(target_handle)
-            .with_context(|| "Close target handle error")?;
-        main_pb.inc(1);
 
         // 创建补丁镜像
-        main_pb.set_message(t!("create_patch.create_patch"));
-        if !is_tty() {
-            println!("{}", t!("create_patch.create_patch"));
-        }
-
-        // 生成补丁清单
-        let patch_manifest = PatchManifest::new(
-            name,
-            description,
-            author,
-            version,
-            &format!("{:?}", base_image_attributes.guid),
+        self.progress.stage(base_index, &t!("create_patch.create_patch"));
+
+        // 部分补丁记录应用补丁后目标镜像的估算统计信息，而不是更新镜像的统计信息
+        let partial = subtree_stats.is_some() || dropped.count > 0;
+        let expected_target_info = if partial {
+            expected_target_info(&base_image_info, &target_image_info, subtree_stats, &dropped)
+        } else {
+            target_image_info.clone()
+        };
+
+        // 生成补丁清单（二进制格式同时保留 XML 清单，以兼容旧版本）
+        let mut patch_manifest = PatchManifest::new(
+            &options.name,
+            &options.description,
+            &options.author,
+            &options.version,
+            &format_guid(&base_image_attributes.guid),
             &base_image_info,
-            &format!("{:?}", target_image_attributes.guid),
-            &target_image_info,
+            &format_guid(&target_image_attributes.guid),
+            &expected_target_info,
             &operations,
-        )
-        .to_xml()
-        .with_context(|| "Serialize patch manifest error")?;
+            options.source_date,
+        );
+        patch_manifest.whole_image = whole_image;
+        patch_manifest.partial = partial;
+        summary
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .set_manifest_id(base_index, &patch_manifest.id);
+        let mut patch_manifest_xml = patch_manifest
+            .to_xml()
+            .with_context(|| "Serialize patch manifest error")?;
+        if options.manifest_format == ManifestFormat::Binary {
+            let binary = patch_manifest
+                .to_binary()
+                .with_context(|| "Serialize binary patch manifest error")?;
+            patch_manifest_xml.push_str(&format!("<PatchManifestBinary>{}</PatchManifestBinary>", binary));
+        }
 
-        // 创建补丁文件
-        let patch_handle = match self.wimgapi.open(
-            patch_image,
-            WIM_GENERIC_WRITE,
-            WIM_OPEN_ALWAYS,
-            match compress {
-                Compress::None => WIM_COMPRESS_NONE,
-                Compress::Xpress => WIM_COMPRESS_XPRESS,
-                Compress::Lzx => WIM_COMPRESS_LZX,
-            },
-        ) {
-            Ok(h) => h,
-            Err(e) => {
-                self.wimgapi.close(base_image_handle).ok();
-                self.wimgapi.close(base_handle).ok();
-                self.wimgapi.close(target_image_handle).ok();
-                self.wimgapi.close(target_handle).ok();
-                return Err(anyhow!("Create patch file error ({})", e));
-            }
-        };
+        // 解压以压缩形式暂存的补丁数据后再捕获
+        if options.stage_compressed {
+            let (staged, expanded) = Self::expand_staged_payloads(&patch_dir, &operations)
+                .with_context(|| "Expand staged payloads error".to_string())?;
+            write_console(
+                ConsoleType::Info,
+                &t!(
+                    "create_patch.stage_compressed_saved",
+                    staged = format_bytes(staged),
+                    expanded = format_bytes(expanded),
+                    saved = format_bytes(expanded.saturating_sub(staged))
+                ),
+            );
+        }
 
-        // 注册消息回调函数
-        self.wimgapi
-            .register_message_callback(patch_handle, CreatePatchCallback);
+        // 对补丁清单与最终的补丁数据签名，签名嵌入镜像信息中补丁清单的旁边
+        if let Some(sign_key) = &options.sign_key {
+            let signature = self
+                .sign_patch_image(sign_key, &patch_manifest_xml, &patch_dir)
+                .with_context(|| "Sign patch image error")?;
+            patch_manifest_xml.push_str(&signature.to_xml().with_context(|| "Serialize patch signature error")?);
+            write_console(
+                ConsoleType::Info,
+                &t!(
+                    "create_patch.signed",
+                    index = base_index,
+                    signer = signature.signer,
+                    public_key = signature.public_key
+                ),
+            );
+        }
 
-        // 捕获镜像
-        let patch_image_handle = match self.wimgapi.capture(patch_handle, &patch_dir, 0) {
-            Ok(handle) => handle,
-            Err(e) => {
-                self.wimgapi.close(patch_handle).ok();
-                return Err(anyhow!("Capture patch image error ({})", e));
-            }
+        // 创建补丁文件（并行构建时按镜像顺序依次追加到补丁文件）
+        // 本次运行中已写入其他镜像的补丁文件继续追加，否则按 --on-existing 选择打开方式
+        ticket.wait();
+        let _capture_guard = self.capture_lock.lock().unwrap_or_else(|e| e.into_inner());
+        let disposition = if patch_image.exists() {
+            WIM_OPEN_ALWAYS
+        } else {
+            output_disposition(options.on_existing)
         };
+        let patch_handle = HandleGuard::new(
+            wimgapi,
+            wimgapi
+                .open(
+                    patch_image,
+                    WIM_GENERIC_WRITE,
+                    disposition,
+                    wim_compression_type(options.compress),
+                )
+                .map_err(|e| anyhow!("Create patch file error ({})", e))?,
+        );
 
-        // 创建补丁文件回调函数
-        extern "system" fn CreatePatchCallback(
-            dwMessageId: u32,
-            wParam: usize,
-            lParam: isize,
-            _pvUserData: *mut std::ffi::c_void,
-        ) -> u32 {
-            match dwMessageId {
-                // 进度回调
-                WIM_MSG_PROGRESS => {
-                    // println!("进度: {}, 剩余: {}秒", wParam, lParam / 1000);
-                }
-                // 处理回调
-                WIM_MSG_PROCESS => {
-                    if wParam != 0 {
-                        let path_ptr = wParam as *mut u16;
-                        let path_str = unsafe {
-                            let mut len = 0;
-                            while *path_ptr.offset(len) != 0 {
-                                len += 1;
-                            }
-                            String::from_utf16_lossy(std::slice::from_raw_parts(path_ptr, len as usize))
-                        };
+        // 注册消息回调函数（排除路径列表与捕获计数通过 pvUserData 传入）
+        let capture_state = CaptureState::new(&self.capture_exclude);
+        wimgapi.register_message_callback(
+            patch_handle.handle(),
+            CaptureCallback,
+            &capture_state as *const CaptureState as *mut std::ffi::c_void,
+        );
 
-                        // 过滤系统文件和目录
-                        let exclude_paths = [
-                            "$ntfs.log",
-                            "hiberfil.sys",
-                            "pagefile.sys",
-                            "swapfile.sys",
-                            "System Volume Information",
-                            "RECYCLER",
-                            "Windows\\CSC",
-                        ];
-
-                        for exclude_path in &exclude_paths {
-                            if path_str
-                                .to_ascii_lowercase()
-                                .contains(&exclude_path.to_ascii_lowercase())
-                            {
-                                let p_bool = lParam as *mut i32;
-                                if !p_bool.is_null() {
-                                    unsafe {
-                                        ptr::write(p_bool, 0);
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
-                _ => {}
-            }
-            // 返回0表示继续处理
-            0
-        }
+        // 捕获镜像
+        let captured = wimgapi.capture(patch_handle.handle(), &patch_dir, acl_flags(options.no_acl));
 
         // 注销消息回调函数
-        self.wimgapi
-            .unregister_message_callback(patch_handle, CreatePatchCallback);
+        wimgapi.unregister_message_callback(patch_handle.handle(), CaptureCallback);
+
+        // 捕获中断（出错或按下 Ctrl-C）时在清理前报告已捕获的数量，便于判断是否重试
+        let patch_image_handle = HandleGuard::new(
+            wimgapi,
+            captured.map_err(|e| {
+                write_console(
+                    ConsoleType::Warning,
+                    &t!(
+                        "create_patch.capture_interrupted",
+                        index = base_index,
+                        count = capture_state.processed()
+                    ),
+                );
+                if is_cancelled() {
+                    anyhow!(t!("cancel.cancelled"))
+                } else {
+                    anyhow!("Capture patch image error ({})", e)
+                }
+            })?,
+        );
 
         // 在</IMAGE>标签前添加基本字段信息
-        let image_info = self
-            .wimgapi
-            .get_image_info(patch_image_handle)
+        let image_info = wimgapi
+            .get_image_info(patch_image_handle.handle())
             .with_context(|| "Get patch image info error")?;
-        let updated_image_info = if let Some(pos) = image_info.rfind("</IMAGE>") {
-            let prefix = &image_info[..pos];
-            let suffix = &image_info[pos..];
-            format!(
-                "{}<NAME>{}</NAME>\
-                <DESCRIPTION>{}</DESCRIPTION>\
-                <DISPLAYNAME>{}</DISPLAYNAME>\
-                <DISPLAYDESCRIPTION>{}</DISPLAYDESCRIPTION>\
-                <FLAGS></FLAGS>{}{}",
-                prefix, name, description, name, description, patch_manifest, suffix
-            )
-        } else {
-            // 错误: 没找到</IMAGE>标签
-            return Err(anyhow!("<IMAGE> tag not found"));
-        };
+        let updated_image_info =
+            embed_patch_fields(&image_info, &options.name, &options.description, &patch_manifest_xml)
+                .ok_or_else(|| anyhow!("<IMAGE> tag not found"))?;
 
         // 将更新后的XML信息设置回映像
-        self.wimgapi
-            .set_image_info(patch_image_handle, &updated_image_info)
+        wimgapi
+            .set_image_info(patch_image_handle.handle(), &updated_image_info)
             .with_context(|| "Set image info error")?;
 
         // 关闭补丁镜像句柄
-        self.wimgapi
-            .close(patch_image_handle)
+        patch_image_handle
+            .close()
             .with_context(|| "Close patch image handle error")?;
-        self.wimgapi
-            .close(patch_handle)
-            .with_context(|| "Close patch handle error")?;
-
-        main_pb.inc(1);
-        main_pb.set_message(format!(
-            "{} ({}{})",
-            t!("create_patch.success"),
-            t!("create_patch.index"),
-            base_index
-        ));
+        patch_handle.close().with_context(|| "Close patch handle error")?;
+        summary
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .finish_image(base_index);
+
+        self.progress.step(base_index);
+        self.progress.stage(
+            base_index,
+            &format!(
+                "{} ({}{})",
+                t!("create_patch.success"),
+                t!("create_patch.index"),
+                base_index
+            ),
+        );
 
-        main_pb.finish_and_clear();
+        self.progress.task_finished(base_index);
 
-        Ok(())
+        Ok(true)
+    }
+
+    /// 将以压缩形式暂存的补丁数据逐个解压为原文件，恢复原文件的时间与属性，并删除压缩文件
+    ///
+    /// 按操作列表定位暂存的完整文件（新增的文件、完整存储的修改文件与回退用的完整文件），
+    /// 更新镜像中名称以暂存后缀结尾的文件不会被当作压缩文件
+    ///
+    /// # 参数
+    ///
+    /// - `patch_dir` - 补丁数据目录
+    /// - `operations` - 创建补丁时生成的操作列表
+    ///
+    /// # 返回值
+    ///
+    /// - `Ok((staged, expanded))` - 暂存时的压缩总字节数与解压后的总字节数
+    /// - `Err(anyhow::Error)` - 暂存文件不存在或解压失败
+    pub(crate) fn expand_staged_payloads(patch_dir: &Path, operations: &[Operation]) -> Result<(u64, u64)> {
+        let mut staged = 0u64;
+        let mut expanded = 0u64;
+        let staged_operations = operations.iter().filter(|operation| {
+            // 引用其他补丁数据的操作没有暂存文件
+            operation.payload_ref.is_none()
+                && match operation.action {
+                    Action::Add => true,
+                    Action::Modify => {
+                        operation.full_fallback
+                            || operation
+                                .storage
+                                .as_deref()
+                                .is_some_and(|storage| storage.eq_ignore_ascii_case("full"))
+                    }
+                    Action::Delete => false,
+                }
+        });
+        for operation in staged_operations {
+            let original = long_path(&patch_dir.join(&operation.path));
+            // 新增的目录直接在补丁数据目录中创建
+            if original.is_dir() {
+                continue;
+            }
+            let mut path = original.clone().into_os_string();
+            path.push(STAGED_PAYLOAD_SUFFIX);
+            let path = PathBuf::from(path);
+            let metadata = path
+                .metadata()
+                .with_context(|| format!("Staged payload not found: {}", path.display()))?;
+            staged += metadata.len();
+            ZstdDiff::file_decompress(&path, &original)
+                .with_context(|| format!("Decompress staged payload failed: {}", path.display()))?;
+            restore_metadata(&original, &metadata)
+                .with_context(|| format!("Restore metadata of staged payload failed: {}", original.display()))?;
+            expanded += original.metadata()?.len();
+            // 逐个删除压缩文件，临时目录峰值占用不超过补丁数据大小加单个压缩文件（压缩文件带有原文件的只读属性）
+            let mut permissions = metadata.permissions();
+            if permissions.readonly() {
+                #[allow(clippy::permissions_set_readonly_false)]
+                permissions.set_readonly(false);
+                fs::set_permissions(&path, permissions)?;
+            }
+            fs::remove_file(&path)?;
+        }
+        Ok((staged, expanded))
+    }
+
+    /// 将整个更新镜像释放到补丁数据目录（整镜像模式）
+    ///
+    /// 当 `options.dry_run` 为真时仅根据镜像信息估算大小，不释放任何文件
+    ///
+    /// # 参数
+    ///
+    /// - `target_handle` - 更新镜像文件句柄
+    /// - `target_index` - 更新镜像索引
+    /// - `patch_dir` - 补丁数据目录
+    /// - `target_image_info` - 更新镜像信息
+    /// - `options` - 创建补丁选项
+    ///
+    /// # 返回值
+    ///
+    /// - `Ok((operations, estimate))` - 空的操作列表以及整镜像的（文件数, 字节数）
+    /// - `Err(anyhow::Error)` - 失败
+    fn extract_whole_image(
+        &self,
+        target_handle: Handle,
+        target_index: u32,
+        patch_dir: &Path,
+        target_image_info: &ImageInfo,
+        options: &CreateOptions,
+    ) -> Result<(Vec<Operation>, BTreeMap<String, (usize, u64)>)> {
+        let wimgapi = self.wimgapi()?;
+        let mut estimate = BTreeMap::new();
+        estimate.insert(
+            "whole".to_string(),
+            (target_image_info.file_count as usize, target_image_info.total_bytes),
+        );
+        if options.dry_run {
+            return Ok((Vec::new(), estimate));
+        }
+
+        // 已挂载的镜像句柄不能直接释放，重新加载一个镜像句柄
+        let image_handle = wimgapi
+            .load_image(target_handle, target_index)
+            .with_context(|| "Load target image failed".to_string())?;
+        let result = wimgapi.apply_image(image_handle, patch_dir, acl_flags(options.no_acl));
+        wimgapi.close(image_handle).ok();
+        result.with_context(|| "Extract target image failed".to_string())?;
+
+        Ok((Vec::new(), estimate))
+    }
+
+    /// 从数据流读取补丁包并应用，适用于补丁包位于内存或通过网络获取的场景
+    ///
+    /// 补丁包会先写入临时目录中的临时文件（wimgapi 只能打开文件），无论成功与否都会在返回前删除
+    ///
+    /// # 参数
+    ///
+    /// - `base_image` - 基础镜像路径
+    /// - `base_index` - 基础镜像索引
+    /// - `patch` - 补丁包数据流
+    /// - `target_image` - 目标镜像路径
+    /// - `options` - 应用补丁选项
+    ///
+    /// # 返回值
+    ///
+    /// - `Ok(())` - 成功
+    /// - `Err(anyhow::Error)` - 写入临时文件或应用补丁失败
+    pub fn apply_patch_from_reader(
+        &self,
+        base_image: &Path,
+        base_index: Option<u32>,
+        patch: impl Read,
+        target_image: &Path,
+        options: &ApplyOptions,
+    ) -> Result<()> {
+        Self::with_spooled_patch(patch, |patch_image| {
+            self.apply_patch(base_image, base_index, patch_image, target_image, options)
+        })
     }
 
     /// 应用补丁
     ///
+    /// 指定 `options.report` 时，无论应用成功与否都写入应用结果报告
+    ///
     /// # 参数
     ///
     /// - `base_image` - 基础镜像路径
     /// - `base_index` - 基础镜像索引
     /// - `patch_image` - 补丁镜像路径
     /// - `target_image` - 目标镜像路径
-    /// - `exclude` - 排除路径列表
-    /// - `force` - 是否强制应用
+    /// - `options` - 应用补丁选项
     ///
     /// # 返回值
     ///
@@ -779,87 +2406,393 @@ impl WimPatch {
         base_index: Option<u32>,
         patch_image: &Path,
         target_image: &Path,
-        exclude: Option<&[String]>,
-        force: bool,
+        options: &ApplyOptions,
     ) -> Result<()> {
-        // 打开补丁包
-        let patch_handle = self
-            .wimgapi
-            .open(patch_image, WIM_GENERIC_READ, WIM_OPEN_EXISTING, WIM_COMPRESS_NONE)
-            .with_context(|| "Open patch image error")?;
-        self.wimgapi
-            .set_temp_path(patch_handle, get_temp_path())
-            .with_context(|| "Set temp path error")?;
+        check_include(options.include.as_deref())?;
+
+        // 记录应用过程
+        let mut report = ApplyReport::new(base_image, patch_image, target_image);
+        let Some(report_path) = &options.report else {
+            return self.apply_patch_chain(base_image, base_index, patch_image, target_image, options, &mut report);
+        };
+        report.patch_attributes = self.get_wim_info(patch_image).ok().as_ref().map(PatchAttributes::from);
+        let result = self.apply_patch_chain(base_image, base_index, patch_image, target_image, options, &mut report);
+        match &result {
+            Ok(()) => {
+                report.success = true;
+                if !options.no_export {
+                    report.target = Some(TargetStats {
+                        image_count: self.get_image_count(target_image).unwrap_or(0),
+                        size: fs::metadata(target_image).map(|m| m.len()).unwrap_or(0),
+                    });
+                }
+            }
+            Err(e) => report.error = Some(format!("{:#}", e)),
+        }
+
+        // 报告写入失败不影响应用结果
+        match report.write(report_path) {
+            Ok(()) => write_console(
+                ConsoleType::Info,
+                &format!("{}: {}", t!("apply_patch.report_written"), report_path.display()),
+            ),
+            Err(e) => write_console(
+                ConsoleType::Warning,
+                &format!("{}: {:?}", t!("apply_patch.report_write_failed"), e),
+            ),
+        }
+        result
+    }
+
+    /// 将同一补丁依次应用到多个基础镜像
+    ///
+    /// 目标镜像、应用结果报告与释放目录路径中的 `{stem}` 替换为各基础镜像的文件名（不含扩展名）。
+    /// 每个基础镜像使用临时目录下独立的子目录，结束后删除该子目录，下一个基础镜像从干净的子目录开始
+    ///
+    /// # 参数
+    ///
+    /// - `base_images` - 基础镜像路径列表
+    /// - `base_index` - 基础镜像索引
+    /// - `patch_image` - 补丁镜像路径
+    /// - `target_template` - 目标镜像路径模板（多个基础镜像时必须包含 `{stem}`）
+    /// - `options` - 应用补丁选项
+    /// - `keep_going` - 某个基础镜像失败后是否继续应用其余基础镜像
+    /// - `after_apply` - 每个基础镜像应用成功后以目标镜像路径调用（如执行应用后命令），返回错误时视为该基础镜像失败
+    ///
+    /// # 返回值
+    ///
+    /// - `Ok(Vec<BatchApplyResult>)` - 各基础镜像的结果（按输入顺序）
+    /// - `Err(anyhow::Error)` - 目标路径模板无效或目标镜像重复，未应用任何基础镜像
+    #[allow(clippy::too_many_arguments)]
+    pub fn apply_patch_batch(
+        &self,
+        base_images: &[PathBuf],
+        base_index: Option<u32>,
+        patch_image: &Path,
+        target_template: &Path,
+        options: &ApplyOptions,
+        keep_going: bool,
+        mut after_apply: impl FnMut(&Path) -> Result<()>,
+    ) -> Result<Vec<BatchApplyResult>> {
+        // 多个基础镜像必须导出到不同的目标镜像
+        let targets: Vec<PathBuf> = base_images
+            .iter()
+            .map(|base_image| expand_base_stem(target_template, base_image))
+            .collect();
+        if !options.no_export && base_images.len() > 1 {
+            if !target_template.to_string_lossy().contains(BASE_STEM_PLACEHOLDER) {
+                return Err(anyhow!(t!("apply_patch.batch_target_template")));
+            }
+            let mut seen = HashSet::new();
+            if let Some(duplicate) = targets
+                .iter()
+                .find(|target| !seen.insert(target.to_string_lossy().to_lowercase()))
+            {
+                return Err(anyhow!(t!(
+                    "apply_patch.batch_duplicate_target",
+                    target = duplicate.display()
+                )));
+            }
+        }
+
+        let mut results = Vec::new();
+        let mut failed = false;
+        for (position, (base_image, target_image)) in base_images.iter().zip(targets).enumerate() {
+            if failed && !keep_going {
+                results.push(BatchApplyResult {
+                    base: base_image.clone(),
+                    target: target_image,
+                    result: None,
+                });
+                continue;
+            }
+
+            write_console(
+                ConsoleType::Info,
+                &t!(
+                    "apply_patch.batch_base",
+                    current = position + 1,
+                    total = base_images.len(),
+                    base = base_image.display()
+                ),
+            );
+            let mut base_options = expand_base_options(options, base_image);
+            // 当前基础镜像的临时文件与挂载目录均位于独立的子目录中
+            let scratch = match create_tmp_dir(&options.scratch_path(), "batch-", "") {
+                Ok(scratch) => scratch,
+                Err(e) => {
+                    failed = true;
+                    results.push(BatchApplyResult {
+                        base: base_image.clone(),
+                        target: target_image,
+                        result: Some(Err(anyhow!(e).context(t!("create_temp_dir.failed")))),
+                    });
+                    continue;
+                }
+            };
+            base_options.scratch_dir = Some(scratch.clone());
+            // 应用后命令的错误由 after_apply 自行输出
+            let result = match self.apply_patch(base_image, base_index, patch_image, &target_image, &base_options) {
+                Ok(()) => {
+                    write_console(ConsoleType::Success, &format!("{}", t!("apply_patch.success")));
+                    after_apply(&target_image)
+                }
+                Err(e) => {
+                    write_console(ConsoleType::Error, &format!("{}: {:?}", t!("apply_patch.failed"), e));
+                    Err(e)
+                }
+            };
+            failed |= result.is_err();
+            clean_scratch(&scratch);
+            results.push(BatchApplyResult {
+                base: base_image.clone(),
+                target: target_image,
+                result: Some(result),
+            });
+        }
+        Ok(results)
+    }
+
+    /// 匹配并应用补丁链，导出目标镜像
+    ///
+    /// # 参数
+    ///
+    /// - `base_image` - 基础镜像路径
+    /// - `base_index` - 基础镜像索引
+    /// - `patch_image` - 补丁镜像路径
+    /// - `target_image` - 目标镜像路径
+    /// - `options` - 应用补丁选项
+    /// - `report` - 应用结果报告，记录补丁链匹配与文件操作结果（失败时保留失败前的进度）
+    ///
+    /// # 返回值
+    ///
+    /// - `Ok(())` - 成功
+    /// - `Err(anyhow::Error)` - 失败
+    fn apply_patch_chain(
+        &self,
+        base_image: &Path,
+        base_index: Option<u32>,
+        patch_image: &Path,
+        target_image: &Path,
+        options: &ApplyOptions,
+        report: &mut ApplyReport,
+    ) -> Result<()> {
+        let wimgapi = self.wimgapi()?;
+        self.excluded.lock().unwrap_or_else(|e| e.into_inner()).clear();
+        self.rebuilt_hives.lock().unwrap_or_else(|e| e.into_inner()).clear();
+
+        // 使用补丁前校验补丁文件哈希
+        if let Some(checksum) = &options.check_checksum {
+            self.check_checksum(patch_image, checksum)?;
+        }
+
+        // 开始应用前先确认签名者公钥可用
+        if let Some(key_path) = &options.verify_signature {
+            read_verifying_key(key_path)?;
+        }
+
+        // 目标镜像已存在时默认拒绝覆盖
+        if !options.no_export {
+            check_target_on_existing(target_image, options.on_existing)?;
+        }
+
+        // 释放目录必须为空，避免与已有文件混合
+        if let Some(extract_to) = &options.extract_to
+            && fs::read_dir(extract_to).is_ok_and(|mut entries| entries.next().is_some())
+        {
+            return Err(anyhow!(t!(
+                "apply_patch.extract_not_empty",
+                path = extract_to.display()
+            )));
+        }
+
+        // 挂载镜像需要管理员权限
+        Self::check_elevated()?;
+
+        // 直接修改基础镜像前确认其可写且未被挂载
+        if options.no_base_copy {
+            self.check_base_in_place(base_image)?;
+        }
 
-        // 读取补丁包中的补丁信息
-        let mut patch_manifest_list: Vec<(u32, PatchManifest)> = Vec::new();
-        for index in 1..self.wimgapi.get_image_count(patch_handle) + 1 {
-            let patch_image_handle = self
-                .wimgapi
-                .load_image(patch_handle, index)
-                .with_context(|| "Load image error")?;
-            let patch_image_info = self
-                .wimgapi
-                .get_image_info(patch_image_handle)
-                .with_context(|| "Get image info error")?;
-            self.wimgapi.close(patch_image_handle)?;
-            patch_manifest_list.push((
-                index,
-                self.parse_patch_info(&patch_image_info)
-                    .with_context(|| "Parse patch info error")?,
-            ));
+        // 检查临时目录剩余空间（基础镜像副本与补丁数据，不复制基础镜像时仅需补丁数据）
+        if !options.skip_space_check {
+            let base_size = match options.no_base_copy {
+                true => 0,
+                false => fs::metadata(base_image)
+                    .with_context(|| "Get base image size failed".to_string())?
+                    .len(),
+            };
+            let patch_size = fs::metadata(patch_image)
+                .with_context(|| "Get patch image size failed".to_string())?
+                .len();
+            Self::check_scratch_space(&options.scratch_path(), base_size + patch_size)?;
         }
-        self.wimgapi
-            .close(patch_handle)
-            .with_context(|| "Close patch handle error")?;
+
+        // 读取补丁包中的补丁信息与压缩类型
+        let (patch_manifest_list, patch_compression) = self
+            .get_manifests_with_compression(patch_image)
+            .with_context(|| "Parse patch info error")?;
 
         // 打开基础镜像
-        let base_handle = self
-            .wimgapi
+        let base_handle = wimgapi
             .open(base_image, WIM_GENERIC_READ, WIM_OPEN_EXISTING, WIM_COMPRESS_NONE)
             .with_context(|| "Open base image error")?;
-        self.wimgapi
-            .set_temp_path(base_handle, get_temp_path())
+        wimgapi
+            .set_temp_path(base_handle, &options.scratch_path())
             .with_context(|| "Set temp path error")?;
 
         // 读取基础镜像信息
-        let base_attributes = self
-            .wimgapi
+        let base_attributes = wimgapi
             .get_attributes(base_handle)
             .with_context(|| "Get base image attributes error")?;
         let mut base_image_info_list: Vec<ImageInfo> = Vec::new();
-        for index in 1..self.wimgapi.get_image_count(base_handle) + 1 {
-            let base_image_handle = self
-                .wimgapi
+        // 指定 --preserve-creation-time 时保留基础镜像信息，导出后恢复创建时间
+        let mut base_image_xml: HashMap<u32, String> = HashMap::new();
+        // 各镜像已记录的补丁版本，用于拒绝意外降级
+        let mut applied_versions: HashMap<u32, Version> = HashMap::new();
+        for index in 1..wimgapi.get_image_count(base_handle) + 1 {
+            let base_image_handle = wimgapi
                 .load_image(base_handle, index)
                 .with_context(|| "Load image error")?;
-            let image_info = self
-                .wimgapi
+            let image_info = wimgapi
                 .get_image_info(base_image_handle)
                 .with_context(|| "Get image info error")?;
-            self.wimgapi.close(base_image_handle)?;
+            wimgapi.close(base_image_handle)?;
             base_image_info_list.push(ImageInfo::from_xml(&image_info).with_context(|| "Parse base image info error")?);
+            if let Some(version) = applied_version(&image_info) {
+                applied_versions.insert(index, version);
+            }
+            if options.preserve_creation_time {
+                base_image_xml.insert(index, image_info);
+            }
         }
-        self.wimgapi
-            .close(base_handle)
-            .with_context(|| "Close base handle error")?;
-
-        // 匹配补丁信息
-        let match_info = self.match_patch(
-            &format!("{:?}", base_attributes.guid),
-            &base_image_info_list,
-            &patch_manifest_list,
-            force,
-        )?;
+        wimgapi.close(base_handle).with_context(|| "Close base handle error")?;
+
+        // 显示基础镜像、补丁与目标镜像的压缩类型（打开已存在文件时传入的压缩类型无效）
+        let target_compression = match options.no_export {
+            true => "-",
+            false => compression_name(wim_compression_type(options.target_compress)),
+        };
+        write_console(
+            ConsoleType::Info,
+            &t!(
+                "apply_patch.compression",
+                base = compression_name(base_attributes.compression_type),
+                patch = compression_name(patch_compression),
+                target = target_compression
+            ),
+        );
+
+        // 指定镜像名称时按名称选择基础镜像索引
+        let base_index = match &options.base_name {
+            Some(name) => Some(find_image_by_name(&base_image_info_list, name)?),
+            None => base_index,
+        };
+
+        // 匹配补丁信息（指定 --base-guid 时代替实际 GUID，镜像统计信息仍会校验）
+        let base_guid = match &options.base_guid {
+            Some(guid) => {
+                write_console(
+                    ConsoleType::Warning,
+                    &t!(
+                        "apply_patch.base_guid_override",
+                        guid = guid,
+                        actual = format_guid(&base_attributes.guid)
+                    ),
+                );
+                guid.clone()
+            }
+            None => format_guid(&base_attributes.guid),
+        };
+        report.base_guid = Some(base_guid.clone());
+        let match_info = self.match_patch(&base_guid, &base_image_info_list, &patch_manifest_list, options)?;
         if match_info.is_empty() {
             return Err(anyhow!(t!("apply_patch.not_match")));
         }
 
-        // 复制源镜像到临时目录
-        fs::copy(base_image, get_temp_path().join(base_image.file_name().unwrap()))
-            .with_context(|| "Copy base image error")?;
-        let base_image = get_temp_path().join(base_image.file_name().unwrap());
+        // 校验指定的截止版本是否存在于补丁链中
+        if let Some(up_to_version) = &options.up_to_version
+            && !match_info.iter().any(|(_, patch_chain, _)| {
+                patch_chain
+                    .iter()
+                    .any(|(_, patch)| Version::parse(&patch.patch_version).is_ok_and(|v| &v == up_to_version))
+            })
+        {
+            let mut versions: Vec<Version> = patch_manifest_list
+                .iter()
+                .filter_map(|(_, patch)| Version::parse(&patch.patch_version).ok())
+                .collect();
+            versions.sort();
+            versions.dedup();
+            let versions: Vec<String> = versions.iter().map(|v| v.to_string()).collect();
+            return Err(anyhow!(
+                "{}: {}",
+                t!("apply_patch.version_not_found", version = up_to_version),
+                versions.join(", ")
+            ));
+        }
+
+        // 检查补丁是否由更新版本的工具创建
+        if !options.skip_tool_version_check {
+            let mut checked = HashSet::new();
+            for (_, patch_chain, _) in &match_info {
+                for (_, patch) in patch_chain {
+                    if checked.insert(patch.id.as_str()) {
+                        Self::check_tool_version(&patch.tool_version, options.force)?;
+                    }
+                }
+            }
+        }
+
+        // 补丁链的最终版本低于镜像已记录的版本时拒绝应用（指定 --allow-downgrade 时仅警告）
+        for (base_image_info, patch_chain, _) in &match_info {
+            if base_index.is_some_and(|index| index != base_image_info.index) {
+                continue;
+            }
+            if let (Some(applied), Some((_, patch))) =
+                (applied_versions.get(&base_image_info.index), patch_chain.last())
+            {
+                Self::check_downgrade(
+                    base_image_info.index,
+                    applied,
+                    &patch.patch_version,
+                    options.allow_downgrade,
+                )?;
+            }
+        }
+
+        // 所有镜像补丁链的文件操作总数，用于显示跨镜像的总进度
+        let total_operations: u64 = match_info
+            .iter()
+            .filter(|(base_image_info, _, _)| base_index.is_none_or(|index| index == base_image_info.index))
+            .map(|(_, patch_chain, _)| chain_operation_count(patch_chain))
+            .sum();
+
+        // 指定 --extract-to 时只应用一个镜像则直接复制到该目录，否则按基础镜像索引创建子目录
+        let single_image = base_index.is_some() || match_info.len() == 1;
+        let extract_dir = |index: u32| {
+            options
+                .extract_to
+                .as_deref()
+                .map(|extract_to| extract_image_dir(extract_to, index, single_image))
+        };
+
+        // 复制源镜像到临时目录（指定 --no-base-copy 时直接修改基础镜像）
+        let base_image = if options.no_base_copy {
+            write_console(ConsoleType::Warning, &t!("apply_patch.no_base_copy_warning"));
+            base_image.to_path_buf()
+        } else {
+            fs::copy(base_image, options.scratch_path().join(base_image.file_name().unwrap()))
+                .with_context(|| "Copy base image error")?;
+            options.scratch_path().join(base_image.file_name().unwrap())
+        };
 
+        // 已应用的镜像（基础镜像索引、期望的目标镜像信息、补丁链中是否包含部分补丁）
+        let mut applied_images: Vec<(u32, ImageInfo, bool)> = Vec::new();
+        // 应用整镜像补丁后重新捕获的镜像（基础镜像索引 -> 镜像路径）
+        let mut whole_images: HashMap<u32, PathBuf> = HashMap::new();
+        // 任一镜像应用失败提前返回时同样结束总体进度条
+        let overall = OverallProgress::start(self.progress.as_ref(), total_operations);
         if let Some(base_index) = base_index {
             if !base_image_info_list
                 .iter()
@@ -867,7 +2800,7 @@ impl WimPatch {
             {
                 return Err(anyhow!(t!("apply_patch.base_index_not_found")));
             }
-            for (base_image_info, match_patch) in match_info {
+            for (base_image_info, match_patch, confidence) in match_info {
                 if base_index == base_image_info.index {
                     write_console(
                         ConsoleType::Info,
@@ -879,12 +2812,33 @@ impl WimPatch {
                             base_image_info.index
                         ),
                     );
-                    self.apply_patch_image(&base_image, base_index, patch_image, &match_patch, exclude, force)?;
+                    write_console(
+                        ConsoleType::Info,
+                        &t!(
+                            "apply_patch.match_confidence",
+                            index = base_index,
+                            confidence = confidence.as_str()
+                        ),
+                    );
+                    report.start_image(base_index, &match_patch, confidence);
+                    if let Some(image) = self.apply_patch_image(
+                        &base_image,
+                        base_index,
+                        patch_image,
+                        &match_patch,
+                        extract_dir(base_index).as_deref(),
+                        &mut ApplyContext { options, report },
+                    )? {
+                        whole_images.insert(base_index, image);
+                    }
+                    report.finish_image(base_index);
+                    let partial = match_patch.iter().any(|(_, patch)| patch.partial);
+                    applied_images.push((base_index, base_image_info, partial));
                 }
             }
         } else {
             // 自动匹配补丁
-            for (base_image_info, match_patch) in match_info {
+            for (base_image_info, match_patch, confidence) in match_info {
                 write_console(
                     ConsoleType::Info,
                     &format!(
@@ -895,58 +2849,622 @@ impl WimPatch {
                         base_image_info.index
                     ),
                 );
-                self.apply_patch_image(
+                write_console(
+                    ConsoleType::Info,
+                    &t!(
+                        "apply_patch.match_confidence",
+                        index = base_image_info.index,
+                        confidence = confidence.as_str()
+                    ),
+                );
+                report.start_image(base_image_info.index, &match_patch, confidence);
+                if let Some(image) = self.apply_patch_image(
                     &base_image,
                     base_image_info.index,
                     patch_image,
                     &match_patch,
-                    exclude,
-                    force,
-                )?;
+                    extract_dir(base_image_info.index).as_deref(),
+                    &mut ApplyContext { options, report },
+                )? {
+                    whole_images.insert(base_image_info.index, image);
+                }
+                report.finish_image(base_image_info.index);
+                let partial = match_patch.iter().any(|(_, patch)| patch.partial);
+                applied_images.push((base_image_info.index, base_image_info, partial));
+            }
+        }
+        drop(overall);
+
+        // 导出前暂停以供检查
+        if options.pause_before_export {
+            if is_tty() {
+                let indices: Vec<u32> = applied_images.iter().map(|(index, _, _)| *index).collect();
+                self.pause_for_inspection(&base_image, &indices, &options.scratch_path())?;
+            } else {
+                write_console(ConsoleType::Warning, &t!("apply_patch.pause_non_tty"));
+            }
+        }
+
+        // 导出到临时文件，全部镜像导出并校验成功后才替换目标镜像（指定 --no-export 时跳过）
+        let result = if options.no_export {
+            Ok(())
+        } else {
+            Self::with_atomic_output(target_image, |output| {
+                self.export_target_image(&base_image, output, &whole_images, &base_image_xml, options)?;
+                self.verify_target_image(output, &applied_images, options.force)
+            })
+        };
+
+        self.progress.clear();
+        for image in whole_images.values() {
+            fs::remove_file(image).ok();
+        }
+        result
+    }
+
+    /// 检查基础镜像能否直接修改：文件不能为只读，且其镜像不能处于挂载状态
+    ///
+    /// # 参数
+    ///
+    /// - `base_image` - 基础镜像路径
+    ///
+    /// # 返回值
+    ///
+    /// - `Ok(())` - 可以直接修改
+    /// - `Err(anyhow::Error)` - 基础镜像只读、已被挂载或无法获取挂载信息
+    fn check_base_in_place(&self, base_image: &Path) -> Result<()> {
+        if fs::metadata(base_image)
+            .with_context(|| "Get base image metadata failed".to_string())?
+            .permissions()
+            .readonly()
+        {
+            return Err(anyhow!(t!("apply_patch.base_readonly", path = base_image.display())));
+        }
+        let mounted: Vec<String> = self
+            .wimgapi()?
+            .get_mounted_image()
+            .with_context(|| "Get mounted image error")?
+            .into_iter()
+            .map(|mount_info| mount_info.wim_path)
+            .collect();
+        if is_image_mounted(base_image, &mounted) {
+            return Err(anyhow!(t!("apply_patch.base_mounted", path = base_image.display())));
+        }
+        Ok(())
+    }
+
+    /// 将应用补丁后的基础镜像导出到目标镜像
+    ///
+    /// # 参数
+    ///
+    /// - `base_image` - 已应用补丁的基础镜像副本路径
+    /// - `target_image` - 目标镜像路径
+    /// - `whole_images` - 应用整镜像补丁后重新捕获的镜像（基础镜像索引 -> 镜像路径）
+    /// - `base_image_xml` - 需要恢复创建时间的基础镜像信息（基础镜像索引 -> 镜像信息 XML）
+    /// - `options` - 应用补丁选项
+    ///
+    /// # 返回值
+    ///
+    /// - `Ok(())` - 成功
+    /// - `Err(anyhow::Error)` - 失败
+    pub(crate) fn export_target_image(
+        &self,
+        base_image: &Path,
+        target_image: &Path,
+        whole_images: &HashMap<u32, PathBuf>,
+        base_image_xml: &HashMap<u32, String>,
+        options: &ApplyOptions,
+    ) -> Result<()> {
+        let wimgapi = self.wimgapi()?;
+        // 打开基础镜像
+        let base_handle = HandleGuard::new(
+            wimgapi,
+            wimgapi
+                .open(base_image, WIM_GENERIC_READ, WIM_OPEN_EXISTING, WIM_COMPRESS_NONE)
+                .with_context(|| "Open base image error")?,
+        );
+        let scratch = options.scratch_path();
+        wimgapi
+            .set_temp_path(base_handle.handle(), &scratch)
+            .with_context(|| "Set temp path error")?;
+
+        // 创建目标镜像（如果文件存在则覆盖，指定 --verify 时写入完整性信息，恢复创建时间时需要读取镜像信息）
+        let target_handle = HandleGuard::new(
+            wimgapi,
+            wimgapi.open_with_flags(
+                target_image,
+                if options.preserve_creation_time {
+                    WIM_GENERIC_READ | WIM_GENERIC_WRITE
+                } else {
+                    WIM_GENERIC_WRITE
+                },
+                WIM_CREATE_ALWAYS,
+                if options.verify { WIM_FLAG_VERIFY } else { 0 },
+                wim_compression_type(options.target_compress),
+            )?,
+        );
+        wimgapi
+            .set_temp_path(target_handle.handle(), &scratch)
+            .with_context(|| "Set temp path error")?;
+
+        // 导出更新镜像
+        for index in 1..=wimgapi.get_image_count(base_handle.handle()) {
+            // 整镜像补丁从重新捕获的镜像导出，保持镜像索引顺序不变
+            if let Some(image) = whole_images.get(&index) {
+                self.export_whole_image(image, target_handle.handle(), &scratch)?;
+            } else {
+                let base_image_handle = HandleGuard::new(
+                    wimgapi,
+                    wimgapi
+                        .load_image(base_handle.handle(), index)
+                        .with_context(|| "Load image error")?,
+                );
+                wimgapi
+                    .export_image(base_image_handle.handle(), target_handle.handle(), 0)
+                    .with_context(|| "Export image error")?;
+                base_image_handle.close().with_context(|| "Close image handle error")?;
+            }
+
+            // 恢复基础镜像的创建时间（LASTMODIFICATIONTIME 仍为导出时间）
+            if let Some(base_xml) = base_image_xml.get(&index) {
+                let target_image_handle = HandleGuard::new(
+                    wimgapi,
+                    wimgapi
+                        .load_image(target_handle.handle(), index)
+                        .with_context(|| "Load target image error")?,
+                );
+                let target_xml = wimgapi
+                    .get_image_info(target_image_handle.handle())
+                    .with_context(|| "Get target image info error")?;
+                wimgapi
+                    .set_image_info(target_image_handle.handle(), &copy_creation_time(base_xml, &target_xml))
+                    .with_context(|| "Set target image info error")?;
+            }
+        }
+        base_handle.close().with_context(|| "Close base handle error")?;
+        target_handle.close().with_context(|| "Close target handle error")?;
+        Ok(())
+    }
+
+    /// 将重新捕获的整镜像导出到目标镜像
+    ///
+    /// # 参数
+    ///
+    /// - `image` - 重新捕获的镜像路径
+    /// - `target_handle` - 目标镜像文件句柄
+    ///
+    /// # 返回值
+    ///
+    /// - `Ok(())` - 成功
+    /// - `Err(anyhow::Error)` - 失败
+    fn export_whole_image(&self, image: &Path, target_handle: Handle, scratch: &Path) -> Result<()> {
+        let wimgapi = self.wimgapi()?;
+        let handle = wimgapi
+            .open(image, WIM_GENERIC_READ, WIM_OPEN_EXISTING, WIM_COMPRESS_NONE)
+            .with_context(|| "Open whole image error")?;
+        if let Err(e) = wimgapi.set_temp_path(handle, scratch) {
+            wimgapi.close(handle).ok();
+            return Err(anyhow!("Set temp path error: {}", e));
+        }
+        let image_handle = match wimgapi.load_image(handle, 1) {
+            Ok(image_handle) => image_handle,
+            Err(e) => {
+                wimgapi.close(handle).ok();
+                return Err(anyhow!("Load whole image error: {}", e));
+            }
+        };
+        let result = wimgapi.export_image(image_handle, target_handle, 0);
+        wimgapi.close(image_handle).ok();
+        wimgapi.close(handle).ok();
+        result.with_context(|| "Export whole image error")?;
+        Ok(())
+    }
+
+    /// 检查当前进程是否以管理员权限运行，挂载与卸载镜像需要管理员权限
+    ///
+    /// # 返回值
+    ///
+    /// - `Ok(())` - 已提升权限，或无法查询进程令牌（交由后续操作报告错误）
+    /// - `Err(anyhow::Error)` - 未以管理员权限运行
+    pub(crate) fn check_elevated() -> Result<()> {
+        if !is_elevated().unwrap_or(true) {
+            return Err(anyhow!("{}", t!("privilege.not_elevated")));
+        }
+        Ok(())
+    }
+
+    /// 检查临时目录所在卷是否有足够的剩余空间
+    ///
+    /// # 参数
+    ///
+    /// - `temp_path` - 临时目录路径
+    /// - `required` - 预计需要的字节数
+    ///
+    /// # 返回值
+    ///
+    /// - `Ok(())` - 空间足够
+    /// - `Err(anyhow::Error)` - 空间不足或获取剩余空间失败
+    fn check_scratch_space(temp_path: &Path, required: u64) -> Result<()> {
+        let available = get_free_space(temp_path)?;
+        if available < required {
+            return Err(anyhow!(
+                "{}",
+                t!(
+                    "scratch.insufficient_space",
+                    path = temp_path.display(),
+                    required = format_bytes(required),
+                    available = format_bytes(available)
+                )
+            ));
+        }
+        Ok(())
+    }
+
+    /// 检查补丁链的最终版本是否低于镜像已记录的补丁版本
+    ///
+    /// # 参数
+    ///
+    /// - `index` - 基础镜像索引
+    /// - `applied` - 镜像信息中记录的已应用补丁版本
+    /// - `patch_version` - 补丁链中最后一个补丁的版本
+    /// - `allow_downgrade` - 是否允许降级（低于已记录版本时仅警告）
+    ///
+    /// # 返回值
+    ///
+    /// - `Ok(())` - 不是降级、版本无法解析或允许降级
+    /// - `Err(anyhow::Error)` - 补丁版本低于已记录版本且未允许降级
+    pub(crate) fn check_downgrade(
+        index: u32,
+        applied: &Version,
+        patch_version: &str,
+        allow_downgrade: bool,
+    ) -> Result<()> {
+        let Ok(patch_version) = Version::parse(patch_version) else {
+            return Ok(());
+        };
+        if patch_version >= *applied {
+            return Ok(());
+        }
+
+        let message = t!(
+            "apply_patch.downgrade",
+            index = index,
+            version = patch_version,
+            applied = applied
+        );
+        if !allow_downgrade {
+            return Err(anyhow!("{}", message));
+        }
+        write_console(ConsoleType::Warning, &message);
+        Ok(())
+    }
+
+    /// 检查补丁的工具版本是否高于当前程序版本
+    ///
+    /// # 参数
+    ///
+    /// - `tool_version` - 创建补丁的工具版本
+    /// - `force` - 是否强制应用（主版本更高时仅警告）
+    ///
+    /// # 返回值
+    ///
+    /// - `Ok(())` - 版本兼容，或版本较新但仅需警告
+    /// - `Err(anyhow::Error)` - 补丁来自更高的主版本且未强制应用
+    pub(crate) fn check_tool_version(tool_version: &str, force: bool) -> Result<()> {
+        // 无法解析的版本号（如旧版补丁）不做检查
+        let (Ok(patch_version), Ok(current_version)) =
+            (Version::parse(tool_version), Version::parse(env!("CARGO_PKG_VERSION")))
+        else {
+            return Ok(());
+        };
+
+        if patch_version.major > current_version.major {
+            let message = t!(
+                "apply_patch.tool_version_major",
+                version = patch_version,
+                current = current_version
+            );
+            if !force {
+                return Err(anyhow!("{}", message));
+            }
+            write_console(ConsoleType::Warning, &message);
+        } else if patch_version > current_version {
+            write_console(
+                ConsoleType::Warning,
+                &t!(
+                    "apply_patch.tool_version_newer",
+                    version = patch_version,
+                    current = current_version
+                ),
+            );
+        }
+        Ok(())
+    }
+
+    /// 按镜像名称查找镜像文件中的镜像索引
+    ///
+    /// # 参数
+    ///
+    /// - `image` - 镜像文件路径
+    /// - `name` - 镜像名称或显示名称（不区分大小写）
+    ///
+    /// # 返回值
+    ///
+    /// - `Ok(u32)` - 唯一匹配的镜像索引
+    /// - `Err(anyhow::Error)` - 读取镜像信息失败，或没有镜像、有多个镜像匹配该名称
+    fn resolve_image_name(&self, image: &Path, name: &str) -> Result<u32> {
+        let wimgapi = self.wimgapi()?;
+        let handle = HandleGuard::new(
+            wimgapi,
+            wimgapi
+                .open(image, WIM_GENERIC_READ, WIM_OPEN_EXISTING, WIM_COMPRESS_NONE)
+                .with_context(|| format!("Open image error: {}", image.display()))?,
+        );
+        wimgapi
+            .set_temp_path(handle.handle(), &get_temp_path())
+            .with_context(|| "Set temp path error")?;
+
+        let mut images = Vec::new();
+        for index in 1..wimgapi.get_image_count(handle.handle()) + 1 {
+            let image_handle = HandleGuard::new(
+                wimgapi,
+                wimgapi
+                    .load_image(handle.handle(), index)
+                    .with_context(|| "Load image error")?,
+            );
+            let xml = wimgapi
+                .get_image_info(image_handle.handle())
+                .with_context(|| "Read image info error")?;
+            images.push(ImageInfo::from_xml(&xml).with_context(|| "Read image info error")?);
+        }
+        handle.close().with_context(|| "Close image handle error")?;
+        find_image_by_name(&images, name)
+    }
+
+    /// 校验目标镜像的统计信息是否与补丁清单中记录的目标镜像信息一致
+    ///
+    /// 镜像中有文件操作被 `--exclude` 排除、未命中 `--include` 或被 `--no-delete` 跳过时，统计信息不一致仅输出由此引起的差异警告；
+    /// 补丁链中包含部分补丁（期望的统计信息为估算值）时同样仅输出警告
+    ///
+    /// # 参数
+    ///
+    /// - `target_image` - 目标镜像路径
+    /// - `expected` - 镜像索引、期望的目标镜像信息以及补丁链中是否包含部分补丁
+    /// - `force` - 是否强制模式（不一致时仅警告）
+    ///
+    /// # 返回值
+    ///
+    /// - `Ok(())` - 校验通过或强制模式
+    /// - `Err(anyhow::Error)` - 校验失败
+    fn verify_target_image(&self, target_image: &Path, expected: &[(u32, ImageInfo, bool)], force: bool) -> Result<()> {
+        let wimgapi = self.wimgapi()?;
+        let target_handle = wimgapi
+            .open(target_image, WIM_GENERIC_READ, WIM_OPEN_EXISTING, WIM_COMPRESS_NONE)
+            .with_context(|| "Open target image error")?;
+
+        let mut mismatched = Vec::new();
+        for (index, expected_info, partial) in expected {
+            let image_handle = match wimgapi.load_image(target_handle, *index) {
+                Ok(handle) => handle,
+                Err(e) => {
+                    wimgapi.close(target_handle).ok();
+                    return Err(anyhow!("Load target image error: {}", e));
+                }
+            };
+            let image_info = wimgapi.get_image_info(image_handle);
+            wimgapi.close(image_handle).ok();
+            let actual_info = match image_info
+                .map_err(anyhow::Error::from)
+                .and_then(|xml| ImageInfo::from_xml(&xml).map_err(anyhow::Error::from))
+            {
+                Ok(info) => info,
+                Err(e) => {
+                    wimgapi.close(target_handle).ok();
+                    return Err(anyhow!("Read target image info error: {}", e));
+                }
+            };
+
+            // 比较目录数、文件数和总字节数
+            let divergence = image_stat_divergence(expected_info, &actual_info);
+            let excluded = self
+                .excluded
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .get(index)
+                .copied()
+                .unwrap_or(0);
+            // 重建的配置单元数量与文件大小差异之和
+            let (rebuilt_hives, rebuilt_bytes) = self
+                .rebuilt_hives
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .get(index)
+                .map_or((0, 0), |hives| (hives.len(), hives.values().sum::<i64>()));
+            if let Some((dirs, files, bytes)) = divergence
+                && excluded > 0
+            {
+                // 排除的操作导致目标镜像与补丁清单不一致，后续补丁可能无法继续应用
+                write_console(
+                    ConsoleType::Warning,
+                    &t!(
+                        "apply_patch.exclude_divergence",
+                        index = index,
+                        count = excluded,
+                        dirs = format!("{:+}", dirs),
+                        files = format!("{:+}", files),
+                        bytes = format!("{:+}", bytes)
+                    ),
+                );
+            } else if let Some((0, 0, bytes)) = divergence
+                && rebuilt_hives > 0
+                && bytes == rebuilt_bytes
+            {
+                // 重建的注册表配置单元与更新镜像中的文件键值相同，差异恰好为这些文件的大小差异
+                write_console(
+                    ConsoleType::Warning,
+                    &t!(
+                        "apply_patch.hive_size_divergence",
+                        index = index,
+                        count = rebuilt_hives,
+                        bytes = format!("{:+}", bytes)
+                    ),
+                );
+            } else if let Some((dirs, files, bytes)) = divergence
+                && *partial
+            {
+                // 部分补丁记录的目标镜像统计信息为估算值
+                write_console(
+                    ConsoleType::Warning,
+                    &t!(
+                        "apply_patch.partial_divergence",
+                        index = index,
+                        dirs = format!("{:+}", dirs),
+                        files = format!("{:+}", files),
+                        bytes = format!("{:+}", bytes)
+                    ),
+                );
+            } else if divergence.is_none() {
+                write_console(
+                    ConsoleType::Success,
+                    &format!(
+                        "{} ({}{})",
+                        t!("apply_patch.verify_passed"),
+                        t!("apply_patch.index"),
+                        index
+                    ),
+                );
+            } else {
+                write_console(
+                    if force {
+                        ConsoleType::Warning
+                    } else {
+                        ConsoleType::Error
+                    },
+                    &format!(
+                        "{} ({}{}): dirs {}/{}, files {}/{}, bytes {}/{}",
+                        t!("apply_patch.verify_failed"),
+                        t!("apply_patch.index"),
+                        index,
+                        actual_info.dir_count,
+                        expected_info.dir_count,
+                        actual_info.file_count,
+                        expected_info.file_count,
+                        actual_info.total_bytes,
+                        expected_info.total_bytes
+                    ),
+                );
+                mismatched.push(index.to_string());
+            }
+        }
+        wimgapi
+            .close(target_handle)
+            .with_context(|| "Close target handle error")?;
+
+        if !mismatched.is_empty() && !force {
+            return Err(anyhow!(
+                "{}: {}",
+                t!("apply_patch.verify_failed"),
+                mismatched.join(", ")
+            ));
+        }
+        Ok(())
+    }
+
+    /// 以只读方式挂载已应用补丁的镜像卷，等待用户检查后卸载
+    ///
+    /// # 参数
+    ///
+    /// - `image_path` - 已应用补丁的镜像路径
+    /// - `indices` - 需要挂载的镜像索引列表
+    /// - `scratch` - 挂载目录所在的目录
+    ///
+    /// # 返回值
+    ///
+    /// - `Ok(())` - 成功
+    /// - `Err(anyhow::Error)` - 失败
+    fn pause_for_inspection(&self, image_path: &Path, indices: &[u32], scratch: &Path) -> Result<()> {
+        let wimgapi = self.wimgapi()?;
+        self.progress.clear();
+
+        // 挂载镜像卷（不指定临时目录即为只读挂载）
+        let mut mounts: Vec<(PathBuf, u32)> = Vec::new();
+        let mut result = Ok(());
+        for index in indices {
+            let mount_path = match create_tmp_dir(scratch, "inspect-", "") {
+                Ok(mount_path) => mount_path,
+                Err(e) => {
+                    result = Err(anyhow!("Create inspect mount dir failed: {}", e));
+                    break;
+                }
+            };
+            if let Err(e) = wimgapi.mount_image(&mount_path, image_path, *index, None) {
+                result = Err(anyhow!("{}: {}", t!("apply_patch.mount_inspect_failed"), e));
+                break;
             }
+            register_mount(&mount_path, image_path, *index);
+            write_console(
+                ConsoleType::Info,
+                &format!(
+                    "{}({}{}): {}",
+                    t!("apply_patch.inspect_mounted"),
+                    t!("apply_patch.index"),
+                    index,
+                    mount_path.display()
+                ),
+            );
+            mounts.push((mount_path, *index));
         }
 
-        // 打开基础镜像
-        let base_handle = self
-            .wimgapi
-            .open(&base_image, WIM_GENERIC_READ, WIM_OPEN_EXISTING, WIM_COMPRESS_NONE)
-            .with_context(|| "Open base image error")?;
-        self.wimgapi
-            .set_temp_path(base_handle, get_temp_path())
-            .with_context(|| "Set temp path error")?;
-
-        // 创建目标镜像（如果文件存在则覆盖）
-        let target_handle = self
-            .wimgapi
-            .open(target_image, WIM_GENERIC_WRITE, WIM_CREATE_ALWAYS, WIM_COMPRESS_LZX)?;
-        self.wimgapi
-            .set_temp_path(target_handle, get_temp_path())
-            .with_context(|| "Set temp path error")?;
+        // 等待用户确认
+        if result.is_ok() {
+            println!("{}", t!("apply_patch.pause_prompt"));
+            let mut input = String::new();
+            std::io::stdin().read_line(&mut input).ok();
+        }
 
-        // 导出更新镜像
-        for index in 1..=self.wimgapi.get_image_count(base_handle) {
-            let base_image_handle = self
-                .wimgapi
-                .load_image(base_handle, index)
-                .with_context(|| "Load image error")?;
-            self.wimgapi
-                .export_image(base_image_handle, target_handle, 0)
-                .with_context(|| "Export image error")?;
-            self.wimgapi
-                .close(base_image_handle)
-                .with_context(|| "Close image handle error")?;
+        // 卸载镜像卷
+        for (mount_path, index) in mounts {
+            if let Err(e) = wimgapi.unmount_image(&mount_path, image_path, index, false) {
+                write_console(
+                    ConsoleType::Warning,
+                    &format!("{}: {}", t!("apply_patch.unmount_inspect_failed"), e),
+                );
+            }
+            unregister_mount(&mount_path);
+            fs::remove_dir_all(&mount_path).ok();
         }
-        self.wimgapi
-            .close(base_handle)
-            .with_context(|| "Close base handle error")?;
-        self.wimgapi
-            .close(target_handle)
-            .with_context(|| "Close target handle error")?;
 
-        self.multi_pb
-            .clear()
-            .with_context(|| "Clear multi pb failed".to_string())?;
+        result
+    }
 
+    /// 将应用补丁后的镜像内容复制到释放目录
+    ///
+    /// 普通复制不保留 ACL，部分属性（如压缩、加密）也可能丢失
+    ///
+    /// # 参数
+    ///
+    /// - `source` - 基础镜像挂载目录或整镜像补丁的释放目录
+    /// - `extract_dir` - 释放目录
+    /// - `base_index` - 基础镜像索引
+    ///
+    /// # 返回值
+    ///
+    /// - `Ok(())` - 成功
+    /// - `Err(anyhow::Error)` - 失败
+    fn extract_applied(&self, source: &Path, extract_dir: &Path, base_index: u32) -> Result<()> {
+        self.progress.stage(base_index, &t!("apply_patch.extracting"));
+        copy_dir(long_path(source), long_path(extract_dir))
+            .with_context(|| format!("Extract applied image failed: {}", extract_dir.display()))?;
+        write_console(
+            ConsoleType::Info,
+            &format!(
+                "{}({}{}): {}",
+                t!("apply_patch.extracted"),
+                t!("apply_patch.index"),
+                base_index,
+                extract_dir.display()
+            ),
+        );
         Ok(())
     }
 
@@ -958,12 +3476,13 @@ impl WimPatch {
     /// - `base_index` - 基础镜像索引
     /// - `patch_image` - 补丁镜像路径
     /// - `patch_manifest_list` - 补丁清单列表
-    /// - `exclude` - 排除路径列表
-    /// - `force` - 是否强制应用
+    /// - `extract_dir` - 卸载前复制应用结果的目录（`--extract-to`）
+    /// - `context` - 应用补丁选项与应用结果报告
     ///
     /// # 返回值
     ///
-    /// - `Ok(())` - 成功
+    /// - `Ok(None)` - 成功，补丁已应用到基础镜像
+    /// - `Ok(Some(PathBuf))` - 成功，补丁链中包含整镜像补丁，返回重新捕获的镜像路径
     /// - `Err(anyhow::Error)` - 失败
     fn apply_patch_image(
         &self,
@@ -971,393 +3490,1228 @@ impl WimPatch {
         base_index: u32,
         patch_image: &Path,
         patch_manifest_list: &Vec<(u32, PatchManifest)>,
-        exclude: Option<&[String]>,
-        force: bool,
-    ) -> Result<()> {
+        extract_dir: Option<&Path>,
+        context: &mut ApplyContext,
+    ) -> Result<Option<PathBuf>> {
+        let wimgapi = self.wimgapi()?;
+        let options = context.options;
+        let scratch = options.scratch_path();
+        // 整镜像补丁无需挂载基础镜像，直接释放补丁镜像后重新捕获
+        if let Some(position) = patch_manifest_list.iter().rposition(|(_, patch)| patch.whole_image) {
+            // 整镜像补丁不支持签名，按未签名的补丁处理
+            if options.verify_signature.is_some() {
+                let (index, _) = &patch_manifest_list[position];
+                if options.require_signature {
+                    return Err(anyhow!("{}", t!("apply_patch.signature_missing", index = index)));
+                }
+                write_console(
+                    ConsoleType::Warning,
+                    &t!("apply_patch.signature_unsigned", index = index),
+                );
+            }
+            return self
+                .apply_whole_image_patch(
+                    base_index,
+                    patch_image,
+                    &patch_manifest_list[position..],
+                    extract_dir,
+                    context,
+                )
+                .map(Some);
+        }
+
         // 计算总步骤数：基础镜像挂载 + 每个补丁镜像的4个步骤 + 基础镜像卸载
         let total_steps = 1 + (patch_manifest_list.len() * 4) + 1;
 
-        // 创建进度条
-        let main_pb = self.multi_pb.add(ProgressBar::new(total_steps as u64));
-        main_pb.set_style(
-            ProgressStyle::with_template("{prefix:.bold.dim} [{elapsed_precise}] [{bar}] {pos}/{len}: {msg}")
-                .unwrap()
-                .progress_chars("=> "),
-        );
-        main_pb.enable_steady_tick(Duration::from_millis(80));
+        // 开始处理镜像
+        self.progress.task_started(base_index, total_steps);
 
         // 打开基础镜像
-        let base_handle = self.wimgapi.open(
-            base_image,
-            WIM_GENERIC_READ | WIM_GENERIC_WRITE | WIM_GENERIC_MOUNT,
-            WIM_OPEN_EXISTING,
-            WIM_COMPRESS_NONE,
-        )?;
-        self.wimgapi
-            .set_temp_path(base_handle, get_temp_path())
+        let base_handle = HandleGuard::new(
+            wimgapi,
+            wimgapi.open(
+                base_image,
+                WIM_GENERIC_READ | WIM_GENERIC_WRITE | WIM_GENERIC_MOUNT,
+                WIM_OPEN_EXISTING,
+                WIM_COMPRESS_NONE,
+            )?,
+        );
+        wimgapi
+            .set_temp_path(base_handle.handle(), &scratch)
             .with_context(|| "Set temp path error")?;
-        let base_image_handle = self
-            .wimgapi
-            .load_image(base_handle, base_index)
-            .with_context(|| "Load base image error")?;
+        let base_image_handle = HandleGuard::new(
+            wimgapi,
+            wimgapi
+                .load_image(base_handle.handle(), base_index)
+                .with_context(|| "Load base image error")?,
+        );
 
         // 获取基础镜像的卷信息
-        let mut base_image_volumes = self
-            .wimgapi
-            .get_image_info(base_image_handle)
+        let mut base_image_volumes = wimgapi
+            .get_image_info(base_image_handle.handle())
             .with_context(|| "Get image info error")?;
 
         // 打开补丁包
-        let patch_handle = self
-            .wimgapi
-            .open(
-                patch_image,
-                WIM_GENERIC_READ | WIM_GENERIC_MOUNT,
-                WIM_OPEN_EXISTING,
-                WIM_COMPRESS_NONE,
-            )
-            .with_context(|| "Open patch image error")?;
-        self.wimgapi
-            .set_temp_path(patch_handle, get_temp_path())
+        let patch_handle = HandleGuard::new(
+            wimgapi,
+            wimgapi
+                .open(
+                    patch_image,
+                    WIM_GENERIC_READ | WIM_GENERIC_MOUNT,
+                    WIM_OPEN_EXISTING,
+                    WIM_COMPRESS_NONE,
+                )
+                .with_context(|| "Open patch image error")?,
+        );
+        wimgapi
+            .set_temp_path(patch_handle.handle(), &scratch)
             .with_context(|| "Set temp path error")?;
 
-        // 挂载基础镜像
-        main_pb.set_message(t!("create_patch.mount_base"));
-        if !is_tty() {
-            write_console(ConsoleType::Info, &t!("create_patch.mount_base"));
-        }
-        let base_mount = get_temp_path().join(get_tmp_name("base-", "", 6));
-        if base_mount.exists() {
-            fs::remove_dir_all(&base_mount).with_context(|| "Remove base image mount path error")?;
-        }
-        fs::create_dir_all(&base_mount).with_context(|| "Create base image mount path error")?;
-        if let Err(e) = self.wimgapi.mount_image_handle(base_image_handle, &base_mount, 0) {
-            self.wimgapi.close(base_image_handle)?;
-            self.wimgapi.close(base_handle)?;
-            return Err(anyhow!("Mount base image error: {:?}", e));
-        }
-        main_pb.inc(1);
+        // 挂载基础镜像（出错时未提交的更改随卸载丢弃）
+        check_cancelled()?;
+        self.progress.stage(base_index, &t!("create_patch.mount_base"));
+        let base_mount = create_tmp_dir(&scratch, "base-", "").with_context(|| "Create base image mount path error")?;
+        let base_mounted = MountGuard::mount(base_image_handle, &base_mount, 0)
+            .map_err(|e| anyhow!("Mount base image error: {}", e))?;
+        self.progress.step(base_index);
 
         for (index, patch_manifest) in patch_manifest_list {
-            main_pb.set_message(t!("apply_patch.mount_patch"));
-            if !is_tty() {
-                write_console(ConsoleType::Info, &t!("apply_patch.mount_patch"));
-            }
+            check_cancelled()?;
+            self.progress.stage(base_index, &t!("apply_patch.mount_patch"));
 
             // 加载补丁镜像
-            let patch_image_handle = self
-                .wimgapi
-                .load_image(patch_handle, *index)
-                .with_context(|| "Load image error")?;
+            let patch_image_handle = HandleGuard::new(
+                wimgapi,
+                wimgapi
+                    .load_image(patch_handle.handle(), *index)
+                    .with_context(|| "Load image error")?,
+            );
+
+            // 读取补丁镜像信息，用于校验其中嵌入的签名
+            let patch_image_info = match options.verify_signature {
+                Some(_) => wimgapi
+                    .get_image_info(patch_image_handle.handle())
+                    .with_context(|| "Get patch image info error")?,
+                None => String::new(),
+            };
 
             // 创建补丁包挂载目录
-            let patch_mount = get_temp_path().join(get_tmp_name("patch-", "", 6));
-            if patch_mount.exists() {
-                fs::remove_dir_all(&patch_mount).with_context(|| "Remove patch mount error")?;
-            }
-            fs::create_dir_all(&patch_mount).with_context(|| "Create patch mount error")?;
+            let patch_mount = create_tmp_dir(&scratch, "patch-", "").with_context(|| "Create patch mount error")?;
 
             // 挂载补丁镜像
-            if let Err(e) = self
-                .wimgapi
-                .mount_image_handle(patch_image_handle, &patch_mount, WIM_FLAG_MOUNT_READONLY)
-            {
-                self.wimgapi.close(patch_image_handle)?;
-                self.wimgapi.close(patch_handle)?;
-                self.wimgapi.unmount_image_handle(base_image_handle).ok();
-                self.wimgapi.close(base_image_handle).ok();
-                self.wimgapi.close(base_handle).ok();
-                return Err(anyhow!(format!("{}: {}", t!("apply_patch.mount_patch_failed"), e)));
-            }
-            main_pb.inc(1);
-
-            // 合并镜像差异
-            main_pb.set_message(t!("apply_patch.merge_diff"));
-            if !is_tty() {
-                write_console(ConsoleType::Info, &t!("apply_patch.merge_diff"));
-            }
-
-            // 应用文件操作
-            if let Err(e) = self.apply_operations(&base_mount, &patch_mount, &patch_manifest.operations, exclude, force)
-            {
-                self.wimgapi.unmount_image_handle(base_image_handle).ok();
-                self.wimgapi.close(base_image_handle).ok();
-                self.wimgapi.close(base_handle).ok();
-                self.wimgapi.unmount_image_handle(patch_image_handle).ok();
-                self.wimgapi.close(patch_image_handle).ok();
-                self.wimgapi.close(patch_handle).ok();
-
-                return Err(anyhow!("Apply operations error: {:?}", e));
-            }
-            main_pb.inc(1);
-
-            // 提交更改
-            main_pb.set_message(t!("apply_patch.commit_changes"));
-            if !is_tty() {
-                write_console(ConsoleType::Info, &t!("apply_patch.commit_changes"));
-            }
-            if let Err(e) = self.wimgapi.commit(base_image_handle, 0) {
-                self.wimgapi.unmount_image_handle(base_image_handle)?;
-                self.wimgapi.close(base_image_handle)?;
-                self.wimgapi.close(base_handle)?;
-                self.wimgapi.unmount_image_handle(patch_image_handle)?;
-                self.wimgapi.close(patch_image_handle)?;
-                self.wimgapi.close(patch_handle)?;
+            let patch_mounted = MountGuard::mount(patch_image_handle, &patch_mount, WIM_FLAG_MOUNT_READONLY)
+                .map_err(|e| anyhow!("{}: {}", t!("apply_patch.mount_patch_failed"), e))?;
+            self.progress.step(base_index);
 
-                return Err(anyhow!("Commit image error: {:?}", e));
-            }
+            // 应用前校验签名（失败时基础镜像的更改随卸载丢弃）
+            self.verify_patch_signature(*index, &patch_image_info, patch_manifest, &patch_mount, options)?;
 
-            // 更新镜像信息（需在提交更改后）
+            // 合并镜像差异
+            self.progress.stage(base_index, &t!("apply_patch.merge_diff"));
+
+            // 应用文件操作（失败前的操作结果同样写入报告）
+            let counts = Cell::default();
+            let run = OperationRun {
+                base_mount: &base_mount,
+                patch_mount: &patch_mount,
+                operations: &patch_manifest.operations,
+                task: base_index,
+                options,
+            };
+            let result = self.apply_operations_counted(&run, &counts);
+            context.report.record_operations(base_index, counts.get());
+            result.map_err(|e| {
+                context.report.record_operation(base_index, OperationOutcome::Failed);
+                anyhow!("Apply operations error: {:?}", e)
+            })?;
+            self.progress.step(base_index);
+
+            // 提交更改（已取消时不提交，更改随卸载丢弃）
+            check_cancelled()?;
+            self.progress.stage(base_index, &t!("apply_patch.commit_changes"));
+            wimgapi
+                .commit(base_mounted.handle(), 0)
+                .map_err(|e| anyhow!("Commit image error: {:?}", e))?;
+
+            // 更新镜像信息（需在提交更改后，补丁清单中的值已反转义，写回时重新转义）
             if let Some(name) = &patch_manifest.target_image_info.name {
-                base_image_volumes = replace_xml_field(&base_image_volumes, "NAME", name);
+                base_image_volumes = replace_xml_field(&base_image_volumes, "NAME", &escape(name.as_str()));
             }
             if let Some(display_name) = &&patch_manifest.target_image_info.display_name {
-                base_image_volumes = replace_xml_field(&base_image_volumes, "DISPLAYNAME", display_name);
+                base_image_volumes =
+                    replace_xml_field(&base_image_volumes, "DISPLAYNAME", &escape(display_name.as_str()));
             }
             if let Some(flags) = &&patch_manifest.target_image_info.flags {
-                base_image_volumes = replace_xml_field(&base_image_volumes, "FLAGS", flags);
+                base_image_volumes = replace_xml_field(&base_image_volumes, "FLAGS", &escape(flags.as_str()));
             }
             if let Some(description) = &&patch_manifest.target_image_info.description {
-                base_image_volumes = replace_xml_field(&base_image_volumes, "DESCRIPTION", description);
+                base_image_volumes =
+                    replace_xml_field(&base_image_volumes, "DESCRIPTION", &escape(description.as_str()));
             }
             if let Some(display_description) = &&patch_manifest.target_image_info.display_description {
-                base_image_volumes = replace_xml_field(&base_image_volumes, "DISPLAYDESCRIPTION", display_description);
+                base_image_volumes = replace_xml_field(
+                    &base_image_volumes,
+                    "DISPLAYDESCRIPTION",
+                    &escape(display_description.as_str()),
+                );
             }
-            main_pb.inc(1);
+            self.progress.step(base_index);
 
-            main_pb.set_message(t!("apply_patch.unmount_patch"));
-            if !is_tty() {
-                write_console(ConsoleType::Info, &t!("apply_patch.unmount_patch"));
-            }
+            self.progress.stage(base_index, &t!("apply_patch.unmount_patch"));
 
             // 卸载补丁包镜像
-            if let Err(e) = self.wimgapi.unmount_image_handle(patch_image_handle) {
-                self.wimgapi.unmount_image_handle(base_image_handle).ok();
-                self.wimgapi.close(base_image_handle).ok();
-                self.wimgapi.close(base_handle).ok();
-                self.wimgapi.close(patch_image_handle).ok();
-                self.wimgapi.close(patch_handle).ok();
-                return Err(anyhow!("{}: {}", t!("apply_patch.unmount_patch_failed"), e));
-            }
-            self.wimgapi
-                .close(patch_image_handle)
-                .with_context(|| "Close patch image handle error")?;
-            main_pb.inc(1);
+            patch_mounted
+                .unmount()
+                .map_err(|e| anyhow!("{}: {}", t!("apply_patch.unmount_patch_failed"), e))?;
+            self.progress.step(base_index);
         }
 
-        self.wimgapi
-            .close(patch_handle)
-            .with_context(|| "Close patch handle error")?;
+        patch_handle.close().with_context(|| "Close patch handle error")?;
 
-        self.wimgapi
-            .set_image_info(base_image_handle, &base_image_volumes)
+        // 记录已应用的补丁版本，之后应用更低版本的补丁时拒绝降级
+        if let Some((_, patch)) = patch_manifest_list.last() {
+            base_image_volumes = set_applied_version(&base_image_volumes, &patch.patch_version);
+        }
+        wimgapi
+            .set_image_info(base_mounted.handle(), &base_image_volumes)
             .with_context(|| "Set image info error")?;
 
+        // 卸载前复制应用结果（失败时挂载由守卫丢弃更改并卸载）
+        if let Some(extract_dir) = extract_dir {
+            self.extract_applied(&base_mount, extract_dir, base_index)?;
+        }
+
         // 卸载基础镜像
-        main_pb.set_message(t!("create_patch.unmount_base"));
-        if !is_tty() {
-            write_console(ConsoleType::Info, &t!("create_patch.unmount_base"));
+        self.progress.stage(base_index, &t!("create_patch.unmount_base"));
+        base_mounted
+            .unmount()
+            .map_err(|e| anyhow!("{}: {}", t!("create_patch.unmount_base_failed"), e))?;
+        base_handle.close().with_context(|| "Close base handle error")?;
+
+        self.progress.step(base_index);
+        self.progress.stage(
+            base_index,
+            &format!(
+                "{} ({}{})",
+                t!("apply_patch.success"),
+                t!("apply_patch.index"),
+                base_index
+            ),
+        );
+
+        self.progress.task_finished(base_index);
+        Ok(None)
+    }
+
+    /// 应用整镜像补丁
+    ///
+    /// 使用 `WIMApplyImage` 释放整镜像补丁，再依次应用其后的差异补丁，最后重新捕获为新的镜像，
+    /// 无需挂载基础镜像并逐个复制文件
+    ///
+    /// # 参数
+    ///
+    /// - `base_index` - 基础镜像索引
+    /// - `patch_image` - 补丁镜像路径
+    /// - `patch_manifest_list` - 补丁清单列表（第一个为整镜像补丁）
+    /// - `extract_dir` - 重新捕获前复制应用结果的目录（`--extract-to`）
+    /// - `context` - 应用补丁选项与应用结果报告
+    ///
+    /// # 返回值
+    ///
+    /// - `Ok(PathBuf)` - 重新捕获的镜像路径
+    /// - `Err(anyhow::Error)` - 失败
+    fn apply_whole_image_patch(
+        &self,
+        base_index: u32,
+        patch_image: &Path,
+        patch_manifest_list: &[(u32, PatchManifest)],
+        extract_dir: Option<&Path>,
+        context: &mut ApplyContext,
+    ) -> Result<PathBuf> {
+        let wimgapi = self.wimgapi()?;
+        let options = context.options;
+        let scratch = options.scratch_path();
+        // 计算总步骤数：释放整镜像 + 其后每个补丁镜像的3个步骤 + 捕获镜像
+        let total_steps = 1 + ((patch_manifest_list.len() - 1) * 3) + 1;
+
+        // 开始处理镜像
+        self.progress.task_started(base_index, total_steps);
+
+        // 打开补丁包
+        let patch_handle = HandleGuard::new(
+            wimgapi,
+            wimgapi
+                .open(
+                    patch_image,
+                    WIM_GENERIC_READ | WIM_GENERIC_MOUNT,
+                    WIM_OPEN_EXISTING,
+                    WIM_COMPRESS_NONE,
+                )
+                .with_context(|| "Open patch image error")?,
+        );
+        wimgapi
+            .set_temp_path(patch_handle.handle(), &scratch)
+            .with_context(|| "Set temp path error")?;
+
+        // 释放整镜像补丁
+        self.progress.stage(base_index, &t!("apply_patch.apply_whole_image"));
+        let apply_dir = create_tmp_dir(&scratch, "whole-", "").with_context(|| "Create whole image dir error")?;
+
+        // 释放目录在任一步骤失败时删除，句柄与挂载由守卫自动清理
+        let result = (|| -> Result<()> {
+            let (whole_index, _) = &patch_manifest_list[0];
+            let whole_image_handle = HandleGuard::new(
+                wimgapi,
+                wimgapi
+                    .load_image(patch_handle.handle(), *whole_index)
+                    .map_err(|e| anyhow!("Load image error: {}", e))?,
+            );
+            wimgapi
+                .apply_image(whole_image_handle.handle(), &apply_dir, acl_flags(options.no_acl))
+                .map_err(|e| anyhow!("Apply whole image error: {}", e))?;
+            drop(whole_image_handle);
+            self.progress.step(base_index);
+
+            // 依次应用整镜像补丁之后的差异补丁
+            for (index, patch_manifest) in &patch_manifest_list[1..] {
+                check_cancelled()?;
+                self.progress.stage(base_index, &t!("apply_patch.mount_patch"));
+                let patch_image_handle = HandleGuard::new(
+                    wimgapi,
+                    wimgapi
+                        .load_image(patch_handle.handle(), *index)
+                        .with_context(|| "Load image error")?,
+                );
+                let patch_mount = create_tmp_dir(&scratch, "patch-", "").with_context(|| "Create patch mount error")?;
+                let patch_mounted = MountGuard::mount(patch_image_handle, &patch_mount, WIM_FLAG_MOUNT_READONLY)
+                    .map_err(|e| anyhow!("{}: {}", t!("apply_patch.mount_patch_failed"), e))?;
+                self.progress.step(base_index);
+
+                self.progress.stage(base_index, &t!("apply_patch.merge_diff"));
+                let counts = Cell::default();
+                let run = OperationRun {
+                    base_mount: &apply_dir,
+                    patch_mount: &patch_mount,
+                    operations: &patch_manifest.operations,
+                    task: base_index,
+                    options,
+                };
+                let result = self.apply_operations_counted(&run, &counts);
+                context.report.record_operations(base_index, counts.get());
+                result.map_err(|e| {
+                    context.report.record_operation(base_index, OperationOutcome::Failed);
+                    anyhow!("Apply operations error: {:?}", e)
+                })?;
+                self.progress.step(base_index);
+
+                self.progress.stage(base_index, &t!("apply_patch.unmount_patch"));
+                patch_mounted
+                    .unmount()
+                    .map_err(|e| anyhow!("{}: {}", t!("apply_patch.unmount_patch_failed"), e))?;
+                self.progress.step(base_index);
+            }
+
+            // 重新捕获前复制应用结果
+            if let Some(extract_dir) = extract_dir {
+                self.extract_applied(&apply_dir, extract_dir, base_index)?;
+            }
+            Ok(())
+        })();
+        if let Err(e) = result {
+            fs::remove_dir_all(&apply_dir).ok();
+            return Err(e);
         }
-        if let Err(e) = self.wimgapi.unmount_image_handle(base_image_handle) {
-            self.wimgapi.close(base_image_handle).ok();
-            self.wimgapi.close(base_handle).ok();
-            return Err(anyhow!("{}: {}", t!("create_patch.unmount_base_failed"), e));
+        patch_handle.close().with_context(|| "Close patch handle error")?;
+
+        // 重新捕获为新的镜像
+        self.progress.stage(base_index, &t!("apply_patch.capture_whole_image"));
+        let image = get_tmp_path(&scratch, "whole-", ".wim");
+        let result = self.capture_whole_image(
+            &apply_dir,
+            &image,
+            &patch_manifest_list.last().unwrap().1,
+            acl_flags(options.no_acl),
+            &scratch,
+        );
+        fs::remove_dir_all(&apply_dir).ok();
+        if let Err(e) = result {
+            fs::remove_file(&image).ok();
+            return Err(e);
         }
-        self.wimgapi
-            .close(base_image_handle)
-            .with_context(|| "Close base image handle error")?;
-        self.wimgapi
-            .close(base_handle)
-            .with_context(|| "Close base handle error")?;
-
-        main_pb.inc(1);
-        main_pb.set_message(format!(
-            "{} ({}{})",
-            t!("apply_patch.success"),
-            t!("apply_patch.index"),
-            base_index
-        ));
+        self.progress.step(base_index);
 
-        main_pb.finish_and_clear();
-        Ok(())
+        self.progress.task_finished(base_index);
+        Ok(image)
+    }
+
+    /// 将目录捕获为新的镜像，并写入补丁清单中的更新镜像信息
+    ///
+    /// # 参数
+    ///
+    /// - `source` - 待捕获的目录
+    /// - `image` - 新镜像路径
+    /// - `patch_manifest` - 补丁链中最后一个补丁的清单
+    /// - `flags` - 捕获标志（如 [`acl_flags`] 返回的标志）
+    /// - `scratch` - 捕获时使用的临时目录
+    ///
+    /// # 返回值
+    ///
+    /// - `Ok(())` - 成功
+    /// - `Err(anyhow::Error)` - 失败
+    fn capture_whole_image(
+        &self,
+        source: &Path,
+        image: &Path,
+        patch_manifest: &PatchManifest,
+        flags: u32,
+        scratch: &Path,
+    ) -> Result<()> {
+        let wimgapi = self.wimgapi()?;
+        let handle = wimgapi
+            .open(image, WIM_GENERIC_WRITE, WIM_CREATE_ALWAYS, WIM_COMPRESS_NONE)
+            .with_context(|| "Create whole image error")?;
+        if let Err(e) = wimgapi.set_temp_path(handle, scratch) {
+            wimgapi.close(handle).ok();
+            return Err(anyhow!("Set temp path error: {}", e));
+        }
+        let image_handle = match wimgapi.capture(handle, source, flags) {
+            Ok(image_handle) => image_handle,
+            Err(e) => {
+                wimgapi.close(handle).ok();
+                return Err(anyhow!("Capture whole image error: {}", e));
+            }
+        };
+
+        // 在</IMAGE>标签前添加更新镜像的基本字段信息
+        let target_info = &patch_manifest.target_image_info;
+        let result = wimgapi
+            .get_image_info(image_handle)
+            .map_err(anyhow::Error::from)
+            .and_then(|image_info| {
+                let pos = image_info
+                    .rfind("</IMAGE>")
+                    .ok_or_else(|| anyhow!("<IMAGE> tag not found"))?;
+                let mut fields = String::new();
+                for (field, value) in [
+                    ("NAME", &target_info.name),
+                    ("DESCRIPTION", &target_info.description),
+                    ("DISPLAYNAME", &target_info.display_name),
+                    ("DISPLAYDESCRIPTION", &target_info.display_description),
+                    ("FLAGS", &target_info.flags),
+                ] {
+                    if let Some(value) = value {
+                        fields.push_str(&format!("<{field}>{}</{field}>", escape(value.as_str())));
+                    }
+                }
+                fields.push_str(&format!(
+                    "<{APPLIED_VERSION_FIELD}>{}</{APPLIED_VERSION_FIELD}>",
+                    patch_manifest.patch_version
+                ));
+                let updated = format!("{}{}{}", &image_info[..pos], fields, &image_info[pos..]);
+                Ok(wimgapi.set_image_info(image_handle, &updated)?)
+            });
+        wimgapi.close(image_handle).ok();
+        wimgapi.close(handle).ok();
+        result.with_context(|| "Set whole image info error")
     }
 
     /// 创建文件操作配置
-    fn create_operations(
+    ///
+    /// 当 `options.dry_run` 为真时仅计算补丁数据大小，不写入任何文件
+    ///
+    /// # 返回值
+    ///
+    /// - `Ok((operations, estimate, dropped))` - 操作列表、按存储类型统计的（文件数, 字节数）以及未写入补丁的差异
+    /// - `Err(anyhow::Error)` - 失败
+    pub(crate) fn create_operations(
         &self,
         base_mount: &Path,
         target_mount: &Path,
         patch_path: &Path,
-        storage: &Storage,
-        preset: &Preset,
-        exclude: Option<&[String]>,
-    ) -> Result<Vec<Operation>> {
+        task: u32,
+        options: &CreateOptions,
+    ) -> Result<(Vec<Operation>, BTreeMap<String, (usize, u64)>, DroppedDiffs)> {
         let mut operations = Vec::new();
+        let mut estimate: BTreeMap<String, (usize, u64)> = BTreeMap::new();
+        // 未写入补丁的差异
+        let mut dropped = DroppedDiffs::default();
+        // 已暂存的完整补丁数据（SHA-256 -> 路径）
+        let mut payloads: HashMap<String, String> = HashMap::new();
+        // 新增的目录
+        let mut added_dirs: HashSet<String> = HashSet::new();
+
+        // 开始处理文件操作（比较完成前总数未知）
+        self.progress.operations_started(task, None);
+
+        let level = zstd_level(options);
+
+        // 完整存储且不压缩时，使用硬链接代替复制以加快补丁数据暂存
+        // 指定 --stage-compressed 时完整文件以 zstd 压缩暂存，捕获前再解压，以 CPU 换取临时目录空间
+        let link_payload =
+            options.storage == Storage::Full && options.compress == Compress::None && !options.stage_compressed;
+        // 暂存时在同一遍读取中计算文件的 SHA-256，用于去重并写入补丁清单
+        let stage_payload = |source: &Path, target: &Path| -> std::io::Result<String> {
+            if options.stage_compressed {
+                let mut staged = target.as_os_str().to_owned();
+                staged.push(STAGED_PAYLOAD_SUFFIX);
+                let hash = ZstdDiff::file_compress_hashed(source, &staged, STAGED_PAYLOAD_LEVEL)
+                    .map_err(std::io::Error::other)?;
+                // 压缩文件保留原文件的时间与属性，解压时恢复
+                restore_metadata(&staged, &fs::metadata(source)?)?;
+                Ok(hash)
+            } else if link_payload {
+                link_or_copy(source, target)
+            } else {
+                // 稀疏文件按稀疏方式暂存，避免临时目录中展开为完整大小
+                copy_hashed(source, target)
+            }
+        };
+        // 暂存完整文件，内容与已暂存的补丁数据相同时删除本次暂存的文件，返回文件哈希与引用的补丁数据路径
+        // 仅评估模式不写入文件，只计算哈希用于去重
+        let stage_full =
+            |source: &Path, path: &str, payloads: &mut HashMap<String, String>| -> Result<(String, Option<String>)> {
+                let target = long_path(&patch_path.join(path));
+                let hash = if options.dry_run {
+                    get_file_sha256(source, None)?
+                } else {
+                    if let Some(parent) = target.parent()
+                        && !parent.exists()
+                    {
+                        fs::create_dir_all(parent)
+                            .with_context(|| format!("Create directory failed: {}", parent.display()))?;
+                    }
+                    stage_payload(source, &target)
+                        .map_err(|e| anyhow!("Copy file failed: {} ({})", source.display(), e))?
+                };
+                let payload_ref = Self::find_payload_ref(payloads, &hash, path);
+                if payload_ref.is_some() && !options.dry_run {
+                    let mut staged = target.into_os_string();
+                    if options.stage_compressed {
+                        staged.push(STAGED_PAYLOAD_SUFFIX);
+                    }
+                    fs::remove_file(&staged).with_context(|| format!("Remove duplicate payload failed: {}", path))?;
+                }
+                Ok((hash, payload_ref))
+            };
 
-        // 创建进度条（用于显示具体操作进度）
-        let sub_pb = self.multi_pb.add(ProgressBar::new(100));
-        sub_pb.set_style(
-            ProgressStyle::with_template("{prefix:.bold.dim} {spinner} {wide_msg}")
-                .unwrap()
-                .tick_strings(&["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"]),
-        );
-        sub_pb.enable_steady_tick(Duration::from_millis(80));
+        // 指定 --preserve-streams 时记录文件的备用数据流
+        let get_streams = |file: &Path| -> Result<Vec<StreamInfo>> {
+            if !options.preserve_streams {
+                return Ok(Vec::new());
+            }
+            let streams = list_streams(file).with_context(|| format!("List streams failed: {}", file.display()))?;
+            Ok(streams
+                .into_iter()
+                .map(|(name, size)| StreamInfo { name, size })
+                .collect())
+        };
+        // 暂存补丁数据或数据流失败时中断比较并返回该错误，避免其他操作引用未暂存的补丁数据
+        let mut stage_error: Option<anyhow::Error> = None;
+
+        // 已处理的差异数量，以及新增和修改文件的已处理字节数与总字节数
+        let mut position = 0u64;
+        let mut processed_bytes = 0u64;
+        let total = Cell::new(0u64);
+        let total_bytes = Cell::new(0u64);
+
+        // 比较目录差异（得到差异总量后切换为确定进度）
+        let on_total = |count: usize, bytes: u64| {
+            total.set(count as u64);
+            total_bytes.set(bytes);
+        };
+        // 指定子目录时检查其在两个镜像中均存在，路径仍相对于镜像根目录
+        let subtree = Path::new(options.subtree.as_deref().unwrap_or(""));
+        for mount in [base_mount, target_mount] {
+            if !mount.join(subtree).is_dir() {
+                return Err(anyhow!(
+                    "{}",
+                    t!(
+                        "create_patch.subtree_not_found",
+                        subtree = subtree.display(),
+                        mount = mount.display()
+                    )
+                ));
+            }
+        }
+
+        let on_diff = |diff_type: DiffType, old: Option<&Path>, new: Option<&Path>, path: &str| {
+            // 已请求取消时中断比较
+            if is_cancelled() {
+                return false;
+            }
+
+            // 更新已处理的文件数与字节数
+            position += 1;
+            if let Some(new_path) = new {
+                processed_bytes += new_path.metadata().map(|m| m.len()).unwrap_or(0);
+            }
+            let report = |message: &str| {
+                self.progress.operation(
+                    task,
+                    &OperationProgress {
+                        position,
+                        total: total.get(),
+                        processed_bytes,
+                        total_bytes: total_bytes.get(),
+                        message,
+                    },
+                )
+            };
 
-        // 比较目录差异
-        compare_directories(base_mount, target_mount, |diff_type, old, new, path| {
             // 检查是否需要排除
-            if let Some(exclude) = exclude {
+            if let Some(exclude) = &options.exclude {
                 for item in exclude {
                     if path.to_ascii_lowercase().contains(&item.to_ascii_lowercase()) {
-                        sub_pb.set_message(format!("{} \\{}", t!("create_patch.exclude"), path));
+                        report(&format!("{} \\{}", t!("create_patch.exclude"), path));
+                        dropped.record(&diff_type, old, new);
                         return true;
                     }
                 }
             }
 
-            // 更新进度条消息
+            // 排除易变文件（调试模式下输出被排除的文件与命中的模式）
+            if options.exclude_volatile
+                && let Some(pattern) = volatile_pattern(path)
+            {
+                report(&format!("{} \\{}", t!("create_patch.exclude"), path));
+                if is_debug() {
+                    self.progress.message(
+                        task,
+                        &format!(
+                            " {}      {}: \\{} ({})",
+                            style(t!("console.debug")).magenta(),
+                            t!("create_patch.exclude_volatile"),
+                            path,
+                            pattern
+                        ),
+                    );
+                }
+                dropped.record(&diff_type, old, new);
+                return true;
+            }
+
+            // 指定包含列表时跳过未命中的差异
+            if !is_included(path, options.include.as_deref()) {
+                report(&format!("{} \\{}", t!("create_patch.not_included"), path));
+                dropped.record(&diff_type, old, new);
+                return true;
+            }
+
+            // 更新进度消息
             let message = match diff_type {
                 DiffType::Add => format!("{} \\{}", t!("create_patch.Add"), path),
                 DiffType::Delete => format!("{} \\{}", t!("create_patch.Delete"), path),
                 DiffType::Modify => format!("{} \\{}", t!("create_patch.Modify"), path),
             };
-            sub_pb.set_message(message.clone());
-            if !is_tty() {
-                println!("{}", message);
-            }
+            report(&message);
 
             // 构造补丁
             match diff_type {
                 // 处理新增操作
                 DiffType::Add => {
                     if let Some(new_path) = new {
+                        let size = new_path.metadata().map(|m| m.len()).unwrap_or(0);
+                        // 暂存新增的文件，暂存时计算的哈希同时写入补丁清单，无需再次读取文件
+                        let (sha256, payload_ref) = if new_path.is_dir() {
+                            added_dirs.insert(path.to_string());
+                            (None, None)
+                        } else {
+                            match stage_full(new_path, path, &mut payloads) {
+                                Ok((hash, payload_ref)) => (Some(hash), payload_ref),
+                                Err(e) => {
+                                    stage_error = Some(e);
+                                    return false;
+                                }
+                            }
+                        };
+                        let streams = if new_path.is_dir() {
+                            Ok(Vec::new())
+                        } else {
+                            get_streams(new_path)
+                        };
+                        let streams = match streams.and_then(|streams| {
+                            Self::stage_streams(new_path, patch_path, path, &streams, options.dry_run, &mut estimate)?;
+                            Ok(streams)
+                        }) {
+                            Ok(streams) => streams,
+                            Err(e) => {
+                                stage_error = Some(e);
+                                return false;
+                            }
+                        };
                         operations.push(Operation {
                             action: Action::Add,
                             path: path.to_string(),
-                            size: Some(new_path.metadata().unwrap().len()),
+                            size: Some(size),
                             storage: None,
+                            payload_ref: payload_ref.clone(),
+                            full_fallback: false,
+                            sparse: !new_path.is_dir() && is_sparse_file(new_path),
+                            case_rename: false,
+                            streams,
+                            dict_window: None,
+                            sha256,
+                            base_sha256: None,
                         });
 
-                        // 确保patch目录存在
-                        let target_path = patch_path.join(path);
+                        // 内容相同的文件已暂存，无需重复存储
+                        if payload_ref.is_some() {
+                            return true;
+                        }
+
+                        // 统计新增文件大小（按完整存储计算）
+                        if !new_path.is_dir() {
+                            let entry = estimate.entry("full".to_string()).or_default();
+                            entry.0 += 1;
+                            entry.1 += size;
+                        }
+
+                        // 仅评估模式不写入文件
+                        if options.dry_run {
+                            return true;
+                        }
+
+                        // 新增的目录在patch目录中创建（新增的文件已在上面暂存）
+                        if new_path.is_dir()
+                            && let Err(e) = fs::create_dir_all(long_path(&patch_path.join(path)))
+                        {
+                            eprintln!("Create directory Failed: {:?}", e);
+                        }
+                    }
+                }
+                // 处理删除操作
+                DiffType::Delete => {
+                    operations.push(Operation {
+                        action: Action::Delete,
+                        path: path.to_string(),
+                        size: None,
+                        storage: None,
+                        payload_ref: None,
+                        full_fallback: false,
+                        sparse: false,
+                        case_rename: false,
+                        streams: Vec::new(),
+                        dict_window: None,
+                        sha256: None,
+                        base_sha256: None,
+                    });
+                }
+                // 处理修改操作
+                DiffType::Modify => {
+                    // 确保patch目录存在
+                    if let Some(old_path) = old
+                        && let Some(new_path) = new
+                    {
+                        // 忽略大小写比较时，名称仅大小写不同的文件或目录在应用后按更新镜像中的大小写重命名
+                        let case_rename = old_path.file_name() != new_path.file_name();
+
+                        // 仅大小写不同的目录记录为新增目录，应用时目录已存在，只重命名
                         if new_path.is_dir() {
-                            if let Err(e) = fs::create_dir_all(&target_path) {
-                                eprintln!("Create directory Failed: {:?}", e);
+                            operations.push(Operation {
+                                action: Action::Add,
+                                path: path.to_string(),
+                                size: Some(0),
+                                storage: None,
+                                payload_ref: None,
+                                full_fallback: false,
+                                sparse: false,
+                                case_rename,
+                                streams: Vec::new(),
+                                dict_window: None,
+                                sha256: None,
+                                base_sha256: None,
+                            });
+                            if !options.dry_run
+                                && let Err(e) = fs::create_dir_all(long_path(&patch_path.join(path)))
+                            {
+                                stage_error = Some(anyhow!("Create directory failed: {} ({})", path, e));
+                                return false;
+                            }
+                            return true;
+                        }
+
+                        // 空文件直接按完整文件记录，无需生成差异
+                        let size = new_path.metadata().map(|m| m.len()).unwrap_or(0);
+                        let file_storage = if size == 0 {
+                            Storage::Full
+                        } else {
+                            options.storage.clone()
+                        };
+                        let storage = match file_storage {
+                            Storage::Full | Storage::WholeImage => "full",
+                            Storage::Zstd => "zstd",
+                            Storage::Bsdiff => "bsdiff",
+                        };
+
+                        // 指定 --hive-diff 时注册表配置单元按键和值生成结构化差异，失败时回退为上面的差异类型
+                        let mut hive = options.hive_diff
+                            && matches!(file_storage, Storage::Zstd | Storage::Bsdiff)
+                            && HiveDiff::is_hive(new_path);
+
+                        // 完整存储时在暂存的同一遍读取中计算哈希，差异存储时在生成差异的同一遍读取中计算
+                        let (sha256, payload_ref) = if matches!(file_storage, Storage::Full | Storage::WholeImage) {
+                            match stage_full(new_path, path, &mut payloads) {
+                                Ok((hash, payload_ref)) => (Some(hash), payload_ref),
+                                Err(e) => {
+                                    stage_error = Some(e);
+                                    return false;
+                                }
+                            }
+                        } else {
+                            (None, None)
+                        };
+
+                        // 旧文件超过字典窗口时 zstd 差异仅使用其末尾部分作为字典，应用时使用相同的窗口
+                        let dict_window = options.dict_window.filter(|window| {
+                            file_storage == Storage::Zstd
+                                && old_path.metadata().is_ok_and(|metadata| metadata.len() > *window)
+                        });
+
+                        // 差异存储时可同时嵌入完整文件，用于应用失败时回退
+                        let full_fallback = options.embed_full_fallback && file_storage != Storage::Full;
+
+                        // 备用数据流按操作路径单独存储，不参与补丁数据去重
+                        let streams = match get_streams(new_path).and_then(|streams| {
+                            Self::stage_streams(new_path, patch_path, path, &streams, options.dry_run, &mut estimate)?;
+                            Ok(streams)
+                        }) {
+                            Ok(streams) => streams,
+                            Err(e) => {
+                                stage_error = Some(e);
+                                return false;
+                            }
+                        };
+
+                        // 记录修改操作
+                        operations.push(Operation {
+                            action: Action::Modify,
+                            path: path.to_string(),
+                            size: Some(size),
+                            storage: Some(if hive { "hive" } else { storage }.to_string()),
+                            payload_ref: payload_ref.clone(),
+                            full_fallback,
+                            sparse: is_sparse_file(new_path),
+                            case_rename,
+                            streams,
+                            dict_window,
+                            sha256,
+                            base_sha256: None,
+                        });
+
+                        // 内容相同的文件已暂存，无需重复存储（回退时使用被引用的完整文件）
+                        if payload_ref.is_some() {
+                            return true;
+                        }
+
+                        // 仅评估模式：计算补丁数据大小，不写入文件
+                        if options.dry_run {
+                            let hive_size = if hive {
+                                HiveDiff::file_diff_size(old_path, new_path, level)
+                                    .inspect_err(|e| Self::hive_fallback(&mut operations, path, storage, e))
+                                    .ok()
+                            } else {
+                                None
+                            };
+                            let (storage, size) = match hive_size {
+                                Some(size) => ("hive", Ok(size)),
+                                None => (
+                                    storage,
+                                    match file_storage {
+                                        Storage::Full | Storage::WholeImage => {
+                                            new_path.metadata().map(|m| m.len()).map_err(anyhow::Error::from)
+                                        }
+                                        Storage::Zstd => {
+                                            ZstdDiff::file_diff_size(old_path, new_path, level, dict_window)
+                                        }
+                                        Storage::Bsdiff => BsDiff::file_diff_size(old_path, new_path),
+                                    },
+                                ),
+                            };
+                            match size {
+                                Ok(size) => {
+                                    let entry = estimate.entry(storage.to_string()).or_default();
+                                    entry.0 += 1;
+                                    entry.1 += size;
+                                }
+                                Err(e) => eprintln!("Estimate diff size Failed: {:?}", e),
+                            }
+                            if full_fallback
+                                && file_storage != Storage::WholeImage
+                                && let Ok(metadata) = new_path.metadata()
+                            {
+                                let entry = estimate.entry("full".to_string()).or_default();
+                                entry.0 += 1;
+                                entry.1 += metadata.len();
                             }
                             return true;
                         }
+
                         // 创建父目录
-                        if let Some(parent) = target_path.parent()
-                            && !parent.exists()
-                            && let Err(e) = fs::create_dir_all(parent)
-                        {
-                            eprintln!("Create directory Failed: {:?}", e);
-                        }
-                        // 复制新增的文件到patch目录
-                        if let Err(e) = fs::copy(new_path, &target_path) {
-                            eprintln!("Copy file Failed: {:?}", e);
-                        }
-                    }
-                }
-                // 处理删除操作
-                DiffType::Delete => {
-                    operations.push(Operation {
-                        action: Action::Delete,
-                        path: path.to_string(),
-                        size: None,
-                        storage: None,
-                    });
-                }
-                // 处理修改操作
-                DiffType::Modify => {
-                    // 确保patch目录存在
-                    if let Some(old_path) = old
-                        && let Some(new_path) = new
-                    {
-                        // 创建父目录
-                        if let Some(parent) = patch_path.join(path).parent()
+                        if let Some(parent) = long_path(&patch_path.join(path)).parent()
                             && !parent.exists()
                             && let Err(e) = fs::create_dir_all(parent)
                         {
                             eprintln!("Create directory Failed: {:?}", e);
                         }
 
-                        // 记录修改操作
-                        operations.push(Operation {
-                            action: Action::Modify,
-                            path: path.to_string(),
-                            size: Some(new_path.metadata().unwrap().len()),
-                            storage: Some(match storage {
-                                Storage::Full => "full".to_string(),
-                                Storage::Zstd => "zstd".to_string(),
-                                Storage::Bsdiff => "bsdiff".to_string(),
-                            }),
-                        });
+                        // 生成注册表配置单元的结构化差异，失败时回退为普通差异
+                        let hive_path = long_path(&patch_path.join(format!("{}.hive", path)));
+                        if hive && let Err(e) = HiveDiff::file_diff(old_path, new_path, &hive_path, level) {
+                            Self::hive_fallback(&mut operations, path, storage, &e);
+                            hive = false;
+                        }
 
                         // 处理修改操作
-                        match storage {
-                            Storage::Full => {
-                                // 复制修改前的文件到patch目录
-                                if let Err(e) = fs::copy(old_path, patch_path.join(path)) {
-                                    eprintln!("Copy file Failed: {:?}", e);
+                        let payload = match file_storage {
+                            // 修改后的文件已在上面暂存（按原文件大小统计，暂存时可能已压缩）
+                            Storage::Full | Storage::WholeImage => new_path.to_path_buf(),
+                            Storage::Zstd | Storage::Bsdiff if hive => {
+                                // 结构化差异不经过普通差异的读取，单独计算更新后文件的哈希
+                                if let Some(operation) = operations.last_mut() {
+                                    operation.sha256 = get_file_sha256(new_path, None).ok();
                                 }
+                                hive_path
                             }
-                            Storage::Zstd => {
-                                // 生成zstd差异文件
-                                if let Err(e) = ZstdDiff::file_diff(
-                                    old_path,
-                                    new_path,
-                                    patch_path.join(format!("{}.diff", path)),
-                                    match preset {
-                                        Preset::Fast => 3,
-                                        Preset::Medium => 9,
-                                        Preset::Best => 19,
-                                        Preset::Extreme => 22,
-                                    },
-                                ) {
-                                    eprintln!("Create diff file Failed: {:?}", e);
+                            Storage::Zstd | Storage::Bsdiff => {
+                                // 生成差异文件，同时得到基础文件与更新后文件的哈希
+                                let diff_path = long_path(&patch_path.join(format!("{}.diff", path)));
+                                let hashes = if file_storage == Storage::Zstd {
+                                    ZstdDiff::file_diff_hashed(old_path, new_path, &diff_path, level, dict_window)
+                                } else {
+                                    BsDiff::file_diff_hashed(old_path, new_path, &diff_path)
+                                };
+                                match hashes {
+                                    Ok(hashes) => {
+                                        if let Some(operation) = operations.last_mut() {
+                                            operation.sha256 = Some(hashes.new);
+                                            operation.base_sha256 = Some(hashes.old);
+                                        }
+                                    }
+                                    Err(e) => eprintln!("Create diff file Failed: {:?}", e),
                                 }
+                                diff_path
                             }
-                            Storage::Bsdiff => {
-                                // 生成bsdiff差异文件
-                                if let Err(e) =
-                                    BsDiff::file_diff(old_path, new_path, patch_path.join(format!("{}.diff", path)))
-                                {
-                                    eprintln!("Create diff file Failed: {:?}", e);
+                        };
+
+                        // 统计补丁数据大小
+                        if let Ok(metadata) = payload.metadata() {
+                            let entry = estimate
+                                .entry(if hive { "hive" } else { storage }.to_string())
+                                .or_default();
+                            entry.0 += 1;
+                            entry.1 += metadata.len();
+                        }
+
+                        // 暂存回退用的完整文件（整镜像存储时补丁数据本身即为上面暂存的完整文件）
+                        if full_fallback && file_storage != Storage::WholeImage {
+                            let fallback_path = long_path(&patch_path.join(path));
+                            match stage_payload(new_path, &fallback_path) {
+                                Ok(_) => {
+                                    let entry = estimate.entry("full".to_string()).or_default();
+                                    entry.0 += 1;
+                                    entry.1 += new_path.metadata().map(|m| m.len()).unwrap_or(0);
                                 }
+                                Err(e) => eprintln!("Copy file Failed: {:?}", e),
                             }
                         }
                     }
                 }
             }
             true
+        };
+        let compare_options = CompareOptions {
+            subtree,
+            mode: options.compare_mode,
+            ignore_case: options.compare_ignore_case,
+            follow_reparse_depth: options.compare_follow_reparse_depth,
+            report_unchanged: options.report_unchanged,
+        };
+        let unchanged = compare_subtree_with_total(base_mount, target_mount, &compare_options, on_total, on_diff);
+        if let Some(e) = stage_error {
+            return Err(e);
+        }
+        let unchanged = unchanged.map_err(|e| {
+            if is_cancelled() {
+                anyhow!(t!("cancel.cancelled"))
+            } else {
+                e
+            }
         })?;
 
-        // 完成子进度条
-        sub_pb.finish_and_clear();
+        // 指定 --report-unchanged 时按路径顺序输出被判定为相同的文件
+        for path in &unchanged {
+            self.progress
+                .message(task, &format!("{} \\{}", t!("create_patch.unchanged"), path));
+        }
+
+        // 剔除不包含任何文件变更的新增目录
+        if options.skip_empty_dirs {
+            let empty_dirs: HashSet<String> = added_dirs
+                .iter()
+                .filter(|dir| {
+                    let prefix = format!("{}\\", dir);
+                    !operations.iter().any(|op| {
+                        op.action != Action::Delete && !added_dirs.contains(&op.path) && op.path.starts_with(&prefix)
+                    })
+                })
+                .cloned()
+                .collect();
+            operations.retain(|op| !(op.action == Action::Add && empty_dirs.contains(&op.path)));
+            // 被忽略的空目录在应用补丁后仍不存在
+            dropped.count += empty_dirs.len() as u64;
+            dropped.dirs -= empty_dirs.len() as i64;
+            if !options.dry_run {
+                for dir in &empty_dirs {
+                    fs::remove_dir_all(patch_path.join(dir)).ok();
+                }
+            }
+        }
+
+        // 文件操作处理完成
+        self.progress.operations_finished(task);
+
+        Ok((operations, estimate, dropped))
+    }
+
+    /// 注册表配置单元无法生成结构化差异时输出警告，并将刚记录的修改操作改回普通差异类型
+    ///
+    /// # 参数
+    ///
+    /// - `operations` - 操作列表（最后一项为该配置单元的修改操作）
+    /// - `path` - 配置单元在镜像中的相对路径
+    /// - `storage` - 回退使用的差异类型
+    /// - `error` - 生成结构化差异的错误
+    fn hive_fallback(operations: &mut [Operation], path: &str, storage: &str, error: &anyhow::Error) {
+        write_console(
+            ConsoleType::Warning,
+            &t!(
+                "create_patch.hive_fallback",
+                path = format!("\\{}", path),
+                storage = storage,
+                error = format!("{:#}", error)
+            ),
+        );
+        if let Some(operation) = operations.last_mut() {
+            operation.storage = Some(storage.to_string());
+        }
+    }
+
+    /// 查找内容相同的已暂存补丁数据
+    ///
+    /// # 参数
+    ///
+    /// - `payloads` - 已暂存的补丁数据（SHA-256 -> 路径）
+    /// - `hash` - 待暂存文件的 SHA-256
+    /// - `path` - 待暂存文件在补丁中的相对路径
+    ///
+    /// # 返回值
+    ///
+    /// - `Some(String)` - 内容相同的已暂存补丁数据路径
+    /// - `None` - 未找到，当前文件已登记为新的补丁数据
+    fn find_payload_ref(payloads: &mut HashMap<String, String>, hash: &str, path: &str) -> Option<String> {
+        if let Some(existing) = payloads.get(hash) {
+            return Some(existing.clone());
+        }
+        payloads.insert(hash.to_string(), path.to_string());
+        None
+    }
+
+    /// 生成仅评估模式的补丁报告
+    ///
+    /// # 参数
+    ///
+    /// - `base_index` - 基础镜像索引
+    /// - `target_index` - 更新镜像索引
+    /// - `operations` - 操作列表
+    /// - `estimate` - 按存储类型统计的（文件数, 字节数）
+    ///
+    /// # 返回值
+    ///
+    /// - `String` - 报告内容
+    fn format_dry_run_report(
+        base_index: u32,
+        target_index: u32,
+        operations: &[Operation],
+        estimate: &BTreeMap<String, (usize, u64)>,
+    ) -> String {
+        let label_w = 18;
+        let total_w = 40;
+        let mut result = String::new();
+
+        result.push_str("Dry Run Operations:\n");
+        result.push_str(&format!("{:-^total_w$}\n", "-"));
+        for operation in operations {
+            let line = match operation.action {
+                Action::Add => format!("+ {}", operation.path),
+                Action::Delete => format!("- {}", operation.path),
+                Action::Modify => format!(
+                    "~ {} [{}]",
+                    operation.path,
+                    operation.storage.as_deref().unwrap_or_default()
+                ),
+            };
+            result.push_str(&line);
+            if let Some(payload_ref) = &operation.payload_ref {
+                result.push_str(&format!(" => {}", payload_ref));
+            }
+            if let Some(size) = operation.size {
+                result.push_str(&format!(" ({})", format_bytes(size)));
+            }
+            if operation.sparse {
+                result.push_str(" [sparse]");
+            }
+            for stream in &operation.streams {
+                result.push_str(&format!(" [:{} {}]", stream.name, format_bytes(stream.size)));
+            }
+            result.push('\n');
+        }
+
+        // 显示操作统计
+        let add_count = operations.iter().filter(|op| op.action == Action::Add).count();
+        let modify_count = operations.iter().filter(|op| op.action == Action::Modify).count();
+        let delete_count = operations.iter().filter(|op| op.action == Action::Delete).count();
+
+        result.push_str("\nDry Run Summary:\n");
+        result.push_str(&format!("{:-^total_w$}\n", "-"));
+        result.push_str(&format!("{:<label_w$} {} -> {}\n", "Index:", base_index, target_index));
+        result.push_str(&format!(
+            "{:<label_w$} +{} / ~{} / -{} (total: {})\n",
+            "Operations:",
+            add_count,
+            modify_count,
+            delete_count,
+            operations.len()
+        ));
+
+        // 显示各存储类型的预估大小
+        for (storage, (count, bytes)) in estimate {
+            result.push_str(&format!(
+                "{:<label_w$} {} files, {}\n",
+                format!("Storage {}:", storage),
+                count,
+                format_bytes(*bytes)
+            ));
+        }
+        let total_bytes: u64 = estimate.values().map(|(_, bytes)| bytes).sum();
+        result.push_str(&format!(
+            "{:<label_w$} {}\n",
+            "Estimated Size:",
+            format_bytes(total_bytes)
+        ));
 
-        Ok(operations)
+        result
     }
 
     /// 根据操作配置对基础镜像执行文件操作
-    fn apply_operations(
+    ///
+    /// # 参数
+    ///
+    /// - `base_mount` - 基础镜像挂载路径
+    /// - `patch_mount` - 补丁镜像挂载路径
+    /// - `operations` - 操作列表
+    /// - `task` - 进度任务编号（镜像索引）
+    /// - `options` - 应用选项（排除列表、强制模式、重试次数与路径重映射）
+    ///
+    /// # 返回值
+    ///
+    /// - `Ok(OperationCounts)` - 各文件操作的结果统计
+    /// - `Err(anyhow::Error)` - 执行文件操作失败
+    pub(crate) fn apply_operations(
         &self,
         base_mount: &Path,
         patch_mount: &Path,
         operations: &Vec<Operation>,
-        exclude: Option<&[String]>,
-        force: bool,
-    ) -> Result<()> {
-        // 创建子进度条，设置总长度为操作数量
-        let sub_pb = self.multi_pb.add(ProgressBar::new(operations.len() as u64));
-        sub_pb.set_style(
-            ProgressStyle::with_template("{prefix:.bold.dim} {spinner} {wide_msg}")
-                .unwrap()
-                .tick_strings(&["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"]),
-        );
-        sub_pb.enable_steady_tick(Duration::from_millis(80));
+        task: u32,
+        options: &ApplyOptions,
+    ) -> Result<OperationCounts> {
+        let run = OperationRun {
+            base_mount,
+            patch_mount,
+            operations,
+            task,
+            options,
+        };
+        let counts = Cell::default();
+        self.apply_operations_counted(&run, &counts)?;
+        Ok(counts.get())
+    }
+
+    /// 根据操作配置对基础镜像执行文件操作，并将各文件操作的结果累加到 `counts`
+    ///
+    /// 执行失败时 `counts` 中保留失败前已记录的结果
+    ///
+    /// # 参数
+    ///
+    /// - `run` - 要执行的文件操作及其基础镜像、补丁镜像挂载路径与应用选项
+    /// - `counts` - 文件操作结果统计
+    fn apply_operations_counted(&self, run: &OperationRun, counts: &Cell<OperationCounts>) -> Result<()> {
+        let OperationRun {
+            base_mount,
+            patch_mount,
+            operations,
+            task,
+            options,
+        } = *run;
+        let exclude = options.exclude.as_deref();
+        let force = options.force;
+        let io_retries = options.io_retries;
+
+        // 记录文件操作结果（指定 --report 时写入应用结果报告）
+        let record = |outcome| {
+            let mut updated = counts.get();
+            updated.record(outcome);
+            counts.set(updated);
+        };
+
+        // 稀疏文件按稀疏方式复制，避免展开为完整大小
+        let copy_file = |source: &Path, target: &Path, sparse: bool| {
+            if sparse {
+                copy_sparse(source, target)
+            } else {
+                fs::copy(source, target)
+            }
+        };
+
+        // 开始处理文件操作，总数为操作数量
+        self.progress.operations_started(task, Some(operations.len() as u64));
+
+        // 需要写入的数据总量（新增和修改操作）
+        let total_bytes: u64 = operations
+            .iter()
+            .filter(|op| op.action != Action::Delete)
+            .filter_map(|op| op.size)
+            .sum();
+        let mut processed_bytes = 0u64;
+        // 目标文件内容已与补丁一致而跳过复制的操作数量
+        let mut already_applied = 0u64;
+
+        // 按执行顺序处理（进度位置为排序后的位置，总数与数据量不受顺序影响）
+        for (position, operation) in order_operations(operations, options.apply_order)
+            .into_iter()
+            .enumerate()
+        {
+            check_cancelled()?;
+            self.progress.overall_step();
+
+            // 更新已处理数据量
+            if operation.action != Action::Delete {
+                processed_bytes += operation.size.unwrap_or(0);
+            }
+
+            // 向进度观察者报告当前操作
+            let report = |message: String| {
+                self.progress.operation(
+                    task,
+                    &OperationProgress {
+                        position: position as u64 + 1,
+                        total: operations.len() as u64,
+                        processed_bytes,
+                        total_bytes,
+                        message: &message,
+                    },
+                )
+            };
 
-        for operation in operations {
             // 判断是否需要排除
             if let Some(exclude) = exclude
                 && exclude.iter().any(|exclude_item| {
@@ -1367,36 +4721,93 @@ impl WimPatch {
                         .contains(&exclude_item.to_ascii_lowercase())
                 })
             {
-                sub_pb.set_message(format!("{} \\{}", t!("create_patch.exclude"), &operation.path));
-                if !is_tty() {
-                    write_console(
-                        ConsoleType::Info,
-                        &format!("{} \\{}", t!("create_patch.exclude"), &operation.path),
-                    );
-                }
-                sub_pb.inc(1);
+                report(format!("{} \\{}", t!("create_patch.exclude"), &operation.path));
+                record(OperationOutcome::Skipped);
+                *self
+                    .excluded
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .entry(task)
+                    .or_default() += 1;
+                continue;
+            }
+
+            // 指定包含列表时跳过未命中的操作
+            if !is_included(&operation.path, options.include.as_deref()) {
+                report(format!("{} \\{}", t!("create_patch.not_included"), &operation.path));
+                record(OperationOutcome::Skipped);
+                *self
+                    .excluded
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .entry(task)
+                    .or_default() += 1;
+                continue;
+            }
+
+            // 跳过删除操作（目标镜像将保留补丁清单中要删除的文件）
+            if options.no_delete && operation.action == Action::Delete {
+                report(format!("{} \\{}", t!("apply_patch.skip_delete"), &operation.path));
+                record(OperationOutcome::Skipped);
+                *self
+                    .excluded
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .entry(task)
+                    .or_default() += 1;
+                continue;
+            }
+
+            // 重映射目标路径（补丁数据仍按原路径读取）
+            let Some(mapped_path) = remap_operation_path(
+                &operation.path,
+                options.strip_components,
+                options.path_prefix.as_deref(),
+            )?
+            else {
+                report(format!("{} \\{}", t!("apply_patch.path_stripped"), &operation.path));
+                record(OperationOutcome::Skipped);
+                continue;
+            };
+
+            // 重建配置单元时已清空其事务日志，更新镜像中的事务日志与重建的配置单元不对应，不再写入
+            if operation.action != Action::Delete
+                && let Some(hive) = HiveDiff::log_owner(&mapped_path)
+                && self
+                    .rebuilt_hives
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .get(&task)
+                    .is_some_and(|hives| hives.contains_key(&hive.to_lowercase()))
+            {
+                report(format!("{} \\{}", t!("apply_patch.hive_log_skipped"), &operation.path));
+                record(OperationOutcome::Skipped);
                 continue;
             }
 
+            let mut outcome = OperationOutcome::Applied;
             match operation.action {
                 // 新增操作
                 Action::Add => {
-                    let source_path = patch_mount.join(&operation.path);
-                    let target_path = base_mount.join(&operation.path);
+                    let source_path = long_path(&patch_mount.join(operation.payload_path()));
+                    let target_path = long_path(&base_mount.join(&mapped_path));
 
                     if source_path.is_dir() {
-                        // 新建目录
+                        // 新建目录（名称仅大小写不同的已有目录按补丁中的大小写重命名）
                         fs::create_dir_all(&target_path)?;
+                        if operation.case_rename
+                            && let Err(e) = restore_name_case(&target_path)
+                        {
+                            write_console(
+                                ConsoleType::Warning,
+                                &format!("Restore file name case Failed: \\{} ({})", &operation.path, e),
+                            );
+                        }
+                        record(OperationOutcome::Applied);
                         continue;
                     }
 
-                    sub_pb.set_message(format!("{} \\{}", t!("create_patch.Add"), &operation.path));
-                    if !is_tty() {
-                        write_console(
-                            ConsoleType::Info,
-                            &format!("{} \\{}", t!("create_patch.Add"), &operation.path),
-                        );
-                    }
+                    report(format!("{} \\{}", t!("create_patch.Add"), &operation.path));
                     // 确保目标目录存在
                     if let Some(parent) = target_path.parent() {
                         fs::create_dir_all(parent)
@@ -1408,22 +4819,27 @@ impl WimPatch {
                                 ConsoleType::Warning,
                                 &format!("Patch file source file not exist: \\{}", &operation.path),
                             );
+                            record(OperationOutcome::Failed);
                             continue;
                         }
                         return Err(anyhow!("Patch file source file not exist: \\{}", &operation.path));
                     }
                     // 复制文件
-                    if let Err(e) = fs::copy(&source_path, &target_path) {
+                    if let Err(e) = Self::retry_file_operation(io_retries, &operation.path, || {
+                        copy_file(&source_path, &target_path, operation.sparse)
+                    }) {
                         if force {
                             write_console(
                                 ConsoleType::Warning,
                                 &format!(
-                                    "Copy file Failed: {} -> {} ({})",
+                                    "{}: Copy file Failed: {} -> {} ({})",
+                                    t!("apply_patch.skipped_force"),
                                     source_path.display(),
                                     target_path.display(),
                                     e
                                 ),
                             );
+                            record(OperationOutcome::Failed);
                             continue;
                         }
                         return Err(anyhow!(format!(
@@ -1433,26 +4849,27 @@ impl WimPatch {
                             e
                         )));
                     }
-                    sub_pb.inc(1);
                 }
                 // 删除操作
                 Action::Delete => {
-                    let target_path = base_mount.join(&operation.path);
-                    sub_pb.set_message(format!("{} \\{}", t!("create_patch.Delete"), &operation.path));
-                    if !is_tty() {
-                        write_console(
-                            ConsoleType::Info,
-                            &format!("{} \\{}", t!("create_patch.Delete"), &operation.path),
-                        );
-                    }
+                    let target_path = long_path(&base_mount.join(&mapped_path));
+                    report(format!("{} \\{}", t!("create_patch.Delete"), &operation.path));
                     if target_path.exists() {
                         if target_path.is_dir() {
-                            if let Err(e) = fs::remove_dir_all(&target_path) {
+                            if let Err(e) = Self::retry_file_operation(io_retries, &operation.path, || {
+                                fs::remove_dir_all(&target_path)
+                            }) {
                                 if force {
                                     write_console(
                                         ConsoleType::Warning,
-                                        &format!("Delete directory Failed: {} -> {}", target_path.display(), e),
+                                        &format!(
+                                            "{}: Delete directory Failed: {} -> {}",
+                                            t!("apply_patch.skipped_force"),
+                                            target_path.display(),
+                                            e
+                                        ),
                                     );
+                                    record(OperationOutcome::Failed);
                                     continue;
                                 }
                                 return Err(anyhow!(format!(
@@ -1462,12 +4879,20 @@ impl WimPatch {
                                 )));
                             }
                         } else {
-                            if let Err(e) = fs::remove_file(&target_path) {
+                            if let Err(e) = Self::retry_file_operation(io_retries, &operation.path, || {
+                                fs::remove_file(&target_path)
+                            }) {
                                 if force {
                                     write_console(
                                         ConsoleType::Warning,
-                                        &format!("Delete file Failed: {} -> {}", target_path.display(), e),
+                                        &format!(
+                                            "{}: Delete file Failed: {} -> {}",
+                                            t!("apply_patch.skipped_force"),
+                                            target_path.display(),
+                                            e
+                                        ),
                                     );
+                                    record(OperationOutcome::Failed);
                                     continue;
                                 }
                                 return Err(anyhow!(format!(
@@ -1478,36 +4903,70 @@ impl WimPatch {
                             }
                         }
                     }
-                    sub_pb.inc(1);
+                    // 删除后不再有重建的配置单元
+                    if let Some(hives) = self
+                        .rebuilt_hives
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .get_mut(&task)
+                    {
+                        hives.remove(&mapped_path.to_lowercase());
+                    }
                 }
                 // 修改操作
                 Action::Modify => {
-                    let source_path = patch_mount.join(&operation.path);
-                    let target_path = base_mount.join(&operation.path);
-
-                    sub_pb.set_message(format!("{} \\{}", t!("create_patch.Modify"), &operation.path));
-                    if !is_tty() {
-                        write_console(
-                            ConsoleType::Info,
-                            &format!("{} \\{}", t!("create_patch.Modify"), &operation.path),
-                        );
-                    }
+                    let source_path = long_path(&patch_mount.join(operation.payload_path()));
+                    let target_path = long_path(&base_mount.join(&mapped_path));
+
+                    report(format!("{} \\{}", t!("create_patch.Modify"), &operation.path));
+
+                    // 之前的补丁按结构化差异重建过的配置单元与更新镜像中的文件字节不同，不能在其上应用字节差异
+                    let hive_key = mapped_path.to_lowercase();
+                    let rebuilt_hive = self
+                        .rebuilt_hives
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .get(&task)
+                        .is_some_and(|hives| hives.contains_key(&hive_key));
+                    let forget_rebuilt_hive = || {
+                        if let Some(hives) = self
+                            .rebuilt_hives
+                            .lock()
+                            .unwrap_or_else(|e| e.into_inner())
+                            .get_mut(&task)
+                        {
+                            hives.remove(&hive_key);
+                        }
+                    };
 
                     if let Some(storage) = &operation.storage {
                         match storage.to_lowercase().as_str() {
+                            // 目标文件已与补丁中的文件一致（如重新应用部分应用过的补丁），跳过复制
+                            "full"
+                                if operation.sha256.as_deref().is_some_and(|sha256| {
+                                    file_matches_sha256(&target_path, operation.size, sha256)
+                                }) =>
+                            {
+                                outcome = OperationOutcome::AlreadyApplied;
+                                already_applied += 1;
+                            }
                             "full" => {
                                 // 复制文件
-                                if let Err(e) = fs::copy(&source_path, &target_path) {
+                                if let Err(e) = Self::retry_file_operation(io_retries, &operation.path, || {
+                                    copy_file(&source_path, &target_path, operation.sparse)
+                                }) {
                                     if force {
                                         write_console(
                                             ConsoleType::Warning,
                                             &format!(
-                                                "Copy file Failed: {} -> {} ({})",
+                                                "{}: Copy file Failed: {} -> {} ({})",
+                                                t!("apply_patch.skipped_force"),
                                                 source_path.display(),
                                                 target_path.display(),
                                                 e
                                             ),
                                         );
+                                        record(OperationOutcome::Failed);
                                         continue;
                                     }
                                     return Err(anyhow!(format!(
@@ -1517,45 +4976,95 @@ impl WimPatch {
                                         e
                                     )));
                                 }
+                                forget_rebuilt_hive();
+                            }
+                            // 目标文件已与更新后的文件一致（如重新应用部分应用过的补丁），无需应用差异
+                            "zstd" | "bsdiff"
+                                if operation.base_sha256.is_some()
+                                    && operation.sha256.as_deref().is_some_and(|sha256| {
+                                        file_matches_sha256(&target_path, operation.size, sha256)
+                                    }) =>
+                            {
+                                outcome = OperationOutcome::AlreadyApplied;
+                                already_applied += 1;
+                            }
+                            // 字节差异只能应用在创建补丁时的基础文件上，之前的补丁重建过的配置单元或已被修改的文件
+                            // 与之不同，应用后的内容将被损坏
+                            "zstd" | "bsdiff"
+                                if rebuilt_hive
+                                    || operation.base_sha256.as_deref().is_some_and(|base_sha256| {
+                                        !file_matches_sha256(&target_path, None, base_sha256)
+                                    }) =>
+                            {
+                                // 优先使用嵌入的完整文件
+                                if Self::apply_full_fallback(patch_mount, &target_path, operation) {
+                                    forget_rebuilt_hive();
+                                    record(OperationOutcome::Applied);
+                                    continue;
+                                }
+                                let message = if rebuilt_hive {
+                                    t!("apply_patch.rebuilt_hive_delta", path = &mapped_path)
+                                } else {
+                                    t!("apply_patch.base_mismatch", path = &mapped_path)
+                                };
+                                if force {
+                                    self.progress.message(
+                                        task,
+                                        &format!(" {}      {}", style(t!("console.error")).red(), message),
+                                    );
+                                    record(OperationOutcome::Failed);
+                                    continue;
+                                }
+                                return Err(anyhow!("{}", message));
                             }
                             "zstd" => {
                                 // 应用zstdiff差异文件
-                                let patch_path = patch_mount.join(format!("{}.diff ", &operation.path));
+                                let patch_path = long_path(&patch_mount.join(format!("{}.diff", &operation.path)));
                                 if patch_path.exists() {
-                                    if let Err(e) = ZstdDiff::file_patch(&target_path, &patch_path, &target_path) {
+                                    if let Err(e) = ZstdDiff::file_patch(
+                                        &target_path,
+                                        &patch_path,
+                                        &target_path,
+                                        operation.dict_window,
+                                    ) {
                                         // 应用zstdiff差异文件失败
+                                        if Self::apply_full_fallback(patch_mount, &target_path, operation) {
+                                            record(OperationOutcome::Applied);
+                                            continue;
+                                        }
                                         if force {
-                                            sub_pb.println(format!(
-                                                " {}      {}: {} ({})",
-                                                style(t!("console.error")).red(),
-                                                t!("apply_patch.diff_failed"),
-                                                target_path
-                                                    .display()
-                                                    .to_string()
-                                                    .strip_prefix(base_mount.display().to_string().as_str())
-                                                    .unwrap(),
-                                                e
-                                            ));
+                                            self.progress.message(
+                                                task,
+                                                &format!(
+                                                    " {}      {}: {} ({})",
+                                                    style(t!("console.error")).red(),
+                                                    t!("apply_patch.diff_failed"),
+                                                    format!("\\{}", mapped_path),
+                                                    e
+                                                ),
+                                            );
+                                            record(OperationOutcome::Failed);
                                             continue;
                                         }
                                         return Err(anyhow!(format!(
                                             "{}: {} ({})",
                                             t!("apply_patch.diff_failed"),
-                                            target_path
-                                                .display()
-                                                .to_string()
-                                                .strip_prefix(base_mount.display().to_string().as_str())
-                                                .unwrap(),
+                                            format!("\\{}", mapped_path),
                                             e
                                         )));
                                     }
                                 } else {
                                     // zstdiff差异文件不存在
+                                    if Self::apply_full_fallback(patch_mount, &target_path, operation) {
+                                        record(OperationOutcome::Applied);
+                                        continue;
+                                    }
                                     if force {
                                         write_console(
                                             ConsoleType::Warning,
                                             &format!("Patch file zstdiff patch file not exist: \\{}", &operation.path),
                                         );
+                                        record(OperationOutcome::Failed);
                                         continue;
                                     }
                                     return Err(anyhow!(format!(
@@ -1564,44 +5073,113 @@ impl WimPatch {
                                     )));
                                 }
                             }
+                            "hive" => {
+                                // 按结构化差异重建注册表配置单元
+                                let patch_path = long_path(&patch_mount.join(format!("{}.hive", &operation.path)));
+                                if patch_path.exists() {
+                                    if let Err(e) = HiveDiff::file_patch(&target_path, &patch_path, &target_path) {
+                                        // 重建配置单元失败
+                                        if Self::apply_full_fallback(patch_mount, &target_path, operation) {
+                                            forget_rebuilt_hive();
+                                            record(OperationOutcome::Applied);
+                                            continue;
+                                        }
+                                        if force {
+                                            self.progress.message(
+                                                task,
+                                                &format!(
+                                                    " {}      {}: {} ({:#})",
+                                                    style(t!("console.error")).red(),
+                                                    t!("apply_patch.hive_failed"),
+                                                    format!("\\{}", mapped_path),
+                                                    e
+                                                ),
+                                            );
+                                            record(OperationOutcome::Failed);
+                                            continue;
+                                        }
+                                        return Err(anyhow!(format!(
+                                            "{}: {} ({:#})",
+                                            t!("apply_patch.hive_failed"),
+                                            format!("\\{}", mapped_path),
+                                            e
+                                        )));
+                                    }
+                                    // 清空原有的事务日志，避免加载时重放到重建的配置单元上
+                                    if let Err(e) = HiveDiff::reset_logs(&target_path) {
+                                        write_console(ConsoleType::Warning, &format!("{:#}", e));
+                                    }
+                                    let size = target_path.metadata().map_or(0, |metadata| metadata.len());
+                                    self.rebuilt_hives
+                                        .lock()
+                                        .unwrap_or_else(|e| e.into_inner())
+                                        .entry(task)
+                                        .or_default()
+                                        .insert(hive_key.clone(), size as i64 - operation.size.unwrap_or(size) as i64);
+                                } else {
+                                    // 结构化差异文件不存在
+                                    if Self::apply_full_fallback(patch_mount, &target_path, operation) {
+                                        forget_rebuilt_hive();
+                                        record(OperationOutcome::Applied);
+                                        continue;
+                                    }
+                                    if force {
+                                        write_console(
+                                            ConsoleType::Warning,
+                                            &format!("Patch file hive diff file not exist: \\{}", &operation.path),
+                                        );
+                                        record(OperationOutcome::Failed);
+                                        continue;
+                                    }
+                                    return Err(anyhow!(format!(
+                                        "Patch file hive diff file not exist: \\{}",
+                                        &operation.path
+                                    )));
+                                }
+                            }
                             "bsdiff" => {
                                 // 应用bsdiff差异文件
-                                let patch_path = patch_mount.join(format!("{}.diff ", &operation.path));
+                                let patch_path = long_path(&patch_mount.join(format!("{}.diff", &operation.path)));
                                 if patch_path.exists() {
                                     if let Err(e) = BsDiff::file_patch(&target_path, &patch_path, &target_path) {
                                         // 应用bsdiff差异文件失败
+                                        if Self::apply_full_fallback(patch_mount, &target_path, operation) {
+                                            record(OperationOutcome::Applied);
+                                            continue;
+                                        }
                                         if force {
-                                            sub_pb.println(format!(
-                                                " {}      {}: {} ({})",
-                                                style(t!("console.error")).red(),
-                                                t!("apply_patch.bsdiff_failed"),
-                                                target_path
-                                                    .display()
-                                                    .to_string()
-                                                    .strip_prefix(base_mount.display().to_string().as_str())
-                                                    .unwrap(),
-                                                e
-                                            ));
+                                            self.progress.message(
+                                                task,
+                                                &format!(
+                                                    " {}      {}: {} ({})",
+                                                    style(t!("console.error")).red(),
+                                                    t!("apply_patch.bsdiff_failed"),
+                                                    format!("\\{}", mapped_path),
+                                                    e
+                                                ),
+                                            );
+                                            record(OperationOutcome::Failed);
                                             continue;
                                         }
                                         return Err(anyhow!(format!(
                                             "{}: {} ({})",
                                             t!("apply_patch.bsdiff_failed"),
-                                            target_path
-                                                .display()
-                                                .to_string()
-                                                .strip_prefix(base_mount.display().to_string().as_str())
-                                                .unwrap(),
+                                            format!("\\{}", mapped_path),
                                             e
                                         )));
                                     }
                                 } else {
                                     // bsdiff差异文件不存在
+                                    if Self::apply_full_fallback(patch_mount, &target_path, operation) {
+                                        record(OperationOutcome::Applied);
+                                        continue;
+                                    }
                                     if force {
                                         write_console(
                                             ConsoleType::Warning,
                                             &format!("Patch file bsdiff patch file not exist: \\{}", &operation.path),
                                         );
+                                        record(OperationOutcome::Failed);
                                         continue;
                                     }
                                     return Err(anyhow!(format!(
@@ -1610,17 +5188,234 @@ impl WimPatch {
                                     )));
                                 }
                             }
-                            _ => {}
+                            _ => {
+                                // 未知的存储类型
+                                if force {
+                                    write_console(
+                                        ConsoleType::Warning,
+                                        &format!("Unknown storage type '{}': \\{}", storage, &operation.path),
+                                    );
+                                    record(OperationOutcome::Failed);
+                                    continue;
+                                }
+                                return Err(anyhow!("Unknown storage type '{}': \\{}", storage, &operation.path));
+                            }
+                        }
+
+                        // 差异还原的文件是完整写入的，恢复稀疏文件的稀疏性
+                        if operation.sparse
+                            && !storage.eq_ignore_ascii_case("full")
+                            && let Err(e) = make_sparse(&target_path)
+                        {
+                            write_console(
+                                ConsoleType::Warning,
+                                &format!("Set sparse file Failed: \\{} ({})", &operation.path, e),
+                            );
+                        }
+
+                        // 忽略大小写创建的补丁中，仅大小写不同的文件按目标镜像中的大小写重命名
+                        if operation.case_rename
+                            && let Err(e) = restore_name_case(&target_path)
+                        {
+                            write_console(
+                                ConsoleType::Warning,
+                                &format!("Restore file name case Failed: \\{} ({})", &operation.path, e),
+                            );
+                        }
+                    } else {
+                        // 修改操作缺少存储类型，无法还原文件
+                        if force {
+                            write_console(
+                                ConsoleType::Warning,
+                                &format!("Modify operation has no storage type: \\{}", &operation.path),
+                            );
+                            record(OperationOutcome::Failed);
+                            continue;
                         }
+                        return Err(anyhow!("Modify operation has no storage type: \\{}", &operation.path));
                     }
-                    sub_pb.inc(1);
                 }
             }
+
+            // 重新创建补丁中记录的备用数据流
+            if !operation.streams.is_empty()
+                && let Err(e) =
+                    Self::restore_streams(patch_mount, &long_path(&base_mount.join(&mapped_path)), operation)
+            {
+                if force {
+                    write_console(
+                        ConsoleType::Warning,
+                        &format!("{}: {:#}", t!("apply_patch.skipped_force"), e),
+                    );
+                    record(OperationOutcome::Failed);
+                    continue;
+                }
+                return Err(e);
+            }
+            record(outcome);
+        }
+
+        // 文件操作处理完成
+        self.progress.operations_finished(task);
+        if already_applied > 0 {
+            self.progress.message(
+                task,
+                &t!(
+                    "apply_patch.already_applied_files",
+                    count = already_applied,
+                    index = task
+                ),
+            );
+        }
+
+        Ok(())
+    }
+
+    /// 执行文件操作，文件被暂时占用时重试，并输出重试结果
+    ///
+    /// # 参数
+    ///
+    /// - `retries` - 最大重试次数
+    /// - `path` - 操作的相对路径（用于输出）
+    /// - `operation` - 文件操作
+    ///
+    /// # 返回值
+    ///
+    /// - `Ok(T)` - 操作成功（可能经过重试）
+    /// - `Err(anyhow::Error)` - 操作失败，重试过时错误信息中附带重试次数
+    pub(crate) fn retry_file_operation<T>(
+        retries: u32,
+        path: &str,
+        operation: impl FnMut() -> std::io::Result<T>,
+    ) -> Result<T> {
+        let (result, retried) = retry_io(retries, operation);
+        match result {
+            Ok(value) => {
+                if retried > 0 {
+                    write_console(
+                        ConsoleType::Info,
+                        &format!(
+                            "{}: \\{}",
+                            t!("apply_patch.io_retry_succeeded", retries = retried),
+                            path
+                        ),
+                    );
+                }
+                Ok(value)
+            }
+            Err(e) if retried > 0 => Err(anyhow!(
+                "{} ({})",
+                e,
+                t!("apply_patch.io_retry_failed", retries = retried)
+            )),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// 获取备用数据流在补丁中的存储路径
+    ///
+    /// # 参数
+    ///
+    /// - `path` - 文件在补丁中的相对路径
+    /// - `index` - 数据流在操作中的序号
+    pub(crate) fn stream_payload_path(path: &str, index: usize) -> String {
+        format!("{}{}{}", path, STREAM_PAYLOAD_SUFFIX, index)
+    }
+
+    /// 将文件的备用数据流暂存到补丁目录，并统计数据流大小
+    ///
+    /// # 参数
+    ///
+    /// - `file` - 包含数据流的文件
+    /// - `patch_path` - 补丁目录
+    /// - `path` - 文件在补丁中的相对路径
+    /// - `streams` - 需要暂存的数据流
+    /// - `dry_run` - 仅统计大小，不写入文件
+    /// - `estimate` - 按存储类型统计的（文件数, 字节数）
+    ///
+    /// # 返回值
+    ///
+    /// - `Ok(())` - 暂存成功
+    /// - `Err` - 读取数据流或写入补丁目录失败
+    fn stage_streams(
+        file: &Path,
+        patch_path: &Path,
+        path: &str,
+        streams: &[StreamInfo],
+        dry_run: bool,
+        estimate: &mut BTreeMap<String, (usize, u64)>,
+    ) -> Result<()> {
+        for (index, stream) in streams.iter().enumerate() {
+            let entry = estimate.entry("stream".to_string()).or_default();
+            entry.0 += 1;
+            entry.1 += stream.size;
+            if dry_run {
+                continue;
+            }
+
+            let target = long_path(&patch_path.join(Self::stream_payload_path(path, index)));
+            let result = (|| {
+                if let Some(parent) = target.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                let mut reader = fs::File::open(stream_path(file, &stream.name))?;
+                let mut writer = fs::File::create(&target)?;
+                std::io::copy(&mut reader, &mut writer)
+            })();
+            result.with_context(|| format!("Copy stream failed: {}:{}", file.display(), stream.name))?;
         }
+        Ok(())
+    }
 
+    /// 在目标文件上重新创建补丁中记录的备用数据流
+    ///
+    /// # 参数
+    ///
+    /// - `patch_mount` - 补丁镜像挂载路径
+    /// - `target_path` - 目标文件路径
+    /// - `operation` - 记录了数据流的新增或修改操作
+    fn restore_streams(patch_mount: &Path, target_path: &Path, operation: &Operation) -> Result<()> {
+        for (index, stream) in operation.streams.iter().enumerate() {
+            let source = long_path(&patch_mount.join(Self::stream_payload_path(&operation.path, index)));
+            let mut reader =
+                fs::File::open(&source).with_context(|| format!("Open stream data Failed: {}", source.display()))?;
+            let mut writer = fs::File::create(stream_path(target_path, &stream.name))
+                .with_context(|| format!("Create stream Failed: \\{}:{}", &operation.path, stream.name))?;
+            std::io::copy(&mut reader, &mut writer)
+                .with_context(|| format!("Write stream Failed: \\{}:{}", &operation.path, stream.name))?;
+        }
         Ok(())
     }
 
+    /// 差异补丁应用失败时，使用补丁中嵌入的完整文件替换目标文件
+    ///
+    /// # 参数
+    ///
+    /// - `patch_mount` - 补丁镜像挂载路径
+    /// - `target_path` - 需要还原的目标文件路径
+    /// - `operation` - 修改操作
+    ///
+    /// # 返回值
+    ///
+    /// - `true` - 已使用完整文件替换
+    /// - `false` - 补丁中没有完整文件或替换失败
+    fn apply_full_fallback(patch_mount: &Path, target_path: &Path, operation: &Operation) -> bool {
+        if !operation.full_fallback {
+            return false;
+        }
+        // 内容相同的完整文件只暂存一次，引用其他补丁数据时从被引用的路径还原
+        match fs::copy(long_path(&patch_mount.join(operation.payload_path())), target_path) {
+            Ok(_) => {
+                write_console(
+                    ConsoleType::Warning,
+                    &format!("{}: \\{}", t!("apply_patch.full_fallback"), &operation.path),
+                );
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
     /// 根据传入的基础 WIM GUID 和卷索引构建补丁链。
     ///
     /// # 参数
@@ -1628,38 +5423,75 @@ impl WimPatch {
     /// - `base_guid` - 外部传入的基础 WIM GUID
     /// - `base_image_info_list` - 基础镜像信息列表
     /// - `patch_info_list` - 补丁包信息列表
-    /// - `force_mode` - 是否强制应用补丁 (对应 --force 参数)
+    /// - `options` - 应用补丁选项，使用其中的以下选项：
+    ///   - `force` - 是否强制应用补丁 (对应 --force 参数)
+    ///   - `up_to_version` - 截止版本，补丁链在第一个版本高于该值的补丁处停止 (对应 --up-to-version 参数)
+    ///   - `from_version` - 起始版本，跳过版本不高于该值的补丁，基础镜像已是该版本的更新结果 (对应 --from-version 参数)
+    ///   - `max_chain_length` - 补丁链的最大长度，超过时报错 (对应 --max-chain-length 参数)
+    ///   - `match_mode` - GUID 或统计信息不一致时的匹配模式 (对应 --match-mode 参数)
     ///
     /// # 返回值
     ///
-    /// - `Vec<(ImageInfo, Vec<(u32, PatchManifest)>)>` - 匹配的基础镜像和补丁包列表
-    fn match_patch(
+    /// - `Vec<(ImageInfo, Vec<(u32, PatchManifest)>, MatchConfidence)>` - 匹配的基础镜像、补丁包列表和补丁链中最弱的匹配程度
+    pub(crate) fn match_patch(
         &self,
         base_guid: &str,
         base_image_info_list: &[ImageInfo],
         patch_info_list: &[(u32, PatchManifest)],
-        force_mode: bool,
-    ) -> Result<Vec<(ImageInfo, Vec<(u32, PatchManifest)>)>> {
+        options: &ApplyOptions,
+    ) -> Result<Vec<(ImageInfo, Vec<(u32, PatchManifest)>, MatchConfidence)>> {
+        let force_mode = options.force;
+        let up_to_version = options.up_to_version.as_ref();
+        let from_version = options.from_version.as_ref();
+
         // 返回的 ImageInfo 是应用所有补丁后的最终目标卷信息
-        let mut result: Vec<(ImageInfo, Vec<(u32, PatchManifest)>)> = Vec::new();
+        let mut result: Vec<(ImageInfo, Vec<(u32, PatchManifest)>, MatchConfidence)> = Vec::new();
 
         // 用于记录已经被添加到某个链条中的补丁索引，避免重复使用
         let mut all_applied_indices: HashSet<u32> = HashSet::new();
 
+        let patch_version =
+            |patch: &PatchManifest| Version::parse(&patch.patch_version).unwrap_or(Version::new(0, 0, 0));
+
+        // 指定起始版本时，版本不高于起始版本的补丁视为已应用
+        let skipped: Vec<&(u32, PatchManifest)> = match from_version {
+            Some(from_version) => patch_info_list
+                .iter()
+                .filter(|(_, patch)| patch_version(patch) <= *from_version)
+                .collect(),
+            None => Vec::new(),
+        };
+        all_applied_indices.extend(skipped.iter().map(|(index, _)| *index));
+
+        // 已经应用过起始版本的基础镜像是之前应用补丁导出的新镜像，GUID 已改变，记录各补丁链实际使用的基线 GUID
+        let mut chain_guids: Vec<String> = Vec::new();
+
         // 遍历所有可能的起始基础镜像卷
         for initial_base_info in base_image_info_list.iter() {
             let mut current_base_info = initial_base_info.clone();
             let mut patch_chain: Vec<(u32, PatchManifest)> = Vec::new();
+            let mut chain_guid = base_guid.to_string();
+            // 补丁链经过的镜像 GUID（基线与各补丁的更新镜像），用于检测补丁链回到之前的镜像。
+            // 只修改文件内容的补丁不改变统计信息，因此不按统计信息判断
+            let mut visited_guids: Vec<String> = vec![chain_guid.clone()];
+            let mut chain_confidence = MatchConfidence::Exact;
 
             // 循环构建补丁链
             loop {
+                // 指定起始版本时，链条中第一个补丁只按卷索引匹配
+                let relaxed = from_version.is_some() && patch_chain.is_empty();
+                // 匹配模式接受仅统计信息一致时，链条中第一个补丁也可按统计信息匹配
+                let stats_only = patch_chain.is_empty() && MatchConfidence::StatsOnly.allowed_by(options.match_mode);
+
                 // 查找所有以当前身份为基线的未应用的候选补丁
                 let mut candidates: Vec<(u32, PatchManifest)> = patch_info_list
                     .iter()
                     .filter(|(index, patch)| {
                         // 身份匹配：补丁期望的基线 WIM GUID 和 Index 必须与当前的卷身份匹配
                         current_base_info.index == patch.base_image_info.index
-                            && base_guid == patch.base_image_guid
+                            && (relaxed
+                                || is_same_guid(&chain_guid, &patch.base_image_guid)
+                                || (stats_only && current_base_info == patch.base_image_info))
                             && !all_applied_indices.contains(index)
                     })
                     .map(|(index, patch)| (*index, patch.clone()))
@@ -1678,11 +5510,102 @@ impl WimPatch {
                     version_a.cmp(&version_b)
                 });
 
+                // 超过截止版本时结束链条
+                if let Some(up_to_version) = up_to_version
+                    && Version::parse(&candidates[0].1.patch_version).unwrap_or_else(|_| Version::new(0, 0, 0))
+                        > *up_to_version
+                {
+                    break;
+                }
+
                 // 选择并校验
                 let (index, next_patch) = candidates.remove(0);
 
+                // 限制补丁链长度，防止异常的补丁包构建出过长的补丁链
+                if options.max_chain_length > 0 && patch_chain.len() >= options.max_chain_length {
+                    return Err(anyhow!(
+                        "{}",
+                        t!(
+                            "apply_patch.chain_too_long",
+                            index = initial_base_info.index,
+                            max = options.max_chain_length
+                        )
+                    ));
+                }
+
+                let stats_match = current_base_info == next_patch.base_image_info;
+                let mut confidence = match (is_same_guid(&chain_guid, &next_patch.base_image_guid), stats_match) {
+                    (true, true) => MatchConfidence::Exact,
+                    (true, false) => MatchConfidence::GuidOnly,
+                    (false, true) => MatchConfidence::StatsOnly,
+                    // 仅指定起始版本时可能出现，统计信息不一致时仅警告
+                    (false, false) => MatchConfidence::Forced,
+                };
+
+                if relaxed {
+                    // 起始版本之前的补丁仍在补丁包中时，校验补丁链是否连续
+                    if let Some((_, previous)) = skipped
+                        .iter()
+                        .filter(|(_, patch)| {
+                            is_same_guid(&patch.base_image_guid, &next_patch.base_image_guid)
+                                && patch.target_image_info.index == next_patch.base_image_info.index
+                        })
+                        .max_by_key(|(_, patch)| patch_version(patch))
+                        && previous.target_image_info != next_patch.base_image_info
+                    {
+                        return Err(anyhow!(
+                            "{}",
+                            t!(
+                                "apply_patch.from_version_gap",
+                                previous = previous.patch_version,
+                                next = next_patch.patch_version
+                            )
+                        ));
+                    }
+                    chain_guid = next_patch.base_image_guid.clone();
+                }
+
                 // [核心校验] 在非强制模式下，检查当前基础卷的统计信息是否与补丁期望的基线一致
-                if current_base_info != next_patch.base_image_info {
+                // 指定起始版本时基础镜像已是中间状态，第一个补丁的统计信息不一致时仅警告
+                if !stats_match && relaxed {
+                    write_console(
+                        ConsoleType::Warning,
+                        &format!(
+                            "{}",
+                            t!(
+                                "apply_patch.from_version_relaxed",
+                                index = current_base_info.index,
+                                version = next_patch.patch_version
+                            )
+                        ),
+                    );
+                } else if !relaxed && confidence.allowed_by(options.match_mode) {
+                    // 匹配模式接受的不完全匹配，说明具体不一致的内容
+                    match confidence {
+                        MatchConfidence::GuidOnly => write_console(
+                            ConsoleType::Warning,
+                            &t!(
+                                "apply_patch.match_guid_only",
+                                index = current_base_info.index,
+                                version = next_patch.patch_version
+                            ),
+                        ),
+                        MatchConfidence::StatsOnly => {
+                            write_console(
+                                ConsoleType::Warning,
+                                &t!(
+                                    "apply_patch.match_stats_only",
+                                    index = current_base_info.index,
+                                    version = next_patch.patch_version,
+                                    guid = next_patch.base_image_guid
+                                ),
+                            );
+                            // 后续补丁以补丁记录的基线 GUID 继续构建补丁链
+                            chain_guid = next_patch.base_image_guid.clone();
+                        }
+                        _ => {}
+                    }
+                } else if !stats_match {
                     if !force_mode {
                         return Err(anyhow!(
                             "{}",
@@ -1696,93 +5619,787 @@ impl WimPatch {
                             t!("apply_patch.base_stat_not_match", index = current_base_info.index)
                         ),
                     );
+                    confidence = MatchConfidence::Forced;
+                }
+                chain_confidence = chain_confidence.max(confidence);
+
+                // 检测补丁将卷恢复到补丁链之前经过的镜像（补丁链存在环），未记录更新镜像 GUID 时无法判断
+                if !next_patch.target_image_guid.is_empty()
+                    && let Some(position) = visited_guids
+                        .iter()
+                        .position(|guid| is_same_guid(guid, &next_patch.target_image_guid))
+                {
+                    // 从该状态开始应用的补丁，补丁的更新镜像与基础镜像相同时为补丁本身
+                    let previous = patch_chain
+                        .get(position)
+                        .map_or(next_patch.patch_version.as_str(), |(_, patch)| {
+                            patch.patch_version.as_str()
+                        });
+                    let message = t!(
+                        "apply_patch.chain_cycle",
+                        version = next_patch.patch_version,
+                        patch_index = index,
+                        index = initial_base_info.index,
+                        previous = previous
+                    );
+                    if !force_mode {
+                        return Err(anyhow!("{}", message));
+                    }
+                    write_console(ConsoleType::Warning, &message);
                 }
 
                 // 更新链条状态
                 current_base_info = next_patch.target_image_info.clone();
+                visited_guids.push(next_patch.target_image_guid.clone());
                 patch_chain.push((index, next_patch));
                 all_applied_indices.insert(index);
             }
 
-            // 如果找到了补丁链，将结果加入
-            if !patch_chain.is_empty() {
-                result.push((current_base_info, patch_chain));
-            }
+            // 如果找到了补丁链，将结果加入
+            if !patch_chain.is_empty() {
+                chain_guids.push(chain_guid);
+                result.push((current_base_info, patch_chain, chain_confidence));
+            }
+        }
+
+        // 指定起始版本时，起始版本之后（截止版本之内）的补丁必须全部连入补丁链
+        if from_version.is_some() {
+            let mut missing: Vec<&str> = patch_info_list
+                .iter()
+                .filter(|(index, patch)| {
+                    !all_applied_indices.contains(index)
+                        && up_to_version.is_none_or(|up_to_version| patch_version(patch) <= *up_to_version)
+                        && chain_guids
+                            .iter()
+                            .any(|guid| is_same_guid(guid, &patch.base_image_guid))
+                })
+                .map(|(_, patch)| patch.patch_version.as_str())
+                .collect();
+            if !missing.is_empty() {
+                missing.sort();
+                return Err(anyhow!(
+                    "{}",
+                    t!("apply_patch.from_version_not_contiguous", versions = missing.join(", "))
+                ));
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// 合并多个补丁包
+    ///
+    /// WIM 文件只能使用一种压缩类型，指定 `keep_compression` 时输出补丁包使用输入补丁包中镜像最多的压缩类型，
+    /// 压缩类型相同的镜像导出时无需重新压缩，其他镜像仍会转换为该压缩类型
+    ///
+    /// # 参数
+    ///
+    /// * `patches` - 补丁包文件路径列表
+    /// * `out` - 输出合并后的补丁包文件路径
+    /// * `options` - 合并补丁选项
+    ///
+    /// # 返回值
+    ///
+    /// * `Ok(())` - 合并成功
+    /// * `Err` - 发生错误
+    pub fn merge_patches(&self, patches: &[PathBuf], out: &Path, options: &MergeOptions) -> Result<()> {
+        // 统计共享补丁数据时需要挂载镜像，挂载镜像需要管理员权限
+        if options.report_shared {
+            Self::check_elevated()?;
+        }
+
+        // 写入输出文件前检测冲突补丁，避免合并后多个补丁都匹配同一基础镜像，无效的输入补丁包立即中止合并
+        let mut manifests = Vec::new();
+        for patch_path in patches {
+            for (_, manifest) in self.verify_merge_input(patch_path)? {
+                manifests.push(manifest);
+            }
+        }
+        let conflicts = find_merge_conflicts(&manifests);
+        for conflict in &conflicts {
+            write_console(
+                ConsoleType::Warning,
+                &t!(
+                    "merge_patch.conflict",
+                    guid = conflict.base_guid,
+                    index = conflict.base_index,
+                    version = conflict.version,
+                    ids = conflict.ids.join(", ")
+                ),
+            );
+        }
+        if options.fail_on_conflict && !conflicts.is_empty() {
+            return Err(anyhow!(t!("merge_patch.conflict_failed", count = conflicts.len())));
+        }
+
+        let compression_type = if options.keep_compression {
+            self.merge_compression_type(patches)?
+        } else {
+            wim_compression_type(options.compress)
+        };
+
+        // 先写入临时文件，合并失败时不留下不完整的输出文件
+        Self::with_atomic_output(out, |output| match &options.flatten_base {
+            Some(base) => self.flatten_merged(patches, base, output, compression_type, options),
+            None => self.export_merged(patches, output, compression_type, options),
+        })?;
+
+        // 统计跨补丁的相同补丁数据
+        if options.report_shared {
+            let (files, bytes) = self.find_shared_payloads(out)?;
+            write_console(
+                ConsoleType::Info,
+                &t!("merge_patch.shared_report", files = files, saved = format_bytes(bytes)),
+            );
+        }
+        Ok(())
+    }
+
+    /// 将补丁链展平为单个补丁
+    ///
+    /// 依次应用所有输入补丁得到最终镜像，再比较基础镜像与最终镜像生成一个补丁，
+    /// 补丁的基础镜像 GUID 与统计信息即为补丁链起点的基础镜像，版本、名称、作者与描述取自输入中版本最高的补丁
+    ///
+    /// # 参数
+    ///
+    /// * `patches` - 补丁包文件路径列表
+    /// * `base` - 补丁链起点的基础镜像路径
+    /// * `out` - 展平后的补丁包文件路径
+    /// * `compression_type` - 合并输入补丁包时使用的压缩类型
+    /// * `options` - 合并补丁选项
+    ///
+    /// # 返回值
+    ///
+    /// * `Ok(())` - 展平成功
+    /// * `Err` - 合并、应用补丁链或创建补丁失败
+    fn flatten_merged(
+        &self,
+        patches: &[PathBuf],
+        base: &Path,
+        out: &Path,
+        compression_type: u32,
+        options: &MergeOptions,
+    ) -> Result<()> {
+        // 挂载镜像需要管理员权限
+        Self::check_elevated()?;
+
+        // 多个输入补丁包先合并为一个，以便按补丁链依次应用
+        let merged = get_tmp_path(&get_temp_path(), "flatten-", ".wim");
+        let chain = match patches {
+            [patch] => patch.clone(),
+            _ => {
+                self.export_merged(patches, &merged, compression_type, options)?;
+                merged.clone()
+            }
+        };
+        let final_image = get_tmp_path(&get_temp_path(), "flatten-", ".wim");
+        let result = self.get_manifests(&chain).and_then(|manifests| {
+            let (_, latest) = manifests
+                .iter()
+                .filter_map(|(_, manifest)| Version::parse(&manifest.patch_version).ok().map(|v| (v, manifest)))
+                .max_by(|(left, _), (right, _)| left.cmp(right))
+                .ok_or_else(|| anyhow!("No patch with a valid version found"))?;
+
+            // 应用补丁链得到最终镜像（不压缩，仅用于比较）
+            let apply_options = ApplyOptions {
+                target_compress: Compress::None,
+                ..Default::default()
+            };
+            self.apply_patch(base, options.flatten_base_index, &chain, &final_image, &apply_options)?;
+
+            // 比较补丁链起点与最终镜像，镜像索引保持不变
+            write_console(
+                ConsoleType::Info,
+                &t!(
+                    "merge_patch.flatten",
+                    count = manifests.len(),
+                    version = latest.patch_version
+                ),
+            );
+            let mut create_options = CreateOptions {
+                version: latest.patch_version.clone(),
+                author: latest.author.clone(),
+                name: latest.name.clone(),
+                description: latest.description.clone(),
+                storage: options
+                    .flatten_storage
+                    .clone()
+                    .unwrap_or_else(|| dominant_storage(&manifests)),
+                compress: options.compress,
+                boot_index: options.boot_index,
+                ..Default::default()
+            };
+            if let Some(preset) = &options.flatten_preset {
+                create_options.preset = preset.clone();
+            }
+            if let Some(manifest_format) = options.flatten_manifest_format {
+                create_options.manifest_format = manifest_format;
+            }
+            self.create_patch(
+                base,
+                options.flatten_base_index,
+                &final_image,
+                options.flatten_base_index,
+                out,
+                &create_options,
+            )
+        });
+        fs::remove_file(&merged).ok();
+        fs::remove_file(&final_image).ok();
+        result
+    }
+
+    /// 将所有输入补丁包的镜像导出到合并后的补丁包
+    ///
+    /// # 参数
+    ///
+    /// * `patches` - 补丁包文件路径列表
+    /// * `out` - 合并后的补丁包文件路径
+    /// * `compression_type` - 合并后补丁包的压缩类型
+    /// * `options` - 合并补丁选项
+    ///
+    /// # 返回值
+    ///
+    /// * `Ok(())` - 导出成功
+    /// * `Err` - 打开、导出或校验补丁包失败
+    fn export_merged(
+        &self,
+        patches: &[PathBuf],
+        out: &Path,
+        compression_type: u32,
+        options: &MergeOptions,
+    ) -> Result<()> {
+        let wimgapi = self.wimgapi()?;
+        let verify_flag = if options.verify { WIM_FLAG_VERIFY } else { 0 };
+        let merge_patch_handle = HandleGuard::new(
+            wimgapi,
+            wimgapi
+                .open_with_flags(out, WIM_GENERIC_WRITE, WIM_CREATE_ALWAYS, verify_flag, compression_type)
+                .with_context(|| "Open out patch error")?,
+        );
+
+        wimgapi
+            .set_temp_path(merge_patch_handle.handle(), &get_temp_path())
+            .with_context(|| "Set temp path error")?;
+
+        // 已导出的镜像数量，以及需要保留的可启动镜像索引
+        let mut exported_count = 0;
+        let mut preserved_boot_index = None;
+
+        // 遍历补丁包
+        for patch_path in patches {
+            write_console(
+                ConsoleType::Info,
+                &format!("{}: {}", t!("merge_patch.merge_patch"), patch_path.display()),
+            );
+
+            let patch_handle = HandleGuard::new(
+                wimgapi,
+                wimgapi
+                    .open_with_flags(
+                        patch_path,
+                        WIM_GENERIC_READ,
+                        WIM_OPEN_EXISTING,
+                        verify_flag,
+                        WIM_COMPRESS_NONE,
+                    )
+                    .with_context(|| "Open patch error")?,
+            );
+
+            wimgapi
+                .set_temp_path(patch_handle.handle(), &get_temp_path())
+                .with_context(|| "Set temp path error")?;
+
+            // 记录第一个带可启动标记的补丁包在合并后的可启动镜像索引
+            if preserved_boot_index.is_none() {
+                let attributes = wimgapi
+                    .get_attributes(patch_handle.handle())
+                    .with_context(|| "Get patch attributes error")?;
+                if attributes.boot_index != 0 {
+                    preserved_boot_index = Some(exported_count + attributes.boot_index);
+                }
+            }
+
+            let first_exported = exported_count + 1;
+            for index in 1..=wimgapi.get_image_count(patch_handle.handle()) {
+                let patch_image_handle = HandleGuard::new(
+                    wimgapi,
+                    wimgapi
+                        .load_image(patch_handle.handle(), index)
+                        .with_context(|| "Load patch image error")?,
+                );
+
+                wimgapi
+                    .export_image(patch_image_handle.handle(), merge_patch_handle.handle(), 0)
+                    .with_context(|| "Export patch image error")?;
+
+                patch_image_handle
+                    .close()
+                    .with_context(|| "Close patch image handle error")?;
+                exported_count += 1;
+            }
+
+            patch_handle.close().with_context(|| "Close patch handle error")?;
+
+            // 导出后解析合并后补丁包中刚导出的镜像的补丁清单
+            if options.verify_after_each {
+                for index in first_exported..=exported_count {
+                    self.read_patch_manifest(merge_patch_handle.handle(), index)
+                        .with_context(|| {
+                            t!(
+                                "merge_patch.verify_output_failed",
+                                index = index,
+                                path = patch_path.display()
+                            )
+                        })?;
+                }
+                write_console(
+                    ConsoleType::Info,
+                    &t!("merge_patch.verified", path = patch_path.display()),
+                );
+            }
+        }
+
+        // 设置可启动镜像
+        if let Some(boot_index) = options.boot_index.or(preserved_boot_index) {
+            self.set_boot_index(merge_patch_handle.handle(), boot_index)?;
+        }
+
+        merge_patch_handle.close().with_context(|| "Close out patch error")?;
+        Ok(())
+    }
+
+    /// 读取并解析补丁包中每个镜像的补丁清单，用于合并前校验输入补丁包
+    ///
+    /// # 参数
+    ///
+    /// * `patch_path` - 输入补丁包文件路径
+    ///
+    /// # 返回值
+    ///
+    /// * `Ok(Vec<(u32, PatchManifest)>)` - 镜像索引与解析后的补丁清单
+    /// * `Err` - 读取或解析失败，错误信息包含补丁包路径
+    fn verify_merge_input(&self, patch_path: &Path) -> Result<Vec<(u32, PatchManifest)>> {
+        self.get_manifests(patch_path)
+            .with_context(|| t!("merge_patch.verify_failed", path = patch_path.display()))
+    }
+
+    /// 读取输入补丁包的压缩类型，选择合并后补丁包的压缩类型
+    ///
+    /// 输入补丁包的压缩类型不同时，WIM 导出会将其他压缩类型的镜像重新压缩，输出警告后继续合并
+    ///
+    /// # 参数
+    ///
+    /// * `patches` - 补丁包文件路径列表
+    ///
+    /// # 返回值
+    ///
+    /// * `Ok(u32)` - 合并后补丁包的压缩类型
+    /// * `Err` - 读取补丁包属性失败
+    fn merge_compression_type(&self, patches: &[PathBuf]) -> Result<u32> {
+        let wimgapi = self.wimgapi()?;
+        let mut inputs = Vec::new();
+        for patch_path in patches {
+            let patch_handle = HandleGuard::new(
+                wimgapi,
+                wimgapi
+                    .open(patch_path, WIM_GENERIC_READ, WIM_OPEN_EXISTING, WIM_COMPRESS_NONE)
+                    .with_context(|| format!("Open patch {} error", patch_path.display()))?,
+            );
+            let attributes = wimgapi
+                .get_attributes(patch_handle.handle())
+                .with_context(|| "Get patch attributes error")?;
+            inputs.push((attributes.compression_type, attributes.image_count));
+        }
+
+        let (compression_type, mixed) = merge_output_compression(&inputs);
+        if mixed {
+            write_console(
+                ConsoleType::Warning,
+                &t!(
+                    "merge_patch.mixed_compression",
+                    compression = compression_name(compression_type)
+                ),
+            );
+        } else {
+            write_console(
+                ConsoleType::Info,
+                &t!(
+                    "merge_patch.keep_compression",
+                    compression = compression_name(compression_type)
+                ),
+            );
+        }
+        Ok(compression_type)
+    }
+
+    /// 将合并的补丁包拆分为每个版本一个补丁文件
+    ///
+    /// # 参数
+    ///
+    /// * `patch` - 补丁包文件路径
+    /// * `out_dir` - 输出目录，补丁文件按 [`split_output_names`] 命名
+    /// * `compress` - 压缩算法
+    ///
+    /// # 返回值
+    ///
+    /// * `Ok(Vec<PathBuf>)` - 写入的补丁文件列表
+    /// * `Err` - 发生错误
+    pub fn split_patch(&self, patch: &Path, out_dir: &Path, compress: Compress) -> Result<Vec<PathBuf>> {
+        let wimgapi = self.wimgapi()?;
+        let manifests = self.get_manifests(patch)?;
+        if manifests.is_empty() {
+            return Err(anyhow!("{}", t!("split_patch.not_patch", path = patch.display())));
+        }
+        fs::create_dir_all(out_dir).with_context(|| format!("Create output dir {} failed", out_dir.display()))?;
+
+        let patch_handle = HandleGuard::new(
+            wimgapi,
+            wimgapi
+                .open(patch, WIM_GENERIC_READ, WIM_OPEN_EXISTING, WIM_COMPRESS_NONE)
+                .with_context(|| "Open patch error")?,
+        );
+        wimgapi
+            .set_temp_path(patch_handle.handle(), &get_temp_path())
+            .with_context(|| "Set temp path error")?;
+
+        let mut written = Vec::new();
+        for (index, name) in split_output_names(&manifests) {
+            let out = out_dir.join(name);
+            write_console(
+                ConsoleType::Info,
+                &format!(
+                    "{}: {}{} -> {}",
+                    t!("split_patch.split_patch"),
+                    t!("apply_patch.index"),
+                    index,
+                    out.display()
+                ),
+            );
+
+            // 每个镜像导出到单独的补丁文件
+            let out_handle = HandleGuard::new(
+                wimgapi,
+                wimgapi
+                    .open(
+                        &out,
+                        WIM_GENERIC_WRITE,
+                        WIM_CREATE_ALWAYS,
+                        wim_compression_type(compress),
+                    )
+                    .with_context(|| format!("Create patch file {} error", out.display()))?,
+            );
+            wimgapi
+                .set_temp_path(out_handle.handle(), &get_temp_path())
+                .with_context(|| "Set temp path error")?;
+            let patch_image_handle = HandleGuard::new(
+                wimgapi,
+                wimgapi
+                    .load_image(patch_handle.handle(), index)
+                    .with_context(|| "Load patch image error")?,
+            );
+            wimgapi
+                .export_image(patch_image_handle.handle(), out_handle.handle(), 0)
+                .with_context(|| "Export patch image error")?;
+            patch_image_handle
+                .close()
+                .with_context(|| "Close patch image handle error")?;
+            out_handle.close().with_context(|| "Close out patch error")?;
+            written.push(out);
+        }
+
+        patch_handle.close().with_context(|| "Close patch handle error")?;
+        Ok(written)
+    }
+
+    /// 在两个目录之间创建目录补丁（不涉及 WIM，不需要 wimgapi.dll）
+    ///
+    /// 目录补丁是一个普通目录：补丁清单写入 [`DIR_PATCH_MANIFEST`]，补丁数据存储在 [`DIR_PATCH_PAYLOAD`] 子目录中，
+    /// 补丁清单中的镜像 GUID 与镜像信息为空；指定签名私钥时签名写入 [`DIR_PATCH_SIGNATURE`]
+    ///
+    /// # 参数
+    ///
+    /// * `base_dir` - 基础目录
+    /// * `target_dir` - 更新目录
+    /// * `out` - 输出的目录补丁路径（不存在或为空目录，`options.on_existing` 为覆盖时创建成功后替换已有内容）
+    /// * `options` - 创建补丁选项（不支持整镜像存储）
+    ///
+    /// # 返回值
+    ///
+    /// * `Ok(true)` - 已创建目录补丁（仅评估模式下只输出操作列表）
+    /// * `Ok(false)` - 两个目录没有差异，未创建目录补丁
+    /// * `Err` - 发生错误
+    pub fn create_dir_patch(
+        &self,
+        base_dir: &Path,
+        target_dir: &Path,
+        out: &Path,
+        options: &CreateOptions,
+    ) -> Result<bool> {
+        if options.storage == Storage::WholeImage {
+            return Err(anyhow!("{}", t!("dir_patch.whole_image_unsupported")));
+        }
+        check_zstd_level(options)?;
+        check_include(options.include.as_deref())?;
+        // 开始比较前先确认私钥可用
+        if let Some(sign_key) = &options.sign_key {
+            read_signing_key(sign_key)?;
+        }
+        // 输出目录位于比较的目录中时，暂存的补丁数据会在比较过程中被当作差异
+        let out_path = canonicalize_lenient(out);
+        if [base_dir, target_dir]
+            .iter()
+            .any(|dir| out_path.starts_with(canonicalize_lenient(dir)))
+        {
+            return Err(anyhow!("{}", t!("dir_patch.out_inside_input", path = out.display())));
+        }
+        let out_not_empty = !options.dry_run && out.exists() && fs::read_dir(out)?.next().is_some();
+        if !out_not_empty {
+            return self.write_dir_patch(base_dir, target_dir, out, options);
+        }
+        if options.on_existing != OnExisting::Overwrite {
+            return Err(anyhow!("{}", t!("dir_patch.out_not_empty", path = out.display())));
         }
 
-        Ok(result)
+        // 覆盖已有内容时先写入同一目录下的临时目录，创建成功后再替换，失败或没有差异时保留原目录补丁
+        let staged = create_tmp_dir(out.parent().unwrap_or(Path::new("")), ".wimpatch-", "")
+            .with_context(|| format!("Create staging dir for {} failed", out.display()))?;
+        let result = self.write_dir_patch(base_dir, target_dir, &staged, options);
+        if !matches!(result, Ok(true)) {
+            fs::remove_dir_all(&staged).ok();
+            return result;
+        }
+        fs::remove_dir_all(out).with_context(|| format!("Remove {} failed", out.display()))?;
+        fs::rename(&staged, out).with_context(|| format!("Replace {} failed", out.display()))?;
+        Ok(true)
     }
 
-    /// 合并多个补丁包
+    /// 比较两个目录并将目录补丁写入输出目录
     ///
     /// # 参数
     ///
-    /// * `patches` - 补丁包文件路径列表
-    /// * `out` - 输出合并后的补丁包文件路径
-    /// * `compress` - 压缩算法
+    /// * `base_dir` - 基础目录
+    /// * `target_dir` - 更新目录
+    /// * `out` - 输出的目录补丁路径（不存在或为空目录）
+    /// * `options` - 创建补丁选项
     ///
     /// # 返回值
     ///
-    /// * `Ok(())` - 合并成功
+    /// * `Ok(true)` - 已创建目录补丁（仅评估模式下只输出操作列表）
+    /// * `Ok(false)` - 两个目录没有差异，未创建目录补丁
     /// * `Err` - 发生错误
-    pub fn merge_patches(&self, patches: &[PathBuf], out: &Path, compress: Compress) -> Result<()> {
-        let merge_patch_handle = self
-            .wimgapi
-            .open(
-                out,
-                WIM_GENERIC_WRITE,
-                WIM_CREATE_ALWAYS,
-                match compress {
-                    Compress::None => WIM_COMPRESS_NONE,
-                    Compress::Xpress => WIM_COMPRESS_XPRESS,
-                    Compress::Lzx => WIM_COMPRESS_LZX,
-                },
-            )
-            .with_context(|| "Open out patch error ")?;
+    fn write_dir_patch(&self, base_dir: &Path, target_dir: &Path, out: &Path, options: &CreateOptions) -> Result<bool> {
+        self.progress.task_started(1, 2);
+
+        // 比较目录差异并暂存补丁数据
+        self.progress.stage(1, &t!("create_patch.compare_diff"));
+        let payload_dir = out.join(DIR_PATCH_PAYLOAD);
+        if !options.dry_run {
+            fs::create_dir_all(&payload_dir).with_context(|| "Create patch dir failed".to_string())?;
+        }
+        let (operations, estimate, _) = self.create_operations(base_dir, target_dir, &payload_dir, 1, options)?;
+        if options.stage_compressed && !options.dry_run {
+            Self::expand_staged_payloads(&payload_dir, &operations)?;
+        }
+        self.progress.step(1);
 
-        self.wimgapi
-            .set_temp_path(merge_patch_handle, get_temp_path())
-            .with_context(|| "Set temp path error ")?;
+        if operations.is_empty() {
+            self.progress.task_finished(1);
+            if !options.dry_run {
+                fs::remove_dir_all(out).ok();
+            }
+            write_console(ConsoleType::Warning, &t!("dir_patch.no_changes"));
+            return Ok(false);
+        }
+        if options.dry_run {
+            self.progress.task_finished(1);
+            println!("{}", Self::format_dry_run_report(1, 1, &operations, &estimate));
+            return Ok(true);
+        }
 
-        // 遍历补丁包
-        for patch_path in patches {
+        // 写入补丁清单
+        self.progress.stage(1, &t!("dir_patch.write_manifest"));
+        let manifest = PatchManifest::new(
+            &options.name,
+            &options.description,
+            &options.author,
+            &options.version,
+            "",
+            &ImageInfo::default(),
+            "",
+            &ImageInfo::default(),
+            &operations,
+            options.source_date,
+        );
+        let manifest_path = out.join(DIR_PATCH_MANIFEST);
+        let manifest_xml = manifest
+            .to_xml()
+            .with_context(|| "Serialize patch manifest to xml failed")?;
+        fs::write(&manifest_path, &manifest_xml)
+            .with_context(|| format!("Write manifest {} failed", manifest_path.display()))?;
+
+        // 对补丁清单与补丁数据签名，签名写入补丁清单旁边的文件
+        if let Some(sign_key) = &options.sign_key {
+            let signature = self
+                .sign_patch_image(sign_key, &manifest_xml, &payload_dir)
+                .with_context(|| "Sign directory patch error")?;
+            let signature_path = out.join(DIR_PATCH_SIGNATURE);
+            fs::write(
+                &signature_path,
+                signature.to_xml().with_context(|| "Serialize patch signature error")?,
+            )
+            .with_context(|| format!("Write signature {} failed", signature_path.display()))?;
             write_console(
                 ConsoleType::Info,
-                &format!("{}: {}", t!("merge_patch.merge_patch"), patch_path.display()),
+                &t!(
+                    "create_patch.signed",
+                    index = 1,
+                    signer = signature.signer,
+                    public_key = signature.public_key
+                ),
             );
-            let patch_handle = self
-                .wimgapi
-                .open(patch_path, WIM_GENERIC_READ, WIM_OPEN_EXISTING, WIM_COMPRESS_NONE)
-                .with_context(|| "Open patch error ")?;
+        }
+        self.progress.step(1);
+        self.progress.task_finished(1);
+        Ok(true)
+    }
+
+    /// 将目录补丁应用到基础目录（不涉及 WIM，不需要 wimgapi.dll）
+    ///
+    /// 基础目录先被复制到输出目录，再在输出目录上执行补丁清单中的文件操作；输出目录与基础目录相同时直接修改基础目录
+    ///
+    /// # 参数
+    ///
+    /// * `base_dir` - 基础目录
+    /// * `patch` - 由 [`create_dir_patch`](Self::create_dir_patch) 创建的目录补丁
+    /// * `out_dir` - 输出目录（不存在或为空目录）
+    /// * `options` - 应用补丁选项，使用其中的文件操作相关选项（排除列表、强制模式、重试次数、路径重映射与跳过删除）
+    ///
+    /// # 返回值
+    ///
+    /// * `Ok(())` - 应用成功
+    /// * `Err` - 发生错误，输出目录（与基础目录不同时）被删除
+    pub fn apply_dir_patch(&self, base_dir: &Path, patch: &Path, out_dir: &Path, options: &ApplyOptions) -> Result<()> {
+        check_include(options.include.as_deref())?;
+        let manifest_path = patch.join(DIR_PATCH_MANIFEST);
+        let xml = fs::read_to_string(&manifest_path)
+            .with_context(|| t!("dir_patch.not_dir_patch", path = patch.display()).to_string())?;
+        let manifest = PatchManifest::from_xml(&xml)
+            .with_context(|| format!("Parse patch manifest {} failed", manifest_path.display()))?;
+
+        let in_place = fs::canonicalize(base_dir)
+            .ok()
+            .is_some_and(|base_dir| fs::canonicalize(out_dir).is_ok_and(|out_dir| out_dir == base_dir));
+        if !in_place && out_dir.exists() && fs::read_dir(out_dir)?.next().is_some() {
+            return Err(anyhow!("{}", t!("dir_patch.out_not_empty", path = out_dir.display())));
+        }
+        // 输出目录位于基础目录中时，复制基础目录会把输出目录自身也复制进去，无限递归
+        if !in_place && canonicalize_lenient(out_dir).starts_with(canonicalize_lenient(base_dir)) {
+            return Err(anyhow!("{}", t!("dir_patch.out_inside_base", path = out_dir.display())));
+        }
 
-            self.wimgapi
-                .set_temp_path(patch_handle, get_temp_path())
-                .with_context(|| "Set temp path error ")?;
+        // 目录补丁的签名存储在补丁清单旁边的文件中，没有该文件时按未签名的补丁处理（要求签名时拒绝应用，否则警告）
+        if let Some(key_path) = &options.verify_signature {
+            read_verifying_key(key_path)?;
+        }
+        let signature_path = patch.join(DIR_PATCH_SIGNATURE);
+        let signature = match signature_path.exists() {
+            true => fs::read_to_string(&signature_path)
+                .with_context(|| format!("Read signature {} failed", signature_path.display()))?,
+            false => String::new(),
+        };
+        self.verify_patch_signature(1, &signature, &manifest, &patch.join(DIR_PATCH_PAYLOAD), options)?;
 
-            for index in 1..=self.wimgapi.get_image_count(patch_handle) {
-                let patch_image_handle = self
-                    .wimgapi
-                    .load_image(patch_handle, index)
-                    .with_context(|| "Load patch image error ")?;
+        self.excluded.lock().unwrap_or_else(|e| e.into_inner()).clear();
+        self.rebuilt_hives.lock().unwrap_or_else(|e| e.into_inner()).clear();
+        self.progress.task_started(1, 2);
 
-                self.wimgapi
-                    .export_image(patch_image_handle, merge_patch_handle, 0)
-                    .with_context(|| "Export patch image error ")?;
+        // 复制基础目录
+        self.progress.stage(1, &t!("dir_patch.copy_base"));
+        if !in_place {
+            copy_dir(base_dir, out_dir)
+                .with_context(|| format!("Copy {} to {} failed", base_dir.display(), out_dir.display()))?;
+        }
+        self.progress.step(1);
+
+        // 执行文件操作
+        self.progress.stage(1, &t!("apply_patch.merge_diff"));
+        let result = self.apply_operations(
+            out_dir,
+            &patch.join(DIR_PATCH_PAYLOAD),
+            &manifest.operations,
+            1,
+            options,
+        );
+        if result.is_err() && !in_place {
+            fs::remove_dir_all(out_dir).ok();
+        }
+        self.progress.step(1);
+        self.progress.task_finished(1);
+        result.map(|_| ())
+    }
 
-                self.wimgapi
-                    .close(patch_image_handle)
-                    .with_context(|| "Close patch image handle error ")?;
+    /// 读取合并后补丁包中各镜像的补丁数据，查找跨镜像内容相同的数据
+    ///
+    /// 不同补丁中内容相同的补丁数据（如长补丁链中反复变化为相同内容的文件）只做统计，不改写补丁清单，
+    /// 报告的是合并为一份时可以节省的空间
+    ///
+    /// # 参数
+    ///
+    /// * `image` - 合并后的补丁包路径
+    ///
+    /// # 返回值
+    ///
+    /// * `Ok((files, bytes))` - 重复数据数量与可以节省的字节数
+    /// * `Err` - 挂载或读取失败
+    fn find_shared_payloads(&self, image: &Path) -> Result<(usize, u64)> {
+        let wimgapi = self.wimgapi()?;
+        let mut payloads: Vec<(u32, String, u64)> = Vec::new();
+        for index in 1..=self.get_image_count(image)? {
+            // 只读挂载镜像卷
+            let mount_path = create_tmp_dir(&get_temp_path(), "shared-", "")
+                .with_context(|| "Create shared payload mount dir failed".to_string())?;
+            wimgapi
+                .mount_image(&mount_path, image, index, None)
+                .with_context(|| format!("Mount merged image {} failed", index))?;
+            register_mount(&mount_path, image, index);
+
+            // 计算补丁数据的哈希值
+            let mut result = Ok(());
+            let mut dirs = vec![mount_path.clone()];
+            'walk: while let Some(dir) = dirs.pop() {
+                let entries = match fs::read_dir(&dir) {
+                    Ok(entries) => entries,
+                    Err(e) => {
+                        result = Err(anyhow!("Read merged image dir failed: {}", e));
+                        break;
+                    }
+                };
+                for entry in entries.flatten() {
+                    let path = entry.path();
+                    if path.is_dir() {
+                        dirs.push(path);
+                        continue;
+                    }
+                    match get_file_sha256(&path, None) {
+                        Ok(hash) => payloads.push((index, hash, entry.metadata().map(|m| m.len()).unwrap_or(0))),
+                        Err(e) => {
+                            result = Err(e.context(format!("Read payload failed: {}", path.display())));
+                            break 'walk;
+                        }
+                    }
+                }
             }
 
-            self.wimgapi
-                .close(patch_handle)
-                .with_context(|| "Close patch handle error ")?;
+            // 卸载镜像卷
+            if let Err(e) = wimgapi.unmount_image(&mount_path, image, index, false) {
+                write_console(ConsoleType::Warning, &format!("Unmount merged image failed: {}", e));
+            }
+            unregister_mount(&mount_path);
+            fs::remove_dir_all(&mount_path).ok();
+            result?;
         }
-
-        self.wimgapi
-            .close(merge_patch_handle)
-            .with_context(|| "Close out patch error ")?;
-        Ok(())
+        Ok(count_shared_payloads(&payloads))
     }
 
     /// 清理无效的挂载点
@@ -1792,11 +6409,14 @@ impl WimPatch {
     /// - `Ok(())` - 成功清理
     /// - `Err(anyhow::Error)` - 失败，返回错误信息
     pub fn clean(&self) -> Result<()> {
+        let wimgapi = self.wimgapi()?;
+        // 卸载镜像需要管理员权限
+        Self::check_elevated()?;
+
         // 获取所有挂载点
-        let mounted_images: Vec<WimMountInfoLevel1> = self
-            .wimgapi
+        let mounted_images: Vec<WimMountInfoLevel1> = wimgapi
             .get_mounted_image()
-            .with_context(|| "Get mounted image error ")?
+            .with_context(|| "Get mounted image error")?
             .into_iter()
             // 过滤无效挂载点
             .filter(|mount_info| {
@@ -1812,7 +6432,7 @@ impl WimPatch {
 
         // 遍历挂载点并尝试卸载
         for mount_info in mounted_images {
-            let result = self.wimgapi.unmount_image(
+            let result = wimgapi.unmount_image(
                 Path::new(&mount_info.mount_path),
                 mount_info.wim_path.as_ref(),
                 mount_info.image_index,
@@ -1842,14 +6462,1028 @@ impl WimPatch {
     /// - `Ok(u32)` - 镜像数量
     /// - `Err(anyhow::Error)` - 失败，返回错误信息
     pub fn get_image_count(&self, image_path: &Path) -> Result<u32> {
-        let handle = self
-            .wimgapi
+        let wimgapi = self.wimgapi()?;
+        let handle = wimgapi
             .open(image_path, WIM_GENERIC_READ, WIM_OPEN_EXISTING, WIM_COMPRESS_NONE)
-            .with_context(|| "Open image error ")?;
-        let count = self.wimgapi.get_image_count(handle);
-        self.wimgapi
-            .close(handle)
-            .with_context(|| "Close image handle error ")?;
+            .with_context(|| "Open image error")?;
+        let count = wimgapi.get_image_count(handle);
+        wimgapi.close(handle).with_context(|| "Close image handle error")?;
         Ok(count)
     }
+
+    /// 获取 WIM 文件的属性（压缩类型、分卷信息、可启动镜像索引与属性标志）
+    ///
+    /// # 参数
+    ///
+    /// - `image_path` - WIM 文件路径
+    ///
+    /// # 返回值
+    ///
+    /// - `Ok(WimInfo)` - WIM 文件属性
+    /// - `Err(anyhow::Error)` - 失败，返回错误信息
+    pub fn get_wim_info(&self, image_path: &Path) -> Result<WimInfo> {
+        let wimgapi = self.wimgapi()?;
+        let handle = wimgapi
+            .open(image_path, WIM_GENERIC_READ, WIM_OPEN_EXISTING, WIM_COMPRESS_NONE)
+            .with_context(|| "Open image error")?;
+        let info = wimgapi.get_attributes(handle);
+        wimgapi.close(handle).with_context(|| "Close image handle error")?;
+        info.with_context(|| "Get image attributes error")
+    }
+}
+
+/// 生成指定镜像索引的清单输出路径（在文件名后追加索引）
+///
+/// # 参数
+///
+/// - `out` - 输出文件路径
+/// - `index` - 补丁镜像索引
+///
+/// # 返回值
+///
+/// - `PathBuf` - 如 `manifest.xml` 对应索引 2 时为 `manifest-2.xml`
+pub(crate) fn manifest_output_path(out: &Path, index: u32) -> PathBuf {
+    let stem = out.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match out.extension() {
+        Some(extension) => format!("{}-{}.{}", stem, index, extension.to_string_lossy()),
+        None => format!("{}-{}", stem, index),
+    };
+    out.with_file_name(name)
+}
+
+/// 查找合并输入中的冲突补丁：基础镜像 GUID、索引与版本相同但操作不同（内容完全相同的重复补丁不视为冲突）
+///
+/// # 参数
+///
+/// - `manifests` - 所有输入补丁包中的补丁清单
+///
+/// # 返回值
+///
+/// - `Vec<MergeConflict>` - 冲突列表，按基础镜像 GUID、索引与版本排序
+pub(crate) fn find_merge_conflicts(manifests: &[PatchManifest]) -> Vec<MergeConflict> {
+    let mut groups: BTreeMap<(String, u32, &str), Vec<&PatchManifest>> = BTreeMap::new();
+    for manifest in manifests {
+        let base_guid = normalize_guid(&manifest.base_image_guid).unwrap_or_else(|| manifest.base_image_guid.clone());
+        groups
+            .entry((
+                base_guid,
+                manifest.base_image_info.index,
+                manifest.patch_version.as_str(),
+            ))
+            .or_default()
+            .push(manifest);
+    }
+
+    groups
+        .into_iter()
+        .filter(|(_, group)| group.iter().any(|manifest| manifest.operations != group[0].operations))
+        .map(|((base_guid, base_index, version), group)| MergeConflict {
+            base_guid,
+            base_index,
+            version: version.to_string(),
+            ids: group.iter().map(|manifest| manifest.id.clone()).collect(),
+        })
+        .collect()
+}
+
+/// 选择合并后补丁包的压缩类型：使用镜像数量最多的输入压缩类型，数量相同时使用先出现的压缩类型
+///
+/// # 参数
+///
+/// - `inputs` - 各输入补丁包的压缩类型与镜像数量
+///
+/// # 返回值
+///
+/// - `(u32, bool)` - 选择的压缩类型，以及输入补丁包的压缩类型是否不同（不同时部分镜像需要重新压缩）
+pub(crate) fn merge_output_compression(inputs: &[(u32, u32)]) -> (u32, bool) {
+    let mut counts: Vec<(u32, u32)> = Vec::new();
+    for (compression_type, image_count) in inputs {
+        match counts.iter_mut().find(|(existing, _)| existing == compression_type) {
+            Some((_, count)) => *count += image_count,
+            None => counts.push((*compression_type, *image_count)),
+        }
+    }
+    // max_by_key 在数量相同时返回最后一个，反向遍历以保留先出现的压缩类型
+    let compression_type = counts
+        .iter()
+        .rev()
+        .max_by_key(|(_, count)| *count)
+        .map(|(compression_type, _)| *compression_type)
+        .unwrap_or(WIM_COMPRESS_LZX);
+    (compression_type, counts.len() > 1)
+}
+
+/// 读取镜像信息中记录的已应用补丁版本
+///
+/// # 参数
+///
+/// - `image_info` - 单个镜像的镜像信息 XML
+///
+/// # 返回值
+///
+/// - `Some(Version)` - 应用补丁时记录的版本
+/// - `None` - 未记录（未应用过补丁或由旧版本应用）或无法解析
+pub(crate) fn applied_version(image_info: &str) -> Option<Version> {
+    get_xml_field(image_info, APPLIED_VERSION_FIELD).and_then(|version| Version::parse(version.trim()).ok())
+}
+
+/// 在补丁镜像的镜像信息中添加名称、描述与补丁清单（添加到 `</IMAGE>` 前）
+///
+/// 名称与描述按 XML 转义，包含 `&`、`<` 等字符时不会破坏镜像信息
+///
+/// # 参数
+///
+/// - `image_info` - 单个镜像的镜像信息 XML（不含字节顺序标记）
+/// - `name` - 补丁名称
+/// - `description` - 补丁描述
+/// - `manifest_xml` - 补丁清单 XML
+///
+/// # 返回值
+///
+/// - `Some(String)` - 添加字段后的镜像信息
+/// - `None` - 镜像信息中没有 `</IMAGE>` 标签
+pub(crate) fn embed_patch_fields(
+    image_info: &str,
+    name: &str,
+    description: &str,
+    manifest_xml: &str,
+) -> Option<String> {
+    let pos = image_info.rfind("</IMAGE>")?;
+    let (name, description) = (escape(name), escape(description));
+    Some(format!(
+        "{}<NAME>{name}</NAME>\
+        <DESCRIPTION>{description}</DESCRIPTION>\
+        <DISPLAYNAME>{name}</DISPLAYNAME>\
+        <DISPLAYDESCRIPTION>{description}</DISPLAYDESCRIPTION>\
+        <FLAGS></FLAGS>{manifest_xml}{}",
+        &image_info[..pos],
+        &image_info[pos..]
+    ))
+}
+
+/// 在镜像信息中记录已应用的补丁版本（已存在时替换，否则添加到 `</IMAGE>` 前）
+///
+/// # 参数
+///
+/// - `image_info` - 单个镜像的镜像信息 XML
+/// - `version` - 补丁链中最后一个补丁的版本
+pub(crate) fn set_applied_version(image_info: &str, version: &str) -> String {
+    if get_xml_field(image_info, APPLIED_VERSION_FIELD).is_some() {
+        return replace_xml_field(image_info, APPLIED_VERSION_FIELD, version);
+    }
+    match image_info.rfind("</IMAGE>") {
+        Some(pos) => format!(
+            "{}<{APPLIED_VERSION_FIELD}>{version}</{APPLIED_VERSION_FIELD}>{}",
+            &image_info[..pos],
+            &image_info[pos..]
+        ),
+        None => image_info.to_string(),
+    }
+}
+
+/// 获取 WIM 压缩类型的名称
+///
+/// # 参数
+///
+/// - `compression_type` - WIM 压缩类型
+pub(crate) fn compression_name(compression_type: u32) -> &'static str {
+    match compression_type {
+        WIM_COMPRESS_NONE => "none",
+        WIM_COMPRESS_XPRESS => "xpress",
+        WIM_COMPRESS_LZX => "lzx",
+        WIM_COMPRESS_LZMS => "lzms",
+        _ => "unknown",
+    }
+}
+
+/// 获取压缩算法对应的 WIM 压缩类型
+///
+/// # 参数
+///
+/// - `compress` - 补丁或目标镜像的压缩算法
+pub(crate) fn wim_compression_type(compress: Compress) -> u32 {
+    match compress {
+        Compress::None => WIM_COMPRESS_NONE,
+        Compress::Xpress => WIM_COMPRESS_XPRESS,
+        Compress::Lzx => WIM_COMPRESS_LZX,
+        Compress::Lzms => WIM_COMPRESS_LZMS,
+    }
+}
+
+/// 估算应用部分补丁后目标镜像的统计信息
+///
+/// 仅比较子目录时，子目录之外保持基础镜像的内容，子目录中为更新镜像的内容；未写入补丁的差异在应用后保持基础镜像的状态
+///
+/// # 参数
+///
+/// - `base` - 基础镜像信息
+/// - `target` - 更新镜像信息（名称等其他字段原样保留）
+/// - `subtree` - 仅比较子目录时基础镜像与更新镜像中该子目录的（目录数, 文件数, 总字节数）
+/// - `dropped` - 未写入补丁的差异
+pub(crate) fn expected_target_info(
+    base: &ImageInfo,
+    target: &ImageInfo,
+    subtree: Option<((u64, u64, u64), (u64, u64, u64))>,
+    dropped: &DroppedDiffs,
+) -> ImageInfo {
+    let (mut dirs, mut files, mut bytes) = (dropped.dirs, dropped.files, dropped.bytes);
+    if let Some(((base_dirs, base_files, base_bytes), (target_dirs, target_files, target_bytes))) = subtree {
+        dirs += (base.dir_count as i64 - base_dirs as i64) - (target.dir_count as i64 - target_dirs as i64);
+        files += (base.file_count as i64 - base_files as i64) - (target.file_count as i64 - target_files as i64);
+        bytes += (base.total_bytes as i64 - base_bytes as i64) - (target.total_bytes as i64 - target_bytes as i64);
+    }
+    let adjust = |value: u64, delta: i64| (value as i64 + delta).max(0) as u64;
+    ImageInfo {
+        dir_count: adjust(target.dir_count, dirs),
+        file_count: adjust(target.file_count, files),
+        total_bytes: adjust(target.total_bytes, bytes),
+        ..target.clone()
+    }
+}
+
+/// 计算目标镜像实际统计信息与期望统计信息的差异
+///
+/// # 参数
+///
+/// - `expected` - 补丁清单中记录的目标镜像信息
+/// - `actual` - 实际导出的目标镜像信息
+///
+/// # 返回值
+///
+/// - `Some((i64, i64, i64))` - 目录数、文件数和总字节数的差异（实际值减期望值）
+/// - `None` - 统计信息一致
+pub(crate) fn image_stat_divergence(expected: &ImageInfo, actual: &ImageInfo) -> Option<(i64, i64, i64)> {
+    let dirs = actual.dir_count as i64 - expected.dir_count as i64;
+    let files = actual.file_count as i64 - expected.file_count as i64;
+    let bytes = actual.total_bytes as i64 - expected.total_bytes as i64;
+    if dirs == 0 && files == 0 && bytes == 0 {
+        None
+    } else {
+        Some((dirs, files, bytes))
+    }
+}
+
+/// 查找路径命中的易变文件排除模式（[`VOLATILE_EXCLUDE`]）
+///
+/// # 参数
+///
+/// - `path` - 文件在镜像中的相对路径
+///
+/// # 返回值
+///
+/// - `Some(&str)` - 命中的模式
+/// - `None` - 未命中
+pub(crate) fn volatile_pattern(path: &str) -> Option<&'static str> {
+    VOLATILE_EXCLUDE.iter().copied().find(|pattern| {
+        std::iter::once(path)
+            .chain(path.match_indices('\\').map(|(pos, _)| &path[pos + 1..]))
+            .any(|tail| wildcard_match(pattern, tail))
+    })
+}
+
+/// 计算 `--extract-to` 下镜像的释放目录
+///
+/// # 参数
+///
+/// - `extract_to` - 指定的释放目录
+/// - `base_index` - 基础镜像索引
+/// - `single` - 是否只应用一个镜像，只应用一个镜像时直接使用指定目录，否则按基础镜像索引创建子目录
+///
+/// # 返回值
+///
+/// - `PathBuf` - 镜像的释放目录
+pub(crate) fn extract_image_dir(extract_to: &Path, base_index: u32, single: bool) -> PathBuf {
+    if single {
+        extract_to.to_path_buf()
+    } else {
+        extract_to.join(base_index.to_string())
+    }
+}
+
+/// 按镜像名称或显示名称（不区分大小写）查找镜像索引
+///
+/// # 参数
+///
+/// - `images` - 镜像信息列表
+/// - `name` - 镜像名称
+///
+/// # 返回值
+///
+/// - `Ok(u32)` - 唯一匹配的镜像索引
+/// - `Err(anyhow::Error)` - 没有镜像匹配（列出所有镜像名称）或有多个镜像匹配（列出候选镜像）
+pub(crate) fn find_image_by_name(images: &[ImageInfo], name: &str) -> Result<u32> {
+    let describe = |info: &ImageInfo| {
+        format!(
+            "{}: {}",
+            info.index,
+            info.name.as_deref().or(info.display_name.as_deref()).unwrap_or("-")
+        )
+    };
+    let matched: Vec<&ImageInfo> = images
+        .iter()
+        .filter(|info| {
+            [&info.name, &info.display_name]
+                .into_iter()
+                .flatten()
+                .any(|image_name| image_name.trim().eq_ignore_ascii_case(name.trim()))
+        })
+        .collect();
+    match matched.as_slice() {
+        [info] => Ok(info.index),
+        [] => Err(anyhow!(
+            "{}",
+            t!(
+                "image_name.not_found",
+                name = name,
+                images = images.iter().map(describe).collect::<Vec<_>>().join("; ")
+            )
+        )),
+        _ => Err(anyhow!(
+            "{}",
+            t!(
+                "image_name.ambiguous",
+                name = name,
+                images = matched.iter().map(|info| describe(info)).collect::<Vec<_>>().join("; ")
+            )
+        )),
+    }
+}
+
+/// 获取压缩预设对应的 zstd 压缩级别
+pub(crate) fn preset_level(preset: &Preset) -> i32 {
+    match preset {
+        Preset::Fast => 3,
+        Preset::Medium => 9,
+        Preset::Best => 19,
+        Preset::Extreme => 22,
+    }
+}
+
+/// 检查目标镜像能否按指定的处理方式写入
+///
+/// # 参数
+///
+/// - `target_image` - 目标镜像路径
+/// - `on_existing` - 目标镜像已存在时的处理方式
+///
+/// # 返回值
+///
+/// - `Ok(())` - 目标镜像不存在，或已存在且允许覆盖
+/// - `Err(anyhow::Error)` - 目标镜像已存在且拒绝覆盖，或指定了不支持的追加
+pub(crate) fn check_target_on_existing(target_image: &Path, on_existing: OnExisting) -> Result<()> {
+    match on_existing {
+        OnExisting::Append => Err(anyhow!("Appending to the target image is not supported")),
+        OnExisting::Error if target_image.exists() => Err(anyhow!(
+            "{}",
+            t!("apply_patch.target_exists", path = target_image.display())
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// 获取输出文件已存在时的处理方式对应的 WIMCreateFile 打开方式
+///
+/// # 参数
+///
+/// - `on_existing` - 输出文件已存在时的处理方式
+///
+/// # 返回值
+///
+/// - `u32` - 拒绝修改时为 `WIM_CREATE_NEW`（文件已存在则失败），覆盖时为 `WIM_CREATE_ALWAYS`，追加时为 `WIM_OPEN_ALWAYS`
+pub(crate) fn output_disposition(on_existing: OnExisting) -> u32 {
+    match on_existing {
+        OnExisting::Error => WIM_CREATE_NEW,
+        OnExisting::Overwrite => WIM_CREATE_ALWAYS,
+        OnExisting::Append => WIM_OPEN_ALWAYS,
+    }
+}
+
+/// 获取补丁清单中修改操作最常用的存储类型（数量相同时按 zstd、bsdiff、full 的顺序选择）
+///
+/// # 参数
+///
+/// - `manifests` - 补丁清单列表
+///
+/// # 返回值
+///
+/// - `Storage` - 最常用的存储类型，没有可识别的修改操作时为 zstd
+pub(crate) fn dominant_storage(manifests: &[(u32, PatchManifest)]) -> Storage {
+    let candidates = [Storage::Zstd, Storage::Bsdiff, Storage::Full];
+    let mut counts = [0usize; 3];
+    for operation in manifests.iter().flat_map(|(_, manifest)| &manifest.operations) {
+        let storage = operation.storage.as_deref().unwrap_or_default();
+        if operation.action == Action::Modify
+            && let Some(position) = ["zstd", "bsdiff", "full"]
+                .iter()
+                .position(|name| storage.eq_ignore_ascii_case(name))
+        {
+            counts[position] += 1;
+        }
+    }
+    // 从后向前比较，数量相同时保留靠前的存储类型
+    let position = (0..candidates.len()).rev().max_by_key(|&i| counts[i]).unwrap_or(0);
+    candidates[position].clone()
+}
+
+/// 获取 zstd 差异使用的压缩级别，指定 `--zstd-level` 时优先使用，否则使用压缩预设对应的级别
+pub(crate) fn zstd_level(options: &CreateOptions) -> i32 {
+    options.zstd_level.unwrap_or_else(|| preset_level(&options.preset))
+}
+
+/// 获取捕获与释放镜像时的安全描述符标志
+///
+/// # 参数
+///
+/// - `no_acl` - 是否跳过文件与目录的安全描述符（`--no-acl`）
+///
+/// # 返回值
+///
+/// - `u32` - 指定 `no_acl` 时为 `WIM_FLAG_NO_DIRACL | WIM_FLAG_NO_FILEACL`，否则为 0
+pub(crate) fn acl_flags(no_acl: bool) -> u32 {
+    if no_acl {
+        WIM_FLAG_NO_DIRACL | WIM_FLAG_NO_FILEACL
+    } else {
+        0
+    }
+}
+
+/// 校验 `--zstd-level` 与存储类型的组合（bsdiff 存储不使用 zstd 压缩级别）
+///
+/// # 返回值
+///
+/// - `Ok(())` - 组合有效
+/// - `Err(anyhow::Error)` - 压缩级别超出范围或与 bsdiff 存储同时指定
+pub(crate) fn check_zstd_level(options: &CreateOptions) -> Result<()> {
+    let Some(level) = options.zstd_level else {
+        return Ok(());
+    };
+    if !(0..=22).contains(&level) {
+        return Err(anyhow!("Invalid zstd level (expected 0-22): {}", level));
+    }
+    if options.storage == Storage::Bsdiff {
+        return Err(anyhow!("{}", t!("create_patch.zstd_level_bsdiff")));
+    }
+    Ok(())
+}
+
+/// 按执行顺序排列文件操作
+///
+/// # 参数
+///
+/// - `operations` - 补丁清单中的操作列表
+/// - `order` - 执行顺序
+///
+/// # 返回值
+///
+/// - `Vec<&Operation>` - `Phased` 时依次为删除、新增与修改操作，各阶段内保持补丁清单中的顺序（父目录先于其中的文件）；
+///   `Manifest` 时与补丁清单顺序相同
+pub(crate) fn order_operations(operations: &[Operation], order: ApplyOrder) -> Vec<&Operation> {
+    let mut ordered: Vec<&Operation> = operations.iter().collect();
+    if order == ApplyOrder::Phased {
+        // 稳定排序：先删除再新增，同一路径由文件变为目录（或反之）及重命名时不会与旧文件冲突
+        ordered.sort_by_key(|operation| match operation.action {
+            Action::Delete => 0,
+            Action::Add => 1,
+            Action::Modify => 2,
+        });
+    }
+    ordered
+}
+
+/// 统计补丁链中需要执行的文件操作数量
+///
+/// 整镜像补丁之前的补丁不会被应用，不计入统计
+///
+/// # 参数
+///
+/// - `patch_chain` - 补丁链（补丁镜像索引与补丁清单）
+///
+/// # 返回值
+///
+/// - `u64` - 文件操作数量
+pub(crate) fn chain_operation_count(patch_chain: &[(u32, PatchManifest)]) -> u64 {
+    let start = patch_chain
+        .iter()
+        .rposition(|(_, patch)| patch.whole_image)
+        .unwrap_or(0);
+    patch_chain[start..]
+        .iter()
+        .map(|(_, patch)| patch.operations.len() as u64)
+        .sum()
+}
+
+/// 生成拆分补丁包时各补丁镜像的输出文件名
+///
+/// 文件名为 `<补丁版本>_<基础镜像 GUID>.wim`，文件名中不允许的字符替换为 `_`，
+/// 文件名相同（不区分大小写）的补丁在版本后追加镜像索引
+///
+/// # 参数
+///
+/// - `manifests` - 补丁镜像索引与补丁清单列表
+///
+/// # 返回值
+///
+/// - `Vec<(u32, String)>` - 补丁镜像索引与输出文件名
+pub(crate) fn split_output_names(manifests: &[(u32, PatchManifest)]) -> Vec<(u32, String)> {
+    let sanitize = |value: &str| -> String {
+        value
+            .chars()
+            .map(|c| if c < ' ' || "<>:\"/\\|?*".contains(c) { '_' } else { c })
+            .collect()
+    };
+    let parts: Vec<(String, String)> = manifests
+        .iter()
+        .map(|(_, manifest)| {
+            // 文件名中的 GUID 不带花括号
+            let guid = normalize_guid(&manifest.base_image_guid).unwrap_or_else(|| manifest.base_image_guid.clone());
+            (
+                sanitize(&manifest.patch_version),
+                sanitize(guid.trim_matches(['{', '}'])),
+            )
+        })
+        .collect();
+
+    manifests
+        .iter()
+        .zip(&parts)
+        .map(|((index, _), (version, guid))| {
+            // 文件名冲突时在版本后追加镜像索引
+            let collided = parts
+                .iter()
+                .filter(|(other_version, other_guid)| {
+                    other_version.eq_ignore_ascii_case(version) && other_guid.eq_ignore_ascii_case(guid)
+                })
+                .count()
+                > 1;
+            let name = if collided {
+                format!("{}-{}_{}.wim", version, index, guid)
+            } else {
+                format!("{}_{}.wim", version, guid)
+            };
+            (*index, name)
+        })
+        .collect()
+}
+
+/// 判断以指定 GUID 为基础镜像的补丁追加到已有补丁后能否在应用时形成补丁链
+///
+/// # 参数
+///
+/// - `existing` - 已有补丁的清单列表
+/// - `base_guid` - 新补丁的基础镜像 GUID
+///
+/// # 返回值
+///
+/// - `bool` - 新补丁的基础镜像为任一已有补丁的基础镜像（同一基线的其他版本）或更新镜像（衔接其后）时返回 `true`
+pub(crate) fn is_appendable_to(existing: &[(u32, PatchManifest)], base_guid: &str) -> bool {
+    existing.iter().any(|(_, manifest)| {
+        is_same_guid(&manifest.base_image_guid, base_guid) || is_same_guid(&manifest.target_image_guid, base_guid)
+    })
+}
+
+/// 将镜像信息中的创建时间替换为另一镜像的创建时间
+///
+/// # 参数
+///
+/// - `source_xml` - 提供创建时间的镜像信息（基础镜像）
+/// - `xml` - 需要替换创建时间的镜像信息（目标镜像）
+///
+/// # 返回值
+///
+/// - `String` - 替换后的镜像信息，任一镜像信息缺少 `CREATIONTIME` 时保持不变
+pub(crate) fn copy_creation_time(source_xml: &str, xml: &str) -> String {
+    let mut result = xml.to_string();
+    for field in ["CREATIONTIME/HIGHPART", "CREATIONTIME/LOWPART"] {
+        if let Some(value) = get_xml_field(source_xml, field) {
+            result = replace_xml_field(&result, field, value);
+        }
+    }
+    result
+}
+
+/// 获取自动匹配索引时无法匹配的镜像索引
+///
+/// # 参数
+///
+/// - `base_count` - 基础镜像的镜像数量
+/// - `target_count` - 更新镜像的镜像数量
+///
+/// # 返回值
+///
+/// - `(Vec<u32>, Vec<u32>)` - 被跳过的基础镜像索引与更新镜像索引（至少一个为空）
+pub(crate) fn unmatched_indices(base_count: u32, target_count: u32) -> (Vec<u32>, Vec<u32>) {
+    let matched = base_count.min(target_count);
+    (
+        (matched + 1..=base_count).collect(),
+        (matched + 1..=target_count).collect(),
+    )
+}
+
+/// 获取创建补丁时需要保留的可启动镜像索引
+///
+/// # 参数
+///
+/// - `base_boot_index` - 基础镜像文件的可启动镜像索引（0 表示无可启动镜像）
+/// - `captured_indices` - 已生成补丁镜像的基础镜像索引（按索引顺序，对应补丁文件末尾的镜像）
+/// - `patch_image_count` - 补丁文件中的镜像数量
+///
+/// # 返回值
+///
+/// - `Option<u32>` - 基础镜像的可启动镜像在补丁文件中对应的索引，未生成补丁时为 `None`
+pub(crate) fn preserved_boot_index(
+    base_boot_index: u32,
+    captured_indices: &[u32],
+    patch_image_count: u32,
+) -> Option<u32> {
+    let position = captured_indices.iter().position(|&index| index == base_boot_index)? as u32;
+    let first = patch_image_count.checked_sub(captured_indices.len() as u32)?;
+    Some(first + position + 1)
+}
+
+/// 判断基础镜像与更新镜像是否为同一文件的同一索引
+///
+/// # 参数
+///
+/// - `base_image` - 基础镜像路径
+/// - `base_index` - 基础镜像索引（`None` 表示自动匹配）
+/// - `target_image` - 更新镜像路径
+/// - `target_index` - 更新镜像索引（`None` 表示自动匹配）
+///
+/// # 返回值
+///
+/// - `bool` - 解析后的路径相同（不区分大小写）且索引相同时返回 `true`
+pub(crate) fn is_same_source(
+    base_image: &Path,
+    base_index: Option<u32>,
+    target_image: &Path,
+    target_index: Option<u32>,
+) -> bool {
+    let resolve = |path: &Path| {
+        fs::canonicalize(path)
+            .unwrap_or_else(|_| path.to_path_buf())
+            .to_string_lossy()
+            .to_lowercase()
+    };
+    base_index == target_index && resolve(base_image) == resolve(target_image)
+}
+
+/// 判断文件内容是否与补丁清单记录的 SHA-256 一致（先比较大小，避免计算明显不同的文件）
+///
+/// # 参数
+///
+/// - `path` - 文件路径
+/// - `size` - 补丁清单记录的文件大小
+/// - `sha256` - 补丁清单记录的 SHA-256
+pub(crate) fn file_matches_sha256(path: &Path, size: Option<u64>, sha256: &str) -> bool {
+    let Ok(metadata) = fs::metadata(path) else {
+        return false;
+    };
+    if !metadata.is_file() || size.is_some_and(|size| size != metadata.len()) {
+        return false;
+    }
+    get_file_sha256(path, None).is_ok_and(|actual| actual.eq_ignore_ascii_case(sha256))
+}
+
+/// 规范化可能尚不存在的路径：规范化其最长的已存在前缀，再拼接其余部分
+///
+/// # 参数
+///
+/// - `path` - 路径
+///
+/// # 返回值
+///
+/// - 规范化后的路径（没有任何已存在的前缀时原样返回）
+pub(crate) fn canonicalize_lenient(path: &Path) -> PathBuf {
+    let mut rest = Vec::new();
+    let mut current = path;
+    loop {
+        if let Ok(canonical) = fs::canonicalize(current) {
+            return rest.iter().rev().fold(canonical, |path, name| path.join(name));
+        }
+        match (current.parent(), current.file_name()) {
+            (Some(parent), Some(name)) => {
+                rest.push(name);
+                current = parent;
+            }
+            _ => return path.to_path_buf(),
+        }
+    }
+}
+
+/// 将路径模板中的 `{stem}` 替换为基础镜像的文件名（不含扩展名）
+///
+/// # 参数
+///
+/// - `template` - 路径模板，如 `D:\out\{stem}-patched.wim`
+/// - `base_image` - 基础镜像路径
+pub(crate) fn expand_base_stem(template: &Path, base_image: &Path) -> PathBuf {
+    let stem = base_image
+        .file_stem()
+        .map(|stem| stem.to_string_lossy())
+        .unwrap_or_default();
+    PathBuf::from(template.to_string_lossy().replace(BASE_STEM_PLACEHOLDER, &stem))
+}
+
+/// 将应用结果报告与释放目录路径中的 `{stem}` 替换为基础镜像的文件名（不含扩展名）
+///
+/// # 参数
+///
+/// - `options` - 应用补丁选项
+/// - `base_image` - 基础镜像路径
+pub(crate) fn expand_base_options(options: &ApplyOptions, base_image: &Path) -> ApplyOptions {
+    ApplyOptions {
+        report: options.report.as_deref().map(|path| expand_base_stem(path, base_image)),
+        extract_to: options
+            .extract_to
+            .as_deref()
+            .map(|path| expand_base_stem(path, base_image)),
+        ..options.clone()
+    }
+}
+
+/// 删除批量应用时某个基础镜像使用的临时子目录
+///
+/// 仍有已登记的挂载点（卸载失败）时不做任何清理，避免删除挂载目录中的镜像内容
+///
+/// # 参数
+///
+/// - `scratch` - 临时子目录路径
+fn clean_scratch(scratch: &Path) {
+    if has_active_mounts() {
+        return;
+    }
+    fs::remove_dir_all(scratch).ok();
+}
+
+/// 判断镜像文件是否在已挂载镜像的来源文件中
+///
+/// # 参数
+///
+/// - `image` - 镜像文件路径
+/// - `mounted` - 已挂载镜像的 WIM 文件路径
+///
+/// # 返回值
+///
+/// - `bool` - 解析后的路径与任一已挂载镜像的来源文件相同（不区分大小写）时返回 `true`
+pub(crate) fn is_image_mounted(image: &Path, mounted: &[String]) -> bool {
+    mounted
+        .iter()
+        .any(|wim_path| is_same_source(image, None, Path::new(wim_path), None))
+}
+
+/// 按 `--strip-components` 与 `--path-prefix` 重映射操作路径（与 `tar` 的行为一致）
+///
+/// # 参数
+///
+/// - `path` - 操作的相对路径
+/// - `strip_components` - 从开头去除的路径层数
+/// - `path_prefix` - 添加到开头的路径前缀
+///
+/// # 返回值
+///
+/// - `Ok(Some(String))` - 重映射后的相对路径（以 `\` 分隔）
+/// - `Ok(None)` - 路径层数不足，整个路径被去除，应跳过该操作
+/// - `Err(anyhow::Error)` - 前缀为绝对路径，或重映射后的路径包含 `..` 会逃逸出挂载目录
+pub(crate) fn remap_operation_path(
+    path: &str,
+    strip_components: usize,
+    path_prefix: Option<&str>,
+) -> Result<Option<String>> {
+    let escapes = |value: &str| value.split(['\\', '/']).any(|component| component == "..");
+    if strip_components == 0 && path_prefix.is_none() {
+        if escapes(path) {
+            return Err(anyhow!("Operation path escapes the mount directory: {}", path));
+        }
+        return Ok(Some(path.to_string()));
+    }
+    if let Some(prefix) = path_prefix
+        && (Path::new(prefix).has_root() || prefix.contains(':'))
+    {
+        return Err(anyhow!("Path prefix must be a relative path: {}", prefix));
+    }
+
+    let split = |value: &str| {
+        value
+            .split(['\\', '/'])
+            .filter(|component| !component.is_empty() && *component != ".")
+            .map(str::to_string)
+            .collect::<Vec<_>>()
+    };
+    let components = split(path);
+    if components.len() <= strip_components {
+        return Ok(None);
+    }
+    let mut mapped = path_prefix.map(split).unwrap_or_default();
+    mapped.extend(components.into_iter().skip(strip_components));
+
+    // 防止重映射后的路径写入挂载目录之外
+    let mapped = mapped.join("\\");
+    if escapes(&mapped) {
+        return Err(anyhow!("Remapped path escapes the mount directory: {}", mapped));
+    }
+    Ok(Some(mapped))
+}
+
+/// 统计跨镜像内容相同的补丁数据
+///
+/// # 参数
+///
+/// - `payloads` - 补丁数据列表（镜像索引, SHA-256, 字节数）
+///
+/// # 返回值
+///
+/// - `(files, bytes)` - 除第一份外出现在其他镜像中的重复数据数量与字节数（同一镜像内的重复已由 `PayloadRef` 处理）
+pub(crate) fn count_shared_payloads(payloads: &[(u32, String, u64)]) -> (usize, u64) {
+    let mut images: HashMap<&str, (u64, HashSet<u32>)> = HashMap::new();
+    for (index, hash, size) in payloads {
+        let entry = images.entry(hash.as_str()).or_insert_with(|| (*size, HashSet::new()));
+        entry.1.insert(*index);
+    }
+    images
+        .values()
+        .filter(|(size, indices)| *size > 0 && indices.len() > 1)
+        .fold((0, 0), |(files, bytes), (size, indices)| {
+            let copies = indices.len() - 1;
+            (files + copies, bytes + size * copies as u64)
+        })
+}
+
+/// 判断捕获路径是否命中排除列表
+///
+/// # 参数
+///
+/// - `path` - 捕获的文件路径
+/// - `exclude` - 小写的排除路径列表
+///
+/// # 返回值
+///
+/// - `true` - 路径中包含任一排除项（不区分大小写）
+/// - `false` - 未命中
+pub(crate) fn is_capture_excluded(path: &str, exclude: &[String]) -> bool {
+    let path = path.to_ascii_lowercase();
+    exclude.iter().any(|exclude_path| path.contains(exclude_path.as_str()))
+}
+
+/// 获取消息回调中的路径相对应用根目录的部分
+///
+/// # 参数
+///
+/// - `path` - 消息中的完整路径
+/// - `root` - 应用镜像时传入的根目录
+///
+/// # 返回值
+///
+/// - `Some(&str)` - 相对路径（不含前导 `\`）
+/// - `None` - 路径不在根目录下，或为根目录本身
+pub(crate) fn tree_relative_path<'a>(path: &'a str, root: &str) -> Option<&'a str> {
+    let prefix = path.get(..root.len())?;
+    if !prefix.eq_ignore_ascii_case(root) {
+        return None;
+    }
+    let relative = path[root.len()..].trim_start_matches('\\');
+    (!relative.is_empty()).then_some(relative)
+}
+
+/// 判断操作路径是否命中包含列表
+///
+/// # 参数
+///
+/// - `path` - 文件操作路径
+/// - `include` - 包含路径列表（`None` 或为空表示不限制）
+///
+/// # 返回值
+///
+/// - `true` - 未指定包含列表，或路径中包含任一包含项（不区分大小写）
+/// - `false` - 未命中
+pub(crate) fn is_included(path: &str, include: Option<&[String]>) -> bool {
+    match include {
+        Some(include) if !include.is_empty() => {
+            let path = path.to_ascii_lowercase();
+            include.iter().any(|item| path.contains(&item.to_ascii_lowercase()))
+        }
+        _ => true,
+    }
+}
+
+/// 校验包含列表（空的包含列表会被 [`is_included`] 视为不限制，与指定包含列表的意图不符）
+///
+/// # 返回值
+///
+/// - `Ok(())` - 未指定包含列表，或列表中至少有一个路径
+/// - `Err(anyhow::Error)` - 指定了空的包含列表
+pub(crate) fn check_include(include: Option<&[String]>) -> Result<()> {
+    if include.is_some_and(<[String]>::is_empty) {
+        return Err(anyhow!("{}", t!("create_patch.empty_include")));
+    }
+    Ok(())
+}
+
+/// 枚举镜像文件回调函数，`pvUserData` 指向 `TreeCollector`
+#[allow(non_snake_case)]
+extern "system" fn TreeCallback(
+    dwMessageId: u32,
+    wParam: usize,
+    lParam: isize,
+    pvUserData: *mut std::ffi::c_void,
+) -> u32 {
+    if wParam == 0 || pvUserData.is_null() {
+        return 0;
+    }
+    let collector = unsafe { &mut *(pvUserData as *mut TreeCollector) };
+    let path_ptr = wParam as *const u16;
+    let path_str = unsafe {
+        let mut len = 0;
+        while *path_ptr.offset(len) != 0 {
+            len += 1;
+        }
+        String::from_utf16_lossy(std::slice::from_raw_parts(path_ptr, len as usize))
+    };
+    let Some(relative) = tree_relative_path(&path_str, &collector.root) else {
+        return 0;
+    };
+
+    match dwMessageId {
+        // 处理回调：记录路径，跳过系统路径
+        WIM_MSG_PROCESS => {
+            if is_capture_excluded(relative, collector.exclude) {
+                let p_bool = lParam as *mut i32;
+                if !p_bool.is_null() {
+                    unsafe {
+                        ptr::write(p_bool, 0);
+                    }
+                }
+            } else {
+                collector.entries.push(TreeEntry {
+                    path: relative.to_string(),
+                    is_dir: false,
+                    size: None,
+                });
+            }
+        }
+        // 文件信息回调：补充大小与目录标识（紧跟在同一路径的处理回调之后）
+        WIM_MSG_FILEINFO => {
+            let find_data = lParam as *const WIN32_FIND_DATAW;
+            if !find_data.is_null()
+                && let Some(entry) = collector.entries.iter_mut().rev().find(|entry| entry.path == relative)
+            {
+                let find_data = unsafe { &*find_data };
+                entry.is_dir = find_data.dwFileAttributes & FILE_ATTRIBUTE_DIRECTORY.0 != 0;
+                entry.size = (!entry.is_dir)
+                    .then(|| (u64::from(find_data.nFileSizeHigh) << 32) | u64::from(find_data.nFileSizeLow));
+            }
+        }
+        _ => {}
+    }
+    // 返回0表示继续处理
+    0
+}
+
+/// 捕获补丁镜像回调函数，`pvUserData` 指向 [`CaptureState`]
+#[allow(non_snake_case)]
+pub(crate) extern "system" fn CaptureCallback(
+    dwMessageId: u32,
+    wParam: usize,
+    lParam: isize,
+    pvUserData: *mut std::ffi::c_void,
+) -> u32 {
+    match dwMessageId {
+        // 进度回调
+        WIM_MSG_PROGRESS => {
+            // println!("进度: {}, 剩余: {}秒", wParam, lParam / 1000);
+        }
+        // 处理回调
+        WIM_MSG_PROCESS => {
+            if wParam != 0 && !pvUserData.is_null() {
+                let path_ptr = wParam as *mut u16;
+                let path_str = unsafe {
+                    let mut len = 0;
+                    while *path_ptr.offset(len) != 0 {
+                        len += 1;
+                    }
+                    String::from_utf16_lossy(std::slice::from_raw_parts(path_ptr, len as usize))
+                };
+
+                // 请求中止时停止捕获，已计数的文件数量由调用方报告
+                let state = unsafe { &*(pvUserData as *const CaptureState) };
+                if (state.should_abort)() {
+                    return WIM_MSG_ABORT_IMAGE;
+                }
+
+                // 过滤排除的文件和目录，其余计入已捕获数量
+                if is_capture_excluded(&path_str, state.exclude) {
+                    let p_bool = lParam as *mut i32;
+                    if !p_bool.is_null() {
+                        unsafe {
+                            ptr::write(p_bool, 0);
+                        }
+                    }
+                } else {
+                    state.processed.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+        _ => {}
+    }
+    // 返回0表示继续处理
+    0
 }
@@ -0,0 +1,358 @@
+use crate::console::{ConsoleType, write_console};
+use crate::is_tty;
+use crate::utils::format_bytes;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use rust_i18n::t;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// 非终端环境下输出文件操作进度汇总的间隔
+const REPORT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// 单个文件操作的进度
+pub struct OperationProgress<'a> {
+    /// 当前操作的序号（从 1 开始）
+    pub position: u64,
+    /// 操作总数
+    pub total: u64,
+    /// 已处理的数据量（字节）
+    pub processed_bytes: u64,
+    /// 需要处理的数据总量（字节）
+    pub total_bytes: u64,
+    /// 操作描述，如 `新增 \Windows\notepad.exe`
+    pub message: &'a str,
+}
+
+/// 进度观察者
+///
+/// `WimPatch` 在创建与应用补丁的各阶段（挂载、比较、捕获、逐个文件操作）调用观察者的回调，
+/// 库使用者可实现此 trait 自行显示进度。所有回调默认均为空操作。
+///
+/// `task` 为正在处理的镜像索引，同时处理多个镜像时用于区分事件来源
+pub trait ProgressObserver: Send + Sync {
+    /// 开始处理一个镜像，`steps` 为阶段总数
+    fn task_started(&self, _task: u32, _steps: u64) {}
+
+    /// 进入新的阶段，`stage` 为本地化的阶段描述
+    fn stage(&self, _task: u32, _stage: &str) {}
+
+    /// 完成一个阶段
+    fn step(&self, _task: u32) {}
+
+    /// 镜像处理完成
+    fn task_finished(&self, _task: u32) {}
+
+    /// 开始逐个处理文件操作，`total` 为 `None` 表示总数尚未确定（仍在比较目录）
+    fn operations_started(&self, _task: u32, _total: Option<u64>) {}
+
+    /// 处理单个文件操作
+    fn operation(&self, _task: u32, _progress: &OperationProgress) {}
+
+    /// 文件操作处理完成
+    fn operations_finished(&self, _task: u32) {}
+
+    /// 开始应用所有镜像的补丁，`total` 为所有匹配的补丁链中文件操作的总数
+    fn overall_started(&self, _total: u64) {}
+
+    /// 开始处理一个文件操作（跨镜像累计，用于显示总进度）
+    fn overall_step(&self) {}
+
+    /// 所有镜像的文件操作处理完成
+    fn overall_finished(&self) {}
+
+    /// 输出不打断进度显示的消息（如强制模式下被跳过的错误）
+    fn message(&self, _task: u32, _message: &str) {}
+
+    /// 所有处理结束，清除进度显示
+    fn clear(&self) {}
+}
+
+/// 总体进度守卫：创建时开始总体进度，离开作用域时（包括出错提前返回）结束总体进度
+pub(crate) struct OverallProgress<'a> {
+    progress: &'a dyn ProgressObserver,
+}
+
+impl<'a> OverallProgress<'a> {
+    /// 开始总体进度
+    ///
+    /// # 参数
+    ///
+    /// - `progress` - 进度观察者
+    /// - `total` - 所有匹配的补丁链中文件操作的总数
+    pub(crate) fn start(progress: &'a dyn ProgressObserver, total: u64) -> Self {
+        progress.overall_started(total);
+        Self { progress }
+    }
+}
+
+impl Drop for OverallProgress<'_> {
+    fn drop(&mut self) {
+        self.progress.overall_finished();
+    }
+}
+
+/// 不输出任何进度的观察者（`WimPatch` 的默认观察者）
+pub struct NoProgress;
+
+impl ProgressObserver for NoProgress {}
+
+/// 命令行进度观察者：终端中使用 indicatif 显示进度条，非终端环境下逐行输出阶段与文件操作
+#[derive(Default)]
+pub struct ConsoleProgress {
+    multi_pb: MultiProgress,
+    /// 各镜像的阶段进度条
+    tasks: Mutex<BTreeMap<u32, ProgressBar>>,
+    /// 各镜像的文件操作进度条、上次输出进度汇总的时间，以及已处理的数据量
+    operations: Mutex<HashMap<u32, (ProgressBar, Instant, u64)>>,
+    /// 跨所有镜像的总进度条
+    overall: Mutex<Option<ProgressBar>>,
+    /// 非终端环境下输出进度汇总的间隔，指定时不再逐行输出文件操作
+    interval: Option<Duration>,
+}
+
+impl ConsoleProgress {
+    /// 创建非终端环境下仅按间隔输出进度汇总的观察者（不逐行输出文件操作，适用于 CI 日志）
+    ///
+    /// # 参数
+    /// - `interval`: 输出进度汇总的间隔
+    pub fn with_interval(interval: Duration) -> Self {
+        Self {
+            interval: Some(interval),
+            ..Default::default()
+        }
+    }
+
+    /// 获取镜像的阶段进度条
+    fn task_bar(&self, task: u32) -> Option<ProgressBar> {
+        self.tasks.lock().ok()?.get(&task).cloned()
+    }
+
+    /// 文件操作进度条样式
+    fn operation_style() -> ProgressStyle {
+        ProgressStyle::with_template("[{bar:30}] {pos}/{len} {prefix} ETA {eta} {wide_msg}")
+            .unwrap()
+            .progress_chars("=> ")
+    }
+}
+
+impl ProgressObserver for ConsoleProgress {
+    fn task_started(&self, task: u32, steps: u64) {
+        let main_pb = ProgressBar::new(steps);
+        main_pb.set_style(
+            ProgressStyle::with_template("{prefix:.bold.dim} [{elapsed_precise}] [{bar}] {pos}/{len}: {msg}")
+                .unwrap()
+                .progress_chars("=> "),
+        );
+        if let Ok(mut tasks) = self.tasks.lock() {
+            // 并行处理多个镜像时进度条按镜像索引排列，而不是按开始处理的顺序
+            let main_pb = match tasks.range(task + 1..).next() {
+                Some((_, next_pb)) => self.multi_pb.insert_before(next_pb, main_pb),
+                None => self.multi_pb.add(main_pb),
+            };
+            main_pb.enable_steady_tick(Duration::from_millis(80));
+            // 同时处理多个镜像时以镜像索引区分进度条
+            if !tasks.is_empty() {
+                main_pb.set_prefix(format!("#{}", task));
+                for (task, pb) in tasks.iter() {
+                    pb.set_prefix(format!("#{}", task));
+                }
+            }
+            tasks.insert(task, main_pb);
+        }
+    }
+
+    fn stage(&self, task: u32, stage: &str) {
+        if let Some(main_pb) = self.task_bar(task) {
+            main_pb.set_message(stage.to_string());
+        }
+        if !is_tty() {
+            write_console(ConsoleType::Info, stage);
+        }
+    }
+
+    fn step(&self, task: u32) {
+        if let Some(main_pb) = self.task_bar(task) {
+            main_pb.inc(1);
+        }
+    }
+
+    fn task_finished(&self, task: u32) {
+        if let Some(main_pb) = self.tasks.lock().ok().and_then(|mut tasks| tasks.remove(&task)) {
+            main_pb.finish_and_clear();
+        }
+    }
+
+    fn operations_started(&self, task: u32, total: Option<u64>) {
+        let sub_pb = match total {
+            Some(total) => {
+                let sub_pb = ProgressBar::new(total);
+                sub_pb.set_style(Self::operation_style());
+                sub_pb
+            }
+            // 总数未确定前显示旋转图标
+            None => {
+                let sub_pb = ProgressBar::new_spinner();
+                sub_pb.set_style(
+                    ProgressStyle::with_template("{prefix:.bold.dim} {spinner} {wide_msg}")
+                        .unwrap()
+                        .tick_strings(&["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"]),
+                );
+                sub_pb
+            }
+        };
+        // 文件操作进度条紧跟在所属镜像的阶段进度条之后
+        let sub_pb = match self.task_bar(task) {
+            Some(main_pb) => self.multi_pb.insert_after(&main_pb, sub_pb),
+            None => self.multi_pb.add(sub_pb),
+        };
+        sub_pb.enable_steady_tick(Duration::from_millis(80));
+        if let Ok(mut operations) = self.operations.lock() {
+            operations.insert(task, (sub_pb, Instant::now(), 0));
+        }
+    }
+
+    fn operation(&self, task: u32, progress: &OperationProgress) {
+        let Ok(mut operations) = self.operations.lock() else {
+            return;
+        };
+        let Some((sub_pb, last_report, processed_bytes)) = operations.get_mut(&task) else {
+            return;
+        };
+        *processed_bytes = progress.processed_bytes;
+
+        // 得到操作总数后切换为确定进度
+        if sub_pb.length() != Some(progress.total) {
+            sub_pb.set_length(progress.total);
+            sub_pb.set_style(Self::operation_style());
+        }
+        sub_pb.set_position(progress.position);
+        sub_pb.set_prefix(format!(
+            "({}/{})",
+            format_bytes(progress.processed_bytes),
+            format_bytes(progress.total_bytes)
+        ));
+        sub_pb.set_message(progress.message.to_string());
+
+        if !is_tty() {
+            // 紧凑模式：仅按间隔输出进度汇总
+            if let Some(interval) = self.interval {
+                if last_report.elapsed() >= interval {
+                    write_console(
+                        ConsoleType::Info,
+                        &format_progress_summary(
+                            progress.position,
+                            progress.total,
+                            progress.processed_bytes,
+                            sub_pb.elapsed(),
+                        ),
+                    );
+                    *last_report = Instant::now();
+                }
+                return;
+            }
+
+            println!("({}/{}) {}", progress.position, progress.total, progress.message);
+            if last_report.elapsed() >= REPORT_INTERVAL {
+                write_console(
+                    ConsoleType::Info,
+                    &format!(
+                        "{} {}/{} ({}/{})",
+                        t!("apply_patch.progress"),
+                        progress.position,
+                        progress.total,
+                        format_bytes(progress.processed_bytes),
+                        format_bytes(progress.total_bytes)
+                    ),
+                );
+                *last_report = Instant::now();
+            }
+        }
+    }
+
+    fn operations_finished(&self, task: u32) {
+        if let Some((sub_pb, _, processed_bytes)) = self
+            .operations
+            .lock()
+            .ok()
+            .and_then(|mut operations| operations.remove(&task))
+        {
+            if let Some(length) = sub_pb.length() {
+                sub_pb.set_position(length);
+                // 紧凑模式下输出最终的进度汇总
+                if self.interval.is_some() && !is_tty() {
+                    write_console(
+                        ConsoleType::Info,
+                        &format_progress_summary(length, length, processed_bytes, sub_pb.elapsed()),
+                    );
+                }
+            }
+            sub_pb.finish_and_clear();
+        }
+    }
+
+    fn overall_started(&self, total: u64) {
+        let overall_pb = self.multi_pb.insert(0, ProgressBar::new(total));
+        overall_pb.set_style(
+            ProgressStyle::with_template("{prefix:.bold} [{elapsed_precise}] [{bar}] {percent}% ({pos}/{len})")
+                .unwrap()
+                .progress_chars("=> "),
+        );
+        overall_pb.set_prefix(t!("apply_patch.overall").to_string());
+        if let Ok(mut overall) = self.overall.lock() {
+            *overall = Some(overall_pb);
+        }
+    }
+
+    fn overall_step(&self) {
+        if let Some(overall_pb) = self.overall.lock().ok().and_then(|overall| overall.clone()) {
+            overall_pb.inc(1);
+        }
+    }
+
+    fn overall_finished(&self) {
+        if let Some(overall_pb) = self.overall.lock().ok().and_then(|mut overall| overall.take()) {
+            overall_pb.finish_and_clear();
+        }
+    }
+
+    fn message(&self, _task: u32, message: &str) {
+        if is_tty() {
+            self.multi_pb.println(message).ok();
+        } else {
+            println!("{}", message);
+        }
+    }
+
+    fn clear(&self) {
+        self.multi_pb.clear().ok();
+    }
+}
+
+/// 格式化紧凑模式的进度汇总，如 `Progress 4123/10000 ops, 41%, 320.00 MB, 00:02:15 elapsed`
+///
+/// # 参数
+/// - `position`: 已处理的操作数量
+/// - `total`: 操作总数
+/// - `processed_bytes`: 已处理的数据量（字节）
+/// - `elapsed`: 开始处理文件操作后经过的时间
+pub(crate) fn format_progress_summary(position: u64, total: u64, processed_bytes: u64, elapsed: Duration) -> String {
+    let percent = match total {
+        0 => 100,
+        total => position.min(total) * 100 / total,
+    };
+    let seconds = elapsed.as_secs();
+    let elapsed = format!("{:02}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60);
+    format!(
+        "{} {}",
+        t!("apply_patch.progress"),
+        t!(
+            "apply_patch.progress_summary",
+            position = position,
+            total = total,
+            percent = percent,
+            bytes = format_bytes(processed_bytes),
+            elapsed = elapsed
+        )
+    )
+}
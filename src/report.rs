@@ -0,0 +1,459 @@
+use crate::manifest::{Action, Operation, PatchManifest};
+use crate::patch::{MatchConfidence, compression_name};
+use crate::utils::format_bytes;
+use crate::wimgapi::WimInfo;
+use anyhow::{Context, Result};
+use chrono::Local;
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+
+/// 文件操作的执行结果
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OperationOutcome {
+    /// 已应用
+    Applied,
+    /// 目标文件内容已与补丁一致，未重复写入
+    AlreadyApplied,
+    /// 按排除规则或路径重映射跳过
+    Skipped,
+    /// 执行失败（强制模式下跳过的错误，或导致应用中止的错误）
+    Failed,
+}
+
+/// 文件操作结果统计
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize)]
+pub struct OperationCounts {
+    pub applied: u64,
+    pub skipped: u64,
+    pub failed: u64,
+    pub already_applied: u64,
+}
+
+impl OperationCounts {
+    /// 记录一个文件操作的结果
+    pub fn record(&mut self, outcome: OperationOutcome) {
+        match outcome {
+            OperationOutcome::Applied => self.applied += 1,
+            OperationOutcome::AlreadyApplied => self.already_applied += 1,
+            OperationOutcome::Skipped => self.skipped += 1,
+            OperationOutcome::Failed => self.failed += 1,
+        }
+    }
+
+    /// 累加另一组文件操作结果统计
+    pub fn merge(&mut self, other: OperationCounts) {
+        self.applied += other.applied;
+        self.skipped += other.skipped;
+        self.failed += other.failed;
+        self.already_applied += other.already_applied;
+    }
+}
+
+/// 补丁链中的单个补丁
+#[derive(Debug, Clone, Serialize)]
+pub struct ReportPatch {
+    /// 补丁在补丁包中的镜像索引
+    pub index: u32,
+    pub id: String,
+    pub name: String,
+    pub version: String,
+}
+
+/// 单个基础镜像的应用结果
+#[derive(Debug, Clone, Serialize)]
+pub struct ReportImage {
+    /// 基础镜像索引
+    pub base_index: u32,
+    /// 解析出的补丁链
+    pub patches: Vec<ReportPatch>,
+    /// 补丁链中最弱的基础镜像匹配程度
+    pub match_confidence: MatchConfidence,
+    /// 文件操作结果统计
+    pub operations: OperationCounts,
+    /// 补丁链是否已全部应用
+    pub completed: bool,
+}
+
+/// 目标镜像统计信息
+#[derive(Debug, Clone, Serialize)]
+pub struct TargetStats {
+    pub image_count: u32,
+    pub size: u64,
+}
+
+/// 补丁文件的 WIM 属性
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PatchAttributes {
+    /// 压缩类型名称
+    pub compression: String,
+    /// 分卷文件中的部件号
+    pub part_number: u16,
+    /// 分卷文件的部件总数
+    pub total_parts: u16,
+    /// 可启动镜像索引（0 表示没有可启动镜像）
+    pub boot_index: u32,
+    /// 已设置的属性名称
+    pub attributes: Vec<String>,
+}
+
+impl From<&WimInfo> for PatchAttributes {
+    fn from(info: &WimInfo) -> Self {
+        Self {
+            compression: compression_name(info.compression_type).to_string(),
+            part_number: info.part_number,
+            total_parts: info.total_parts,
+            boot_index: info.boot_index,
+            attributes: info.attribute_names().into_iter().map(String::from).collect(),
+        }
+    }
+}
+
+impl PatchAttributes {
+    /// 生成文本信息
+    ///
+    /// # 参数
+    ///
+    /// - `label_w` - 标签列宽度
+    pub fn to_text(&self, label_w: usize) -> String {
+        let mut result = String::new();
+        result.push_str(&format!("{:<label_w$} {}\n", "Compression:", self.compression));
+        result.push_str(&format!(
+            "{:<label_w$} {}/{}\n",
+            "Part:", self.part_number, self.total_parts
+        ));
+        result.push_str(&format!("{:<label_w$} {}\n", "Boot Index:", self.boot_index));
+        result.push_str(&format!(
+            "{:<label_w$} {}\n",
+            "Attributes:",
+            match self.attributes.is_empty() {
+                true => "-".to_string(),
+                false => self.attributes.join(", "),
+            }
+        ));
+        result
+    }
+}
+
+/// 应用补丁结果报告
+///
+/// 应用过程中逐步记录，应用失败时同样写入，保留失败前的进度
+#[derive(Debug, Clone, Serialize)]
+pub struct ApplyReport {
+    pub tool_version: String,
+    pub timestamp: String,
+    pub base_image: String,
+    pub patch_image: String,
+    /// 补丁文件的 WIM 属性
+    pub patch_attributes: Option<PatchAttributes>,
+    pub target_image: String,
+    pub base_guid: Option<String>,
+    pub images: Vec<ReportImage>,
+    /// 所有镜像的文件操作结果统计
+    pub operations: OperationCounts,
+    pub target: Option<TargetStats>,
+    pub success: bool,
+    pub error: Option<String>,
+}
+
+impl ApplyReport {
+    /// 开始记录一次补丁应用
+    ///
+    /// # 参数
+    ///
+    /// - `base_image` - 基础镜像路径
+    /// - `patch_image` - 补丁镜像路径
+    /// - `target_image` - 目标镜像路径
+    pub fn new(base_image: &Path, patch_image: &Path, target_image: &Path) -> Self {
+        Self {
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+            timestamp: Local::now().to_rfc3339(),
+            base_image: base_image.display().to_string(),
+            patch_image: patch_image.display().to_string(),
+            patch_attributes: None,
+            target_image: target_image.display().to_string(),
+            base_guid: None,
+            images: Vec::new(),
+            operations: OperationCounts::default(),
+            target: None,
+            success: false,
+            error: None,
+        }
+    }
+
+    /// 记录开始应用到基础镜像的补丁链
+    pub fn start_image(
+        &mut self,
+        base_index: u32,
+        patch_chain: &[(u32, PatchManifest)],
+        match_confidence: MatchConfidence,
+    ) {
+        self.images.push(ReportImage {
+            base_index,
+            match_confidence,
+            patches: patch_chain
+                .iter()
+                .map(|(index, patch)| ReportPatch {
+                    index: *index,
+                    id: patch.id.clone(),
+                    name: patch.name.clone(),
+                    version: patch.patch_version.clone(),
+                })
+                .collect(),
+            operations: OperationCounts::default(),
+            completed: false,
+        });
+    }
+
+    /// 获取最近开始应用的指定基础镜像
+    fn image_mut(&mut self, base_index: u32) -> Option<&mut ReportImage> {
+        self.images
+            .iter_mut()
+            .rev()
+            .find(|image| image.base_index == base_index)
+    }
+
+    /// 标记基础镜像的补丁链已全部应用
+    pub fn finish_image(&mut self, base_index: u32) {
+        if let Some(image) = self.image_mut(base_index) {
+            image.completed = true;
+        }
+    }
+
+    /// 记录基础镜像中一个文件操作的结果
+    pub fn record_operation(&mut self, base_index: u32, outcome: OperationOutcome) {
+        if let Some(image) = self.image_mut(base_index) {
+            image.operations.record(outcome);
+        }
+        self.operations.record(outcome);
+    }
+
+    /// 记录基础镜像中一组文件操作的结果
+    pub fn record_operations(&mut self, base_index: u32, counts: OperationCounts) {
+        if let Some(image) = self.image_mut(base_index) {
+            image.operations.merge(counts);
+        }
+        self.operations.merge(counts);
+    }
+
+    /// 生成文本报告
+    pub fn to_text(&self) -> String {
+        let label_w = 18;
+        let total_w = 40;
+        let mut result = String::new();
+
+        result.push_str("Apply Report:\n");
+        result.push_str(&format!("{:-^total_w$}\n", "-"));
+        result.push_str(&format!("{:<label_w$} {}\n", "Tool Version:", self.tool_version));
+        result.push_str(&format!("{:<label_w$} {}\n", "Timestamp:", self.timestamp));
+        result.push_str(&format!("{:<label_w$} {}\n", "Base Image:", self.base_image));
+        result.push_str(&format!("{:<label_w$} {}\n", "Patch Image:", self.patch_image));
+        if let Some(attributes) = &self.patch_attributes {
+            result.push_str(&attributes.to_text(label_w));
+        }
+        result.push_str(&format!("{:<label_w$} {}\n", "Target Image:", self.target_image));
+        result.push_str(&format!(
+            "{:<label_w$} {}\n",
+            "Base GUID:",
+            self.base_guid.as_deref().unwrap_or("-")
+        ));
+
+        // 各基础镜像的补丁链与操作统计
+        for image in &self.images {
+            result.push_str(&format!(
+                "\nIndex {} ({}):\n",
+                image.base_index,
+                if image.completed { "completed" } else { "incomplete" }
+            ));
+            result.push_str(&format!("{:-^total_w$}\n", "-"));
+            for patch in &image.patches {
+                result.push_str(&format!("  #{} {} {}\n", patch.index, patch.name, patch.version));
+            }
+            result.push_str(&format!("{:<label_w$} {}\n", "Match:", image.match_confidence.as_str()));
+            result.push_str(&format!(
+                "{:<label_w$} applied {} / skipped {} / failed {} / already applied {}\n",
+                "Operations:",
+                image.operations.applied,
+                image.operations.skipped,
+                image.operations.failed,
+                image.operations.already_applied
+            ));
+        }
+
+        result.push_str("\nSummary:\n");
+        result.push_str(&format!("{:-^total_w$}\n", "-"));
+        result.push_str(&format!(
+            "{:<label_w$} applied {} / skipped {} / failed {} / already applied {}\n",
+            "Operations:",
+            self.operations.applied,
+            self.operations.skipped,
+            self.operations.failed,
+            self.operations.already_applied
+        ));
+        if let Some(target) = &self.target {
+            result.push_str(&format!(
+                "{:<label_w$} {} images, {}\n",
+                "Target:",
+                target.image_count,
+                format_bytes(target.size)
+            ));
+        }
+        result.push_str(&format!(
+            "{:<label_w$} {}\n",
+            "Result:",
+            if self.success { "success" } else { "failed" }
+        ));
+        if let Some(error) = &self.error {
+            result.push_str(&format!("{:<label_w$} {}\n", "Error:", error));
+        }
+        result
+    }
+
+    /// 写入报告文件，扩展名为 `.json` 时写入 JSON，否则写入文本
+    ///
+    /// # 参数
+    ///
+    /// - `path` - 报告文件路径
+    ///
+    /// # 返回值
+    ///
+    /// - `Ok(())` - 成功
+    /// - `Err(anyhow::Error)` - 失败
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let content = if path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("json"))
+        {
+            serde_json::to_string_pretty(self).with_context(|| "Serialize apply report error")?
+        } else {
+            self.to_text()
+        };
+        fs::write(path, content).with_context(|| format!("Write apply report failed: {}", path.display()))
+    }
+}
+
+/// 单个镜像索引的创建结果
+#[derive(Debug, Clone, Serialize)]
+pub struct CreateSummaryImage {
+    pub base_index: u32,
+    pub target_index: u32,
+    /// 补丁清单 ID（生成补丁清单前为空）
+    pub id: Option<String>,
+    pub version: String,
+    /// 存储类型
+    pub storage: String,
+    pub add: u64,
+    pub modify: u64,
+    pub delete: u64,
+    /// 补丁数据总字节数（未压缩）
+    pub payload_bytes: u64,
+    /// 补丁镜像是否已写入补丁文件
+    pub captured: bool,
+}
+
+/// 创建补丁结果摘要（`--summary-json`）
+///
+/// 创建过程中逐步记录，捕获失败时同样写入，保留已计算出的操作统计
+#[derive(Debug, Clone, Serialize)]
+pub struct CreateSummary {
+    pub tool_version: String,
+    pub timestamp: String,
+    pub base_image: String,
+    pub target_image: String,
+    pub patch_image: String,
+    pub images: Vec<CreateSummaryImage>,
+    pub success: bool,
+    pub error: Option<String>,
+}
+
+impl CreateSummary {
+    /// 开始记录一次补丁创建
+    ///
+    /// # 参数
+    ///
+    /// - `base_image` - 基础镜像路径
+    /// - `target_image` - 更新镜像路径
+    /// - `patch_image` - 补丁文件路径
+    pub fn new(base_image: &Path, target_image: &Path, patch_image: &Path) -> Self {
+        Self {
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+            timestamp: Local::now().to_rfc3339(),
+            base_image: base_image.display().to_string(),
+            target_image: target_image.display().to_string(),
+            patch_image: patch_image.display().to_string(),
+            images: Vec::new(),
+            success: false,
+            error: None,
+        }
+    }
+
+    /// 记录镜像索引比较得到的文件操作
+    ///
+    /// # 参数
+    ///
+    /// - `base_index` - 基础镜像索引
+    /// - `target_index` - 更新镜像索引
+    /// - `version` - 补丁版本
+    /// - `storage` - 存储类型
+    /// - `operations` - 文件操作列表
+    /// - `payload_bytes` - 补丁数据总字节数
+    pub fn record_image(
+        &mut self,
+        base_index: u32,
+        target_index: u32,
+        version: &str,
+        storage: &str,
+        operations: &[Operation],
+        payload_bytes: u64,
+    ) {
+        let count = |action: Action| operations.iter().filter(|operation| operation.action == action).count() as u64;
+        self.images.push(CreateSummaryImage {
+            base_index,
+            target_index,
+            id: None,
+            version: version.to_string(),
+            storage: storage.to_string(),
+            add: count(Action::Add),
+            modify: count(Action::Modify),
+            delete: count(Action::Delete),
+            payload_bytes,
+            captured: false,
+        });
+        // 并行构建时按基础镜像索引排列
+        self.images.sort_by_key(|image| image.base_index);
+    }
+
+    /// 获取指定基础镜像索引的记录
+    fn image_mut(&mut self, base_index: u32) -> Option<&mut CreateSummaryImage> {
+        self.images.iter_mut().find(|image| image.base_index == base_index)
+    }
+
+    /// 记录镜像索引生成的补丁清单 ID
+    pub fn set_manifest_id(&mut self, base_index: u32, id: &str) {
+        if let Some(image) = self.image_mut(base_index) {
+            image.id = Some(id.to_string());
+        }
+    }
+
+    /// 标记镜像索引的补丁镜像已写入补丁文件
+    pub fn finish_image(&mut self, base_index: u32) {
+        if let Some(image) = self.image_mut(base_index) {
+            image.captured = true;
+        }
+    }
+
+    /// 以 JSON 格式写入摘要文件
+    ///
+    /// # 参数
+    ///
+    /// - `path` - 摘要文件路径
+    ///
+    /// # 返回值
+    ///
+    /// - `Ok(())` - 成功
+    /// - `Err(anyhow::Error)` - 失败
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let content = serde_json::to_string_pretty(self).with_context(|| "Serialize create summary error")?;
+        fs::write(path, content).with_context(|| format!("Write create summary failed: {}", path.display()))
+    }
+}
@@ -0,0 +1,198 @@
+use crate::cli::{CompareMode, Compress, Preset};
+use crate::console::{ConsoleType, write_console};
+use crate::{get_temp_path, get_wimgapi_path};
+use crate::patch::{ApplyOptions, CreateOptions, WimPatch};
+use crate::progress::ConsoleProgress;
+use crate::utils::{compare_directories_with_total, get_tmp_name};
+use crate::wimgapi::{
+    WIM_COMPRESS_NONE, WIM_CREATE_ALWAYS, WIM_GENERIC_READ, WIM_GENERIC_WRITE, WIM_OPEN_EXISTING, Wimgapi,
+};
+use anyhow::{Context, Result, anyhow};
+use rust_i18n::t;
+use std::fs;
+use std::path::Path;
+
+/// 运行自检：构建两个小型目录并捕获为 WIM，创建补丁、应用补丁并校验结果
+///
+/// # 返回值
+///
+/// - `Ok(())` - 所有阶段均通过
+/// - `Err(anyhow::Error)` - 任一阶段失败，返回失败阶段及错误信息
+pub fn run_self_test() -> Result<()> {
+    // 加载 wimgapi.dll
+    let wimgapi = run_stage(&t!("self_test.load_wimgapi"), || {
+        Wimgapi::new(get_wimgapi_path()).with_context(|| "Failed to load wimgapi.dll".to_string())
+    })?;
+    let mut wim_patch = run_stage(&t!("self_test.init"), WimPatch::new)?;
+    wim_patch.set_progress_observer(ConsoleProgress::default());
+
+    // 准备测试文件
+    let test_dir = get_temp_path().join(get_tmp_name("selftest-", "", 6));
+    let base_dir = test_dir.join("base");
+    let target_dir = test_dir.join("target");
+    run_stage(&t!("self_test.prepare_files"), || {
+        prepare_test_files(&base_dir, &target_dir)
+    })?;
+
+    // 捕获基础镜像与更新镜像
+    let base_image = test_dir.join("base.wim");
+    let target_image = test_dir.join("target.wim");
+    run_stage(&t!("self_test.capture_base"), || {
+        capture_image(&wimgapi, &base_dir, &base_image)
+    })?;
+    run_stage(&t!("self_test.capture_target"), || {
+        capture_image(&wimgapi, &target_dir, &target_image)
+    })?;
+
+    // 创建补丁（需要挂载镜像）
+    let patch_image = test_dir.join("patch.wim");
+    let options = CreateOptions {
+        preset: Preset::Fast,
+        author: "WimPatch".to_string(),
+        name: "self-test".to_string(),
+        compress: Compress::None,
+        ..Default::default()
+    };
+    run_stage(&t!("self_test.create_patch"), || {
+        wim_patch
+            .create_patch(&base_image, Some(1), &target_image, Some(1), &patch_image, &options)
+            .inspect_err(|_| write_console(ConsoleType::Warning, &t!("self_test.privilege_hint")))
+    })?;
+
+    // 应用补丁
+    let result_image = test_dir.join("result.wim");
+    run_stage(&t!("self_test.apply_patch"), || {
+        wim_patch
+            .apply_patch(
+                &base_image,
+                Some(1),
+                &patch_image,
+                &result_image,
+                &ApplyOptions::default(),
+            )
+            .inspect_err(|_| write_console(ConsoleType::Warning, &t!("self_test.privilege_hint")))
+    })?;
+
+    // 校验应用结果与更新目录一致
+    let result_dir = test_dir.join("result");
+    run_stage(&t!("self_test.verify"), || {
+        verify_result(&wimgapi, &result_image, &result_dir, &target_dir)
+    })?;
+
+    fs::remove_dir_all(&test_dir).ok();
+    Ok(())
+}
+
+/// 执行自检阶段并输出结果
+///
+/// # 参数
+///
+/// - `name` - 阶段名称
+/// - `stage` - 阶段执行函数
+///
+/// # 返回值
+///
+/// - `Ok(T)` - 阶段通过，返回阶段结果
+/// - `Err(anyhow::Error)` - 阶段失败，错误信息中附带阶段名称
+fn run_stage<T>(name: &str, stage: impl FnOnce() -> Result<T>) -> Result<T> {
+    match stage() {
+        Ok(value) => {
+            write_console(ConsoleType::Success, &format!("{}: {}", name, t!("self_test.stage_ok")));
+            Ok(value)
+        }
+        Err(e) => {
+            write_console(
+                ConsoleType::Error,
+                &format!("{}: {}", name, t!("self_test.stage_failed")),
+            );
+            Err(e.context(name.to_string()))
+        }
+    }
+}
+
+/// 准备测试目录，覆盖新增、删除、修改与未变更的文件
+fn prepare_test_files(base_dir: &Path, target_dir: &Path) -> Result<()> {
+    fs::create_dir_all(base_dir.join("sub")).with_context(|| "Create base dir failed".to_string())?;
+    fs::create_dir_all(target_dir.join("sub")).with_context(|| "Create target dir failed".to_string())?;
+
+    // 未变更的文件
+    for dir in [base_dir, target_dir] {
+        fs::write(dir.join("unchanged.txt"), "WimPatch self-test\r\n")?;
+        fs::write(dir.join("sub").join("data.bin"), [0x5Au8; 4096])?;
+    }
+
+    // 修改的文件
+    fs::write(base_dir.join("modify.txt"), "version 1.0.0\r\n".repeat(64))?;
+    fs::write(target_dir.join("modify.txt"), "version 1.1.0\r\n".repeat(64))?;
+
+    // 删除的文件
+    fs::write(base_dir.join("delete.txt"), "deleted in target\r\n")?;
+
+    // 新增的文件与目录
+    fs::create_dir_all(target_dir.join("added"))?;
+    fs::write(target_dir.join("added").join("new.txt"), "added in target\r\n")?;
+    Ok(())
+}
+
+/// 将目录捕获为新的 WIM 文件
+fn capture_image(wimgapi: &Wimgapi, source: &Path, image: &Path) -> Result<()> {
+    let handle = wimgapi
+        .open(image, WIM_GENERIC_WRITE, WIM_CREATE_ALWAYS, WIM_COMPRESS_NONE)
+        .with_context(|| "Create image error")?;
+    if let Err(e) = wimgapi.set_temp_path(handle, &get_temp_path()) {
+        wimgapi.close(handle).ok();
+        return Err(anyhow!("Set temp path error: {}", e));
+    }
+    let image_handle = match wimgapi.capture(handle, source, 0) {
+        Ok(image_handle) => image_handle,
+        Err(e) => {
+            wimgapi.close(handle).ok();
+            return Err(anyhow!("Capture image error: {}", e));
+        }
+    };
+    wimgapi
+        .close(image_handle)
+        .with_context(|| "Close image handle error")?;
+    wimgapi.close(handle).with_context(|| "Close image error")?;
+    Ok(())
+}
+
+/// 释放应用后的镜像并与更新目录比较
+fn verify_result(wimgapi: &Wimgapi, result_image: &Path, result_dir: &Path, target_dir: &Path) -> Result<()> {
+    fs::create_dir_all(result_dir).with_context(|| "Create result dir failed".to_string())?;
+    let handle = wimgapi
+        .open(result_image, WIM_GENERIC_READ, WIM_OPEN_EXISTING, WIM_COMPRESS_NONE)
+        .with_context(|| "Open result image error")?;
+    if let Err(e) = wimgapi.set_temp_path(handle, &get_temp_path()) {
+        wimgapi.close(handle).ok();
+        return Err(anyhow!("Set temp path error: {}", e));
+    }
+    let image_handle = match wimgapi.load_image(handle, 1) {
+        Ok(image_handle) => image_handle,
+        Err(e) => {
+            wimgapi.close(handle).ok();
+            return Err(anyhow!("Load result image error: {}", e));
+        }
+    };
+    let result = wimgapi.apply_image(image_handle, result_dir, 0);
+    wimgapi.close(image_handle).ok();
+    wimgapi.close(handle).ok();
+    result.with_context(|| "Apply result image error")?;
+
+    // 比较文件差异（释放的文件修改时间与更新目录不同，按内容哈希比较）
+    let mut differences = Vec::new();
+    compare_directories_with_total(
+        target_dir,
+        result_dir,
+        CompareMode::Hash,
+        |_, _| {},
+        |_, _, _, path| {
+            differences.push(path.to_string());
+            true
+        },
+    )?;
+    if !differences.is_empty() {
+        return Err(anyhow!("{}", t!("self_test.mismatch", paths = differences.join(", "))));
+    }
+    Ok(())
+}
@@ -0,0 +1,206 @@
+use crate::manifest::PatchManifest;
+use crate::utils::{get_file_sha256, get_sha256, list_streams, long_path, stream_path};
+use anyhow::{Context, Result, anyhow};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use quick_xml::SeError;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// 签名内容的版本标识，签名格式变化时更换，旧版本的签名不会被误认为有效
+const SIGNATURE_CONTEXT: &str = "WimPatch-Signature-V2";
+
+/// 补丁镜像的数字签名（Ed25519），嵌入镜像信息 XML 中补丁清单的旁边
+///
+/// 签名覆盖补丁清单与补丁镜像中每个补丁数据文件的 SHA-256，签名内容见 [`signed_message`]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename = "PatchSignature")]
+pub struct PatchSignature {
+    /// 签名者标识（公钥 SHA-256 的前 16 个十六进制字符）
+    #[serde(rename = "Signer")]
+    pub signer: String,
+
+    /// 签名者公钥（Base64）
+    #[serde(rename = "PublicKey")]
+    pub public_key: String,
+
+    /// 签名值（Base64）
+    #[serde(rename = "Value")]
+    pub value: String,
+}
+
+impl PatchSignature {
+    /// 对补丁清单与补丁数据哈希签名
+    ///
+    /// # 参数
+    ///
+    /// * `key` - 签名私钥
+    /// * `manifest` - 补丁清单（应与应用时解析得到的清单一致）
+    /// * `payloads` - 补丁数据文件的相对路径与 SHA-256，见 [`payload_hashes`]
+    pub fn sign(key: &SigningKey, manifest: &PatchManifest, payloads: &[(String, String)]) -> Result<Self> {
+        let message = signed_message(manifest, payloads)?;
+        let verifying_key = key.verifying_key();
+        Ok(Self {
+            signer: key_fingerprint(&verifying_key),
+            public_key: STANDARD.encode(verifying_key.as_bytes()),
+            value: STANDARD.encode(key.sign(&message).to_bytes()),
+        })
+    }
+
+    /// 使用受信任的公钥校验签名
+    ///
+    /// # 参数
+    ///
+    /// * `key` - 受信任的签名者公钥
+    /// * `manifest` - 从补丁镜像解析得到的补丁清单
+    /// * `payloads` - 补丁镜像中补丁数据文件的相对路径与 SHA-256
+    ///
+    /// # 返回值
+    ///
+    /// * `Ok(())` - 签名有效且由该公钥签名
+    /// * `Err(anyhow::Error)` - 签名者不受信任、签名格式错误，或补丁清单与补丁数据被修改
+    pub fn verify(&self, key: &VerifyingKey, manifest: &PatchManifest, payloads: &[(String, String)]) -> Result<()> {
+        if STANDARD.decode(self.public_key.trim()).ok().as_deref() != Some(key.as_bytes().as_slice()) {
+            return Err(anyhow!("Patch is signed by an untrusted key: {}", self.signer));
+        }
+        let value: [u8; 64] = STANDARD
+            .decode(self.value.trim())
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| anyhow!("Invalid signature value"))?;
+        let message = signed_message(manifest, payloads)?;
+        key.verify_strict(&message, &Signature::from_bytes(&value))
+            .map_err(|_| anyhow!("Signature does not match the patch manifest and payloads"))
+    }
+
+    /// 生成XML字符串
+    pub fn to_xml(&self) -> Result<String, SeError> {
+        quick_xml::se::to_string(self)
+    }
+
+    /// 从镜像信息中解析签名
+    ///
+    /// # 参数
+    ///
+    /// * `image_info` - 补丁镜像的镜像信息 XML
+    ///
+    /// # 返回值
+    ///
+    /// * `Some(Ok(PatchSignature))` - 解析成功
+    /// * `Some(Err)` - 签名格式错误
+    /// * `None` - 补丁未签名
+    pub fn from_image_info(image_info: &str) -> Option<Result<Self>> {
+        let start = image_info.find("<PatchSignature>")?;
+        let end = image_info[start..].find("</PatchSignature>")? + start + "</PatchSignature>".len();
+        Some(quick_xml::de::from_str(&image_info[start..end]).with_context(|| "Parse patch signature failed"))
+    }
+}
+
+/// 生成签名内容：版本标识、补丁清单的 SHA-256，以及按路径排序的补丁数据哈希，每项一行
+///
+/// 补丁清单按 MessagePack 序列化后计算哈希，与清单在镜像信息中的存储格式无关
+///
+/// # 参数
+///
+/// * `manifest` - 补丁清单
+/// * `payloads` - 补丁数据文件的相对路径与 SHA-256
+pub fn signed_message(manifest: &PatchManifest, payloads: &[(String, String)]) -> Result<Vec<u8>> {
+    let manifest_data = rmp_serde::to_vec_named(manifest).with_context(|| "Serialize patch manifest failed")?;
+    let mut payloads = payloads.to_vec();
+    payloads.sort();
+    let mut message = format!("{}\nmanifest: {}\n", SIGNATURE_CONTEXT, get_sha256(&manifest_data));
+    for (path, sha256) in &payloads {
+        message.push_str(&format!("{} {}\n", sha256, path));
+    }
+    Ok(message.into_bytes())
+}
+
+/// 计算目录中所有补丁数据文件的 SHA-256
+///
+/// 文件的备用数据流（应用时随文件一起复制）按 `路径:数据流名称` 单独计入；
+/// 重解析点不跟随，计入其指向路径的 SHA-256
+///
+/// # 参数
+///
+/// * `dir` - 补丁数据目录（创建时的暂存目录，或应用时补丁镜像的挂载目录）
+/// * `skip` - 返回 `true` 的路径（及其子项）不计入，如捕获时排除的路径
+///
+/// # 返回值
+///
+/// * `Ok(Vec<(String, String)>)` - 按路径排序的相对路径（以 `\` 分隔）与 SHA-256
+/// * `Err(anyhow::Error)` - 读取目录或文件失败
+pub fn payload_hashes(dir: &Path, skip: impl Fn(&Path) -> bool) -> Result<Vec<(String, String)>> {
+    let mut hashes = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(current) = dirs.pop() {
+        for entry in fs::read_dir(&current).with_context(|| format!("Read directory failed: {}", current.display()))? {
+            let path = entry?.path();
+            if skip(&path) {
+                continue;
+            }
+            let file_type = fs::symlink_metadata(&path)
+                .with_context(|| format!("Get payload metadata failed: {}", path.display()))?
+                .file_type();
+            if file_type.is_dir() {
+                dirs.push(path);
+                continue;
+            }
+            let rel_path = path
+                .strip_prefix(dir)
+                .map_err(|e| anyhow!("{}: {}", e, path.display()))?
+                .to_string_lossy()
+                .to_string();
+            if file_type.is_symlink() {
+                let target = fs::read_link(&path).with_context(|| format!("Read link failed: {}", path.display()))?;
+                hashes.push((rel_path, get_sha256(target.to_string_lossy().as_bytes())));
+                continue;
+            }
+            let sha256 = get_file_sha256(long_path(&path), None)
+                .with_context(|| format!("Hash payload failed: {}", path.display()))?;
+            for (name, _) in list_streams(&path).with_context(|| format!("List streams failed: {}", path.display()))? {
+                let stream_sha256 = get_file_sha256(long_path(&stream_path(&path, &name)), None)
+                    .with_context(|| format!("Hash payload stream failed: {}:{}", path.display(), name))?;
+                hashes.push((format!("{}:{}", rel_path, name), stream_sha256));
+            }
+            hashes.push((rel_path, sha256));
+        }
+    }
+    hashes.sort();
+    Ok(hashes)
+}
+
+/// 计算公钥的签名者标识（公钥 SHA-256 的前 16 个十六进制字符）
+pub fn key_fingerprint(key: &VerifyingKey) -> String {
+    get_sha256(key.as_bytes())[..16].to_string()
+}
+
+/// 读取 Base64 编码的 32 字节密钥文件
+fn read_key_bytes(path: &Path) -> Result<[u8; 32]> {
+    let text = fs::read_to_string(path).with_context(|| format!("Read key file failed: {}", path.display()))?;
+    STANDARD
+        .decode(text.trim())
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| anyhow!("Key file must contain 32 bytes encoded as Base64: {}", path.display()))
+}
+
+/// 读取签名私钥文件（Base64 编码的 32 字节 Ed25519 私钥种子）
+///
+/// # 参数
+///
+/// * `path` - 私钥文件路径
+pub fn read_signing_key(path: &Path) -> Result<SigningKey> {
+    Ok(SigningKey::from_bytes(&read_key_bytes(path)?))
+}
+
+/// 读取签名者公钥文件（Base64 编码的 32 字节 Ed25519 公钥）
+///
+/// # 参数
+///
+/// * `path` - 公钥文件路径
+pub fn read_verifying_key(path: &Path) -> Result<VerifyingKey> {
+    VerifyingKey::from_bytes(&read_key_bytes(path)?)
+        .map_err(|_| anyhow!("Invalid Ed25519 public key: {}", path.display()))
+}
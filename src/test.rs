@@ -1,20 +1,111 @@
 #[cfg(test)]
 mod tests {
+    use crate::benchmark::{format_benchmark, run_benchmark};
     use crate::bsdiff::BsDiff;
-    use crate::manifest::{Action, ImageInfo, Operation, PatchManifest};
-    use crate::utils::{compare_directories, get_tmp_name, replace_xml_field, DiffType};
+    use crate::checksum::{Checksum, ChecksumImage};
+    use crate::cli::{
+        parse_patch_compress, parse_source_date, parse_subtree, parse_zstd_level, ApplyOrder, CompareMode, Compress,
+        ListSort, MatchMode, OnExisting, Preset, Storage,
+    };
+    use crate::hivediff::{HiveDiff, HiveKey, HiveOp, HiveValue};
+    use crate::manifest::{decode_xml_path, encode_xml_path, Action, ImageInfo, Operation, PatchManifest, StreamInfo};
+    use crate::patch::{
+        acl_flags, applied_version, canonicalize_lenient, chain_operation_count, check_include,
+        check_target_on_existing, check_zstd_level, compression_name, copy_creation_time, count_shared_payloads,
+        dominant_storage, embed_patch_fields, expand_base_options, expand_base_stem, expected_target_info,
+        extract_image_dir, file_matches_sha256, find_image_by_name, find_merge_conflicts, image_stat_divergence,
+        is_appendable_to, is_capture_excluded, is_image_mounted, is_included, is_same_source, manifest_output_path,
+        merge_output_compression, order_operations, output_disposition, preserved_boot_index, remap_operation_path,
+        set_applied_version, split_output_names, tree_relative_path, unmatched_indices, volatile_pattern,
+        wim_compression_type, zstd_level, ApplyOptions, CaptureCallback, CaptureQueue, CaptureState, CreateOptions,
+        DroppedDiffs, MatchConfidence, MergeConflict, MergeOptions, WimPatch, APPLIED_VERSION_FIELD,
+        DEFAULT_MAX_CHAIN_LENGTH, DIR_PATCH_MANIFEST, DIR_PATCH_PAYLOAD, DIR_PATCH_SIGNATURE, STAGED_PAYLOAD_SUFFIX,
+    };
+    use crate::progress::{format_progress_summary, OperationProgress, OverallProgress, ProgressObserver};
+    use crate::report::{ApplyReport, CreateSummary, OperationCounts, OperationOutcome, PatchAttributes};
+    use crate::signature::{key_fingerprint, payload_hashes, read_signing_key, read_verifying_key, PatchSignature};
+    use crate::utils::{
+        build_file_map, compare_directories, compare_directories_with_total, compare_subtree_with_total, copy_dir,
+        copy_hashed, copy_sparse, create_tmp_dir, for_each_bounded, format_bytes, format_guid, get_file_sha256,
+        get_sha256, get_tmp_name, get_tmp_path, get_xml_field, is_same_file, is_same_guid, is_sparse_file, link_or_copy,
+        list_streams, long_path, make_sparse, merge_pattern_file, normalize_guid, replace_xml_field, restore_name_case,
+        retry_io, run_post_apply, stream_path, wildcard_match, CompareOptions, DiffType, HashReader, ReparseFollow,
+        POST_APPLY_TARGET_ENV,
+    };
     use crate::wimgapi::{
-        Wimgapi, WIM_COMPRESS_LZX, WIM_COMPRESS_NONE, WIM_CREATE_ALWAYS, WIM_FLAG_MOUNT_READONLY,
-        WIM_GENERIC_MOUNT, WIM_GENERIC_READ, WIM_GENERIC_WRITE, WIM_MSG_PROCESS, WIM_MSG_PROGRESS,
+        decode_image_info, encode_image_info, wimgapi_search_paths, win32_error_message, HandleGuard, MountGuard,
+        WimApiError, WimInfo, Wimgapi, UTF16_BOM, WIM_ATTRIBUTE_NORMAL, WIM_ATTRIBUTE_READONLY, WIM_ATTRIBUTE_SPANNED,
+        WIM_COMPRESS_LZMS, WIM_COMPRESS_LZX, WIM_COMPRESS_NONE, WIM_COMPRESS_XPRESS, WIM_CREATE_ALWAYS, WIM_CREATE_NEW,
+        WIM_FLAG_MOUNT_READONLY, WIM_FLAG_NO_DIRACL, WIM_FLAG_NO_FILEACL, WIM_FLAG_VERIFY, WIM_GENERIC_MOUNT,
+        WIM_GENERIC_READ, WIM_GENERIC_WRITE, WIM_MSG_ABORT_IMAGE, WIM_MSG_PROCESS, WIM_MSG_PROGRESS, WIM_OPEN_ALWAYS,
         WIM_OPEN_EXISTING, WIM_REFERENCE_APPEND,
     };
     use crate::zstdiff::ZstdDiff;
     use crate::get_temp_path;
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+    use ed25519_dalek::SigningKey;
     use indicatif::{ProgressBar, ProgressStyle};
+    use std::io::Write;
+    use std::os::windows::fs::OpenOptionsExt;
     use std::path::{Path, PathBuf};
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
     use std::thread::sleep;
-    use std::time::Duration;
+    use std::time::{Duration, Instant};
     use std::{fs, ptr, thread};
+    use windows::core::GUID;
+
+    /// 创建测试用的补丁操作（其余字段为空）
+    fn test_operation(action: Action, path: &str, size: Option<u64>, storage: Option<&str>) -> Operation {
+        Operation {
+            action,
+            path: path.to_string(),
+            size,
+            storage: storage.map(|storage| storage.to_string()),
+            payload_ref: None,
+            full_fallback: false,
+            sparse: false,
+            case_rename: false,
+            streams: Vec::new(),
+            dict_window: None,
+            sha256: None,
+            base_sha256: None,
+        }
+    }
+
+    /// 创建测试用的补丁清单（名称为 `test-patch`，镜像信息为默认值）
+    fn test_manifest(version: &str, base_guid: &str, target_guid: &str, operations: &[Operation]) -> PatchManifest {
+        PatchManifest::new(
+            "test-patch",
+            "",
+            "",
+            version,
+            base_guid,
+            &ImageInfo::default(),
+            target_guid,
+            &ImageInfo::default(),
+            operations,
+            None,
+        )
+    }
+
+    /// 按内容哈希比较两个目录，返回有差异的路径（复制或应用后的文件修改时间与原文件不同）
+    fn hash_differences(base_dir: &Path, target_dir: &Path) -> Vec<String> {
+        let mut differences = Vec::new();
+        compare_directories_with_total(
+            base_dir,
+            target_dir,
+            CompareMode::Hash,
+            |_, _| {},
+            |_, _, _, path| {
+                differences.push(path.to_string());
+                true
+            },
+        )
+        .unwrap();
+        differences
+    }
 
     /// 进度条测试
     #[test]
@@ -117,8 +208,8 @@ mod tests {
             old_file.extension().unwrap().to_string_lossy()
         ));
 
-        ZstdDiff::file_diff(&old_file, updated_file, &patch_file, 9).unwrap();
-        ZstdDiff::file_patch(old_file, patch_file, new_file).unwrap();
+        ZstdDiff::file_diff(&old_file, updated_file, &patch_file, 9, None).unwrap();
+        ZstdDiff::file_patch(old_file, patch_file, new_file, None).unwrap();
     }
 
     // 回调函数，用于处理WIM消息并显示进度
@@ -195,7 +286,7 @@ mod tests {
         //     WIM_FLAG_EXCLUDE_HIDDEN | WIM_FLAG_EXCLUDE_SYSTEM | WIM_FLAG_EXCLUDE_CRITICAL;
 
         // 注册消息回调函数以显示进度和排除特定路径
-        wimgapi.register_message_callback(handle, WIMMessageCallback);
+        wimgapi.register_message_callback(handle, WIMMessageCallback, ptr::null_mut());
 
         // 捕获src目录到wim
         let hImage = wimgapi.capture(handle, &src, 0).unwrap();
@@ -291,7 +382,7 @@ mod tests {
             )
             .unwrap();
 
-        wimgapi.set_temp_path(h_wim, get_temp_path()).unwrap();
+        wimgapi.set_temp_path(h_wim, &get_temp_path()).unwrap();
         let h_image = wimgapi.load_image(h_wim, 1).unwrap();
         wimgapi
             .mount_image_handle(h_image, &mount_path, WIM_FLAG_MOUNT_READONLY)
@@ -415,18 +506,42 @@ mod tests {
             path: "file".to_string(),
             size: Some(0),
             storage: None,
+            payload_ref: None,
+            full_fallback: false,
+            sparse: false,
+            case_rename: false,
+            streams: Vec::new(),
+            dict_window: None,
+            sha256: None,
+            base_sha256: None,
         });
         operations.push(Operation {
             action: Action::Add,
             path: "file_2".to_string(),
             size: Some(0),
             storage: None,
+            payload_ref: None,
+            full_fallback: false,
+            sparse: false,
+            case_rename: false,
+            streams: Vec::new(),
+            dict_window: None,
+            sha256: None,
+            base_sha256: None,
         });
         operations.push(Operation {
             action: Action::Delete,
             path: "delete_file".to_string(),
             size: None,
             storage: None,
+            payload_ref: None,
+            full_fallback: false,
+            sparse: false,
+            case_rename: false,
+            streams: Vec::new(),
+            dict_window: None,
+            sha256: None,
+            base_sha256: None,
         });
         let manifest = PatchManifest::new(
             "test-patch",
@@ -438,12 +553,5717 @@ mod tests {
             "",
             &image_info,
             &operations,
+            None,
         );
 
         println!("{:#?}", manifest);
         println!("{}", manifest.to_xml().unwrap());
     }
 
+    /// 清单编码测试：XML 与二进制两种编码往返后得到相同的清单
+    #[test]
+    fn test_manifest_binary_roundtrip() {
+        let image_info = ImageInfo {
+            index: 1,
+            name: Some("Windows 11PE".to_string()),
+            dir_count: 10,
+            file_count: 20,
+            total_bytes: 4096,
+            ..Default::default()
+        };
+        let operations = vec![
+            Operation {
+                action: Action::Add,
+                path: "Windows\\System32\\new.dll".to_string(),
+                size: Some(1024),
+                storage: Some("full".to_string()),
+                payload_ref: None,
+                full_fallback: false,
+                sparse: false,
+                case_rename: false,
+                streams: Vec::new(),
+                dict_window: None,
+                sha256: None,
+                base_sha256: None,
+            },
+            Operation {
+                action: Action::Modify,
+                path: "Windows\\System32\\config.ini".to_string(),
+                size: Some(256),
+                storage: Some("zstd".to_string()),
+                payload_ref: Some("Windows\\System32\\other.ini".to_string()),
+                full_fallback: false,
+                sparse: false,
+                case_rename: false,
+                streams: Vec::new(),
+                dict_window: None,
+                sha256: None,
+                base_sha256: None,
+            },
+            Operation {
+                action: Action::Delete,
+                path: "Windows\\old.txt".to_string(),
+                size: None,
+                storage: None,
+                payload_ref: None,
+                full_fallback: false,
+                sparse: false,
+                case_rename: false,
+                streams: Vec::new(),
+                dict_window: None,
+                sha256: None,
+                base_sha256: None,
+            },
+        ];
+        let manifest = PatchManifest::new(
+            "test-patch",
+            "description",
+            "author",
+            "1.0.0",
+            "guid-base",
+            &image_info,
+            "guid-target",
+            &image_info,
+            &operations,
+            None,
+        );
+
+        let from_xml = PatchManifest::from_xml(&manifest.to_xml().unwrap()).unwrap();
+        let from_binary = PatchManifest::from_binary(&manifest.to_binary().unwrap()).unwrap();
+        assert_eq!(from_xml, manifest);
+        assert_eq!(from_binary, manifest);
+        assert_eq!(from_xml, from_binary);
+    }
+
+    /// 操作统计测试：统计新增/修改字节数、存储类型分布与最大的操作
+    #[test]
+    fn test_operation_details() {
+        let operations = vec![
+            test_operation(Action::Add, "big.bin", Some(4096), None),
+            test_operation(Action::Add, "dir", Some(0), None),
+            test_operation(Action::Modify, "a.dll", Some(2048), Some("zstd")),
+            test_operation(Action::Modify, "b.dll", Some(1024), Some("zstd")),
+            test_operation(Action::Modify, "c.dll", Some(512), Some("full")),
+            test_operation(Action::Delete, "old.txt", None, None),
+        ];
+
+        let details = WimPatch::format_operation_details(&operations, 2, 18);
+        assert!(details.contains(&format!("{:<18} {}", "Added Bytes:", format_bytes(4096))));
+        assert!(details.contains(&format!("{:<18} {}", "Modified Bytes:", format_bytes(3584))));
+        assert!(details.contains(&format!("  {:<16} {} ({})", "zstd", 2, format_bytes(3072))));
+        assert!(details.contains(&format!("  {:<16} {} ({})", "full", 1, format_bytes(512))));
+        assert!(details.contains("Largest Operations (top 2):"));
+        assert!(details.contains("big.bin") && details.contains("a.dll"));
+        assert!(!details.contains("b.dll") && !details.contains("old.txt"));
+    }
+
+    /// GUID 格式化测试：创建时写入清单的 GUID 在应用时仍能匹配（兼容旧版调试格式）
+    #[test]
+    fn test_format_guid() {
+        let guid = GUID::from_u128(0x3f2504e0_4f89_11d3_9a0c_0305e82c3301);
+        let image_info = ImageInfo::default();
+        let operations = vec![Operation {
+            action: Action::Add,
+            path: "file".to_string(),
+            size: Some(0),
+            storage: None,
+            payload_ref: None,
+            full_fallback: false,
+            sparse: false,
+            case_rename: false,
+            streams: Vec::new(),
+            dict_window: None,
+            sha256: None,
+            base_sha256: None,
+        }];
+
+        // 创建补丁时写入清单的 GUID
+        let manifest = PatchManifest::new(
+            "test-patch",
+            "",
+            "",
+            "1.0.0",
+            &format_guid(&guid),
+            &image_info,
+            &format_guid(&GUID::zeroed()),
+            &image_info,
+            &operations,
+            None,
+        );
+        assert_eq!(manifest.base_image_guid, "{3F2504E0-4F89-11D3-9A0C-0305E82C3301}");
+
+        // 经过 XML 序列化后，应用补丁时仍能匹配基础镜像 GUID
+        let manifest = PatchManifest::from_xml(&manifest.to_xml().unwrap()).unwrap();
+        assert!(is_same_guid(&format_guid(&guid), &manifest.base_image_guid));
+        assert!(!is_same_guid(&format_guid(&guid), &manifest.target_image_guid));
+
+        // 旧版补丁使用 GUID 调试格式存储，同样可以匹配
+        assert!(is_same_guid(&format!("{:?}", guid), &manifest.base_image_guid));
+
+        // 命令行输入的各种写法都规范化为写入清单的格式
+        for input in [
+            "{3F2504E0-4F89-11D3-9A0C-0305E82C3301}",
+            "{3f2504e0-4f89-11d3-9a0c-0305e82c3301}",
+            "3f2504e0-4f89-11d3-9a0c-0305e82c3301",
+            " 3F2504E04F8911D39A0C0305E82C3301 ",
+        ] {
+            assert_eq!(normalize_guid(input), Some(format_guid(&guid)));
+        }
+        assert_eq!(normalize_guid("not-a-guid"), None);
+    }
+
+    /// 指定基础镜像 GUID 测试：重新捕获的镜像使用原 GUID 匹配补丁，但仍校验镜像统计信息
+    #[test]
+    fn test_match_patch_base_guid() {
+        let wim_patch = WimPatch::new().unwrap();
+        let original = "{3F2504E0-4F89-11D3-9A0C-0305E82C3301}";
+        let recaptured = "{9A0C0305-E82C-3301-3F25-04E04F8911D3}";
+        let base_info = ImageInfo {
+            index: 1,
+            file_count: 10,
+            ..Default::default()
+        };
+        let manifest = PatchManifest::new(
+            "test-patch",
+            "",
+            "",
+            "1.0.0",
+            original,
+            &base_info,
+            "",
+            &ImageInfo::default(),
+            &[],
+            None,
+        );
+        let patches = vec![(1, manifest)];
+
+        // 重新捕获后 GUID 改变，无法匹配
+        let result = wim_patch
+            .match_patch(recaptured, &[base_info.clone()], &patches, &ApplyOptions::default())
+            .unwrap();
+        assert!(result.is_empty());
+
+        // 使用原 GUID 代替后可以匹配
+        let result = wim_patch
+            .match_patch(original, &[base_info.clone()], &patches, &ApplyOptions::default())
+            .unwrap();
+        assert_eq!(result.len(), 1);
+
+        // 内容统计不一致时仍然拒绝
+        let changed_info = ImageInfo {
+            file_count: 11,
+            ..base_info
+        };
+        assert!(
+            wim_patch
+                .match_patch(original, &[changed_info], &patches, &ApplyOptions::default())
+                .is_err()
+        );
+    }
+
+    /// 读取补丁清单测试：返回每个镜像索引对应的结构化补丁清单
+    #[test]
+    fn test_get_manifests() {
+        let root = std::env::temp_dir().join(get_tmp_name("manifests-", "", 6));
+        let source = root.join("source");
+        fs::create_dir_all(&source).unwrap();
+        fs::write(source.join("file.txt"), b"payload").unwrap();
+
+        // 捕获两个镜像并写入补丁清单
+        let wimgapi = Wimgapi::new(None).unwrap();
+        let patch_image = root.join("patch.wim");
+        let handle = wimgapi
+            .open(&patch_image, WIM_GENERIC_WRITE, WIM_CREATE_ALWAYS, WIM_COMPRESS_NONE)
+            .unwrap();
+        wimgapi.set_temp_path(handle, &get_temp_path()).unwrap();
+        for version in ["1.0.0", "1.1.0"] {
+            let image_handle = wimgapi.capture(handle, &source, 0).unwrap();
+            let manifest = PatchManifest::new(
+                "test-patch",
+                "",
+                "WimPatch",
+                version,
+                "{3F2504E0-4F89-11D3-9A0C-0305E82C3301}",
+                &ImageInfo::default(),
+                "",
+                &ImageInfo::default(),
+                &[],
+                None,
+            );
+            let image_info = wimgapi.get_image_info(image_handle).unwrap();
+            let image_info = image_info.replace("</IMAGE>", &format!("{}</IMAGE>", manifest.to_xml().unwrap()));
+            wimgapi.set_image_info(image_handle, &image_info).unwrap();
+            wimgapi.close(image_handle).unwrap();
+        }
+        wimgapi.close(handle).unwrap();
+
+        let manifests = WimPatch::new().unwrap().get_manifests(&patch_image).unwrap();
+        assert_eq!(manifests.len(), 2);
+        assert_eq!(manifests[0].0, 1);
+        assert_eq!(manifests[1].0, 2);
+        assert_eq!(manifests[0].1.patch_version, "1.0.0");
+        assert_eq!(manifests[1].1.patch_version, "1.1.0");
+        assert_eq!(manifests[1].1.name, "test-patch");
+        assert_eq!(manifests[1].1.author, "WimPatch");
+
+        // 提取清单：指定的索引不存在时报错且不写入文件
+        let wim_patch = WimPatch::new().unwrap();
+        let out = root.join("manifest.xml");
+        assert!(wim_patch.extract_manifest(&patch_image, Some(3), &out, false).is_err());
+        assert!(!out.exists());
+        let written = wim_patch.extract_manifest(&patch_image, Some(2), &out, false).unwrap();
+        assert_eq!(written, vec![out.clone()]);
+        let extracted = PatchManifest::from_xml(&fs::read_to_string(&out).unwrap()).unwrap();
+        assert_eq!(extracted.patch_version, "1.1.0");
+
+        // 提取所有清单时任一写入失败，已写入的文件同样删除
+        fs::create_dir_all(root.join("manifest-2.xml")).unwrap();
+        assert!(wim_patch.extract_manifest(&patch_image, None, &out, false).is_err());
+        assert!(!root.join("manifest-1.xml").exists());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// 数据流补丁测试：数据流写入临时文件后执行操作，无论操作与数据流读取是否成功都会删除临时文件
+    #[test]
+    fn test_with_spooled_patch() {
+        let mut spooled = PathBuf::new();
+        let content = WimPatch::with_spooled_patch(&b"WimPatch stream"[..], |patch| {
+            spooled = patch.to_path_buf();
+            Ok(fs::read(patch)?)
+        })
+        .unwrap();
+        assert_eq!(content, b"WimPatch stream");
+        assert!(!spooled.exists());
+
+        // 操作失败时同样删除临时文件
+        let result = WimPatch::with_spooled_patch(&b"invalid patch"[..], |patch| {
+            spooled = patch.to_path_buf();
+            WimPatch::new()?.get_manifests(patch)
+        });
+        assert!(result.is_err());
+        assert!(!spooled.exists());
+
+        // 读取数据流失败时不执行操作
+        struct FailingReader;
+        impl std::io::Read for FailingReader {
+            fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+                Err(std::io::Error::other("stream broken"))
+            }
+        }
+        let result = WimPatch::with_spooled_patch(FailingReader, |_| -> anyhow::Result<()> { unreachable!() });
+        assert!(result.is_err());
+    }
+
+    /// 展平存储类型测试：按修改操作中最常用的存储类型选择，数量相同时按 zstd、bsdiff、full 的顺序
+    #[test]
+    fn test_dominant_storage() {
+        let manifest = |storages: &[&str]| {
+            let mut operations: Vec<Operation> = storages
+                .iter()
+                .map(|storage| test_operation(Action::Modify, "file.bin", Some(1), Some(*storage)))
+                .collect();
+            operations.push(test_operation(Action::Add, "new.bin", Some(1), Some("full")));
+            test_manifest("1.0.0", "", "", &operations)
+        };
+        assert_eq!(dominant_storage(&[]), Storage::Zstd);
+        assert_eq!(dominant_storage(&[(1, manifest(&["bsdiff", "hive"]))]), Storage::Bsdiff);
+        assert_eq!(
+            dominant_storage(&[(1, manifest(&["full", "bsdiff"])), (2, manifest(&["full"]))]),
+            Storage::Full
+        );
+        assert_eq!(
+            dominant_storage(&[(1, manifest(&["Full", "bsdiff", "zstd"]))]),
+            Storage::Zstd
+        );
+        // 新增操作的存储类型不计入
+        assert_eq!(dominant_storage(&[(1, manifest(&[]))]), Storage::Zstd);
+    }
+
+    /// 跨补丁共享数据统计测试：只统计出现在多个镜像中的相同数据，忽略同一镜像内的重复与空文件
+    #[test]
+    fn test_count_shared_payloads() {
+        let payloads = vec![
+            (1, "aaaa".to_string(), 100),
+            (2, "aaaa".to_string(), 100),
+            (3, "aaaa".to_string(), 100),
+            (1, "bbbb".to_string(), 50),
+            (1, "bbbb".to_string(), 50),
+            (2, "cccc".to_string(), 0),
+            (3, "cccc".to_string(), 0),
+            (2, "dddd".to_string(), 10),
+        ];
+        assert_eq!(count_shared_payloads(&payloads), (2, 200));
+        assert_eq!(count_shared_payloads(&[]), (0, 0));
+    }
+
+    /// 压缩暂存测试：捕获前按操作列表解压还原为原文件并恢复时间与属性，名称以暂存后缀结尾的文件保持不变，
+    /// 并统计节省的临时目录空间
+    #[test]
+    fn test_expand_staged_payloads() {
+        let root = std::env::temp_dir().join(get_tmp_name("stage-", "", 6));
+        let base_dir = root.join("base");
+        let target_dir = root.join("target");
+        let patch_dir = root.join("patch");
+        for dir in [&base_dir, &target_dir, &patch_dir] {
+            fs::create_dir_all(dir.join("sub")).unwrap();
+        }
+        let content = b"WimPatch staged payload\r\n".repeat(4096);
+        let full = target_dir.join("sub").join("full.bin");
+        fs::write(&full, &content).unwrap();
+        let modified = std::time::SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        fs::File::options()
+            .write(true)
+            .open(&full)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        let mut permissions = fs::metadata(&full).unwrap().permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(&full, permissions).unwrap();
+        let genuine = format!("genuine.txt{}", STAGED_PAYLOAD_SUFFIX);
+        fs::write(target_dir.join(&genuine), b"not compressed").unwrap();
+        fs::write(base_dir.join("modify.txt"), b"old").unwrap();
+        fs::write(target_dir.join("modify.txt"), b"new").unwrap();
+
+        let options = CreateOptions {
+            storage: Storage::Full,
+            stage_compressed: true,
+            author: String::new(),
+            name: "test-patch".to_string(),
+            ..Default::default()
+        };
+        let (operations, _, _) = WimPatch::without_wimgapi()
+            .unwrap()
+            .create_operations(&base_dir, &target_dir, &patch_dir, 1, &options)
+            .unwrap();
+        assert!(
+            patch_dir
+                .join(format!("sub\\full.bin{}", STAGED_PAYLOAD_SUFFIX))
+                .exists()
+        );
+
+        let (staged, expanded) = WimPatch::expand_staged_payloads(&patch_dir, &operations).unwrap();
+        assert_eq!(expanded, content.len() as u64 + 14 + 3);
+        assert!(staged < expanded / 10);
+        assert_eq!(fs::read(patch_dir.join("sub\\full.bin")).unwrap(), content);
+        assert_eq!(fs::read(patch_dir.join(&genuine)).unwrap(), b"not compressed");
+        assert_eq!(fs::read(patch_dir.join("modify.txt")).unwrap(), b"new");
+        let metadata = fs::metadata(patch_dir.join("sub\\full.bin")).unwrap();
+        assert_eq!(metadata.modified().unwrap(), modified);
+        assert!(metadata.permissions().readonly());
+        let staged_files = fs::read_dir(&patch_dir)
+            .unwrap()
+            .chain(fs::read_dir(patch_dir.join("sub")).unwrap())
+            .filter(|entry| {
+                let name = entry.as_ref().unwrap().file_name();
+                name.to_string_lossy().ends_with(STAGED_PAYLOAD_SUFFIX) && name != genuine.as_str()
+            })
+            .count();
+        assert_eq!(staged_files, 0);
+
+        // 清除只读属性后再删除
+        for file in [full, patch_dir.join("sub\\full.bin")] {
+            let mut permissions = fs::metadata(&file).unwrap().permissions();
+            #[allow(clippy::permissions_set_readonly_false)]
+            permissions.set_readonly(false);
+            fs::set_permissions(&file, permissions).unwrap();
+        }
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// 起始版本测试：已更新到中间版本的基础镜像（GUID 已改变）只应用后续补丁，并校验补丁链连续
+    #[test]
+    fn test_match_patch_from_version() {
+        let wim_patch = WimPatch::new().unwrap();
+        let original = "{3F2504E0-4F89-11D3-9A0C-0305E82C3301}";
+        let updated = "{9A0C0305-E82C-3301-3F25-04E04F8911D3}";
+        let state = |file_count| ImageInfo {
+            index: 1,
+            file_count,
+            ..Default::default()
+        };
+        let new_patch = |version, base, target| {
+            PatchManifest::new(
+                "test-patch",
+                "",
+                "",
+                version,
+                original,
+                &state(base),
+                "",
+                &state(target),
+                &[],
+                None,
+            )
+        };
+        let patches = vec![
+            (1, new_patch("1.0.0", 10, 11)),
+            (2, new_patch("1.1.0", 11, 12)),
+            (3, new_patch("1.2.0", 12, 13)),
+        ];
+        let from_version = ApplyOptions {
+            from_version: Some(semver::Version::new(1, 0, 0)),
+            ..Default::default()
+        };
+
+        // 未指定起始版本时，更新后的镜像 GUID 不匹配
+        let result = wim_patch
+            .match_patch(updated, &[state(11)], &patches, &ApplyOptions::default())
+            .unwrap();
+        assert!(result.is_empty());
+
+        // 跳过 1.0.0，依次应用 1.1.0 和 1.2.0
+        let result = wim_patch
+            .match_patch(updated, &[state(11)], &patches, &from_version)
+            .unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].0, state(13));
+        let versions: Vec<&str> = result[0]
+            .1
+            .iter()
+            .map(|(_, patch)| patch.patch_version.as_str())
+            .collect();
+        assert_eq!(versions, ["1.1.0", "1.2.0"]);
+
+        // 缺少 1.1.0 时补丁链不连续
+        let gap = vec![patches[0].clone(), patches[2].clone()];
+        assert!(
+            wim_patch
+                .match_patch(updated, &[state(11)], &gap, &from_version)
+                .is_err()
+        );
+    }
+
+    /// 补丁链长度与环检测测试：补丁将卷恢复到之前的镜像时报错，补丁链超过最大长度时报错
+    #[test]
+    fn test_match_patch_chain_guard() {
+        let wim_patch = WimPatch::new().unwrap();
+        let guid = "{3F2504E0-4F89-11D3-9A0C-0305E82C3301}";
+        let state = |file_count| ImageInfo {
+            index: 1,
+            file_count,
+            ..Default::default()
+        };
+        let new_patch = |version, base, target, target_guid: &str| {
+            PatchManifest::new(
+                "test-patch",
+                "",
+                "",
+                version,
+                guid,
+                &state(base),
+                target_guid,
+                &state(target),
+                &[],
+                None,
+            )
+        };
+        let target_guid = |n: u32| format!("{{3F2504E0-4F89-11D3-9A0C-0305E82C33{:02}}}", n);
+
+        // 1.1.0 将卷恢复到基线镜像
+        let cyclic = vec![
+            (1, new_patch("1.0.0", 10, 11, target_guid(2).as_str())),
+            (2, new_patch("1.1.0", 11, 10, guid)),
+            (3, new_patch("1.2.0", 10, 11, target_guid(4).as_str())),
+        ];
+        let error = wim_patch
+            .match_patch(guid, &[state(10)], &cyclic, &ApplyOptions::default())
+            .unwrap_err();
+        assert!(error.to_string().contains("1.1.0"));
+
+        // 强制模式下仅警告，每个补丁仍只使用一次
+        let force = ApplyOptions {
+            force: true,
+            ..Default::default()
+        };
+        let result = wim_patch.match_patch(guid, &[state(10)], &cyclic, &force).unwrap();
+        assert_eq!(result[0].1.len(), 3);
+
+        // 只修改文件内容的补丁链统计信息不变，更新镜像不同，不是环
+        let content_only = vec![
+            (1, new_patch("1.0.0", 10, 10, target_guid(2).as_str())),
+            (2, new_patch("1.1.0", 10, 10, target_guid(3).as_str())),
+        ];
+        let result = wim_patch
+            .match_patch(guid, &[state(10)], &content_only, &ApplyOptions::default())
+            .unwrap();
+        assert_eq!(result[0].1.len(), 2);
+
+        // 超过最大长度时报错，默认限制与命令行一致
+        assert_eq!(ApplyOptions::default().max_chain_length, DEFAULT_MAX_CHAIN_LENGTH);
+        let patches = vec![
+            (1, new_patch("1.0.0", 10, 11, "")),
+            (2, new_patch("1.1.0", 11, 12, "")),
+            (3, new_patch("1.2.0", 12, 13, "")),
+        ];
+        let limited = |max_chain_length| ApplyOptions {
+            max_chain_length,
+            ..Default::default()
+        };
+        assert!(
+            wim_patch
+                .match_patch(guid, &[state(10)], &patches, &limited(2))
+                .is_err()
+        );
+        assert_eq!(
+            wim_patch
+                .match_patch(guid, &[state(10)], &patches, &limited(3))
+                .unwrap()[0]
+                .1
+                .len(),
+            3
+        );
+    }
+
+    /// 差异总量测试：在第一次差异回调前报告差异数量与新增、修改文件的字节数
+    #[test]
+    fn test_compare_directories_total() {
+        let root = std::env::temp_dir().join(get_tmp_name("compare-", "", 6));
+        let base_dir = root.join("base");
+        let target_dir = root.join("target");
+        fs::create_dir_all(&base_dir).unwrap();
+        fs::create_dir_all(&target_dir).unwrap();
+        fs::write(base_dir.join("same.txt"), b"same").unwrap();
+        fs::write(target_dir.join("same.txt"), b"same").unwrap();
+        fs::write(base_dir.join("modify.txt"), b"old").unwrap();
+        fs::write(target_dir.join("modify.txt"), b"new content").unwrap();
+        fs::write(base_dir.join("delete.txt"), b"delete").unwrap();
+        fs::write(target_dir.join("add.txt"), b"added").unwrap();
+
+        let mut total = None;
+        let mut processed = 0;
+        compare_directories_with_total(
+            &base_dir,
+            &target_dir,
+            CompareMode::Bytes,
+            |count, bytes| total = Some((count, bytes)),
+            |_, _, _, _| {
+                processed += 1;
+                true
+            },
+        )
+        .unwrap();
+        assert_eq!(total, Some((3, 16)));
+        assert_eq!(processed, 3);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// 文件比较方式测试：元数据、逐字节与哈希比较对内容和修改时间变化的判断
+    #[test]
+    fn test_compare_mode() {
+        let root = std::env::temp_dir().join(get_tmp_name("compare-mode-", "", 6));
+        fs::create_dir_all(&root).unwrap();
+        let one = root.join("one.bin");
+        let another = root.join("another.bin");
+        let modified = fs::FileTimes::new().set_modified(std::time::SystemTime::UNIX_EPOCH + Duration::from_secs(1));
+        let write = |path: &Path, content: &[u8]| {
+            fs::write(path, content).unwrap();
+            fs::File::options()
+                .write(true)
+                .open(path)
+                .unwrap()
+                .set_times(modified)
+                .unwrap();
+        };
+
+        // 内容与修改时间均相同
+        write(&one, b"same content");
+        write(&another, b"same content");
+        for mode in [CompareMode::Meta, CompareMode::Bytes, CompareMode::Hash] {
+            assert!(is_same_file(&one, &another, mode), "{:?}", mode);
+        }
+
+        // 大小与修改时间相同但内容不同：仅元数据比较无法发现
+        write(&another, b"diff content");
+        assert!(is_same_file(&one, &another, CompareMode::Meta));
+        assert!(!is_same_file(&one, &another, CompareMode::Bytes));
+        assert!(!is_same_file(&one, &another, CompareMode::Hash));
+
+        // 内容相同但修改时间不同：哈希比较忽略修改时间
+        write(&another, b"same content");
+        fs::File::options()
+            .write(true)
+            .open(&another)
+            .unwrap()
+            .set_modified(std::time::SystemTime::now())
+            .unwrap();
+        assert!(!is_same_file(&one, &another, CompareMode::Meta));
+        assert!(!is_same_file(&one, &another, CompareMode::Bytes));
+        assert!(is_same_file(&one, &another, CompareMode::Hash));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// 完整性校验合并测试：写入完整性信息的合并补丁可以重新打开并加载所有镜像
+    #[test]
+    fn test_merge_verify() {
+        let root = std::env::temp_dir().join(get_tmp_name("merge-", "", 6));
+        let wimgapi = Wimgapi::new(None).unwrap();
+
+        // 捕获两个补丁包（合并只导出镜像，无需有效的补丁清单）
+        let mut patches = Vec::new();
+        for index in 0..2 {
+            let source = root.join(format!("source{index}"));
+            fs::create_dir_all(&source).unwrap();
+            fs::write(source.join("file.txt"), format!("patch {index}")).unwrap();
+            let patch = root.join(format!("patch{index}.wim"));
+            let handle = wimgapi
+                .open(&patch, WIM_GENERIC_WRITE, WIM_CREATE_ALWAYS, WIM_COMPRESS_NONE)
+                .unwrap();
+            wimgapi.set_temp_path(handle, &get_temp_path()).unwrap();
+            let image_handle = wimgapi.capture(handle, &source, 0).unwrap();
+            wimgapi.close(image_handle).unwrap();
+            wimgapi.close(handle).unwrap();
+            patches.push(patch);
+        }
+
+        let merged = root.join("merged.wim");
+        let wim_patch = WimPatch::new().unwrap();
+        wim_patch
+            .merge_patches(
+                &patches,
+                &merged,
+                &MergeOptions {
+                    compress: Compress::Lzx,
+                    boot_index: None,
+                    verify: true,
+                    report_shared: false,
+                    keep_compression: false,
+                    fail_on_conflict: false,
+                    verify_after_each: false,
+                    flatten_base: None,
+                    flatten_base_index: None,
+                    flatten_storage: None,
+                    flatten_preset: None,
+                    flatten_manifest_format: None,
+                },
+            )
+            .unwrap();
+
+        // 重新打开并加载所有镜像
+        let handle = wimgapi
+            .open_with_flags(
+                &merged,
+                WIM_GENERIC_READ,
+                WIM_OPEN_EXISTING,
+                WIM_FLAG_VERIFY,
+                WIM_COMPRESS_NONE,
+            )
+            .unwrap();
+        wimgapi.set_temp_path(handle, &get_temp_path()).unwrap();
+        assert_eq!(wimgapi.get_image_count(handle), 2);
+        for index in 1..=2 {
+            let image_handle = wimgapi.load_image(handle, index).unwrap();
+            wimgapi.close(image_handle).unwrap();
+        }
+        wimgapi.close(handle).unwrap();
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// 路径重映射测试：去除路径层数、添加前缀及两者组合，并拒绝逃逸出挂载目录的路径
+    #[test]
+    fn test_remap_operation_path() {
+        let path = r"Program Files\App\app.exe";
+
+        // 未指定时保持原路径
+        assert_eq!(remap_operation_path(path, 0, None).unwrap().as_deref(), Some(path));
+
+        // 去除路径层数
+        assert_eq!(
+            remap_operation_path(path, 1, None).unwrap().as_deref(),
+            Some(r"App\app.exe")
+        );
+        assert_eq!(remap_operation_path(path, 3, None).unwrap(), None);
+        assert_eq!(remap_operation_path(r"Program Files", 1, None).unwrap(), None);
+
+        // 添加前缀
+        assert_eq!(
+            remap_operation_path(path, 0, Some(r"Root\")).unwrap().as_deref(),
+            Some(r"Root\Program Files\App\app.exe")
+        );
+
+        // 组合
+        assert_eq!(
+            remap_operation_path(path, 2, Some("Apps/Tools")).unwrap().as_deref(),
+            Some(r"Apps\Tools\app.exe")
+        );
+
+        // 逃逸出挂载目录
+        assert!(remap_operation_path(path, 0, Some("..")).is_err());
+        assert!(remap_operation_path(r"..\..\Windows\evil.dll", 1, None).is_err());
+        assert!(remap_operation_path(r"..\evil.dll", 0, None).is_err());
+        assert!(remap_operation_path(path, 0, Some(r"C:\Windows")).is_err());
+        assert!(remap_operation_path(path, 0, Some(r"\Windows")).is_err());
+    }
+
+    /// 目录比较基准测试：10 万个文件的合成目录树（运行：cargo test bench_compare_directories -- --ignored --nocapture）
+    #[test]
+    #[ignore]
+    fn bench_compare_directories() {
+        let root = std::env::temp_dir().join(get_tmp_name("bench-", "", 6));
+        let base_dir = root.join("base");
+        let target_dir = root.join("target");
+
+        // 100 个目录，每个目录 1000 个文件，其中 1% 修改、0.5% 新增、0.5% 删除
+        for dir in 0..100 {
+            fs::create_dir_all(base_dir.join(format!("dir{dir}"))).unwrap();
+            fs::create_dir_all(target_dir.join(format!("dir{dir}"))).unwrap();
+            for file in 0..1000 {
+                let name = format!("dir{dir}\\file{file}.txt");
+                let content = format!("{:0>64}", file);
+                if file % 200 != 0 {
+                    fs::write(base_dir.join(&name), &content).unwrap();
+                }
+                if file % 200 != 1 {
+                    let content = if file % 100 == 2 {
+                        content.replace('0', "1")
+                    } else {
+                        content
+                    };
+                    fs::write(target_dir.join(&name), content).unwrap();
+                }
+            }
+        }
+
+        let start = Instant::now();
+        let (mut added, mut deleted, mut modified) = (0, 0, 0);
+        compare_directories(&base_dir, &target_dir, |diff_type, _, _, _| {
+            match diff_type {
+                DiffType::Add => added += 1,
+                DiffType::Delete => deleted += 1,
+                DiffType::Modify => modified += 1,
+            }
+            true
+        })
+        .unwrap();
+        println!("compare 100k files: {:?}", start.elapsed());
+
+        assert_eq!(added, 500);
+        assert_eq!(deleted, 500);
+        assert_eq!(modified, 1000);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// 整镜像补丁与挂载逐文件应用的耗时对比（需要管理员权限）
+    #[test]
+    #[ignore]
+    fn bench_whole_image_apply() {
+        let root = std::env::temp_dir().join(get_tmp_name("bench-", "", 6));
+        let base_dir = root.join("base");
+        let target_dir = root.join("target");
+
+        // 20 个目录，每个目录 500 个 64 KiB 的文件，其中 90% 修改
+        for dir in 0..20 {
+            fs::create_dir_all(base_dir.join(format!("dir{dir}"))).unwrap();
+            fs::create_dir_all(target_dir.join(format!("dir{dir}"))).unwrap();
+            for file in 0..500 {
+                let name = format!("dir{dir}\\file{file}.bin");
+                fs::write(base_dir.join(&name), vec![(file % 251) as u8; 64 * 1024]).unwrap();
+                let byte = if file % 10 == 0 { file % 251 } else { (file + 1) % 251 };
+                fs::write(target_dir.join(&name), vec![byte as u8; 64 * 1024]).unwrap();
+            }
+        }
+
+        // 捕获基础镜像与更新镜像
+        let wimgapi = Wimgapi::new(None).unwrap();
+        let capture = |source: &Path, image: &Path| {
+            let handle = wimgapi
+                .open(image, WIM_GENERIC_WRITE, WIM_CREATE_ALWAYS, WIM_COMPRESS_NONE)
+                .unwrap();
+            wimgapi.set_temp_path(handle, &get_temp_path()).unwrap();
+            let image_handle = wimgapi.capture(handle, source, 0).unwrap();
+            wimgapi.close(image_handle).unwrap();
+            wimgapi.close(handle).unwrap();
+        };
+        let base_image = root.join("base.wim");
+        let target_image = root.join("target.wim");
+        capture(&base_dir, &base_image);
+        capture(&target_dir, &target_image);
+
+        let wim_patch = WimPatch::new().unwrap();
+        for storage in [Storage::Full, Storage::WholeImage] {
+            let patch_image = root.join(format!("{:?}.wim", storage));
+            let options = CreateOptions {
+                storage: storage.clone(),
+                preset: Preset::Fast,
+                author: "bench".to_string(),
+                name: "bench".to_string(),
+                compress: Compress::None,
+                ..Default::default()
+            };
+            wim_patch
+                .create_patch(&base_image, Some(1), &target_image, Some(1), &patch_image, &options)
+                .unwrap();
+
+            let result_image = root.join(format!("{:?}-result.wim", storage));
+            let start = Instant::now();
+            wim_patch
+                .apply_patch(
+                    &base_image,
+                    Some(1),
+                    &patch_image,
+                    &result_image,
+                    &ApplyOptions::default(),
+                )
+                .unwrap();
+            println!("apply {:?}: {:?}", storage, start.elapsed());
+        }
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// 合并补丁时保留压缩类型与重新压缩的耗时对比（需要管理员权限）
+    /// （运行：cargo test bench_merge_keep_compression -- --ignored --nocapture）
+    #[test]
+    #[ignore]
+    fn bench_merge_keep_compression() {
+        let root = std::env::temp_dir().join(get_tmp_name("bench-", "", 6));
+        let wimgapi = Wimgapi::new(None).unwrap();
+        let capture = |source: &Path, image: &Path| {
+            let handle = wimgapi
+                .open(image, WIM_GENERIC_WRITE, WIM_CREATE_ALWAYS, WIM_COMPRESS_NONE)
+                .unwrap();
+            wimgapi.set_temp_path(handle, &get_temp_path()).unwrap();
+            let image_handle = wimgapi.capture(handle, source, 0).unwrap();
+            wimgapi.close(image_handle).unwrap();
+            wimgapi.close(handle).unwrap();
+        };
+
+        // 基础镜像与两个更新镜像：10 个目录，每个目录 500 个 64 KiB 的文本文件，每个版本修改一半文件
+        let base_image = root.join("base.wim");
+        let mut patches = Vec::new();
+        for version in 0..3 {
+            let source = root.join(format!("v{version}"));
+            for dir in 0..10 {
+                fs::create_dir_all(source.join(format!("dir{dir}"))).unwrap();
+                for file in 0..500 {
+                    let revision = if file % 2 == 0 { version } else { 0 };
+                    let line = format!("dir{dir} file{file} revision{revision}\r\n");
+                    let content = line.repeat(64 * 1024 / line.len());
+                    fs::write(source.join(format!("dir{dir}\\file{file}.txt")), content).unwrap();
+                }
+            }
+            if version == 0 {
+                capture(&source, &base_image);
+                continue;
+            }
+            let target_image = root.join(format!("v{version}.wim"));
+            capture(&source, &target_image);
+            let patch_image = root.join(format!("patch{version}.wim"));
+            let options = CreateOptions {
+                storage: Storage::Full,
+                author: "bench".to_string(),
+                name: "bench".to_string(),
+                version: format!("1.{version}.0"),
+                compress: Compress::Lzx,
+                ..Default::default()
+            };
+            WimPatch::new()
+                .unwrap()
+                .create_patch(&base_image, Some(1), &target_image, Some(1), &patch_image, &options)
+                .unwrap();
+            patches.push(patch_image);
+        }
+
+        // 输入补丁包均为 LZX：--compress xpress 需要重新压缩，--keep-compression 直接导出
+        let wim_patch = WimPatch::new().unwrap();
+        for keep_compression in [false, true] {
+            let merged = root.join(format!("merged-{keep_compression}.wim"));
+            let options = MergeOptions {
+                compress: Compress::Xpress,
+                boot_index: None,
+                verify: false,
+                report_shared: false,
+                keep_compression,
+                fail_on_conflict: false,
+                verify_after_each: false,
+                flatten_base: None,
+                flatten_base_index: None,
+                flatten_storage: None,
+                flatten_preset: None,
+                flatten_manifest_format: None,
+            };
+            let start = Instant::now();
+            wim_patch.merge_patches(&patches, &merged, &options).unwrap();
+            println!(
+                "merge keep_compression={}: {:?}, {}",
+                keep_compression,
+                start.elapsed(),
+                format_bytes(fs::metadata(&merged).unwrap().len())
+            );
+        }
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// 应用补丁时复制基础镜像与 --no-base-copy 的耗时对比（需要管理员权限）
+    /// （运行：cargo test bench_no_base_copy -- --ignored --nocapture）
+    #[test]
+    #[ignore]
+    fn bench_no_base_copy() {
+        let root = std::env::temp_dir().join(get_tmp_name("bench-", "", 6));
+        let base_dir = root.join("base");
+        let target_dir = root.join("target");
+        fs::create_dir_all(&base_dir).unwrap();
+        fs::create_dir_all(&target_dir).unwrap();
+
+        // 64 个 64 MiB 不可压缩的文件（基础镜像约 4 GiB），更新镜像只修改一个小文件
+        let mut block = vec![0u8; 1024 * 1024];
+        let mut state = 0x9E37_79B9_7F4A_7C15u64;
+        for file in 0..64 {
+            let name = format!("file{file}.bin");
+            let mut writer = fs::File::create(base_dir.join(&name)).unwrap();
+            for _ in 0..64 {
+                for byte in block.iter_mut() {
+                    state ^= state << 13;
+                    state ^= state >> 7;
+                    state ^= state << 17;
+                    *byte = state as u8;
+                }
+                writer.write_all(&block).unwrap();
+            }
+            fs::hard_link(base_dir.join(&name), target_dir.join(&name)).unwrap();
+        }
+        fs::write(base_dir.join("version.txt"), "1.0.0").unwrap();
+        fs::write(target_dir.join("version.txt"), "1.1.0").unwrap();
+
+        // 捕获基础镜像与更新镜像
+        let wimgapi = Wimgapi::new(None).unwrap();
+        let capture = |source: &Path, image: &Path| {
+            let handle = wimgapi
+                .open(image, WIM_GENERIC_WRITE, WIM_CREATE_ALWAYS, WIM_COMPRESS_NONE)
+                .unwrap();
+            wimgapi.set_temp_path(handle, &get_temp_path()).unwrap();
+            let image_handle = wimgapi.capture(handle, source, 0).unwrap();
+            wimgapi.close(image_handle).unwrap();
+            wimgapi.close(handle).unwrap();
+        };
+        let base_image = root.join("base.wim");
+        let target_image = root.join("target.wim");
+        capture(&base_dir, &base_image);
+        capture(&target_dir, &target_image);
+        println!("base image: {}", format_bytes(fs::metadata(&base_image).unwrap().len()));
+
+        let wim_patch = WimPatch::new().unwrap();
+        let patch_image = root.join("patch.wim");
+        let options = CreateOptions {
+            storage: Storage::Full,
+            author: "bench".to_string(),
+            name: "bench".to_string(),
+            compress: Compress::None,
+            compare_mode: CompareMode::Hash,
+            ..Default::default()
+        };
+        wim_patch
+            .create_patch(&base_image, Some(1), &target_image, Some(1), &patch_image, &options)
+            .unwrap();
+
+        for no_base_copy in [false, true] {
+            // --no-base-copy 会修改基础镜像，在计时前复制一份可丢弃的基础镜像
+            let base_copy = root.join(format!("base-{no_base_copy}.wim"));
+            fs::copy(&base_image, &base_copy).unwrap();
+            let result_image = root.join(format!("result-{no_base_copy}.wim"));
+            let options = ApplyOptions {
+                no_base_copy,
+                ..Default::default()
+            };
+            let start = Instant::now();
+            wim_patch
+                .apply_patch(&base_copy, Some(1), &patch_image, &result_image, &options)
+                .unwrap();
+            println!("apply no_base_copy={}: {:?}", no_base_copy, start.elapsed());
+        }
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// 可复现构建测试：指定时间戳后相同输入生成相同的补丁清单
+    #[test]
+    fn test_source_date() {
+        assert_eq!(parse_source_date("1700000000"), Ok(1700000000));
+        assert_eq!(parse_source_date("2023-11-14T22:13:20Z"), Ok(1700000000));
+        assert_eq!(parse_source_date("2023-11-15T06:13:20+08:00"), Ok(1700000000));
+        assert!(parse_source_date("yesterday").is_err());
+
+        let new_manifest = |source_date| {
+            PatchManifest::new(
+                "test-patch",
+                "",
+                "",
+                "1.0.0",
+                "guid-base",
+                &ImageInfo::default(),
+                "guid-target",
+                &ImageInfo::default(),
+                &[],
+                source_date,
+            )
+        };
+        let manifest = new_manifest(Some(1700000000));
+        assert_eq!(manifest.timestamp, "2023-11-14T22:13:20+00:00");
+        assert_eq!(
+            manifest.to_xml().unwrap(),
+            new_manifest(Some(1700000000)).to_xml().unwrap()
+        );
+
+        // 未指定时使用当前时间与随机 ID
+        assert_ne!(new_manifest(None).id, new_manifest(None).id);
+    }
+
+    /// 提取清单测试：按索引生成输出路径，JSON 与 XML 内容一致
+    #[test]
+    fn test_extract_manifest_output() {
+        assert_eq!(
+            manifest_output_path(Path::new(r"C:\out\manifest.xml"), 2),
+            PathBuf::from(r"C:\out\manifest-2.xml")
+        );
+        assert_eq!(
+            manifest_output_path(Path::new("manifest"), 1),
+            PathBuf::from("manifest-1")
+        );
+
+        let manifest = test_manifest("1.0.0", "guid-base", "guid-target", &[]);
+        let json: serde_json::Value = serde_json::from_str(&manifest.to_json().unwrap()).unwrap();
+        assert_eq!(json["ID"], manifest.id);
+        assert_eq!(json["BaseImageGuid"], "guid-base");
+    }
+
+    /// 限制并发处理测试：同时处理的数量不超过上限，失败后不再开始新的项并返回第一个失败项的错误
+    #[test]
+    fn test_for_each_bounded() {
+        let items: Vec<u32> = (1..=8).collect();
+        for concurrency in [0, 1, 3] {
+            let running = AtomicUsize::new(0);
+            let peak = AtomicUsize::new(0);
+            let processed = AtomicUsize::new(0);
+            for_each_bounded(&items, concurrency, |_| {
+                let current = running.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(current, Ordering::SeqCst);
+                sleep(Duration::from_millis(20));
+                running.fetch_sub(1, Ordering::SeqCst);
+                processed.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            })
+            .unwrap();
+            assert_eq!(processed.load(Ordering::SeqCst), items.len());
+            assert!(peak.load(Ordering::SeqCst) <= concurrency.max(1));
+        }
+
+        // 顺序处理时失败后立即停止
+        let processed = AtomicUsize::new(0);
+        let result = for_each_bounded(&items, 1, |&item| {
+            processed.fetch_add(1, Ordering::SeqCst);
+            if item >= 3 {
+                return Err(anyhow::anyhow!("index {} failed", item));
+            }
+            Ok(())
+        });
+        assert_eq!(result.unwrap_err().to_string(), "index 3 failed");
+        assert_eq!(processed.load(Ordering::SeqCst), 3);
+        assert!(for_each_bounded(&Vec::<u32>::new(), 4, |_| Err(anyhow::anyhow!("unreachable"))).is_ok());
+    }
+
+    /// 文件操作重试测试：暂时性错误重试后成功，超过次数或非暂时性错误时失败
+    #[test]
+    fn test_retry_io() {
+        // 注入的文件操作：前两次返回共享冲突
+        let locked_fake = |failures: u32| {
+            let mut calls = 0;
+            move || {
+                calls += 1;
+                if calls <= failures {
+                    Err(std::io::Error::from_raw_os_error(32))
+                } else {
+                    Ok(calls)
+                }
+            }
+        };
+        let (result, retried) = retry_io(2, locked_fake(2));
+        assert_eq!(result.unwrap(), 3);
+        assert_eq!(retried, 2);
+
+        let (result, retried) = retry_io(1, locked_fake(2));
+        assert!(result.is_err());
+        assert_eq!(retried, 1);
+        let error = WimPatch::retry_file_operation(1, "file.txt", locked_fake(2)).unwrap_err();
+        let retry_failed = rust_i18n::t!("apply_patch.io_retry_failed", retries = 1);
+        assert!(error.to_string().ends_with(&format!("({})", retry_failed)));
+        let value = WimPatch::retry_file_operation(2, "file.txt", locked_fake(2)).unwrap();
+        assert_eq!(value, 3);
+
+        // 非暂时性错误不重试
+        let (result, retried) = retry_io(2, || std::fs::metadata("Z:\\not-exist\\file.txt"));
+        assert!(result.is_err());
+        assert_eq!(retried, 0);
+
+        // 拒绝访问（只读属性或权限不足）不是暂时性错误，只尝试一次
+        let mut attempts = 0;
+        let (result, retried) = retry_io(3, || -> std::io::Result<()> {
+            attempts += 1;
+            Err(std::io::Error::from_raw_os_error(5))
+        });
+        assert_eq!(result.unwrap_err().raw_os_error(), Some(5));
+        assert_eq!(retried, 0);
+        assert_eq!(attempts, 1);
+
+        // 被独占打开的文件在释放后可以删除
+        let path = std::env::temp_dir().join(get_tmp_name("locked-", ".txt", 6));
+        fs::write(&path, b"locked").unwrap();
+        let file = fs::OpenOptions::new().read(true).share_mode(0).open(&path).unwrap();
+        let handle = thread::spawn(move || {
+            sleep(Duration::from_millis(150));
+            drop(file);
+        });
+        let (result, retried) = retry_io(3, || fs::remove_file(&path));
+        handle.join().unwrap();
+        assert!(result.is_ok());
+        assert!(retried > 0);
+        assert!(!path.exists());
+
+        // 应用补丁时目标文件被暂时占用：不重试时失败且文件不变，重试后写入新内容
+        let root = std::env::temp_dir().join(get_tmp_name("locked-apply-", "", 6));
+        let base_dir = root.join("base");
+        let patch_dir = root.join("patch");
+        fs::create_dir_all(&base_dir).unwrap();
+        fs::create_dir_all(&patch_dir).unwrap();
+        fs::write(base_dir.join("file.txt"), b"old").unwrap();
+        fs::write(patch_dir.join("file.txt"), b"new").unwrap();
+        let operations = vec![test_operation(Action::Modify, "file.txt", Some(3), Some("full"))];
+        let lock = |release_after: Duration| {
+            let file = fs::OpenOptions::new()
+                .read(true)
+                .share_mode(0)
+                .open(base_dir.join("file.txt"))
+                .unwrap();
+            thread::spawn(move || {
+                sleep(release_after);
+                drop(file);
+            })
+        };
+        let wim_patch = WimPatch::new().unwrap();
+        let options = |io_retries| ApplyOptions {
+            io_retries,
+            ..Default::default()
+        };
+
+        let handle = lock(Duration::from_millis(150));
+        let result = wim_patch.apply_operations(&base_dir, &patch_dir, &operations, 1, &options(0));
+        handle.join().unwrap();
+        assert!(result.is_err());
+        assert_eq!(fs::read(base_dir.join("file.txt")).unwrap(), b"old");
+
+        let handle = lock(Duration::from_millis(150));
+        let counts = wim_patch
+            .apply_operations(&base_dir, &patch_dir, &operations, 1, &options(3))
+            .unwrap();
+        handle.join().unwrap();
+        assert_eq!(counts.applied, 1);
+        assert_eq!(fs::read(base_dir.join("file.txt")).unwrap(), b"new");
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// 工具版本检查测试：更高主版本的补丁需要强制应用
+    #[test]
+    fn test_check_tool_version() {
+        let current = semver::Version::parse(env!("CARGO_PKG_VERSION")).unwrap();
+        let newer_major = format!("{}.0.0", current.major + 1);
+        let newer_minor = format!("{}.{}.0", current.major, current.minor + 1);
+
+        assert!(WimPatch::check_tool_version(&newer_major, false).is_err());
+        assert!(WimPatch::check_tool_version(&newer_major, true).is_ok());
+        assert!(WimPatch::check_tool_version(&newer_minor, false).is_ok());
+        assert!(WimPatch::check_tool_version(env!("CARGO_PKG_VERSION"), false).is_ok());
+        assert!(WimPatch::check_tool_version("", false).is_ok());
+    }
+
+    /// 硬链接暂存测试：暂存目录中仅包含变更文件，且内容与目标文件一致
+    #[test]
+    fn test_link_or_copy() {
+        let root = std::env::temp_dir().join(get_tmp_name("link-", "", 6));
+        let target_dir = root.join("target");
+        let patch_dir = root.join("patch");
+        fs::create_dir_all(target_dir.join("sub")).unwrap();
+        fs::create_dir_all(patch_dir.join("sub")).unwrap();
+        fs::write(target_dir.join("changed.txt"), b"changed").unwrap();
+        fs::write(target_dir.join("sub").join("added.txt"), b"added").unwrap();
+        fs::write(target_dir.join("unchanged.txt"), b"unchanged").unwrap();
+
+        // 仅暂存变更文件
+        for (path, content) in [("changed.txt", b"changed".as_slice()), ("sub\\added.txt", b"added")] {
+            let hash = link_or_copy(target_dir.join(path), patch_dir.join(path)).unwrap();
+            assert_eq!(hash, get_sha256(content));
+        }
+
+        assert_eq!(fs::read(patch_dir.join("changed.txt")).unwrap(), b"changed");
+        assert_eq!(fs::read(patch_dir.join("sub").join("added.txt")).unwrap(), b"added");
+        assert!(!patch_dir.join("unchanged.txt").exists());
+        assert_eq!(fs::read_dir(&patch_dir).unwrap().count(), 2);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// 硬链接暂存捕获测试：完整存储且不压缩时补丁数据以硬链接暂存，捕获到镜像后释放的内容与更新后的文件一致
+    /// （运行：cargo test test_link_payload_capture -- --ignored）
+    #[test]
+    #[ignore]
+    fn test_link_payload_capture() {
+        let root = std::env::temp_dir().join(get_tmp_name("link-capture-", "", 6));
+        let base_dir = root.join("base");
+        let target_dir = root.join("target");
+        let patch_dir = root.join("patch");
+        let applied = root.join("applied");
+        for dir in [&base_dir, &target_dir, &patch_dir, &applied] {
+            fs::create_dir_all(dir).unwrap();
+        }
+        fs::create_dir_all(target_dir.join("sub")).unwrap();
+        fs::write(base_dir.join("changed.txt"), b"old").unwrap();
+        fs::write(target_dir.join("changed.txt"), b"new content").unwrap();
+        fs::write(target_dir.join("sub").join("added.bin"), [0xA5u8; 64 * 1024]).unwrap();
+
+        let options = CreateOptions {
+            storage: Storage::Full,
+            compress: Compress::None,
+            author: String::new(),
+            name: "test-patch".to_string(),
+            ..Default::default()
+        };
+        let wim_patch = WimPatch::new().unwrap();
+        let (operations, _, _) = wim_patch
+            .create_operations(&base_dir, &target_dir, &patch_dir, 1, &options)
+            .unwrap();
+        assert!(operations.iter().any(|op| op.path == "changed.txt"));
+        assert!(operations.iter().any(|op| op.path == "sub\\added.bin"));
+
+        // 捕获暂存目录并释放到新目录
+        let image = root.join("patch.wim");
+        let wimgapi = Wimgapi::new(None).unwrap();
+        let handle = wimgapi
+            .open(&image, WIM_GENERIC_WRITE, WIM_CREATE_ALWAYS, WIM_COMPRESS_NONE)
+            .unwrap();
+        wimgapi.set_temp_path(handle, &root).unwrap();
+        let image_handle = wimgapi.capture(handle, &patch_dir, 0).unwrap();
+        wimgapi.apply_image(image_handle, &applied, 0).unwrap();
+        wimgapi.close(image_handle).unwrap();
+        wimgapi.close(handle).unwrap();
+        assert_eq!(fs::read(applied.join("changed.txt")).unwrap(), b"new content");
+        assert_eq!(
+            fs::read(applied.join("sub").join("added.bin")).unwrap(),
+            [0xA5u8; 64 * 1024]
+        );
+
+        // 暂存的文件是更新后文件的硬链接：修改更新后的文件，暂存文件随之变化
+        fs::write(target_dir.join("changed.txt"), b"relinked").unwrap();
+        assert_eq!(fs::read(patch_dir.join("changed.txt")).unwrap(), b"relinked");
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// 忽略空目录测试：指定 --skip-empty-dirs 时不记录也不暂存不包含文件变更的新增目录
+    #[test]
+    fn test_skip_empty_dirs() {
+        let root = std::env::temp_dir().join(get_tmp_name("skip-empty-", "", 6));
+        let base_dir = root.join("base");
+        let target_dir = root.join("target");
+        fs::create_dir_all(&base_dir).unwrap();
+        fs::create_dir_all(target_dir.join("empty").join("nested")).unwrap();
+        fs::create_dir_all(target_dir.join("filled")).unwrap();
+        fs::write(target_dir.join("filled").join("file.txt"), b"added").unwrap();
+
+        let wim_patch = WimPatch::new().unwrap();
+        for skip_empty_dirs in [false, true] {
+            let patch_dir = root.join(format!("patch-{}", skip_empty_dirs));
+            fs::create_dir_all(&patch_dir).unwrap();
+            let options = CreateOptions {
+                preset: Preset::Fast,
+                author: String::new(),
+                name: "test-patch".to_string(),
+                skip_empty_dirs,
+                ..Default::default()
+            };
+            let (operations, _, _) = wim_patch
+                .create_operations(&base_dir, &target_dir, &patch_dir, 1, &options)
+                .unwrap();
+            let paths: Vec<&str> = operations.iter().map(|op| op.path.as_str()).collect();
+
+            // 包含文件的新增目录始终保留
+            assert!(paths.contains(&"filled"));
+            assert!(paths.contains(&"filled\\file.txt"));
+            assert!(patch_dir.join("filled").join("file.txt").exists());
+
+            // 不包含文件变更的新增目录（含嵌套的空目录）仅在未指定时记录
+            assert_eq!(paths.contains(&"empty"), !skip_empty_dirs);
+            assert_eq!(paths.contains(&"empty\\nested"), !skip_empty_dirs);
+            assert_eq!(patch_dir.join("empty").exists(), !skip_empty_dirs);
+        }
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// 复制并计算哈希测试：一次读取得到与 get_file_sha256 相同的哈希，保留只读属性、备用数据流与稀疏性，
+    /// copy_sparse 同样保留只读属性、备用数据流与创建时间
+    #[test]
+    fn test_copy_hashed() {
+        let root = std::env::temp_dir().join(get_tmp_name("copy-hashed-", "", 6));
+        fs::create_dir_all(&root).unwrap();
+
+        let source = root.join("readonly.txt");
+        fs::write(&source, b"payload").unwrap();
+        fs::write(stream_path(&source, "Zone.Identifier"), b"[ZoneTransfer]").unwrap();
+        let mut permissions = fs::metadata(&source).unwrap().permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(&source, permissions).unwrap();
+        let target = root.join("readonly-copy.txt");
+        assert_eq!(copy_hashed(&source, &target).unwrap(), get_sha256(b"payload"));
+        assert_eq!(fs::read(&target).unwrap(), b"payload");
+        assert!(fs::metadata(&target).unwrap().permissions().readonly());
+        // 与 fs::copy 一致保留备用数据流
+        assert_eq!(
+            list_streams(&target).unwrap(),
+            vec![("Zone.Identifier".to_string(), 14)]
+        );
+        assert_eq!(
+            fs::read(stream_path(&target, "Zone.Identifier")).unwrap(),
+            b"[ZoneTransfer]"
+        );
+
+        // 稀疏文件复制后仍为稀疏文件
+        let sparse = root.join("sparse.bin");
+        let mut content = vec![0u8; 256 * 1024];
+        content[..4].copy_from_slice(b"head");
+        fs::write(&sparse, &content).unwrap();
+        make_sparse(&sparse).unwrap();
+        let sparse_copy = root.join("sparse-copy.bin");
+        assert_eq!(copy_hashed(&sparse, &sparse_copy).unwrap(), get_sha256(&content));
+        assert_eq!(fs::read(&sparse_copy).unwrap(), content);
+        assert!(is_sparse_file(&sparse_copy));
+
+        // 按稀疏文件复制时同样保留属性、备用数据流与创建时间
+        let sparse_target = root.join("readonly-sparse.txt");
+        assert_eq!(copy_sparse(&source, &sparse_target).unwrap(), 7);
+        assert_eq!(fs::read(&sparse_target).unwrap(), b"payload");
+        assert!(is_sparse_file(&sparse_target));
+        assert!(fs::metadata(&sparse_target).unwrap().permissions().readonly());
+        assert_eq!(
+            fs::metadata(&sparse_target).unwrap().created().unwrap(),
+            fs::metadata(&source).unwrap().created().unwrap()
+        );
+        assert_eq!(
+            fs::read(stream_path(&sparse_target, "Zone.Identifier")).unwrap(),
+            b"[ZoneTransfer]"
+        );
+
+        for path in [&source, &target, &sparse_target] {
+            let mut permissions = fs::metadata(path).unwrap().permissions();
+            #[allow(clippy::permissions_set_readonly_false)]
+            permissions.set_readonly(false);
+            fs::set_permissions(path, permissions).unwrap();
+        }
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// 暂存完整文件的读取量对比：复制后再计算哈希需要读取两遍源文件，copy_hashed 只读取一遍
+    /// （运行：cargo test bench_copy_hashed -- --ignored --nocapture，文件应大于可用内存以免第二遍命中缓存）
+    #[test]
+    #[ignore]
+    fn bench_copy_hashed() {
+        let root = std::env::temp_dir().join(get_tmp_name("bench-", "", 6));
+        fs::create_dir_all(&root).unwrap();
+
+        // 4 GiB 不可压缩的文件
+        let source = root.join("payload.bin");
+        let mut writer = fs::File::create(&source).unwrap();
+        let mut block = vec![0u8; 1024 * 1024];
+        let mut state = 0x9E37_79B9_7F4A_7C15u64;
+        for _ in 0..4096 {
+            for byte in block.iter_mut() {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                *byte = state as u8;
+            }
+            writer.write_all(&block).unwrap();
+        }
+        drop(writer);
+        let size = fs::metadata(&source).unwrap().len();
+
+        let start = Instant::now();
+        fs::copy(&source, root.join("copy.bin")).unwrap();
+        let hash = get_file_sha256(&source, None).unwrap();
+        println!("copy + hash: {:?}, read {}", start.elapsed(), format_bytes(size * 2));
+
+        let start = Instant::now();
+        assert_eq!(copy_hashed(&source, root.join("hashed.bin")).unwrap(), hash);
+        println!("copy_hashed: {:?}, read {}", start.elapsed(), format_bytes(size));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// 捕获排除测试：命中自定义排除项的路径不会被捕获到镜像中
+    #[test]
+    fn test_capture_exclude() {
+        let exclude = vec!["skip_me".to_string()];
+        assert!(is_capture_excluded("C:\\capture\\Skip_Me\\file.txt", &exclude));
+        assert!(!is_capture_excluded("C:\\capture\\keep.txt", &exclude));
+
+        let root = std::env::temp_dir().join(get_tmp_name("exclude-", "", 6));
+        let source = root.join("source");
+        let applied = root.join("applied");
+        let image = root.join("exclude.wim");
+        fs::create_dir_all(source.join("skip_me")).unwrap();
+        fs::create_dir_all(&applied).unwrap();
+        fs::write(source.join("keep.txt"), b"keep").unwrap();
+        fs::write(source.join("skip_me").join("file.txt"), b"skip").unwrap();
+
+        // 捕获时通过 pvUserData 传入排除列表
+        let wimgapi = Wimgapi::new(None).unwrap();
+        let handle = wimgapi
+            .open(&image, WIM_GENERIC_WRITE, WIM_CREATE_ALWAYS, WIM_COMPRESS_NONE)
+            .unwrap();
+        wimgapi.set_temp_path(handle, &root).unwrap();
+        let state = CaptureState::with_abort(&exclude, || false);
+        wimgapi.register_message_callback(
+            handle,
+            CaptureCallback,
+            &state as *const CaptureState as *mut std::ffi::c_void,
+        );
+        let image_handle = wimgapi.capture(handle, &source, 0).unwrap();
+        wimgapi.unregister_message_callback(handle, CaptureCallback);
+
+        // 释放镜像并检查排除的路径不存在
+        wimgapi.apply_image(image_handle, &applied, 0).unwrap();
+        wimgapi.close(image_handle).unwrap();
+        wimgapi.close(handle).unwrap();
+        assert!(applied.join("keep.txt").exists());
+        assert!(!applied.join("skip_me").join("file.txt").exists());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// 相同镜像检测测试：同一文件的同一索引拒绝创建补丁，没有差异的目录不产生任何操作
+    #[test]
+    fn test_same_source() {
+        let root = std::env::temp_dir().join(get_tmp_name("same-", "", 6));
+        let base_dir = root.join("base");
+        let target_dir = root.join("target");
+        let patch_dir = root.join("patch");
+        for dir in [&base_dir, &target_dir, &patch_dir] {
+            fs::create_dir_all(dir.join("sub")).unwrap();
+        }
+        for dir in [&base_dir, &target_dir] {
+            fs::write(dir.join("file.txt"), b"unchanged").unwrap();
+            fs::write(dir.join("sub").join("data.bin"), [0x5Au8; 1024]).unwrap();
+        }
+
+        // 同一文件（路径写法不同）的同一索引
+        let image = root.join("image.wim");
+        fs::write(&image, b"").unwrap();
+        let alias = root.join("sub-dir").join("..").join("IMAGE.WIM");
+        fs::create_dir_all(root.join("sub-dir")).unwrap();
+        assert!(is_same_source(&image, Some(1), &alias, Some(1)));
+        assert!(is_same_source(&image, None, &image, None));
+        assert!(!is_same_source(&image, Some(1), &image, Some(2)));
+        assert!(!is_same_source(&image, Some(1), &root.join("other.wim"), Some(1)));
+
+        let options = CreateOptions {
+            author: String::new(),
+            name: "test-patch".to_string(),
+            ..Default::default()
+        };
+        let wim_patch = WimPatch::new().unwrap();
+        let patch_image = root.join("patch.wim");
+        assert!(
+            wim_patch
+                .create_patch(&image, Some(1), &alias, Some(1), &patch_image, &options)
+                .is_err()
+        );
+        assert!(!patch_image.exists());
+
+        // 内容完全相同的目录不产生任何操作，不会写入补丁镜像
+        let (operations, _, _) = wim_patch
+            .create_operations(&base_dir, &target_dir, &patch_dir, 1, &options)
+            .unwrap();
+        assert!(operations.is_empty());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// 跳过无差异索引测试：自动匹配索引时没有差异的索引不捕获到补丁文件，全部没有差异时报错且不生成补丁文件
+    /// （需要管理员权限）
+    #[test]
+    #[ignore]
+    fn test_create_skip_unchanged_index() {
+        let root = std::env::temp_dir().join(get_tmp_name("skip-index-", "", 6));
+        let same_dir = root.join("same");
+        let base_dir = root.join("base");
+        let target_dir = root.join("target");
+        for (dir, content) in [(&same_dir, b"same"), (&base_dir, b"1.0"), (&target_dir, b"1.1")] {
+            fs::create_dir_all(dir).unwrap();
+            fs::write(dir.join("file.txt"), content).unwrap();
+        }
+
+        // 索引 1 两边相同，索引 2 有差异
+        let wimgapi = Wimgapi::new(None).unwrap();
+        let capture = |sources: &[&PathBuf], image: &Path| {
+            let handle = wimgapi
+                .open(image, WIM_GENERIC_WRITE, WIM_CREATE_ALWAYS, WIM_COMPRESS_NONE)
+                .unwrap();
+            wimgapi.set_temp_path(handle, &get_temp_path()).unwrap();
+            for source in sources {
+                wimgapi.close(wimgapi.capture(handle, source, 0).unwrap()).unwrap();
+            }
+            wimgapi.close(handle).unwrap();
+        };
+        let base_image = root.join("base.wim");
+        let target_image = root.join("target.wim");
+        capture(&[&same_dir, &base_dir], &base_image);
+        capture(&[&same_dir, &target_dir], &target_image);
+
+        let options = CreateOptions {
+            storage: Storage::Full,
+            preset: Preset::Fast,
+            author: String::new(),
+            name: "test-patch".to_string(),
+            ..Default::default()
+        };
+        let wim_patch = WimPatch::new().unwrap();
+        let patch_image = root.join("patch.wim");
+        wim_patch
+            .create_patch(&base_image, None, &target_image, None, &patch_image, &options)
+            .unwrap();
+
+        // 补丁文件只包含索引 2 的补丁镜像
+        let manifests = wim_patch.get_manifests(&patch_image).unwrap();
+        assert_eq!(manifests.len(), 1);
+        assert_eq!(manifests[0].1.base_image_info.index, 2);
+
+        // 全部索引都没有差异时报错，不生成补丁文件
+        let target_copy = root.join("target-copy.wim");
+        let unchanged_image = root.join("unchanged.wim");
+        fs::copy(&target_image, &target_copy).unwrap();
+        let error = wim_patch
+            .create_patch(&target_image, None, &target_copy, None, &unchanged_image, &options)
+            .unwrap_err();
+        assert_eq!(error.to_string(), rust_i18n::t!("create_patch.no_changes_all"));
+        assert!(!unchanged_image.exists());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// 严格索引匹配测试：镜像数量不同时，指定 --strict-index 报错且不生成补丁文件，
+    /// 未指定时跳过无法匹配的索引，只为匹配的索引生成补丁（需要管理员权限）
+    #[test]
+    #[ignore]
+    fn test_create_strict_index() {
+        let root = std::env::temp_dir().join(get_tmp_name("strict-index-", "", 6));
+        let base_dir = root.join("base");
+        let extra_dir = root.join("extra");
+        let target_dir = root.join("target");
+        for (dir, content) in [(&base_dir, b"1.0"), (&extra_dir, b"2.0"), (&target_dir, b"1.1")] {
+            fs::create_dir_all(dir).unwrap();
+            fs::write(dir.join("file.txt"), content).unwrap();
+        }
+
+        // 基础镜像有 2 个镜像，更新镜像只有 1 个
+        let wimgapi = Wimgapi::new(None).unwrap();
+        let capture = |sources: &[&PathBuf], image: &Path| {
+            let handle = wimgapi
+                .open(image, WIM_GENERIC_WRITE, WIM_CREATE_ALWAYS, WIM_COMPRESS_NONE)
+                .unwrap();
+            wimgapi.set_temp_path(handle, &get_temp_path()).unwrap();
+            for source in sources {
+                wimgapi.close(wimgapi.capture(handle, source, 0).unwrap()).unwrap();
+            }
+            wimgapi.close(handle).unwrap();
+        };
+        let base_image = root.join("base.wim");
+        let target_image = root.join("target.wim");
+        capture(&[&base_dir, &extra_dir], &base_image);
+        capture(&[&target_dir], &target_image);
+
+        let mut options = CreateOptions {
+            storage: Storage::Full,
+            preset: Preset::Fast,
+            author: String::new(),
+            name: "test-patch".to_string(),
+            strict_index: true,
+            ..Default::default()
+        };
+        let wim_patch = WimPatch::new().unwrap();
+        let patch_image = root.join("patch.wim");
+        let error = wim_patch
+            .create_patch(&base_image, None, &target_image, None, &patch_image, &options)
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            rust_i18n::t!("create_patch.index_count_mismatch", base = 2, target = 1)
+        );
+        assert!(!patch_image.exists());
+
+        // 未指定时跳过基础镜像的索引 2，只为索引 1 生成补丁
+        options.strict_index = false;
+        wim_patch
+            .create_patch(&base_image, None, &target_image, None, &patch_image, &options)
+            .unwrap();
+        let manifests = wim_patch.get_manifests(&patch_image).unwrap();
+        assert_eq!(manifests.len(), 1);
+        assert_eq!(manifests[0].1.base_image_info.index, 1);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// 追加补丁衔接测试：新补丁的基础镜像为已有补丁的基础镜像或更新镜像时才能形成补丁链
+    #[test]
+    fn test_is_appendable_to() {
+        let manifest = |base_guid: &str, target_guid: &str| test_manifest("1.0.0", base_guid, target_guid, &[]);
+        let existing = vec![(
+            1,
+            manifest(
+                "{3F2504E0-4F89-11D3-9A0C-0305E82C3301}",
+                "{3F2504E0-4F89-11D3-9A0C-0305E82C3302}",
+            ),
+        )];
+        // 衔接在已有补丁之后（GUID 大小写与括号不影响匹配）
+        assert!(is_appendable_to(&existing, "3f2504e0-4f89-11d3-9a0c-0305e82c3302"));
+        // 同一基线的其他版本
+        assert!(is_appendable_to(&existing, "{3F2504E0-4F89-11D3-9A0C-0305E82C3301}"));
+        // 无关的基础镜像
+        assert!(!is_appendable_to(&existing, "{3F2504E0-4F89-11D3-9A0C-0305E82C3303}"));
+        assert!(!is_appendable_to(&[], "{3F2504E0-4F89-11D3-9A0C-0305E82C3301}"));
+    }
+
+    /// 总体进度测试：出错提前返回时同样结束总体进度
+    #[test]
+    fn test_overall_progress() {
+        // 记录事件的观察者
+        struct Recorder(Arc<Mutex<Vec<String>>>);
+        impl ProgressObserver for Recorder {
+            fn overall_started(&self, total: u64) {
+                self.0.lock().unwrap().push(format!("start {}", total));
+            }
+            fn overall_finished(&self) {
+                self.0.lock().unwrap().push("finish".to_string());
+            }
+        }
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let recorder = Recorder(events.clone());
+        let apply = |fail: bool| -> anyhow::Result<()> {
+            let _overall = OverallProgress::start(&recorder, 3);
+            if fail {
+                return Err(anyhow::anyhow!("apply failed"));
+            }
+            Ok(())
+        };
+        assert!(apply(false).is_ok());
+        assert!(apply(true).is_err());
+        assert_eq!(
+            events.lock().unwrap().drain(..).collect::<Vec<_>>(),
+            vec!["start 3", "finish", "start 3", "finish"]
+        );
+    }
+
+    /// 进度观察者测试：创建与应用文件操作时按顺序收到开始、逐个操作与完成事件
+    #[test]
+    fn test_progress_observer() {
+        // 记录事件的观察者
+        struct Recorder(Arc<Mutex<Vec<String>>>);
+        impl ProgressObserver for Recorder {
+            fn operations_started(&self, task: u32, total: Option<u64>) {
+                self.0.lock().unwrap().push(format!("start {} {:?}", task, total));
+            }
+            fn operation(&self, task: u32, progress: &OperationProgress) {
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push(format!("op {} {}/{}", task, progress.position, progress.total));
+            }
+            fn operations_finished(&self, task: u32) {
+                self.0.lock().unwrap().push(format!("finish {}", task));
+            }
+        }
+
+        let root = std::env::temp_dir().join(get_tmp_name("observer-", "", 6));
+        let base_dir = root.join("base");
+        let target_dir = root.join("target");
+        let patch_dir = root.join("patch");
+        for dir in [&base_dir, &target_dir, &patch_dir] {
+            fs::create_dir_all(dir).unwrap();
+        }
+        fs::write(base_dir.join("modify.txt"), b"old").unwrap();
+        fs::write(base_dir.join("delete.txt"), b"deleted").unwrap();
+        fs::write(target_dir.join("modify.txt"), b"new").unwrap();
+        fs::write(target_dir.join("add.txt"), b"added").unwrap();
+
+        let options = CreateOptions {
+            storage: Storage::Full,
+            author: String::new(),
+            name: "test-patch".to_string(),
+            ..Default::default()
+        };
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let mut wim_patch = WimPatch::new().unwrap();
+        wim_patch.set_progress_observer(Recorder(events.clone()));
+
+        // 创建：比较完成前总数未知
+        let (operations, _, _) = wim_patch
+            .create_operations(&base_dir, &target_dir, &patch_dir, 2, &options)
+            .unwrap();
+        assert_eq!(operations.len(), 3);
+        assert_eq!(
+            events.lock().unwrap().drain(..).collect::<Vec<_>>(),
+            vec!["start 2 None", "op 2 1/3", "op 2 2/3", "op 2 3/3", "finish 2"]
+        );
+
+        // 应用：总数为操作数量，返回各操作的结果统计
+        let counts = wim_patch
+            .apply_operations(&base_dir, &patch_dir, &operations, 3, &ApplyOptions::default())
+            .unwrap();
+        assert_eq!(counts.applied + counts.already_applied, 3);
+        assert_eq!((counts.skipped, counts.failed), (0, 0));
+        assert_eq!(
+            events.lock().unwrap().drain(..).collect::<Vec<_>>(),
+            vec!["start 3 Some(3)", "op 3 1/3", "op 3 2/3", "op 3 3/3", "finish 3"]
+        );
+        let mut differences = 0;
+        compare_directories(&base_dir, &target_dir, |_, _, _, _| {
+            differences += 1;
+            true
+        })
+        .unwrap();
+        assert_eq!(differences, 0);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// 挂载守卫测试：挂载后提前返回错误或发生 panic 时自动卸载，不残留挂载点
+    #[test]
+    fn test_mount_guard() {
+        let root = std::env::temp_dir().join(get_tmp_name("guard-", "", 6));
+        let source = root.join("source");
+        let mount_path = root.join("mount");
+        fs::create_dir_all(&source).unwrap();
+        fs::create_dir_all(&mount_path).unwrap();
+        fs::write(source.join("file.txt"), b"payload").unwrap();
+
+        // 捕获测试镜像
+        let wimgapi = Wimgapi::new(None).unwrap();
+        let image = root.join("image.wim");
+        let handle = wimgapi
+            .open(&image, WIM_GENERIC_WRITE, WIM_CREATE_ALWAYS, WIM_COMPRESS_NONE)
+            .unwrap();
+        wimgapi.set_temp_path(handle, &get_temp_path()).unwrap();
+        wimgapi.close(wimgapi.capture(handle, &source, 0).unwrap()).unwrap();
+        wimgapi.close(handle).unwrap();
+
+        // 挂载后返回错误
+        let result = (|| -> anyhow::Result<()> {
+            let handle = HandleGuard::new(
+                &wimgapi,
+                wimgapi.open(
+                    &image,
+                    WIM_GENERIC_READ | WIM_GENERIC_MOUNT,
+                    WIM_OPEN_EXISTING,
+                    WIM_COMPRESS_NONE,
+                )?,
+            );
+            wimgapi.set_temp_path(handle.handle(), &get_temp_path())?;
+            let image_handle = HandleGuard::new(&wimgapi, wimgapi.load_image(handle.handle(), 1)?);
+            let _mounted = MountGuard::mount(image_handle, &mount_path, WIM_FLAG_MOUNT_READONLY)?;
+            assert!(mount_path.join("file.txt").exists());
+            Err(anyhow::anyhow!("forced error"))
+        })();
+        assert!(result.is_err());
+
+        // 挂载点已卸载
+        let is_mounted = || {
+            let mount_path = mount_path.to_string_lossy().to_ascii_lowercase();
+            wimgapi
+                .get_mounted_image()
+                .unwrap()
+                .iter()
+                .any(|info| info.mount_path.trim_end_matches('\\').to_ascii_lowercase() == mount_path)
+        };
+        assert!(!mount_path.join("file.txt").exists());
+        assert!(!is_mounted());
+
+        // 挂载后发生 panic（发布版本按展开方式处理 panic，守卫同样会被释放）
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let handle = HandleGuard::new(
+                &wimgapi,
+                wimgapi
+                    .open(
+                        &image,
+                        WIM_GENERIC_READ | WIM_GENERIC_MOUNT,
+                        WIM_OPEN_EXISTING,
+                        WIM_COMPRESS_NONE,
+                    )
+                    .unwrap(),
+            );
+            wimgapi.set_temp_path(handle.handle(), &get_temp_path()).unwrap();
+            let image_handle = HandleGuard::new(&wimgapi, wimgapi.load_image(handle.handle(), 1).unwrap());
+            let _mounted = MountGuard::mount(image_handle, &mount_path, WIM_FLAG_MOUNT_READONLY).unwrap();
+            assert!(mount_path.join("file.txt").exists());
+            panic!("forced panic");
+        }));
+        assert!(result.is_err());
+        assert!(!mount_path.join("file.txt").exists());
+        assert!(!is_mounted());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// 应用结果报告测试：按镜像与总计统计操作结果，失败时保留已完成的进度
+    #[test]
+    fn test_apply_report() {
+        let manifest = test_manifest("1.1.0", "{3F2504E0-4F89-11D3-9A0C-0305E82C3301}", "", &[]);
+        let mut report = ApplyReport::new(Path::new("base.wim"), Path::new("patch.wim"), Path::new("target.wim"));
+        report.base_guid = Some("{3F2504E0-4F89-11D3-9A0C-0305E82C3301}".to_string());
+
+        // 应用文件操作返回的统计按镜像累加
+        report.start_image(1, &[(2, manifest.clone())], MatchConfidence::Exact);
+        let mut counts = OperationCounts::default();
+        counts.record(OperationOutcome::Applied);
+        counts.record(OperationOutcome::Applied);
+        report.record_operations(1, counts);
+        report.record_operation(1, OperationOutcome::Skipped);
+        report.finish_image(1);
+        report.start_image(2, &[(2, manifest)], MatchConfidence::GuidOnly);
+        report.record_operation(2, OperationOutcome::Failed);
+        report.error = Some("Apply operations error".to_string());
+
+        assert_eq!(report.images[0].operations.applied, 2);
+        assert_eq!(report.images[0].operations.skipped, 1);
+        assert!(report.images[0].completed);
+        assert_eq!(report.images[1].operations.failed, 1);
+        assert!(!report.images[1].completed);
+        assert_eq!(
+            report.operations,
+            OperationCounts {
+                applied: 2,
+                skipped: 1,
+                failed: 1,
+                already_applied: 0
+            }
+        );
+
+        let root = std::env::temp_dir().join(get_tmp_name("report-", "", 6));
+        fs::create_dir_all(&root).unwrap();
+
+        // 扩展名为 .json 时写入 JSON
+        report.write(&root.join("report.json")).unwrap();
+        let json: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(root.join("report.json")).unwrap()).unwrap();
+        assert_eq!(json["success"], false);
+        assert_eq!(json["images"][0]["patches"][0]["version"], "1.1.0");
+        assert_eq!(json["images"][1]["operations"]["failed"], 1);
+        assert_eq!(json["images"][1]["match_confidence"], "guid-only");
+        assert_eq!(json["tool_version"], env!("CARGO_PKG_VERSION"));
+
+        // 其他扩展名写入文本
+        report.write(&root.join("report.txt")).unwrap();
+        let text = fs::read_to_string(root.join("report.txt")).unwrap();
+        assert!(text.contains("Index 2 (incomplete)"));
+        assert!(text.contains("guid-only"));
+        assert!(text.contains("applied 2 / skipped 1 / failed 1"));
+        assert!(text.contains("Apply operations error"));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// 补丁清单路径测试：增补平面字符、XML 特殊字符与控制字符在 XML 清单中无损往返
+    #[test]
+    fn test_manifest_xml_paths() {
+        let paths = [
+            "Windows\\Fonts\\😀 emoji.txt",
+            "Users\\𠀀𪜀\\扩展B.txt",
+            "a<b>&c\"d'e.txt",
+            "control\u{1}\u{8}\u{1F}.txt",
+            "line\tbreak\r\n.txt",
+            " leading and trailing ",
+            "question?0041.txt",
+            "nonchar\u{FFFE}\u{FFFF}.txt",
+        ];
+        let operations: Vec<Operation> = paths
+            .iter()
+            .map(|path| Operation {
+                action: Action::Add,
+                path: path.to_string(),
+                size: Some(1),
+                storage: None,
+                payload_ref: Some(format!("{}.payload", path)),
+                full_fallback: false,
+                sparse: false,
+                case_rename: false,
+                streams: Vec::new(),
+                dict_window: None,
+                sha256: None,
+                base_sha256: None,
+            })
+            .collect();
+        let manifest = test_manifest("1.0.0", "{3F2504E0-4F89-11D3-9A0C-0305E82C3301}", "", &operations);
+
+        let xml = manifest.to_xml().unwrap();
+        // 增补平面字符直接保存，XML 中不包含控制字符
+        assert!(xml.contains("😀 emoji.txt"));
+        assert!(xml.contains("𠀀𪜀"));
+        assert!(!xml.chars().any(|c| c < ' '));
+        assert_eq!(PatchManifest::from_xml(&xml).unwrap(), manifest);
+
+        // 普通路径保持不变，与旧版本清单兼容
+        assert_eq!(encode_xml_path("Windows\\a b.dll"), "Windows\\a b.dll");
+        assert_eq!(encode_xml_path(" a\u{1}?"), "?0020a?0001?003F");
+        assert_eq!(decode_xml_path("?0020a?0001?003F"), " a\u{1}?");
+        // 无效的转义序列保持不变
+        assert_eq!(decode_xml_path("a?zz?12"), "a?zz?12");
+    }
+
+    /// 自动匹配索引测试：镜像数量不同时列出被跳过的索引
+    #[test]
+    fn test_unmatched_indices() {
+        assert_eq!(unmatched_indices(3, 3), (vec![], vec![]));
+        assert_eq!(unmatched_indices(4, 2), (vec![3, 4], vec![]));
+        assert_eq!(unmatched_indices(1, 3), (vec![], vec![2, 3]));
+        assert_eq!(unmatched_indices(0, 1), (vec![], vec![1]));
+    }
+
+    /// 保留可启动镜像测试：基础镜像的可启动镜像映射到补丁文件中对应的镜像索引
+    #[test]
+    fn test_preserved_boot_index() {
+        // 基础镜像无可启动镜像
+        assert_eq!(preserved_boot_index(0, &[1, 2, 3], 3), None);
+        // 全部索引均生成补丁
+        assert_eq!(preserved_boot_index(2, &[1, 2, 3], 3), Some(2));
+        // 索引 1 无变更被跳过
+        assert_eq!(preserved_boot_index(3, &[2, 3], 2), Some(2));
+        // 可启动镜像无变更，未生成补丁
+        assert_eq!(preserved_boot_index(1, &[2, 3], 2), None);
+        // 追加到已有 2 个镜像的补丁文件
+        assert_eq!(preserved_boot_index(2, &[2], 3), Some(3));
+    }
+
+    /// 保留创建时间测试：替换嵌套的 HIGHPART/LOWPART，最后修改时间保持不变
+    #[test]
+    fn test_copy_creation_time() {
+        let base_xml = "<IMAGE INDEX=\"1\"><CREATIONTIME><HIGHPART>0x01DA844F</HIGHPART>\
+            <LOWPART>0xF3BEDE84</LOWPART></CREATIONTIME><LASTMODIFICATIONTIME><HIGHPART>0x01DB5F6E</HIGHPART>\
+            <LOWPART>0x47D41114</LOWPART></LASTMODIFICATIONTIME></IMAGE>";
+        let target_xml = "<IMAGE INDEX=\"1\"><CREATIONTIME><HIGHPART>0x01DC0001</HIGHPART>\
+            <LOWPART>0x00000001</LOWPART></CREATIONTIME><LASTMODIFICATIONTIME><HIGHPART>0x01DC0002</HIGHPART>\
+            <LOWPART>0x00000002</LOWPART></LASTMODIFICATIONTIME></IMAGE>";
+
+        assert_eq!(get_xml_field(base_xml, "CREATIONTIME/HIGHPART"), Some("0x01DA844F"));
+        assert_eq!(
+            get_xml_field(base_xml, "LASTMODIFICATIONTIME/LOWPART"),
+            Some("0x47D41114")
+        );
+        assert_eq!(get_xml_field(base_xml, "CREATIONTIME/NAME"), None);
+
+        let xml = copy_creation_time(base_xml, target_xml);
+        assert_eq!(get_xml_field(&xml, "CREATIONTIME/HIGHPART"), Some("0x01DA844F"));
+        assert_eq!(get_xml_field(&xml, "CREATIONTIME/LOWPART"), Some("0xF3BEDE84"));
+        assert_eq!(get_xml_field(&xml, "LASTMODIFICATIONTIME/HIGHPART"), Some("0x01DC0002"));
+        assert_eq!(get_xml_field(&xml, "LASTMODIFICATIONTIME/LOWPART"), Some("0x00000002"));
+
+        // 基础镜像缺少创建时间时保持不变
+        assert_eq!(copy_creation_time("<IMAGE></IMAGE>", target_xml), target_xml);
+    }
+
+    /// 导出目标镜像保留创建时间测试：指定 preserve_creation_time 时目标镜像的创建时间与基础镜像一致
+    #[test]
+    fn test_export_preserve_creation_time() {
+        let root = std::env::temp_dir().join(get_tmp_name("creation-time-", "", 6));
+        let source = root.join("source");
+        fs::create_dir_all(&source).unwrap();
+        fs::write(source.join("file.txt"), b"base").unwrap();
+
+        // 基础镜像的创建时间设置为较早的固定值
+        let old_time = "<IMAGE><CREATIONTIME><HIGHPART>0x01DA844F</HIGHPART>\
+            <LOWPART>0xF3BEDE84</LOWPART></CREATIONTIME></IMAGE>";
+        let wimgapi = Wimgapi::new(None).unwrap();
+        let base_image = root.join("base.wim");
+        let handle = wimgapi
+            .open(&base_image, WIM_GENERIC_WRITE, WIM_CREATE_ALWAYS, WIM_COMPRESS_NONE)
+            .unwrap();
+        wimgapi.set_temp_path(handle, &get_temp_path()).unwrap();
+        let image_handle = wimgapi.capture(handle, &source, 0).unwrap();
+        let image_info = wimgapi.get_image_info(image_handle).unwrap();
+        let base_xml = copy_creation_time(old_time, &image_info);
+        wimgapi.set_image_info(image_handle, &base_xml).unwrap();
+        wimgapi.close(image_handle).unwrap();
+        wimgapi.close(handle).unwrap();
+
+        let creation_time = |image: &Path| {
+            let handle = HandleGuard::new(
+                &wimgapi,
+                wimgapi
+                    .open(image, WIM_GENERIC_READ, WIM_OPEN_EXISTING, WIM_COMPRESS_NONE)
+                    .unwrap(),
+            );
+            wimgapi.set_temp_path(handle.handle(), &get_temp_path()).unwrap();
+            let image_handle = HandleGuard::new(&wimgapi, wimgapi.load_image(handle.handle(), 1).unwrap());
+            let xml = wimgapi.get_image_info(image_handle.handle()).unwrap();
+            ["CREATIONTIME/HIGHPART", "CREATIONTIME/LOWPART"].map(|field| get_xml_field(&xml, field).map(String::from))
+        };
+
+        let wim_patch = WimPatch::new().unwrap();
+        let target_image = root.join("target.wim");
+        let options = ApplyOptions {
+            preserve_creation_time: true,
+            ..Default::default()
+        };
+        let base_image_xml = std::collections::HashMap::from([(1, base_xml)]);
+        wim_patch
+            .export_target_image(
+                &base_image,
+                &target_image,
+                &Default::default(),
+                &base_image_xml,
+                &options,
+            )
+            .unwrap();
+        assert_eq!(
+            creation_time(&target_image),
+            [Some("0x01DA844F".to_string()), Some("0xF3BEDE84".to_string())]
+        );
+
+        // 未指定时创建时间为导出时间
+        wim_patch
+            .export_target_image(
+                &base_image,
+                &target_image,
+                &Default::default(),
+                &Default::default(),
+                &ApplyOptions::default(),
+            )
+            .unwrap();
+        assert_ne!(creation_time(&target_image), creation_time(&base_image));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// 拆分补丁文件名测试：替换非法字符，文件名冲突时追加镜像索引
+    #[test]
+    fn test_split_output_names() {
+        let new_manifest = |version: &str, guid: &str| test_manifest(version, guid, "guid-target", &[]);
+        let manifests = vec![
+            (1, new_manifest("1.0.0", "{3F2504E0-4F89-11D3-9A0C-0305E82C3301}")),
+            (2, new_manifest("1.1.0", "3f2504e0-4f89-11d3-9a0c-0305e82c3301")),
+            (3, new_manifest("1.1.0", "{3F2504E0-4F89-11D3-9A0C-0305E82C3301}")),
+            (4, new_manifest("2.0/beta", "guid:base")),
+        ];
+        assert_eq!(
+            split_output_names(&manifests),
+            vec![
+                (1, "1.0.0_3F2504E0-4F89-11D3-9A0C-0305E82C3301.wim".to_string()),
+                (2, "1.1.0-2_3F2504E0-4F89-11D3-9A0C-0305E82C3301.wim".to_string()),
+                (3, "1.1.0-3_3F2504E0-4F89-11D3-9A0C-0305E82C3301.wim".to_string()),
+                (4, "2.0_beta_guid_base.wim".to_string()),
+            ]
+        );
+    }
+
+    /// 排除差异测试：计算目标镜像统计信息与补丁清单的差异
+    #[test]
+    fn test_image_stat_divergence() {
+        let expected = ImageInfo {
+            dir_count: 10,
+            file_count: 100,
+            total_bytes: 4096,
+            ..ImageInfo::default()
+        };
+        assert_eq!(image_stat_divergence(&expected, &expected.clone()), None);
+
+        // 排除删除操作后目标镜像多出文件
+        let actual = ImageInfo {
+            file_count: 102,
+            total_bytes: 6144,
+            ..expected.clone()
+        };
+        assert_eq!(image_stat_divergence(&expected, &actual), Some((0, 2, 2048)));
+
+        // 排除新增目录后目标镜像缺少目录
+        let actual = ImageInfo {
+            dir_count: 9,
+            ..expected.clone()
+        };
+        assert_eq!(image_stat_divergence(&expected, &actual), Some((-1, 0, 0)));
+    }
+
+    /// 跳过删除测试：指定 no_delete 时删除操作被跳过，新增与修改操作仍然应用
+    #[test]
+    fn test_apply_no_delete() {
+        let root = std::env::temp_dir().join(get_tmp_name("nodelete-", "", 6));
+        let base_dir = root.join("base");
+        let patch_dir = root.join("patch");
+        fs::create_dir_all(&base_dir).unwrap();
+        fs::create_dir_all(&patch_dir).unwrap();
+        fs::write(base_dir.join("modify.txt"), b"old").unwrap();
+        fs::write(base_dir.join("delete.txt"), b"keep me").unwrap();
+        fs::write(patch_dir.join("modify.txt"), b"new").unwrap();
+        fs::write(patch_dir.join("add.txt"), b"added").unwrap();
+
+        let operation =
+            |action, path: &str, size: Option<u64>| test_operation(action, path, size, size.map(|_| "full"));
+        let operations = vec![
+            operation(Action::Add, "add.txt", Some(5)),
+            operation(Action::Modify, "modify.txt", Some(3)),
+            operation(Action::Delete, "delete.txt", None),
+        ];
+        let options = ApplyOptions {
+            no_delete: true,
+            ..Default::default()
+        };
+        let wim_patch = WimPatch::new().unwrap();
+        wim_patch
+            .apply_operations(&base_dir, &patch_dir, &operations, 1, &options)
+            .unwrap();
+        assert_eq!(fs::read(base_dir.join("add.txt")).unwrap(), b"added");
+        assert_eq!(fs::read(base_dir.join("modify.txt")).unwrap(), b"new");
+        assert_eq!(fs::read(base_dir.join("delete.txt")).unwrap(), b"keep me");
+
+        // 未指定时正常删除
+        wim_patch
+            .apply_operations(&base_dir, &patch_dir, &operations, 1, &ApplyOptions::default())
+            .unwrap();
+        assert!(!base_dir.join("delete.txt").exists());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// 校验文件测试：生成与解析校验文件，哈希比较不区分大小写
+    #[test]
+    fn test_checksum_sidecar() {
+        let manifest = test_manifest("1.1.0", "guid-base", "guid-target", &[]);
+        let sha256 = "AB".repeat(32);
+        let checksum = Checksum::new(&sha256, "patch.wim", &[(2, manifest.clone())]);
+        assert_eq!(checksum.sha256, "ab".repeat(32));
+        assert_eq!(
+            checksum.images,
+            vec![ChecksumImage {
+                index: 2,
+                id: manifest.id.clone(),
+                version: "1.1.0".to_string(),
+            }]
+        );
+        assert!(checksum.matches(&sha256));
+        assert!(!checksum.matches(&"cd".repeat(32)));
+
+        // 解析生成的内容（忽略注释、空行与 CRLF）
+        let text = checksum.to_text().replace('\n', "\r\n") + "\r\n# comment\r\n";
+        assert_eq!(Checksum::from_text(&text).unwrap(), checksum);
+
+        // 缺少或格式错误的哈希
+        assert!(Checksum::from_text("file: patch.wim\n").is_err());
+        assert!(Checksum::from_text("sha256: 1234\n").is_err());
+        assert!(Checksum::from_text(&format!("sha256: {}\nimage: 1\n", sha256)).is_err());
+    }
+
+    /// 保留压缩类型测试：使用镜像最多的输入压缩类型，压缩类型不同时标记需要重新压缩
+    #[test]
+    fn test_merge_output_compression() {
+        assert_eq!(
+            merge_output_compression(&[(WIM_COMPRESS_XPRESS, 1), (WIM_COMPRESS_XPRESS, 2)]),
+            (WIM_COMPRESS_XPRESS, false)
+        );
+        assert_eq!(
+            merge_output_compression(&[(WIM_COMPRESS_XPRESS, 1), (WIM_COMPRESS_LZX, 2)]),
+            (WIM_COMPRESS_LZX, true)
+        );
+        // 数量相同时使用先出现的压缩类型
+        assert_eq!(
+            merge_output_compression(&[(WIM_COMPRESS_NONE, 2), (WIM_COMPRESS_LZX, 1), (WIM_COMPRESS_LZX, 1)]),
+            (WIM_COMPRESS_NONE, true)
+        );
+        assert_eq!(merge_output_compression(&[]), (WIM_COMPRESS_LZX, false));
+        assert_eq!(compression_name(WIM_COMPRESS_LZMS), "lzms");
+    }
+
+    /// 原子输出测试：导出失败时不产生不完整的目标镜像，已存在的目标镜像保持不变
+    #[test]
+    fn test_atomic_output() {
+        let root = std::env::temp_dir().join(get_tmp_name("atomic-", "", 6));
+        fs::create_dir_all(&root).unwrap();
+        fs::create_dir_all(get_temp_path()).unwrap();
+        let target = root.join("target.wim");
+
+        // 导出中途失败：目标镜像不存在，临时文件被删除
+        let mut temp = PathBuf::new();
+        let result = WimPatch::with_atomic_output(&target, |output| {
+            temp = output.to_path_buf();
+            fs::write(output, b"partial")?;
+            Err::<(), _>(anyhow::anyhow!("export failed"))
+        });
+        assert!(result.is_err());
+        assert!(!target.exists());
+        assert!(!temp.exists());
+
+        // 导出成功后替换目标镜像
+        WimPatch::with_atomic_output(&target, |output| Ok(fs::write(output, b"old")?)).unwrap();
+        assert_eq!(fs::read(&target).unwrap(), b"old");
+
+        // 已存在的目标镜像在失败时保持不变
+        let result = WimPatch::with_atomic_output(&target, |output| {
+            fs::write(output, b"partial")?;
+            Err::<(), _>(anyhow::anyhow!("export failed"))
+        });
+        assert!(result.is_err());
+        assert_eq!(fs::read(&target).unwrap(), b"old");
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// 备用数据流测试：指定 preserve_streams 时记录新增与修改文件的数据流，并在应用时重新创建
+    #[test]
+    fn test_preserve_streams() {
+        let root = std::env::temp_dir().join(get_tmp_name("streams-", "", 6));
+        let base_dir = root.join("base");
+        let target_dir = root.join("target");
+        let patch_dir = root.join("patch");
+        fs::create_dir_all(&base_dir).unwrap();
+        fs::create_dir_all(&target_dir).unwrap();
+        fs::create_dir_all(&patch_dir).unwrap();
+        fs::write(base_dir.join("modify.txt"), b"old").unwrap();
+        fs::write(target_dir.join("modify.txt"), b"new").unwrap();
+        fs::write(target_dir.join("add.txt"), b"added").unwrap();
+        let zone = b"[ZoneTransfer]\r\nZoneId=3\r\n";
+        fs::write(stream_path(target_dir.join("add.txt"), "Zone.Identifier"), zone).unwrap();
+        fs::write(stream_path(target_dir.join("modify.txt"), "custom"), b"custom stream").unwrap();
+        assert_eq!(
+            list_streams(target_dir.join("add.txt")).unwrap(),
+            vec![("Zone.Identifier".to_string(), zone.len() as u64)]
+        );
+
+        let mut options = CreateOptions {
+            storage: Storage::Full,
+            author: String::new(),
+            name: "test-patch".to_string(),
+            ..Default::default()
+        };
+        let wim_patch = WimPatch::new().unwrap();
+
+        // 未指定时不记录数据流
+        let (operations, _, _) = wim_patch
+            .create_operations(&base_dir, &target_dir, &patch_dir, 1, &options)
+            .unwrap();
+        assert!(operations.iter().all(|op| op.streams.is_empty()));
+        fs::remove_dir_all(&patch_dir).unwrap();
+        fs::create_dir_all(&patch_dir).unwrap();
+
+        options.preserve_streams = true;
+        let (operations, _, _) = wim_patch
+            .create_operations(&base_dir, &target_dir, &patch_dir, 1, &options)
+            .unwrap();
+        let add = operations.iter().find(|op| op.path == "add.txt").unwrap();
+        assert_eq!(
+            add.streams,
+            vec![StreamInfo {
+                name: "Zone.Identifier".to_string(),
+                size: zone.len() as u64,
+            }]
+        );
+        assert!(patch_dir.join(WimPatch::stream_payload_path("add.txt", 0)).exists());
+
+        // 数据流信息在清单序列化后保持不变
+        let manifest = test_manifest("1.0.0", "", "", &operations);
+        let parsed = PatchManifest::from_xml(&manifest.to_xml().unwrap()).unwrap();
+        assert_eq!(parsed.operations, operations);
+
+        // 应用后数据流与更新目录一致
+        wim_patch
+            .apply_operations(&base_dir, &patch_dir, &parsed.operations, 1, &ApplyOptions::default())
+            .unwrap();
+        assert_eq!(fs::read(base_dir.join("add.txt")).unwrap(), b"added");
+        assert_eq!(
+            fs::read(stream_path(base_dir.join("add.txt"), "Zone.Identifier")).unwrap(),
+            zone
+        );
+        assert_eq!(fs::read(base_dir.join("modify.txt")).unwrap(), b"new");
+        assert_eq!(
+            fs::read(stream_path(base_dir.join("modify.txt"), "custom")).unwrap(),
+            b"custom stream"
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// 备用数据流镜像测试：捕获的镜像创建补丁时暂存数据流，应用后释放的目标镜像中数据流与更新目录一致（需要管理员权限）
+    #[test]
+    #[ignore]
+    fn test_preserve_streams_wim() {
+        let root = std::env::temp_dir().join(get_tmp_name("streams-wim-", "", 6));
+        let base_dir = root.join("base");
+        let target_dir = root.join("target");
+        let result_dir = root.join("result");
+        for dir in [&base_dir, &target_dir, &result_dir] {
+            fs::create_dir_all(dir).unwrap();
+        }
+        fs::write(base_dir.join("modify.txt"), b"old").unwrap();
+        fs::write(target_dir.join("modify.txt"), b"new").unwrap();
+        fs::write(target_dir.join("add.txt"), b"added").unwrap();
+        let zone = b"[ZoneTransfer]\r\nZoneId=3\r\n";
+        fs::write(stream_path(target_dir.join("add.txt"), "Zone.Identifier"), zone).unwrap();
+        fs::write(stream_path(target_dir.join("modify.txt"), "custom"), b"custom stream").unwrap();
+
+        let wimgapi = Wimgapi::new(None).unwrap();
+        let capture = |source: &Path, image: &Path| {
+            let handle = wimgapi
+                .open(image, WIM_GENERIC_WRITE, WIM_CREATE_ALWAYS, WIM_COMPRESS_NONE)
+                .unwrap();
+            wimgapi.set_temp_path(handle, &get_temp_path()).unwrap();
+            wimgapi.close(wimgapi.capture(handle, source, 0).unwrap()).unwrap();
+            wimgapi.close(handle).unwrap();
+        };
+        let base_image = root.join("base.wim");
+        let target_image = root.join("target.wim");
+        capture(&base_dir, &base_image);
+        capture(&target_dir, &target_image);
+
+        let options = CreateOptions {
+            storage: Storage::Full,
+            preset: Preset::Fast,
+            author: String::new(),
+            name: "test-patch".to_string(),
+            compress: Compress::None,
+            preserve_streams: true,
+            compare_mode: CompareMode::Hash,
+            ..Default::default()
+        };
+        let wim_patch = WimPatch::new().unwrap();
+        let patch_image = root.join("patch.wim");
+        wim_patch
+            .create_patch(&base_image, Some(1), &target_image, Some(1), &patch_image, &options)
+            .unwrap();
+        let manifests = wim_patch.get_manifests(&patch_image).unwrap();
+        let streams = |path: &str| {
+            let operation = manifests[0].1.operations.iter().find(|op| op.path == path).unwrap();
+            operation
+                .streams
+                .iter()
+                .map(|stream| stream.name.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(streams("add.txt"), ["Zone.Identifier"]);
+        assert_eq!(streams("modify.txt"), ["custom"]);
+
+        // 释放应用后的目标镜像，检查数据流
+        let result_image = root.join("result.wim");
+        wim_patch
+            .apply_patch(
+                &base_image,
+                Some(1),
+                &patch_image,
+                &result_image,
+                &ApplyOptions::default(),
+            )
+            .unwrap();
+        let handle = wimgapi
+            .open(&result_image, WIM_GENERIC_READ, WIM_OPEN_EXISTING, WIM_COMPRESS_NONE)
+            .unwrap();
+        wimgapi.set_temp_path(handle, &get_temp_path()).unwrap();
+        let image_handle = wimgapi.load_image(handle, 1).unwrap();
+        wimgapi.apply_image(image_handle, &result_dir, 0).unwrap();
+        wimgapi.close(image_handle).unwrap();
+        wimgapi.close(handle).unwrap();
+        assert_eq!(
+            fs::read(stream_path(result_dir.join("add.txt"), "Zone.Identifier")).unwrap(),
+            zone
+        );
+        assert_eq!(
+            fs::read(stream_path(result_dir.join("modify.txt"), "custom")).unwrap(),
+            b"custom stream"
+        );
+        assert_eq!(fs::read(result_dir.join("modify.txt")).unwrap(), b"new");
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// 目录补丁测试：不加载 wimgapi.dll 在两个目录之间创建并应用补丁
+    #[test]
+    fn test_dir_patch() {
+        let root = std::env::temp_dir().join(get_tmp_name("dirpatch-", "", 6));
+        let base_dir = root.join("base");
+        let target_dir = root.join("target");
+        let patch_dir = root.join("patch");
+        let out_dir = root.join("out");
+        fs::create_dir_all(base_dir.join("sub")).unwrap();
+        fs::create_dir_all(target_dir.join("sub")).unwrap();
+        fs::create_dir_all(target_dir.join("added")).unwrap();
+        fs::write(base_dir.join("unchanged.txt"), b"same").unwrap();
+        fs::write(target_dir.join("unchanged.txt"), b"same").unwrap();
+        fs::write(base_dir.join("sub").join("modify.txt"), "version 1.0.0\r\n".repeat(64)).unwrap();
+        fs::write(
+            target_dir.join("sub").join("modify.txt"),
+            "version 1.1.0\r\n".repeat(64),
+        )
+        .unwrap();
+        fs::write(base_dir.join("delete.txt"), b"deleted").unwrap();
+        fs::write(target_dir.join("added").join("new.txt"), b"added").unwrap();
+
+        let options = CreateOptions {
+            preset: Preset::Fast,
+            version: "1.1.0".to_string(),
+            author: String::new(),
+            name: "dir-patch".to_string(),
+            compress: Compress::None,
+            skip_space_check: true,
+            compare_mode: CompareMode::Hash,
+            ..Default::default()
+        };
+        let wim_patch = WimPatch::without_wimgapi().unwrap();
+
+        // 输出目录位于比较的目录中时拒绝创建
+        for dir in [&base_dir, &target_dir] {
+            assert!(
+                wim_patch
+                    .create_dir_patch(&base_dir, &target_dir, &dir.join("patch"), &options)
+                    .is_err()
+            );
+            assert!(!dir.join("patch").exists());
+        }
+
+        assert!(
+            wim_patch
+                .create_dir_patch(&base_dir, &target_dir, &patch_dir, &options)
+                .unwrap()
+        );
+        assert!(patch_dir.join(DIR_PATCH_MANIFEST).is_file());
+        assert!(patch_dir.join(DIR_PATCH_PAYLOAD).is_dir());
+
+        // 输出目录不为空时拒绝创建
+        assert!(
+            wim_patch
+                .create_dir_patch(&base_dir, &target_dir, &patch_dir, &options)
+                .is_err()
+        );
+
+        // 覆盖时创建失败保留原目录补丁，成功后替换且不残留临时目录
+        let overwrite = CreateOptions {
+            on_existing: OnExisting::Overwrite,
+            ..options.clone()
+        };
+        let manifest = fs::read(patch_dir.join(DIR_PATCH_MANIFEST)).unwrap();
+        assert!(
+            wim_patch
+                .create_dir_patch(&root.join("missing"), &target_dir, &patch_dir, &overwrite)
+                .is_err()
+        );
+        assert_eq!(fs::read(patch_dir.join(DIR_PATCH_MANIFEST)).unwrap(), manifest);
+        assert!(
+            wim_patch
+                .create_dir_patch(&base_dir, &target_dir, &patch_dir, &overwrite)
+                .unwrap()
+        );
+        assert!(patch_dir.join(DIR_PATCH_MANIFEST).is_file());
+        assert_eq!(fs::read_dir(&root).unwrap().count(), 3);
+
+        // 应用到新的输出目录，基础目录保持不变
+        wim_patch
+            .apply_dir_patch(&base_dir, &patch_dir, &out_dir, &ApplyOptions::default())
+            .unwrap();
+        let differences = hash_differences(&target_dir, &out_dir);
+        assert!(differences.is_empty(), "{:?}", differences);
+        assert!(base_dir.join("delete.txt").exists());
+
+        // 输出目录位于基础目录中（尚不存在）时拒绝应用，不复制任何内容
+        let nested = base_dir.join("nested").join("out");
+        assert!(
+            wim_patch
+                .apply_dir_patch(&base_dir, &patch_dir, &nested, &ApplyOptions::default())
+                .is_err()
+        );
+        assert!(!base_dir.join("nested").exists());
+        assert_eq!(
+            canonicalize_lenient(&nested),
+            fs::canonicalize(&base_dir).unwrap().join("nested").join("out")
+        );
+
+        // 输出目录与基础目录相同时直接修改基础目录
+        wim_patch
+            .apply_dir_patch(&base_dir, &patch_dir, &base_dir, &ApplyOptions::default())
+            .unwrap();
+        assert!(!base_dir.join("delete.txt").exists());
+        assert_eq!(fs::read(base_dir.join("added").join("new.txt")).unwrap(), b"added");
+
+        // 没有差异时不创建补丁
+        let empty_patch = root.join("empty");
+        assert!(
+            !wim_patch
+                .create_dir_patch(&target_dir, &out_dir, &empty_patch, &options)
+                .unwrap()
+        );
+        assert!(!empty_patch.exists());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// 总进度测试：补丁链的文件操作数量不包含整镜像补丁之前的补丁
+    #[test]
+    fn test_chain_operation_count() {
+        let operation = |path: &str| test_operation(Action::Add, path, Some(1), None);
+        let manifest = |operations: &[Operation]| test_manifest("1.0.0", "", "", operations);
+        let mut whole = manifest(&[]);
+        whole.whole_image = true;
+
+        assert_eq!(chain_operation_count(&[]), 0);
+        let chain = vec![
+            (1, manifest(&[operation("a"), operation("b")])),
+            (2, manifest(&[operation("c")])),
+        ];
+        assert_eq!(chain_operation_count(&chain), 3);
+
+        // 整镜像补丁之前的补丁不会被应用
+        let chain = vec![
+            (1, manifest(&[operation("a"), operation("b")])),
+            (2, whole),
+            (3, manifest(&[operation("c")])),
+        ];
+        assert_eq!(chain_operation_count(&chain), 1);
+    }
+
+    /// 易变文件排除测试：通配符匹配完整路径或任一路径分隔符之后的部分路径
+    #[test]
+    fn test_volatile_pattern() {
+        assert!(wildcard_match("*.evtx", "Windows\\System32\\winevt\\Logs\\System.evtx"));
+        assert!(wildcard_match("a?c*", "ABCdef"));
+        assert!(!wildcard_match("*.etl", "trace.etl.bak"));
+        assert!(wildcard_match("*", ""));
+
+        assert_eq!(
+            volatile_pattern("Windows\\System32\\winevt\\Logs\\Application.evtx"),
+            Some("*.evtx")
+        );
+        assert_eq!(
+            volatile_pattern("Windows\\Prefetch\\CMD.EXE-0BD30981.pf"),
+            Some("Prefetch\\*")
+        );
+        assert_eq!(
+            volatile_pattern("Windows\\System32\\config\\SYSTEM.LOG1"),
+            Some("*.LOG1")
+        );
+        assert_eq!(volatile_pattern("Windows\\System32\\config\\SYSTEM"), None);
+        // 目录本身与名称中包含 Prefetch 的其他文件不被排除
+        assert_eq!(volatile_pattern("Windows\\Prefetch"), None);
+        assert_eq!(volatile_pattern("Windows\\NoPrefetch.txt"), None);
+    }
+
+    /// 匹配模式测试：按匹配模式接受仅 GUID 或仅统计信息一致的基础镜像，并返回补丁链的匹配程度
+    #[test]
+    fn test_match_patch_match_mode() {
+        let wim_patch = WimPatch::new().unwrap();
+        let original = "{3F2504E0-4F89-11D3-9A0C-0305E82C3301}";
+        let recaptured = "{9A0C0305-E82C-3301-3F25-04E04F8911D3}";
+        let state = |file_count| ImageInfo {
+            index: 1,
+            file_count,
+            ..Default::default()
+        };
+        let new_patch = |version, base, target| {
+            PatchManifest::new(
+                "test-patch",
+                "",
+                "",
+                version,
+                original,
+                &state(base),
+                "",
+                &state(target),
+                &[],
+                None,
+            )
+        };
+        let patches = vec![(1, new_patch("1.0.0", 10, 11)), (2, new_patch("1.1.0", 11, 12))];
+        let mode = |match_mode| ApplyOptions {
+            match_mode,
+            ..Default::default()
+        };
+
+        // GUID 与统计信息均一致
+        let result = wim_patch
+            .match_patch(original, &[state(10)], &patches, &mode(MatchMode::Exact))
+            .unwrap();
+        assert_eq!(result[0].2, MatchConfidence::Exact);
+
+        // 仅 GUID 一致：默认拒绝，--match-mode guid 接受
+        assert!(
+            wim_patch
+                .match_patch(original, &[state(9)], &patches, &mode(MatchMode::Exact))
+                .is_err()
+        );
+        let result = wim_patch
+            .match_patch(original, &[state(9)], &patches, &mode(MatchMode::Guid))
+            .unwrap();
+        assert_eq!(result[0].2, MatchConfidence::GuidOnly);
+
+        // 强制模式下记录为强制应用
+        let force = ApplyOptions {
+            force: true,
+            ..Default::default()
+        };
+        let result = wim_patch.match_patch(original, &[state(9)], &patches, &force).unwrap();
+        assert_eq!(result[0].2, MatchConfidence::Forced);
+
+        // 仅统计信息一致：默认不匹配，--match-mode stats 接受并继续构建完整的补丁链
+        assert!(
+            wim_patch
+                .match_patch(recaptured, &[state(10)], &patches, &mode(MatchMode::Exact))
+                .unwrap()
+                .is_empty()
+        );
+        let result = wim_patch
+            .match_patch(recaptured, &[state(10)], &patches, &mode(MatchMode::Stats))
+            .unwrap();
+        assert_eq!(result[0].1.len(), 2);
+        assert_eq!(result[0].2, MatchConfidence::StatsOnly);
+        assert!(
+            wim_patch
+                .match_patch(recaptured, &[state(10)], &patches, &mode(MatchMode::Guid))
+                .unwrap()
+                .is_empty()
+        );
+
+        // GUID 与统计信息均不一致时任何模式都不匹配
+        assert!(
+            wim_patch
+                .match_patch(recaptured, &[state(9)], &patches, &mode(MatchMode::Any))
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    /// 存储方式测试：依次测试完整存储、各压缩预设的 zstd 差异与 bsdiff 差异，非终端输出以制表符分隔
+    #[test]
+    fn test_benchmark() {
+        let root = std::env::temp_dir().join(get_tmp_name("benchmark-", "", 6));
+        fs::create_dir_all(&root).unwrap();
+        let old = root.join("old.bin");
+        let new = root.join("new.bin");
+        fs::write(&old, b"WimPatch benchmark 1.0.0\r\n".repeat(4096)).unwrap();
+        fs::write(&new, b"WimPatch benchmark 1.1.0\r\n".repeat(4096)).unwrap();
+
+        let results = run_benchmark(&old, &new).unwrap();
+        let methods: Vec<&str> = results.iter().map(|result| result.method.as_str()).collect();
+        assert_eq!(
+            methods,
+            [
+                "full",
+                "zstd fast (3)",
+                "zstd medium (9)",
+                "zstd best (19)",
+                "zstd extreme (22)",
+                "bsdiff"
+            ]
+        );
+        assert_eq!(results[0].size, fs::metadata(&new).unwrap().len());
+        assert!(results[1..].iter().all(|result| result.size < results[0].size));
+
+        let table = format_benchmark(&results, false);
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines[0], "method\tsize\tratio\ttime_ms");
+        assert_eq!(lines.len(), results.len() + 1);
+        assert!(lines[1].starts_with(&format!("full\t{}\t100.00\t", results[0].size)));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// 长路径测试：超过 MAX_PATH 的路径添加长路径前缀，深层目录中的新增、修改与删除操作均可创建与应用
+    #[test]
+    fn test_long_path() {
+        let deep = format!(
+            "Windows\\WinSxS\\{}",
+            ["amd64_microsoft-windows-servicingstack_31bf3856ad364e35"; 8].join("\\")
+        );
+        assert_eq!(long_path(Path::new("C:\\Windows")), PathBuf::from("C:\\Windows"));
+        assert_eq!(long_path(Path::new(&deep)), PathBuf::from(&deep));
+        assert_eq!(
+            long_path(Path::new(&format!("C:\\Mount/{}", deep))),
+            PathBuf::from(format!("\\\\?\\C:\\Mount\\{}", deep))
+        );
+        assert_eq!(
+            long_path(Path::new(&format!("\\\\server\\share\\{}", deep))),
+            PathBuf::from(format!("\\\\?\\UNC\\server\\share\\{}", deep))
+        );
+        let prefixed = format!("\\\\?\\C:\\{}", deep);
+        assert_eq!(long_path(Path::new(&prefixed)), PathBuf::from(&prefixed));
+
+        let root = std::env::temp_dir().join(get_tmp_name("longpath-", "", 6));
+        let base_dir = root.join("base");
+        let target_dir = root.join("target");
+        let patch_dir = root.join("patch");
+        for dir in [&base_dir, &target_dir] {
+            fs::create_dir_all(dir.join(&deep)).unwrap();
+        }
+        fs::create_dir_all(&patch_dir).unwrap();
+        assert!(base_dir.join(&deep).join("modify.txt").as_os_str().len() > 260);
+        fs::write(base_dir.join(&deep).join("modify.txt"), "version 1.0.0\r\n".repeat(64)).unwrap();
+        fs::write(
+            target_dir.join(&deep).join("modify.txt"),
+            "version 1.1.0\r\n".repeat(64),
+        )
+        .unwrap();
+        fs::write(base_dir.join(&deep).join("delete.txt"), b"deleted").unwrap();
+        fs::write(target_dir.join(&deep).join("add.txt"), b"added").unwrap();
+        fs::write(
+            stream_path(target_dir.join(&deep).join("add.txt"), "Zone.Identifier"),
+            b"[ZoneTransfer]",
+        )
+        .unwrap();
+
+        let options = CreateOptions {
+            preset: Preset::Fast,
+            author: String::new(),
+            name: "test-patch".to_string(),
+            compress: Compress::None,
+            preserve_streams: true,
+            ..Default::default()
+        };
+        let wim_patch = WimPatch::new().unwrap();
+        let (operations, _, _) = wim_patch
+            .create_operations(&base_dir, &target_dir, &patch_dir, 1, &options)
+            .unwrap();
+        let action = |name: &str| {
+            operations
+                .iter()
+                .find(|op| op.path == format!("{}\\{}", deep, name))
+                .map(|op| op.action.clone())
+        };
+        assert_eq!(action("add.txt"), Some(Action::Add));
+        assert_eq!(action("modify.txt"), Some(Action::Modify));
+        assert_eq!(action("delete.txt"), Some(Action::Delete));
+
+        wim_patch
+            .apply_operations(&base_dir, &patch_dir, &operations, 1, &ApplyOptions::default())
+            .unwrap();
+        let differences = hash_differences(&target_dir, &base_dir);
+        assert!(differences.is_empty(), "{:?}", differences);
+        assert_eq!(
+            fs::read(stream_path(base_dir.join(&deep).join("add.txt"), "Zone.Identifier")).unwrap(),
+            b"[ZoneTransfer]"
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// 字典窗口测试：仅使用旧文件末尾部分作为字典，应用时使用相同窗口还原，并比较不同窗口的差异大小
+    #[test]
+    fn test_zstd_dict_window() {
+        assert_eq!(ZstdDiff::dictionary(b"0123456789", None), b"0123456789");
+        assert_eq!(ZstdDiff::dictionary(b"0123456789", Some(4)), b"6789");
+        assert_eq!(ZstdDiff::dictionary(b"0123456789", Some(64)), b"0123456789");
+
+        let root = std::env::temp_dir().join(get_tmp_name("dictwindow-", "", 6));
+        fs::create_dir_all(&root).unwrap();
+        let old_file = root.join("old.bin");
+        let new_file = root.join("new.bin");
+
+        // 伪随机内容只能依靠字典压缩，新文件每 64 KB 修改一处
+        let mut seed = 0x2545F491u32;
+        let old: Vec<u8> = (0..4 * 1024 * 1024)
+            .map(|_| {
+                seed ^= seed << 13;
+                seed ^= seed >> 17;
+                seed ^= seed << 5;
+                seed as u8
+            })
+            .collect();
+        let mut new = old.clone();
+        for offset in (0..new.len()).step_by(64 * 1024) {
+            new[offset] ^= 0xFF;
+        }
+        fs::write(&old_file, &old).unwrap();
+        fs::write(&new_file, &new).unwrap();
+
+        let mut sizes = Vec::new();
+        for dict_window in [None, Some(1024 * 1024), Some(64 * 1024)] {
+            let diff_file = root.join("new.bin.diff");
+            let output_file = root.join("output.bin");
+            ZstdDiff::file_diff(&old_file, &new_file, &diff_file, 3, dict_window).unwrap();
+            let size = fs::metadata(&diff_file).unwrap().len();
+            assert_eq!(
+                ZstdDiff::file_diff_size(&old_file, &new_file, 3, dict_window).unwrap(),
+                size
+            );
+
+            ZstdDiff::file_patch(&old_file, &diff_file, &output_file, dict_window).unwrap();
+            assert_eq!(fs::read(&output_file).unwrap(), new);
+            sizes.push(size);
+        }
+        // 字典越小，可引用的旧内容越少
+        assert!(sizes[0] < sizes[1]);
+        assert!(sizes[1] < sizes[2]);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// 镜像名称测试：按名称或显示名称（不区分大小写）选择镜像，重名时报错并列出候选镜像
+    #[test]
+    fn test_find_image_by_name() {
+        let image = |index, name: &str, display_name: Option<&str>| ImageInfo {
+            index,
+            name: Some(name.to_string()),
+            display_name: display_name.map(str::to_string),
+            ..Default::default()
+        };
+        let images = vec![
+            image(1, "Windows 11 Home", None),
+            image(2, "Windows 11 Pro", Some("Windows 11 专业版")),
+            image(3, "Windows 11 Education", None),
+            image(4, "Windows 11 Education", None),
+        ];
+
+        assert_eq!(find_image_by_name(&images, "Windows 11 Pro").unwrap(), 2);
+        assert_eq!(find_image_by_name(&images, "windows 11 home").unwrap(), 1);
+        assert_eq!(find_image_by_name(&images, "Windows 11 专业版").unwrap(), 2);
+
+        // 重名时列出候选镜像
+        let error = find_image_by_name(&images, "Windows 11 Education")
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("3: Windows 11 Education"));
+        assert!(error.contains("4: Windows 11 Education"));
+        assert!(!error.contains("Windows 11 Home"));
+
+        // 没有匹配时列出所有镜像
+        let error = find_image_by_name(&images, "Windows 11 Enterprise")
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("1: Windows 11 Home"));
+    }
+
+    /// 释放目录测试：只应用一个镜像时直接释放到指定目录，否则按基础镜像索引创建子目录
+    #[test]
+    fn test_extract_image_dir() {
+        let extract_to = Path::new(r"D:\extract");
+        assert_eq!(extract_image_dir(extract_to, 3, true), PathBuf::from(r"D:\extract"));
+        assert_eq!(extract_image_dir(extract_to, 1, false), extract_to.join("1"));
+        assert_eq!(extract_image_dir(extract_to, 6, false), extract_to.join("6"));
+    }
+
+    /// zstd 压缩级别测试：指定 --zstd-level 时代替压缩预设对应的级别，范围为 0 到 22，不能与 bsdiff 存储同时使用
+    #[test]
+    fn test_zstd_level() {
+        assert_eq!(parse_zstd_level("15"), Ok(15));
+        assert_eq!(parse_zstd_level("0"), Ok(0));
+        assert_eq!(parse_zstd_level("22"), Ok(22));
+        assert!(parse_zstd_level("23").is_err());
+        assert!(parse_zstd_level("-1").is_err());
+        assert!(parse_zstd_level("max").is_err());
+
+        let mut options = CreateOptions {
+            preset: Preset::Best,
+            author: String::new(),
+            name: "test-patch".to_string(),
+            ..Default::default()
+        };
+
+        // 未指定时使用压缩预设对应的级别
+        assert_eq!(zstd_level(&options), 19);
+        assert!(check_zstd_level(&options).is_ok());
+
+        options.zstd_level = Some(15);
+        assert_eq!(zstd_level(&options), 15);
+        assert!(check_zstd_level(&options).is_ok());
+
+        options.zstd_level = Some(23);
+        assert!(check_zstd_level(&options).is_err());
+
+        // bsdiff 存储不使用 zstd 压缩级别
+        options.zstd_level = Some(15);
+        options.storage = Storage::Bsdiff;
+        assert!(check_zstd_level(&options).is_err());
+        options.zstd_level = None;
+        assert!(check_zstd_level(&options).is_ok());
+    }
+
+    /// 输出文件处理方式测试：默认拒绝修改已存在的文件，覆盖与追加对应相应的打开方式，应用补丁不支持追加
+    #[test]
+    fn test_on_existing() {
+        assert_eq!(OnExisting::default(), OnExisting::Error);
+        assert_eq!(output_disposition(OnExisting::Error), WIM_CREATE_NEW);
+        assert_eq!(output_disposition(OnExisting::Overwrite), WIM_CREATE_ALWAYS);
+        assert_eq!(output_disposition(OnExisting::Append), WIM_OPEN_ALWAYS);
+
+        let root = std::env::temp_dir().join(get_tmp_name("on-existing-", "", 6));
+        fs::create_dir_all(&root).unwrap();
+        let existing = root.join("existing.wim");
+        let missing = root.join("missing.wim");
+        fs::write(&existing, b"MSWIM").unwrap();
+
+        // 目标镜像不存在时均可写入
+        assert!(check_target_on_existing(&missing, OnExisting::Error).is_ok());
+        assert!(check_target_on_existing(&missing, OnExisting::Overwrite).is_ok());
+
+        // 目标镜像已存在时仅在指定覆盖时写入
+        let error = check_target_on_existing(&existing, OnExisting::Error).unwrap_err();
+        assert!(error.to_string().contains("existing.wim"));
+        assert!(check_target_on_existing(&existing, OnExisting::Overwrite).is_ok());
+
+        // 应用补丁不支持追加
+        assert!(check_target_on_existing(&missing, OnExisting::Append).is_err());
+        assert!(check_target_on_existing(&existing, OnExisting::Append).is_err());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// 差异哈希测试：生成差异的同一遍读取中计算的哈希与单独读取文件计算的结果一致，且差异仍可正确应用
+    #[test]
+    fn test_file_diff_hashed() {
+        let root = std::env::temp_dir().join(get_tmp_name("diffhash-", "", 6));
+        fs::create_dir_all(&root).unwrap();
+        let old_file = root.join("old.bin");
+        let new_file = root.join("new.bin");
+        let diff_file = root.join("new.bin.diff");
+        let output_file = root.join("output.bin");
+        let old = b"WimPatch streaming hash\r\n".repeat(8192);
+        let mut new = old.clone();
+        new.extend_from_slice(b"appended in target\r\n");
+        new[1024] = b'#';
+        fs::write(&old_file, &old).unwrap();
+        fs::write(&new_file, &new).unwrap();
+        let old_hash = get_file_sha256(&old_file, None).unwrap();
+        let new_hash = get_file_sha256(&new_file, None).unwrap();
+
+        // 读取器与内存数据的哈希与逐块读取文件的结果一致
+        let mut reader = HashReader::new(fs::File::open(&new_file).unwrap());
+        std::io::copy(&mut reader, &mut std::io::sink()).unwrap();
+        assert_eq!(reader.finish(), new_hash);
+        assert_eq!(get_sha256(&old), old_hash);
+
+        let hashes = ZstdDiff::file_diff_hashed(&old_file, &new_file, &diff_file, 3, None).unwrap();
+        assert_eq!(hashes.old, old_hash);
+        assert_eq!(hashes.new, new_hash);
+        ZstdDiff::file_patch(&old_file, &diff_file, &output_file, None).unwrap();
+        assert_eq!(fs::read(&output_file).unwrap(), new);
+
+        let hashes = BsDiff::file_diff_hashed(&old_file, &new_file, &diff_file).unwrap();
+        assert_eq!(hashes.old, old_hash);
+        assert_eq!(hashes.new, new_hash);
+        BsDiff::file_patch(&old_file, &diff_file, &output_file).unwrap();
+        assert_eq!(fs::read(&output_file).unwrap(), new);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// 操作列表测试：按路径、大小与操作类型排序，并按数量限制截断
+    #[test]
+    fn test_operation_list() {
+        let operations = vec![
+            test_operation(Action::Modify, "b.dll", Some(1024), Some("zstd")),
+            test_operation(Action::Delete, "a.txt", None, None),
+            test_operation(Action::Add, "c.bin", Some(4096), None),
+        ];
+        let paths = |list: &str| {
+            list.lines()
+                .filter_map(|line| line.rsplit_once("  ").map(|(_, path)| path.to_string()))
+                .collect::<Vec<_>>()
+        };
+
+        let list = WimPatch::format_operation_list(&operations, ListSort::Path, None);
+        assert_eq!(paths(&list), ["a.txt", "b.dll", "c.bin"]);
+        assert!(list.starts_with(&format!("- {:<8} {:>10}  a.txt\n", "-", "-")));
+        assert!(list.contains(&format!("~ {:<8} {:>10}  b.dll\n", "zstd", format_bytes(1024))));
+        assert_eq!(
+            paths(&WimPatch::format_operation_list(&operations, ListSort::Size, None)),
+            ["c.bin", "b.dll", "a.txt"]
+        );
+        assert_eq!(
+            paths(&WimPatch::format_operation_list(&operations, ListSort::Action, None)),
+            ["c.bin", "b.dll", "a.txt"]
+        );
+
+        let limited = WimPatch::format_operation_list(&operations, ListSort::Path, Some(1));
+        assert_eq!(paths(&limited), ["a.txt"]);
+        assert!(limited.ends_with("... 2 more\n"));
+    }
+
+    /// 应用后命令测试：命令可通过环境变量读取目标镜像路径，并返回命令的退出码
+    #[test]
+    fn test_run_post_apply() {
+        let root = std::env::temp_dir().join(get_tmp_name("postapply-", "", 6));
+        fs::create_dir_all(&root).unwrap();
+        let target = root.join("target.wim");
+        let marker = root.join("target.wim.marker");
+
+        let command = format!("echo applied> \"%{}%.marker\"", POST_APPLY_TARGET_ENV);
+        assert_eq!(run_post_apply(&command, &target).unwrap(), 0);
+        assert_eq!(fs::read_to_string(&marker).unwrap().trim_end(), "applied");
+        assert_eq!(run_post_apply("exit /b 3", &target).unwrap(), 3);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// WIM 属性解析测试：解析压缩类型名称、分卷信息与属性标志
+    #[test]
+    fn test_patch_attributes() {
+        let mut info = WimInfo {
+            wim_path: String::from("patch.swm"),
+            guid: GUID::zeroed(),
+            image_count: 1,
+            compression_type: WIM_COMPRESS_LZMS,
+            part_number: 2,
+            total_parts: 3,
+            boot_index: 1,
+            wim_attributes: WIM_ATTRIBUTE_SPANNED | WIM_ATTRIBUTE_READONLY,
+            wim_flags_and_attr: 0,
+        };
+        let attributes = PatchAttributes::from(&info);
+        assert_eq!(attributes.compression, "lzms");
+        assert_eq!(attributes.attributes, ["spanned", "readonly"]);
+        let text = attributes.to_text(18);
+        assert!(text.contains(&format!("{:<18} {}", "Part:", "2/3")));
+        assert!(text.contains(&format!("{:<18} {}", "Attributes:", "spanned, readonly")));
+
+        info.compression_type = WIM_COMPRESS_XPRESS;
+        info.wim_attributes = WIM_ATTRIBUTE_NORMAL;
+        let attributes = PatchAttributes::from(&info);
+        assert_eq!(attributes.compression, "xpress");
+        assert_eq!(attributes.attributes, ["normal"]);
+    }
+
+    /// 合并冲突测试：基础镜像与版本相同但操作不同的补丁视为冲突，内容相同的重复补丁与不同版本的补丁不视为冲突
+    #[test]
+    fn test_find_merge_conflicts() {
+        let image_info = ImageInfo {
+            index: 1,
+            ..Default::default()
+        };
+        let operation = |path: &str| test_operation(Action::Add, path, Some(1), None);
+        let manifest = |version: &str, path: &str| {
+            PatchManifest::new(
+                "patch",
+                "",
+                "",
+                version,
+                "guid-base",
+                &image_info,
+                "guid-target",
+                &image_info,
+                &[operation(path)],
+                None,
+            )
+        };
+
+        let first = manifest("1.1.0", "a.txt");
+        let second = manifest("1.1.0", "b.txt");
+        let duplicate = first.clone();
+        let newer = manifest("1.2.0", "c.txt");
+        assert!(find_merge_conflicts(&[first.clone(), duplicate, newer.clone()]).is_empty());
+
+        let conflicts = find_merge_conflicts(&[first.clone(), newer, second.clone()]);
+        assert_eq!(
+            conflicts,
+            [MergeConflict {
+                base_guid: String::from("guid-base"),
+                base_index: 1,
+                version: String::from("1.1.0"),
+                ids: vec![first.id, second.id],
+            }]
+        );
+    }
+
+    /// 挂载检查测试：不区分大小写匹配已挂载镜像的来源文件
+    #[test]
+    fn test_is_image_mounted() {
+        let mounted = vec![String::from(r"C:\Images\Base.wim"), String::from(r"D:\other.wim")];
+        assert!(is_image_mounted(Path::new(r"c:\images\base.WIM"), &mounted));
+        assert!(is_image_mounted(Path::new(r"D:\other.wim"), &mounted));
+        assert!(!is_image_mounted(Path::new(r"C:\Images\Base-copy.wim"), &mounted));
+        assert!(!is_image_mounted(Path::new(r"C:\Images\Base.wim"), &[]));
+    }
+
+    /// Win32 错误说明测试：附带系统错误信息，权限不足相关的错误码提示以管理员身份运行
+    #[test]
+    fn test_win32_error_display() {
+        let message = win32_error_message(2).unwrap();
+        assert!(!message.is_empty() && !message.ends_with('\n'));
+        assert_eq!(
+            WimApiError::Win32Error(2).to_string(),
+            format!("Win32 Error: 2 ({})", message)
+        );
+
+        let hint = "run WimPatch as administrator";
+        assert!(WimApiError::Win32Error(5).to_string().contains(hint));
+        assert!(WimApiError::Win32Error(1314).to_string().contains(hint));
+        assert!(win32_error_message(5).is_some());
+
+        // 系统没有信息的错误码只显示错误码
+        assert!(win32_error_message(0x7fff_fff0).is_none());
+        assert_eq!(
+            WimApiError::Win32Error(0x7fff_fff0).to_string(),
+            "Win32 Error: 2147483632"
+        );
+    }
+
+    /// 子目录比较测试：仅子目录中的差异进入操作列表，路径仍相对于镜像根目录
+    #[test]
+    fn test_create_subtree() {
+        assert_eq!(parse_subtree("Windows/System32/"), Ok("Windows\\System32".to_string()));
+        assert_eq!(
+            parse_subtree("\\Windows"),
+            Err("Subtree must be relative to the image root: \\Windows".to_string())
+        );
+        assert!(parse_subtree("C:\\Windows").is_err());
+        assert!(parse_subtree("Windows\\..\\Users").is_err());
+        assert!(parse_subtree("/").is_err());
+
+        let root = std::env::temp_dir().join(get_tmp_name("subtree-", "", 6));
+        let base_dir = root.join("base");
+        let target_dir = root.join("target");
+        let patch_dir = root.join("patch");
+        fs::create_dir_all(base_dir.join("sub").join("inner")).unwrap();
+        fs::create_dir_all(target_dir.join("sub").join("inner")).unwrap();
+        fs::create_dir_all(&patch_dir).unwrap();
+        // 子目录外的变更
+        fs::write(base_dir.join("outside.txt"), b"old").unwrap();
+        fs::write(target_dir.join("outside.txt"), b"new").unwrap();
+        fs::write(target_dir.join("added-outside.txt"), b"new").unwrap();
+        // 子目录内的变更
+        fs::write(base_dir.join("sub").join("changed.txt"), b"old").unwrap();
+        fs::write(target_dir.join("sub").join("changed.txt"), b"new").unwrap();
+        fs::write(target_dir.join("sub").join("inner").join("added.txt"), b"new").unwrap();
+        fs::write(base_dir.join("sub").join("removed.txt"), b"old").unwrap();
+
+        let mut options = CreateOptions {
+            storage: Storage::Full,
+            author: String::new(),
+            name: "test-patch".to_string(),
+            subtree: Some("sub".to_string()),
+            ..Default::default()
+        };
+        let wim_patch = WimPatch::new().unwrap();
+        let (operations, _, _) = wim_patch
+            .create_operations(&base_dir, &target_dir, &patch_dir, 1, &options)
+            .unwrap();
+        let mut paths: Vec<&str> = operations.iter().map(|op| op.path.as_str()).collect();
+        paths.sort();
+        assert_eq!(
+            paths,
+            vec!["sub\\changed.txt", "sub\\inner\\added.txt", "sub\\removed.txt"]
+        );
+        // 补丁数据按相对于镜像根目录的路径暂存
+        assert!(patch_dir.join("sub").join("changed.txt").exists());
+
+        // 子目录在任一镜像中不存在时报错
+        options.subtree = Some("missing".to_string());
+        assert!(
+            wim_patch
+                .create_operations(&base_dir, &target_dir, &patch_dir, 1, &options)
+                .is_err()
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// 捕获队列测试：各位置按顺序轮到，提前结束的位置不会阻塞后续位置
+    #[test]
+    fn test_capture_queue() {
+        let queue = CaptureQueue::default();
+        let order = Mutex::new(Vec::new());
+        thread::scope(|scope| {
+            // 逆序启动，模拟后面的镜像先完成比较
+            for position in (0..4).rev() {
+                let (queue, order) = (&queue, &order);
+                scope.spawn(move || {
+                    let ticket = queue.ticket(position);
+                    // 位置 2 没有差异，无需捕获
+                    if position == 2 {
+                        return;
+                    }
+                    ticket.wait();
+                    order.lock().unwrap().push(position);
+                });
+            }
+        });
+        assert_eq!(order.into_inner().unwrap(), vec![0, 1, 3]);
+    }
+
+    /// 并行创建多镜像补丁测试：3 个镜像同时构建，补丁镜像按索引顺序写入且清单完整（需要管理员权限）
+    #[test]
+    #[ignore]
+    fn test_create_patch_concurrent() {
+        let root = std::env::temp_dir().join(get_tmp_name("concurrent-", "", 6));
+        let base_dir = root.join("base");
+        fs::create_dir_all(&base_dir).unwrap();
+        for index in 1..=3 {
+            fs::write(base_dir.join(format!("file{index}.txt")), b"base").unwrap();
+        }
+
+        // 基础镜像的 3 个镜像内容相同，更新镜像的第 N 个镜像只修改 fileN.txt
+        let wimgapi = Wimgapi::new(None).unwrap();
+        let base_image = root.join("base.wim");
+        let target_image = root.join("target.wim");
+        let base_handle = wimgapi
+            .open(&base_image, WIM_GENERIC_WRITE, WIM_CREATE_ALWAYS, WIM_COMPRESS_NONE)
+            .unwrap();
+        let target_handle = wimgapi
+            .open(&target_image, WIM_GENERIC_WRITE, WIM_CREATE_ALWAYS, WIM_COMPRESS_NONE)
+            .unwrap();
+        wimgapi.set_temp_path(base_handle, &get_temp_path()).unwrap();
+        wimgapi.set_temp_path(target_handle, &get_temp_path()).unwrap();
+        for index in 1..=3 {
+            let target_dir = root.join(format!("target{index}"));
+            fs::create_dir_all(&target_dir).unwrap();
+            for file in 1..=3 {
+                let content: &[u8] = if file == index { b"target" } else { b"base" };
+                fs::write(target_dir.join(format!("file{file}.txt")), content).unwrap();
+            }
+            wimgapi.close(wimgapi.capture(base_handle, &base_dir, 0).unwrap()).unwrap();
+            wimgapi.close(wimgapi.capture(target_handle, &target_dir, 0).unwrap()).unwrap();
+        }
+        wimgapi.close(base_handle).unwrap();
+        wimgapi.close(target_handle).unwrap();
+
+        let options = CreateOptions {
+            storage: Storage::Full,
+            preset: Preset::Fast,
+            author: String::new(),
+            name: "test-patch".to_string(),
+            compress: Compress::None,
+            concurrency: 3,
+            // 未修改的文件在各更新目录中重新写入，修改时间与基础目录不同，按内容比较
+            compare_mode: CompareMode::Hash,
+            ..Default::default()
+        };
+        let wim_patch = WimPatch::new().unwrap();
+        let patch_image = root.join("patch.wim");
+        wim_patch
+            .create_patch(&base_image, None, &target_image, None, &patch_image, &options)
+            .unwrap();
+
+        // 第 N 个补丁镜像对应基础镜像索引 N，且只包含 fileN.txt 的修改
+        let manifests = wim_patch.get_manifests(&patch_image).unwrap();
+        assert_eq!(manifests.len(), 3);
+        for (position, (patch_index, manifest)) in manifests.iter().enumerate() {
+            let index = position as u32 + 1;
+            assert_eq!(*patch_index, index);
+            assert_eq!(manifest.base_image_info.index, index);
+            assert_eq!(manifest.target_image_info.index, index);
+            assert_eq!(manifest.operations.len(), 1);
+            assert_eq!(manifest.operations[0].path, format!("file{index}.txt"));
+            assert_eq!(manifest.operations[0].action, Action::Modify);
+        }
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// 跳过无差异镜像测试：内容相同的索引不写入补丁镜像，所有索引都没有差异时报错且不生成补丁文件（需要管理员权限）
+    #[test]
+    #[ignore]
+    fn test_create_patch_skip_unchanged() {
+        let root = std::env::temp_dir().join(get_tmp_name("unchanged-", "", 6));
+        let base_dir = root.join("base");
+        let changed_dir = root.join("changed");
+        fs::create_dir_all(&base_dir).unwrap();
+        fs::create_dir_all(&changed_dir).unwrap();
+        fs::write(base_dir.join("file.txt"), b"base").unwrap();
+        fs::write(changed_dir.join("file.txt"), b"target").unwrap();
+
+        // 基础镜像的 2 个镜像内容相同，更新镜像只有第 2 个镜像发生变化
+        let wimgapi = Wimgapi::new(None).unwrap();
+        let capture = |image: &Path, sources: &[&Path]| {
+            let handle = wimgapi
+                .open(image, WIM_GENERIC_WRITE, WIM_CREATE_ALWAYS, WIM_COMPRESS_NONE)
+                .unwrap();
+            wimgapi.set_temp_path(handle, &get_temp_path()).unwrap();
+            for source in sources {
+                wimgapi.close(wimgapi.capture(handle, source, 0).unwrap()).unwrap();
+            }
+            wimgapi.close(handle).unwrap();
+        };
+        let base_image = root.join("base.wim");
+        let target_image = root.join("target.wim");
+        let unchanged_image = root.join("unchanged.wim");
+        capture(&base_image, &[&base_dir, &base_dir]);
+        capture(&target_image, &[&base_dir, &changed_dir]);
+        capture(&unchanged_image, &[&base_dir, &base_dir]);
+
+        let options = CreateOptions {
+            storage: Storage::Full,
+            preset: Preset::Fast,
+            author: String::new(),
+            name: "test-patch".to_string(),
+            compress: Compress::None,
+            ..Default::default()
+        };
+        let wim_patch = WimPatch::new().unwrap();
+        let patch_image = root.join("patch.wim");
+        wim_patch
+            .create_patch(&base_image, None, &target_image, None, &patch_image, &options)
+            .unwrap();
+
+        // 只捕获了第 2 个镜像的补丁
+        let manifests = wim_patch.get_manifests(&patch_image).unwrap();
+        assert_eq!(manifests.len(), 1);
+        assert_eq!(manifests[0].1.base_image_info.index, 2);
+        assert_eq!(manifests[0].1.operations.len(), 1);
+        assert_eq!(manifests[0].1.operations[0].path, "file.txt");
+
+        // 所有索引都没有差异
+        let empty_patch = root.join("empty.wim");
+        assert!(
+            wim_patch
+                .create_patch(&base_image, None, &unchanged_image, None, &empty_patch, &options)
+                .is_err()
+        );
+        assert!(!empty_patch.exists());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// 合并逐个校验测试：补丁清单无效的输入立即中止合并，错误信息包含该文件且不留下输出文件
+    #[test]
+    fn test_merge_verify_after_each() {
+        let root = std::env::temp_dir().join(get_tmp_name("merge-verify-", "", 6));
+        let source = root.join("source");
+        fs::create_dir_all(&source).unwrap();
+        fs::write(source.join("file.txt"), b"payload").unwrap();
+
+        // 有效补丁包写入补丁清单，损坏的补丁包没有补丁清单
+        let wimgapi = Wimgapi::new(None).unwrap();
+        let capture = |patch: &Path, with_manifest: bool| {
+            let handle = wimgapi
+                .open(patch, WIM_GENERIC_WRITE, WIM_CREATE_ALWAYS, WIM_COMPRESS_NONE)
+                .unwrap();
+            wimgapi.set_temp_path(handle, &get_temp_path()).unwrap();
+            let image_handle = wimgapi.capture(handle, &source, 0).unwrap();
+            if with_manifest {
+                let manifest = test_manifest("1.0.0", "{3F2504E0-4F89-11D3-9A0C-0305E82C3301}", "", &[]);
+                let image_info = wimgapi.get_image_info(image_handle).unwrap();
+                let image_info = image_info.replace("</IMAGE>", &format!("{}</IMAGE>", manifest.to_xml().unwrap()));
+                wimgapi.set_image_info(image_handle, &image_info).unwrap();
+            }
+            wimgapi.close(image_handle).unwrap();
+            wimgapi.close(handle).unwrap();
+        };
+        let valid = root.join("valid.wim");
+        let corrupt = root.join("corrupt.wim");
+        capture(&valid, true);
+        capture(&corrupt, false);
+
+        let options = MergeOptions {
+            compress: Compress::None,
+            boot_index: None,
+            verify: false,
+            report_shared: false,
+            keep_compression: false,
+            fail_on_conflict: false,
+            verify_after_each: true,
+            flatten_base: None,
+            flatten_base_index: None,
+            flatten_storage: None,
+            flatten_preset: None,
+            flatten_manifest_format: None,
+        };
+        let wim_patch = WimPatch::new().unwrap();
+        let merged = root.join("merged.wim");
+        let err = wim_patch
+            .merge_patches(&[valid.clone(), corrupt.clone()], &merged, &options)
+            .unwrap_err();
+        assert!(format!("{:?}", err).contains(&corrupt.display().to_string()));
+        assert!(!merged.exists());
+
+        // 未指定时无效的输入同样在合并前报错
+        let unverified = MergeOptions {
+            verify_after_each: false,
+            ..options.clone()
+        };
+        let err = wim_patch
+            .merge_patches(&[valid.clone(), corrupt.clone()], &merged, &unverified)
+            .unwrap_err();
+        assert!(format!("{:?}", err).contains(&corrupt.display().to_string()));
+        assert!(!merged.exists());
+
+        // 只有有效输入时合并成功，输出补丁包的补丁清单可解析
+        wim_patch.merge_patches(&[valid], &merged, &options).unwrap();
+        assert_eq!(wim_patch.get_manifests(&merged).unwrap().len(), 1);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// 创建结果摘要测试：按镜像索引统计操作数量，捕获失败时保留已计算的统计
+    #[test]
+    fn test_create_summary() {
+        let operation = |action, path: &str| test_operation(action, path, None, None);
+        let mut summary = CreateSummary::new(Path::new("base.wim"), Path::new("target.wim"), Path::new("patch.wim"));
+
+        // 并行构建时索引 2 先完成比较
+        summary.record_image(2, 2, "1.0.0", "zstd", &[operation(Action::Delete, "old.txt")], 0);
+        summary.record_image(
+            1,
+            1,
+            "1.0.0",
+            "zstd",
+            &[
+                operation(Action::Add, "new.txt"),
+                operation(Action::Add, "dir"),
+                operation(Action::Modify, "changed.txt"),
+            ],
+            1024,
+        );
+        summary.set_manifest_id(1, "patch-1");
+        summary.finish_image(1);
+        summary.error = Some("Capture patch image error".to_string());
+
+        let root = std::env::temp_dir().join(get_tmp_name("summary-", "", 6));
+        fs::create_dir_all(&root).unwrap();
+        let path = root.join("summary.json");
+        summary.write(&path).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(json["success"], false);
+        assert_eq!(json["error"], "Capture patch image error");
+        assert_eq!(json["images"][0]["base_index"], 1);
+        assert_eq!(json["images"][0]["add"], 2);
+        assert_eq!(json["images"][0]["modify"], 1);
+        assert_eq!(json["images"][0]["delete"], 0);
+        assert_eq!(json["images"][0]["payload_bytes"], 1024);
+        assert_eq!(json["images"][0]["storage"], "zstd");
+        assert_eq!(json["images"][0]["id"], "patch-1");
+        assert_eq!(json["images"][0]["version"], "1.0.0");
+        assert_eq!(json["images"][0]["captured"], true);
+        // 未生成补丁清单的索引没有 ID
+        assert_eq!(json["images"][1]["delete"], 1);
+        assert!(json["images"][1]["id"].is_null());
+        assert_eq!(json["images"][1]["captured"], false);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// 安全描述符标志测试：指定 --no-acl 时捕获与释放均跳过 ACL 且内容不变
+    #[test]
+    fn test_acl_flags() {
+        assert_eq!(acl_flags(false), 0);
+        assert_eq!(acl_flags(true), WIM_FLAG_NO_DIRACL | WIM_FLAG_NO_FILEACL);
+
+        let root = std::env::temp_dir().join(get_tmp_name("acl-", "", 6));
+        let source = root.join("source");
+        fs::create_dir_all(source.join("dir")).unwrap();
+        fs::write(source.join("dir").join("file.txt"), b"payload").unwrap();
+
+        // 以不处理 ACL 的方式捕获并释放
+        let wimgapi = Wimgapi::new(None).unwrap();
+        let image = root.join("image.wim");
+        let handle = wimgapi
+            .open(&image, WIM_GENERIC_WRITE, WIM_CREATE_ALWAYS, WIM_COMPRESS_NONE)
+            .unwrap();
+        wimgapi.set_temp_path(handle, &get_temp_path()).unwrap();
+        let image_handle = wimgapi.capture(handle, &source, acl_flags(true)).unwrap();
+        wimgapi.close(image_handle).unwrap();
+        wimgapi.close(handle).unwrap();
+
+        let apply_dir = root.join("apply");
+        fs::create_dir_all(&apply_dir).unwrap();
+        let handle = wimgapi
+            .open(&image, WIM_GENERIC_READ, WIM_OPEN_EXISTING, WIM_COMPRESS_NONE)
+            .unwrap();
+        wimgapi.set_temp_path(handle, &get_temp_path()).unwrap();
+        let image_handle = wimgapi.load_image(handle, 1).unwrap();
+        wimgapi.apply_image(image_handle, &apply_dir, acl_flags(true)).unwrap();
+        wimgapi.close(image_handle).unwrap();
+        wimgapi.close(handle).unwrap();
+
+        assert_eq!(fs::read(apply_dir.join("dir").join("file.txt")).unwrap(), b"payload");
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// 压缩类型测试：目标镜像默认 LZX，指定 LZMS 时导出的目标镜像使用 LZMS 压缩，补丁包的压缩算法不接受 LZMS
+    #[test]
+    fn test_wim_compression_type() {
+        assert_eq!(ApplyOptions::default().target_compress, Compress::Lzx);
+        assert_eq!(parse_patch_compress("lzx"), Ok(Compress::Lzx));
+        assert_eq!(parse_patch_compress("Xpress"), Ok(Compress::Xpress));
+        assert_eq!(parse_patch_compress("none"), Ok(Compress::None));
+        assert!(parse_patch_compress("lzms").is_err());
+        assert!(parse_patch_compress("zip").is_err());
+        assert_eq!(wim_compression_type(Compress::None), WIM_COMPRESS_NONE);
+        assert_eq!(wim_compression_type(Compress::Xpress), WIM_COMPRESS_XPRESS);
+        assert_eq!(wim_compression_type(Compress::Lzx), WIM_COMPRESS_LZX);
+        assert_eq!(wim_compression_type(Compress::Lzms), WIM_COMPRESS_LZMS);
+
+        let root = std::env::temp_dir().join(get_tmp_name("target-compress-", "", 6));
+        let source = root.join("source");
+        fs::create_dir_all(&source).unwrap();
+        fs::write(source.join("file.txt"), b"payload").unwrap();
+
+        // 捕获未压缩镜像后导出到 LZMS 压缩的目标镜像
+        let wimgapi = Wimgapi::new(None).unwrap();
+        let base_image = root.join("base.wim");
+        let base_handle = wimgapi
+            .open(&base_image, WIM_GENERIC_WRITE, WIM_CREATE_ALWAYS, WIM_COMPRESS_NONE)
+            .unwrap();
+        wimgapi.set_temp_path(base_handle, &get_temp_path()).unwrap();
+        let image_handle = wimgapi.capture(base_handle, &source, 0).unwrap();
+
+        let target_image = root.join("target.esd");
+        let target_handle = wimgapi
+            .open(
+                &target_image,
+                WIM_GENERIC_WRITE,
+                WIM_CREATE_ALWAYS,
+                wim_compression_type(Compress::Lzms),
+            )
+            .unwrap();
+        wimgapi.set_temp_path(target_handle, &get_temp_path()).unwrap();
+        wimgapi.export_image(image_handle, target_handle, 0).unwrap();
+        wimgapi.close(image_handle).unwrap();
+        wimgapi.close(target_handle).unwrap();
+        wimgapi.close(base_handle).unwrap();
+
+        let target_handle = wimgapi
+            .open(&target_image, WIM_GENERIC_READ, WIM_OPEN_EXISTING, WIM_COMPRESS_NONE)
+            .unwrap();
+        let attributes = wimgapi.get_attributes(target_handle).unwrap();
+        assert_eq!(attributes.compression_type, WIM_COMPRESS_LZMS);
+        assert_eq!(attributes.image_count, 1);
+        wimgapi.close(target_handle).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// 包含列表测试：仅包含、仅排除与两者组合（排除优先）时创建与应用保留的操作
+    #[test]
+    fn test_include_filter() {
+        let include = vec!["Keep".to_string()];
+        assert!(is_included("keep\\a.txt", Some(include.as_slice())));
+        assert!(!is_included("other\\b.txt", Some(include.as_slice())));
+        assert!(is_included("other\\b.txt", None));
+        assert!(is_included("other\\b.txt", Some(&[])));
+
+        let root = std::env::temp_dir().join(get_tmp_name("include-", "", 6));
+        let base_dir = root.join("base");
+        let target_dir = root.join("target");
+        let patch_dir = root.join("patch");
+        fs::create_dir_all(&base_dir).unwrap();
+        fs::create_dir_all(target_dir.join("keep")).unwrap();
+        fs::create_dir_all(target_dir.join("other")).unwrap();
+        fs::write(target_dir.join("keep").join("a.txt"), b"a").unwrap();
+        fs::write(target_dir.join("keep").join("skip.txt"), b"skip").unwrap();
+        fs::write(target_dir.join("other").join("b.txt"), b"b").unwrap();
+
+        let mut options = CreateOptions {
+            storage: Storage::Full,
+            author: String::new(),
+            name: "test-patch".to_string(),
+            compress: Compress::None,
+            ..Default::default()
+        };
+        let wim_patch = WimPatch::new().unwrap();
+        let create = |options: &CreateOptions| {
+            let _ = fs::remove_dir_all(&patch_dir);
+            fs::create_dir_all(&patch_dir).unwrap();
+            let (operations, _, _) = wim_patch
+                .create_operations(&base_dir, &target_dir, &patch_dir, 1, options)
+                .unwrap();
+            operations
+        };
+        let paths = |operations: &[Operation]| {
+            let mut paths: Vec<String> = operations.iter().map(|op| op.path.to_ascii_lowercase()).collect();
+            paths.sort();
+            paths
+        };
+
+        // 仅包含：只保留路径含有 keep 的差异
+        options.include = Some(vec!["keep".to_string()]);
+        assert_eq!(paths(&create(&options)), vec!["keep", "keep\\a.txt", "keep\\skip.txt"]);
+
+        // 仅排除：其余差异全部保留
+        options.include = None;
+        options.exclude = Some(vec!["skip".to_string()]);
+        assert_eq!(
+            paths(&create(&options)),
+            vec!["keep", "keep\\a.txt", "other", "other\\b.txt"]
+        );
+
+        // 包含与排除组合：排除优先
+        options.include = Some(vec!["keep".to_string()]);
+        assert_eq!(paths(&create(&options)), vec!["keep", "keep\\a.txt"]);
+
+        // 应用时同样按包含列表过滤，排除优先
+        options.include = None;
+        options.exclude = None;
+        let operations = create(&options);
+        assert_eq!(operations.len(), 5);
+        let apply_options = ApplyOptions {
+            include: Some(vec!["keep".to_string()]),
+            exclude: Some(vec!["skip".to_string()]),
+            ..Default::default()
+        };
+        wim_patch
+            .apply_operations(&base_dir, &patch_dir, &operations, 1, &apply_options)
+            .unwrap();
+        assert_eq!(fs::read(base_dir.join("keep").join("a.txt")).unwrap(), b"a");
+        assert!(!base_dir.join("keep").join("skip.txt").exists());
+        assert!(!base_dir.join("other").exists());
+
+        // 包含列表文件：忽略 BOM、空行与注释行，追加到命令行指定的项之后
+        let list = root.join("include.txt");
+        fs::write(&list, "\u{feff}# comment\r\nkeep\r\n\r\n  other  \r\n").unwrap();
+        assert_eq!(
+            merge_pattern_file(Some(vec!["first".to_string()]), Some(list.as_path())).unwrap(),
+            Some(vec!["first".to_string(), "keep".to_string(), "other".to_string()])
+        );
+        assert_eq!(merge_pattern_file(None, None).unwrap(), None);
+        assert!(merge_pattern_file(None, Some(root.join("missing.txt").as_path())).is_err());
+        // 只有注释与空行的列表文件不能被当作不限制
+        let empty = root.join("empty.txt");
+        fs::write(&empty, "# nothing\r\n\r\n").unwrap();
+        assert!(merge_pattern_file(None, Some(empty.as_path())).is_err());
+
+        // 创建与应用补丁时均拒绝空的包含列表
+        let empty_include = rust_i18n::t!("create_patch.empty_include").to_string();
+        assert_eq!(check_include(Some(&[])).unwrap_err().to_string(), empty_include);
+        assert!(check_include(Some(&[String::from("Windows")])).is_ok());
+        assert!(check_include(None).is_ok());
+        let (base_dir, target_dir) = (root.join("base"), root.join("target"));
+        fs::create_dir_all(&base_dir).unwrap();
+        fs::create_dir_all(&target_dir).unwrap();
+        let wim_patch = WimPatch::without_wimgapi();
+        let create_options = CreateOptions {
+            include: Some(Vec::new()),
+            ..Default::default()
+        };
+        let result = wim_patch.create_dir_patch(&base_dir, &target_dir, &root.join("patch"), &create_options);
+        assert_eq!(result.unwrap_err().to_string(), empty_include);
+        assert!(!root.join("patch").exists());
+        let apply_options = ApplyOptions {
+            include: Some(Vec::new()),
+            ..Default::default()
+        };
+        let result = wim_patch.apply_dir_patch(&base_dir, &root.join("patch"), &root.join("out"), &apply_options);
+        assert_eq!(result.unwrap_err().to_string(), empty_include);
+        let result = wim_patch.apply_patch(
+            &root.join("base.wim"),
+            Some(1),
+            &root.join("patch.wim"),
+            &root.join("target.wim"),
+            &apply_options,
+        );
+        assert_eq!(result.unwrap_err().to_string(), empty_include);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// wimgapi.dll 加载测试：区分未找到与位数不匹配（无效的库），未指定路径时从默认位置搜索
+    #[test]
+    fn test_wimgapi_load_errors() {
+        let paths = wimgapi_search_paths();
+        assert_eq!(paths[0], PathBuf::from("wimgapi.dll"));
+        assert!(
+            paths
+                .iter()
+                .any(|path| path.ends_with(Path::new("System32").join("wimgapi.dll")))
+        );
+        assert!(Wimgapi::new(None).is_ok());
+
+        let root = std::env::temp_dir().join(get_tmp_name("wimgapi-", "", 6));
+        fs::create_dir_all(&root).unwrap();
+
+        // 指定的路径不存在
+        let missing = root.join("missing").join("wimgapi.dll");
+        match Wimgapi::new(Some(missing.clone())) {
+            Err(WimApiError::LibraryNotFound(searched)) => assert_eq!(searched, vec![missing]),
+            other => panic!("unexpected result: {:?}", other.err()),
+        }
+
+        // 不是有效 PE 文件的库与位数不匹配一样返回 ERROR_BAD_EXE_FORMAT
+        let invalid = root.join("wimgapi.dll");
+        fs::write(&invalid, b"not a library").unwrap();
+        let error = Wimgapi::new(Some(invalid.clone())).err().unwrap();
+        assert!(matches!(&error, WimApiError::LibraryBitness(path) if path == &invalid));
+        assert!(error.to_string().contains("--wimgapi-path"));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// 已应用版本测试：在镜像信息中记录与读取补丁版本，低于已记录版本的补丁默认被拒绝
+    #[test]
+    fn test_applied_version() {
+        let image_info = "<IMAGE INDEX=\"1\"><NAME>Windows</NAME></IMAGE>";
+        assert_eq!(applied_version(image_info), None);
+        let recorded = set_applied_version(image_info, "2.0.0");
+        assert_eq!(
+            recorded,
+            "<IMAGE INDEX=\"1\"><NAME>Windows</NAME><WIMPATCHVERSION>2.0.0</WIMPATCHVERSION></IMAGE>"
+        );
+        assert_eq!(applied_version(&recorded), Some(semver::Version::new(2, 0, 0)));
+        // 再次应用时替换已记录的版本
+        let updated = set_applied_version(&recorded, "2.1.0");
+        assert_eq!(updated.matches(APPLIED_VERSION_FIELD).count(), 2);
+        assert_eq!(applied_version(&updated), Some(semver::Version::new(2, 1, 0)));
+        // 镜像信息可以照常解析
+        assert_eq!(ImageInfo::from_xml(&updated).unwrap().name.as_deref(), Some("Windows"));
+
+        let applied = semver::Version::new(2, 0, 0);
+        assert!(WimPatch::check_downgrade(1, &applied, "1.0.0", false).is_err());
+        assert!(WimPatch::check_downgrade(1, &applied, "1.0.0", true).is_ok());
+        assert!(WimPatch::check_downgrade(1, &applied, "2.0.0", false).is_ok());
+        assert!(WimPatch::check_downgrade(1, &applied, "2.1.0", false).is_ok());
+        assert!(WimPatch::check_downgrade(1, &applied, "not-a-version", false).is_ok());
+    }
+
+    /// 降级保护测试：镜像已应用 2.0.0 后默认拒绝应用 1.0.0 补丁，指定 --allow-downgrade 时应用并记录 1.0.0（需要管理员权限）
+    #[test]
+    #[ignore]
+    fn test_apply_downgrade() {
+        let root = std::env::temp_dir().join(get_tmp_name("downgrade-", "", 6));
+        let base_dir = root.join("base");
+        let target_dir = root.join("target");
+        fs::create_dir_all(&base_dir).unwrap();
+        fs::create_dir_all(&target_dir).unwrap();
+        fs::write(base_dir.join("file.txt"), b"1.0").unwrap();
+        fs::write(target_dir.join("file.txt"), b"1.1").unwrap();
+
+        let wimgapi = Wimgapi::new(None).unwrap();
+        let capture = |source: &Path, image: &Path| {
+            let handle = wimgapi
+                .open(image, WIM_GENERIC_WRITE, WIM_CREATE_ALWAYS, WIM_COMPRESS_NONE)
+                .unwrap();
+            wimgapi.set_temp_path(handle, &get_temp_path()).unwrap();
+            let image_handle = wimgapi.capture(handle, source, 0).unwrap();
+            wimgapi.close(image_handle).unwrap();
+            wimgapi.close(handle).unwrap();
+        };
+        let base_image = root.join("base.wim");
+        let target_image = root.join("target.wim");
+        capture(&base_dir, &base_image);
+        capture(&target_dir, &target_image);
+
+        let options = CreateOptions {
+            storage: Storage::Full,
+            preset: Preset::Fast,
+            author: String::new(),
+            name: "test-patch".to_string(),
+            compress: Compress::None,
+            ..Default::default()
+        };
+        let wim_patch = WimPatch::new().unwrap();
+        let patch_image = root.join("patch.wim");
+        wim_patch
+            .create_patch(&base_image, Some(1), &target_image, Some(1), &patch_image, &options)
+            .unwrap();
+
+        // 模拟基础镜像之前已应用到 2.0.0
+        let image_info = |image: &Path| {
+            let handle = wimgapi
+                .open(image, WIM_GENERIC_READ, WIM_OPEN_EXISTING, WIM_COMPRESS_NONE)
+                .unwrap();
+            let image_handle = wimgapi.load_image(handle, 1).unwrap();
+            let info = wimgapi.get_image_info(image_handle).unwrap();
+            wimgapi.close(image_handle).unwrap();
+            wimgapi.close(handle).unwrap();
+            info
+        };
+        let recorded = set_applied_version(&image_info(&base_image), "2.0.0");
+        let handle = wimgapi
+            .open(
+                &base_image,
+                WIM_GENERIC_READ | WIM_GENERIC_WRITE,
+                WIM_OPEN_EXISTING,
+                WIM_COMPRESS_NONE,
+            )
+            .unwrap();
+        wimgapi.set_temp_path(handle, &get_temp_path()).unwrap();
+        let image_handle = wimgapi.load_image(handle, 1).unwrap();
+        wimgapi.set_image_info(image_handle, &recorded).unwrap();
+        wimgapi.close(image_handle).unwrap();
+        wimgapi.close(handle).unwrap();
+
+        // 默认拒绝降级，不产生目标镜像
+        let result_image = root.join("result.wim");
+        assert!(
+            wim_patch
+                .apply_patch(
+                    &base_image,
+                    Some(1),
+                    &patch_image,
+                    &result_image,
+                    &ApplyOptions::default()
+                )
+                .is_err()
+        );
+        assert!(!result_image.exists());
+
+        // 允许降级时应用，并记录新的版本
+        let apply_options = ApplyOptions {
+            allow_downgrade: true,
+            ..Default::default()
+        };
+        wim_patch
+            .apply_patch(&base_image, Some(1), &patch_image, &result_image, &apply_options)
+            .unwrap();
+        assert_eq!(
+            applied_version(&image_info(&result_image)),
+            Some(semver::Version::new(1, 0, 0))
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// 紧凑进度汇总测试：按操作数量计算百分比，经过时间格式化为时:分:秒
+    #[test]
+    fn test_progress_summary() {
+        let summary = format_progress_summary(4123, 10000, 320 * 1024 * 1024, Duration::from_secs(135));
+        assert!(summary.contains("4123/10000"));
+        assert!(summary.contains("41%"));
+        assert!(summary.contains(&format_bytes(320 * 1024 * 1024)));
+        assert!(summary.contains("00:02:15"));
+
+        let summary = format_progress_summary(10000, 10000, 0, Duration::from_secs(3 * 3600 + 61));
+        assert!(summary.contains("100%"));
+        assert!(summary.contains("03:01:01"));
+
+        // 没有任何操作时视为已完成
+        assert!(format_progress_summary(0, 0, 0, Duration::ZERO).contains("100%"));
+    }
+
+    /// 批量应用路径模板测试：`{stem}` 替换为基础镜像文件名（不含扩展名）
+    #[test]
+    fn test_expand_base_stem() {
+        assert_eq!(
+            expand_base_stem(
+                Path::new(r"D:\out\{stem}-patched.wim"),
+                Path::new(r"C:\fleet\pc-01.wim")
+            ),
+            PathBuf::from(r"D:\out\pc-01-patched.wim")
+        );
+        // 不含占位符时原样返回
+        assert_eq!(
+            expand_base_stem(Path::new(r"D:\out\target.wim"), Path::new(r"C:\fleet\pc-01.wim")),
+            PathBuf::from(r"D:\out\target.wim")
+        );
+
+        // 应用结果报告与释放目录同样替换
+        let options = expand_base_options(
+            &ApplyOptions {
+                report: Some(PathBuf::from(r"D:\out\{stem}.json")),
+                extract_to: Some(PathBuf::from(r"D:\out\{stem}")),
+                force: true,
+                ..Default::default()
+            },
+            Path::new(r"C:\fleet\pc-01.wim"),
+        );
+        assert_eq!(options.report, Some(PathBuf::from(r"D:\out\pc-01.json")));
+        assert_eq!(options.extract_to, Some(PathBuf::from(r"D:\out\pc-01")));
+        assert!(options.force);
+    }
+
+    /// 批量应用测试：同一补丁应用到两个内容不同的基础镜像，各自导出到 `{stem}-patched.wim`，
+    /// 每个目标镜像保留自己基础镜像中补丁未涉及的内容（需要管理员权限）
+    #[test]
+    #[ignore]
+    fn test_apply_patch_batch() {
+        let root = std::env::temp_dir().join(get_tmp_name("batch-", "", 6));
+        let base_dir = root.join("base");
+        let other_dir = root.join("other");
+        let target_dir = root.join("target");
+        for (dir, owner) in [(&base_dir, b"pc-01"), (&other_dir, b"pc-02"), (&target_dir, b"pc-01")] {
+            fs::create_dir_all(dir).unwrap();
+            fs::write(dir.join("owner.txt"), owner).unwrap();
+        }
+        fs::write(base_dir.join("file.txt"), b"1.0").unwrap();
+        fs::write(other_dir.join("file.txt"), b"1.0").unwrap();
+        fs::write(target_dir.join("file.txt"), b"1.1").unwrap();
+
+        let wimgapi = Wimgapi::new(None).unwrap();
+        let capture = |source: &Path, image: &Path| {
+            let handle = wimgapi
+                .open(image, WIM_GENERIC_WRITE, WIM_CREATE_ALWAYS, WIM_COMPRESS_NONE)
+                .unwrap();
+            wimgapi.set_temp_path(handle, &get_temp_path()).unwrap();
+            let image_handle = wimgapi.capture(handle, source, 0).unwrap();
+            wimgapi.close(image_handle).unwrap();
+            wimgapi.close(handle).unwrap();
+        };
+        let base_image = root.join("base.wim");
+        let target_image = root.join("target.wim");
+        capture(&base_dir, &base_image);
+        capture(&target_dir, &target_image);
+
+        let options = CreateOptions {
+            storage: Storage::Full,
+            preset: Preset::Fast,
+            author: String::new(),
+            name: "test-patch".to_string(),
+            compress: Compress::None,
+            ..Default::default()
+        };
+        let wim_patch = WimPatch::new().unwrap();
+        let patch_image = root.join("patch.wim");
+        wim_patch
+            .create_patch(&base_image, Some(1), &target_image, Some(1), &patch_image, &options)
+            .unwrap();
+
+        // 两个不同的基础镜像：pc-02 单独捕获，GUID 与 owner.txt 的内容不同，统计信息相同
+        let bases = vec![root.join("pc-01.wim"), root.join("pc-02.wim")];
+        fs::copy(&base_image, &bases[0]).unwrap();
+        capture(&other_dir, &bases[1]);
+        let batch_options = ApplyOptions {
+            match_mode: MatchMode::Stats,
+            ..Default::default()
+        };
+
+        // 目标路径不含 {stem} 时拒绝批量应用
+        assert!(
+            wim_patch
+                .apply_patch_batch(
+                    &bases,
+                    Some(1),
+                    &patch_image,
+                    &root.join("out.wim"),
+                    &ApplyOptions::default(),
+                    false,
+                    |_| Ok(())
+                )
+                .is_err()
+        );
+
+        // 批量应用只删除各基础镜像自己的临时子目录，临时目录中已有的其他文件保持不变
+        let sentinel = get_temp_path().join(get_tmp_name("sentinel-", ".txt", 6));
+        fs::write(&sentinel, b"keep").unwrap();
+
+        let mut applied = Vec::new();
+        let results = wim_patch
+            .apply_patch_batch(
+                &bases,
+                Some(1),
+                &patch_image,
+                &root.join("{stem}-patched.wim"),
+                &batch_options,
+                false,
+                |target| {
+                    applied.push(target.to_path_buf());
+                    Ok(())
+                },
+            )
+            .unwrap();
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|result| matches!(result.result, Some(Ok(())))));
+        assert_eq!(
+            applied,
+            vec![root.join("pc-01-patched.wim"), root.join("pc-02-patched.wim")]
+        );
+        // 每个目标镜像由各自的基础镜像生成
+        for (target, owner) in applied.iter().zip([b"pc-01", b"pc-02"]) {
+            let apply_dir = root.join(format!("apply-{}", String::from_utf8_lossy(owner)));
+            fs::create_dir_all(&apply_dir).unwrap();
+            let handle = wimgapi
+                .open(target, WIM_GENERIC_READ, WIM_OPEN_EXISTING, WIM_COMPRESS_NONE)
+                .unwrap();
+            wimgapi.set_temp_path(handle, &get_temp_path()).unwrap();
+            let image_handle = wimgapi.load_image(handle, 1).unwrap();
+            wimgapi.apply_image(image_handle, &apply_dir, 0).unwrap();
+            wimgapi.close(image_handle).unwrap();
+            wimgapi.close(handle).unwrap();
+            assert_eq!(fs::read(apply_dir.join("file.txt")).unwrap(), b"1.1");
+            assert_eq!(fs::read(apply_dir.join("owner.txt")).unwrap(), owner);
+        }
+        assert_eq!(fs::read(&sentinel).unwrap(), b"keep");
+        fs::remove_file(&sentinel).ok();
+
+        fs::remove_dir_all(&root).ok();
+    }
+
+    /// 重复应用测试：完整存储的文件已与补丁一致时第二次应用不再复制，并单独统计
+    #[test]
+    fn test_reapply_full_noop() {
+        // 记录提示信息的观察者
+        struct Recorder(Arc<Mutex<Vec<String>>>);
+        impl ProgressObserver for Recorder {
+            fn message(&self, _task: u32, message: &str) {
+                self.0.lock().unwrap().push(message.to_string());
+            }
+        }
+
+        let root = std::env::temp_dir().join(get_tmp_name("reapply-", "", 6));
+        let base_dir = root.join("base");
+        let target_dir = root.join("target");
+        let patch_dir = root.join("patch");
+        fs::create_dir_all(&base_dir).unwrap();
+        fs::create_dir_all(&target_dir).unwrap();
+        fs::write(base_dir.join("modify.txt"), b"version 1.0.0").unwrap();
+        fs::write(target_dir.join("modify.txt"), b"version 1.1.0").unwrap();
+
+        let options = CreateOptions {
+            storage: Storage::Full,
+            preset: Preset::Fast,
+            version: "1.1.0".to_string(),
+            author: String::new(),
+            name: "dir-patch".to_string(),
+            compress: Compress::None,
+            skip_space_check: true,
+            ..Default::default()
+        };
+        let messages = Arc::new(Mutex::new(Vec::new()));
+        let mut wim_patch = WimPatch::without_wimgapi().unwrap();
+        wim_patch.set_progress_observer(Recorder(messages.clone()));
+        assert!(
+            wim_patch
+                .create_dir_patch(&base_dir, &target_dir, &patch_dir, &options)
+                .unwrap()
+        );
+
+        // 第一次应用复制文件
+        wim_patch
+            .apply_dir_patch(&base_dir, &patch_dir, &base_dir, &ApplyOptions::default())
+            .unwrap();
+        assert_eq!(fs::read(base_dir.join("modify.txt")).unwrap(), b"version 1.1.0");
+        assert!(messages.lock().unwrap().is_empty());
+
+        // 第二次应用不再写入文件：只读文件无法被覆盖，应用仍然成功
+        let modified = base_dir.join("modify.txt");
+        let mut permissions = fs::metadata(&modified).unwrap().permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(&modified, permissions.clone()).unwrap();
+        wim_patch
+            .apply_dir_patch(&base_dir, &patch_dir, &base_dir, &ApplyOptions::default())
+            .unwrap();
+        assert_eq!(messages.lock().unwrap().len(), 1);
+
+        // 内容不一致或大小不符时不视为已应用
+        let sha256 = get_file_sha256(target_dir.join("modify.txt"), None).unwrap();
+        assert!(file_matches_sha256(&modified, Some(13), &sha256.to_uppercase()));
+        assert!(!file_matches_sha256(&modified, Some(14), &sha256));
+        assert!(!file_matches_sha256(&base_dir.join("missing.txt"), None, &sha256));
+
+        #[allow(clippy::permissions_set_readonly_false)]
+        permissions.set_readonly(false);
+        fs::set_permissions(&modified, permissions).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// 镜像文件列表路径测试：去掉应用根目录前缀，根目录本身与其他目录下的路径被忽略
+    #[test]
+    fn test_tree_relative_path() {
+        let root = r"C:\Temp\tree-abc123";
+        assert_eq!(
+            tree_relative_path(r"C:\Temp\tree-abc123\Windows\System32", root),
+            Some(r"Windows\System32")
+        );
+        // 根目录大小写不同
+        assert_eq!(
+            tree_relative_path(r"c:\temp\TREE-ABC123\file.txt", root),
+            Some("file.txt")
+        );
+        assert_eq!(tree_relative_path(r"C:\Temp\tree-abc123", root), None);
+        assert_eq!(tree_relative_path(r"C:\Temp\tree-abc123\", root), None);
+        assert_eq!(tree_relative_path(r"D:\Other\file.txt", root), None);
+    }
+
+    /// 镜像文件列表测试：不挂载镜像列出捕获的文件、目录与大小，跳过系统路径（需要管理员权限）
+    #[test]
+    #[ignore]
+    fn test_list_tree() {
+        let root = std::env::temp_dir().join(get_tmp_name("tree-", "", 6));
+        let source = root.join("source");
+        fs::create_dir_all(source.join("sub")).unwrap();
+        fs::write(source.join("file.txt"), b"hello").unwrap();
+        fs::write(source.join("sub").join("nested.txt"), b"nested file").unwrap();
+        fs::write(source.join("pagefile.sys"), b"system").unwrap();
+
+        let wimgapi = Wimgapi::new(None).unwrap();
+        let image = root.join("tree.wim");
+        let handle = wimgapi
+            .open(&image, WIM_GENERIC_WRITE, WIM_CREATE_ALWAYS, WIM_COMPRESS_NONE)
+            .unwrap();
+        wimgapi.set_temp_path(handle, &get_temp_path()).unwrap();
+        let image_handle = wimgapi.capture(handle, &source, 0).unwrap();
+        wimgapi.close(image_handle).unwrap();
+        wimgapi.close(handle).unwrap();
+
+        let wim_patch = WimPatch::new().unwrap();
+        let paths: Vec<String> = wim_patch
+            .list_tree(&image, 1, false)
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.path)
+            .collect();
+        assert!(paths.contains(&"file.txt".to_string()));
+        assert!(paths.contains(&r"sub\nested.txt".to_string()));
+        assert!(!paths.iter().any(|path| path.eq_ignore_ascii_case("pagefile.sys")));
+
+        // 指定 sizes 时记录文件大小与目录标识
+        let entries = wim_patch.list_tree(&image, 1, true).unwrap();
+        let find = |path: &str| entries.iter().find(|entry| entry.path == path).unwrap();
+        assert_eq!(find("file.txt").size, Some(5));
+        assert_eq!(find(r"sub\nested.txt").size, Some(11));
+        assert!(find("sub").is_dir);
+        assert_eq!(find("sub").size, None);
+
+        // 索引不存在时报错
+        assert!(wim_patch.list_tree(&image, 2, false).is_err());
+
+        fs::remove_dir_all(&root).ok();
+    }
+
+    /// 临时路径测试：连续生成的路径互不相同，且不与已存在的文件冲突
+    #[test]
+    fn test_get_tmp_path() {
+        let root = std::env::temp_dir().join(get_tmp_name("tmp-path-", "", 6));
+        fs::create_dir_all(&root).unwrap();
+
+        let mut names = std::collections::HashSet::new();
+        for _ in 0..1000 {
+            let path = get_tmp_path(&root, "base-", ".wim");
+            assert!(!path.exists());
+            let name = path.file_name().unwrap().to_string_lossy().into_owned();
+            assert!(name.starts_with("base-") && name.ends_with(".wim"));
+            assert_eq!(name.len(), "base-".len() + 10 + ".wim".len());
+            // 创建文件，后续生成的路径必须避开它
+            fs::write(&path, b"").unwrap();
+            assert!(names.insert(name));
+        }
+        assert_eq!(fs::read_dir(&root).unwrap().count(), 1000);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// 临时目录测试：所在目录不存在时先创建，每次都新建空目录且不复用已存在的目录
+    #[test]
+    fn test_create_tmp_dir() {
+        let root = std::env::temp_dir().join(get_tmp_name("tmp-dir-", "", 6));
+        let parent = root.join("nested");
+
+        let mut dirs = std::collections::HashSet::new();
+        for _ in 0..200 {
+            let dir = create_tmp_dir(&parent, "patch-", "").unwrap();
+            assert!(dir.is_dir());
+            assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+            assert!(dir.file_name().unwrap().to_string_lossy().starts_with("patch-"));
+            // 写入标记文件，后续创建的目录不得与它所在目录相同
+            fs::write(dir.join("marker"), b"keep").unwrap();
+            assert!(dirs.insert(dir));
+        }
+        assert_eq!(fs::read_dir(&parent).unwrap().count(), 200);
+        for dir in &dirs {
+            assert_eq!(fs::read(dir.join("marker")).unwrap(), b"keep");
+        }
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// 注册表配置单元结构化差异测试：值的修改与删除、键的新增与删除及安全描述符变化
+    #[test]
+    fn test_hive_diff() {
+        let value = |name: &str, data: &[u8]| HiveValue {
+            name: name.to_string(),
+            kind: 1,
+            data: data.to_vec(),
+        };
+        let key = |name: &str, values: Vec<HiveValue>, subkeys: Vec<HiveKey>| HiveKey {
+            name: name.to_string(),
+            class: String::new(),
+            security: vec![1, 0, 4, 128],
+            values: values.into_iter().map(|v| (v.name.to_lowercase(), v)).collect(),
+            subkeys: subkeys.into_iter().map(|k| (k.name.to_lowercase(), k)).collect(),
+        };
+
+        let old = key(
+            "",
+            vec![value("Keep", b"1"), value("Change", b"old"), value("Remove", b"x")],
+            vec![
+                key("Gone", vec![], vec![key("Child", vec![value("", b"d")], vec![])]),
+                key("Stay", vec![value("A", b"a")], vec![]),
+            ],
+        );
+        let mut new = key(
+            "",
+            vec![value("Keep", b"1"), value("Change", b"new"), value("Added", b"y")],
+            vec![
+                key("Stay", vec![value("A", b"a")], vec![]),
+                key("Fresh", vec![value("B", b"b")], vec![key("Nested", vec![], vec![])]),
+            ],
+        );
+        new.subkeys.get_mut("stay").unwrap().security = vec![1, 0, 4, 144];
+
+        let ops = HiveDiff::diff(&old, &new).unwrap();
+        // 删除键时先删除子键
+        let delete = |path: &str| HiveOp::DeleteKey { path: path.to_string() };
+        let child = ops.iter().position(|op| op == &delete("Gone\\Child")).unwrap();
+        let parent = ops.iter().position(|op| op == &delete("Gone")).unwrap();
+        assert!(child < parent);
+        assert!(ops.contains(&HiveOp::SetSecurity {
+            path: "Stay".to_string(),
+            security: vec![1, 0, 4, 144],
+        }));
+        assert!(ops.contains(&HiveOp::DeleteValue {
+            path: String::new(),
+            name: "Remove".to_string(),
+        }));
+        let keep = |op: &HiveOp| matches!(op, HiveOp::SetValue { value, .. } if value.name == "Keep");
+        assert!(!ops.iter().any(keep));
+
+        // 序列化往返后应用到旧键树得到新键树
+        let decoded = HiveDiff::decode(&HiveDiff::encode(&ops, 3).unwrap()).unwrap();
+        assert_eq!(decoded, ops);
+        let mut rebuilt = old.clone();
+        HiveDiff::apply(&mut rebuilt, &decoded).unwrap();
+        assert_eq!(rebuilt, new);
+
+        // 相同的键树没有差异
+        assert!(HiveDiff::diff(&new, &new).unwrap().is_empty());
+
+        // 已有键的类名改变时无法表示，调用方回退为普通差异
+        let mut reclassed = new.clone();
+        reclassed.subkeys.get_mut("stay").unwrap().class = "Secret".to_string();
+        assert!(HiveDiff::diff(&new, &reclassed).is_err());
+
+        // 仅大小写不同的键改名同样无法表示；值改名时按新名称重新设置
+        let mut renamed = new.clone();
+        renamed.subkeys.get_mut("stay").unwrap().name = "STAY".to_string();
+        assert!(HiveDiff::diff(&new, &renamed).is_err());
+        let mut renamed = new.clone();
+        renamed.values.insert("keep".to_string(), value("KEEP", b"1"));
+        assert_eq!(
+            HiveDiff::diff(&new, &renamed).unwrap(),
+            vec![HiveOp::SetValue {
+                path: String::new(),
+                value: value("KEEP", b"1"),
+            }]
+        );
+    }
+
+    /// 注册表配置单元文件差异测试：由 reg save 导出两个不同的配置单元，按结构化差异重建（需要管理员权限）
+    #[test]
+    #[ignore]
+    fn test_hive_file_diff() {
+        let root = get_temp_path().join("test_hive_file_diff");
+        fs::remove_dir_all(&root).ok();
+        fs::create_dir_all(root.join("old")).unwrap();
+        fs::create_dir_all(root.join("new")).unwrap();
+        let key = r"HKCU\Software\WimPatchHiveTest";
+        let reg = |args: &[&str]| {
+            let status = std::process::Command::new("reg").args(args).status().unwrap();
+            assert!(status.success(), "reg {:?}", args);
+        };
+        let save = |path: &Path| reg(&["save", key, path.to_str().unwrap(), "/y"]);
+
+        reg(&["add", key, "/v", "Keep", "/d", "1", "/f"]);
+        reg(&["add", key, "/v", "Change", "/t", "REG_DWORD", "/d", "1", "/f"]);
+        reg(&["add", &format!(r"{}\Gone", key), "/v", "X", "/d", "x", "/f"]);
+        let old_hive = root.join("old").join("SOFTWARE");
+        save(&old_hive);
+
+        reg(&["add", key, "/v", "Change", "/t", "REG_DWORD", "/d", "2", "/f"]);
+        reg(&["delete", &format!(r"{}\Gone", key), "/f"]);
+        reg(&["add", &format!(r"{}\Fresh\Nested", key), "/ve", "/d", "default", "/f"]);
+        let new_hive = root.join("new").join("SOFTWARE");
+        save(&new_hive);
+        reg(&["delete", key, "/f"]);
+
+        assert!(HiveDiff::is_hive(&old_hive));
+        assert!(!HiveDiff::is_hive(&root.join("old")));
+
+        let patch = root.join("SOFTWARE.hive");
+        HiveDiff::file_diff(&old_hive, &new_hive, &patch, 3).unwrap();
+        assert!(HiveDiff::file_diff_size(&old_hive, &new_hive, 3).unwrap() > 0);
+
+        // 原地重建旧配置单元
+        HiveDiff::file_patch(&old_hive, &patch, &old_hive).unwrap();
+        let rebuilt = HiveDiff::read_hive(&old_hive).unwrap();
+        let expected = HiveDiff::read_hive(&new_hive).unwrap();
+        assert!(HiveDiff::diff(&rebuilt, &expected).unwrap().is_empty());
+        assert!(rebuilt.subkeys.contains_key("fresh"));
+        assert!(!rebuilt.subkeys.contains_key("gone"));
+
+        fs::remove_dir_all(&root).ok();
+    }
+
+    /// 配置单元差异端到端测试：创建时生成 hive 存储的操作，应用时重建配置单元并清空其事务日志；
+    /// 无效的配置单元回退为 zstd 差异；之后的字节差异不会应用到重建过的配置单元上（需要管理员权限）
+    #[test]
+    #[ignore]
+    fn test_hive_diff_operations() {
+        let root = get_temp_path().join("test_hive_diff_operations");
+        fs::remove_dir_all(&root).ok();
+        let config = Path::new("Windows").join("System32").join("config");
+        let (base_dir, target_dir, later_dir) = (root.join("base"), root.join("target"), root.join("later"));
+        for dir in [&base_dir, &target_dir, &later_dir] {
+            fs::create_dir_all(dir.join(&config)).unwrap();
+            fs::create_dir_all(dir.join("Fake")).unwrap();
+        }
+        let key = r"HKCU\Software\WimPatchHiveTest";
+        let reg = |args: &[&str]| {
+            let status = std::process::Command::new("reg").args(args).status().unwrap();
+            assert!(status.success(), "reg {:?}", args);
+        };
+        let save = |dir: &Path| reg(&["save", key, dir.join(&config).join("SOFTWARE").to_str().unwrap(), "/y"]);
+
+        reg(&["add", key, "/v", "Change", "/t", "REG_DWORD", "/d", "1", "/f"]);
+        save(&base_dir);
+        reg(&["add", key, "/v", "Change", "/t", "REG_DWORD", "/d", "2", "/f"]);
+        save(&target_dir);
+        reg(&["add", key, "/v", "Later", "/d", "3", "/f"]);
+        save(&later_dir);
+        reg(&["delete", key, "/f"]);
+
+        // 事务日志在基础与更新镜像中不同
+        fs::write(base_dir.join(&config).join("SOFTWARE.LOG1"), b"base log").unwrap();
+        fs::write(target_dir.join(&config).join("SOFTWARE.LOG1"), b"target log").unwrap();
+        // 名称为配置单元且带有签名，但不是有效的配置单元
+        let fake = |dir: &Path, content: &[u8]| {
+            fs::write(
+                dir.join("Fake").join("SOFTWARE"),
+                [b"regf".as_slice(), content].concat(),
+            )
+            .unwrap()
+        };
+        fake(&base_dir, &[1u8; 4096]);
+        fake(&target_dir, &[2u8; 4096]);
+        fake(&later_dir, &[2u8; 4096]);
+
+        let mut options = CreateOptions {
+            storage: Storage::Zstd,
+            hive_diff: true,
+            author: String::new(),
+            name: "test-patch".to_string(),
+            ..Default::default()
+        };
+        let wim_patch = WimPatch::new().unwrap();
+        let patch_dir = root.join("patch");
+        fs::create_dir_all(&patch_dir).unwrap();
+        let (operations, _, _) = wim_patch
+            .create_operations(&base_dir, &target_dir, &patch_dir, 1, &options)
+            .unwrap();
+        let storage = |operations: &[Operation], path: &Path| {
+            let path = path.to_string_lossy();
+            let operation = operations.iter().find(|op| op.path == path).unwrap();
+            operation.storage.clone().unwrap()
+        };
+        assert_eq!(storage(&operations, &config.join("SOFTWARE")), "hive");
+        assert_eq!(storage(&operations, &Path::new("Fake").join("SOFTWARE")), "zstd");
+        assert!(patch_dir.join(&config).join("SOFTWARE.hive").exists());
+
+        // 应用：重建配置单元并清空事务日志（更新镜像中的事务日志在重建后跳过，重建前写入的也会被清空），
+        // 无效的配置单元按 zstd 差异还原
+        let counts = wim_patch
+            .apply_operations(&base_dir, &patch_dir, &operations, 1, &ApplyOptions::default())
+            .unwrap();
+        assert_eq!((counts.applied + counts.skipped, counts.failed), (3, 0));
+        let rebuilt = HiveDiff::read_hive(&base_dir.join(&config).join("SOFTWARE")).unwrap();
+        let expected = HiveDiff::read_hive(&target_dir.join(&config).join("SOFTWARE")).unwrap();
+        assert!(HiveDiff::diff(&rebuilt, &expected).unwrap().is_empty());
+        assert_eq!(
+            fs::metadata(base_dir.join(&config).join("SOFTWARE.LOG1"))
+                .unwrap()
+                .len(),
+            0
+        );
+        assert_eq!(
+            fs::read(base_dir.join("Fake").join("SOFTWARE")).unwrap(),
+            fs::read(target_dir.join("Fake").join("SOFTWARE")).unwrap()
+        );
+
+        // 之后不使用 --hive-diff 创建的字节差异基于更新镜像中的原始配置单元，新的进程中也不会应用到重建的配置单元上
+        options.hive_diff = false;
+        let later_patch_dir = root.join("later_patch");
+        fs::create_dir_all(&later_patch_dir).unwrap();
+        let (operations, _, _) = wim_patch
+            .create_operations(&target_dir, &later_dir, &later_patch_dir, 1, &options)
+            .unwrap();
+        let software = operations
+            .iter()
+            .find(|op| op.storage.as_deref() == Some("zstd"))
+            .unwrap();
+        assert!(software.base_sha256.is_some());
+        let before = fs::read(base_dir.join(&config).join("SOFTWARE")).unwrap();
+        let error = WimPatch::new()
+            .unwrap()
+            .apply_operations(&base_dir, &later_patch_dir, &operations, 1, &ApplyOptions::default())
+            .unwrap_err();
+        assert!(error.to_string().contains(&*rust_i18n::t!(
+            "apply_patch.base_mismatch",
+            path = config.join("SOFTWARE").to_string_lossy()
+        )));
+        assert_eq!(fs::read(base_dir.join(&config).join("SOFTWARE")).unwrap(), before);
+
+        fs::remove_dir_all(&root).ok();
+    }
+
+    /// 展平合并测试：三个补丁组成的补丁链展平为一个补丁，基础镜像信息与补丁链起点相同，一步应用即得到最终内容（需要管理员权限）
+    #[test]
+    #[ignore]
+    fn test_merge_flatten() {
+        let root = std::env::temp_dir().join(get_tmp_name("flatten-", "", 6));
+        let wimgapi = Wimgapi::new(None).unwrap();
+        let capture = |version: &str, extra: bool| {
+            let source = root.join(format!("source-{version}"));
+            fs::create_dir_all(&source).unwrap();
+            fs::write(source.join("version.txt"), version).unwrap();
+            if extra {
+                fs::write(source.join("extra.txt"), b"extra").unwrap();
+            }
+            let image = root.join(format!("{version}.wim"));
+            let handle = wimgapi
+                .open(&image, WIM_GENERIC_WRITE, WIM_CREATE_ALWAYS, WIM_COMPRESS_NONE)
+                .unwrap();
+            wimgapi.set_temp_path(handle, &get_temp_path()).unwrap();
+            let image_handle = wimgapi.capture(handle, &source, 0).unwrap();
+            wimgapi.close(image_handle).unwrap();
+            wimgapi.close(handle).unwrap();
+            image
+        };
+        // 1.1.0 新增的文件在 1.3.0 中被删除
+        let images = [
+            capture("1.0.0", false),
+            capture("1.1.0", true),
+            capture("1.2.0", true),
+            capture("1.3.0", false),
+        ];
+
+        let wim_patch = WimPatch::new().unwrap();
+        let mut patches = Vec::new();
+        for (step, version) in ["1.1.0", "1.2.0", "1.3.0"].iter().enumerate() {
+            let options = CreateOptions {
+                preset: Preset::Fast,
+                version: version.to_string(),
+                author: "tester".to_string(),
+                name: format!("patch-{version}"),
+                compress: Compress::None,
+                ..Default::default()
+            };
+            let patch = root.join(format!("patch-{version}.wim"));
+            wim_patch
+                .create_patch(&images[step], Some(1), &images[step + 1], Some(1), &patch, &options)
+                .unwrap();
+            patches.push(patch);
+        }
+        let first = wim_patch.get_manifests(&patches[0]).unwrap().remove(0).1;
+
+        let flattened = root.join("flattened.wim");
+        let options = MergeOptions {
+            compress: Compress::None,
+            boot_index: None,
+            verify: false,
+            report_shared: false,
+            keep_compression: false,
+            fail_on_conflict: false,
+            verify_after_each: false,
+            flatten_base: Some(images[0].clone()),
+            flatten_base_index: Some(1),
+            flatten_storage: None,
+            flatten_preset: None,
+            flatten_manifest_format: None,
+        };
+        wim_patch.merge_patches(&patches, &flattened, &options).unwrap();
+
+        // 只有一个补丁，基础镜像与补丁链起点相同，版本与名称取自最后一个补丁
+        let manifests = wim_patch.get_manifests(&flattened).unwrap();
+        assert_eq!(manifests.len(), 1);
+        let manifest = &manifests[0].1;
+        assert_eq!(manifest.patch_version, "1.3.0");
+        assert_eq!(manifest.name, "patch-1.3.0");
+        assert_eq!(manifest.author, "tester");
+        assert!(is_same_guid(&manifest.base_image_guid, &first.base_image_guid));
+        assert_eq!(manifest.base_image_info, first.base_image_info);
+        assert!(manifest.operations.iter().all(|op| op.path != "extra.txt"));
+
+        // 一步应用到起点镜像即得到最终内容
+        let extract_to = root.join("extract");
+        let apply_options = ApplyOptions {
+            extract_to: Some(extract_to.clone()),
+            no_export: true,
+            ..Default::default()
+        };
+        wim_patch
+            .apply_patch(&images[0], Some(1), &flattened, &root.join("out.wim"), &apply_options)
+            .unwrap();
+        assert_eq!(fs::read(extract_to.join("version.txt")).unwrap(), b"1.3.0");
+        assert!(!extract_to.join("extra.txt").exists());
+
+        fs::remove_dir_all(&root).ok();
+    }
+
+    /// 镜像信息编码测试：读取时去除字节顺序标记，写入时恰好添加一个，名称与描述转义后拼接
+    #[test]
+    fn test_image_info_encoding() {
+        let xml = "<IMAGE INDEX=\"1\"><DIRCOUNT>1</DIRCOUNT><FILECOUNT>1</FILECOUNT><TOTALBYTES>1</TOTALBYTES></IMAGE>";
+
+        // WIMGetImageInformation 返回的缓冲区以 BOM 开头、以 NUL 结尾
+        let units: Vec<u16> = std::iter::once(UTF16_BOM as u16)
+            .chain(xml.encode_utf16())
+            .chain([0, 0])
+            .collect();
+        assert_eq!(decode_image_info(&units), xml);
+        assert_eq!(decode_image_info(&[]), "");
+
+        // 已带 BOM 的字符串编码后不会出现两个 BOM
+        let encoded = encode_image_info(xml);
+        assert_eq!(encoded[0], UTF16_BOM as u16);
+        assert_ne!(encoded[1], UTF16_BOM as u16);
+        assert_eq!(encode_image_info(&format!("{}{}", UTF16_BOM, xml)), encoded);
+        assert_eq!(decode_image_info(&encoded), xml);
+
+        // 非 ASCII 与 XML 特殊字符拼接后仍可解析出原值
+        let name = "补丁 & <更新> 𝒲";
+        let description = "Mise à jour \"1.1\" — パッチ";
+        let embedded = embed_patch_fields(xml, name, description, "").unwrap();
+        let info = ImageInfo::from_xml(&embedded).unwrap();
+        assert_eq!(info.name.as_deref(), Some(name));
+        assert_eq!(info.display_name.as_deref(), Some(name));
+        assert_eq!(info.description.as_deref(), Some(description));
+        assert!(embed_patch_fields("<IMAGE>", name, description, "").is_none());
+    }
+
+    /// 镜像信息往返测试：非 ASCII 名称与描述经过读取、拼接、写入、再读取后保持不变
+    #[test]
+    fn test_image_info_round_trip() {
+        let root = std::env::temp_dir().join(get_tmp_name("image-info-", "", 6));
+        let source = root.join("source");
+        fs::create_dir_all(&source).unwrap();
+        fs::write(source.join("file.txt"), b"payload").unwrap();
+
+        let wimgapi = Wimgapi::new(None).unwrap();
+        let image = root.join("image.wim");
+        let handle = wimgapi
+            .open(&image, WIM_GENERIC_WRITE, WIM_CREATE_ALWAYS, WIM_COMPRESS_NONE)
+            .unwrap();
+        wimgapi.set_temp_path(handle, &get_temp_path()).unwrap();
+        let image_handle = wimgapi.capture(handle, &source, 0).unwrap();
+
+        let name = "Windows 11 专业版 & <测试>";
+        let description = "Ünïcödé 説明 𝒲";
+        let manifest = PatchManifest::new(
+            name,
+            description,
+            "WimPatch",
+            "1.0.0",
+            "{3F2504E0-4F89-11D3-9A0C-0305E82C3301}",
+            &ImageInfo::default(),
+            "",
+            &ImageInfo::default(),
+            &[],
+            None,
+        );
+        let image_info = wimgapi.get_image_info(image_handle).unwrap();
+        assert!(!image_info.starts_with(UTF16_BOM));
+        let embedded = embed_patch_fields(&image_info, name, description, &manifest.to_xml().unwrap()).unwrap();
+        wimgapi.set_image_info(image_handle, &embedded).unwrap();
+
+        // 再次读取与写入的内容相同
+        let round_trip = wimgapi.get_image_info(image_handle).unwrap();
+        assert!(!round_trip.starts_with(UTF16_BOM));
+        let info = ImageInfo::from_xml(&round_trip).unwrap();
+        assert_eq!(info.name.as_deref(), Some(name));
+        assert_eq!(info.description.as_deref(), Some(description));
+        assert_eq!(info.display_description.as_deref(), Some(description));
+        wimgapi.close(image_handle).unwrap();
+        wimgapi.close(handle).unwrap();
+
+        // 补丁清单同样可以从补丁包中解析
+        let manifests = WimPatch::new().unwrap().get_manifests(&image).unwrap();
+        assert_eq!(manifests.len(), 1);
+        assert_eq!(manifests[0].1.name, name);
+        assert_eq!(manifests[0].1.description, description);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// 执行顺序测试：文件被删除后以同名目录重新添加，分阶段执行先删除再新增，按补丁清单顺序执行则失败
+    #[test]
+    fn test_apply_order() {
+        let root = std::env::temp_dir().join(get_tmp_name("order-", "", 6));
+        let base_dir = root.join("base");
+        let patch_dir = root.join("patch");
+        fs::create_dir_all(&base_dir).unwrap();
+        fs::create_dir_all(patch_dir.join("item")).unwrap();
+        fs::write(base_dir.join("item"), b"was a file").unwrap();
+        fs::write(base_dir.join("modify.txt"), b"old").unwrap();
+        fs::write(patch_dir.join("item").join("inner.txt"), b"inner").unwrap();
+        fs::write(patch_dir.join("modify.txt"), b"new").unwrap();
+
+        let operation =
+            |action, path: &str, size: Option<u64>| test_operation(action, path, size, size.map(|_| "full"));
+        // 补丁清单中新增操作排在删除操作之前
+        let operations = vec![
+            operation(Action::Modify, "modify.txt", Some(3)),
+            operation(Action::Add, "item", None),
+            operation(Action::Add, "item\\inner.txt", Some(5)),
+            operation(Action::Delete, "item", None),
+        ];
+
+        // 分阶段时删除、新增、修改依次执行，阶段内保持补丁清单顺序
+        let phased: Vec<&str> = order_operations(&operations, ApplyOrder::Phased)
+            .iter()
+            .map(|op| op.path.as_str())
+            .collect();
+        assert_eq!(phased, ["item", "item", "item\\inner.txt", "modify.txt"]);
+        let manifest_order = order_operations(&operations, ApplyOrder::Manifest);
+        assert_eq!(manifest_order[0].path, "modify.txt");
+
+        // 按补丁清单顺序时无法在原文件处创建目录
+        let wim_patch = WimPatch::without_wimgapi().unwrap();
+        let manifest = ApplyOptions {
+            apply_order: ApplyOrder::Manifest,
+            ..Default::default()
+        };
+        assert!(
+            wim_patch
+                .apply_operations(&base_dir, &patch_dir, &operations, 1, &manifest)
+                .is_err()
+        );
+
+        // 默认分阶段执行，文件被替换为包含新文件的目录
+        fs::remove_dir_all(&base_dir).ok();
+        fs::create_dir_all(&base_dir).unwrap();
+        fs::write(base_dir.join("item"), b"was a file").unwrap();
+        fs::write(base_dir.join("modify.txt"), b"old").unwrap();
+        wim_patch
+            .apply_operations(&base_dir, &patch_dir, &operations, 1, &ApplyOptions::default())
+            .unwrap();
+        assert!(base_dir.join("item").is_dir());
+        assert_eq!(fs::read(base_dir.join("item").join("inner.txt")).unwrap(), b"inner");
+        assert_eq!(fs::read(base_dir.join("modify.txt")).unwrap(), b"new");
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// 忽略大小写比较测试：仅大小写不同的文件记录为使用目标大小写的修改，而不是删除加新增；应用时恢复目标大小写
+    #[test]
+    fn test_compare_ignore_case() {
+        let root = std::env::temp_dir().join(get_tmp_name("compare-case-", "", 6));
+        let base_dir = root.join("base");
+        let target_dir = root.join("target");
+        fs::create_dir_all(&base_dir).unwrap();
+        fs::create_dir_all(&target_dir).unwrap();
+        fs::write(base_dir.join("Readme.txt"), b"readme").unwrap();
+        fs::write(target_dir.join("README.TXT"), b"readme").unwrap();
+        fs::write(base_dir.join("same.txt"), b"same").unwrap();
+        fs::write(target_dir.join("same.txt"), b"same").unwrap();
+        fs::create_dir_all(base_dir.join("Sub")).unwrap();
+        fs::create_dir_all(target_dir.join("SUB")).unwrap();
+        fs::write(base_dir.join("Sub").join("inner.txt"), b"inner").unwrap();
+        fs::write(target_dir.join("SUB").join("inner.txt"), b"inner").unwrap();
+
+        let collect = |ignore_case: bool| {
+            let mut diffs = Vec::new();
+            let options = CompareOptions {
+                ignore_case,
+                ..Default::default()
+            };
+            compare_subtree_with_total(
+                &base_dir,
+                &target_dir,
+                &options,
+                |_, _| {},
+                |diff_type, _, _, path| {
+                    diffs.push(format!("{:?} {}", diff_type, path));
+                    true
+                },
+            )
+            .unwrap();
+            diffs.sort();
+            diffs
+        };
+        // 区分大小写时为删除加新增
+        assert_eq!(
+            collect(false),
+            vec![
+                "Add README.TXT",
+                "Add SUB",
+                "Add SUB\\inner.txt",
+                "Delete Readme.txt",
+                "Delete Sub",
+                "Delete Sub\\inner.txt"
+            ]
+        );
+        // 忽略大小写时内容相同也记录为修改，路径使用目标目录中的大小写；目录中未变化的文件不重复报告
+        assert_eq!(collect(true), vec!["Modify README.TXT", "Modify SUB"]);
+
+        // 创建补丁时记录大小写重命名，应用后按操作路径恢复文件与目录名称的大小写
+        let patch_dir = root.join("patch");
+        fs::create_dir_all(&patch_dir).unwrap();
+        let options = CreateOptions {
+            storage: Storage::Full,
+            name: "test-patch".to_string(),
+            compare_ignore_case: true,
+            ..Default::default()
+        };
+        let wim_patch = WimPatch::new().unwrap();
+        let (operations, _, _) = wim_patch
+            .create_operations(&base_dir, &target_dir, &patch_dir, 1, &options)
+            .unwrap();
+        assert!(operations.iter().all(|op| op.case_rename));
+        wim_patch
+            .apply_operations(&base_dir, &patch_dir, &operations, 1, &ApplyOptions::default())
+            .unwrap();
+        let names = |dir: &Path| -> Vec<String> {
+            let mut names: Vec<_> = fs::read_dir(dir)
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+                .collect();
+            names.sort();
+            names
+        };
+        assert_eq!(names(&base_dir), vec!["README.TXT", "SUB", "same.txt"]);
+        assert_eq!(names(&base_dir.join("SUB")), vec!["inner.txt"]);
+        assert!(!restore_name_case(base_dir.join("README.TXT")).unwrap());
+        assert!(!restore_name_case(base_dir.join("SUB")).unwrap());
+
+        // 未记录大小写重命名的操作不改变已有名称的大小写
+        let operations = vec![Operation {
+            case_rename: false,
+            ..operations.iter().find(|op| op.path == "SUB").unwrap().clone()
+        }];
+        fs::rename(base_dir.join("SUB"), base_dir.join("sub")).unwrap();
+        wim_patch
+            .apply_operations(&base_dir, &patch_dir, &operations, 1, &ApplyOptions::default())
+            .unwrap();
+        assert_eq!(names(&base_dir), vec!["README.TXT", "same.txt", "sub"]);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// 捕获计数测试：模拟捕获 N 个文件后中止，回调返回中止并报告已捕获的数量（排除的路径不计入）
+    #[test]
+    fn test_capture_interrupted_count() {
+        static ABORT: AtomicBool = AtomicBool::new(false);
+        let exclude = vec!["skip_me".to_string()];
+        let state = CaptureState::with_abort(&exclude, || ABORT.load(Ordering::Relaxed));
+        let user_data = &state as *const CaptureState as *mut std::ffi::c_void;
+        let process = |path: &str| {
+            let wide: Vec<u16> = path.encode_utf16().chain(std::iter::once(0)).collect();
+            let mut capture = 1i32;
+            let result = CaptureCallback(
+                WIM_MSG_PROCESS,
+                wide.as_ptr() as usize,
+                &mut capture as *mut i32 as isize,
+                user_data,
+            );
+            (result, capture)
+        };
+
+        for index in 0..3 {
+            assert_eq!(process(&format!("C:\\capture\\file{}.txt", index)), (0, 1));
+        }
+        assert_eq!(process("C:\\capture\\skip_me\\file.txt"), (0, 0));
+        assert_eq!(state.processed(), 3);
+
+        // 请求中止后回调返回 WIM_MSG_ABORT_IMAGE，计数保持中止前的数量
+        ABORT.store(true, Ordering::Relaxed);
+        assert_eq!(process("C:\\capture\\file3.txt").0, WIM_MSG_ABORT_IMAGE);
+        assert_eq!(state.processed(), 3);
+    }
+
+    /// 补丁签名测试：有效签名通过校验；修改清单、补丁数据或使用其他公钥时校验失败
+    #[test]
+    fn test_patch_signature() {
+        let root = std::env::temp_dir().join(get_tmp_name("signature-", "", 6));
+        let patch_dir = root.join("patch");
+        fs::create_dir_all(patch_dir.join("Windows")).unwrap();
+        fs::write(patch_dir.join("Windows").join("new.dll"), b"new").unwrap();
+        fs::write(patch_dir.join("config.ini.diff"), b"diff").unwrap();
+
+        // 私钥文件为 Base64 编码的 32 字节种子，公钥从私钥导出
+        let key_path = root.join("patch.key");
+        let public_path = root.join("patch.pub");
+        fs::write(&key_path, STANDARD.encode([7u8; 32])).unwrap();
+        let key = read_signing_key(&key_path).unwrap();
+        fs::write(&public_path, STANDARD.encode(key.verifying_key().as_bytes())).unwrap();
+        let public_key = read_verifying_key(&public_path).unwrap();
+
+        let operations = vec![Operation {
+            action: Action::Add,
+            path: "Windows\\new.dll".to_string(),
+            size: Some(3),
+            storage: None,
+            payload_ref: None,
+            full_fallback: false,
+            sparse: false,
+            case_rename: false,
+            streams: Vec::new(),
+            dict_window: None,
+            sha256: None,
+            base_sha256: None,
+        }];
+        let image_info = ImageInfo::default();
+        let manifest = PatchManifest::new(
+            "signed",
+            "",
+            "",
+            "1.0.0",
+            "",
+            &image_info,
+            "",
+            &image_info,
+            &operations,
+            None,
+        );
+        let payloads = payload_hashes(&patch_dir, |_| false).unwrap();
+        assert_eq!(payloads.len(), 2);
+        let signature = PatchSignature::sign(&key, &manifest, &payloads).unwrap();
+        assert_eq!(signature.signer, key_fingerprint(&public_key));
+
+        // 嵌入镜像信息后解析得到相同的签名
+        let image_xml = format!("<IMAGE INDEX=\"1\">{}</IMAGE>", signature.to_xml().unwrap());
+        let parsed = PatchSignature::from_image_info(&image_xml).unwrap().unwrap();
+        assert_eq!(parsed, signature);
+        assert!(PatchSignature::from_image_info("<IMAGE INDEX=\"1\"></IMAGE>").is_none());
+        parsed.verify(&public_key, &manifest, &payloads).unwrap();
+
+        // 修改补丁清单
+        let mut tampered = manifest.clone();
+        tampered.operations[0].path = "Windows\\evil.dll".to_string();
+        assert!(signature.verify(&public_key, &tampered, &payloads).is_err());
+
+        // 修改补丁数据
+        fs::write(patch_dir.join("Windows").join("new.dll"), b"evil").unwrap();
+        let tampered_payloads = payload_hashes(&patch_dir, |_| false).unwrap();
+        assert!(signature.verify(&public_key, &manifest, &tampered_payloads).is_err());
+
+        // 其他签名者的公钥不受信任
+        let other = SigningKey::from_bytes(&[9u8; 32]).verifying_key();
+        assert!(signature.verify(&other, &manifest, &payloads).is_err());
+
+        // 补丁数据的备用数据流应用时随文件复制，同样计入签名
+        fs::write(patch_dir.join("Windows").join("new.dll"), b"new").unwrap();
+        assert_eq!(payload_hashes(&patch_dir, |_| false).unwrap(), payloads);
+        let payload = patch_dir.join("Windows").join("new.dll");
+        fs::write(stream_path(&payload, "hidden"), b"evil").unwrap();
+        let stream_payloads = payload_hashes(&patch_dir, |_| false).unwrap();
+        let paths: Vec<&str> = stream_payloads.iter().map(|(path, _)| path.as_str()).collect();
+        assert!(paths.contains(&"Windows\\new.dll:hidden"));
+        assert!(signature.verify(&public_key, &manifest, &stream_payloads).is_err());
+
+        // 未签名的目录补丁在要求签名时拒绝应用
+        let dir_patch = root.join("dir-patch");
+        fs::create_dir_all(dir_patch.join(DIR_PATCH_PAYLOAD)).unwrap();
+        fs::write(dir_patch.join(DIR_PATCH_MANIFEST), manifest.to_xml().unwrap()).unwrap();
+        fs::create_dir_all(root.join("base")).unwrap();
+        let options = ApplyOptions {
+            verify_signature: Some(public_path),
+            require_signature: true,
+            ..Default::default()
+        };
+        let wim_patch = WimPatch::without_wimgapi().unwrap();
+        let error = wim_patch
+            .apply_dir_patch(&root.join("base"), &dir_patch, &root.join("out"), &options)
+            .unwrap_err();
+        assert!(error.to_string().contains("not signed"));
+        assert!(!root.join("out").exists());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// 目录补丁签名测试：create-dir 指定私钥时写入签名文件，应用时校验通过；修改补丁数据或清单后拒绝应用
+    #[test]
+    fn test_dir_patch_signature() {
+        let root = std::env::temp_dir().join(get_tmp_name("dir-signature-", "", 6));
+        let base_dir = root.join("base");
+        let target_dir = root.join("target");
+        let patch_dir = root.join("patch");
+        fs::create_dir_all(&base_dir).unwrap();
+        fs::create_dir_all(&target_dir).unwrap();
+        fs::write(base_dir.join("modify.txt"), b"old").unwrap();
+        fs::write(target_dir.join("modify.txt"), b"new").unwrap();
+        fs::write(target_dir.join("add.txt"), b"added").unwrap();
+
+        let key_path = root.join("patch.key");
+        let public_path = root.join("patch.pub");
+        fs::write(&key_path, STANDARD.encode([7u8; 32])).unwrap();
+        let key = read_signing_key(&key_path).unwrap();
+        fs::write(&public_path, STANDARD.encode(key.verifying_key().as_bytes())).unwrap();
+
+        let create_options = CreateOptions {
+            storage: Storage::Full,
+            version: "1.1.0".to_string(),
+            author: String::new(),
+            name: "signed-dir-patch".to_string(),
+            compress: Compress::None,
+            skip_space_check: true,
+            compare_mode: CompareMode::Hash,
+            sign_key: Some(key_path),
+            ..Default::default()
+        };
+        let wim_patch = WimPatch::without_wimgapi().unwrap();
+        assert!(
+            wim_patch
+                .create_dir_patch(&base_dir, &target_dir, &patch_dir, &create_options)
+                .unwrap()
+        );
+        assert!(patch_dir.join(DIR_PATCH_SIGNATURE).is_file());
+
+        // 签名有效时应用
+        let options = ApplyOptions {
+            verify_signature: Some(public_path),
+            require_signature: true,
+            ..Default::default()
+        };
+        wim_patch
+            .apply_dir_patch(&base_dir, &patch_dir, &root.join("out"), &options)
+            .unwrap();
+        assert!(hash_differences(&target_dir, &root.join("out")).is_empty());
+
+        // 修改补丁数据后拒绝应用，不产生输出目录
+        let invalid = rust_i18n::t!("apply_patch.signature_invalid", index = 1).to_string();
+        let payload = patch_dir.join(DIR_PATCH_PAYLOAD).join("add.txt");
+        fs::write(&payload, b"evil").unwrap();
+        let error = wim_patch
+            .apply_dir_patch(&base_dir, &patch_dir, &root.join("tampered"), &options)
+            .unwrap_err();
+        assert!(format!("{:#}", error).contains(&invalid));
+        assert!(!root.join("tampered").exists());
+        fs::write(&payload, b"added").unwrap();
+
+        // 修改补丁清单后拒绝应用
+        let manifest_path = patch_dir.join(DIR_PATCH_MANIFEST);
+        let manifest = fs::read_to_string(&manifest_path).unwrap();
+        fs::write(&manifest_path, manifest.replace("signed-dir-patch", "forged-dir-patch")).unwrap();
+        let error = wim_patch
+            .apply_dir_patch(&base_dir, &patch_dir, &root.join("tampered"), &options)
+            .unwrap_err();
+        assert!(format!("{:#}", error).contains(&invalid));
+        assert!(!root.join("tampered").exists());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// 补丁镜像签名测试：指定私钥创建的补丁校验通过后应用；修改补丁镜像中的清单后，要求签名时拒绝应用（需要管理员权限）
+    #[test]
+    #[ignore]
+    fn test_create_patch_signature() {
+        let root = std::env::temp_dir().join(get_tmp_name("wim-signature-", "", 6));
+        let base_dir = root.join("base");
+        let target_dir = root.join("target");
+        fs::create_dir_all(&base_dir).unwrap();
+        fs::create_dir_all(&target_dir).unwrap();
+        fs::write(base_dir.join("modify.txt"), b"old").unwrap();
+        fs::write(target_dir.join("modify.txt"), b"new").unwrap();
+
+        let wimgapi = Wimgapi::new(None).unwrap();
+        let capture = |source: &Path, image: &Path| {
+            let handle = wimgapi
+                .open(image, WIM_GENERIC_WRITE, WIM_CREATE_ALWAYS, WIM_COMPRESS_NONE)
+                .unwrap();
+            wimgapi.set_temp_path(handle, &get_temp_path()).unwrap();
+            wimgapi.close(wimgapi.capture(handle, source, 0).unwrap()).unwrap();
+            wimgapi.close(handle).unwrap();
+        };
+        let base_image = root.join("base.wim");
+        let target_image = root.join("target.wim");
+        capture(&base_dir, &base_image);
+        capture(&target_dir, &target_image);
+
+        let key_path = root.join("patch.key");
+        let public_path = root.join("patch.pub");
+        fs::write(&key_path, STANDARD.encode([7u8; 32])).unwrap();
+        let key = read_signing_key(&key_path).unwrap();
+        fs::write(&public_path, STANDARD.encode(key.verifying_key().as_bytes())).unwrap();
+
+        let create_options = CreateOptions {
+            storage: Storage::Full,
+            preset: Preset::Fast,
+            author: String::new(),
+            name: "signed-patch".to_string(),
+            compress: Compress::None,
+            compare_mode: CompareMode::Hash,
+            sign_key: Some(key_path),
+            ..Default::default()
+        };
+        let wim_patch = WimPatch::new().unwrap();
+        let patch_image = root.join("patch.wim");
+        wim_patch
+            .create_patch(
+                &base_image,
+                Some(1),
+                &target_image,
+                Some(1),
+                &patch_image,
+                &create_options,
+            )
+            .unwrap();
+
+        // 签名有效时应用
+        let options = ApplyOptions {
+            verify_signature: Some(public_path),
+            require_signature: true,
+            ..Default::default()
+        };
+        wim_patch
+            .apply_patch(&base_image, Some(1), &patch_image, &root.join("result.wim"), &options)
+            .unwrap();
+
+        // 修改补丁镜像信息中的补丁清单后拒绝应用，不产生目标镜像
+        let handle = wimgapi
+            .open(
+                &patch_image,
+                WIM_GENERIC_READ | WIM_GENERIC_WRITE,
+                WIM_OPEN_EXISTING,
+                WIM_COMPRESS_NONE,
+            )
+            .unwrap();
+        wimgapi.set_temp_path(handle, &get_temp_path()).unwrap();
+        let image_handle = wimgapi.load_image(handle, 1).unwrap();
+        let image_info = wimgapi.get_image_info(image_handle).unwrap();
+        assert!(image_info.contains("signed-patch"));
+        wimgapi
+            .set_image_info(image_handle, &image_info.replace("signed-patch", "forged-patch"))
+            .unwrap();
+        wimgapi.close(image_handle).unwrap();
+        wimgapi.close(handle).unwrap();
+
+        let tampered = root.join("tampered.wim");
+        let error = wim_patch
+            .apply_patch(&base_image, Some(1), &patch_image, &tampered, &options)
+            .unwrap_err();
+        assert!(format!("{:#}", error).contains(&*rust_i18n::t!("apply_patch.signature_invalid", index = 1)));
+        assert!(!tampered.exists());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// 重解析点跟随测试：指向上级目录的联接形成循环时不跟随，比较能够结束；指向目录内的联接按层数限制跟随
+    #[test]
+    fn test_compare_follow_reparse() {
+        let root = std::env::temp_dir().join(get_tmp_name("compare-reparse-", "", 6));
+        let base_dir = root.join("base");
+        let target_dir = root.join("target");
+        fs::create_dir_all(&base_dir).unwrap();
+        fs::create_dir_all(target_dir.join("real")).unwrap();
+        fs::write(target_dir.join("real").join("file.txt"), b"file").unwrap();
+
+        // 创建联接（无需管理员权限）：link 指向目录内的 real，loop 指向自身的上级目录
+        let junction = |link: &Path, target: &Path| {
+            let status = std::process::Command::new("cmd")
+                .arg("/c")
+                .arg("mklink")
+                .arg("/J")
+                .arg(link)
+                .arg(target)
+                .status()
+                .unwrap();
+            assert!(status.success());
+        };
+        junction(&target_dir.join("link"), &target_dir.join("real"));
+        junction(&target_dir.join("real").join("loop"), &target_dir);
+
+        let collect = |depth: u32| {
+            let mut diffs = Vec::new();
+            let options = CompareOptions {
+                follow_reparse_depth: depth,
+                ..Default::default()
+            };
+            compare_subtree_with_total(
+                &base_dir,
+                &target_dir,
+                &options,
+                |_, _| {},
+                |_, _, _, path| {
+                    diffs.push(path.to_string());
+                    true
+                },
+            )
+            .unwrap();
+            diffs.sort();
+            diffs
+        };
+        // 不跟随时重解析点作为条目记录
+        assert_eq!(collect(0), vec!["link", "real", "real\\file.txt", "real\\loop"]);
+        // 跟随 link，其中指向上级目录的 loop 形成循环，不再跟随
+        assert_eq!(
+            collect(3),
+            vec![
+                "link",
+                "link\\file.txt",
+                "link\\loop",
+                "real",
+                "real\\file.txt",
+                "real\\loop"
+            ]
+        );
+
+        // 联接需先于其目标删除，避免跟随联接删除目标中的文件
+        fs::remove_dir(target_dir.join("link")).unwrap();
+        fs::remove_dir(target_dir.join("real").join("loop")).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// 未变化文件报告测试：仅返回两个目录中都存在且相同的文件（按路径排序），差异与目录不计入
+    #[test]
+    fn test_compare_report_unchanged() {
+        let root = std::env::temp_dir().join(get_tmp_name("compare-unchanged-", "", 6));
+        let base_dir = root.join("base");
+        let target_dir = root.join("target");
+        for dir in [&base_dir, &target_dir] {
+            fs::create_dir_all(dir.join("sub")).unwrap();
+            fs::write(dir.join("sub").join("same.txt"), b"same").unwrap();
+            for name in ["b.txt", "a.txt", "c.txt"] {
+                fs::write(dir.join(name), name).unwrap();
+            }
+        }
+        fs::write(base_dir.join("changed.txt"), b"old").unwrap();
+        fs::write(target_dir.join("changed.txt"), b"new").unwrap();
+        fs::write(target_dir.join("added.txt"), b"added").unwrap();
+
+        // 两份相同文件的写入时间不同，按内容哈希比较
+        let mut diffs = Vec::new();
+        let options = CompareOptions {
+            mode: CompareMode::Hash,
+            report_unchanged: true,
+            ..Default::default()
+        };
+        let unchanged = compare_subtree_with_total(
+            &base_dir,
+            &target_dir,
+            &options,
+            |_, _| {},
+            |_, _, _, path| {
+                diffs.push(path.to_string());
+                true
+            },
+        )
+        .unwrap();
+        diffs.sort();
+        assert_eq!(unchanged, vec!["a.txt", "b.txt", "c.txt", "sub\\same.txt"]);
+        // 未指定时不收集
+        let unchanged = compare_subtree_with_total(
+            &base_dir,
+            &target_dir,
+            &CompareOptions::default(),
+            |_, _| {},
+            |_, _, _, _| true,
+        )
+        .unwrap();
+        assert!(unchanged.is_empty());
+        assert_eq!(diffs, vec!["added.txt", "changed.txt"]);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// 部分补丁测试：仅比较子目录、排除易变文件或指定包含列表时记录估算的目标统计信息，应用时不因统计信息不一致而失败
+    #[test]
+    fn test_partial_patch_apply() {
+        // 估算：子目录之外保持基础镜像，未写入补丁的差异保持基础镜像的状态
+        let info = |dirs, files, bytes| ImageInfo {
+            dir_count: dirs,
+            file_count: files,
+            total_bytes: bytes,
+            ..Default::default()
+        };
+        let dropped = DroppedDiffs {
+            count: 1,
+            dirs: 0,
+            files: -1,
+            bytes: -10,
+        };
+        assert_eq!(
+            expected_target_info(&info(10, 100, 1000), &info(12, 110, 1500), None, &dropped),
+            info(12, 109, 1490)
+        );
+        assert_eq!(
+            expected_target_info(
+                &info(10, 100, 1000),
+                &info(12, 110, 1500),
+                Some(((2, 10, 100), (3, 12, 300))),
+                &DroppedDiffs::default()
+            ),
+            info(11, 102, 1200)
+        );
+
+        let root = std::env::temp_dir().join(get_tmp_name("partial-", "", 6));
+        let base_dir = root.join("base");
+        let target_dir = root.join("target");
+        let logs = Path::new("Windows").join("System32").join("winevt").join("Logs");
+        for dir in [&base_dir, &target_dir] {
+            fs::create_dir_all(dir.join("sub")).unwrap();
+            fs::create_dir_all(dir.join(&logs)).unwrap();
+            fs::write(dir.join("keep.txt"), b"keep").unwrap();
+        }
+        fs::write(base_dir.join("outside.txt"), b"old").unwrap();
+        fs::write(target_dir.join("outside.txt"), b"new outside").unwrap();
+        fs::write(base_dir.join("sub").join("changed.txt"), b"old").unwrap();
+        fs::write(target_dir.join("sub").join("changed.txt"), b"new").unwrap();
+        fs::write(target_dir.join("sub").join("added.txt"), b"added").unwrap();
+        fs::write(base_dir.join(&logs).join("System.evtx"), b"base log").unwrap();
+        fs::write(target_dir.join(&logs).join("System.evtx"), b"target log, longer").unwrap();
+
+        let wimgapi = Wimgapi::new(None).unwrap();
+        let capture = |source: &Path, image: &Path| {
+            let handle = wimgapi
+                .open(image, WIM_GENERIC_WRITE, WIM_CREATE_ALWAYS, WIM_COMPRESS_NONE)
+                .unwrap();
+            wimgapi.set_temp_path(handle, &get_temp_path()).unwrap();
+            let image_handle = wimgapi.capture(handle, source, 0).unwrap();
+            wimgapi.close(image_handle).unwrap();
+            wimgapi.close(handle).unwrap();
+        };
+        let base_image = root.join("base.wim");
+        let target_image = root.join("target.wim");
+        capture(&base_dir, &base_image);
+        capture(&target_dir, &target_image);
+
+        let wim_patch = WimPatch::new().unwrap();
+        let cases = [
+            (
+                "subtree",
+                CreateOptions {
+                    subtree: Some("sub".to_string()),
+                    ..Default::default()
+                },
+            ),
+            (
+                "volatile",
+                CreateOptions {
+                    exclude_volatile: true,
+                    ..Default::default()
+                },
+            ),
+            (
+                "include",
+                CreateOptions {
+                    include: Some(vec!["sub".to_string()]),
+                    ..Default::default()
+                },
+            ),
+        ];
+        for (name, options) in cases {
+            let options = CreateOptions {
+                storage: Storage::Full,
+                compress: Compress::None,
+                name: name.to_string(),
+                ..options
+            };
+            let patch_image = root.join(format!("{}.wim", name));
+            wim_patch
+                .create_patch(&base_image, Some(1), &target_image, Some(1), &patch_image, &options)
+                .unwrap();
+            assert!(wim_patch.get_manifests(&patch_image).unwrap()[0].1.partial);
+
+            // 不指定 --force 也能应用，并得到部分更新的内容
+            let extract_to = root.join(format!("{}-extract", name));
+            let apply_options = ApplyOptions {
+                extract_to: Some(extract_to.clone()),
+                ..Default::default()
+            };
+            wim_patch
+                .apply_patch(
+                    &base_image,
+                    Some(1),
+                    &patch_image,
+                    &root.join(format!("{}-result.wim", name)),
+                    &apply_options,
+                )
+                .unwrap();
+            assert_eq!(fs::read(extract_to.join("sub").join("changed.txt")).unwrap(), b"new");
+            assert!(extract_to.join("sub").join("added.txt").exists());
+            // 仅排除易变文件时子目录之外的修改仍然写入补丁
+            let outside: &[u8] = if name == "volatile" { b"new outside" } else { b"old" };
+            assert_eq!(fs::read(extract_to.join("outside.txt")).unwrap(), outside);
+            assert_eq!(
+                fs::read(extract_to.join(&logs).join("System.evtx")).unwrap(),
+                b"base log"
+            );
+        }
+
+        fs::remove_dir_all(&root).ok();
+    }
+
+    /// 排除易变文件统计测试：被排除的新增、删除与修改记录为未写入补丁的差异，用于估算应用补丁后的统计信息
+    #[test]
+    fn test_exclude_volatile_dropped() {
+        let root = std::env::temp_dir().join(get_tmp_name("volatile-", "", 6));
+        let base_dir = root.join("base");
+        let target_dir = root.join("target");
+        let patch_dir = root.join("patch");
+        for dir in [&base_dir, &target_dir] {
+            fs::create_dir_all(dir.join("Windows").join("Prefetch")).unwrap();
+        }
+        fs::create_dir_all(&patch_dir).unwrap();
+        let prefetch = Path::new("Windows").join("Prefetch");
+        fs::write(base_dir.join(&prefetch).join("OLD.EXE-00000001.pf"), [1u8; 10]).unwrap();
+        fs::write(target_dir.join(&prefetch).join("NEW.EXE-00000002.pf"), [2u8; 30]).unwrap();
+        fs::write(base_dir.join("Windows").join("System.evtx"), [3u8; 100]).unwrap();
+        fs::write(target_dir.join("Windows").join("System.evtx"), [4u8; 150]).unwrap();
+        fs::write(base_dir.join("keep.txt"), b"old").unwrap();
+        fs::write(target_dir.join("keep.txt"), b"new").unwrap();
+
+        let options = CreateOptions {
+            storage: Storage::Full,
+            exclude_volatile: true,
+            ..Default::default()
+        };
+        let wim_patch = WimPatch::new().unwrap();
+        let (operations, _, dropped) = wim_patch
+            .create_operations(&base_dir, &target_dir, &patch_dir, 1, &options)
+            .unwrap();
+        assert_eq!(
+            operations.iter().map(|op| op.path.as_str()).collect::<Vec<_>>(),
+            vec!["keep.txt"]
+        );
+        // 删除的文件仍然存在，新增的文件仍不存在，修改的文件保持原大小
+        assert_eq!(
+            dropped,
+            DroppedDiffs {
+                count: 3,
+                dirs: 0,
+                files: 0,
+                bytes: 10 - 30 + (100 - 150),
+            }
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// 目录复制测试：目录联接按链接复制或跳过，不跟随到链接目标
+    #[test]
+    fn test_copy_dir_links() {
+        let root = std::env::temp_dir().join(get_tmp_name("copy-dir-", "", 6));
+        let source = root.join("source");
+        let outside = root.join("outside");
+        fs::create_dir_all(source.join("sub")).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+        fs::write(source.join("sub").join("file.txt"), b"file").unwrap();
+        fs::write(outside.join("big.bin"), [0u8; 4096]).unwrap();
+        let status = std::process::Command::new("cmd")
+            .arg("/c")
+            .arg("mklink")
+            .arg("/J")
+            .arg(source.join("link"))
+            .arg(&outside)
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success());
+
+        let target = root.join("target");
+        copy_dir(&source, &target).unwrap();
+        assert_eq!(fs::read(target.join("sub").join("file.txt")).unwrap(), b"file");
+        // 目录联接复制为链接（无法创建链接时跳过），不会复制为包含链接目标内容的普通目录
+        assert!(fs::symlink_metadata(target.join("link")).map_or(true, |metadata| metadata.file_type().is_symlink()));
+
+        fs::remove_dir_all(&root).ok();
+    }
+
+    /// 并行构建文件映射测试：多个工作线程遍历深层与多分支目录，所有条目都被收集且遍历能够结束
+    #[test]
+    fn test_build_file_map_workers() {
+        let root = std::env::temp_dir().join(get_tmp_name("filemap-", "", 6));
+        let mut expected = Vec::new();
+        for branch in 0..8 {
+            let mut dir = root.join(format!("b{branch}"));
+            let mut rel = format!("b{branch}");
+            expected.push(rel.clone());
+            for depth in 0..6 {
+                fs::create_dir_all(&dir).unwrap();
+                fs::write(dir.join("file.txt"), b"data").unwrap();
+                expected.push(format!("{rel}\\file.txt"));
+                dir = dir.join(format!("d{depth}"));
+                rel = format!("{rel}\\d{depth}");
+                if depth < 5 {
+                    expected.push(rel.clone());
+                }
+            }
+        }
+        expected.sort();
+
+        for workers in [1, 4, 16] {
+            let reparse = ReparseFollow::new(&root, 0);
+            let file_map = build_file_map(&root, &root, workers, &reparse).unwrap();
+            let mut keys: Vec<_> = file_map.into_keys().collect();
+            keys.sort();
+            assert_eq!(keys, expected);
+        }
+
+        // 起始目录不存在时返回错误而不是一直等待
+        let reparse = ReparseFollow::new(&root, 0);
+        assert!(build_file_map(&root, &root.join("missing"), 4, &reparse).is_err());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// 完整存储修改测试：暂存并应用的是修改后的文件内容（压缩与硬链接暂存两种方式）
+    #[test]
+    fn test_full_storage_modify_payload() {
+        for compress in [Compress::Lzx, Compress::None] {
+            let root = std::env::temp_dir().join(get_tmp_name("full-modify-", "", 6));
+            let base_dir = root.join("base");
+            let target_dir = root.join("target");
+            let patch_dir = root.join("patch");
+            for dir in [&base_dir, &target_dir, &patch_dir] {
+                fs::create_dir_all(dir).unwrap();
+            }
+            fs::write(base_dir.join("modify.bin"), b"old content").unwrap();
+            fs::write(target_dir.join("modify.bin"), b"new content, longer").unwrap();
+
+            let options = CreateOptions {
+                storage: Storage::Full,
+                compress,
+                name: "test-patch".to_string(),
+                ..Default::default()
+            };
+            let wim_patch = WimPatch::new().unwrap();
+            let (operations, _, _) = wim_patch
+                .create_operations(&base_dir, &target_dir, &patch_dir, 1, &options)
+                .unwrap();
+            assert_eq!(operations.len(), 1);
+            assert_eq!(operations[0].action, Action::Modify);
+            assert_eq!(fs::read(patch_dir.join("modify.bin")).unwrap(), b"new content, longer");
+
+            wim_patch
+                .apply_operations(&base_dir, &patch_dir, &operations, 1, &ApplyOptions::default())
+                .unwrap();
+            assert_eq!(fs::read(base_dir.join("modify.bin")).unwrap(), b"new content, longer");
+            // 硬链接暂存时应用不应改动目标目录中的文件
+            assert_eq!(fs::read(target_dir.join("modify.bin")).unwrap(), b"new content, longer");
+
+            fs::remove_dir_all(&root).unwrap();
+        }
+    }
+
+    /// 暂存失败测试：补丁数据无法写入补丁目录时返回错误，而不是记录引用了缺失数据的操作
+    #[test]
+    fn test_stage_payload_failure() {
+        let root = std::env::temp_dir().join(get_tmp_name("stage-fail-", "", 6));
+        let base_dir = root.join("base");
+        let target_dir = root.join("target");
+        let patch_dir = root.join("patch");
+        for dir in [&base_dir, &target_dir.join("sub"), &patch_dir] {
+            fs::create_dir_all(dir).unwrap();
+        }
+        fs::write(target_dir.join("sub").join("a.txt"), b"same").unwrap();
+        fs::write(target_dir.join("sub").join("b.txt"), b"same").unwrap();
+        // 补丁目录中与新增目录同名的文件使暂存失败
+        fs::write(patch_dir.join("sub"), b"blocker").unwrap();
+
+        let options = CreateOptions {
+            storage: Storage::Full,
+            name: "test-patch".to_string(),
+            ..Default::default()
+        };
+        let wim_patch = WimPatch::new().unwrap();
+        let error = wim_patch
+            .create_operations(&base_dir, &target_dir, &patch_dir, 1, &options)
+            .unwrap_err();
+        assert!(format!("{:#}", error).contains("Copy file failed"));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// 补丁数据去重测试：内容相同的两个新增文件只暂存一份
+    #[test]
+    fn test_payload_dedupe() {
+        let root = std::env::temp_dir().join(get_tmp_name("dedupe-", "", 6));
+        let base_dir = root.join("base");
+        let target_dir = root.join("target");
+        let patch_dir = root.join("patch");
+        fs::create_dir_all(&base_dir).unwrap();
+        fs::create_dir_all(&target_dir).unwrap();
+        fs::create_dir_all(&patch_dir).unwrap();
+        fs::write(target_dir.join("a.txt"), b"same content").unwrap();
+        fs::write(target_dir.join("b.txt"), b"same content").unwrap();
+        fs::write(target_dir.join("c.txt"), b"other content").unwrap();
+
+        let options = CreateOptions {
+            storage: Storage::Full,
+            author: String::new(),
+            name: "test-patch".to_string(),
+            ..Default::default()
+        };
+        let wim_patch = WimPatch::new().unwrap();
+        let (operations, _, _) = wim_patch
+            .create_operations(&base_dir, &target_dir, &patch_dir, 1, &options)
+            .unwrap();
+        assert_eq!(operations.len(), 3);
+
+        // 仅有一个操作引用已暂存的补丁数据
+        let refs: Vec<&Operation> = operations.iter().filter(|op| op.payload_ref.is_some()).collect();
+        assert_eq!(refs.len(), 1);
+        let payload_ref = refs[0].payload_ref.as_deref().unwrap();
+        assert_ne!(payload_ref, refs[0].path);
+        assert!(payload_ref == "a.txt" || payload_ref == "b.txt");
+        assert!(!patch_dir.join(&refs[0].path).exists());
+        assert_eq!(
+            fs::read(patch_dir.join(refs[0].payload_path())).unwrap(),
+            b"same content"
+        );
+        assert_eq!(fs::read_dir(&patch_dir).unwrap().count(), 2);
+
+        // 引用在清单序列化后保持不变
+        let manifest = test_manifest("1.0.0", "", "", &operations);
+        let parsed = PatchManifest::from_xml(&manifest.to_xml().unwrap()).unwrap();
+        assert!(
+            parsed
+                .operations
+                .iter()
+                .any(|op| op.payload_ref.as_deref() == Some(payload_ref))
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// 空文件与稀疏文件测试：修改为空的文件按完整文件存储，稀疏文件记录标记并在应用时保持稀疏
+    #[test]
+    fn test_empty_and_sparse_files() {
+        let root = std::env::temp_dir().join(get_tmp_name("sparse-", "", 6));
+        let base_dir = root.join("base");
+        let target_dir = root.join("target");
+        let patch_dir = root.join("patch");
+        let apply_dir = root.join("apply");
+        for dir in [&base_dir, &target_dir, &patch_dir, &apply_dir] {
+            fs::create_dir_all(dir).unwrap();
+        }
+        fs::write(base_dir.join("empty.txt"), b"will be emptied").unwrap();
+        fs::write(target_dir.join("empty.txt"), b"").unwrap();
+
+        // 中间带有数据的 4 MB 稀疏文件
+        let mut content = vec![0u8; 4 * 1024 * 1024];
+        content[2 * 1024 * 1024..2 * 1024 * 1024 + 5].copy_from_slice(b"hive!");
+        fs::write(root.join("hive.dat"), &content).unwrap();
+        copy_sparse(root.join("hive.dat"), target_dir.join("hive.dat")).unwrap();
+        assert!(is_sparse_file(target_dir.join("hive.dat")));
+        assert!(!is_sparse_file(root.join("hive.dat")));
+
+        let options = CreateOptions {
+            preset: Preset::Fast,
+            author: String::new(),
+            name: "test-patch".to_string(),
+            ..Default::default()
+        };
+        let wim_patch = WimPatch::new().unwrap();
+        let (operations, _, _) = wim_patch
+            .create_operations(&base_dir, &target_dir, &patch_dir, 1, &options)
+            .unwrap();
+
+        // 空文件不生成差异
+        let empty = operations.iter().find(|op| op.path == "empty.txt").unwrap();
+        assert_eq!(empty.storage.as_deref(), Some("full"));
+        assert_eq!(empty.size, Some(0));
+        assert!(!patch_dir.join("empty.txt.diff").exists());
+
+        // 稀疏文件记录标记，暂存与应用后仍为稀疏文件
+        let hive = operations.iter().find(|op| op.path == "hive.dat").unwrap();
+        assert!(hive.sparse);
+        assert!(is_sparse_file(patch_dir.join("hive.dat")));
+        fs::write(apply_dir.join("empty.txt"), b"will be emptied").unwrap();
+        wim_patch
+            .apply_operations(&apply_dir, &patch_dir, &operations, 1, &ApplyOptions::default())
+            .unwrap();
+        assert!(is_sparse_file(apply_dir.join("hive.dat")));
+        assert_eq!(fs::read(apply_dir.join("hive.dat")).unwrap(), content);
+        assert_eq!(fs::read(apply_dir.join("empty.txt")).unwrap(), b"");
+
+        // 差异还原后的文件可重新转换为稀疏文件
+        make_sparse(root.join("hive.dat")).unwrap();
+        assert!(is_sparse_file(root.join("hive.dat")));
+        assert_eq!(fs::read(root.join("hive.dat")).unwrap(), content);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// 完整文件回退测试：差异文件损坏时使用嵌入的完整文件还原
+    #[test]
+    fn test_embed_full_fallback() {
+        let root = std::env::temp_dir().join(get_tmp_name("fallback-", "", 6));
+        let base_dir = root.join("base");
+        let patch_dir = root.join("patch");
+        fs::create_dir_all(&base_dir).unwrap();
+        fs::create_dir_all(&patch_dir).unwrap();
+        fs::write(base_dir.join("file.txt"), b"old").unwrap();
+        fs::write(patch_dir.join("file.txt.diff"), b"corrupt diff").unwrap();
+        fs::write(patch_dir.join("file.txt"), b"new").unwrap();
+
+        let mut operations = vec![Operation {
+            action: Action::Modify,
+            path: "file.txt".to_string(),
+            size: Some(3),
+            storage: Some("zstd".to_string()),
+            payload_ref: None,
+            full_fallback: false,
+            sparse: false,
+            case_rename: false,
+            streams: Vec::new(),
+            dict_window: None,
+            sha256: None,
+            base_sha256: None,
+        }];
+
+        // 未嵌入完整文件时，损坏的差异文件导致应用失败
+        let wim_patch = WimPatch::new().unwrap();
+        assert!(
+            wim_patch
+                .apply_operations(&base_dir, &patch_dir, &operations, 1, &ApplyOptions::default())
+                .is_err()
+        );
+
+        // 嵌入完整文件后回退为完整文件
+        fs::write(base_dir.join("file.txt"), b"old").unwrap();
+        operations[0].full_fallback = true;
+        wim_patch
+            .apply_operations(&base_dir, &patch_dir, &operations, 1, &ApplyOptions::default())
+            .unwrap();
+        assert_eq!(fs::read(base_dir.join("file.txt")).unwrap(), b"new");
+
+        // 完整文件与其他补丁数据相同时只暂存一次，回退时使用被引用的补丁数据
+        fs::write(base_dir.join("file.txt"), b"old").unwrap();
+        fs::rename(patch_dir.join("file.txt"), patch_dir.join("other.txt")).unwrap();
+        operations[0].payload_ref = Some("other.txt".to_string());
+        wim_patch
+            .apply_operations(&base_dir, &patch_dir, &operations, 1, &ApplyOptions::default())
+            .unwrap();
+        assert_eq!(fs::read(base_dir.join("file.txt")).unwrap(), b"new");
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// 缺少存储类型的修改操作：清单可正常解析，但应用时报错而不是静默跳过
+    #[test]
+    fn test_modify_without_storage() {
+        // 由正常清单去掉 Storage 元素，模拟手工编辑或损坏的清单
+        let operations = vec![Operation {
+            action: Action::Modify,
+            path: "file.txt".to_string(),
+            size: Some(3),
+            storage: Some("full".to_string()),
+            payload_ref: None,
+            full_fallback: false,
+            sparse: false,
+            case_rename: false,
+            streams: Vec::new(),
+            dict_window: None,
+            sha256: None,
+            base_sha256: None,
+        }];
+        let xml = test_manifest("1.0.0", "", "", &operations).to_xml().unwrap();
+        assert!(xml.contains("<Storage>full</Storage>"));
+        let xml = xml.replace("<Storage>full</Storage>", "");
+
+        let manifest = PatchManifest::from_xml(&xml).unwrap();
+        assert_eq!(manifest.operations.len(), 1);
+        assert_eq!(manifest.operations[0].action, Action::Modify);
+        assert!(manifest.operations[0].storage.is_none());
+
+        let root = std::env::temp_dir().join(get_tmp_name("modify-", "", 6));
+        let base_dir = root.join("base");
+        let patch_dir = root.join("patch");
+        fs::create_dir_all(&base_dir).unwrap();
+        fs::create_dir_all(&patch_dir).unwrap();
+        fs::write(base_dir.join("file.txt"), b"old").unwrap();
+        fs::write(patch_dir.join("file.txt"), b"new").unwrap();
+
+        let wim_patch = WimPatch::new().unwrap();
+        assert!(
+            wim_patch
+                .apply_operations(&base_dir, &patch_dir, &manifest.operations, 1, &ApplyOptions::default())
+                .is_err()
+        );
+        // 强制模式下仅警告，文件保持不变
+        assert!(
+            wim_patch
+                .apply_operations(
+                    &base_dir,
+                    &patch_dir,
+                    &manifest.operations,
+                    1,
+                    &ApplyOptions {
+                        force: true,
+                        ..Default::default()
+                    }
+                )
+                .is_ok()
+        );
+        assert_eq!(fs::read(base_dir.join("file.txt")).unwrap(), b"old");
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
     pub struct WimMountHandle {
         // 挂载点路径，drop时需要卸载这个路径
         mount_path: PathBuf,
@@ -1,19 +1,39 @@
-use crate::BUFFER_SIZE;
+use crate::cli::CompareMode;
+use crate::console::{ConsoleType, write_console};
+use crate::{BUFFER_SIZE, TEMP_NAME_LENGTH};
 use anyhow::{anyhow, Result};
+use rust_i18n::t;
 use sha2::{Digest, Sha256};
-use std::collections::HashMap;
-use std::ffi::OsString;
-use std::fs::{read_dir, File};
-use std::io::{BufReader, Read};
+use std::collections::{HashMap, HashSet};
+use std::ffi::{OsString, c_void};
+use std::fs;
+use std::fs::{read_dir, File, FileTimes};
+use std::io::{BufReader, Read, Seek, SeekFrom, Write};
 use std::iter::repeat_with;
-use std::os::windows::ffi::OsStringExt;
+use std::os::windows::ffi::{OsStrExt, OsStringExt};
+use std::os::windows::fs::{FileTimesExt, FileTypeExt, MetadataExt, symlink_dir, symlink_file};
+use std::os::windows::io::AsRawHandle;
+use std::os::windows::process::CommandExt;
 use std::path::{Path, PathBuf};
-use std::sync::atomic::Ordering;
-use windows::Win32::Foundation::{CloseHandle, MAX_PATH};
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+use uuid::Uuid;
+use windows::Win32::Foundation::{CloseHandle, ERROR_HANDLE_EOF, ERROR_SHARING_VIOLATION, HANDLE, MAX_PATH};
+use windows::Win32::Security::{GetTokenInformation, TOKEN_ELEVATION, TOKEN_QUERY, TokenElevation};
+use windows::Win32::Storage::FileSystem::{
+    FILE_ATTRIBUTE_SPARSE_FILE, FILE_FLAGS_AND_ATTRIBUTES, FindClose, FindFirstStreamW, FindNextStreamW,
+    FindStreamInfoStandard, GetDiskFreeSpaceExW, SetFileAttributesW, WIN32_FIND_STREAM_DATA,
+};
 use windows::Win32::System::Diagnostics::ToolHelp::{
     CreateToolhelp32Snapshot, Process32FirstW, Process32NextW, PROCESSENTRY32W, TH32CS_SNAPPROCESS,
 };
-use windows::Win32::System::Threading::GetCurrentProcessId;
+use windows::Win32::System::IO::DeviceIoControl;
+use windows::Win32::System::Ioctl::{FILE_ZERO_DATA_INFORMATION, FSCTL_SET_SPARSE, FSCTL_SET_ZERO_DATA};
+use windows::Win32::System::Threading::{GetCurrentProcess, GetCurrentProcessId, OpenProcessToken};
+use windows::core::{GUID, PCWSTR};
 
 /// 生成临时文件名
 ///
@@ -36,6 +56,52 @@ pub fn get_tmp_name(prefix: &str, suffix: &str, rand_len: usize) -> OsString {
     buf
 }
 
+/// 在目录中生成尚不存在的临时路径
+///
+/// 随机部分的长度由 `--temp-name-length` 指定（默认 10），生成的路径已存在时重新生成，
+/// 避免多个实例共用同一临时目录时名称冲突
+///
+/// # 参数
+/// - `dir`: 所在目录
+/// - `prefix`: 前缀
+/// - `suffix`: 后缀
+///
+/// # 返回
+/// - `PathBuf` : 不存在的临时路径
+pub fn get_tmp_path(dir: &Path, prefix: &str, suffix: &str) -> PathBuf {
+    loop {
+        let path = dir.join(get_tmp_name(prefix, suffix, TEMP_NAME_LENGTH.load(Ordering::Relaxed)));
+        // 不跟随符号链接，失效的链接同样视为已存在
+        if fs::symlink_metadata(&path).is_err() {
+            return path;
+        }
+    }
+}
+
+/// 在目录中创建新的临时目录
+///
+/// 直接以 `fs::create_dir` 创建，名称已被占用（`AlreadyExists`）时换名重试，
+/// 不会删除或复用已存在的同名目录
+///
+/// # 参数
+/// - `dir`: 所在目录（不存在时先创建）
+/// - `prefix`: 前缀
+/// - `suffix`: 后缀
+///
+/// # 返回
+/// - `PathBuf` : 新创建的临时目录
+pub fn create_tmp_dir(dir: &Path, prefix: &str, suffix: &str) -> std::io::Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    loop {
+        let path = dir.join(get_tmp_name(prefix, suffix, TEMP_NAME_LENGTH.load(Ordering::Relaxed)));
+        match fs::create_dir(&path) {
+            Ok(()) => return Ok(path),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
 /// 将文件大小格式化为可读字节单位（MiB/KiB）
 ///
 /// # 参数
@@ -133,6 +199,28 @@ pub fn launched_from_explorer() -> bool {
     false
 }
 
+/// 检查当前进程是否以管理员权限（已提升）运行
+///
+/// # 返回值
+/// - `windows::core::Result<bool>`: 进程令牌已提升时为 `true`，无法查询进程令牌时返回错误
+pub fn is_elevated() -> windows::core::Result<bool> {
+    unsafe {
+        let mut token = HANDLE::default();
+        OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token)?;
+        let mut elevation = TOKEN_ELEVATION::default();
+        let mut size = 0u32;
+        let result = GetTokenInformation(
+            token,
+            TokenElevation,
+            Some(&mut elevation as *mut TOKEN_ELEVATION as *mut c_void),
+            std::mem::size_of::<TOKEN_ELEVATION>() as u32,
+            &mut size,
+        );
+        let _ = CloseHandle(token);
+        result.map(|()| elevation.TokenIsElevated != 0)
+    }
+}
+
 /// 计算文件的 SHA256 哈希值
 /// # 参数
 /// - `path`: 文件路径
@@ -189,12 +277,36 @@ fn get_file_metadata(path: impl AsRef<Path>) -> Option<(u64, u64)> {
 /// # 参数
 /// - `one`: 第一个文件路径
 /// - `another`: 第二个文件路径
+/// - `mode`: 比较方式
+///   - `Meta`: 仅比较大小和修改时间，最快，但会漏掉大小和修改时间均未变化的修改
+///   - `Bytes`: 元数据相同时再逐字节比较，两个文件都需完整读取
+///   - `Hash`: 大小相同时比较 SHA-256，忽略修改时间，每个文件只读取一遍
 /// # 返回值
 /// - `true`: 文件相同
 /// - `false`: 文件不相同
-fn is_same_file(one: impl AsRef<Path>, another: impl AsRef<Path>) -> bool {
+pub fn is_same_file(one: impl AsRef<Path>, another: impl AsRef<Path>, mode: CompareMode) -> bool {
+    let metadata = (get_file_metadata(&one), get_file_metadata(&another));
+    match mode {
+        CompareMode::Meta => {
+            return matches!(metadata, (Some(meta1), Some(meta2)) if meta1 == meta2);
+        }
+        CompareMode::Hash => {
+            // 大小不同时无需计算哈希
+            if let (Some((size1, _)), Some((size2, _))) = metadata
+                && size1 != size2
+            {
+                return false;
+            }
+            return match (get_file_sha256(&one, None), get_file_sha256(&another, None)) {
+                (Ok(hash1), Ok(hash2)) => hash1 == hash2,
+                _ => false,
+            };
+        }
+        CompareMode::Bytes => {}
+    }
+
     // 先比较文件元数据（大小和修改时间）
-    if let (Some((size1, mtime1)), Some((size2, mtime2))) = (get_file_metadata(&one), get_file_metadata(&another)) {
+    if let (Some((size1, mtime1)), Some((size2, mtime2))) = metadata {
         // 如果大小或修改时间不同，直接返回false，避免二进制对比
         if size1 != size2 || mtime1 != mtime2 {
             return false;
@@ -247,49 +359,194 @@ pub enum DiffType {
 /// - `false`: 中断比较
 pub type DiffCallback<'a> = dyn FnMut(DiffType, Option<&'a Path>, Option<&'a Path>, &'a str) -> bool;
 
-/// 对比两个目录的差异（带回调函数）
+/// 目录比较选项
+#[derive(Debug, Clone, Copy)]
+pub struct CompareOptions<'a> {
+    /// 相对于根目录的子目录（空路径表示整个目录）
+    pub subtree: &'a Path,
+    /// 修改文件的比较方式
+    pub mode: CompareMode,
+    /// 按不区分大小写的方式匹配路径，仅大小写不同的文件报告为修改（相对路径使用目标目录中的大小写）
+    pub ignore_case: bool,
+    /// 跟随目录重解析点（联接、符号链接）的最大嵌套层数，0 表示不跟随（重解析点作为条目记录）
+    pub follow_reparse_depth: u32,
+    /// 收集两个目录中都存在且内容相同的文件，用于诊断比较结果
+    pub report_unchanged: bool,
+}
+
+impl Default for CompareOptions<'_> {
+    fn default() -> Self {
+        Self {
+            subtree: Path::new(""),
+            mode: CompareMode::Bytes,
+            ignore_case: false,
+            follow_reparse_depth: 0,
+            report_unchanged: false,
+        }
+    }
+}
+
+/// 对比两个目录的差异（带回调函数，逐字节比较修改的文件）
+/// # 参数
+/// - `base_dir`: 基准目录路径
+/// - `target_dir`: 目标目录路径
+/// - `callback`: 差异回调函数，返回false可中断比较
+/// # 返回值
+/// - `Result<(), String>`: 比较结果，成功返回Ok(())，失败返回对应的错误信息
+pub fn compare_directories<F>(base_dir: impl AsRef<Path>, target_dir: impl AsRef<Path>, callback: F) -> Result<()>
+where
+    F: FnMut(DiffType, Option<&Path>, Option<&Path>, &str) -> bool,
+{
+    compare_directories_with_total(base_dir, target_dir, CompareMode::Bytes, |_, _| {}, callback)
+}
+
+/// 对比两个目录的差异，并在第一次差异回调前报告差异总量（用于显示确定的进度）
 /// # 参数
 /// - `base_dir`: 基准目录路径
 /// - `target_dir`: 目标目录路径
+/// - `mode`: 修改文件的比较方式
+/// - `on_total`: 差异总量回调，参数为（差异数量, 新增和修改文件的总字节数）
 /// - `callback`: 差异回调函数，返回false可中断比较
 /// # 返回值
 /// - `Result<(), String>`: 比较结果，成功返回Ok(())，失败返回对应的错误信息
-pub fn compare_directories<F>(base_dir: impl AsRef<Path>, target_dir: impl AsRef<Path>, mut callback: F) -> Result<()>
+pub fn compare_directories_with_total<T, F>(
+    base_dir: impl AsRef<Path>,
+    target_dir: impl AsRef<Path>,
+    mode: CompareMode,
+    on_total: T,
+    callback: F,
+) -> Result<()>
+where
+    T: FnOnce(usize, u64),
+    F: FnMut(DiffType, Option<&Path>, Option<&Path>, &str) -> bool,
+{
+    let options = CompareOptions {
+        mode,
+        ..Default::default()
+    };
+    compare_subtree_with_total(base_dir, target_dir, &options, on_total, callback).map(|_| ())
+}
+
+/// 按比较选项对比两个目录（或其中指定子目录）的差异，回调中的相对路径仍相对于 `base_dir` / `target_dir` 根目录
+/// # 参数
+/// - `base_dir`: 基准目录路径
+/// - `target_dir`: 目标目录路径
+/// - `options`: 比较选项
+/// - `on_total`: 差异总量回调，参数为（差异数量, 新增和修改文件的总字节数）
+/// - `callback`: 差异回调函数，返回false可中断比较
+/// # 返回值
+/// - `Ok(Vec<String>)`: 比较完成，指定 `report_unchanged` 时为按路径排序的未变化文件（否则为空）
+/// - `Err(anyhow::Error)`: 读取目录失败或比较被回调中断
+pub fn compare_subtree_with_total<T, F>(
+    base_dir: impl AsRef<Path>,
+    target_dir: impl AsRef<Path>,
+    options: &CompareOptions,
+    on_total: T,
+    mut callback: F,
+) -> Result<Vec<String>>
 where
+    T: FnOnce(usize, u64),
     F: FnMut(DiffType, Option<&Path>, Option<&Path>, &str) -> bool,
 {
+    let CompareOptions {
+        subtree,
+        mode,
+        ignore_case,
+        follow_reparse_depth,
+        report_unchanged,
+    } = *options;
     let base_dir = base_dir.as_ref();
     let target_dir = target_dir.as_ref();
+    let base_start = base_dir.join(subtree);
+    let target_start = target_dir.join(subtree);
 
     // 检查目录是否存在
-    if !base_dir.exists() {
-        return Err(anyhow!("Base directory does not exist: {}", base_dir.display()));
+    if !base_start.exists() {
+        return Err(anyhow!("Base directory does not exist: {}", base_start.display()));
     }
-    if !target_dir.exists() {
-        return Err(anyhow!("Target directory does not exist: {}", target_dir.display()));
+    if !target_start.exists() {
+        return Err(anyhow!("Target directory does not exist: {}", target_start.display()));
     }
 
-    if !base_dir.is_dir() {
-        return Err(anyhow!("Base path is not a directory: {}", base_dir.display()));
+    if !base_start.is_dir() {
+        return Err(anyhow!("Base path is not a directory: {}", base_start.display()));
     }
-    if !target_dir.is_dir() {
-        return Err(anyhow!("Target path is not a directory: {}", target_dir.display()));
+    if !target_start.is_dir() {
+        return Err(anyhow!("Target path is not a directory: {}", target_start.display()));
     }
 
-    // 构建文件映射
-    let mut base_files = HashMap::new();
-    if let Err(err) = build_file_map(base_dir, base_dir, &mut base_files) {
-        return Err(anyhow!("Failed to read base directory: {}", err));
-    }
+    // 并行构建文件映射
+    let workers = worker_count();
+    let base_reparse = ReparseFollow::new(base_dir, follow_reparse_depth);
+    let target_reparse = ReparseFollow::new(target_dir, follow_reparse_depth);
+    let (base_files, target_files) = thread::scope(|scope| {
+        let base_worker = scope.spawn(|| build_file_map(base_dir, &base_start, workers, &base_reparse));
+        let target_files = build_file_map(target_dir, &target_start, workers, &target_reparse);
+        let base_files = base_worker
+            .join()
+            .unwrap_or_else(|_| Err(std::io::Error::other("Worker panicked")));
+        (base_files, target_files)
+    });
+    let base_files = base_files.map_err(|err| anyhow!("Failed to read base directory: {}", err))?;
+    let target_files = target_files.map_err(|err| anyhow!("Failed to read target directory: {}", err))?;
 
-    let mut target_files = HashMap::new();
-    if let Err(err) = build_file_map(target_dir, target_dir, &mut target_files) {
-        return Err(anyhow!("Failed to read target directory: {}", err));
+    // 未跟随的重解析点（形成循环、指向目录外或超出层数）作为条目记录，输出警告
+    for (rel_path, skip) in base_reparse.skipped().into_iter().chain(target_reparse.skipped()) {
+        let path = format!("\\{}", rel_path);
+        let message = match skip {
+            ReparseSkip::Cycle => t!("compare.reparse_cycle", path = path),
+            ReparseSkip::Outside => t!("compare.reparse_outside", path = path),
+            ReparseSkip::TooDeep => t!("compare.reparse_too_deep", path = path, depth = follow_reparse_depth),
+        };
+        write_console(ConsoleType::Warning, &message);
     }
 
+    // 忽略大小写时以小写路径作为键，值中保留原始大小写的相对路径
+    let key_files = |files: HashMap<String, PathBuf>| -> HashMap<String, (String, PathBuf)> {
+        files
+            .into_iter()
+            .map(|(rel_path, path)| {
+                let key = if ignore_case {
+                    rel_path.to_lowercase()
+                } else {
+                    rel_path.clone()
+                };
+                (key, (rel_path, path))
+            })
+            .collect()
+    };
+    let base_files = key_files(base_files);
+    let target_files = key_files(target_files);
+
+    // 并行检测修改的文件
+    let candidates: Vec<(&String, &PathBuf, &PathBuf)> = target_files
+        .iter()
+        .filter_map(|(key, (_, target_path))| base_files.get(key).map(|(_, base_path)| (key, base_path, target_path)))
+        .collect();
+    let modified = find_modified_files(&candidates, workers, mode)?;
+
+    // 名称仅大小写不同的文件或目录即使内容相同也报告为修改，以便应用时恢复目标目录中的大小写
+    // （只比较最后一级名称，目录改变大小写时其中未变化的文件不重复报告）
+    let is_modified = |key: &String, target_rel: &String| {
+        modified.contains(key.as_str())
+            || base_files
+                .get(key)
+                .is_some_and(|(base_rel, _)| Path::new(base_rel).file_name() != Path::new(target_rel).file_name())
+    };
+
+    // 复用文件映射统计差异总量，无需再次遍历目录
+    let deleted_count = base_files.keys().filter(|key| !target_files.contains_key(*key)).count();
+    let (changed_count, changed_bytes) = target_files
+        .iter()
+        .filter(|(key, (target_rel, _))| !base_files.contains_key(*key) || is_modified(key, target_rel))
+        .fold((0, 0), |(count, bytes), (_, (_, target_path))| {
+            (count + 1, bytes + target_path.metadata().map(|m| m.len()).unwrap_or(0))
+        });
+    on_total(deleted_count + changed_count, changed_bytes);
+
     // 检查基准目录中有但目标目录中没有的文件（删除）
-    for (rel_path, base_path) in &base_files {
-        if !target_files.contains_key(rel_path) {
+    for (key, (rel_path, base_path)) in &base_files {
+        if !target_files.contains_key(key) {
             // 调用回调函数，如果返回false则中断比较
             if !callback(DiffType::Delete, Some(base_path), None, rel_path) {
                 return Err(anyhow!("Comparison interrupted by callback"));
@@ -298,28 +555,365 @@ where
     }
 
     // 检查目标目录中有但基准目录中没有的文件（新增）或有变化的文件（修改）
-    for (rel_path, target_path) in &target_files {
-        if !base_files.contains_key(rel_path) {
+    let mut unchanged = Vec::new();
+    for (key, (rel_path, target_path)) in &target_files {
+        if !base_files.contains_key(key) {
             // 调用回调函数，如果返回false则中断比较
             if !callback(DiffType::Add, None, Some(target_path), rel_path) {
                 return Err(anyhow!("Comparison interrupted by callback"));
             }
-        } else {
-            let base_path = &base_files[rel_path];
-            if base_path.is_file() && target_path.is_file() && !is_same_file(base_path, target_path) {
-                // 调用回调函数，如果返回false则中断比较
-                if !callback(DiffType::Modify, Some(base_path), Some(target_path), rel_path) {
-                    return Err(anyhow!("Comparison interrupted by callback"));
-                }
+        } else if is_modified(key, rel_path) {
+            let (_, base_path) = &base_files[key];
+            // 调用回调函数，如果返回false则中断比较
+            if !callback(DiffType::Modify, Some(base_path), Some(target_path), rel_path) {
+                return Err(anyhow!("Comparison interrupted by callback"));
             }
+        } else if report_unchanged && target_path.is_file() && base_files[key].1.is_file() {
+            unchanged.push(rel_path.clone());
         }
     }
 
-    Ok(())
+    // 文件映射按哈希顺序遍历，排序后输出顺序稳定
+    unchanged.sort();
+    Ok(unchanged)
+}
+
+/// 获取并行处理的工作线程数量
+pub fn worker_count() -> usize {
+    thread::available_parallelism().map(|count| count.get()).unwrap_or(4)
+}
+
+/// 以最多 `concurrency` 个线程并行处理各项，任一项失败后不再开始新的项
+///
+/// # 参数
+/// - `items`: 待处理的项（按顺序领取）
+/// - `concurrency`: 同时处理的最大数量（0 视为 1）
+/// - `process`: 处理函数
+///
+/// # 返回值
+/// - `Ok(())`: 所有项均处理成功
+/// - `Err(anyhow::Error)`: 按顺序第一个失败项的错误
+pub fn for_each_bounded<T: Sync>(
+    items: &[T],
+    concurrency: usize,
+    process: impl Fn(&T) -> Result<()> + Sync,
+) -> Result<()> {
+    let next = AtomicUsize::new(0);
+    let failed = AtomicBool::new(false);
+    let mut errors: Vec<(usize, anyhow::Error)> = thread::scope(|scope| {
+        let handles: Vec<_> = (0..concurrency.clamp(1, items.len().max(1)))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut errors = Vec::new();
+                    while !failed.load(Ordering::Acquire) {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(item) = items.get(index) else {
+                            break;
+                        };
+                        if let Err(err) = process(item) {
+                            failed.store(true, Ordering::Release);
+                            errors.push((index, err));
+                        }
+                    }
+                    errors
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| {
+                handle
+                    .join()
+                    .unwrap_or_else(|_| vec![(usize::MAX, anyhow!("Worker panicked"))])
+            })
+            .collect()
+    });
+    errors.sort_by_key(|(index, _)| *index);
+    match errors.into_iter().next() {
+        Some((_, err)) => Err(err),
+        None => Ok(()),
+    }
+}
+
+/// 并行检测内容发生变化的文件
+///
+/// # 参数
+/// - `candidates`: 两个目录中都存在的路径（相对路径、基准路径、目标路径），目录会被跳过
+/// - `workers`: 工作线程数量
+/// - `mode`: 比较方式
+///
+/// # 返回值
+/// - `Ok(HashSet<&str>)`: 内容发生变化的文件的相对路径
+/// - `Err(anyhow::Error)`: 工作线程崩溃
+fn find_modified_files<'a>(
+    candidates: &[(&'a String, &PathBuf, &PathBuf)],
+    workers: usize,
+    mode: CompareMode,
+) -> Result<HashSet<&'a str>> {
+    let next = AtomicUsize::new(0);
+    thread::scope(|scope| {
+        let handles: Vec<_> = (0..workers.max(1))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut modified = Vec::new();
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let Some((rel_path, base_path, target_path)) = candidates.get(index) else {
+                            break;
+                        };
+                        if base_path.is_file() && target_path.is_file() && !is_same_file(base_path, target_path, mode) {
+                            modified.push(rel_path.as_str());
+                        }
+                    }
+                    modified
+                })
+            })
+            .collect();
+        let mut modified = HashSet::new();
+        for handle in handles {
+            modified.extend(handle.join().map_err(|_| anyhow!("Worker panicked"))?);
+        }
+        Ok(modified)
+    })
+}
+
+/// 未跟随目录重解析点的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum ReparseSkip {
+    /// 指向自身的上级目录或已跟随过的目录，跟随会形成循环
+    Cycle,
+    /// 无法解析，或指向根目录之外（如挂载镜像中指向系统盘的绝对路径）
+    Outside,
+    /// 超出允许跟随的最大嵌套层数
+    TooDeep,
+}
+
+/// 构建文件映射时跟随目录重解析点（联接、符号链接）的状态
+pub(crate) struct ReparseFollow {
+    /// 规范化的根目录，重解析点的目标必须位于其中
+    root: Option<PathBuf>,
+    /// 最大嵌套层数，0 表示不跟随
+    max_depth: u32,
+    /// 已跟随的重解析点目标（规范化路径）
+    visited: Mutex<HashSet<PathBuf>>,
+    /// 未跟随的重解析点（相对路径与原因）
+    skipped: Mutex<Vec<(String, ReparseSkip)>>,
+}
+
+impl ReparseFollow {
+    /// 创建跟随状态
+    ///
+    /// # 参数
+    /// - `root_dir`: 根目录
+    /// - `max_depth`: 最大嵌套层数，0 表示不跟随
+    pub(crate) fn new(root_dir: &Path, max_depth: u32) -> Self {
+        Self {
+            root: (max_depth > 0).then(|| fs::canonicalize(root_dir).ok()).flatten(),
+            max_depth,
+            visited: Mutex::new(HashSet::new()),
+            skipped: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// 判断是否跟随目录重解析点
+    ///
+    /// # 参数
+    /// - `link`: 重解析点路径
+    /// - `depth`: 到达该重解析点时已跟随的层数
+    ///
+    /// # 返回值
+    /// - `Ok(())`: 跟随，目标已登记为已跟随
+    /// - `Err(ReparseSkip)`: 不跟随的原因
+    pub(crate) fn check(&self, link: &Path, depth: u32) -> std::result::Result<(), ReparseSkip> {
+        if depth >= self.max_depth {
+            return Err(ReparseSkip::TooDeep);
+        }
+        let root = self.root.as_ref().ok_or(ReparseSkip::Outside)?;
+        let target = fs::canonicalize(link).map_err(|_| ReparseSkip::Outside)?;
+        if !target.starts_with(root) {
+            return Err(ReparseSkip::Outside);
+        }
+        // 目标为重解析点自身的上级目录时，跟随会无限嵌套
+        let parent = link.parent().and_then(|parent| fs::canonicalize(parent).ok());
+        if parent.is_some_and(|parent| parent.starts_with(&target)) {
+            return Err(ReparseSkip::Cycle);
+        }
+        // 每个目标只跟随一次，多个重解析点互相指向时同样能够结束
+        let mut visited = self.visited.lock().map_err(|_| ReparseSkip::Cycle)?;
+        if !visited.insert(target) {
+            return Err(ReparseSkip::Cycle);
+        }
+        Ok(())
+    }
+
+    /// 登记未跟随的重解析点
+    fn skip(&self, rel_path: &str, reason: ReparseSkip) {
+        if let Ok(mut skipped) = self.skipped.lock() {
+            skipped.push((rel_path.to_string(), reason));
+        }
+    }
+
+    /// 获取未跟随的重解析点（按相对路径排序）
+    pub(crate) fn skipped(&self) -> Vec<(String, ReparseSkip)> {
+        let mut skipped = self.skipped.lock().map(|skipped| skipped.clone()).unwrap_or_default();
+        skipped.sort();
+        skipped
+    }
+}
+
+/// 构建文件映射时的待处理目录队列
+struct DirQueue {
+    state: Mutex<DirQueueState>,
+    /// 有新目录入队、全部目录处理完成或有线程崩溃时通知等待的线程
+    ready: Condvar,
 }
 
-/// 构建文件映射，键为相对于根目录的路径，值为完整路径
-fn build_file_map(root_dir: &Path, current_dir: &Path, file_map: &mut HashMap<String, PathBuf>) -> std::io::Result<()> {
+/// 待处理目录队列的状态
+struct DirQueueState {
+    /// 待处理的目录与到达时已跟随的重解析点层数
+    dirs: Vec<(PathBuf, u32)>,
+    /// 已入队但尚未处理完成的目录数量
+    pending: usize,
+    /// 有工作线程崩溃，其余线程不再等待
+    aborted: bool,
+}
+
+impl DirQueue {
+    /// 创建只包含起始目录的队列
+    fn new(start_dir: &Path) -> Self {
+        Self {
+            state: Mutex::new(DirQueueState {
+                dirs: vec![(start_dir.to_path_buf(), 0)],
+                pending: 1,
+                aborted: false,
+            }),
+            ready: Condvar::new(),
+        }
+    }
+
+    /// 锁定队列状态
+    fn lock(&self) -> std::io::Result<MutexGuard<'_, DirQueueState>> {
+        self.state
+            .lock()
+            .map_err(|_| std::io::Error::other("Directory queue poisoned"))
+    }
+
+    /// 加入待处理目录
+    fn push(&self, dir: PathBuf, depth: u32) -> std::io::Result<()> {
+        let mut state = self.lock()?;
+        state.dirs.push((dir, depth));
+        state.pending += 1;
+        self.ready.notify_one();
+        Ok(())
+    }
+
+    /// 取出待处理目录，队列为空时等待，全部目录处理完成（或有线程崩溃）时返回 `None`
+    fn pop(&self) -> std::io::Result<Option<(PathBuf, u32)>> {
+        let mut state = self.lock()?;
+        loop {
+            if state.aborted {
+                return Ok(None);
+            }
+            if let Some(item) = state.dirs.pop() {
+                return Ok(Some(item));
+            }
+            if state.pending == 0 {
+                return Ok(None);
+            }
+            state = self
+                .ready
+                .wait(state)
+                .map_err(|_| std::io::Error::other("Directory queue poisoned"))?;
+        }
+    }
+
+    /// 标记一个目录处理完成，最后一个目录完成时唤醒所有等待的线程
+    fn done(&self) -> std::io::Result<()> {
+        let mut state = self.lock()?;
+        state.pending -= 1;
+        if state.pending == 0 {
+            self.ready.notify_all();
+        }
+        Ok(())
+    }
+}
+
+/// 工作线程崩溃时标记队列终止，避免其余线程一直等待
+struct DirQueueAbort<'a>(&'a DirQueue);
+
+impl Drop for DirQueueAbort<'_> {
+    fn drop(&mut self) {
+        if !thread::panicking() {
+            return;
+        }
+        if let Ok(mut state) = self.0.state.lock() {
+            state.aborted = true;
+        }
+        self.0.ready.notify_all();
+    }
+}
+
+/// 并行构建文件映射，键为相对于根目录的路径，值为完整路径
+///
+/// # 参数
+/// - `root_dir`: 根目录（相对路径的起点）
+/// - `start_dir`: 开始遍历的目录（`root_dir` 或其子目录）
+/// - `workers`: 工作线程数量
+/// - `reparse`: 目录重解析点的跟随状态（不跟随时重解析点作为条目记录，不进入其中）
+///
+/// # 返回值
+/// - `Ok(HashMap<String, PathBuf>)`: 文件映射
+/// - `Err(std::io::Error)`: 读取目录失败或工作线程崩溃
+pub(crate) fn build_file_map(
+    root_dir: &Path,
+    start_dir: &Path,
+    workers: usize,
+    reparse: &ReparseFollow,
+) -> std::io::Result<HashMap<String, PathBuf>> {
+    let queue = DirQueue::new(start_dir);
+    let error = Mutex::new(None);
+
+    let file_map = thread::scope(|scope| {
+        let handles: Vec<_> = (0..workers.max(1))
+            .map(|_| {
+                scope.spawn(|| -> std::io::Result<HashMap<String, PathBuf>> {
+                    let _abort = DirQueueAbort(&queue);
+                    let mut file_map = HashMap::new();
+                    while let Some((current_dir, depth)) = queue.pop()? {
+                        if let Err(err) =
+                            read_dir_entries(root_dir, &current_dir, depth, &mut file_map, &queue, reparse)
+                            && let Ok(mut error) = error.lock()
+                        {
+                            error.get_or_insert(err);
+                        }
+                        queue.done()?;
+                    }
+                    Ok(file_map)
+                })
+            })
+            .collect();
+        let mut file_map = HashMap::new();
+        for handle in handles {
+            file_map.extend(handle.join().map_err(|_| std::io::Error::other("Worker panicked"))??);
+        }
+        Ok(file_map)
+    })?;
+
+    match error.into_inner().ok().flatten() {
+        Some(err) => Err(err),
+        None => Ok(file_map),
+    }
+}
+
+/// 读取单个目录的条目，子目录（及允许跟随的目录重解析点）加入待处理队列
+fn read_dir_entries(
+    root_dir: &Path,
+    current_dir: &Path,
+    depth: u32,
+    file_map: &mut HashMap<String, PathBuf>,
+    queue: &DirQueue,
+    reparse: &ReparseFollow,
+) -> std::io::Result<()> {
     for entry in read_dir(current_dir)? {
         let entry = entry?;
 
@@ -331,40 +925,145 @@ fn build_file_map(root_dir: &Path, current_dir: &Path, file_map: &mut HashMap<St
             .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidData, "Failed to convert path to string"))?
             .to_string();
 
-        file_map.insert(rel_path.clone(), path.clone());
+        // 如果是目录，加入队列由空闲线程处理；目录重解析点（file_type 不跟随，不视为目录）仅在允许时跟随
+        let file_type = entry.file_type()?;
+        let next_depth = if file_type.is_dir() {
+            Some(depth)
+        } else if reparse.max_depth > 0 && file_type.is_symlink() && path.is_dir() {
+            match reparse.check(&path, depth) {
+                Ok(()) => Some(depth + 1),
+                Err(reason) => {
+                    reparse.skip(&rel_path, reason);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        file_map.insert(rel_path, path.clone());
 
-        // 如果是目录，递归处理
-        if entry.file_type()?.is_dir() {
-            build_file_map(root_dir, &path, file_map)?;
+        if let Some(next_depth) = next_depth {
+            queue.push(path, next_depth)?;
         }
     }
 
     Ok(())
 }
 
+/// 查找XML中指定字段的值所在的范围
+///
+/// # 参数
+/// - `xml`: 输入的XML字符串
+/// - `field_name`: 字段名，嵌套字段以 `/` 分隔，如 `CREATIONTIME/HIGHPART`
+///
+/// # 返回值
+/// - `Some((start, end))`: 字段值（开始标签与结束标签之间的内容）的字节范围
+/// - `None`: 没有找到字段
+fn find_xml_field(xml: &str, field_name: &str) -> Option<(usize, usize)> {
+    let (mut start, mut end) = (0, xml.len());
+    for name in field_name.split('/') {
+        let start_tag = format!("<{name}>");
+        let end_tag = format!("</{name}>");
+        let start_pos = start + xml[start..end].find(&start_tag)? + start_tag.len();
+        let end_pos = start_pos + xml[start_pos..end].find(&end_tag)?;
+        (start, end) = (start_pos, end_pos);
+    }
+    Some((start, end))
+}
+
+/// 使用通配符匹配字符串（不区分大小写），`*` 匹配任意数量的任意字符，`?` 匹配单个字符
+///
+/// # 参数
+/// - `pattern`: 通配符模式
+/// - `text`: 待匹配的字符串
+///
+/// # 返回值
+/// - `true`: 匹配
+/// - `false`: 不匹配
+pub fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // 上一个 `*` 的位置，以及它当前匹配到的文本位置
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = star {
+            // 回溯：让 `*` 多匹配一个字符
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// 合并命令行指定的路径列表与列表文件中的路径
+///
+/// # 参数
+/// - `items`: 命令行指定的路径列表
+/// - `file`: 路径列表文件，每行一项，忽略空行与 `#` 开头的注释行
+///
+/// # 返回值
+/// - `Ok(Some(Vec<String>))`: 合并后的路径列表
+/// - `Ok(None)`: 均未指定
+/// - `Err`: 读取列表文件失败，或列表文件与命令行均未提供任何路径
+pub fn merge_pattern_file(items: Option<Vec<String>>, file: Option<&Path>) -> Result<Option<Vec<String>>> {
+    let Some(file) = file else {
+        return Ok(items);
+    };
+    let text = fs::read_to_string(file).map_err(|e| anyhow!("Read list file {} failed: {}", file.display(), e))?;
+    let mut items = items.unwrap_or_default();
+    items.extend(
+        text.trim_start_matches('\u{feff}')
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(String::from),
+    );
+    // 空列表会被视为不限制，指定了列表文件却没有任何路径时直接报错
+    if items.is_empty() {
+        return Err(anyhow!("List file {} contains no entries", file.display()));
+    }
+    Ok(Some(items))
+}
+
+/// 获取XML中指定字段的值
+///
+/// # 参数
+/// - `xml`: 输入的XML字符串
+/// - `field_name`: 字段名，嵌套字段以 `/` 分隔，如 `CREATIONTIME/HIGHPART`
+///
+/// # 返回值
+/// - `Some(&str)`: 字段的值
+/// - `None`: 没有找到字段
+pub fn get_xml_field<'a>(xml: &'a str, field_name: &str) -> Option<&'a str> {
+    find_xml_field(xml, field_name).map(|(start, end)| &xml[start..end])
+}
+
 /// 替换XML中指定字段的值，不依赖字段的当前值
 ///
 /// # 参数
 /// - `xml`: 输入的XML字符串
-/// - `field_name`: 要替换的字段名
+/// - `field_name`: 要替换的字段名，嵌套字段以 `/` 分隔，如 `CREATIONTIME/HIGHPART`
 /// - `value`: 新的值
 ///
 /// # 返回值
 /// - `String`: 替换后的XML字符串
 pub fn replace_xml_field(xml: &str, field_name: &str, value: &str) -> String {
-    let start_tag = format!("<{field_name}>");
-    let end_tag = format!("</{field_name}>");
-
-    if let Some(start_pos) = xml.find(&start_tag)
-        && let Some(end_pos) = xml[start_pos + start_tag.len()..].find(&end_tag)
-    {
-        let total_start = start_pos + start_tag.len();
-        let total_end = total_start + end_pos;
-
+    if let Some((start, end)) = find_xml_field(xml, field_name) {
         let mut result = String::with_capacity(xml.len());
-        result.push_str(&xml[..total_start]);
+        result.push_str(&xml[..start]);
         result.push_str(value);
-        result.push_str(&xml[total_end..]);
+        result.push_str(&xml[end..]);
 
         return result;
     }
@@ -372,3 +1071,593 @@ pub fn replace_xml_field(xml: &str, field_name: &str, value: &str) -> String {
     // 如果没有找到字段，返回原始XML
     xml.to_string()
 }
+
+/// 将 GUID 字符串规范化为与 [`format_guid`] 相同的格式（大写、带连字符与花括号）
+///
+/// # 参数
+/// - `guid`: GUID 字符串，支持 `{XXXXXXXX-...}`、`XXXXXXXX-...` 及无连字符等格式，不区分大小写
+///
+/// # 返回值
+/// - `Some(String)`: 规范化后的 GUID 字符串
+/// - `None`: 不是有效的 GUID
+pub fn normalize_guid(guid: &str) -> Option<String> {
+    Uuid::parse_str(guid.trim()).ok().map(format_uuid)
+}
+
+/// 将 GUID 格式化为带花括号的标准 UUID 字符串，如 `{3F2504E0-4F89-11D3-9A0C-0305E82C3301}`
+///
+/// # 参数
+/// - `guid`: GUID 结构体
+///
+/// # 返回值
+/// - `String`: 格式化后的 GUID 字符串
+pub fn format_guid(guid: &GUID) -> String {
+    format_uuid(Uuid::from_fields(guid.data1, guid.data2, guid.data3, &guid.data4))
+}
+
+/// 将 UUID 格式化为补丁清单中使用的 GUID 字符串（大写、带花括号）
+fn format_uuid(uuid: Uuid) -> String {
+    format!("{:X}", uuid.braced())
+}
+
+/// 判断两个 GUID 字符串是否表示同一个 GUID（忽略大小写、花括号等格式差异）
+///
+/// # 参数
+/// - `one`: 第一个 GUID 字符串
+/// - `another`: 第二个 GUID 字符串
+///
+/// # 返回值
+/// - `true`: 表示同一个 GUID
+/// - `false`: 不同或无法解析
+pub fn is_same_guid(one: &str, another: &str) -> bool {
+    match (normalize_guid(one), normalize_guid(another)) {
+        (Some(one), Some(another)) => one == another,
+        _ => one == another,
+    }
+}
+
+/// 获取路径所在卷中调用者可用的剩余空间
+///
+/// # 参数
+/// - `path`: 卷中已存在的目录路径
+///
+/// # 返回值
+/// - `Ok(u64)`: 可用字节数
+/// - `Err(anyhow::Error)`: 获取失败
+pub fn get_free_space(path: impl AsRef<Path>) -> Result<u64> {
+    let wide: Vec<u16> = path.as_ref().as_os_str().encode_wide().chain(Some(0)).collect();
+    let mut free_bytes = 0u64;
+    unsafe { GetDiskFreeSpaceExW(PCWSTR(wide.as_ptr()), Some(&mut free_bytes as *mut u64), None, None) }
+        .map_err(|e| anyhow!("Get disk free space failed: {}", e))?;
+    Ok(free_bytes)
+}
+
+/// 创建硬链接，失败时（如跨卷或目标文件系统不支持）回退为复制文件，同时得到文件的 SHA-256
+///
+/// # 参数
+/// - `source`: 源文件路径
+/// - `target`: 目标文件路径
+///
+/// # 返回值
+/// - `std::io::Result<String>`: 源文件的 SHA-256 哈希值（复制时在同一遍读取中计算）
+pub fn link_or_copy(source: impl AsRef<Path>, target: impl AsRef<Path>) -> std::io::Result<String> {
+    if fs::hard_link(&source, &target).is_ok() {
+        // 硬链接不读取文件内容，单独计算哈希
+        return get_file_sha256(source, None).map_err(std::io::Error::other);
+    }
+    copy_hashed(source, target)
+}
+
+/// 递归复制目录，稀疏文件按稀疏方式复制
+///
+/// 符号链接与目录联接按链接复制（目录联接复制为目录符号链接），不跟随到链接目标；无法创建链接时跳过并输出警告
+///
+/// # 参数
+/// - `source`: 源目录路径
+/// - `target`: 目标目录路径（不存在时创建）
+///
+/// # 返回值
+/// - `std::io::Result<()>`: 操作结果
+pub fn copy_dir(source: impl AsRef<Path>, target: impl AsRef<Path>) -> std::io::Result<()> {
+    let mut dirs = vec![(source.as_ref().to_path_buf(), target.as_ref().to_path_buf())];
+    while let Some((source_dir, target_dir)) = dirs.pop() {
+        fs::create_dir_all(&target_dir)?;
+        for entry in read_dir(&source_dir)? {
+            let entry = entry?;
+            let source_path = entry.path();
+            let target_path = target_dir.join(entry.file_name());
+            let file_type = entry.file_type()?;
+            if file_type.is_symlink() {
+                let link = fs::read_link(&source_path).and_then(|link| {
+                    if file_type.is_symlink_dir() {
+                        symlink_dir(&link, &target_path)
+                    } else {
+                        symlink_file(&link, &target_path)
+                    }
+                });
+                if let Err(e) = link {
+                    write_console(
+                        ConsoleType::Warning,
+                        &t!("apply_patch.link_skipped", path = source_path.display(), error = e),
+                    );
+                }
+            } else if file_type.is_dir() {
+                dirs.push((source_path, target_path));
+            } else if is_sparse_file(&source_path) {
+                copy_sparse(&source_path, &target_path)?;
+            } else {
+                fs::copy(&source_path, &target_path)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 统计目录中的子目录数、文件数与文件总字节数（不跟随目录重解析点，重解析点按文件统计）
+///
+/// # 参数
+/// - `dir`: 目录路径（自身不计入目录数）
+///
+/// # 返回值
+/// - `Ok((dirs, files, bytes))`: 子目录数、文件数与文件总字节数
+/// - `Err(std::io::Error)`: 读取目录失败
+pub fn dir_stats(dir: impl AsRef<Path>) -> std::io::Result<(u64, u64, u64)> {
+    let (mut dir_count, mut file_count, mut total_bytes) = (0, 0, 0);
+    let mut dirs = vec![dir.as_ref().to_path_buf()];
+    while let Some(current) = dirs.pop() {
+        for entry in read_dir(&current)? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                dir_count += 1;
+                dirs.push(entry.path());
+            } else {
+                file_count += 1;
+                total_bytes += entry.metadata()?.len();
+            }
+        }
+    }
+    Ok((dir_count, file_count, total_bytes))
+}
+
+/// 稀疏文件处理时的数据块大小（与 NTFS 稀疏分配单位 64 KB 一致）
+const SPARSE_CHUNK_SIZE: usize = 64 * 1024;
+
+/// 判断文件是否为 NTFS 稀疏文件（`FILE_ATTRIBUTE_SPARSE_FILE`）
+///
+/// # 参数
+/// - `path`: 文件路径
+///
+/// # 返回值
+/// - `true`: 稀疏文件
+/// - `false`: 非稀疏文件或无法获取属性
+pub fn is_sparse_file(path: impl AsRef<Path>) -> bool {
+    fs::metadata(path).is_ok_and(|metadata| metadata.file_attributes() & FILE_ATTRIBUTE_SPARSE_FILE.0 != 0)
+}
+
+/// 通过 `FSCTL_SET_SPARSE` 将文件标记为稀疏文件
+///
+/// # 参数
+/// - `file`: 以写入方式打开的文件
+///
+/// # 返回值
+/// - `std::io::Result<()>`: 操作结果
+fn set_sparse(file: &File) -> std::io::Result<()> {
+    unsafe {
+        DeviceIoControl(
+            HANDLE(file.as_raw_handle()),
+            FSCTL_SET_SPARSE,
+            None,
+            0,
+            None,
+            0,
+            None,
+            None,
+        )
+    }
+    .map_err(std::io::Error::from)
+}
+
+/// 复制文件并保留稀疏性：目标文件标记为稀疏文件，全零的数据块不写入（保持未分配）
+///
+/// 与 `fs::copy` 一致保留备用数据流、文件属性、创建时间与修改时间
+///
+/// # 参数
+/// - `source`: 源文件路径
+/// - `target`: 目标文件路径
+///
+/// # 返回值
+/// - `std::io::Result<u64>`: 复制的字节数（文件逻辑大小）
+pub fn copy_sparse(source: impl AsRef<Path>, target: impl AsRef<Path>) -> std::io::Result<u64> {
+    let metadata = fs::metadata(&source)?;
+    let mut reader = File::open(&source)?;
+    let mut writer = File::create(&target)?;
+    set_sparse(&writer)?;
+    let total = write_content(&mut reader, &mut writer, true)?;
+    drop(writer);
+    copy_streams(&source, &target)?;
+    restore_metadata(&target, &metadata)?;
+    Ok(total)
+}
+
+/// 复制文件并在同一遍读取中计算源文件的 SHA-256，暂存补丁数据时无需再次读取文件
+///
+/// 与 `fs::copy` 一致保留备用数据流、文件属性、创建时间与修改时间，稀疏文件按 [`copy_sparse`] 的方式复制
+///
+/// # 参数
+/// - `source`: 源文件路径
+/// - `target`: 目标文件路径
+///
+/// # 返回值
+/// - `std::io::Result<String>`: 源文件的 SHA-256 哈希值（十六进制小写，与 [`get_file_sha256`] 一致）
+pub fn copy_hashed(source: impl AsRef<Path>, target: impl AsRef<Path>) -> std::io::Result<String> {
+    let metadata = fs::metadata(&source)?;
+    let sparse = metadata.file_attributes() & FILE_ATTRIBUTE_SPARSE_FILE.0 != 0;
+    let mut reader = HashReader::new(File::open(&source)?);
+    let mut writer = File::create(&target)?;
+    if sparse {
+        set_sparse(&writer)?;
+    }
+    write_content(&mut reader, &mut writer, sparse)?;
+    drop(writer);
+    copy_streams(&source, &target)?;
+    restore_metadata(&target, &metadata)?;
+    Ok(reader.finish())
+}
+
+/// 将源文件的所有备用数据流复制到目标文件（目标文件须可写）
+///
+/// # 参数
+/// - `source`: 源文件路径
+/// - `target`: 目标文件路径
+///
+/// # 返回值
+/// - `std::io::Result<()>`: 操作结果
+pub fn copy_streams(source: impl AsRef<Path>, target: impl AsRef<Path>) -> std::io::Result<()> {
+    for (name, _) in list_streams(&source)? {
+        let mut reader = File::open(stream_path(&source, &name))?;
+        let mut writer = File::create(stream_path(&target, &name))?;
+        std::io::copy(&mut reader, &mut writer)?;
+    }
+    Ok(())
+}
+
+/// 将文件的创建时间、修改时间与属性恢复为指定的元数据
+///
+/// # 参数
+/// - `target`: 目标文件路径
+/// - `metadata`: 要恢复的元数据（通常为源文件的元数据）
+///
+/// # 返回值
+/// - `std::io::Result<()>`: 操作结果
+pub fn restore_metadata(target: impl AsRef<Path>, metadata: &fs::Metadata) -> std::io::Result<()> {
+    let mut times = FileTimes::new();
+    if let Ok(created) = metadata.created() {
+        times = times.set_created(created);
+    }
+    if let Ok(modified) = metadata.modified() {
+        times = times.set_modified(modified);
+    }
+    fs::OpenOptions::new().write(true).open(&target)?.set_times(times)?;
+
+    // 最后设置属性，避免只读属性导致无法写入
+    let wide: Vec<u16> = target.as_ref().as_os_str().encode_wide().chain(Some(0)).collect();
+    unsafe {
+        SetFileAttributesW(
+            PCWSTR(wide.as_ptr()),
+            FILE_FLAGS_AND_ATTRIBUTES(metadata.file_attributes()),
+        )
+    }
+    .map_err(std::io::Error::from)
+}
+
+/// 将读取器中的全部内容写入文件
+///
+/// # 参数
+/// - `reader`: 读取器
+/// - `writer`: 目标文件
+/// - `sparse`: 目标文件为稀疏文件时全零的数据块不写入（保持未分配）
+///
+/// # 返回值
+/// - `std::io::Result<u64>`: 写入的字节数（文件逻辑大小）
+fn write_content(reader: &mut impl Read, writer: &mut File, sparse: bool) -> std::io::Result<u64> {
+    let buffer_size = match sparse {
+        true => SPARSE_CHUNK_SIZE,
+        false => BUFFER_SIZE.load(Ordering::Relaxed),
+    };
+    let mut buffer = vec![0u8; buffer_size];
+    let mut total = 0u64;
+    loop {
+        let bytes_read = reader.read(&mut buffer)?;
+        if bytes_read == 0 {
+            break;
+        }
+        // 全零的数据块直接跳过，保留为稀疏区域
+        if sparse && buffer[..bytes_read].iter().all(|byte| *byte == 0) {
+            writer.seek(SeekFrom::Current(bytes_read as i64))?;
+        } else {
+            writer.write_all(&buffer[..bytes_read])?;
+        }
+        total += bytes_read as u64;
+    }
+    writer.set_len(total)?;
+    Ok(total)
+}
+
+/// 将已写入的文件转换为稀疏文件：标记为稀疏文件，并通过 `FSCTL_SET_ZERO_DATA` 释放全零的数据块
+///
+/// # 参数
+/// - `path`: 文件路径
+///
+/// # 返回值
+/// - `std::io::Result<()>`: 操作结果
+pub fn make_sparse(path: impl AsRef<Path>) -> std::io::Result<()> {
+    let mut file = fs::OpenOptions::new().read(true).write(true).open(path)?;
+    set_sparse(&file)?;
+
+    // 合并相邻的全零数据块，逐段释放
+    let mut buffer = vec![0u8; SPARSE_CHUNK_SIZE];
+    let mut offset = 0i64;
+    let mut zero_start = None;
+    loop {
+        let bytes_read = file.read(&mut buffer)?;
+        let is_zero = bytes_read > 0 && buffer[..bytes_read].iter().all(|byte| *byte == 0);
+        match (is_zero, zero_start) {
+            (true, None) => zero_start = Some(offset),
+            (false, Some(start)) => {
+                let info = FILE_ZERO_DATA_INFORMATION {
+                    FileOffset: start,
+                    BeyondFinalZero: offset,
+                };
+                unsafe {
+                    DeviceIoControl(
+                        HANDLE(file.as_raw_handle()),
+                        FSCTL_SET_ZERO_DATA,
+                        Some(&info as *const FILE_ZERO_DATA_INFORMATION as *const std::ffi::c_void),
+                        size_of::<FILE_ZERO_DATA_INFORMATION>() as u32,
+                        None,
+                        0,
+                        None,
+                        None,
+                    )
+                }
+                .map_err(std::io::Error::from)?;
+                zero_start = None;
+            }
+            _ => {}
+        }
+        if bytes_read == 0 {
+            break;
+        }
+        offset += bytes_read as i64;
+    }
+    Ok(())
+}
+
+/// 文件或目录在磁盘上的名称与指定路径仅大小写不同时（NTFS 不区分大小写但保留大小写），重命名为指定路径的大小写
+///
+/// # 参数
+/// - `path`: 文件或目录路径（最后一级名称为期望的大小写）
+///
+/// # 返回值
+/// - `Ok(true)`: 已重命名
+/// - `Ok(false)`: 名称大小写已一致，无需重命名
+/// - `Err(std::io::Error)`: 获取实际名称或重命名失败
+pub fn restore_name_case(path: impl AsRef<Path>) -> std::io::Result<bool> {
+    let path = path.as_ref();
+    // 打开文件获取的最终路径使用磁盘上实际的大小写
+    let actual = fs::canonicalize(path)?;
+    let (Some(expected_name), Some(actual_name)) = (path.file_name(), actual.file_name()) else {
+        return Ok(false);
+    };
+    if expected_name == actual_name
+        || expected_name.to_string_lossy().to_lowercase() != actual_name.to_string_lossy().to_lowercase()
+    {
+        return Ok(false);
+    }
+    fs::rename(path.with_file_name(actual_name), path)?;
+    Ok(true)
+}
+
+/// 获取文件的 NTFS 备用数据流（不含默认的未命名数据流）
+///
+/// # 参数
+/// - `path`: 文件路径
+///
+/// # 返回值
+/// - `Ok(Vec<(String, u64)>)`: 数据流名称（不含前导冒号与 `:$DATA` 类型后缀）与大小
+/// - `Err(std::io::Error)`: 枚举失败
+pub fn list_streams(path: impl AsRef<Path>) -> std::io::Result<Vec<(String, u64)>> {
+    let wide: Vec<u16> = long_path(path.as_ref())
+        .as_os_str()
+        .encode_wide()
+        .chain(Some(0))
+        .collect();
+    let mut data = WIN32_FIND_STREAM_DATA::default();
+    let data_ptr = &mut data as *mut WIN32_FIND_STREAM_DATA as *mut std::ffi::c_void;
+    let handle = match unsafe { FindFirstStreamW(PCWSTR(wide.as_ptr()), FindStreamInfoStandard, data_ptr, None) } {
+        Ok(handle) => handle,
+        // 没有任何数据流（如目录）
+        Err(e) if e.code() == ERROR_HANDLE_EOF.to_hresult() => return Ok(Vec::new()),
+        Err(e) => return Err(std::io::Error::from(e)),
+    };
+
+    let mut streams = Vec::new();
+    loop {
+        let name_buf = &data.cStreamName;
+        let len = name_buf.iter().position(|c| *c == 0).unwrap_or(name_buf.len());
+        let name = String::from_utf16_lossy(&name_buf[..len]);
+        // 名称格式为 `:名称:$DATA`，默认数据流为 `::$DATA`
+        if let Some(name) = name.strip_prefix(':').and_then(|name| name.strip_suffix(":$DATA"))
+            && !name.is_empty()
+        {
+            streams.push((name.to_string(), data.StreamSize as u64));
+        }
+        if unsafe { FindNextStreamW(handle, data_ptr) }.is_err() {
+            break;
+        }
+    }
+    unsafe { FindClose(handle) }.ok();
+    Ok(streams)
+}
+
+/// 获取文件备用数据流的路径（`文件路径:数据流名称`）
+///
+/// # 参数
+/// - `path`: 文件路径
+/// - `name`: 数据流名称
+///
+/// # 返回值
+/// - `PathBuf`: 数据流路径，可直接用于打开、读写数据流
+pub fn stream_path(path: impl AsRef<Path>, name: &str) -> PathBuf {
+    let mut stream = path.as_ref().as_os_str().to_owned();
+    stream.push(":");
+    stream.push(name);
+    PathBuf::from(stream)
+}
+
+/// 超过该长度的路径需要长路径前缀（目录路径的限制为 MAX_PATH 减去 8.3 文件名所需的 12 个字符）
+const LONG_PATH_THRESHOLD: usize = 248;
+
+/// 为超过 MAX_PATH 限制的绝对路径添加长路径前缀（`\\?\`，UNC 路径为 `\\?\UNC\`）
+///
+/// 挂载目录中较深的路径（如 WinSxS）可能超过 MAX_PATH，直接传给 Win32 API 时会失败。
+/// 长路径前缀会关闭路径规范化，因此添加前缀时将 `/` 替换为 `\`；未超过限制、已带前缀或相对路径原样返回
+///
+/// # 参数
+/// - `path`: 文件路径
+///
+/// # 返回值
+/// - `PathBuf`: 可直接用于文件操作的路径
+pub fn long_path(path: &Path) -> PathBuf {
+    let Some(text) = path.to_str() else {
+        return path.to_path_buf();
+    };
+    if text.encode_utf16().count() < LONG_PATH_THRESHOLD
+        || text.starts_with(r"\\?\")
+        || text.starts_with(r"\\.\")
+        || !path.is_absolute()
+    {
+        return path.to_path_buf();
+    }
+    let text = text.replace('/', "\\");
+    match text.strip_prefix(r"\\") {
+        Some(unc) => PathBuf::from(format!(r"\\?\UNC\{}", unc)),
+        None => PathBuf::from(format!(r"\\?\{}", text)),
+    }
+}
+
+/// 文件操作重试的初始等待时间（每次重试加倍，最多 6.4 秒）
+const IO_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// 判断 IO 错误是否为可重试的暂时性错误（文件被索引服务或杀毒软件短暂占用）
+///
+/// 拒绝访问通常由只读属性或权限不足引起，重试无法恢复，不视为暂时性错误
+pub fn is_transient_io_error(error: &std::io::Error) -> bool {
+    error.raw_os_error() == Some(ERROR_SHARING_VIOLATION.0 as i32)
+}
+
+/// 执行文件操作，遇到暂时性错误时按指数退避重试
+///
+/// # 参数
+/// - `retries`: 最大重试次数
+/// - `operation`: 文件操作
+///
+/// # 返回值
+/// - `(std::io::Result<T>, u32)`: 操作结果以及实际重试次数
+pub fn retry_io<T>(retries: u32, mut operation: impl FnMut() -> std::io::Result<T>) -> (std::io::Result<T>, u32) {
+    let mut attempt = 0;
+    loop {
+        match operation() {
+            Err(e) if attempt < retries && is_transient_io_error(&e) => {
+                thread::sleep(IO_RETRY_DELAY * 2u32.pow(attempt.min(6)));
+                attempt += 1;
+            }
+            result => return (result, attempt),
+        }
+    }
+}
+
+/// 仅统计写入字节数的写入器，用于在不落盘的情况下评估输出大小
+#[derive(Debug, Default)]
+pub struct CountWriter {
+    /// 已写入的字节数
+    pub count: u64,
+}
+
+impl Write for CountWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.count += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// 读取时同时计算 SHA-256 的读取器，用于在生成差异的同一遍读取中得到文件哈希，无需再次读取文件
+pub struct HashReader<R> {
+    /// 被包装的读取器
+    inner: R,
+    /// SHA-256 哈希器
+    hasher: Sha256,
+}
+
+impl<R: Read> HashReader<R> {
+    /// 包装读取器
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            hasher: Sha256::new(),
+        }
+    }
+
+    /// 获取已读取内容的 SHA-256 哈希值（十六进制小写）
+    pub fn finish(self) -> String {
+        format!("{:x}", self.hasher.finalize())
+    }
+}
+
+impl<R: Read> Read for HashReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let count = self.inner.read(buf)?;
+        self.hasher.update(&buf[..count]);
+        Ok(count)
+    }
+}
+
+/// 计算内存中数据的 SHA-256 哈希值（十六进制小写，与 [`get_file_sha256`] 一致）
+pub fn get_sha256(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+/// 生成差异时顺带计算的旧文件与新文件 SHA-256 哈希值
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileHashes {
+    /// 旧文件哈希值
+    pub old: String,
+    /// 新文件哈希值
+    pub new: String,
+}
+
+/// 应用后命令中可读取目标镜像路径的环境变量名
+pub const POST_APPLY_TARGET_ENV: &str = "WIMPATCH_TARGET";
+
+/// 通过 `cmd /C` 执行应用后命令，目标镜像的绝对路径通过环境变量 [`POST_APPLY_TARGET_ENV`] 传入
+///
+/// # 参数
+/// - `command`: 命令行（原样传给 `cmd /C`）
+/// - `target`: 目标镜像路径
+///
+/// # 返回值
+/// - `Result<i32>`: 命令的退出码（被终止而没有退出码时为 1），无法启动命令时返回错误
+pub fn run_post_apply(command: &str, target: &Path) -> Result<i32> {
+    let target = std::path::absolute(target).unwrap_or_else(|_| target.to_path_buf());
+    let status = Command::new("cmd")
+        .arg("/C")
+        .raw_arg(command)
+        .env(POST_APPLY_TARGET_ENV, &target)
+        .status()
+        .map_err(|e| anyhow!("Run post-apply command failed: {}", e))?;
+    Ok(status.code().unwrap_or(1))
+}
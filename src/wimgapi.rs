@@ -7,8 +7,21 @@ use std::os::windows::ffi::OsStrExt;
 use std::path::{Path, PathBuf};
 use std::ptr::null_mut;
 use std::{mem, ptr};
-use windows::core::GUID;
-use windows::Win32::Foundation::{GetLastError, GENERIC_EXECUTE};
+use windows::core::{w, GUID, PWSTR};
+use windows::Win32::Foundation::{GetLastError, ERROR_SUCCESS, GENERIC_EXECUTE};
+use windows::Win32::System::Diagnostics::Debug::{
+    FormatMessageW, FORMAT_MESSAGE_FROM_SYSTEM, FORMAT_MESSAGE_IGNORE_INSERTS,
+};
+use windows::Win32::System::Registry::{RegGetValueW, HKEY_LOCAL_MACHINE, RRF_RT_REG_SZ, RRF_SUBKEY_WOW6432KEY};
+
+/// wimgapi.dll 的文件名
+const WIMGAPI_DLL: &str = "wimgapi.dll";
+
+/// 加载库时的 Win32 错误码：ERROR_MOD_NOT_FOUND（文件不存在）
+const ERROR_MOD_NOT_FOUND: i32 = 126;
+
+/// 加载库时的 Win32 错误码：ERROR_BAD_EXE_FORMAT（位数不匹配或不是有效的库）
+const ERROR_BAD_EXE_FORMAT: i32 = 193;
 
 /// WIMGAPI错误类型枚举
 #[derive(Debug)]
@@ -17,6 +30,10 @@ pub enum WimApiError {
     Win32Error(u32),
     /// 库加载错误
     LibraryError(libloading::Error),
+    /// 所有搜索位置均未找到 wimgapi.dll
+    LibraryNotFound(Vec<PathBuf>),
+    /// wimgapi.dll 与程序的位数不匹配
+    LibraryBitness(PathBuf),
     /// 通用错误信息
     Message(String),
 }
@@ -24,8 +41,37 @@ pub enum WimApiError {
 impl std::fmt::Display for WimApiError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            WimApiError::Win32Error(code) => write!(f, "Win32 Error: {}", code),
+            WimApiError::Win32Error(code) => {
+                write!(f, "Win32 Error: {}", code)?;
+                if let Some(message) = win32_error_message(*code) {
+                    write!(f, " ({})", message)?;
+                }
+                if let Some(hint) = win32_error_hint(*code) {
+                    write!(f, " - {}", hint)?;
+                }
+                Ok(())
+            }
             WimApiError::LibraryError(err) => write!(f, "Library Error: {}", err),
+            WimApiError::LibraryNotFound(searched) => {
+                let searched: Vec<String> = searched.iter().map(|path| path.display().to_string()).collect();
+                write!(
+                    f,
+                    "wimgapi.dll was not found (searched: {}). Install the Deployment Tools of the Windows ADK, \
+                     or pass --wimgapi-path with the full path of a {}-bit wimgapi.dll",
+                    searched.join("; "),
+                    usize::BITS
+                )
+            }
+            WimApiError::LibraryBitness(path) => write!(
+                f,
+                "{} is not a valid {}-bit library (Win32 Error: {}). It is most likely a 32/64-bit mismatch: \
+                 pass --wimgapi-path with the wimgapi.dll for this architecture, e.g. from the ADK \
+                 Deployment Tools\\{}\\DISM folder",
+                path.display(),
+                usize::BITS,
+                ERROR_BAD_EXE_FORMAT,
+                adk_arch()
+            ),
             WimApiError::Message(msg) => write!(f, "{}", msg),
         }
     }
@@ -40,6 +86,195 @@ impl std::error::Error for WimApiError {
     }
 }
 
+/// 常见 Win32 错误码的处理建议
+///
+/// # 参数
+/// - `code`: Win32 错误码
+///
+/// # 返回值
+/// - `Option<&'static str>`: 处理建议，未收录的错误码返回 `None`
+fn win32_error_hint(code: u32) -> Option<&'static str> {
+    match code {
+        // ERROR_ACCESS_DENIED、ERROR_PRIVILEGE_NOT_HELD
+        5 | 1314 => Some("run WimPatch as administrator"),
+        _ => None,
+    }
+}
+
+/// 使用 FormatMessageW 获取 Win32 错误码的系统错误信息
+///
+/// # 参数
+/// - `code`: Win32 错误码
+///
+/// # 返回值
+/// - `Option<String>`: 去除末尾换行的系统错误信息，系统没有该错误码的信息时返回 `None`
+pub(crate) fn win32_error_message(code: u32) -> Option<String> {
+    let mut buffer = [0u16; 512];
+    let len = unsafe {
+        FormatMessageW(
+            FORMAT_MESSAGE_FROM_SYSTEM | FORMAT_MESSAGE_IGNORE_INSERTS,
+            None,
+            code,
+            0,
+            PWSTR(buffer.as_mut_ptr()),
+            buffer.len() as u32,
+            None,
+        )
+    };
+    let message = String::from_utf16_lossy(&buffer[..len as usize]);
+    let message = message.trim_end();
+    (!message.is_empty()).then(|| message.to_string())
+}
+
+/// 镜像信息 XML 开头的 Unicode 字节顺序标记
+pub(crate) const UTF16_BOM: char = '\u{feff}';
+
+/// 解码 WIMGetImageInformation 返回的 UTF-16LE 镜像信息
+///
+/// 返回的缓冲区以字节顺序标记开头，解码时去除，避免在字符串中间拼接字段时混入 BOM
+///
+/// # 参数
+/// - `units`: UTF-16 代码单元（可能以 NUL 结尾）
+///
+/// # 返回值
+/// - `String`: 不含字节顺序标记与末尾 NUL 的 XML 字符串
+pub(crate) fn decode_image_info(units: &[u16]) -> String {
+    let len = units.iter().rposition(|&unit| unit != 0).map_or(0, |pos| pos + 1);
+    String::from_utf16_lossy(&units[..len])
+        .trim_start_matches(UTF16_BOM)
+        .to_string()
+}
+
+/// 将镜像信息 XML 编码为 WIMSetImageInformation 需要的 UTF-16LE
+///
+/// 无论传入的字符串是否已带字节顺序标记，编码结果都以恰好一个 BOM 开头
+///
+/// # 参数
+/// - `xml_info`: 镜像信息 XML 字符串
+///
+/// # 返回值
+/// - `Vec<u16>`: 以字节顺序标记开头的 UTF-16 代码单元
+pub(crate) fn encode_image_info(xml_info: &str) -> Vec<u16> {
+    std::iter::once(UTF16_BOM as u16)
+        .chain(xml_info.trim_start_matches(UTF16_BOM).encode_utf16())
+        .collect()
+}
+
+/// 当前程序架构在 ADK 部署工具中的目录名
+fn adk_arch() -> &'static str {
+    if cfg!(target_arch = "x86") {
+        "x86"
+    } else if cfg!(target_arch = "aarch64") {
+        "arm64"
+    } else {
+        "amd64"
+    }
+}
+
+/// 从注册表读取 Windows Kits 的安装目录
+///
+/// # 返回值
+/// - `Option<PathBuf>`: `KitsRoot10` 的值，未安装 Windows Kits 时返回 `None`
+fn adk_kits_root() -> Option<PathBuf> {
+    // 64 位系统上 Windows Kits 写入注册表的 32 位视图，先查询 32 位视图
+    for flags in [RRF_RT_REG_SZ | RRF_SUBKEY_WOW6432KEY, RRF_RT_REG_SZ] {
+        let mut buffer = [0u16; 520];
+        let mut size = (buffer.len() * 2) as u32;
+        let result = unsafe {
+            RegGetValueW(
+                HKEY_LOCAL_MACHINE,
+                w!("SOFTWARE\\Microsoft\\Windows Kits\\Installed Roots"),
+                w!("KitsRoot10"),
+                flags,
+                None,
+                Some(buffer.as_mut_ptr() as *mut c_void),
+                Some(&mut size),
+            )
+        };
+        if result == ERROR_SUCCESS {
+            let len = buffer.iter().position(|c| *c == 0).unwrap_or(buffer.len());
+            let root = String::from_utf16_lossy(&buffer[..len]);
+            if !root.is_empty() {
+                return Some(PathBuf::from(root));
+            }
+        }
+    }
+    None
+}
+
+/// 未指定路径时依次尝试加载 wimgapi.dll 的位置
+///
+/// # 返回值
+/// - `Vec<PathBuf>`: 默认搜索路径、程序所在目录、System32 与 ADK 部署工具目录（已安装时）
+pub(crate) fn wimgapi_search_paths() -> Vec<PathBuf> {
+    let mut paths = vec![PathBuf::from(WIMGAPI_DLL)];
+    if let Ok(exe) = std::env::current_exe()
+        && let Some(dir) = exe.parent()
+    {
+        paths.push(dir.join(WIMGAPI_DLL));
+    }
+    if let Some(root) = std::env::var_os("SystemRoot") {
+        paths.push(PathBuf::from(root).join("System32").join(WIMGAPI_DLL));
+    }
+    if let Some(kits_root) = adk_kits_root() {
+        paths.push(
+            kits_root
+                .join("Assessment and Deployment Kit")
+                .join("Deployment Tools")
+                .join(adk_arch())
+                .join("DISM")
+                .join(WIMGAPI_DLL),
+        );
+    }
+    paths
+}
+
+/// 加载指定路径的 wimgapi.dll，区分文件不存在与位数不匹配
+///
+/// # 参数
+/// - `path`: wimgapi.dll 路径（不含目录时按系统默认顺序搜索）
+///
+/// # 返回值
+/// - `Ok(Library)`: 加载成功
+/// - `Err(WimApiError)`: 不存在时为 `LibraryNotFound`，位数不匹配时为 `LibraryBitness`，其他错误为 `LibraryError`
+fn load_library(path: &Path) -> Result<Library, WimApiError> {
+    unsafe { Library::new(path) }.map_err(|err| {
+        let code = std::error::Error::source(&err)
+            .and_then(|source| source.downcast_ref::<std::io::Error>())
+            .and_then(|source| source.raw_os_error());
+        match code {
+            Some(ERROR_MOD_NOT_FOUND) => WimApiError::LibraryNotFound(vec![path.to_path_buf()]),
+            Some(ERROR_BAD_EXE_FORMAT) => WimApiError::LibraryBitness(path.to_path_buf()),
+            _ => WimApiError::LibraryError(err),
+        }
+    })
+}
+
+/// 依次尝试 [`wimgapi_search_paths`] 中的位置，返回第一个加载成功的 wimgapi.dll
+///
+/// # 返回值
+/// - `Ok(Library)`: 加载成功
+/// - `Err(WimApiError)`: 均加载失败，优先报告位数不匹配，其次其他加载错误，否则为 `LibraryNotFound`
+fn search_library() -> Result<Library, WimApiError> {
+    let mut searched = Vec::new();
+    let mut failure = None;
+    for path in wimgapi_search_paths() {
+        match load_library(&path) {
+            Ok(lib) => return Ok(lib),
+            Err(WimApiError::LibraryNotFound(_)) => searched.push(path),
+            Err(err @ WimApiError::LibraryBitness(_)) => {
+                if !matches!(failure, Some(WimApiError::LibraryBitness(_))) {
+                    failure = Some(err);
+                }
+            }
+            Err(err) => {
+                failure.get_or_insert(err);
+            }
+        }
+    }
+    Err(failure.unwrap_or(WimApiError::LibraryNotFound(searched)))
+}
+
 impl From<libloading::Error> for WimApiError {
     fn from(err: libloading::Error) -> Self {
         WimApiError::LibraryError(err)
@@ -69,6 +304,14 @@ pub const WIM_FLAG_SHARE_WRITE: u32 = 64;
 pub const WIM_FLAG_FILEINFO: u32 = 128;
 pub const WIM_FLAG_MOUNT_READONLY: u32 = 0x0000_0200;
 
+pub const WIM_ATTRIBUTE_NORMAL: u32 = 0x00000000;
+pub const WIM_ATTRIBUTE_RESOURCE_ONLY: u32 = 0x00000001;
+pub const WIM_ATTRIBUTE_METADATA_ONLY: u32 = 0x00000002;
+pub const WIM_ATTRIBUTE_VERIFY_DATA: u32 = 0x00000004;
+pub const WIM_ATTRIBUTE_RP_FIX: u32 = 0x00000008;
+pub const WIM_ATTRIBUTE_SPANNED: u32 = 0x00000010;
+pub const WIM_ATTRIBUTE_READONLY: u32 = 0x00000020;
+
 pub const WIM_MOUNT_FLAG_MOUNTED: u32 = 0x00000001;
 pub const WIM_MOUNT_FLAG_MOUNTING: u32 = 0x00000002;
 pub const WIM_MOUNT_FLAG_REMOUNTABLE: u32 = 0x00000004;
@@ -228,10 +471,32 @@ pub struct WimInfo {
     pub wim_flags_and_attr: u32,
 }
 
+impl WimInfo {
+    /// 已设置的 WIM 属性名称，未设置任何属性时为 `normal`
+    pub fn attribute_names(&self) -> Vec<&'static str> {
+        let names: Vec<&'static str> = [
+            (WIM_ATTRIBUTE_RESOURCE_ONLY, "resource-only"),
+            (WIM_ATTRIBUTE_METADATA_ONLY, "metadata-only"),
+            (WIM_ATTRIBUTE_VERIFY_DATA, "verify-data"),
+            (WIM_ATTRIBUTE_RP_FIX, "rp-fix"),
+            (WIM_ATTRIBUTE_SPANNED, "spanned"),
+            (WIM_ATTRIBUTE_READONLY, "readonly"),
+        ]
+        .into_iter()
+        .filter(|(flag, _)| self.wim_attributes & flag != 0)
+        .map(|(_, name)| name)
+        .collect();
+        if names.is_empty() && self.wim_attributes == WIM_ATTRIBUTE_NORMAL {
+            return vec!["normal"];
+        }
+        names
+    }
+}
+
 type Pcwstr = *const u16;
 type Pwstr = *mut u16;
 type Pdword = *mut u32;
-type Handle = usize;
+pub type Handle = usize;
 
 type DsofWimcreateFile = unsafe extern "system" fn(
     pszWimPath: Pcwstr,
@@ -362,7 +627,7 @@ impl Wimgapi {
     /// 加载 wimgapi.dll 并解析所需函数
     ///
     /// # 参数
-    ///  - `path`: 可选的 wimgapi.dll 路径，默认值为 "wimgapi.dll"
+    ///  - `path`: 可选的 wimgapi.dll 路径，未指定时依次搜索 [`wimgapi_search_paths`] 中的位置
     ///
     /// # 示例
     /// ```
@@ -371,9 +636,12 @@ impl Wimgapi {
     ///
     /// # 返回值
     ///  - `Ok(Self)`: 成功加载 wimgapi.dll 并解析函数
-    ///  - `Err(WimApiError)`: 加载失败或解析函数失败
+    ///  - `Err(WimApiError)`: 加载失败（未找到或位数不匹配）或解析函数失败
     pub fn new(path: Option<PathBuf>) -> Result<Self, WimApiError> {
-        let lib = { unsafe { Library::new(path.unwrap_or(PathBuf::from("wimgapi.dll"))) } }?;
+        let lib = match path {
+            Some(path) => load_library(&path)?,
+            None => search_library()?,
+        };
         unsafe {
             Ok(Self {
                 WIMCreateFile: *lib.get(b"WIMCreateFile")?,
@@ -433,6 +701,28 @@ impl Wimgapi {
     /// - `Ok(Handle)`: 句柄
     /// - `Err(...)`：失败则返回 FALSE，则包含 Win32 错误码或说明
     pub fn open(&self, path: &Path, access: u32, operate: u32, compression_type: u32) -> Result<Handle, WimApiError> {
+        self.open_with_flags(path, access, operate, 0, compression_type)
+    }
+
+    /// 使用指定标志创建新映像文件或打开现有映像文件
+    ///
+    /// # 参数
+    ///  - `path`、`access`、`operate`、`compression_type`: 与 `open` 相同
+    ///  - `flags`: 指定打开映像文件时的特殊操作：
+    ///     - `WIM_FLAG_VERIFY`: 为新文件生成数据完整性信息，并验证和更新现有文件的完整性信息。会显著增加处理时间。
+    ///     - `WIM_FLAG_SHARE_WRITE`: 以允许其他进程同时写入的模式打开映像文件。
+    ///
+    /// # 返回值
+    /// - `Ok(Handle)`: 句柄
+    /// - `Err(...)`：失败则返回 FALSE，则包含 Win32 错误码或说明
+    pub fn open_with_flags(
+        &self,
+        path: &Path,
+        access: u32,
+        operate: u32,
+        flags: u32,
+        compression_type: u32,
+    ) -> Result<Handle, WimApiError> {
         let mut _creation: u32 = 0;
 
         let handle = unsafe {
@@ -440,7 +730,7 @@ impl Wimgapi {
                 to_wide(path.as_os_str()).as_ptr(),
                 access,
                 operate,
-                0,
+                flags,
                 compression_type, // 打开已存在文件时此处通常无效
                 &mut _creation as *mut u32,
             )
@@ -996,7 +1286,7 @@ impl Wimgapi {
     /// - `hImage`: 由 WIMCreateFile、WIMLoadImage 或 WIMCaptureImage 函数返回的句柄
     ///
     /// # 返回值
-    /// - `Ok(String)`: 包含卷映像信息的 XML 字符串
+    /// - `Ok(String)`: 包含卷映像信息的 XML 字符串（不含字节顺序标记）
     /// - `Err(WimApiError)`: 错误信息
     pub fn get_image_info(&self, handle: Handle) -> Result<String, WimApiError> {
         let mut pv: *mut std::ffi::c_void = ptr::null_mut();
@@ -1010,7 +1300,11 @@ impl Wimgapi {
         }
 
         // Interpret pv as UTF-16LE buffer of size bytes -> u16 units = size/2
-        let xml_string = Wimgapi::utf16_ptr_to_string(pv as *const u16, (size as usize) / 2);
+        if pv.is_null() || size == 0 {
+            return Ok(String::new());
+        }
+        let units = unsafe { std::slice::from_raw_parts(pv as *const u16, (size as usize) / 2) };
+        let xml_string = decode_image_info(units);
 
         Ok(xml_string)
     }
@@ -1053,7 +1347,7 @@ impl Wimgapi {
     /// - `xml_info`: 包含卷映像信息的 XML 字符串
     ///
     /// # 说明
-    /// - 传入的 XML 数据必须是 Unicode 格式
+    /// - 传入的 XML 数据必须是 Unicode 格式，编码时统一添加一个字节顺序标记
     /// - 如果输入句柄来自 WIMCreateFile 函数，则 XML 数据必须用 `<WIM></WIM>` 标记括起来
     /// - 如果输入句柄来自 WIMLoadImage 或 WIMCaptureImage 函数，则 XML 数据必须用 `<IMAGE></IMAGE>` 标记括起来
     ///
@@ -1062,7 +1356,7 @@ impl Wimgapi {
     /// - `Err(WimApiError)`: 错误信息
     pub fn set_image_info(&self, hImage: Handle, xml_info: &str) -> Result<(), WimApiError> {
         // 将 Rust 字符串转换为 UTF-16 编码的字节数组
-        let utf16_chars: Vec<u16> = xml_info.encode_utf16().collect();
+        let utf16_chars = encode_image_info(xml_info);
         let buffer_size = (utf16_chars.len() * std::mem::size_of::<u16>()) as u32;
 
         // 调用 WIMSetImageInformation 函数
@@ -1080,6 +1374,7 @@ impl Wimgapi {
     /// # 参数
     /// - `handle`: 由 WIMCreateFile 返回的 `.wim` 文件句柄。
     /// - `callback`: 指向应用程序定义的回调函数的指针。
+    /// - `user_data`: 传递给回调函数 `pvUserData` 参数的指针，不需要时传入 `null_mut()`。
     ///
     /// # 返回值
     /// - 如果函数成功执行，则返回值为回调函数从 0 开始的索引。
@@ -1088,8 +1383,9 @@ impl Wimgapi {
         &self,
         handle: Handle,
         callback: extern "system" fn(u32, usize, isize, *mut c_void) -> u32,
+        user_data: *mut c_void,
     ) -> u32 {
-        unsafe { (self.WIMRegisterMessageCallback)(handle, callback, null_mut()) }
+        unsafe { (self.WIMRegisterMessageCallback)(handle, callback, user_data) }
     }
 
     /// 取消注册使用映像特定数据调用的函数。
@@ -1108,4 +1404,103 @@ impl Wimgapi {
     ) -> bool {
         unsafe { (self.WIMUnregisterMessageCallback)(handle, fpMessageProc) }
     }
+}
+
+/// 句柄守卫：离开作用域时自动关闭 WIMGAPI 句柄
+///
+/// 用于 `.wim` 文件句柄与卷映像句柄，提前返回或发生 panic 时也能保证句柄被关闭。
+/// 需要检查关闭结果时调用 [`HandleGuard::close`]。
+pub struct HandleGuard<'a> {
+    wimgapi: &'a Wimgapi,
+    handle: Handle,
+    closed: bool,
+}
+
+impl<'a> HandleGuard<'a> {
+    /// 接管句柄
+    ///
+    /// # 参数
+    /// - `wimgapi`: 用于关闭句柄的 WIMGAPI 实例。
+    /// - `handle`: 需要在离开作用域时关闭的句柄。
+    pub fn new(wimgapi: &'a Wimgapi, handle: Handle) -> Self {
+        Self {
+            wimgapi,
+            handle,
+            closed: false,
+        }
+    }
+
+    /// 获取被守卫的句柄
+    pub fn handle(&self) -> Handle {
+        self.handle
+    }
+
+    /// 立即关闭句柄并返回关闭结果
+    ///
+    /// # 返回值
+    /// - `Ok(())`: 关闭成功
+    /// - `Err(...)`：关闭失败，句柄不会再次被关闭
+    pub fn close(mut self) -> Result<(), WimApiError> {
+        self.closed = true;
+        self.wimgapi.close(self.handle)
+    }
+}
+
+impl Drop for HandleGuard<'_> {
+    fn drop(&mut self) {
+        if !self.closed {
+            self.wimgapi.close(self.handle).ok();
+        }
+    }
+}
+
+/// 挂载守卫：离开作用域时自动卸载映像并关闭卷映像句柄
+///
+/// 提前返回或发生 panic 时映像不会残留在挂载目录中。
+/// 需要检查卸载结果时调用 [`MountGuard::unmount`]。
+pub struct MountGuard<'a> {
+    image: HandleGuard<'a>,
+    mounted: bool,
+}
+
+impl<'a> MountGuard<'a> {
+    /// 将卷映像挂载到指定目录
+    ///
+    /// # 参数
+    /// - `image`: WIMLoadImage 返回的卷映像句柄。
+    /// - `mount_path`: 挂载目录，目录必须已存在且为空。
+    /// - `flags`: 挂载标志，如 `WIM_FLAG_MOUNT_READONLY`。
+    ///
+    /// # 返回值
+    /// - `Ok(MountGuard)`: 挂载成功
+    /// - `Err(...)`：挂载失败，卷映像句柄随之关闭
+    pub fn mount(image: HandleGuard<'a>, mount_path: &Path, flags: u32) -> Result<Self, WimApiError> {
+        image.wimgapi.mount_image_handle(image.handle, mount_path, flags)?;
+        Ok(Self { image, mounted: true })
+    }
+
+    /// 获取已挂载的卷映像句柄
+    pub fn handle(&self) -> Handle {
+        self.image.handle
+    }
+
+    /// 立即卸载映像并关闭卷映像句柄
+    ///
+    /// # 返回值
+    /// - `Ok(())`: 卸载并关闭成功
+    /// - `Err(...)`：卸载或关闭失败，映像不会再次被卸载
+    pub fn unmount(mut self) -> Result<(), WimApiError> {
+        self.mounted = false;
+        self.image.wimgapi.unmount_image_handle(self.image.handle)?;
+        self.image.closed = true;
+        self.image.wimgapi.close(self.image.handle)
+    }
+}
+
+impl Drop for MountGuard<'_> {
+    fn drop(&mut self) {
+        if self.mounted {
+            self.image.wimgapi.unmount_image_handle(self.image.handle).ok();
+        }
+    }
 }
\ No newline at end of file
@@ -1,12 +1,27 @@
+use crate::utils::{CountWriter, FileHashes, HashReader, get_sha256};
 use anyhow::{Context, Result};
 use std::fs::File;
-use std::io::{copy, BufReader, BufWriter, Cursor, Read, Write};
+use std::io::{BufReader, BufWriter, Cursor, Read, Write, copy};
 use std::path::Path;
 use zstd::{Decoder, Encoder};
 
 pub struct ZstdDiff {}
 
 impl ZstdDiff {
+    /// 获取用作字典的旧文件内容
+    ///
+    /// 指定字典窗口时仅使用旧文件末尾的 `dict_window` 字节，生成与应用差异时必须使用相同的窗口
+    ///
+    /// # 参数
+    /// - `old`: 旧文件内容
+    /// - `dict_window`: 字典窗口大小（字节），为 `None` 时使用整个旧文件
+    pub fn dictionary(old: &[u8], dict_window: Option<u64>) -> &[u8] {
+        match dict_window {
+            Some(window) if (window as usize) < old.len() => &old[old.len() - window as usize..],
+            _ => old,
+        }
+    }
+
     /// 生成zstd差异补丁
     ///
     /// # 参数
@@ -53,6 +68,7 @@ impl ZstdDiff {
     /// - `new_file_path`: 新文件路径
     /// - `patch_file_path`: 输出的补丁文件路径
     /// - `level`: 压缩级别，范围为0至22，0表示无压缩，22表示最大压缩
+    /// - `dict_window`: 字典窗口大小（字节），为 `None` 时将整个旧文件作为字典
     ///
     /// # 返回值
     /// 成功时返回Ok(())，失败时返回Err
@@ -61,32 +77,101 @@ impl ZstdDiff {
         new_file_path: impl AsRef<Path>,
         patch_file_path: impl AsRef<Path>,
         level: i32,
+        dict_window: Option<u64>,
     ) -> Result<()> {
+        Self::file_diff_hashed(old_file_path, new_file_path, patch_file_path, level, dict_window).map(|_| ())
+    }
+
+    /// 生成zstd差异补丁文件，并在同一遍读取中计算旧文件与新文件的 SHA-256
+    ///
+    /// 旧文件整体读入内存作为字典，新文件读入编码器时经过哈希器，两者都无需再次读取
+    ///
+    /// # 参数
+    /// - `old_file_path`: 原始文件路径
+    /// - `new_file_path`: 新文件路径
+    /// - `patch_file_path`: 输出的补丁文件路径
+    /// - `level`: 压缩级别，范围为0至22，0表示无压缩，22表示最大压缩
+    /// - `dict_window`: 字典窗口大小（字节），为 `None` 时将整个旧文件作为字典
+    ///
+    /// # 返回值
+    /// 成功时返回Ok(旧文件与新文件的哈希值)，失败时返回Err
+    pub fn file_diff_hashed(
+        old_file_path: impl AsRef<Path>,
+        new_file_path: impl AsRef<Path>,
+        patch_file_path: impl AsRef<Path>,
+        level: i32,
+        dict_window: Option<u64>,
+    ) -> Result<FileHashes> {
         // 读取旧文件
         let mut old_file_content = Vec::new();
         File::open(old_file_path)?
             .read_to_end(&mut old_file_content)
             .with_context(|| "Read old file failed")?;
 
-        // 读取新文件
+        // 读取新文件（读取时计算哈希）
         let new_file = File::open(new_file_path).with_context(|| "Open new file failed")?;
-        let mut new_reader = BufReader::new(new_file);
+        let mut new_reader = HashReader::new(BufReader::new(new_file));
 
         // 创建补丁文件
         let patch_file = File::create(patch_file_path).with_context(|| "Create patch file failed")?;
         let mut writer = BufWriter::new(patch_file);
 
-        // 创建编码器，将旧文件内容作为字典
-        let mut encoder = Encoder::with_dictionary(&mut writer, level, &old_file_content)
+        // 创建编码器，将旧文件内容（或其末尾的字典窗口）作为字典
+        let dictionary = Self::dictionary(&old_file_content, dict_window);
+        let mut encoder = Encoder::with_dictionary(&mut writer, level, dictionary)
             .with_context(|| "Create encoder with dictionary failed")?;
 
         // 从新文件读取内容并编码到补丁文件
         copy(&mut new_reader, &mut encoder).with_context(|| "Stream new file into encoder failed")?;
 
         // 完成编码并写入补丁文件
+        encoder
+            .finish()
+            .with_context(|| "Finish encoding failed")?
+            .flush()
+            .with_context(|| "Flush writer failed")?;
+
+        Ok(FileHashes {
+            old: get_sha256(&old_file_content),
+            new: new_reader.finish(),
+        })
+    }
+
+    /// 计算zstd差异补丁文件大小（不写入文件）
+    ///
+    /// # 参数
+    /// - `old_file_path`: 原始文件路径
+    /// - `new_file_path`: 新文件路径
+    /// - `level`: 压缩级别，范围为0至22，0表示无压缩，22表示最大压缩
+    /// - `dict_window`: 字典窗口大小（字节），为 `None` 时将整个旧文件作为字典
+    ///
+    /// # 返回值
+    /// 成功时返回Ok(补丁大小)，失败时返回Err
+    pub fn file_diff_size(
+        old_file_path: impl AsRef<Path>,
+        new_file_path: impl AsRef<Path>,
+        level: i32,
+        dict_window: Option<u64>,
+    ) -> Result<u64> {
+        // 读取旧文件
+        let mut old_file_content = Vec::new();
+        File::open(old_file_path)?
+            .read_to_end(&mut old_file_content)
+            .with_context(|| "Read old file failed")?;
+
+        // 读取新文件
+        let new_file = File::open(new_file_path).with_context(|| "Open new file failed")?;
+        let mut new_reader = BufReader::new(new_file);
+
+        // 创建编码器，仅统计输出字节数
+        let mut writer = CountWriter::default();
+        let dictionary = Self::dictionary(&old_file_content, dict_window);
+        let mut encoder = Encoder::with_dictionary(&mut writer, level, dictionary)
+            .with_context(|| "Create encoder with dictionary failed")?;
+        copy(&mut new_reader, &mut encoder).with_context(|| "Stream new file into encoder failed")?;
         encoder.finish().with_context(|| "Finish encoding failed")?;
 
-        Ok(())
+        Ok(writer.count)
     }
 
     /// 应用zstd差异补丁文件
@@ -95,6 +180,7 @@ impl ZstdDiff {
     /// - `old_file_path`: 原始文件路径
     /// - `patch_file_path`: 补丁文件路径
     /// - `new_file_path`: 输出的新文件路径
+    /// - `dict_window`: 生成差异时使用的字典窗口大小（字节）
     ///
     /// # 返回值
     /// 成功时返回Ok(())，失败时返回Err
@@ -102,6 +188,7 @@ impl ZstdDiff {
         old_file_path: impl AsRef<Path>,
         patch_file_path: impl AsRef<Path>,
         new_file_path: impl AsRef<Path>,
+        dict_window: Option<u64>,
     ) -> Result<()> {
         // 读取旧文件
         let mut old_file_content = Vec::new();
@@ -119,8 +206,9 @@ impl ZstdDiff {
         let new_file = File::create(new_file_path).with_context(|| "Create new file failed")?;
         let mut writer = BufWriter::new(new_file);
 
-        // 创建解码器，将旧文件内容作为字典
-        let mut decoder = Decoder::with_dictionary(Cursor::new(&patch_content), &old_file_content)
+        // 创建解码器，将旧文件内容（或其末尾的字典窗口）作为字典
+        let dictionary = Self::dictionary(&old_file_content, dict_window);
+        let mut decoder = Decoder::with_dictionary(Cursor::new(&patch_content), dictionary)
             .with_context(|| "Failed to create decoder with dictionary")?;
 
         // 从解码器读取内容并写入新文件
@@ -131,4 +219,66 @@ impl ZstdDiff {
 
         Ok(())
     }
+
+    /// 压缩文件（不使用字典）
+    ///
+    /// # 参数
+    /// - `source_file_path`: 源文件路径
+    /// - `target_file_path`: 输出的压缩文件路径
+    /// - `level`: 压缩级别，范围为0至22，0表示无压缩，22表示最大压缩
+    ///
+    /// # 返回值
+    /// 成功时返回Ok(())，失败时返回Err
+    pub fn file_compress(
+        source_file_path: impl AsRef<Path>,
+        target_file_path: impl AsRef<Path>,
+        level: i32,
+    ) -> Result<()> {
+        Self::file_compress_hashed(source_file_path, target_file_path, level).map(|_| ())
+    }
+
+    /// 压缩文件（不使用字典），并在同一遍读取中计算源文件的 SHA-256
+    ///
+    /// # 参数
+    /// - `source_file_path`: 源文件路径
+    /// - `target_file_path`: 输出的压缩文件路径
+    /// - `level`: 压缩级别，范围为0至22，0表示无压缩，22表示最大压缩
+    ///
+    /// # 返回值
+    /// 成功时返回Ok(源文件的哈希值)，失败时返回Err
+    pub fn file_compress_hashed(
+        source_file_path: impl AsRef<Path>,
+        target_file_path: impl AsRef<Path>,
+        level: i32,
+    ) -> Result<String> {
+        let source_file = File::open(source_file_path).with_context(|| "Open source file failed")?;
+        let mut reader = HashReader::new(BufReader::new(source_file));
+        let target_file = File::create(target_file_path).with_context(|| "Create compressed file failed")?;
+        let mut encoder = Encoder::new(BufWriter::new(target_file), level).with_context(|| "Create encoder failed")?;
+        copy(&mut reader, &mut encoder).with_context(|| "Stream source file into encoder failed")?;
+        encoder
+            .finish()
+            .with_context(|| "Finish encoding failed")?
+            .flush()
+            .with_context(|| "Flush writer failed")?;
+        Ok(reader.finish())
+    }
+
+    /// 解压由 `file_compress` 生成的压缩文件
+    ///
+    /// # 参数
+    /// - `source_file_path`: 压缩文件路径
+    /// - `target_file_path`: 输出的文件路径
+    ///
+    /// # 返回值
+    /// 成功时返回Ok(())，失败时返回Err
+    pub fn file_decompress(source_file_path: impl AsRef<Path>, target_file_path: impl AsRef<Path>) -> Result<()> {
+        let source_file = File::open(source_file_path).with_context(|| "Open compressed file failed")?;
+        let mut decoder = Decoder::new(source_file).with_context(|| "Create decoder failed")?;
+        let target_file = File::create(target_file_path).with_context(|| "Create target file failed")?;
+        let mut writer = BufWriter::new(target_file);
+        copy(&mut decoder, &mut writer).with_context(|| "Stream decoder into writer failed")?;
+        writer.flush().with_context(|| "Flush writer failed")?;
+        Ok(())
+    }
 }